@@ -21,6 +21,18 @@ impl Event {
     }
 }
 
+/// How the process whose events follow an [`EventKind::Process`] came to be.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProcessOrigin {
+    /// The first instrumented process, not started by another one.
+    Root,
+    /// Forked from `parent_pid` at its `fork_idx`th [`Fork`](EventKind::Fork), so it starts
+    /// with a copy of the parent's memory.
+    Fork { parent_pid: u32, fork_idx: u32 },
+    /// Started by `exec` in a descendant of the root process, so it starts with fresh memory.
+    Exec { parent_pid: u32 },
+}
+
 #[derive(Serialize, Deserialize, Copy, Clone)]
 pub enum EventKind {
     /// A copy from one local to another. This also covers casts such as `&mut
@@ -85,6 +97,8 @@ pub enum EventKind {
 
     StoreValue(Pointer),
 
+    /// Pointer arithmetic: the base pointer, the signed offset in elements, and the resulting
+    /// pointer.
     Offset(Pointer, isize, Pointer),
 
     /// Marks the start of events in a new function body.
@@ -93,9 +107,39 @@ pub enum EventKind {
     /// events after a [`BeginFuncBody`](Self::BeginFuncBody) event are treated as copies.
     BeginFuncBody,
 
+    /// Written at the start of each process's events.
+    /// A log can contain more than one if its process `exec`s another instrumented program.
+    Process {
+        pid: u32,
+        origin: ProcessOrigin,
+    },
+
+    /// The process forked.  The child's events are written to its own log.
+    Fork,
+
+    /// Written once at the end of the event log,
+    /// recording how many events the runtime didn't record.
+    /// If either count is nonzero, the log is incomplete.
+    Stats {
+        /// Events dropped because the runtime's queue was full.
+        dropped: u64,
+        /// Events skipped by sampling.
+        sampled_out: u64,
+    },
+
     Done,
 }
 
+impl EventKind {
+    /// Whether this event records the structure of the trace rather than an operation on a
+    /// pointer.  These are never sampled out, as the rest of the trace can't be interpreted
+    /// correctly without them.
+    pub fn is_structural(&self) -> bool {
+        use EventKind::*;
+        matches!(self, Process { .. } | Fork | Stats { .. } | Done)
+    }
+}
+
 impl Debug for EventKind {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         use EventKind::*;
@@ -114,6 +158,16 @@ impl Debug for EventKind {
                 new_ptr,
             } => write!(f, "realloc(0x{:x}, {}) -> 0x{:x}", old_ptr, size, new_ptr),
             Ret(ptr) => write!(f, "ret(0x{:x})", ptr),
+            Process { pid, origin } => write!(f, "process({}, {:?})", pid, origin),
+            Fork => write!(f, "fork"),
+            Stats {
+                dropped,
+                sampled_out,
+            } => write!(
+                f,
+                "stats(dropped = {}, sampled out = {})",
+                dropped, sampled_out
+            ),
             Done => write!(f, "done"),
             BeginFuncBody => write!(f, "begin func body"),
             LoadAddr(ptr) => write!(f, "load(0x{:x})", ptr),
@@ -49,13 +49,10 @@ pub fn calloc(mir_loc: MirLocId, nmemb: u64, size: u64, ptr: usize) {
 pub fn realloc(mir_loc: MirLocId, old_ptr: usize, size: u64, new_ptr: usize) {
     RUNTIME.send_event(Event {
         mir_loc,
-        kind: EventKind::Free { ptr: old_ptr },
-    });
-    RUNTIME.send_event(Event {
-        mir_loc,
-        kind: EventKind::Alloc {
+        kind: EventKind::Realloc {
+            old_ptr,
             size: size as usize,
-            ptr: new_ptr,
+            new_ptr,
         },
     });
 }
@@ -4,6 +4,7 @@ pub mod metadata;
 pub mod mir_loc;
 pub mod parse;
 pub mod runtime;
+pub mod span;
 
 pub use handlers::*;
 use runtime::{global_runtime::RUNTIME, skip::notify_if_events_were_skipped_before_main};
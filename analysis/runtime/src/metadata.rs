@@ -7,13 +7,31 @@ use std::{
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::mir_loc::{Func, FuncId, MirLoc, MirLocId};
+use crate::mir_loc::{Func, FuncId, Local, MirLoc, MirLocId};
+use crate::span::{DisplaySpan, SpanId, SpanInterner, SpanTable};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Metadata {
     pub locs: Vec<MirLoc>,
     pub functions: HashMap<FuncId, String>,
     pub projections: HashMap<u64, Vec<usize>>,
+    pub expectations: Vec<LocalExpectation>,
+    /// The source locations referred to by [`EventMetadata::span`].
+    ///
+    /// [`EventMetadata::span`]: crate::mir_loc::EventMetadata::span
+    pub spans: SpanTable,
+}
+
+/// An expectation about the permissions a local requires at run time, written in the
+/// instrumented source as `#[c2rust_pdg::expect(write, ...)]` on the local's `let` or parameter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalExpectation {
+    pub func: Func,
+    pub local: Local,
+    /// The source location of the local's binding.
+    pub span: String,
+    /// The permissions named in the attribute, each one of `read`, `write`, `offset`, or `free`.
+    pub perms: Vec<String>,
 }
 
 impl Metadata {
@@ -21,6 +39,11 @@ impl Metadata {
         &self.locs[index as usize]
     }
 
+    /// Display a [`SpanId`] from this [`Metadata`] as `file:line:col: line:col`.
+    pub fn span(&self, span: Option<SpanId>) -> DisplaySpan<'_> {
+        self.spans.display(span)
+    }
+
     pub fn read(bytes: &[u8]) -> bincode::Result<Self> {
         bincode_deserialize_many(bytes)
     }
@@ -48,9 +71,21 @@ impl FromIterator<Metadata> for Metadata {
         let mut locs = Vec::new();
         let mut functions = HashMap::new();
         let mut projections = HashMap::new();
-        for metadata in iter {
+        let mut expectations = Vec::new();
+        let mut spans = SpanInterner::default();
+        for mut metadata in iter {
+            // Each crate numbers its spans separately, so renumber them in the merged table.
+            let span_ids = metadata
+                .spans
+                .iter()
+                .map(|(file, span)| spans.insert(file, span.lo, span.hi))
+                .collect::<Vec<_>>();
+            for loc in &mut metadata.locs {
+                loc.metadata.span = loc.metadata.span.map(|span| span_ids[span as usize]);
+            }
             locs.extend(metadata.locs);
             functions.extend(metadata.functions);
+            expectations.extend(metadata.expectations);
 
             for (key, proj) in metadata.projections.into_iter() {
                 projections
@@ -67,6 +102,8 @@ impl FromIterator<Metadata> for Metadata {
             locs,
             functions,
             projections,
+            expectations,
+            spans: spans.finish(),
         }
     }
 }
@@ -82,3 +119,76 @@ impl Debug for MirLoc {
         write!(f, "{fn_name}:{basic_block_idx}:{statement_idx}")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir_loc::{DefPathHash, EventMetadata, Fingerprint, FuncId};
+    use crate::span::SourcePos;
+
+    /// Build the [`Metadata`] of a crate with one [`MirLoc`] per span, each with its span
+    /// interned in the crate's own table.
+    fn crate_metadata(name: &str, spans: &[(&str, u32)]) -> Metadata {
+        let mut interner = SpanInterner::default();
+        let locs = spans
+            .iter()
+            .enumerate()
+            .map(|(i, &(file, line))| {
+                let pos = SourcePos { line, col: 1 };
+                let span = interner.insert(file, pos, pos);
+                MirLoc {
+                    func: Func {
+                        id: FuncId(DefPathHash(Fingerprint(name.len() as u64, i as u64))),
+                        name: format!("{name}_{i}"),
+                    },
+                    basic_block_idx: 0,
+                    statement_idx: i,
+                    metadata: EventMetadata {
+                        span: Some(span),
+                        ..Default::default()
+                    },
+                }
+            })
+            .collect();
+        Metadata {
+            locs,
+            functions: HashMap::new(),
+            projections: HashMap::new(),
+            expectations: Vec::new(),
+            spans: interner.finish(),
+        }
+    }
+
+    #[test]
+    fn merge_renumbers_spans() {
+        let a = crate_metadata("a", &[("a.rs", 1), ("shared.rs", 2), ("a.rs", 3)]);
+        let b = crate_metadata("bb", &[("shared.rs", 2), ("b.rs", 4), ("shared.rs", 5)]);
+        // Both crates number their spans from 0.
+        assert_eq!(b.locs[0].metadata.span, Some(0));
+
+        // Merge through serialization, as `Metadata::read` does for files with several crates.
+        let mut bytes = bincode::serialize(&a).unwrap();
+        bytes.extend(bincode::serialize(&b).unwrap());
+        let merged = Metadata::read(&bytes).unwrap();
+
+        let locs = merged
+            .locs
+            .iter()
+            .map(|loc| merged.span(loc.metadata.span).to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locs,
+            [
+                "a.rs:1:1: 1:1",
+                "shared.rs:2:1: 2:1",
+                "a.rs:3:1: 3:1",
+                "shared.rs:2:1: 2:1",
+                "b.rs:4:1: 4:1",
+                "shared.rs:5:1: 5:1",
+            ]
+        );
+        // The span both crates share is stored once.
+        assert_eq!(merged.locs[1].metadata.span, merged.locs[3].metadata.span);
+        assert_eq!(merged.spans.iter().count(), 5);
+    }
+}
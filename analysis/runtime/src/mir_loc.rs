@@ -1,3 +1,4 @@
+use crate::span::SpanId;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::fmt::Debug;
@@ -199,6 +200,10 @@ pub struct EventMetadata {
     pub transfer_kind: TransferKind,
     /// Any string useful for debugging.
     pub debug_info: String,
+    /// The source location of the instrumented statement in [`Metadata::spans`].
+    ///
+    /// [`Metadata::spans`]: crate::metadata::Metadata::spans
+    pub span: Option<SpanId>,
 }
 
 impl EventMetadata {
@@ -208,6 +213,7 @@ impl EventMetadata {
             destination,
             transfer_kind,
             debug_info: _,
+            span: _,
         } = self;
         (source, destination, transfer_kind)
     }
@@ -84,6 +84,20 @@ pub mod env {
         Ok(path.into())
     }
 
+    /// Read a UTF-8 string from `var`, where `expected` describes what it must be.
+    pub fn string<K: AsRef<OsStr>>(var: K, expected: &str) -> Result<String, String> {
+        let var = var.as_ref();
+        let value = env::var_os(var).ok_or_else(|| {
+            let var = var.to_string_lossy();
+            format!("missing ${var}, must be {expected}")
+        })?;
+        value.into_string().map_err(|value| {
+            let var = var.to_string_lossy();
+            let value = value.to_string_lossy();
+            format!("found \"{value}\" in ${var}, but it must be UTF-8")
+        })
+    }
+
     pub fn one_of<K: AsRef<OsStr>, T: GetChoices + AsStr + 'static>(
         var: K,
     ) -> Result<&'static T, String> {
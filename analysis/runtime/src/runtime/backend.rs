@@ -3,13 +3,18 @@ use crossbeam_utils::Backoff;
 use enum_dispatch::enum_dispatch;
 use fs_err::{File, OpenOptions};
 use std::fmt::Debug;
-use std::io::{stderr, BufWriter, Write};
+use std::io::{self, stderr, BufWriter, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
 use std::sync::Arc;
 
 use bincode;
 
-use super::{AnyError, Detect, FINISHED};
-use crate::events::{Event, EventKind};
+use super::{
+    fork::{child_log_path, remove_child_logs},
+    AnyError, Detect, FINISHED,
+};
+use crate::events::{Event, EventKind, ProcessOrigin};
 use crate::metadata::Metadata;
 use crate::parse::{self, AsStr, GetChoices};
 
@@ -24,6 +29,7 @@ pub(super) trait WriteEvent {
 pub enum BackendKind {
     Debug,
     Log,
+    Socket,
 }
 
 impl AsStr for BackendKind {
@@ -31,13 +37,14 @@ impl AsStr for BackendKind {
         match self {
             Self::Debug => "debug",
             Self::Log => "log",
+            Self::Socket => "socket",
         }
     }
 }
 
 impl GetChoices for BackendKind {
     fn choices() -> &'static [Self] {
-        &[Self::Debug, Self::Log]
+        &[Self::Debug, Self::Log, Self::Socket]
     }
 }
 
@@ -51,6 +58,13 @@ pub struct DebugBackend {
     metadata: Metadata,
 }
 
+impl DebugBackend {
+    /// Print events to stderr, described using `metadata`.
+    pub fn new(metadata: Metadata) -> Self {
+        Self { metadata }
+    }
+}
+
 impl WriteEvent for DebugBackend {
     fn write(&mut self, event: Event) {
         let mir_loc = self.metadata.get(event.mir_loc);
@@ -66,6 +80,43 @@ pub struct LogBackend {
     writer: BufWriter<File>,
 }
 
+impl LogBackend {
+    /// Write events to the file at `path`, appending to it if `append` is set
+    /// and truncating it otherwise.
+    pub fn open(path: &Path, append: bool) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+        let writer = BufWriter::new(file);
+        Ok(Self { writer })
+    }
+}
+
+impl LogBackend {
+    /// Open the log of the root process, removing the logs of the descendants of an earlier run
+    /// unless appending to it.
+    fn detect_root() -> Result<Self, AnyError> {
+        let path = parse::env::path("INSTRUMENT_OUTPUT")?;
+        let append: bool = *parse::env::one_of("INSTRUMENT_OUTPUT_APPEND")?;
+        if !append {
+            remove_child_logs(&path)?;
+        }
+        Ok(Self::open(&path, append)?)
+    }
+
+    /// Open the log of a descendant of the root process, at [`child_log_path`].
+    fn detect_child(origin: ProcessOrigin) -> Result<Self, AnyError> {
+        let root_log = parse::env::path("INSTRUMENT_OUTPUT")?;
+        let path = child_log_path(&root_log, std::process::id());
+        // A process that `exec`s continues the log it started when it was forked.
+        let append = matches!(origin, ProcessOrigin::Exec { .. });
+        Ok(Self::open(&path, append)?)
+    }
+}
+
 impl WriteEvent for LogBackend {
     fn write(&mut self, event: Event) {
         bincode::serialize_into(&mut self.writer, &event).unwrap();
@@ -76,10 +127,35 @@ impl WriteEvent for LogBackend {
     }
 }
 
+/// Streams events in the same format as [`LogBackend`] to a TCP socket,
+/// so a consumer can process them while the program is still running.
+pub struct SocketBackend {
+    writer: BufWriter<TcpStream>,
+}
+
+impl SocketBackend {
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let writer = BufWriter::new(stream);
+        Ok(Self { writer })
+    }
+}
+
+impl WriteEvent for SocketBackend {
+    fn write(&mut self, event: Event) {
+        bincode::serialize_into(&mut self.writer, &event).unwrap();
+    }
+
+    fn flush(&mut self) {
+        self.writer.flush().unwrap();
+    }
+}
+
 #[enum_dispatch(WriteEvent)]
 pub enum Backend {
     Debug(DebugBackend),
     Log(LogBackend),
+    Socket(SocketBackend),
 }
 
 impl Backend {
@@ -125,7 +201,7 @@ impl Detect for DebugBackend {
         // but that may require adding `color-eyre`/`eyre` as a dependency
         let bytes = fs_err::read(path)?;
         let metadata = Metadata::read(&bytes)?;
-        Ok(Self { metadata })
+        Ok(Self::new(metadata))
     }
 }
 
@@ -133,14 +209,14 @@ impl Detect for LogBackend {
     fn detect() -> Result<Self, AnyError> {
         let path = parse::env::path("INSTRUMENT_OUTPUT")?;
         let append: bool = *parse::env::one_of("INSTRUMENT_OUTPUT_APPEND")?;
-        let file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .append(append)
-            .truncate(!append)
-            .open(&path)?;
-        let writer = BufWriter::new(file);
-        Ok(Self { writer })
+        Ok(Self::open(&path, append)?)
+    }
+}
+
+impl Detect for SocketBackend {
+    fn detect() -> Result<Self, AnyError> {
+        let addr = parse::env::string("INSTRUMENT_OUTPUT", "a socket address")?;
+        Ok(Self::connect(addr)?)
     }
 }
 
@@ -155,13 +231,92 @@ impl Backend {
         let this = match kind {
             BackendKind::Debug => Self::Debug(DebugBackend::detect()?),
             BackendKind::Log => Self::Log(LogBackend::detect()?),
+            BackendKind::Socket => Self::Socket(SocketBackend::detect()?),
         };
         Ok(this)
     }
 }
 
+impl Backend {
+    /// Detect the [`Backend`] for a process that started as `origin`.
+    ///
+    /// Descendants of the root process write to their own log rather than `$INSTRUMENT_OUTPUT`,
+    /// and the root process removes those left by an earlier run.
+    pub fn detect_for(origin: Option<ProcessOrigin>) -> Result<Self, AnyError> {
+        let kind = BackendKind::detect()?;
+        match origin {
+            Some(origin @ (ProcessOrigin::Fork { .. } | ProcessOrigin::Exec { .. }))
+                if kind == BackendKind::Log =>
+            {
+                Ok(Self::Log(LogBackend::detect_child(origin)?))
+            }
+            Some(ProcessOrigin::Root) if kind == BackendKind::Log => {
+                Ok(Self::Log(LogBackend::detect_root()?))
+            }
+            _ => Self::detect_kind(kind),
+        }
+    }
+}
+
 impl Detect for Backend {
     fn detect() -> Result<Self, AnyError> {
         Self::detect_kind(BackendKind::detect()?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::scoped_runtime::{
+        Backpressure, ExistingRuntime, RuntimeKind, ScopedRuntime,
+    };
+    use std::io::{Cursor, Read};
+    use std::net::TcpListener;
+
+    #[test]
+    fn socket_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let backend = SocketBackend::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let runtime = ScopedRuntime::builder()
+            .kind(RuntimeKind::MainThread)
+            .backend(Backend::Socket(backend))
+            .backpressure(Backpressure::Block)
+            .origin(ProcessOrigin::Root)
+            .build()
+            .unwrap();
+        runtime.send_event(Event {
+            mir_loc: 1,
+            kind: EventKind::Alloc {
+                size: 8,
+                ptr: 0x1000,
+            },
+        });
+        runtime.send_event(Event {
+            mir_loc: 2,
+            kind: EventKind::StoreAddr(0x1000),
+        });
+        runtime.finalize();
+        // Close the socket, so the reader sees the end of the stream.
+        drop(runtime);
+
+        let mut bytes = Vec::new();
+        stream.read_to_end(&mut bytes).unwrap();
+        let mut cursor = Cursor::new(&bytes[..]);
+        let mut events = Vec::new();
+        while cursor.position() < bytes.len() as u64 {
+            let event: Event = bincode::deserialize_from(&mut cursor).unwrap();
+            events.push(format!("{}: {:?}", event.mir_loc, event.kind));
+        }
+        assert_eq!(
+            events,
+            [
+                format!("0: process({}, Root)", std::process::id()),
+                "1: malloc(8) -> 0x1000".to_owned(),
+                "2: store(0x1000)".to_owned(),
+                "0: stats(dropped = 0, sampled out = 0)".to_owned(),
+            ]
+        );
+    }
+}
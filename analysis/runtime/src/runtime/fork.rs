@@ -0,0 +1,286 @@
+//! Tracking of the processes an instrumented program forks and `exec`s.
+//!
+//! Every process writes its own log, so that the events of a parent and a child running
+//! concurrently aren't interleaved.  The first process uses `$INSTRUMENT_OUTPUT` as is, and sets
+//! `$INSTRUMENT_ROOT_PID`, which its descendants inherit, so that they write to
+//! [`child_log_path`] instead.  Each log starts with an [`EventKind::Process`] event recording
+//! how its process came to be, which `c2rust-pdg` uses to stitch the logs back into a process
+//! tree.
+//!
+//! A forked child can't keep using the runtime it inherits, as the parent's background thread
+//! doesn't exist in the child, and the parent's log is still open in the parent.  The `fork` child
+//! handler may only call async-signal-safe functions, so it only marks the child as forked, and
+//! the runtime is replaced when the child sends its first event through
+//! [`GlobalRuntime::send_event`](super::global_runtime::GlobalRuntime::send_event).
+//!
+//! # Limitations
+//!
+//! Replacing the runtime allocates and opens the child's log, which is not async-signal-safe.
+//! This works for the usual child, which runs instrumented code or `exec`s right away, with
+//! libcs such as glibc and musl that keep `malloc` usable in the child of a multithreaded
+//! process.  But the child may deadlock if its first event is sent from a signal handler, or if
+//! another thread of its parent held a lock the runtime needs, such as the environment lock,
+//! when it forked.
+
+use std::{
+    env, io,
+    os::unix::process::parent_id,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Once,
+    },
+};
+
+use crate::events::{Event, EventKind, ProcessOrigin};
+
+use super::global_runtime::RUNTIME;
+
+const ROOT_PID_VAR: &str = "INSTRUMENT_ROOT_PID";
+
+/// The number of times this process image has forked.
+static FORK_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// The pid of the parent and its [`FORK_COUNT`] at the last fork, read by the child.
+static FORK_PARENT_PID: AtomicU32 = AtomicU32::new(0);
+static FORK_IDX: AtomicU32 = AtomicU32::new(0);
+
+/// Set in a forked child until it has replaced the runtime inherited from its parent.
+static FORKED: AtomicBool = AtomicBool::new(false);
+
+/// The log of the descendant `pid` of the process that logs to `root_log`.
+pub fn child_log_path(root_log: &Path, pid: u32) -> PathBuf {
+    let mut path = root_log.as_os_str().to_owned();
+    path.push(format!(".{pid}"));
+    path.into()
+}
+
+/// The pids of the descendants whose logs, at [`child_log_path`], are next to `root_log`, in
+/// increasing order.
+pub fn child_log_pids(root_log: &Path) -> io::Result<Vec<u32>> {
+    let dir = match root_log.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let root_name = match root_log.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return Ok(Vec::new()),
+    };
+    let mut pids = Vec::new();
+    for entry in fs_err::read_dir(dir)? {
+        let pid = entry?.file_name().to_str().and_then(|name| {
+            let suffix = name.strip_prefix(root_name)?;
+            suffix.strip_prefix('.')?.parse::<u32>().ok()
+        });
+        pids.extend(pid);
+    }
+    pids.sort();
+    Ok(pids)
+}
+
+/// Remove the logs that the descendants of an earlier run left next to `root_log`, so they
+/// aren't mistaken for descendants of this run.
+pub(super) fn remove_child_logs(root_log: &Path) -> io::Result<()> {
+    for pid in child_log_pids(root_log)? {
+        fs_err::remove_file(child_log_path(root_log, pid))?;
+    }
+    Ok(())
+}
+
+/// The [`EventKind::Process`] event that starts the events of this process.
+pub(super) fn process_event(origin: ProcessOrigin) -> Event {
+    Event {
+        mir_loc: 0,
+        kind: EventKind::Process {
+            pid: process::id(),
+            origin,
+        },
+    }
+}
+
+/// Determine how this process started, when its runtime is first initialized.
+///
+/// If it is the root process, this marks it as such for its descendants.
+pub(super) fn detect_origin() -> ProcessOrigin {
+    let pid = process::id();
+    let root_pid = env::var(ROOT_PID_VAR)
+        .ok()
+        .and_then(|root_pid| root_pid.parse::<u32>().ok());
+    match root_pid {
+        Some(root_pid) if root_pid != pid => ProcessOrigin::Exec {
+            parent_pid: parent_id(),
+        },
+        _ => {
+            env::set_var(ROOT_PID_VAR, pid.to_string());
+            ProcessOrigin::Root
+        }
+    }
+}
+
+unsafe extern "C" fn prepare_fork() {
+    let fork_idx = FORK_COUNT.fetch_add(1, Ordering::SeqCst);
+    FORK_PARENT_PID.store(process::id(), Ordering::SeqCst);
+    FORK_IDX.store(fork_idx, Ordering::SeqCst);
+    RUNTIME.send_event(Event {
+        mir_loc: 0,
+        kind: EventKind::Fork,
+    });
+}
+
+unsafe extern "C" fn child_after_fork() {
+    // # Async-signal-safety: the child of a multithreaded process may only call
+    // async-signal-safe functions until it `exec`s, so only mark it as forked here,
+    // and replace its runtime once it sends its first event.
+    FORK_COUNT.store(0, Ordering::SeqCst);
+    FORKED.store(true, Ordering::SeqCst);
+}
+
+/// Install the `fork` handlers that record [`EventKind::Fork`] events and mark children.
+///
+/// This only does anything the first time it is called.
+pub(super) fn install_fork_handlers() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        // SAFETY: the handlers don't unwind.
+        let ret = unsafe { libc::pthread_atfork(Some(prepare_fork), None, Some(child_after_fork)) };
+        if ret != 0 {
+            eprintln!(
+                "failed to install fork handlers ({ret}), events in forked children will be lost"
+            );
+        }
+    });
+}
+
+/// If this is a forked child that hasn't yet replaced its runtime, return how it started.
+///
+/// This only returns [`Some`] once per fork, and the caller must then replace the runtime, which
+/// is subject to the [limitations](self#limitations) of running in a forked child.
+///
+/// # Async-signal-safety: an atomic swap is safe.
+pub(super) fn take_fork_origin() -> Option<ProcessOrigin> {
+    if !FORKED.swap(false, Ordering::SeqCst) {
+        return None;
+    }
+    Some(ProcessOrigin::Fork {
+        parent_pid: FORK_PARENT_PID.load(Ordering::SeqCst),
+        fork_idx: FORK_IDX.load(Ordering::SeqCst),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::backend::{Backend, LogBackend};
+    use crate::runtime::scoped_runtime::{RuntimeKind, ScopedRuntime};
+    use std::io::Cursor;
+
+    fn read_log(path: &Path) -> Vec<String> {
+        let bytes = fs_err::read(path).unwrap();
+        let mut cursor = Cursor::new(&bytes[..]);
+        let mut events = Vec::new();
+        while cursor.position() < bytes.len() as u64 {
+            let event: Event = bincode::deserialize_from(&mut cursor).unwrap();
+            events.push(format!("{}: {:?}", event.mir_loc, event.kind));
+        }
+        events
+    }
+
+    fn free(ptr: usize) -> Event {
+        Event {
+            mir_loc: 1,
+            kind: EventKind::Free { ptr },
+        }
+    }
+
+    /// This is the only test that uses [`RUNTIME`] and the `INSTRUMENT_*` variables, as they are
+    /// global to the test process.
+    #[test]
+    fn fork_writes_child_log() {
+        let root_log = env::temp_dir().join(format!("c2rust-fork-test-{}.bc", process::id()));
+        // The child detects its backend from the environment.
+        env::set_var("INSTRUMENT_BACKEND", "log");
+        env::set_var("INSTRUMENT_OUTPUT", &root_log);
+        let builder = ScopedRuntime::builder()
+            .kind(RuntimeKind::MainThread)
+            .backend(Backend::Log(LogBackend::open(&root_log, false).unwrap()));
+        RUNTIME.try_init_with(builder).unwrap();
+        RUNTIME.send_event(free(0x10));
+
+        // SAFETY: the child only sends events and exits without unwinding.
+        let child_pid = match unsafe { libc::fork() } {
+            -1 => panic!("fork failed: {}", std::io::Error::last_os_error()),
+            0 => {
+                RUNTIME.send_event(free(0x20));
+                RUNTIME.finalize();
+                // Skip the test harness, which is only running in the parent.
+                unsafe { libc::_exit(0) };
+            }
+            pid => pid,
+        };
+        let mut status = 0;
+        assert_eq!(
+            unsafe { libc::waitpid(child_pid, &mut status, 0) },
+            child_pid
+        );
+        assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+        RUNTIME.send_event(free(0x30));
+        RUNTIME.finalize();
+
+        let child_log = child_log_path(&root_log, child_pid as u32);
+        let parent = read_log(&root_log);
+        let child = read_log(&child_log);
+        fs_err::remove_file(&root_log).unwrap();
+        fs_err::remove_file(&child_log).unwrap();
+
+        let pid = process::id();
+        let stats = "0: stats(dropped = 0, sampled out = 0)".to_owned();
+        assert_eq!(
+            parent,
+            [
+                format!("0: process({pid}, Root)"),
+                "1: free(0x10)".to_owned(),
+                "0: fork".to_owned(),
+                "1: free(0x30)".to_owned(),
+                stats.clone(),
+            ]
+        );
+        assert_eq!(
+            child,
+            [
+                format!("0: process({child_pid}, Fork {{ parent_pid: {pid}, fork_idx: 0 }})"),
+                "1: free(0x20)".to_owned(),
+                stats,
+            ]
+        );
+    }
+
+    #[test]
+    fn stale_child_logs_removed() {
+        let dir = env::temp_dir().join(format!("c2rust-child-logs-test-{}", process::id()));
+        fs_err::create_dir_all(&dir).unwrap();
+        let root_log = dir.join("run.bc");
+        let names = [
+            "run.bc",
+            "run.bc.123",
+            "run.bc.45",
+            "run.bc.x",
+            "run.bcx.7",
+            "other.bc.9",
+        ];
+        for name in names {
+            fs_err::write(dir.join(name), "").unwrap();
+        }
+
+        assert_eq!(child_log_pids(&root_log).unwrap(), [45, 123]);
+        remove_child_logs(&root_log).unwrap();
+        assert_eq!(child_log_pids(&root_log).unwrap(), []);
+        let mut left = fs_err::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(left, ["other.bc.9", "run.bc", "run.bc.x", "run.bcx.7"]);
+        fs_err::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,17 +1,25 @@
-use std::process;
+use std::{
+    process, ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
 
 use once_cell::sync::OnceCell;
 
 use crate::events::Event;
 
 use super::{
-    scoped_runtime::{ExistingRuntime, ScopedRuntime},
+    fork,
+    scoped_runtime::{ExistingRuntime, RuntimeBuilder, RuntimeKind, ScopedRuntime},
     skip::{skip_event, SkipReason},
-    AnyError, Detect,
+    AnyError,
 };
 
 pub struct GlobalRuntime {
     runtime: OnceCell<ScopedRuntime>,
+
+    /// The runtime of a forked child, which replaces the [`ScopedRuntime`] inherited from
+    /// its parent.  It is leaked, as is any it replaces when the child forks again.
+    forked: AtomicPtr<ScopedRuntime>,
 }
 
 impl GlobalRuntime {
@@ -22,6 +30,49 @@ impl GlobalRuntime {
     const fn new() -> Self {
         Self {
             runtime: OnceCell::new(),
+            forked: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// The [`ScopedRuntime`] of this process, if it has been initialized.
+    ///
+    /// In a forked child, this first replaces the runtime inherited from the parent.
+    fn current(&self) -> Option<&ScopedRuntime> {
+        self.replace_after_fork();
+        let forked = self.forked.load(Ordering::Acquire);
+        // SAFETY: `forked` is either null or from `Box::into_raw`, and is never freed.
+        unsafe { forked.as_ref() }.or_else(|| self.runtime.get())
+    }
+
+    /// Replace the [`ScopedRuntime`] of a child process that has just forked.
+    ///
+    /// The child starts with only the thread that forked, so it can't use the parent's
+    /// background thread, and records its events on its own thread instead.
+    ///
+    /// # Async-signal-safety: only an atomic swap, except in a child that has just forked,
+    /// where building the new runtime is not safe (see the [limitations](fork#limitations)).
+    fn replace_after_fork(&self) {
+        let origin = match fork::take_fork_origin() {
+            Some(origin) => origin,
+            None => return,
+        };
+        if self.runtime.get().is_none() {
+            // Forked before `main`, so there's nothing to replace.
+            return;
+        }
+        let runtime = ScopedRuntime::builder()
+            .kind(RuntimeKind::MainThread)
+            .origin(origin)
+            .build();
+        match runtime {
+            Ok(runtime) => {
+                let runtime = Box::into_raw(Box::new(runtime));
+                self.forked.store(runtime, Ordering::Release);
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                process::exit(1);
+            }
         }
     }
 
@@ -43,8 +94,9 @@ impl GlobalRuntime {
     ///
     /// May be called from a signal handler, so it needs to be async-signal-safe.
     pub fn send_event(&self, event: Event) {
-        // # Async-signal-safety: OnceCell::get() is just a dereference
-        match self.runtime.get() {
+        // # Async-signal-safety: `current` is safe outside a newly forked child,
+        // and OnceCell::get() is just a dereference
+        match self.current() {
             None => {
                 // Silently drop the [`Event`] as the [`ScopedRuntime`] isn't ready/initialized yet.
                 //
@@ -57,11 +109,27 @@ impl GlobalRuntime {
         }
     }
 
-    /// Try to initialize the [`GlobalRuntime`] with [`ScopedRuntime::detect`].
+    /// Try to initialize the [`GlobalRuntime`] with a [`ScopedRuntime`] configured from the
+    /// environment, as in [`ScopedRuntime::detect`].
     ///
     /// This (or [`GlobalRuntime::init`]), on [`RUNTIME`], should be called at the top of `main`.
     pub fn try_init(&self) -> Result<&ScopedRuntime, AnyError> {
-        self.runtime.get_or_try_init(ScopedRuntime::detect)
+        self.try_init_with(ScopedRuntime::builder())
+    }
+
+    /// Try to initialize the [`GlobalRuntime`] with a [`ScopedRuntime`] configured by `builder`.
+    ///
+    /// Embedders should call this before the instrumented `main` runs,
+    /// as the [`GlobalRuntime::init`] there does nothing once it's already initialized.
+    ///
+    /// This also starts tracking forks, and records how this process started,
+    /// so that each process writes its own log (see [`fork`]).
+    pub fn try_init_with(&self, builder: RuntimeBuilder) -> Result<&ScopedRuntime, AnyError> {
+        let runtime = self
+            .runtime
+            .get_or_try_init(|| builder.origin(fork::detect_origin()).build())?;
+        fork::install_fork_handlers();
+        Ok(runtime)
     }
 
     /// Same as [`GlobalRuntime::try_init`], if there is an error,
@@ -82,7 +150,7 @@ impl GlobalRuntime {
     /// When used from [`RUNTIME`], a `static`, this must be called at the end of `main` to properly finalize,
     /// as `static` destructors are not run.
     pub fn finalize(&self) {
-        if let Some(runtime) = self.runtime.get() {
+        if let Some(runtime) = self.current() {
             runtime.finalize();
         }
     }
@@ -1,4 +1,5 @@
 pub mod backend;
+pub mod fork;
 pub mod global_runtime;
 pub mod scoped_runtime;
 pub mod skip;
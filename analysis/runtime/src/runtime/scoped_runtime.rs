@@ -1,5 +1,10 @@
 use std::{
-    sync::{Arc, Mutex},
+    env,
+    num::NonZeroU64,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
     thread,
 };
 
@@ -9,12 +14,13 @@ use enum_dispatch::enum_dispatch;
 use once_cell::sync::OnceCell;
 
 use crate::{
-    events::Event,
+    events::{Event, EventKind, ProcessOrigin},
     parse::{self, AsStr, GetChoices},
 };
 
 use super::{
     backend::{Backend, WriteEvent},
+    fork::process_event,
     skip::{skip_event, SkipReason},
     AnyError, Detect, FINISHED,
 };
@@ -52,6 +58,159 @@ impl Detect for RuntimeKind {
     }
 }
 
+/// The default capacity, in [`Event`]s, of the queue between a [`BackgroundThreadRuntime`]
+/// and its [`Backend`].
+pub const DEFAULT_BUFFER_SIZE: usize = 1 << 20;
+
+/// Which [`Event`]s a runtime records.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Sampling {
+    /// Record every [`Event`].
+    All,
+    /// Record only one of every `n` [`Event`]s, dropping the rest.
+    ///
+    /// This reduces overhead on long-running programs at the cost of completeness:
+    /// pointers whose origin was dropped appear in the PDG as derived from an unknown origin.
+    OneIn(NonZeroU64),
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+/// What a [`BackgroundThreadRuntime`] does with an [`Event`] when its queue is full.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait for the [`Backend`] to make room, slowing down the program.
+    Block,
+    /// Drop the [`Event`], counting it in the log's [`EventKind::Stats`] footer.
+    Drop,
+    /// Drop the [`Event`] like [`Backpressure::Drop`], and also halve the fraction of
+    /// [`Event`]s sampled, restoring it as the queue drains.
+    AdaptiveSampling,
+}
+
+impl AsStr for Backpressure {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::Drop => "drop",
+            Self::AdaptiveSampling => "adaptive",
+        }
+    }
+}
+
+impl GetChoices for Backpressure {
+    fn choices() -> &'static [Self] {
+        &[Self::Block, Self::Drop, Self::AdaptiveSampling]
+    }
+}
+
+impl Default for Backpressure {
+    fn default() -> Self {
+        Self::Block
+    }
+}
+
+impl Detect for Backpressure {
+    /// Read `$INSTRUMENT_BACKPRESSURE`, which is optional.
+    fn detect() -> Result<Self, AnyError> {
+        match env::var_os("INSTRUMENT_BACKPRESSURE") {
+            None => Ok(Self::default()),
+            Some(value) => Ok(*parse::one_of(&value)?),
+        }
+    }
+}
+
+/// The most that [`Backpressure::AdaptiveSampling`] reduces sampling by, as a power of 2.
+const MAX_ADAPTIVE_SHIFT: u32 = 16;
+
+/// Decides which [`Event`]s to record, and counts those that aren't.
+struct Sampler {
+    sampling: Sampling,
+    count: AtomicU64,
+    /// Sampling is further reduced by a factor of `1 << adaptive_shift`
+    /// by [`Backpressure::AdaptiveSampling`].
+    adaptive_shift: AtomicU32,
+    sampled_out: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Sampler {
+    fn new(sampling: Sampling) -> Self {
+        Self {
+            sampling,
+            count: AtomicU64::new(0),
+            adaptive_shift: AtomicU32::new(0),
+            sampled_out: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether the next [`Event`] should be recorded.
+    ///
+    /// # Async-signal-safety: atomic operations are safe.
+    fn should_record(&self) -> bool {
+        let n = match self.sampling {
+            Sampling::All => 1,
+            Sampling::OneIn(n) => n.get(),
+        };
+        let shift = self.adaptive_shift.load(Ordering::Relaxed);
+        let n = n.saturating_mul(1 << shift);
+        if n == 1 {
+            return true;
+        }
+        let record = self.count.fetch_add(1, Ordering::Relaxed) % n == 0;
+        if !record {
+            self.sampled_out.fetch_add(1, Ordering::Relaxed);
+        }
+        record
+    }
+
+    /// Count an [`Event`] dropped because the queue was full.
+    fn drop_event(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Sample less, in response to a full queue.
+    fn back_off(&self) {
+        let _ = self
+            .adaptive_shift
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |shift| {
+                (shift < MAX_ADAPTIVE_SHIFT).then_some(shift + 1)
+            });
+    }
+
+    /// Sample more, as the queue has drained.
+    fn recover(&self) {
+        let _ = self
+            .adaptive_shift
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |shift| {
+                shift.checked_sub(1)
+            });
+    }
+
+    /// The [`EventKind::Stats`] footer to end the log with.
+    fn stats_event(&self) -> Event {
+        Event {
+            mir_loc: 0,
+            kind: EventKind::Stats {
+                dropped: self.dropped.load(Ordering::Relaxed),
+                sampled_out: self.sampled_out.load(Ordering::Relaxed),
+            },
+        }
+    }
+}
+
+/// Configuration shared by all [`ScopedRuntime`]s.
+struct RuntimeConfig {
+    buffer_size: usize,
+    sampling: Sampling,
+    backpressure: Backpressure,
+}
+
 #[enum_dispatch]
 pub trait ExistingRuntime {
     /// Finalize the [`ExistingRuntime`].
@@ -66,7 +225,7 @@ pub trait ExistingRuntime {
 }
 
 trait Runtime: ExistingRuntime + Sized {
-    fn try_init(backend: Backend) -> Result<Self, AnyError>;
+    fn try_init(backend: Backend, config: RuntimeConfig) -> Result<Self, AnyError>;
 }
 
 #[enum_dispatch(ExistingRuntime)]
@@ -76,50 +235,152 @@ pub enum ScopedRuntime {
 }
 
 impl ScopedRuntime {
+    /// Configure a [`ScopedRuntime`] in code rather than through environment variables.
+    pub fn builder() -> RuntimeBuilder {
+        RuntimeBuilder::default()
+    }
+
     pub fn detect_kind(kind: RuntimeKind) -> Result<Self, AnyError> {
-        let backend = Backend::detect()?;
-        let this = match kind {
-            RuntimeKind::MainThread => Self::MainThread(MainThreadRuntime::try_init(backend)?),
-            RuntimeKind::BackgroundThread => {
-                Self::BackgroundThread(BackgroundThreadRuntime::try_init(backend)?)
-            }
-        };
-        Ok(this)
+        Self::builder().kind(kind).build()
     }
 }
 
 impl Detect for ScopedRuntime {
     fn detect() -> Result<Self, AnyError> {
-        Self::detect_kind(RuntimeKind::detect()?)
+        Self::builder().build()
+    }
+}
+
+/// A builder for a [`ScopedRuntime`], for embedders of this runtime that want to configure it
+/// without environment variables.
+///
+/// Anything not set explicitly is configured as [`ScopedRuntime::detect`] does:
+/// the [`RuntimeKind`] from `$INSTRUMENT_RUNTIME`, the [`Backend`] from `$INSTRUMENT_BACKEND`
+/// and the variables it needs, and the [`Backpressure`] from `$INSTRUMENT_BACKPRESSURE`.
+#[derive(Default)]
+pub struct RuntimeBuilder {
+    kind: Option<RuntimeKind>,
+    backend: Option<Backend>,
+    buffer_size: Option<usize>,
+    sampling: Sampling,
+    backpressure: Option<Backpressure>,
+    origin: Option<ProcessOrigin>,
+}
+
+impl RuntimeBuilder {
+    pub fn kind(mut self, kind: RuntimeKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Set the capacity, in [`Event`]s, of the queue between a [`BackgroundThreadRuntime`]
+    /// and its [`Backend`].  Defaults to [`DEFAULT_BUFFER_SIZE`].
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+
+    pub fn sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Set what happens when the queue of a [`BackgroundThreadRuntime`] is full.
+    pub fn backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.backpressure = Some(backpressure);
+        self
+    }
+
+    /// Set how this process started, which is written as the first [`Event`]
+    /// and decides where a detected [`Backend`] writes to.
+    pub fn origin(mut self, origin: ProcessOrigin) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    pub fn build(self) -> Result<ScopedRuntime, AnyError> {
+        let kind = match self.kind {
+            Some(kind) => kind,
+            None => RuntimeKind::detect()?,
+        };
+        let backend = match self.backend {
+            Some(backend) => backend,
+            None => Backend::detect_for(self.origin)?,
+        };
+        let buffer_size = self.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE);
+        if buffer_size == 0 {
+            return Err("runtime buffer size must be nonzero".into());
+        }
+        let backpressure = match self.backpressure {
+            Some(backpressure) => backpressure,
+            None => Backpressure::detect()?,
+        };
+        let config = RuntimeConfig {
+            buffer_size,
+            sampling: self.sampling,
+            backpressure,
+        };
+        let runtime = match kind {
+            RuntimeKind::MainThread => {
+                ScopedRuntime::MainThread(MainThreadRuntime::try_init(backend, config)?)
+            }
+            RuntimeKind::BackgroundThread => {
+                ScopedRuntime::BackgroundThread(BackgroundThreadRuntime::try_init(backend, config)?)
+            }
+        };
+        if let Some(origin) = self.origin {
+            runtime.send_event(process_event(origin));
+        }
+        Ok(runtime)
     }
 }
 
 pub struct MainThreadRuntime {
     backend: Mutex<Backend>,
+    sampler: Sampler,
+    finalized: OnceCell<()>,
 }
 
 impl ExistingRuntime for MainThreadRuntime {
     fn finalize(&self) {
-        self.backend.lock().unwrap().flush();
+        let mut backend = self.backend.lock().unwrap();
+        self.finalized.get_or_init(|| {
+            backend.write(self.sampler.stats_event());
+        });
+        backend.flush();
     }
 
     // # Async-signal-safety: NOT SAFE!!!
     // Do not use this with programs that install signal handlers.
     fn send_event(&self, event: Event) {
+        if !event.kind.is_structural() && !self.sampler.should_record() {
+            return;
+        }
         self.backend.lock().unwrap().write(event);
     }
 }
 
 impl Runtime for MainThreadRuntime {
-    fn try_init(backend: Backend) -> Result<Self, AnyError> {
+    fn try_init(backend: Backend, config: RuntimeConfig) -> Result<Self, AnyError> {
         let backend = Mutex::new(backend);
-        Ok(Self { backend })
+        Ok(Self {
+            backend,
+            sampler: Sampler::new(config.sampling),
+            finalized: OnceCell::new(),
+        })
     }
 }
 
 pub struct BackgroundThreadRuntime {
     tx: Arc<ArrayQueue<Event>>,
     finalized: OnceCell<()>,
+    sampler: Sampler,
+    backpressure: Backpressure,
 }
 
 impl BackgroundThreadRuntime {
@@ -140,13 +401,35 @@ impl BackgroundThreadRuntime {
             event = event_back;
         }
     }
+
+    /// Push an [`Event`] without blocking,
+    /// dropping it if the queue is full and adapting sampling if configured.
+    ///
+    /// # Async-signal-safety: `ArrayQueue::push` and atomic operations are safe.
+    fn try_push_event(&self, event: Event) {
+        let adaptive = self.backpressure == Backpressure::AdaptiveSampling;
+        match self.tx.push(event) {
+            Ok(()) => {
+                if adaptive && self.tx.len() < self.tx.capacity() / 4 {
+                    self.sampler.recover();
+                }
+            }
+            Err(_) => {
+                self.sampler.drop_event();
+                if adaptive {
+                    self.sampler.back_off();
+                }
+            }
+        }
+    }
 }
 
 impl ExistingRuntime for BackgroundThreadRuntime {
     fn finalize(&self) {
         // Only run the finalizer once.
         self.finalized.get_or_init(|| {
-            // Notify the backend that we're done.
+            // Record how complete the log is, then notify the backend that we're done.
+            self.push_event(self.sampler.stats_event(), true);
             self.push_event(Event::done(), true);
 
             // Wait for the backend thread to finish.
@@ -166,11 +449,16 @@ impl ExistingRuntime for BackgroundThreadRuntime {
     /// Otherwise, it sends the [`Event`] to the channel,
     /// panicking if there is a [`SendError`](std::sync::mpsc::SendError).
     fn send_event(&self, event: Event) {
+        // # Async-signal-safety: `should_record` is safe.
+        if !event.kind.is_structural() && !self.sampler.should_record() {
+            return;
+        }
         match self.finalized.get() {
-            None => {
+            None => match self.backpressure {
                 // # Async-signal-safety: `push_event` is safe if `can_sleep == false`
-                self.push_event(event, false);
-            }
+                Backpressure::Block => self.push_event(event, false),
+                Backpressure::Drop | Backpressure::AdaptiveSampling => self.try_push_event(event),
+            },
             Some(()) => {
                 // Silently drop the [`Event`] as the [`BackgroundThreadRuntime`]
                 // has already been [`BackgroundThreadRuntime::finalize`]d.
@@ -196,13 +484,15 @@ impl Drop for BackgroundThreadRuntime {
 impl Runtime for BackgroundThreadRuntime {
     /// Initialize the [`BackgroundThreadRuntime`], which includes [`thread::spawn`]ing,
     /// so it must be run post-`main`.
-    fn try_init(mut backend: Backend) -> Result<Self, AnyError> {
-        let tx = Arc::new(ArrayQueue::new(1 << 20));
+    fn try_init(mut backend: Backend, config: RuntimeConfig) -> Result<Self, AnyError> {
+        let tx = Arc::new(ArrayQueue::new(config.buffer_size));
         let rx = Arc::clone(&tx);
         thread::spawn(move || backend.run(rx));
         Ok(Self {
             tx,
             finalized: OnceCell::new(),
+            sampler: Sampler::new(config.sampling),
+            backpressure: config.backpressure,
         })
     }
 }
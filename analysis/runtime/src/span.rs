@@ -0,0 +1,384 @@
+//! A compact, deduplicated table of the source locations in the [`Metadata`].
+//!
+//! Each distinct file name is stored once, and each distinct span once, as an index into the
+//! file names and its line and column numbers.  [`EventMetadata::span`] refers to a span by its
+//! [`SpanId`].  When serialized, each span is delta-encoded against the previous one and written
+//! as variable-length integers, as consecutive spans are usually in the same file and on nearby
+//! lines.
+//!
+//! [`Metadata`]: crate::metadata::Metadata
+//! [`EventMetadata::span`]: crate::mir_loc::EventMetadata::span
+
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+/// An index into a [`SpanTable`].
+pub type SpanId = u32;
+
+/// A position in a source file, with 1-based line and column numbers.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SourcePos {
+    pub line: u32,
+    pub col: u32,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SourceSpan {
+    /// An index into the file names of the [`SpanTable`].
+    pub file: u32,
+    pub lo: SourcePos,
+    pub hi: SourcePos,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(try_from = "EncodedSpanTable")]
+pub struct SpanTable {
+    files: Vec<String>,
+    spans: Vec<SourceSpan>,
+}
+
+impl SpanTable {
+    pub fn get(&self, id: SpanId) -> Option<&SourceSpan> {
+        self.spans.get(id as usize)
+    }
+
+    pub fn file(&self, span: &SourceSpan) -> &str {
+        &self.files[span.file as usize]
+    }
+
+    /// Iterate over the spans in order of [`SpanId`], along with their file names.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &SourceSpan)> + '_ {
+        self.spans.iter().map(|span| (self.file(span), span))
+    }
+
+    /// Display `span` as `file:line:col: line:col`, or `no-location` if there is none.
+    pub fn display(&self, span: Option<SpanId>) -> DisplaySpan<'_> {
+        DisplaySpan { table: self, span }
+    }
+}
+
+pub struct DisplaySpan<'a> {
+    table: &'a SpanTable,
+    span: Option<SpanId>,
+}
+
+impl Display for DisplaySpan<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let span = match self.span.and_then(|id| self.table.get(id)) {
+            Some(span) => span,
+            None => return write!(f, "no-location"),
+        };
+        let SourceSpan { lo, hi, .. } = span;
+        write!(
+            f,
+            "{}:{}:{}: {}:{}",
+            self.table.file(span),
+            lo.line,
+            lo.col,
+            hi.line,
+            hi.col
+        )
+    }
+}
+
+/// Builds a [`SpanTable`], deduplicating file names and spans.
+#[derive(Default)]
+pub struct SpanInterner {
+    table: SpanTable,
+    file_ids: HashMap<String, u32>,
+    span_ids: HashMap<SourceSpan, SpanId>,
+}
+
+impl SpanInterner {
+    pub fn insert(&mut self, file: &str, lo: SourcePos, hi: SourcePos) -> SpanId {
+        let files = &mut self.table.files;
+        let file = match self.file_ids.get(file) {
+            Some(&id) => id,
+            None => {
+                let id = files.len().try_into().unwrap();
+                files.push(file.to_owned());
+                self.file_ids.insert(file.to_owned(), id);
+                id
+            }
+        };
+        let spans = &mut self.table.spans;
+        *self
+            .span_ids
+            .entry(SourceSpan { file, lo, hi })
+            .or_insert_with_key(|&span| {
+                spans.push(span);
+                (spans.len() - 1).try_into().unwrap()
+            })
+    }
+
+    pub fn finish(self) -> SpanTable {
+        self.table
+    }
+}
+
+/// The serialized form of a [`SpanTable`].
+#[derive(Serialize, Deserialize)]
+struct EncodedSpanTable {
+    files: Vec<String>,
+    /// The delta-encoded spans, as LEB128 integers.
+    spans: Vec<u8>,
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, String> {
+    let mut n = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes
+            .split_first()
+            .ok_or_else(|| "truncated span table".to_owned())?;
+        *bytes = rest;
+        // The 10th byte only has room for the top bit of a `u64`.
+        if shift == 63 && byte & 0x7f > 1 {
+            break;
+        }
+        n |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err("overlong integer in span table".into())
+}
+
+/// Map signed deltas to unsigned integers so that small negative deltas stay small.
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+impl From<&SpanTable> for EncodedSpanTable {
+    fn from(table: &SpanTable) -> Self {
+        let mut bytes = Vec::new();
+        let mut prev = SourceSpan::default();
+        for span in &table.spans {
+            let delta = |cur: u32, prev: u32| zigzag(i64::from(cur) - i64::from(prev));
+            write_varint(&mut bytes, delta(span.file, prev.file));
+            write_varint(&mut bytes, delta(span.lo.line, prev.lo.line));
+            write_varint(&mut bytes, span.lo.col.into());
+            write_varint(&mut bytes, delta(span.hi.line, span.lo.line));
+            write_varint(&mut bytes, span.hi.col.into());
+            prev = *span;
+        }
+        Self {
+            files: table.files.clone(),
+            spans: bytes,
+        }
+    }
+}
+
+impl TryFrom<EncodedSpanTable> for SpanTable {
+    type Error = String;
+
+    fn try_from(encoded: EncodedSpanTable) -> Result<Self, Self::Error> {
+        let EncodedSpanTable {
+            files,
+            spans: bytes,
+        } = encoded;
+        let mut bytes = &bytes[..];
+        let mut spans = Vec::new();
+        let mut prev = SourceSpan::default();
+        while !bytes.is_empty() {
+            let mut next = |base: u32, signed: bool| -> Result<u32, String> {
+                let n = read_varint(&mut bytes)?;
+                let n = if signed {
+                    i64::from(base).checked_add(unzigzag(n))
+                } else {
+                    i64::try_from(n).ok()
+                };
+                n.and_then(|n| u32::try_from(n).ok())
+                    .ok_or_else(|| "span table entry out of range".to_owned())
+            };
+            let file = next(prev.file, true)?;
+            let lo_line = next(prev.lo.line, true)?;
+            let lo_col = next(0, false)?;
+            let hi_line = next(lo_line, true)?;
+            let hi_col = next(0, false)?;
+            if file as usize >= files.len() {
+                return Err(format!("span table refers to missing file {file}"));
+            }
+            let span = SourceSpan {
+                file,
+                lo: SourcePos {
+                    line: lo_line,
+                    col: lo_col,
+                },
+                hi: SourcePos {
+                    line: hi_line,
+                    col: hi_col,
+                },
+            };
+            spans.push(span);
+            prev = span;
+        }
+        Ok(Self { files, spans })
+    }
+}
+
+impl Serialize for SpanTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EncodedSpanTable::from(self).serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(line: u32, col: u32) -> SourcePos {
+        SourcePos { line, col }
+    }
+
+    fn round_trip(table: &SpanTable) -> SpanTable {
+        let bytes = bincode::serialize(table).unwrap();
+        bincode::deserialize(&bytes).unwrap()
+    }
+
+    fn read_all(mut bytes: &[u8]) -> Result<u64, String> {
+        let n = read_varint(&mut bytes)?;
+        assert!(bytes.is_empty());
+        Ok(n)
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        for n in [
+            0,
+            1,
+            0x7f,
+            0x80,
+            0x3fff,
+            0x4000,
+            u32::MAX.into(),
+            u64::MAX - 1,
+            u64::MAX,
+        ] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, n);
+            assert_eq!(read_all(&bytes), Ok(n));
+        }
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, u64::MAX);
+        assert_eq!(bytes.len(), 10);
+    }
+
+    #[test]
+    fn varint_truncated() {
+        assert_eq!(read_all(&[]), Err("truncated span table".to_owned()));
+        assert_eq!(read_all(&[0x80]), Err("truncated span table".to_owned()));
+        assert_eq!(read_all(&[0xff; 9]), Err("truncated span table".to_owned()));
+    }
+
+    #[test]
+    fn varint_overlong() {
+        let overlong = Err("overlong integer in span table".to_owned());
+        // 11 bytes.
+        let mut bytes = vec![0x80; 10];
+        bytes.push(0x01);
+        assert_eq!(read_all(&bytes), overlong);
+        // 10 bytes, but more than 64 bits.
+        let mut bytes = vec![0xff; 9];
+        bytes.push(0x02);
+        assert_eq!(read_all(&bytes), overlong);
+    }
+
+    #[test]
+    fn zigzag_round_trip() {
+        for n in [
+            0,
+            1,
+            -1,
+            2,
+            -2,
+            i64::from(u32::MAX),
+            -i64::from(u32::MAX),
+            i64::MAX,
+            i64::MIN,
+        ] {
+            assert_eq!(unzigzag(zigzag(n)), n);
+        }
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+    }
+
+    #[test]
+    fn empty_table() {
+        let table = SpanInterner::default().finish();
+        let encoded = EncodedSpanTable::from(&table);
+        assert!(encoded.files.is_empty());
+        assert!(encoded.spans.is_empty());
+        let decoded = round_trip(&table);
+        assert_eq!(decoded.iter().count(), 0);
+        assert_eq!(decoded.display(Some(0)).to_string(), "no-location");
+    }
+
+    #[test]
+    fn table_round_trip() {
+        let mut spans = SpanInterner::default();
+        let spans_in = [
+            ("a.rs", pos(100, 5), pos(102, 1)),
+            // Negative line and file deltas.
+            ("b.rs", pos(3, 1), pos(3, 20)),
+            ("a.rs", pos(1, 1), pos(1, 9)),
+            // The largest values a span can hold, and a jump back down from them.
+            ("b.rs", pos(u32::MAX, u32::MAX), pos(u32::MAX, u32::MAX)),
+            ("a.rs", pos(0, 0), pos(0, 0)),
+        ];
+        let ids = spans_in
+            .iter()
+            .map(|&(file, lo, hi)| spans.insert(file, lo, hi))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [0, 1, 2, 3, 4]);
+        // Duplicates are interned.
+        assert_eq!(spans.insert("b.rs", pos(3, 1), pos(3, 20)), 1);
+
+        let table = round_trip(&spans.finish());
+        let spans_out = table
+            .iter()
+            .map(|(file, span)| (file, span.lo, span.hi))
+            .collect::<Vec<_>>();
+        assert_eq!(spans_out, spans_in);
+        assert_eq!(table.display(Some(1)).to_string(), "b.rs:3:1: 3:20");
+    }
+
+    #[test]
+    fn invalid_tables() {
+        let decode = |files: &[&str], spans: Vec<u8>| {
+            let files = files.iter().map(|&f| f.to_owned()).collect();
+            SpanTable::try_from(EncodedSpanTable { files, spans }).map(|_| ())
+        };
+        // A span cut off after its file and line.
+        assert_eq!(
+            decode(&["a.rs"], vec![0, 2]),
+            Err("truncated span table".to_owned())
+        );
+        // A file index past the end of the file names.
+        assert_eq!(
+            decode(&["a.rs"], vec![2, 2, 1, 0, 1]),
+            Err("span table refers to missing file 1".to_owned())
+        );
+        // A line number below 0.
+        assert_eq!(
+            decode(&["a.rs"], vec![0, 1, 1, 0, 1]),
+            Err("span table entry out of range".to_owned())
+        );
+    }
+}
@@ -1,5 +1,11 @@
 use crate::annotate::AnnotationBuffer;
+use crate::api::{AnalysisResults, FnResults, PointerResults};
+use crate::arc_mutex::{self, ThreadSpawn};
+use crate::assignment_file::{self, AssignmentWriter};
 use crate::borrowck;
+use crate::c_strings;
+use crate::cfg_sets;
+use crate::const_prop;
 use crate::context::{
     self, AnalysisCtxt, AnalysisCtxtData, DontRewriteFieldReason, DontRewriteFnReason,
     DontRewriteStaticReason, FlagSet, GlobalAnalysisCtxt, GlobalAssignment, LFnSig, LTy, LTyCtxt,
@@ -9,6 +15,7 @@ use crate::dataflow;
 use crate::dataflow::DataflowConstraints;
 use crate::equiv::GlobalEquivSet;
 use crate::equiv::LocalEquivSet;
+use crate::free_policy;
 use crate::labeled_ty::LabeledTyCtxt;
 use crate::panic_detail;
 use crate::panic_detail::PanicDetail;
@@ -17,21 +24,30 @@ use crate::pointee_type::PointeeTypes;
 use crate::pointer_id::GlobalPointerTable;
 use crate::pointer_id::LocalPointerTable;
 use crate::pointer_id::PointerTable;
+use crate::profile::{self, Pass};
+use crate::progress;
+use crate::rc_refcell;
+use crate::realloc_vecs;
 use crate::recent_writes::RecentWrites;
+use crate::returned_slice_len::{self, ReturnedSliceLen};
 use crate::rewrite;
+use crate::summary;
 use crate::type_desc;
 use crate::type_desc::Ownership;
 use crate::util;
 use crate::util::Callee;
 use crate::util::TestAttr;
 use ::log::warn;
+use c2rust_pdg::compare::StaticLocalPerms;
 use c2rust_pdg::graph::Graphs;
+use rustc_hir as hir;
 use rustc_hir::def::DefKind;
 use rustc_hir::def_id::CrateNum;
 use rustc_hir::def_id::DefId;
 use rustc_hir::def_id::DefIndex;
 use rustc_hir::def_id::LocalDefId;
 use rustc_hir::definitions::DefPathData;
+use rustc_hir::intravisit;
 use rustc_index::vec::IndexVec;
 use rustc_middle::mir::visit::{PlaceContext, Visitor};
 use rustc_middle::mir::{
@@ -42,6 +58,7 @@ use rustc_middle::ty::GenericArgKind;
 use rustc_middle::ty::Ty;
 use rustc_middle::ty::TyCtxt;
 use rustc_middle::ty::TyKind;
+use rustc_middle::ty::TypeckResults;
 use rustc_middle::ty::WithOptConstParam;
 use rustc_span::{Span, Symbol};
 use std::collections::HashMap;
@@ -50,7 +67,7 @@ use std::env;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::fmt::Write as _;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
@@ -59,6 +76,7 @@ use std::ops::Deref;
 use std::ops::DerefMut;
 use std::ops::Index;
 use std::panic::AssertUnwindSafe;
+use std::path::Path;
 use std::str::FromStr;
 
 /// A wrapper around `T` that dynamically tracks whether it's initialized or not.
@@ -392,6 +410,43 @@ fn mark_foreign_fixed<'tcx>(
     }
 }
 
+/// Stop trusting the pointers passed to exported functions by external callers.  Under the `check`
+/// policy, the pointer inputs of every function callable from outside the crate lose `NON_NULL`,
+/// and the analysis is forbidden from adding it back.  Under the `fixed` policy, they're `FIXED`.
+fn mark_extern_inputs_untrusted(
+    gacx: &GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    g_updates_forbidden: &mut GlobalPointerTable<PermissionSet>,
+    policy: &str,
+) {
+    let tcx = gacx.tcx;
+    for (did, lsig) in gacx.fn_sigs.iter() {
+        if !did.is_local()
+            || tcx.is_foreign_item(did)
+            || !tcx.codegen_fn_attrs(did).contains_extern_indicator()
+        {
+            continue;
+        }
+        match policy {
+            "check" => {
+                for lty in lsig.inputs.iter().flat_map(|lty| lty.iter()) {
+                    let ptr = lty.label;
+                    if !ptr.is_none() {
+                        gasn.perms[ptr].remove(PermissionSet::NON_NULL);
+                        g_updates_forbidden[ptr].insert(PermissionSet::NON_NULL);
+                    }
+                }
+            }
+            "fixed" => {
+                for &lty in lsig.inputs {
+                    make_ty_fixed(gasn, lty);
+                }
+            }
+            _ => panic!("unknown policy for untrusted extern inputs: {policy:?}"),
+        }
+    }
+}
+
 fn mark_all_statics_fixed<'tcx>(gacx: &mut GlobalAnalysisCtxt<'tcx>, gasn: &mut GlobalAssignment) {
     for (did, lty) in gacx.static_tys.iter() {
         make_ty_fixed(gasn, lty);
@@ -536,6 +591,9 @@ fn get_fixed_defs(tcx: TyCtxt) -> io::Result<HashSet<DefId>> {
     if let Ok(prefixes) = env::var("C2RUST_ANALYZE_REWRITE_PATHS") {
         check_rewrite_path_prefixes(tcx, &mut fixed_defs, &prefixes);
     }
+    if let Ok(path) = env::var("C2RUST_ANALYZE_CFG_DIVERGENT_FNS") {
+        cfg_sets::read_divergent_fns(tcx, &mut fixed_defs, &path)?;
+    }
     Ok(fixed_defs)
 }
 
@@ -569,14 +627,44 @@ struct FuncInfo<'tcx> {
     recent_writes: MaybeUnset<RecentWrites>,
 }
 
-fn run(tcx: TyCtxt) {
-    eprintln!("all defs:");
-    for ldid in tcx.hir_crate_items(()).definitions() {
-        eprintln!("{:?}", ldid);
+/// Run the analysis and generate rewrites for the crate.  If `apply_rewrites` is set, the rewrites
+/// are also applied as configured by `C2RUST_ANALYZE_REWRITE_MODE`.
+pub(crate) fn run(tcx: TyCtxt, apply_rewrites: bool) -> AnalysisResults {
+    progress::init();
+    free_policy::init();
+
+    if !progress::quiet() {
+        eprintln!("all defs:");
+        for ldid in tcx.hir_crate_items(()).definitions() {
+            eprintln!("{:?}", ldid);
+        }
     }
 
     // Load the list of fixed defs early, so any errors are reported immediately.
-    let fixed_defs = get_fixed_defs(tcx).unwrap();
+    let mut fixed_defs = get_fixed_defs(tcx).unwrap();
+
+    // Growable array structs are rewritten separately, so the struct and the functions that use
+    // its fields are excluded from the main analysis.
+    let mut vec_structs = Vec::new();
+    if env::var("C2RUST_ANALYZE_REWRITE_VECS").as_deref() == Ok("1") {
+        vec_structs = rewrite::find_vec_structs(tcx, &fixed_defs, &foreign_mentioned_tys(tcx));
+        for vs in &vec_structs {
+            fixed_defs.extend(vs.fixed_defs());
+        }
+    }
+
+    // Destructors replaced by `Drop` impls, and the fields they free, are excluded from the main
+    // analysis too.  Calls to the destructors are analyzed as calls to `free`.
+    let mut drop_impls = Vec::new();
+    if env::var("C2RUST_ANALYZE_DROP_IMPLS").as_deref() == Ok("1") {
+        drop_impls = rewrite::find_drop_impls(tcx, &fixed_defs, &foreign_mentioned_tys(tcx));
+        for di in &drop_impls {
+            fixed_defs.extend(di.fixed_defs());
+        }
+    }
+    rewrite::set_drop_fns(&drop_impls);
+
+    profile::init();
 
     let rewrite_pointwise = env::var("C2RUST_ANALYZE_REWRITE_MODE")
         .ok()
@@ -585,15 +673,22 @@ fn run(tcx: TyCtxt) {
     let mut gacx = GlobalAnalysisCtxt::new(tcx);
     let mut func_info = HashMap::new();
 
+    if let Ok(dir) = env::var("C2RUST_ANALYZE_SUMMARY_DIR") {
+        gacx.fn_summaries = summary::read_summaries(tcx, Path::new(&dir)).unwrap();
+    }
+
     // Follow a postorder traversal, so that callers are visited after their callees.  This means
     // callee signatures will usually be up to date when we visit the call site.
     let all_fn_ldids = fn_body_owners_postorder(tcx);
-    eprintln!("callgraph traversal order:");
-    for &ldid in &all_fn_ldids {
-        eprintln!("  {:?}", ldid);
+    if !progress::quiet() {
+        eprintln!("callgraph traversal order:");
+        for &ldid in &all_fn_ldids {
+            eprintln!("  {:?}", ldid);
+        }
     }
 
     populate_field_users(&mut gacx, &all_fn_ldids);
+    gacx.arg_consts = const_prop::propagate_arg_consts(tcx, &all_fn_ldids);
 
     // ----------------------------------
     // Label all global types
@@ -626,9 +721,11 @@ fn run(tcx: TyCtxt) {
 
     // Collect all `static` items.
     let all_static_dids = all_static_items(tcx);
-    eprintln!("statics:");
-    for &did in &all_static_dids {
-        eprintln!("  {:?}", did);
+    if !progress::quiet() {
+        eprintln!("statics:");
+        for &did in &all_static_dids {
+            eprintln!("  {:?}", did);
+        }
     }
 
     // Assign global `PointerId`s for types of `static` items.
@@ -656,7 +753,9 @@ fn run(tcx: TyCtxt) {
     // Infer pointee types
     // ----------------------------------
 
+    progress::start("pointee type constraints", all_fn_ldids.len());
     for &ldid in &all_fn_ldids {
+        progress::step();
         if gacx.fn_analysis_invalid(ldid.to_def_id()) {
             continue;
         }
@@ -701,7 +800,11 @@ fn run(tcx: TyCtxt) {
             label_rvalue_tys(&mut acx, &mir);
             update_pointer_info(&mut acx, &mir);
 
-            pointee_type::generate_constraints(&acx, &mir)
+            profile::time(Pass::PointeeTypes, ldid, || {
+                let pointee_constraints = pointee_type::generate_constraints(&acx, &mir);
+                profile::count(pointee_constraints.constraints.len());
+                pointee_constraints
+            })
         }));
 
         let mut info = FuncInfo::default();
@@ -721,6 +824,7 @@ fn run(tcx: TyCtxt) {
         info.recent_writes.set(RecentWrites::new(&mir));
         func_info.insert(ldid, info);
     }
+    progress::finish();
 
     // Iterate pointee constraints to a fixpoint.
     let mut global_pointee_types = GlobalPointerTable::<PointeeTypes>::new(gacx.num_pointers());
@@ -741,7 +845,9 @@ fn run(tcx: TyCtxt) {
             tys.incomplete = false;
         }
 
+        progress::start("pointee types", all_fn_ldids.len());
         for &ldid in &all_fn_ldids {
+            progress::step();
             if gacx.fn_analysis_invalid(ldid.to_def_id()) {
                 continue;
             }
@@ -750,8 +856,17 @@ fn run(tcx: TyCtxt) {
 
             let pointee_constraints = info.pointee_constraints.get();
             let pointee_types = global_pointee_types.and_mut(info.local_pointee_types.get_mut());
-            pointee_type::solve_constraints(pointee_constraints, pointee_types);
+            let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+                profile::time(Pass::PointeeTypes, ldid, || {
+                    pointee_type::solve_constraints(pointee_constraints, pointee_types)
+                })
+            }));
+
+            if let Err(pd) = r {
+                gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+            }
         }
+        progress::finish();
 
         if global_pointee_types == old_global_pointee_types {
             break;
@@ -772,9 +887,15 @@ fn run(tcx: TyCtxt) {
         let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
         let name = tcx.item_name(ldid.to_def_id());
         let pointee_types = global_pointee_types.and(info.local_pointee_types.get());
-        print_function_pointee_types(&acx, name, &mir, pointee_types);
+        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+            print_function_pointee_types(&acx, name, &mir, pointee_types);
+        }));
 
         info.acx_data.set(acx.into_data());
+
+        if let Err(pd) = r {
+            gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+        }
     }
 
     // ----------------------------------
@@ -785,7 +906,10 @@ fn run(tcx: TyCtxt) {
     // that two pointer types must be converted to the same reference type.  Some additional data
     // computed during this the process is kept around for use in later passes.
     let mut global_equiv = GlobalEquivSet::new(gacx.num_pointers());
+    unify_static_fn_ptrs(&gacx, &mut global_equiv);
+    progress::start("dataflow constraints", all_fn_ldids.len());
     for &ldid in &all_fn_ldids {
+        progress::step();
         if gacx.fn_analysis_invalid(ldid.to_def_id()) {
             continue;
         }
@@ -800,7 +924,12 @@ fn run(tcx: TyCtxt) {
         let pointee_types = global_pointee_types.and(info.local_pointee_types.get());
 
         let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
-            dataflow::generate_constraints(&acx, &mir, recent_writes, pointee_types)
+            profile::time(Pass::Dataflow, ldid, || {
+                let (dataflow, equiv_constraints) =
+                    dataflow::generate_constraints(&acx, &mir, recent_writes, pointee_types);
+                profile::count(dataflow.num_constraints() + equiv_constraints.len());
+                (dataflow, equiv_constraints)
+            })
         }));
 
         let (dataflow, equiv_constraints) = match r {
@@ -823,6 +952,7 @@ fn run(tcx: TyCtxt) {
         info.dataflow.set(dataflow);
         info.local_equiv.set(local_equiv);
     }
+    progress::finish();
 
     // ----------------------------------
     // Remap `PointerId`s by equivalence class
@@ -831,7 +961,9 @@ fn run(tcx: TyCtxt) {
     // Remap pointers based on equivalence classes, so all members of an equivalence class now use
     // the same `PointerId`.
     let (global_counter, global_equiv_map) = global_equiv.renumber();
-    eprintln!("global_equiv_map = {global_equiv_map:?}");
+    if !progress::quiet() {
+        eprintln!("global_equiv_map = {global_equiv_map:?}");
+    }
     pointee_type::remap_pointers_global(
         &mut global_pointee_types,
         &global_equiv_map,
@@ -846,7 +978,9 @@ fn run(tcx: TyCtxt) {
 
         let info = func_info.get_mut(&ldid).unwrap();
         let (local_counter, local_equiv_map) = info.local_equiv.renumber(&global_equiv_map);
-        eprintln!("local_equiv_map = {local_equiv_map:?}");
+        if !progress::quiet() {
+            eprintln!("local_equiv_map = {local_equiv_map:?}");
+        }
         pointee_type::remap_pointers_local(
             &mut global_pointee_types,
             &mut info.local_pointee_types,
@@ -902,6 +1036,10 @@ fn run(tcx: TyCtxt) {
 
     mark_foreign_fixed(&mut gacx, &mut gasn, tcx);
 
+    if let Ok(policy) = env::var("C2RUST_ANALYZE_UNTRUSTED_EXTERN_INPUTS") {
+        mark_extern_inputs_untrusted(&gacx, &mut gasn, &mut g_updates_forbidden, &policy);
+    }
+
     if rewrite_pointwise {
         // In pointwise mode, we restrict rewriting to a single fn at a time.  All statics and
         // struct fields are marked `FIXED` so they won't be rewritten.
@@ -935,7 +1073,9 @@ fn run(tcx: TyCtxt) {
     let mut func_def_path_hash_to_ldid = HashMap::new();
     for &ldid in &all_fn_ldids {
         let def_path_hash: (u64, u64) = tcx.def_path_hash(ldid.to_def_id()).0.as_value();
-        eprintln!("def_path_hash {:?} = {:?}", def_path_hash, ldid);
+        if !progress::quiet() {
+            eprintln!("def_path_hash {:?} = {:?}", def_path_hash, ldid);
+        }
         func_def_path_hash_to_ldid.insert(def_path_hash, ldid);
     }
 
@@ -1022,11 +1162,15 @@ fn run(tcx: TyCtxt) {
                     }
                 };
 
+                // If the node's origin wasn't observed, some of its uses may be missing from the
+                // trace, so the absence of evidence (e.g. of NULL values) proves nothing.
+                let uncertain = n.info.as_ref().map_or(false, |info| info.uncertain);
+
                 let old_perms = asn.perms()[ptr];
                 let mut perms = old_perms;
                 if known_nulls.contains(&(n.function.id, dest)) {
                     perms.remove(PermissionSet::NON_NULL);
-                } else if allow_unsound {
+                } else if allow_unsound && !uncertain {
                     perms.insert(PermissionSet::NON_NULL);
                     // Unsound update: if we have never seen a NULL for
                     // this local in the PDG, prevent the static analysis
@@ -1097,7 +1241,7 @@ fn run(tcx: TyCtxt) {
                     // attribute to cause it to be marked FIXED.  If the whole ADT is
                     // listed/annotated, then every field is marked FIXED.
                     let field_fixed = def_fixed
-                        || fixed_defs.contains(&ldid.to_def_id())
+                        || fixed_defs.contains(&field.did)
                         || field.did.as_local().map_or(false, |ldid| {
                             util::has_test_attr(tcx, ldid, TestAttr::FixedSignature)
                         });
@@ -1148,9 +1292,168 @@ fn run(tcx: TyCtxt) {
         &mut g_updates_forbidden,
     );
 
-    eprintln!("=== ADT Metadata ===");
-    eprintln!("{:?}", gacx.adt_metadata);
+    if !progress::quiet() {
+        eprintln!("=== ADT Metadata ===");
+        eprintln!("{:?}", gacx.adt_metadata);
+    }
+
+    if let Ok(path) = env::var("C2RUST_ANALYZE_LOAD_ASSIGNMENT") {
+        // Skip the solver and use the assignment from the file instead.
+        // Errors in the file, which may have been edited by hand, are reported as normal
+        // compiler errors rather than panics.
+        let file = assignment_file::read_assignment_file(&path, parse_def_id).unwrap_or_else(|e| {
+            tcx.sess
+                .fatal(format!("failed to load assignment file: {e}"))
+        });
+        let fail = |e: String| -> ! {
+            tcx.sess
+                .fatal(format!("failed to load assignment file {path}: {e}"))
+        };
+        if let Err(e) = file.apply_global(&mut gasn) {
+            fail(e);
+        }
+        for &ldid in &all_fn_ldids {
+            let info = func_info.get_mut(&ldid).unwrap();
+            let found = match file.apply_local(ldid.to_def_id(), &mut info.lasn) {
+                Ok(found) => found,
+                Err(e) => fail(e),
+            };
+            if !found {
+                warn!("assignment file {path:?} has no entry for {ldid:?}");
+            }
+        }
+    } else {
+        solve_assignment(
+            tcx,
+            &mut gacx,
+            &mut gasn,
+            &g_updates_forbidden,
+            &mut func_info,
+            &all_fn_ldids,
+        );
+
+        if env::var("C2RUST_ANALYZE_C_STRINGS").is_ok() {
+            infer_c_strings(tcx, &mut gacx, &mut gasn, &mut func_info, &all_fn_ldids);
+        }
+
+        if env::var("C2RUST_ANALYZE_REALLOC_VECS").is_ok() {
+            infer_realloc_vecs(tcx, &mut gacx, &mut gasn, &mut func_info, &all_fn_ldids);
+        }
+
+        if env::var("C2RUST_ANALYZE_RC_REFCELL").is_ok() {
+            infer_rc_refcells(tcx, &mut gacx, &mut gasn, &mut func_info, &all_fn_ldids);
+        }
+
+        if env::var("C2RUST_ANALYZE_ARC_MUTEX").is_ok() {
+            infer_arc_mutexes(tcx, &mut gacx, &mut gasn, &mut func_info, &all_fn_ldids);
+        }
+    }
+
+    if let Ok(path) = env::var("C2RUST_ANALYZE_DUMP_ASSIGNMENT") {
+        dump_assignment(&path, tcx, &mut gacx, &gasn, &mut func_info, &all_fn_ldids).unwrap();
+    }
+
+    if let Ok(path) = env::var("C2RUST_ANALYZE_DUMP_LOCAL_PERMS") {
+        dump_local_perms(
+            &path,
+            tcx,
+            &mut gacx,
+            &mut gasn,
+            &mut func_info,
+            &all_fn_ldids,
+        )
+        .unwrap();
+    }
+
+    if let Ok(dir) = env::var("C2RUST_ANALYZE_SUMMARY_DIR") {
+        summary::write_summaries(Path::new(&dir), tcx, &gacx, &gasn).unwrap();
+    }
+
+    // Check that these perms haven't changed.
+    let mut known_perm_error_ptrs = HashSet::new();
+    for (ptr, perms) in gacx.known_fn_ptr_perms() {
+        if gasn.perms[ptr] != perms {
+            known_perm_error_ptrs.insert(ptr);
+            warn!(
+                "known permissions changed for PointerId {ptr:?}: {perms:?} -> {:?}",
+                gasn.perms[ptr]
+            );
+        }
+    }
+
+    let mut known_perm_error_fns = HashSet::new();
+    for (&def_id, lsig) in &gacx.fn_sigs {
+        if !tcx.is_foreign_item(def_id) {
+            continue;
+        }
+        for lty in lsig.inputs_and_output().flat_map(|lty| lty.iter()) {
+            let ptr = lty.label;
+            if !ptr.is_none() && known_perm_error_ptrs.contains(&ptr) {
+                known_perm_error_fns.insert(def_id);
+                warn!("known permissions changed for {def_id:?}: {lsig:?}");
+                break;
+            }
+        }
+    }
+
+    let results = if !rewrite_pointwise {
+        run2(
+            None,
+            tcx,
+            gacx,
+            gasn,
+            &global_pointee_types,
+            func_info,
+            &all_fn_ldids,
+            &fixed_defs,
+            &vec_structs,
+            &drop_impls,
+            &known_perm_error_fns,
+            apply_rewrites,
+        )
+    } else {
+        let mut results = AnalysisResults::default();
+        for &ldid in &all_fn_ldids {
+            let pointwise_results = run2(
+                Some(ldid),
+                tcx,
+                gacx.clone(),
+                gasn.clone(),
+                &global_pointee_types,
+                func_info.clone(),
+                &all_fn_ldids,
+                &fixed_defs,
+                &vec_structs,
+                &drop_impls,
+                &known_perm_error_fns,
+                apply_rewrites,
+            );
+            // Each pointwise run only rewrites `ldid`, so keep only the results for that function.
+            let name = tcx.def_path_str(ldid.to_def_id());
+            results.fns.extend(
+                pointwise_results
+                    .fns
+                    .into_iter()
+                    .filter(|f| f.def_path == name),
+            );
+        }
+        results
+    };
 
+    profile::report(tcx);
+    results
+}
+
+/// Run the dataflow solver and borrowck analysis, updating `gasn` and the local assignment of each
+/// function until they reach a fixpoint.
+fn solve_assignment<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &mut GlobalAssignment,
+    g_updates_forbidden: &GlobalPointerTable<PermissionSet>,
+    func_info: &mut HashMap<LocalDefId, FuncInfo<'tcx>>,
+    all_fn_ldids: &[LocalDefId],
+) {
     let mut loop_count = 0;
     loop {
         // Loop until the global assignment reaches a fixpoint.  The inner loop also runs until a
@@ -1160,7 +1463,9 @@ fn run(tcx: TyCtxt) {
         loop_count += 1;
         let old_gasn = gasn.clone();
 
-        for &ldid in &all_fn_ldids {
+        progress::start("dataflow and borrowck", all_fn_ldids.len());
+        for &ldid in all_fn_ldids {
+            progress::step();
             if gacx.fn_analysis_invalid(ldid.to_def_id()) {
                 continue;
             }
@@ -1179,18 +1484,22 @@ fn run(tcx: TyCtxt) {
             let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
                 // `dataflow.propagate` and `borrowck_mir` both run until the assignment converges
                 // on a fixpoint, so there's no need to do multiple iterations here.
-                info.dataflow
-                    .propagate(&mut asn.perms_mut(), &updates_forbidden);
-
-                borrowck::borrowck_mir(
-                    &acx,
-                    &info.dataflow,
-                    &mut asn.perms_mut(),
-                    &updates_forbidden,
-                    name.as_str(),
-                    &mir,
-                    field_ltys,
-                );
+                profile::time(Pass::Dataflow, ldid, || {
+                    info.dataflow
+                        .propagate(&mut asn.perms_mut(), &updates_forbidden)
+                });
+
+                profile::time(Pass::Borrowck, ldid, || {
+                    borrowck::borrowck_mir(
+                        &acx,
+                        &info.dataflow,
+                        &mut asn.perms_mut(),
+                        &updates_forbidden,
+                        name.as_str(),
+                        &mir,
+                        field_ltys,
+                    )
+                });
             }));
 
             info.acx_data.set(acx.into_data());
@@ -1207,6 +1516,7 @@ fn run(tcx: TyCtxt) {
                 }
             }
         }
+        progress::finish();
 
         let mut num_changed = 0;
         for (ptr, &old) in old_gasn.perms.iter() {
@@ -1215,113 +1525,614 @@ fn run(tcx: TyCtxt) {
                 let added = new & !old;
                 let removed = old & !new;
                 let kept = old & new;
-                eprintln!(
-                    "changed {:?}: added {:?}, removed {:?}, kept {:?}",
-                    ptr, added, removed, kept
-                );
+                if !progress::quiet() {
+                    eprintln!(
+                        "changed {:?}: added {:?}, removed {:?}, kept {:?}",
+                        ptr, added, removed, kept
+                    );
+                }
                 num_changed += 1;
             }
-        }
-        eprintln!(
-            "iteration {}: {} global pointers changed",
-            loop_count, num_changed
-        );
+        }
+        eprintln!(
+            "iteration {}: {} global pointers changed",
+            loop_count, num_changed
+        );
+
+        if *gasn == old_gasn {
+            break;
+        }
+    }
+    eprintln!("reached fixpoint in {} iterations", loop_count);
+
+    // Do final processing on each function.
+    for &ldid in all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            continue;
+        }
+
+        let info = func_info.get_mut(&ldid).unwrap();
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let mut asn = gasn.and(&mut info.lasn);
+
+        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+            // Add the CELL permission to pointers that need it.
+            info.dataflow.propagate_cell(&mut asn);
+
+            acx.check_string_literal_perms(&asn);
+        }));
+
+        info.acx_data.set(acx.into_data());
+
+        match r {
+            Ok(()) => {}
+            Err(pd) => {
+                gacx.mark_fn_failed(
+                    ldid.to_def_id(),
+                    DontRewriteFnReason::MISC_ANALYSIS_INVALID,
+                    pd,
+                );
+                continue;
+            }
+        }
+    }
+}
+
+/// Set `C_STRING` on the pointers that can be rewritten to `CString` or `&CStr`, as described in
+/// `c_strings`.  This runs after the permissions have reached a fixpoint, since the candidates are
+/// chosen based on their final permissions.
+fn infer_c_strings<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &mut GlobalAssignment,
+    func_info: &mut HashMap<LocalDefId, FuncInfo<'tcx>>,
+    all_fn_ldids: &[LocalDefId],
+) {
+    c_strings::mark_global_candidates(gacx, gasn);
+
+    for &ldid in all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            continue;
+        }
+
+        let info = func_info.get_mut(&ldid).unwrap();
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let mut asn = gasn.and(&mut info.lasn);
+        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+            c_strings::mark_local_candidates(&acx, &mir, &mut asn);
+        }));
+        info.acx_data.set(acx.into_data());
+        if let Err(pd) = r {
+            gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+        }
+    }
+
+    // Removing `C_STRING` from a global pointer in one function can affect other functions, so
+    // loop until the global assignment reaches a fixpoint, as in `solve_assignment`.
+    loop {
+        let old_gasn = gasn.clone();
+        for &ldid in all_fn_ldids {
+            if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+                // The signature of this function won't be rewritten.  This includes functions
+                // that failed during an earlier iteration.
+                let lsig = gacx.fn_sigs[&ldid.to_def_id()];
+                for lty in lsig.inputs_and_output().flat_map(|lty| lty.iter()) {
+                    if !lty.label.is_none() {
+                        gasn.flags[lty.label].remove(FlagSet::C_STRING);
+                    }
+                }
+                continue;
+            }
+            let info = func_info.get_mut(&ldid).unwrap();
+            let mut asn = gasn.and(&mut info.lasn);
+            let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+                info.dataflow.propagate_c_string(&mut asn)
+            }));
+            if let Err(pd) = r.and_then(|r| r.map_err(PanicDetail::new)) {
+                gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+            }
+        }
+        if *gasn == old_gasn {
+            break;
+        }
+    }
+}
+
+/// Set `VEC` on the owned slices that can be rewritten to `Vec<T>`, as described in
+/// `realloc_vecs`.  Like `infer_c_strings`, this runs after the permissions have reached a
+/// fixpoint, and after `infer_c_strings` itself, since strings are never `Vec`s.
+fn infer_realloc_vecs<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &mut GlobalAssignment,
+    func_info: &mut HashMap<LocalDefId, FuncInfo<'tcx>>,
+    all_fn_ldids: &[LocalDefId],
+) {
+    for &ldid in all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            continue;
+        }
+        let info = func_info.get_mut(&ldid).unwrap();
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let mut asn = gasn.and(&mut info.lasn);
+        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+            realloc_vecs::mark_realloc_buffers(&acx, &mir, &mut asn);
+        }));
+        info.acx_data.set(acx.into_data());
+        if let Err(pd) = r {
+            gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+        }
+    }
+
+    // Adding `VEC` to a global pointer in one function can affect other functions, so loop until
+    // the global assignment reaches a fixpoint, as in `infer_c_strings`.
+    loop {
+        let old_gasn = gasn.clone();
+        for &ldid in all_fn_ldids {
+            if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+                continue;
+            }
+            let info = func_info.get_mut(&ldid).unwrap();
+            let mut asn = gasn.and(&mut info.lasn);
+            let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+                info.dataflow.propagate_vec(&mut asn)
+            }));
+            if let Err(pd) = r.and_then(|r| r.map_err(PanicDetail::new)) {
+                gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+            }
+        }
+        if *gasn == old_gasn {
+            break;
+        }
+    }
+
+    // The signatures of functions that won't be rewritten keep their `Box<[T]>` types.  Callers
+    // convert between those and `Vec<T>`.
+    for &ldid in all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            let lsig = gacx.fn_sigs[&ldid.to_def_id()];
+            for lty in lsig.inputs_and_output().flat_map(|lty| lty.iter()) {
+                if !lty.label.is_none() {
+                    gasn.flags[lty.label].remove(FlagSet::VEC);
+                }
+            }
+        }
+    }
+}
+
+/// Collect the pointers that never become handles to a shared object: borrows of places, which
+/// never share ownership, and the signatures of functions that won't be rewritten, which keep
+/// their raw pointer types.
+fn collect_unshared_ptrs<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    func_info: &mut HashMap<LocalDefId, FuncInfo<'tcx>>,
+    all_fn_ldids: &[LocalDefId],
+) -> HashSet<PointerId> {
+    let mut excluded = HashSet::new();
+    for &ldid in all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            let lsig = gacx.fn_sigs[&ldid.to_def_id()];
+            for lty in lsig.inputs_and_output().flat_map(|lty| lty.iter()) {
+                if !lty.label.is_none() {
+                    excluded.insert(lty.label);
+                }
+            }
+            continue;
+        }
+        let info = func_info.get_mut(&ldid).unwrap();
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+            rc_refcell::collect_borrows(&acx, &mir, &mut excluded);
+        }));
+        info.acx_data.set(acx.into_data());
+        if let Err(pd) = r {
+            gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+        }
+    }
+    excluded
+}
+
+/// Set `REFCELL` on the pointers to heap objects that are aliased while they're mutated, as
+/// described in `rc_refcell`.
+fn infer_rc_refcells<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &mut GlobalAssignment,
+    func_info: &mut HashMap<LocalDefId, FuncInfo<'tcx>>,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let excluded = collect_unshared_ptrs(tcx, gacx, func_info, all_fn_ldids);
+
+    for &ldid in all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            continue;
+        }
+        let info = func_info.get_mut(&ldid).unwrap();
+        let mut asn = gasn.and(&mut info.lasn);
+        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+            rc_refcell::mark_shared_owners(&mut asn, &excluded);
+        }));
+        if let Err(pd) = r {
+            gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+        }
+    }
+
+    // As in `infer_realloc_vecs`, loop until the global assignment reaches a fixpoint.
+    loop {
+        let old_gasn = gasn.clone();
+        for &ldid in all_fn_ldids {
+            if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+                continue;
+            }
+            let info = func_info.get_mut(&ldid).unwrap();
+            let mut asn = gasn.and(&mut info.lasn);
+            let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+                info.dataflow.propagate_shared(
+                    &mut asn,
+                    FlagSet::REFCELL,
+                    rc_refcell::can_share,
+                    &excluded,
+                )
+            }));
+            if let Err(pd) = r.and_then(|r| r.map_err(PanicDetail::new)) {
+                gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+            }
+        }
+        if *gasn == old_gasn {
+            break;
+        }
+    }
+}
+
+/// Set `MUTEX` on the pointers to objects that are shared with other threads, as described in
+/// `arc_mutex`.
+fn infer_arc_mutexes<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &mut GlobalAssignment,
+    func_info: &mut HashMap<LocalDefId, FuncInfo<'tcx>>,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let mut excluded = collect_unshared_ptrs(tcx, gacx, func_info, all_fn_ldids);
+
+    let mut spawns = Vec::new();
+    for &ldid in all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            continue;
+        }
+        let info = func_info.get_mut(&ldid).unwrap();
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+            arc_mutex::find_thread_spawns(&acx, &mir)
+        }));
+        info.acx_data.set(acx.into_data());
+        match r {
+            Ok(fn_spawns) => spawns.extend(fn_spawns.into_iter().map(|spawn| (ldid, spawn))),
+            Err(pd) => {
+                gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+            }
+        }
+    }
+
+    // Find the uses of the argument of each start routine that will be rewritten.
+    let mut start_routines = HashMap::new();
+    for (_, spawn) in &spawns {
+        let ldid = match *spawn {
+            ThreadSpawn::PthreadCreate {
+                start_routine: Some(def_id),
+                ..
+            } => match def_id.as_local() {
+                Some(ldid) if func_info.contains_key(&ldid) => ldid,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) || start_routines.contains_key(&ldid) {
+            continue;
+        }
+        let info = func_info.get_mut(&ldid).unwrap();
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+            arc_mutex::find_start_routine_arg(&acx, &mir)
+        }));
+        info.acx_data.set(acx.into_data());
+        match r {
+            Ok(arg) => {
+                start_routines.insert(ldid, arg);
+            }
+            Err(pd) => {
+                gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+            }
+        }
+    }
+
+    // The argument of a start routine keeps its `void*` type, and so do the pointers captured by a
+    // closure passed to `std::thread::spawn`, since closure bodies aren't rewritten.
+    let mut fixed = HashMap::<LocalDefId, Vec<PointerId>>::new();
+    for (&ldid, arg) in &start_routines {
+        fixed.entry(ldid).or_default().extend(&arg.copies);
+    }
+    for (ldid, spawn) in &spawns {
+        if let ThreadSpawn::StdSpawn { ref captures } = *spawn {
+            fixed.entry(*ldid).or_default().extend(captures);
+        }
+    }
+    for (ldid, ptrs) in fixed {
+        let info = func_info.get_mut(&ldid).unwrap();
+        let mut asn = gasn.and(&mut info.lasn);
+        let (_, mut flags) = asn.all_mut();
+        for ptr in ptrs {
+            flags[ptr].insert(FlagSet::FIXED);
+            excluded.insert(ptr);
+        }
+    }
+
+    // Both ends of a `pthread_create` must agree on the type of the shared object, so the argument
+    // and the casts in the start routine get `MUTEX` only if they all can have it.
+    let mut can_share = |ldid: LocalDefId, ptr: PointerId| {
+        let info = func_info.get_mut(&ldid).unwrap();
+        let asn = gasn.and(&mut info.lasn);
+        rc_refcell::can_share(asn.perms()[ptr], asn.flags()[ptr]) && !excluded.contains(&ptr)
+    };
+    let mut seeds = HashMap::<LocalDefId, Vec<PointerId>>::new();
+    let mut unshared = Vec::new();
+    for (ldid, spawn) in &spawns {
+        let (arg, start_routine) = match *spawn {
+            ThreadSpawn::PthreadCreate { arg, start_routine } => (arg, start_routine),
+            ThreadSpawn::StdSpawn { .. } => continue,
+        };
+        let routine = start_routine
+            .and_then(|def_id| def_id.as_local())
+            .and_then(|routine_ldid| Some((routine_ldid, start_routines.get(&routine_ldid)?)));
+        let ok = match routine {
+            Some((routine_ldid, routine_arg)) => {
+                !routine_arg.casts.is_empty()
+                    && can_share(*ldid, arg)
+                    && routine_arg
+                        .casts
+                        .iter()
+                        .all(|&ptr| can_share(routine_ldid, ptr))
+            }
+            None => false,
+        };
+        match routine {
+            Some((routine_ldid, routine_arg)) if ok => {
+                seeds.entry(*ldid).or_default().push(arg);
+                seeds
+                    .entry(routine_ldid)
+                    .or_default()
+                    .extend(&routine_arg.casts);
+            }
+            _ => unshared.push(arg),
+        }
+    }
+    // A pointer passed to a thread that won't receive an `Arc` must stay raw.
+    excluded.extend(unshared);
+
+    for (ldid, ptrs) in seeds {
+        let info = func_info.get_mut(&ldid).unwrap();
+        let mut asn = gasn.and(&mut info.lasn);
+        let (_, mut flags) = asn.all_mut();
+        for ptr in ptrs {
+            flags[ptr].insert(FlagSet::MUTEX);
+        }
+    }
+
+    // As in `infer_realloc_vecs`, loop until the global assignment reaches a fixpoint.
+    loop {
+        let old_gasn = gasn.clone();
+        for &ldid in all_fn_ldids {
+            if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+                continue;
+            }
+            let info = func_info.get_mut(&ldid).unwrap();
+            let mut asn = gasn.and(&mut info.lasn);
+            let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+                info.dataflow.propagate_shared(
+                    &mut asn,
+                    FlagSet::MUTEX,
+                    rc_refcell::can_share,
+                    &excluded,
+                )
+            }));
+            if let Err(pd) = r.and_then(|r| r.map_err(PanicDetail::new)) {
+                gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+            }
+        }
+        if *gasn == old_gasn {
+            break;
+        }
+    }
+}
+
+/// Write the current permission/flag assignment to `path` in the format described in
+/// `assignment_file`, with comments describing where each pointer appears.
+fn dump_assignment<'tcx>(
+    path: &str,
+    tcx: TyCtxt<'tcx>,
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &GlobalAssignment,
+    func_info: &mut HashMap<LocalDefId, FuncInfo<'tcx>>,
+    all_fn_ldids: &[LocalDefId],
+) -> io::Result<()> {
+    let print_lty = |lty: LTy| {
+        context::print_ty_with_pointer_labels(lty, |ptr| {
+            if ptr.is_none() {
+                String::new()
+            } else {
+                format!("{{{}}}", ptr)
+            }
+        })
+    };
 
-        if gasn == old_gasn {
-            break;
-        }
+    let mut w = AssignmentWriter::new();
+
+    let mut fn_dids = gacx.fn_sigs.keys().copied().collect::<Vec<_>>();
+    fn_dids.sort();
+    for did in fn_dids {
+        let lsig = &gacx.fn_sigs[&did];
+        let inputs = lsig
+            .inputs
+            .iter()
+            .map(|&lty| print_lty(lty))
+            .collect::<Vec<_>>();
+        w.comment(&format!(
+            "fn {}({}) -> {}",
+            tcx.def_path_str(did),
+            inputs.join(", "),
+            print_lty(lsig.output)
+        ));
     }
-    eprintln!("reached fixpoint in {} iterations", loop_count);
+    let mut static_dids = gacx.static_tys.keys().copied().collect::<Vec<_>>();
+    static_dids.sort();
+    for did in static_dids {
+        w.comment(&format!(
+            "static {}: {} (address {})",
+            tcx.def_path_str(did),
+            print_lty(gacx.static_tys[&did]),
+            gacx.addr_of_static[&did]
+        ));
+    }
+    let mut field_dids = gacx.field_ltys.keys().copied().collect::<Vec<_>>();
+    field_dids.sort();
+    for did in field_dids {
+        w.comment(&format!(
+            "field {}: {}",
+            tcx.def_path_str(did),
+            print_lty(gacx.field_ltys[&did])
+        ));
+    }
+    w.global(gasn);
 
-    // Do final processing on each function.
-    for &ldid in &all_fn_ldids {
-        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
-            continue;
+    for &ldid in all_fn_ldids {
+        let info = func_info.get_mut(&ldid).unwrap();
+        w.begin_fn(ldid.to_def_id(), &info.lasn);
+        w.comment(&tcx.def_path_str(ldid.to_def_id()));
+        if info.acx_data.is_set() {
+            let ldid_const = WithOptConstParam::unknown(ldid);
+            let mir = tcx.mir_built(ldid_const);
+            let mir = mir.borrow();
+            let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+            for (local, decl) in mir.local_decls.iter_enumerated() {
+                w.comment(&format!(
+                    "{:?} ({}): {} (address {})",
+                    local,
+                    describe_local(tcx, decl),
+                    print_lty(acx.local_tys[local]),
+                    acx.addr_of_local[local]
+                ));
+            }
+            info.acx_data.set(acx.into_data());
         }
+        w.local_entries(&info.lasn);
+    }
+
+    fs::write(path, w.finish())
+}
 
+/// Write a JSON report of the permissions of the pointer stored in each local of each function,
+/// for comparison against the dynamic analysis with `c2rust-pdg --compare-static`.
+fn dump_local_perms<'tcx>(
+    path: &str,
+    tcx: TyCtxt<'tcx>,
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &mut GlobalAssignment,
+    func_info: &mut HashMap<LocalDefId, FuncInfo<'tcx>>,
+    all_fn_ldids: &[LocalDefId],
+) -> io::Result<()> {
+    let mut report = Vec::new();
+    for &ldid in all_fn_ldids {
         let info = func_info.get_mut(&ldid).unwrap();
+        if !info.acx_data.is_set() {
+            continue;
+        }
+        let def_path_hash: (u64, u64) = tcx.def_path_hash(ldid.to_def_id()).0.as_value();
         let ldid_const = WithOptConstParam::unknown(ldid);
         let mir = tcx.mir_built(ldid_const);
         let mir = mir.borrow();
         let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
-        let mut asn = gasn.and(&mut info.lasn);
-
-        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
-            // Add the CELL permission to pointers that need it.
-            info.dataflow.propagate_cell(&mut asn);
-
-            acx.check_string_literal_perms(&asn);
-        }));
-
-        info.acx_data.set(acx.into_data());
-
-        match r {
-            Ok(()) => {}
-            Err(pd) => {
-                gacx.mark_fn_failed(
-                    ldid.to_def_id(),
-                    DontRewriteFnReason::MISC_ANALYSIS_INVALID,
-                    pd,
-                );
-                continue;
-            }
+        let asn = gasn.and(&mut info.lasn);
+        for (local, decl) in mir.local_decls.iter_enumerated() {
+            let ptr = match acx.ptr_of(local) {
+                Some(x) => x,
+                None => continue,
+            };
+            let span = tcx
+                .sess
+                .source_map()
+                .span_to_diagnostic_string(local_span(decl));
+            report.push(StaticLocalPerms {
+                def_path_hash,
+                function: tcx.def_path_str(ldid.to_def_id()),
+                local: local.as_u32(),
+                span,
+                perms: assignment_file::format_perms(asn.perms()[ptr]),
+            });
         }
+        info.acx_data.set(acx.into_data());
     }
 
-    // Check that these perms haven't changed.
-    let mut known_perm_error_ptrs = HashSet::new();
-    for (ptr, perms) in gacx.known_fn_ptr_perms() {
-        if gasn.perms[ptr] != perms {
-            known_perm_error_ptrs.insert(ptr);
-            warn!(
-                "known permissions changed for PointerId {ptr:?}: {perms:?} -> {:?}",
-                gasn.perms[ptr]
-            );
-        }
-    }
+    let f = File::create(path)?;
+    serde_json::to_writer_pretty(f, &report)?;
+    Ok(())
+}
 
-    let mut known_perm_error_fns = HashSet::new();
-    for (&def_id, lsig) in &gacx.fn_sigs {
-        if !tcx.is_foreign_item(def_id) {
+#[allow(clippy::too_many_arguments)]
+/// Find the functions whose returned slice can be fused with the length they report through an
+/// out-parameter (see [`returned_slice_len`]).  Functions that won't be rewritten keep their
+/// signatures, and so do methods, functions whose address is taken, and functions that can be
+/// called from outside the crate.
+fn find_returned_slice_lens<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &mut GlobalAssignment,
+    func_info: &mut HashMap<LocalDefId, FuncInfo<'tcx>>,
+    all_fn_ldids: &[LocalDefId],
+    addr_taken_fns: &HashSet<DefId>,
+) -> HashMap<DefId, ReturnedSliceLen<'tcx>> {
+    let mut returned_slice_lens = HashMap::new();
+    for &ldid in all_fn_ldids {
+        let def_id = ldid.to_def_id();
+        if gacx.dont_rewrite_fn(def_id)
+            || tcx.def_kind(def_id) != DefKind::Fn
+            || addr_taken_fns.contains(&def_id)
+            || tcx.codegen_fn_attrs(def_id).contains_extern_indicator()
+        {
             continue;
         }
-        for lty in lsig.inputs_and_output().flat_map(|lty| lty.iter()) {
-            let ptr = lty.label;
-            if !ptr.is_none() && known_perm_error_ptrs.contains(&ptr) {
-                known_perm_error_fns.insert(def_id);
-                warn!("known permissions changed for {def_id:?}: {lsig:?}");
-                break;
-            }
-        }
-    }
-
-    if !rewrite_pointwise {
-        run2(
-            None,
-            tcx,
-            gacx,
-            gasn,
-            &global_pointee_types,
-            func_info,
-            &all_fn_ldids,
-            &fixed_defs,
-            &known_perm_error_fns,
-        );
-    } else {
-        for &ldid in &all_fn_ldids {
-            run2(
-                Some(ldid),
-                tcx,
-                gacx.clone(),
-                gasn.clone(),
-                &global_pointee_types,
-                func_info.clone(),
-                &all_fn_ldids,
-                &fixed_defs,
-                &known_perm_error_fns,
-            );
+        let info = func_info.get_mut(&ldid).unwrap();
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let asn = gasn.and(&mut info.lasn);
+        let rsl =
+            returned_slice_len::find_returned_slice_len(&acx, &asn.perms(), &asn.flags(), &mir);
+        info.acx_data.set(acx.into_data());
+        if let Some(rsl) = rsl {
+            returned_slice_lens.insert(def_id, rsl);
         }
     }
+    returned_slice_lens
 }
 
 fn run2<'tcx>(
@@ -1333,8 +2144,11 @@ fn run2<'tcx>(
     mut func_info: HashMap<LocalDefId, FuncInfo<'tcx>>,
     all_fn_ldids: &Vec<LocalDefId>,
     fixed_defs: &HashSet<DefId>,
+    vec_structs: &[rewrite::VecStruct],
+    drop_impls: &[rewrite::DropImpl],
     known_perm_error_fns: &HashSet<DefId>,
-) {
+    apply_rewrites: bool,
+) -> AnalysisResults {
     // ----------------------------------
     // Generate rewrites
     // ----------------------------------
@@ -1354,7 +2168,13 @@ fn run2<'tcx>(
         let desc = type_desc::perms_to_desc(lty.ty, perms, flags);
         match desc.own {
             Ownership::Imm | Ownership::Cell | Ownership::Mut => true,
-            Ownership::Raw | Ownership::RawMut | Ownership::Rc | Ownership::Box => false,
+            Ownership::Raw
+            | Ownership::RawMut
+            | Ownership::Rc
+            | Ownership::Box
+            | Ownership::Vec
+            | Ownership::RcRefCell
+            | Ownership::ArcMutex => false,
         }
     });
 
@@ -1390,6 +2210,10 @@ fn run2<'tcx>(
 
     // Generate rewrites for all functions.
     let mut all_rewrites = Vec::new();
+    // MIR-level rewrites for each function, kept for reporting in `AnalysisResults`.
+    let mut all_mir_rewrites = HashMap::<LocalDefId, Vec<(Location, rewrite::MirRewrite)>>::new();
+    // Descriptions of the rewrites of each function, for comparison across `cfg` sets.
+    let mut fn_rewrite_descs = HashMap::<LocalDefId, Vec<String>>::new();
 
     let mut manual_shim_casts = rewrite::ManualShimCasts::No;
     if let Ok(val) = env::var("C2RUST_ANALYZE_USE_MANUAL_SHIMS") {
@@ -1399,11 +2223,27 @@ fn run2<'tcx>(
     }
     let manual_shim_casts = manual_shim_casts;
 
+    let shim_null_checks = if env::var("C2RUST_ANALYZE_UNTRUSTED_EXTERN_INPUTS").is_ok() {
+        rewrite::ShimNullChecks::Yes
+    } else {
+        rewrite::ShimNullChecks::No
+    };
+
+    // Rewrites inside `macro_rules!` bodies are applied to the macro definition, which is only
+    // valid if every expansion of the macro gets the same rewrite.
+    let macro_expr_sites = rewrite::collect_macro_expr_sites(tcx);
+    let mut macro_def_rewrites = rewrite::MacroDefRewrites::default();
+
+    let addr_taken_fns = const_prop::collect_addr_taken_fns(tcx, all_fn_ldids);
+
     // It may take multiple tries to reach a state where all rewrites succeed.
     for i in 0.. {
         assert!(i < 100);
         func_reports.clear();
         all_rewrites.clear();
+        all_mir_rewrites.clear();
+        fn_rewrite_descs.clear();
+        macro_def_rewrites.clear();
         eprintln!("\n--- start rewriting ---");
 
         // Update non-rewritten items first.  This has two purposes.  First, it clears the
@@ -1412,7 +2252,20 @@ fn run2<'tcx>(
         // rewrite, such as pointers in the signatures of non-rewritten functions.
         process_new_dont_rewrite_items(&mut gacx, &mut gasn);
 
+        // Removing the length out-parameter from a function changes how its callers must be
+        // rewritten, so this must be decided before rewriting any function.
+        gacx.returned_slice_lens = find_returned_slice_lens(
+            tcx,
+            &mut gacx,
+            &mut gasn,
+            &mut func_info,
+            all_fn_ldids,
+            &addr_taken_fns,
+        );
+
+        progress::start("rewriting", all_fn_ldids.len());
         for &ldid in all_fn_ldids {
+            progress::step();
             if gacx.dont_rewrite_fn(ldid.to_def_id()) {
                 continue;
             }
@@ -1425,6 +2278,8 @@ fn run2<'tcx>(
             let mut acx = gacx.function_context_with_data(&mir, info.acx_data.take());
             let asn = gasn.and(&mut info.lasn);
             let pointee_types = global_pointee_types.and(info.local_pointee_types.get());
+            let mut macro_err = None;
+            let mut self_err = None;
 
             let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
                 if util::has_test_attr(tcx, ldid, TestAttr::SkipRewrite) {
@@ -1435,7 +2290,7 @@ fn run2<'tcx>(
                 }
 
                 let hir_body_id = tcx.hir().body_owned_by(ldid);
-                let expr_rewrites = rewrite::gen_expr_rewrites(
+                let (expr_rewrites, mir_rewrites) = rewrite::gen_expr_rewrites(
                     &mut acx,
                     &asn,
                     pointee_types,
@@ -1443,7 +2298,32 @@ fn run2<'tcx>(
                     &mir,
                     hir_body_id,
                 );
-                let ty_rewrites = rewrite::gen_ty_rewrites(&acx, &asn, pointee_types, &mir, ldid);
+                let ty_rewrites =
+                    match rewrite::gen_ty_rewrites(&acx, &asn, pointee_types, &mir, ldid) {
+                        Ok(x) => x,
+                        Err(msg) => {
+                            self_err = Some(msg);
+                            return;
+                        }
+                    };
+                // Rewrites that land inside macro expansions need special handling.
+                let split = rewrite::split_macro_rewrites(expr_rewrites).and_then(
+                    |(expr_rewrites, expr_macro_rewrites)| {
+                        let (ty_rewrites, ty_macro_rewrites) =
+                            rewrite::split_macro_rewrites(ty_rewrites)?;
+                        if let Some(&(span, _)) = ty_macro_rewrites.first() {
+                            return Err(format!("type at {span:?} is inside a macro"));
+                        }
+                        Ok((expr_rewrites, expr_macro_rewrites, ty_rewrites))
+                    },
+                );
+                let (expr_rewrites, expr_macro_rewrites, ty_rewrites) = match split {
+                    Ok(x) => x,
+                    Err(msg) => {
+                        macro_err = Some(msg);
+                        return;
+                    }
+                };
                 // Print rewrites
                 let report = func_reports.entry(ldid).or_default();
                 writeln!(
@@ -1457,9 +2337,39 @@ fn run2<'tcx>(
                 for &(span, ref rw) in expr_rewrites.iter().chain(ty_rewrites.iter()) {
                     writeln!(report, "  {}: {}", describe_span(tcx, span), rw).unwrap();
                 }
+                for &(span, ref rw) in &expr_macro_rewrites {
+                    writeln!(report, "  {} (macro): {}", describe_span(tcx, span), rw).unwrap();
+                }
                 writeln!(report).unwrap();
+                fn_rewrite_descs.insert(
+                    ldid,
+                    expr_rewrites
+                        .iter()
+                        .chain(ty_rewrites.iter())
+                        .chain(expr_macro_rewrites.iter())
+                        .map(|&(span, ref rw)| {
+                            let pos = tcx.sess.source_map().span_to_diagnostic_string(span);
+                            format!("{pos}: {rw}")
+                        })
+                        .collect(),
+                );
+                if !expr_rewrites.is_empty() || !ty_rewrites.is_empty() {
+                    let spans = expr_rewrites
+                        .iter()
+                        .chain(&ty_rewrites)
+                        .map(|&(span, _)| span)
+                        .collect::<Vec<_>>();
+                    all_rewrites.extend(rewrite::gen_lint_rewrites(tcx, ldid, &mir, &spans));
+                }
                 all_rewrites.extend(expr_rewrites);
                 all_rewrites.extend(ty_rewrites);
+                macro_def_rewrites.add(ldid, expr_macro_rewrites);
+                let mut mir_rewrites = mir_rewrites
+                    .into_iter()
+                    .flat_map(|(loc, rws)| rws.into_iter().map(move |rw| (loc, rw)))
+                    .collect::<Vec<_>>();
+                mir_rewrites.sort_by_key(|&(loc, _)| loc);
+                all_mir_rewrites.insert(ldid, mir_rewrites);
             }));
 
             info.acx_data.set(acx.into_data());
@@ -1471,6 +2381,35 @@ fn run2<'tcx>(
                     continue;
                 }
             }
+
+            if let Some(msg) = macro_err {
+                gacx.mark_fn_failed(
+                    ldid.to_def_id(),
+                    DontRewriteFnReason::MACRO_EXPANSION,
+                    PanicDetail::new(msg),
+                );
+                continue;
+            }
+
+            if let Some(msg) = self_err {
+                gacx.mark_fn_failed(
+                    ldid.to_def_id(),
+                    DontRewriteFnReason::NON_RECEIVER_SELF,
+                    PanicDetail::new(msg),
+                );
+                continue;
+            }
+        }
+        progress::finish();
+
+        let (macro_rewrites, macro_failures) = macro_def_rewrites.finish(&macro_expr_sites);
+        all_rewrites.extend(macro_rewrites);
+        for (ldid, msg) in macro_failures {
+            gacx.mark_fn_failed(
+                ldid.to_def_id(),
+                DontRewriteFnReason::MACRO_EXPANSION,
+                PanicDetail::new(msg),
+            );
         }
 
         // This call never panics, which is important because this is the fallback if the more
@@ -1486,6 +2425,7 @@ fn run2<'tcx>(
                     &gasn,
                     def_id,
                     manual_shim_casts,
+                    shim_null_checks,
                 ));
             }));
             match r {
@@ -1506,13 +2446,38 @@ fn run2<'tcx>(
         }
     }
 
+    if let Ok(dir) = env::var("C2RUST_ANALYZE_CFG_REPORT") {
+        cfg_sets::write_fn_rewrites(Path::new(&dir), &gacx, all_fn_ldids, &fn_rewrite_descs)
+            .unwrap();
+    }
+
     // Generate rewrites for statics
     let mut static_rewrites = Vec::new();
+    let static_uses = rewrite::collect_static_uses(tcx);
+    let mut local_statics = HashSet::new();
+    if env::var("C2RUST_ANALYZE_REWRITE_LOCAL_STATICS").as_deref() == Ok("1") {
+        let (local_static_rewrites, rewritten) =
+            rewrite::gen_local_static_rewrites(&gacx, &gasn, &static_uses, fixed_defs);
+        static_rewrites.extend(local_static_rewrites);
+        local_statics = rewritten;
+    }
     for (&def_id, &ptr) in gacx.addr_of_static.iter() {
-        if fixed_defs.contains(&def_id) {
+        if fixed_defs.contains(&def_id) || local_statics.contains(&def_id) {
             continue;
         }
-        static_rewrites.extend(rewrite::gen_static_rewrites(tcx, &gasn, def_id, ptr));
+        static_rewrites.extend(rewrite::gen_static_rewrites(
+            &gacx,
+            &gasn,
+            &static_uses,
+            def_id,
+            ptr,
+        ));
+        static_rewrites.extend(rewrite::gen_static_fn_ptr_rewrites(
+            &gacx,
+            &gasn,
+            global_pointee_types,
+            def_id,
+        ));
     }
     let mut statics_report = String::new();
     writeln!(
@@ -1532,6 +2497,12 @@ fn run2<'tcx>(
     }
     all_rewrites.extend(static_rewrites);
 
+    // Generate rewrites for growable array structs
+    all_rewrites.extend(rewrite::gen_vec_struct_rewrites(tcx, vec_structs));
+
+    // Generate `Drop` impls for structs with destructors
+    all_rewrites.extend(rewrite::gen_drop_impl_rewrites(&gacx, drop_impls));
+
     // Generate rewrites for ADTs
     let mut adt_reports = HashMap::<DefId, String>::new();
     for &def_id in gacx.adt_metadata.table.keys() {
@@ -1558,10 +2529,18 @@ fn run2<'tcx>(
         all_rewrites.extend(adt_rewrites);
     }
 
+    // Add the helper module called by some expression rewrites
+    if rewrite::use_helper_module() {
+        all_rewrites.push(rewrite::gen_helper_module_rewrite(tcx));
+    }
+
     // ----------------------------------
     // Print reports for tests and debugging
     // ----------------------------------
 
+    // Pointer permissions for each function, kept for reporting in `AnalysisResults`.
+    let mut all_pointer_results = HashMap::<LocalDefId, Vec<PointerResults>>::new();
+
     // Print analysis results for each function in `all_fn_ldids`, going in declaration order.
     // Concretely, we iterate over `body_owners()`, which is a superset of `all_fn_ldids`, and
     // filter based on membership in `func_info`, which contains an entry for each ID in
@@ -1585,25 +2564,45 @@ fn run2<'tcx>(
         let asn = gasn.and(&mut info.lasn);
         let pointee_types = global_pointee_types.and(info.local_pointee_types.get());
 
-        // Print labeling and rewrites for the current function.
+        // Print labeling and rewrites for the current function.  The function's rewrites have
+        // already been generated at this point, so a panic here only records the error.
+        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+            eprintln!("\nfinal labeling for {:?}:", name);
+            let lcx1 = crate::labeled_ty::LabeledTyCtxt::new(tcx);
+            let lcx2 = crate::labeled_ty::LabeledTyCtxt::new(tcx);
+            for (local, decl) in mir.local_decls.iter_enumerated() {
+                print_labeling_for_var(
+                    lcx1,
+                    lcx2,
+                    format_args!("{:?} ({})", local, describe_local(tcx, decl)),
+                    acx.addr_of_local[local],
+                    acx.local_tys[local],
+                    &asn.perms(),
+                    &asn.flags(),
+                );
+            }
 
-        eprintln!("\nfinal labeling for {:?}:", name);
-        let lcx1 = crate::labeled_ty::LabeledTyCtxt::new(tcx);
-        let lcx2 = crate::labeled_ty::LabeledTyCtxt::new(tcx);
-        for (local, decl) in mir.local_decls.iter_enumerated() {
-            print_labeling_for_var(
-                lcx1,
-                lcx2,
-                format_args!("{:?} ({})", local, describe_local(tcx, decl)),
-                acx.addr_of_local[local],
-                acx.local_tys[local],
-                &asn.perms(),
-                &asn.flags(),
-            );
-        }
+            let mut pointer_results = Vec::new();
+            for (local, decl) in mir.local_decls.iter_enumerated() {
+                for lty in acx.local_tys[local].iter() {
+                    let ptr = lty.label;
+                    if ptr.is_none() {
+                        continue;
+                    }
+                    pointer_results.push(PointerResults {
+                        local: local.as_usize(),
+                        local_desc: describe_local(tcx, decl),
+                        ptr,
+                        perms: asn.perms()[ptr],
+                        flags: asn.flags()[ptr],
+                    });
+                }
+            }
+            all_pointer_results.insert(ldid, pointer_results);
 
-        eprintln!("\ntype assignment for {:?}:", name);
-        rewrite::dump_rewritten_local_tys(&acx, &asn, pointee_types, &mir, describe_local);
+            eprintln!("\ntype assignment for {:?}:", name);
+            rewrite::dump_rewritten_local_tys(&acx, &asn, pointee_types, &mir, describe_local);
+        }));
 
         eprintln!();
         if let Some(report) = func_reports.remove(&ldid) {
@@ -1611,6 +2610,10 @@ fn run2<'tcx>(
         }
 
         info.acx_data.set(acx.into_data());
+
+        if let Err(pd) = r {
+            gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::ANALYSIS_PANIC, pd);
+        }
     }
 
     // Generate annotations for all functions.
@@ -1750,28 +2753,32 @@ fn run2<'tcx>(
     let annotations = ann.finish();
 
     // Apply rewrite to all functions at once.
-    let mut update_files = rewrite::UpdateFiles::No;
-    if let Ok(val) = env::var("C2RUST_ANALYZE_REWRITE_MODE") {
-        match val.as_str() {
-            "none" => {}
-            "inplace" => {
-                update_files = rewrite::UpdateFiles::InPlace;
-            }
-            "alongside" => {
-                update_files = rewrite::UpdateFiles::Alongside;
-            }
-            "pointwise" => {
-                let pointwise_fn_ldid = pointwise_fn_ldid.expect(
-                    "C2RUST_ANALYZE_REWRITE_MODE=pointwise, \
+    if apply_rewrites {
+        let mut update_files = rewrite::UpdateFiles::No;
+        if let Ok(val) = env::var("C2RUST_ANALYZE_REWRITE_MODE") {
+            match val.as_str() {
+                "none" => {}
+                "inplace" => {
+                    update_files = rewrite::UpdateFiles::InPlace;
+                }
+                "alongside" => {
+                    update_files = rewrite::UpdateFiles::Alongside;
+                }
+                "pointwise" => {
+                    let pointwise_fn_ldid = pointwise_fn_ldid.expect(
+                        "C2RUST_ANALYZE_REWRITE_MODE=pointwise, \
                             but pointwise_fn_ldid is unset?",
-                );
-                let pointwise_fn_name = tcx.item_name(pointwise_fn_ldid.to_def_id());
-                update_files = rewrite::UpdateFiles::AlongsidePointwise(pointwise_fn_name);
+                    );
+                    let pointwise_fn_name = tcx.item_name(pointwise_fn_ldid.to_def_id());
+                    update_files = rewrite::UpdateFiles::AlongsidePointwise(pointwise_fn_name);
+                }
+                _ => panic!("bad value {:?} for C2RUST_ANALYZE_REWRITE_MODE", val),
             }
-            _ => panic!("bad value {:?} for C2RUST_ANALYZE_REWRITE_MODE", val),
         }
+        rewrite::apply_rewrites(tcx, all_rewrites, annotations, update_files);
     }
-    rewrite::apply_rewrites(tcx, all_rewrites, annotations, update_files);
+
+    free_policy::report();
 
     // ----------------------------------
     // Report caught panics
@@ -1827,6 +2834,32 @@ fn run2<'tcx>(
             known_perm_error_fns.len()
         );
     }
+
+    // ----------------------------------
+    // Collect results
+    // ----------------------------------
+
+    let mut results = AnalysisResults::default();
+    for ldid in tcx.hir().body_owners() {
+        if !func_info.contains_key(&ldid) {
+            continue;
+        }
+        let def_id = ldid.to_def_id();
+        let skip_reasons = gacx.dont_rewrite_fns.get(def_id);
+        let mir_rewrites = if skip_reasons.is_empty() {
+            all_mir_rewrites.remove(&ldid).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        results.fns.push(FnResults {
+            def_path: tcx.def_path_str(def_id),
+            pointers: all_pointer_results.remove(&ldid).unwrap_or_default(),
+            mir_rewrites,
+            skip_reasons,
+            failure: gacx.fns_failed.get(&def_id).map(|pd| pd.to_string_short()),
+        });
+    }
+    results
 }
 
 pub trait AssignPointerIds<'tcx> {
@@ -1886,6 +2919,18 @@ fn make_sig_fixed(gasn: &mut GlobalAssignment, lsig: &LFnSig) {
     }
 }
 
+/// Mark the pointer fields of `#[repr(transparent)]` wrappers appearing in `lty` as
+/// non-rewritten.  `lty` belongs to an item that keeps its original type, so the wrappers it
+/// mentions must keep their original representation too.
+fn dont_rewrite_wrapped_ptrs<'tcx>(gacx: &mut GlobalAnalysisCtxt<'tcx>, lty: LTy<'tcx>) {
+    for lty in lty.iter() {
+        if let Some(field) = util::transparent_ptr_field(gacx.tcx, lty.ty) {
+            gacx.dont_rewrite_fields
+                .add(field.did, DontRewriteFieldReason::WRAPPER_NOT_REWRITTEN);
+        }
+    }
+}
+
 /// For testing, putting #[c2rust_analyze_test::fail_before_analysis] on a function marks it as
 /// failed at this point.
 fn apply_test_attr_fail_before_analysis(
@@ -2074,6 +3119,82 @@ fn all_static_items(tcx: TyCtxt) -> Vec<DefId> {
     order
 }
 
+/// Return the `DefId`s of all functions mentioned in the initializer of the static `ldid`.
+fn static_fn_defs(tcx: TyCtxt, ldid: LocalDefId) -> Vec<DefId> {
+    struct FnDefVisitor<'tcx> {
+        typeck_results: &'tcx TypeckResults<'tcx>,
+        fn_def_ids: Vec<DefId>,
+    }
+
+    impl<'tcx> intravisit::Visitor<'tcx> for FnDefVisitor<'tcx> {
+        fn visit_expr(&mut self, ex: &'tcx hir::Expr<'tcx>) {
+            if let TyKind::FnDef(def_id, _) = *self.typeck_results.expr_ty(ex).kind() {
+                if !self.fn_def_ids.contains(&def_id) {
+                    self.fn_def_ids.push(def_id);
+                }
+            }
+            intravisit::walk_expr(self, ex);
+        }
+    }
+
+    let body_id = tcx.hir().body_owned_by(ldid);
+    let mut v = FnDefVisitor {
+        typeck_results: tcx.typeck_body(body_id),
+        fn_def_ids: Vec::new(),
+    };
+    intravisit::Visitor::visit_body(&mut v, tcx.hir().body(body_id));
+    v.fn_def_ids
+}
+
+/// Unify the function pointer types in each static, such as the entries of a dispatch table
+/// `static HANDLERS: [Option<unsafe extern "C" fn(*mut T)>; N]`, with the signatures of the
+/// functions mentioned in the static's initializer.  Calls through the table then see the same
+/// pointer permissions as the handlers themselves, so the table's element type can be rewritten
+/// along with the handler signatures.
+fn unify_static_fn_ptrs(gacx: &GlobalAnalysisCtxt, global_equiv: &mut GlobalEquivSet) {
+    let tcx = gacx.tcx;
+    for (&did, &lty) in gacx.static_tys.iter() {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        if tcx.is_foreign_item(did) {
+            continue;
+        }
+        let fn_ptr_ltys = lty
+            .iter()
+            .filter(|lty| lty.ty.is_fn_ptr())
+            .collect::<Vec<_>>();
+        if fn_ptr_ltys.is_empty() {
+            continue;
+        }
+
+        for def_id in static_fn_defs(tcx, ldid) {
+            let lsig = match gacx.fn_sigs.get(&def_id) {
+                Some(x) => x,
+                None => continue,
+            };
+            let sig = tcx.erase_regions(tcx.erase_late_bound_regions(tcx.fn_sig(def_id)));
+            for &fn_ptr_lty in &fn_ptr_ltys {
+                let ptr_sig =
+                    tcx.erase_regions(tcx.erase_late_bound_regions(fn_ptr_lty.ty.fn_sig(tcx)));
+                if ptr_sig.inputs_and_output != sig.inputs_and_output {
+                    continue;
+                }
+                let mut local_equiv = LocalEquivSet::new(0);
+                let mut equiv = global_equiv.and_mut(&mut local_equiv);
+                for (&arg_lty, sig_lty) in fn_ptr_lty.args.iter().zip(lsig.inputs_and_output()) {
+                    for (a, b) in arg_lty.iter().zip(sig_lty.iter()) {
+                        if !a.label.is_none() && !b.label.is_none() {
+                            equiv.unify(a.label, b.label);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 fn is_impl_clone(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
     let clone_trait_def_id = match tcx.lang_items().clone_trait() {
         Some(def_id) => def_id,
@@ -2263,8 +3384,11 @@ fn process_new_dont_rewrite_items(gacx: &mut GlobalAnalysisCtxt, gasn: &mut Glob
 
         for did in gacx.dont_rewrite_fns.take_new_keys() {
             found_any = true;
-            let lsig = &gacx.fn_sigs[&did];
-            make_sig_fixed(gasn, lsig);
+            let lsig = gacx.fn_sigs[&did];
+            make_sig_fixed(gasn, &lsig);
+            for lty in lsig.inputs_and_output() {
+                dont_rewrite_wrapped_ptrs(gacx, lty);
+            }
 
             let ldid = match did.as_local() {
                 Some(x) => x,
@@ -2285,12 +3409,14 @@ fn process_new_dont_rewrite_items(gacx: &mut GlobalAnalysisCtxt, gasn: &mut Glob
             found_any = true;
             let lty = gacx.static_tys[&did];
             make_ty_fixed(gasn, lty);
+            dont_rewrite_wrapped_ptrs(gacx, lty);
         }
 
         for did in gacx.dont_rewrite_fields.take_new_keys() {
             found_any = true;
             let lty = gacx.field_ltys[&did];
             make_ty_fixed(gasn, lty);
+            dont_rewrite_wrapped_ptrs(gacx, lty);
         }
 
         // The previous steps can cause more items to become non-rewritten.  Keep going until
@@ -2310,7 +3436,7 @@ impl rustc_driver::Callbacks for AnalysisCallbacks {
         queries: &'tcx rustc_interface::Queries<'tcx>,
     ) -> rustc_driver::Compilation {
         queries.global_ctxt().unwrap().peek_mut().enter(|tcx| {
-            run(tcx);
+            run(tcx, true);
         });
         rustc_driver::Compilation::Continue
     }
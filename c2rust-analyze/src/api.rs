@@ -0,0 +1,194 @@
+//! Programmatic entry point for running the analysis.
+//!
+//! [`analyze_crate`] runs the full analysis and rewrite generation on a single crate, much like
+//! `C2RUST_ANALYZE_NO_CARGO=1 c2rust-analyze foo.rs` does, but instead of applying the generated
+//! rewrites, it returns an [`AnalysisResults`] describing the inferred permissions for each
+//! pointer, the MIR-level rewrites planned for each function, and the reasons (if any) that a
+//! function won't be rewritten.
+//!
+//! [`build_cast`] and [`CastBuilder`] generate the same cast chains between two pointer types
+//! (described by [`TypeDesc`]s) that the rewriter inserts, for use by other refactoring tools.
+//!
+//! The items defined and re-exported here are the stable interface of the library, and breaking
+//! changes to them are only made in new minor versions.  Everything else in the crate may change
+//! at any time.
+
+use crate::analyze;
+use crate::pointer_id::GlobalPointerTable;
+use anyhow::{anyhow, ensure, Context};
+use rustc_driver::RunCompiler;
+use rustc_middle::mir::Location;
+use rustc_middle::ty::TyCtxt;
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub use crate::context::{DontRewriteFnReason, FlagSet, PermissionSet};
+pub use crate::pointer_id::PointerId;
+pub use crate::rewrite::{CastBuilder, MirRewrite, RewriteKind, SubLoc, ZeroizeType};
+pub use crate::type_desc::{
+    perms_to_desc, perms_to_desc_with_pointee, Ownership, PtrDesc, Quantity, TypeDesc,
+};
+
+/// Results of analyzing a crate.
+#[derive(Clone, Debug, Default)]
+pub struct AnalysisResults {
+    /// Results for each function in the crate, in declaration order.
+    pub fns: Vec<FnResults>,
+}
+
+/// Analysis results for a single function.
+#[derive(Clone, Debug)]
+pub struct FnResults {
+    /// The def path of the function, as printed by `TyCtxt::def_path_str`.
+    pub def_path: String,
+    /// Permissions and flags for each pointer appearing in the types of the function's MIR
+    /// locals.  Empty if analysis of the function failed.
+    pub pointers: Vec<PointerResults>,
+    /// MIR rewrites planned for the function, sorted by location.  Empty if the function won't
+    /// be rewritten.
+    pub mir_rewrites: Vec<(Location, MirRewrite)>,
+    /// Reasons why the function won't be rewritten.  Empty if the function will be rewritten.
+    pub skip_reasons: DontRewriteFnReason,
+    /// Short description of the panic that caused analysis of the function to fail, if any.
+    pub failure: Option<String>,
+}
+
+/// Inferred permissions and flags for one pointer type within a MIR local's type.
+#[derive(Clone, Debug)]
+pub struct PointerResults {
+    /// Index of the MIR local whose type contains this pointer.
+    pub local: usize,
+    /// Human-readable description of the local, such as its source-level name.
+    pub local_desc: String,
+    pub ptr: PointerId,
+    pub perms: PermissionSet,
+    pub flags: FlagSet,
+}
+
+/// `rustc_driver` callbacks that run the analysis without applying rewrites and save the results.
+#[derive(Default)]
+struct ResultsCallbacks {
+    results: Option<AnalysisResults>,
+}
+
+impl rustc_driver::Callbacks for ResultsCallbacks {
+    fn after_expansion<'tcx>(
+        &mut self,
+        _compiler: &rustc_interface::interface::Compiler,
+        queries: &'tcx rustc_interface::Queries<'tcx>,
+    ) -> rustc_driver::Compilation {
+        queries.global_ctxt().unwrap().peek_mut().enter(|tcx| {
+            self.results = Some(analyze::run(tcx, false));
+        });
+        rustc_driver::Compilation::Stop
+    }
+}
+
+/// Run the analysis on the crate whose root source file is `crate_root`.
+///
+/// `rustc_args` are passed through to `rustc` (for example, `--edition 2021` or
+/// `--crate-type lib`).  The `--sysroot` is set automatically.  The analysis is configured by the
+/// same `C2RUST_ANALYZE_*` environment variables as the `c2rust-analyze` binary, except that
+/// rewrites are never written to disk.
+///
+/// Panics during analysis of individual functions are caught and reported in
+/// [`FnResults::failure`], as in the binary; call [`set_panic_hook`](crate::set_panic_hook)
+/// beforehand to include backtraces and source locations in those reports.
+pub fn analyze_crate(crate_root: &Path, rustc_args: &[String]) -> anyhow::Result<AnalysisResults> {
+    let sysroot = resolve_sysroot()?;
+    let sysroot = sysroot
+        .to_str()
+        .ok_or_else(|| anyhow!("sysroot path is not UTF-8: {}", sysroot.display()))?;
+    let crate_root = crate_root
+        .to_str()
+        .ok_or_else(|| anyhow!("crate root path is not UTF-8: {}", crate_root.display()))?;
+
+    let mut args = vec!["rustc".to_owned(), crate_root.to_owned()];
+    args.extend(rustc_args.iter().cloned());
+    args.extend(["--sysroot".to_owned(), sysroot.to_owned()]);
+
+    let mut callbacks = ResultsCallbacks::default();
+    RunCompiler::new(&args, &mut callbacks)
+        .run()
+        .map_err(|_| anyhow!("`rustc` failed"))?;
+    callbacks
+        .results
+        .ok_or_else(|| anyhow!("`rustc` exited before running the analysis"))
+}
+
+/// Compute the rewrites that cast a value of type `from` to type `to`, in the order they apply
+/// to the value.  For example, casting `Option<&mut T>` to `&T` gives `[OptionDowngrade { mutbl:
+/// false, deref: true }, OptionUnwrap]`, which is rendered as `p.as_deref().unwrap()`.
+///
+/// The pointee types of `from` and `to` must be equal up to lifetimes.  Returns `Err` with a
+/// description of the problem if the cast is unsupported.
+pub fn build_cast<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    from: TypeDesc<'tcx>,
+    to: TypeDesc<'tcx>,
+) -> Result<Vec<RewriteKind>, String> {
+    // No `LTy`s are involved, so the permission and flag tables are never read.
+    let perms = GlobalPointerTable::<PermissionSet>::empty();
+    let flags = GlobalPointerTable::<FlagSet>::empty();
+    let mut rewrites = Vec::new();
+    CastBuilder::new(tcx, &perms, &flags, |rk| rewrites.push(rk))
+        .try_build_cast_desc_desc(from, to)?;
+    Ok(rewrites)
+}
+
+/// Resolve the current `rustc` sysroot using `rustc --print sysroot`.
+///
+/// Normally, `rustc` looks up the sysroot by the location of its own binary.
+/// This works because the `rustc` on `$PATH` is actually `rustup`,
+/// and `rustup` invokes the real `rustc`, which is in a location relative to the sysroot.
+/// As we invoke `rustc_driver` directly here, we are `rustc`,
+/// and thus we have to explicitly specify the sysroot that the real `rustc` would normally use.
+///
+/// Note that the sysroot contains the toolchain and host target name,
+/// but this has no effect on cross-compiling.
+/// Every toolchain's `rustc` is able to itself cross-compile.
+/// It's unclear why the host target needs to be in the sysroot directory name, but it is.
+///
+/// Also note that this sysroot lookup should be done at runtime,
+/// not at compile-time in the `build.rs`,
+/// as the toolchain locations could be different
+/// from where this binary was compiled and where it is running
+/// (it could be on a different machine with a different `$RUSTUP_HOME`).
+///
+/// TODO(kkysen) deduplicate this with `c2rust_build_paths::SysRoot::resolve`
+pub fn resolve_sysroot() -> anyhow::Result<PathBuf> {
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = Command::new(rustc)
+        .args(&["--print", "sysroot"])
+        .output()
+        .context("could not invoke `rustc` to find rust sysroot")?;
+    // trim, but `str::trim` doesn't exist on `[u8]`
+    let path = output
+        .stdout
+        .as_slice()
+        .split(|c| c.is_ascii_whitespace())
+        .next()
+        .unwrap_or_default();
+    #[cfg(unix)]
+    let path = {
+        use std::os::unix::ffi::OsStrExt;
+
+        OsStr::from_bytes(path)
+    };
+    #[cfg(not(unix))]
+    let path = {
+        // Windows is hard, so just require UTF-8
+        let path = std::str::from_utf8(path).context("`rustc --print sysroot` is not UTF-8")?;
+        OsStr::new(path)
+    };
+    let path = Path::new(path).to_owned();
+    // `rustc` reports a million errors if the sysroot is wrong, so try to check first.
+    ensure!(
+        path.is_dir(),
+        "invalid sysroot (not a dir): {}",
+        path.display()
+    );
+    Ok(path)
+}
@@ -0,0 +1,221 @@
+//! Inference of the objects shared with other threads, which can be rewritten to `Arc<Mutex<T>>`,
+//! for `--arc-mutex`.
+//!
+//! A C program starts a thread with `pthread_create(&t, attr, start_routine, arg)`, and the new
+//! thread receives `arg` as the `void*` argument of `start_routine`.  The object behind `arg` is
+//! then shared between two threads, which neither `Box<T>` nor `Rc<RefCell<T>>` can express.  The
+//! pointer passed as `arg` and the pointers that `start_routine` casts its argument to start out
+//! with [`FlagSet::MUTEX`], which then spreads to every single-object pointer that exchanges values
+//! with one that has it (see [`DataflowConstraints::propagate_shared`]), so both threads hold
+//! handles to the same `Arc<Mutex<T>>`.
+//!
+//! The argument itself keeps its `void*` type, so that `start_routine` can still be passed to
+//! `pthread_create`.  The spawning thread hands over its handle with `Arc::into_raw`, and the new
+//! thread takes it back with `Arc::from_raw` where it casts the argument to the object's type.
+//! When `start_routine` isn't a function that will be rewritten, `arg` never gets the flag.
+//!
+//! `std::thread::spawn` passes data to the new thread through the captures of a closure.  Closure
+//! bodies aren't rewritten, so the raw pointers captured by a closure passed to
+//! `std::thread::spawn` keep their types instead.
+//!
+//! [`FlagSet::MUTEX`]: crate::context::FlagSet::MUTEX
+//! [`DataflowConstraints::propagate_shared`]: crate::dataflow::DataflowConstraints::propagate_shared
+
+use crate::context::AnalysisCtxt;
+use crate::pointer_id::PointerId;
+use crate::util::{ty_callee, Callee, UnknownDefCallee};
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir::{
+    AggregateKind, Body, CastKind, Local, Location, Operand, Rvalue, StatementKind, TerminatorKind,
+};
+use rustc_middle::ty::adjustment::PointerCast;
+use rustc_middle::ty::TyKind;
+use std::collections::HashSet;
+
+/// A call that starts a new thread.
+#[derive(Clone, Debug)]
+pub enum ThreadSpawn {
+    /// `pthread_create(thread, attr, start_routine, arg)`.  `start_routine` is `None` if it isn't
+    /// the name of a function.
+    PthreadCreate {
+        arg: PointerId,
+        start_routine: Option<DefId>,
+    },
+    /// `std::thread::spawn(f)`, where `captures` are the raw pointers captured by the closure `f`.
+    StdSpawn { captures: Vec<PointerId> },
+}
+
+/// The uses of the argument of a thread's start routine.
+#[derive(Clone, Debug, Default)]
+pub struct StartRoutineArg {
+    /// The argument and the locals it's copied to, which keep their raw pointer types.
+    pub copies: Vec<PointerId>,
+    /// The results of casting the argument to the type of the shared object.
+    pub casts: Vec<PointerId>,
+}
+
+/// Find the calls in `mir` that start a new thread.
+pub fn find_thread_spawns<'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    mir: &Body<'tcx>,
+) -> Vec<ThreadSpawn> {
+    let tcx = acx.tcx();
+    let mut spawns = Vec::new();
+    for bb_data in mir.basic_blocks().iter() {
+        let (func, args) = match bb_data.terminator().kind {
+            TerminatorKind::Call {
+                ref func, ref args, ..
+            } => (func, args),
+            _ => continue,
+        };
+        let def_id = match ty_callee(tcx, func.ty(mir, tcx)) {
+            Callee::UnknownDef(UnknownDefCallee::Direct { def_id, .. }) => def_id,
+            _ => continue,
+        };
+
+        if tcx.item_name(def_id).as_str() == "pthread_create" && args.len() == 4 {
+            let arg = acx.type_of(&args[3]).label;
+            if arg.is_none() {
+                continue;
+            }
+            let start_routine =
+                operand_local(&args[2]).and_then(|local| find_fn_item(acx, mir, local));
+            spawns.push(ThreadSpawn::PthreadCreate { arg, start_routine });
+        } else if tcx.def_path_str(def_id) == "std::thread::spawn" && args.len() == 1 {
+            let captures = operand_local(&args[0])
+                .and_then(|local| find_def(mir, local))
+                .map(|rv| match *rv {
+                    Rvalue::Aggregate(ref kind, ref ops)
+                        if matches!(**kind, AggregateKind::Closure(..)) =>
+                    {
+                        ops.iter()
+                            .flat_map(|op| acx.type_of(op).iter())
+                            .filter(|lty| lty.ty.is_unsafe_ptr() && !lty.label.is_none())
+                            .map(|lty| lty.label)
+                            .collect()
+                    }
+                    _ => Vec::new(),
+                })
+                .unwrap_or_default();
+            spawns.push(ThreadSpawn::StdSpawn { captures });
+        }
+    }
+    spawns
+}
+
+/// Find the uses of the first argument of `mir`, the body of a thread's start routine.
+pub fn find_start_routine_arg<'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    mir: &Body<'tcx>,
+) -> StartRoutineArg {
+    let mut result = StartRoutineArg::default();
+    if mir.arg_count == 0 {
+        return result;
+    }
+
+    // Collect the argument and the locals it's copied to, such as the temporary that holds it
+    // before a cast.
+    let mut copies = HashSet::from([Local::from_u32(1)]);
+    loop {
+        let mut changed = false;
+        for bb_data in mir.basic_blocks().iter() {
+            for stmt in &bb_data.statements {
+                let (pl, rv) = match stmt.kind {
+                    StatementKind::Assign(ref x) => (x.0, &x.1),
+                    _ => continue,
+                };
+                if let Rvalue::Use(ref op) = *rv {
+                    let is_copy = operand_local(op).map_or(false, |l| copies.contains(&l));
+                    if is_copy {
+                        if let Some(local) = pl.as_local() {
+                            changed |= copies.insert(local);
+                        }
+                    }
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for &local in &copies {
+        let ptr = acx.type_of(local).label;
+        if !ptr.is_none() {
+            result.copies.push(ptr);
+        }
+    }
+
+    for (bb, bb_data) in mir.basic_blocks().iter_enumerated() {
+        for (statement_index, stmt) in bb_data.statements.iter().enumerate() {
+            let rv = match stmt.kind {
+                StatementKind::Assign(ref x) => &x.1,
+                _ => continue,
+            };
+            if let Rvalue::Cast(_, ref op, _) = *rv {
+                if operand_local(op).map_or(false, |l| copies.contains(&l)) {
+                    let loc = Location {
+                        block: bb,
+                        statement_index,
+                    };
+                    let ptr = acx.type_of_rvalue(rv, loc).label;
+                    if !ptr.is_none() {
+                        result.casts.push(ptr);
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+/// The local read by `op`, if it reads a whole local.
+fn operand_local(op: &Operand) -> Option<Local> {
+    match *op {
+        Operand::Copy(pl) | Operand::Move(pl) => pl.as_local(),
+        Operand::Constant(..) => None,
+    }
+}
+
+/// The `Rvalue` assigned to `local`, if there's exactly one assignment to it in `mir`.
+fn find_def<'a, 'tcx>(mir: &'a Body<'tcx>, local: Local) -> Option<&'a Rvalue<'tcx>> {
+    let mut defs = mir
+        .basic_blocks()
+        .iter()
+        .flat_map(|bb_data| &bb_data.statements)
+        .filter_map(|stmt| match stmt.kind {
+            StatementKind::Assign(ref x) if x.0.as_local() == Some(local) => Some(&x.1),
+            _ => None,
+        });
+    let rv = defs.next()?;
+    match defs.next() {
+        Some(_) => None,
+        None => Some(rv),
+    }
+}
+
+/// Resolve the function pointer in `local`, as in `Some(f as unsafe extern "C" fn(..))`, to the
+/// function item it was created from.
+fn find_fn_item<'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    mir: &Body<'tcx>,
+    mut local: Local,
+) -> Option<DefId> {
+    let tcx = acx.tcx();
+    loop {
+        match *find_def(mir, local)? {
+            Rvalue::Use(ref op) => local = operand_local(op)?,
+            Rvalue::Aggregate(ref kind, ref ops) => match **kind {
+                AggregateKind::Adt(..) if ops.len() == 1 => local = operand_local(&ops[0])?,
+                _ => return None,
+            },
+            Rvalue::Cast(CastKind::Pointer(PointerCast::ReifyFnPointer), ref op, _) => {
+                return match *op.ty(mir, tcx).kind() {
+                    TyKind::FnDef(def_id, _) => Some(def_id),
+                    _ => None,
+                };
+            }
+            _ => return None,
+        }
+    }
+}
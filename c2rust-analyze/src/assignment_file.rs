@@ -0,0 +1,452 @@
+//! Textual dump/restore of the permission and flag assignment.
+//!
+//! The file format is line-based.  Blank lines and lines starting with `#` are ignored.  A
+//! `global <N>` line starts the section for the `N` global pointers, and a `fn <N> <DefId>` line
+//! starts the section for the `N` local pointers of the function with the given `DefId` (printed
+//! in `Debug` format, as in the `--fixed-defs-list` file).  Each other line has the form
+//! `<pointer> <permissions> <flags>`, such as `l3 READ|WRITE|UNIQUE CELL`, where permissions and
+//! flags are `|`-separated lists of names, or `-` for the empty set.
+//!
+//! `PointerId`s are assigned deterministically, so a file dumped by one run can be loaded into a
+//! later run on the same unmodified input.  Each entry is checked against the pointer count of
+//! its section when the file is read, and the pointer counts are checked against the crate when
+//! the file is applied, so a hand-edited or stale file produces an error instead of a panic.
+
+use crate::context::{FlagSet, GlobalAssignment, LocalAssignment, PermissionSet};
+use crate::pointer_id::PointerId;
+use rustc_hir::def_id::DefId;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::str::FromStr;
+
+const PERMISSION_NAMES: &[(&str, PermissionSet)] = &[
+    ("READ", PermissionSet::READ),
+    ("WRITE", PermissionSet::WRITE),
+    ("UNIQUE", PermissionSet::UNIQUE),
+    ("LINEAR", PermissionSet::LINEAR),
+    ("OFFSET_ADD", PermissionSet::OFFSET_ADD),
+    ("OFFSET_SUB", PermissionSet::OFFSET_SUB),
+    ("FREE", PermissionSet::FREE),
+    ("NON_NULL", PermissionSet::NON_NULL),
+];
+
+const FLAG_NAMES: &[(&str, FlagSet)] = &[
+    ("CELL", FlagSet::CELL),
+    ("FIXED", FlagSet::FIXED),
+    ("C_STRING", FlagSet::C_STRING),
+    ("VEC", FlagSet::VEC),
+    ("REFCELL", FlagSet::REFCELL),
+    ("MUTEX", FlagSet::MUTEX),
+];
+
+fn format_set<T: Copy + PartialEq + Default>(
+    names: &[(&str, T)],
+    value: T,
+    contains: impl Fn(&T, T) -> bool,
+) -> String {
+    if value == T::default() {
+        return "-".into();
+    }
+    names
+        .iter()
+        .filter(|&&(_, bit)| contains(&value, bit))
+        .map(|&(name, _)| name)
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn parse_set<T: Copy + Default>(
+    names: &[(&str, T)],
+    s: &str,
+    union: impl Fn(T, T) -> T,
+) -> Result<T, String> {
+    let mut value = T::default();
+    if s == "-" {
+        return Ok(value);
+    }
+    for part in s.split('|') {
+        let bit = names
+            .iter()
+            .find(|&&(name, _)| name == part)
+            .map(|&(_, bit)| bit)
+            .ok_or_else(|| format!("unknown name {:?}", part))?;
+        value = union(value, bit);
+    }
+    Ok(value)
+}
+
+/// Format `perms` as a `|`-separated list of names, or `-` for the empty set.
+pub fn format_perms(perms: PermissionSet) -> String {
+    format_set(PERMISSION_NAMES, perms, PermissionSet::contains)
+}
+
+/// Parse a permission set in the format produced by [`format_perms`].
+pub fn parse_perms(s: &str) -> Result<PermissionSet, String> {
+    parse_set(PERMISSION_NAMES, s, PermissionSet::union)
+}
+
+fn format_entry(out: &mut String, ptr: PointerId, perms: PermissionSet, flags: FlagSet) {
+    writeln!(
+        out,
+        "{} {} {}",
+        ptr,
+        format_perms(perms),
+        format_set(FLAG_NAMES, flags, FlagSet::contains),
+    )
+    .unwrap();
+}
+
+/// Builder for the text of an assignment file.
+pub struct AssignmentWriter {
+    out: String,
+}
+
+impl AssignmentWriter {
+    pub fn new() -> AssignmentWriter {
+        let mut out = String::new();
+        out.push_str("# c2rust-analyze permission/flag assignment\n");
+        out.push_str("# Format: `<pointer> <permissions> <flags>`; use `-` for an empty set.\n");
+        AssignmentWriter { out }
+    }
+
+    /// Emit a `#` comment line, such as a description of the pointers that follow.
+    pub fn comment(&mut self, s: &str) {
+        for line in s.lines() {
+            writeln!(self.out, "# {}", line).unwrap();
+        }
+    }
+
+    pub fn global(&mut self, gasn: &GlobalAssignment) {
+        writeln!(self.out, "\nglobal {}", gasn.perms.len()).unwrap();
+        for (ptr, &perms) in gasn.perms.iter() {
+            format_entry(&mut self.out, ptr, perms, gasn.flags[ptr]);
+        }
+    }
+
+    /// Begin the section for the local pointers of `def_id`.  Comments emitted after this call
+    /// and before `local_entries` appear at the top of the section.
+    pub fn begin_fn(&mut self, def_id: DefId, lasn: &LocalAssignment) {
+        writeln!(self.out, "\nfn {} {:?}", lasn.perms.len(), def_id).unwrap();
+    }
+
+    pub fn local_entries(&mut self, lasn: &LocalAssignment) {
+        for (ptr, &perms) in lasn.perms.iter() {
+            format_entry(&mut self.out, ptr, perms, lasn.flags[ptr]);
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+/// The entries of one section of an assignment file.
+#[derive(Clone, Debug, Default)]
+pub struct AssignmentSection {
+    /// The line number of the `global` or `fn` line that starts the section.
+    pub line: usize,
+    pub num_pointers: usize,
+    pub entries: Vec<(PointerId, PermissionSet, FlagSet)>,
+}
+
+impl AssignmentSection {
+    /// Check that the section's pointer count matches the `num_pointers` of the crate.
+    /// `what` describes the section for the error message.
+    fn check_count(&self, num_pointers: usize, what: &str) -> Result<(), String> {
+        if self.num_pointers == num_pointers {
+            return Ok(());
+        }
+        Err(format!(
+            "line {}: {} has {} pointers, but the crate has {}; \
+             was it generated from a different version of the input?",
+            self.line, what, self.num_pointers, num_pointers
+        ))
+    }
+}
+
+/// The parsed contents of an assignment file.
+#[derive(Clone, Debug, Default)]
+pub struct AssignmentFile {
+    pub global: AssignmentSection,
+    pub fns: HashMap<DefId, AssignmentSection>,
+}
+
+impl AssignmentFile {
+    /// Overwrite the entries of `gasn` with those in the `global` section.  Fails without
+    /// changing `gasn` if the section has the wrong number of pointers.
+    pub fn apply_global(&self, gasn: &mut GlobalAssignment) -> Result<(), String> {
+        self.global
+            .check_count(gasn.perms.len(), "the global section")?;
+        // `parse_line` checked that each entry is a global pointer within `num_pointers`.
+        for &(ptr, perms, flags) in &self.global.entries {
+            gasn.perms[ptr] = perms;
+            gasn.flags[ptr] = flags;
+        }
+        Ok(())
+    }
+
+    /// Overwrite the entries of `lasn` with those in the section for `def_id`.  Returns `false`
+    /// if the file has no section for `def_id`, and fails without changing `lasn` if the
+    /// section has the wrong number of pointers.
+    pub fn apply_local(&self, def_id: DefId, lasn: &mut LocalAssignment) -> Result<bool, String> {
+        let section = match self.fns.get(&def_id) {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+        section.check_count(lasn.perms.len(), &format!("the section for {:?}", def_id))?;
+        // `parse_line` checked that each entry is a local pointer within `num_pointers`.
+        for &(ptr, perms, flags) in &section.entries {
+            lasn.perms[ptr] = perms;
+            lasn.flags[ptr] = flags;
+        }
+        Ok(true)
+    }
+}
+
+fn parse_pointer_id(s: &str) -> Result<PointerId, String> {
+    let (ctor, index): (fn(u32) -> PointerId, _) = if let Some(index) = s.strip_prefix('g') {
+        (PointerId::global, index)
+    } else if let Some(index) = s.strip_prefix('l') {
+        (PointerId::local, index)
+    } else {
+        return Err(format!("bad pointer {:?}: expected `g<N>` or `l<N>`", s));
+    };
+    let index = u32::from_str(index)
+        .ok()
+        // The top bit of a `PointerId` marks it as global, and the largest global index is
+        // reserved for `PointerId::NONE`.
+        .filter(|&index| index < 0x7fff_ffff)
+        .ok_or_else(|| format!("bad pointer index in {:?}", s))?;
+    Ok(ctor(index))
+}
+
+fn parse_line(
+    file: &mut AssignmentFile,
+    current: &mut Option<Option<DefId>>,
+    line_no: usize,
+    line: &str,
+    parse_def_id: &impl Fn(&str) -> Result<DefId, String>,
+) -> Result<(), String> {
+    if let Some(rest) = line.strip_prefix("global ") {
+        let num_pointers = usize::from_str(rest.trim()).map_err(|_| "bad pointer count")?;
+        file.global.line = line_no;
+        file.global.num_pointers = num_pointers;
+        *current = Some(None);
+        return Ok(());
+    }
+
+    if let Some(rest) = line.strip_prefix("fn ") {
+        let (count, def_id) = rest
+            .split_once(' ')
+            .ok_or("expected `fn <count> <DefId>`")?;
+        let num_pointers = usize::from_str(count).map_err(|_| "bad pointer count")?;
+        let def_id = parse_def_id(def_id.trim())?;
+        let section = AssignmentSection {
+            line: line_no,
+            num_pointers,
+            entries: Vec::new(),
+        };
+        if let Some(old) = file.fns.insert(def_id, section) {
+            return Err(format!(
+                "duplicate section for {:?} (first on line {})",
+                def_id, old.line
+            ));
+        }
+        *current = Some(Some(def_id));
+        return Ok(());
+    }
+
+    let mut words = line.split_ascii_whitespace();
+    let (ptr, perms, flags) = match (words.next(), words.next(), words.next(), words.next()) {
+        (Some(ptr), Some(perms), Some(flags), None) => (ptr, perms, flags),
+        _ => return Err("expected `<pointer> <permissions> <flags>`".into()),
+    };
+    let ptr = parse_pointer_id(ptr)?;
+    let perms = parse_set(PERMISSION_NAMES, perms, PermissionSet::union)?;
+    let flags = parse_set(FLAG_NAMES, flags, FlagSet::union)?;
+    let (section, kind_ok, kind) = match *current {
+        None => return Err("entry appears before any `global` or `fn` line".into()),
+        Some(None) => (&mut file.global, ptr.is_global(), "global"),
+        Some(Some(def_id)) => (file.fns.get_mut(&def_id).unwrap(), ptr.is_local(), "local"),
+    };
+    if !kind_ok {
+        return Err(format!(
+            "expected a {} pointer in the section starting on line {}, but got {}",
+            kind, section.line, ptr
+        ));
+    }
+    if ptr.index() as usize >= section.num_pointers {
+        return Err(format!(
+            "pointer {} is out of range for the {} pointers of the section starting on line {}",
+            ptr, section.num_pointers, section.line
+        ));
+    }
+    section.entries.push((ptr, perms, flags));
+    Ok(())
+}
+
+/// Read an assignment file.  `parse_def_id` is used to parse the `DefId` on each `fn` line.  A
+/// line that can't be parsed produces an `InvalidData` error naming the file and line number.
+pub fn read_assignment_file(
+    path: &str,
+    parse_def_id: impl Fn(&str) -> Result<DefId, String>,
+) -> io::Result<AssignmentFile> {
+    read_assignment(BufReader::new(File::open(path)?), path, parse_def_id)
+}
+
+fn read_assignment(
+    f: impl BufRead,
+    path: &str,
+    parse_def_id: impl Fn(&str) -> Result<DefId, String>,
+) -> io::Result<AssignmentFile> {
+    let mut file = AssignmentFile::default();
+    let mut current = None;
+    for (i, line) in f.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        parse_line(&mut file, &mut current, i + 1, line, &parse_def_id).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("failed to parse {} line {}: {}", path, i + 1, e),
+            )
+        })?;
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_round_trip() {
+        let perms = PermissionSet::READ | PermissionSet::OFFSET_ADD | PermissionSet::NON_NULL;
+        let s = format_set(PERMISSION_NAMES, perms, PermissionSet::contains);
+        assert_eq!(s, "READ|OFFSET_ADD|NON_NULL");
+        assert_eq!(
+            parse_set(PERMISSION_NAMES, &s, PermissionSet::union),
+            Ok(perms)
+        );
+
+        let s = format_set(FLAG_NAMES, FlagSet::empty(), FlagSet::contains);
+        assert_eq!(s, "-");
+        assert_eq!(
+            parse_set(FLAG_NAMES, &s, FlagSet::union),
+            Ok(FlagSet::empty())
+        );
+
+        assert!(parse_set(FLAG_NAMES, "CELL|BOGUS", FlagSet::union).is_err());
+    }
+
+    #[test]
+    fn pointer_id_round_trip() {
+        for ptr in [PointerId::global(7), PointerId::local(0)] {
+            assert_eq!(parse_pointer_id(&ptr.to_string()), Ok(ptr));
+        }
+        assert!(parse_pointer_id("x3").is_err());
+    }
+
+    #[test]
+    fn parse_error_line_number() {
+        let text = "# comment\nglobal 1\ng0 READ BOGUS\n";
+        let err = read_assignment(text.as_bytes(), "test.txt", |s| Err(s.to_owned())).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "failed to parse test.txt line 3: unknown name \"BOGUS\""
+        );
+
+        let text = "l0 READ -\n";
+        let err = read_assignment(text.as_bytes(), "test.txt", |s| Err(s.to_owned())).unwrap_err();
+        assert!(err.to_string().contains("line 1:"), "{}", err);
+    }
+
+    fn parse_test_def_id(s: &str) -> Result<DefId, String> {
+        let index = s
+            .strip_prefix("f")
+            .and_then(|s| u32::from_str(s).ok())
+            .ok_or_else(|| format!("bad DefId {:?}", s))?;
+        Ok(DefId {
+            krate: rustc_hir::def_id::LOCAL_CRATE,
+            index: rustc_hir::def_id::DefIndex::from_u32(index),
+        })
+    }
+
+    fn read_err(text: &str) -> String {
+        read_assignment(text.as_bytes(), "test.txt", parse_test_def_id)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn malformed_entries() {
+        assert_eq!(
+            read_err("global 2\nl0 READ -\n"),
+            "failed to parse test.txt line 2: \
+             expected a global pointer in the section starting on line 1, but got l0"
+        );
+        assert_eq!(
+            read_err("global 0\nfn 1 f1\ng0 READ -\n"),
+            "failed to parse test.txt line 3: \
+             expected a local pointer in the section starting on line 2, but got g0"
+        );
+        assert_eq!(
+            read_err("global 2\ng1 READ -\ng2 READ -\n"),
+            "failed to parse test.txt line 3: \
+             pointer g2 is out of range for the 2 pointers of the section starting on line 1"
+        );
+        assert_eq!(
+            read_err("fn 1 f1\nl0 - -\n\nfn 3 f1\n"),
+            "failed to parse test.txt line 4: duplicate section for DefId(0:1) (first on line 1)"
+        );
+        assert_eq!(
+            read_err("global 1\ng4294967295 - -\n"),
+            "failed to parse test.txt line 2: bad pointer index in \"g4294967295\""
+        );
+        assert_eq!(
+            read_err("global 1\ng2147483647 - -\n"),
+            "failed to parse test.txt line 2: bad pointer index in \"g2147483647\""
+        );
+    }
+
+    #[test]
+    fn wrong_pointer_counts() {
+        let text = "global 2\ng1 WRITE -\n\nfn 1 f1\nl0 READ CELL\n";
+        let file = read_assignment(text.as_bytes(), "test.txt", parse_test_def_id).unwrap();
+        let def_id = parse_test_def_id("f1").unwrap();
+
+        let mut gasn = GlobalAssignment::new(3, PermissionSet::empty(), FlagSet::empty());
+        assert_eq!(
+            file.apply_global(&mut gasn),
+            Err(
+                "line 1: the global section has 2 pointers, but the crate has 3; \
+                 was it generated from a different version of the input?"
+                    .to_owned()
+            )
+        );
+        assert_eq!(gasn.perms[PointerId::global(1)], PermissionSet::empty());
+        let mut gasn = GlobalAssignment::new(2, PermissionSet::empty(), FlagSet::empty());
+        assert_eq!(file.apply_global(&mut gasn), Ok(()));
+        assert_eq!(gasn.perms[PointerId::global(1)], PermissionSet::WRITE);
+
+        let mut lasn = LocalAssignment::new(2, PermissionSet::empty(), FlagSet::empty());
+        assert_eq!(
+            file.apply_local(def_id, &mut lasn),
+            Err(
+                "line 4: the section for DefId(0:1) has 1 pointers, but the crate has 2; \
+                 was it generated from a different version of the input?"
+                    .to_owned()
+            )
+        );
+        let mut lasn = LocalAssignment::new(1, PermissionSet::empty(), FlagSet::empty());
+        assert_eq!(file.apply_local(def_id, &mut lasn), Ok(true));
+        assert_eq!(lasn.flags[PointerId::local(0)], FlagSet::CELL);
+        let other = parse_test_def_id("f2").unwrap();
+        assert_eq!(file.apply_local(other, &mut lasn), Ok(false));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::borrowck::atoms::{AllFacts, AtomMaps, Loan, Path, SubPoint};
+use log::trace;
 use rustc_middle::mir::visit::{
     MutatingUseContext, NonMutatingUseContext, NonUseContext, PlaceContext, Visitor,
 };
@@ -96,7 +97,7 @@ struct DefUseVisitor<'tcx, 'a> {
 impl<'tcx> Visitor<'tcx> for DefUseVisitor<'tcx, '_> {
     fn visit_place(&mut self, place: &Place<'tcx>, context: PlaceContext, location: Location) {
         self.super_place(place, context, location);
-        eprintln!(
+        trace!(
             "visit place {:?} with context {:?} = {:?} at {:?}",
             place,
             context,
@@ -132,7 +133,7 @@ impl<'tcx> Visitor<'tcx> for DefUseVisitor<'tcx, '_> {
     }
 
     fn visit_local(&mut self, local: Local, context: PlaceContext, location: Location) {
-        eprintln!(
+        trace!(
             "visit local {:?} with context {:?} = {:?} at {:?}",
             local,
             context,
@@ -157,7 +158,7 @@ impl<'tcx> Visitor<'tcx> for DefUseVisitor<'tcx, '_> {
 
     fn visit_statement(&mut self, stmt: &Statement<'tcx>, location: Location) {
         self.super_statement(stmt, location);
-        eprintln!("visit stmt {:?} at {:?}", stmt, location);
+        trace!("visit stmt {:?} at {:?}", stmt, location);
 
         if let StatementKind::StorageDead(local) = stmt.kind {
             // Observed: `StorageDead` emits `path_moved_at_base` at the `Mid` point.
@@ -194,7 +195,7 @@ impl<'tcx> LoanInvalidatedAtVisitor<'tcx, '_> {
         context: PlaceContext,
         location: Location,
     ) {
-        eprintln!(
+        trace!(
             "access loan {:?} (kind {:?}) at location {:?} (context {:?} = {:?})",
             loan,
             borrow_kind,
@@ -223,7 +224,7 @@ impl<'tcx> LoanInvalidatedAtVisitor<'tcx, '_> {
 impl<'tcx> Visitor<'tcx> for LoanInvalidatedAtVisitor<'tcx, '_> {
     fn visit_place(&mut self, place: &Place<'tcx>, context: PlaceContext, location: Location) {
         //self.super_place(place, context, location);
-        eprintln!(
+        trace!(
             "loan_invalidated_at: visit place {:?} with context {:?} = {:?} at {:?}",
             place,
             context,
@@ -260,7 +261,7 @@ impl<'tcx> Visitor<'tcx> for LoanInvalidatedAtVisitor<'tcx, '_> {
     }
 
     fn visit_local(&mut self, local: Local, context: PlaceContext, location: Location) {
-        eprintln!(
+        trace!(
             "loan_invalidated_at: visit local {:?} with context {:?} = {:?} at {:?}",
             local,
             context,
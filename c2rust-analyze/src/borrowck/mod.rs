@@ -5,8 +5,10 @@ use crate::context::{AnalysisCtxt, PermissionSet};
 use crate::dataflow::DataflowConstraints;
 use crate::labeled_ty::{LabeledTy, LabeledTyCtxt};
 use crate::pointer_id::{PointerTable, PointerTableMut};
+use crate::profile;
 use crate::util::{describe_rvalue, RvalueDesc};
 use indexmap::{IndexMap, IndexSet};
+use log::trace;
 use rustc_hir::def_id::DefId;
 use rustc_middle::mir::{Body, LocalKind, Place, StatementKind, START_BLOCK};
 use rustc_middle::ty::{
@@ -130,6 +132,7 @@ pub fn borrowck_mir<'tcx>(
     loop {
         eprintln!("run polonius");
         let (facts, maps, output) = run_polonius(acx, hypothesis, name, mir, &field_ltys);
+        profile::count(facts.subset_base.len());
         eprintln!(
             "polonius: iteration {}: {} errors, {} move_errors",
             i,
@@ -173,7 +176,7 @@ pub fn borrowck_mir<'tcx>(
                     },
                     _ => panic!("loan {:?} was issued by non-assign stmt {:?}?", loan, stmt),
                 };
-                eprintln!("want to drop UNIQUE from pointer {:?}", ptr);
+                trace!("want to drop UNIQUE from pointer {:?}", ptr);
 
                 if hypothesis[ptr].contains(PermissionSet::UNIQUE) {
                     hypothesis[ptr].remove(PermissionSet::UNIQUE);
@@ -257,10 +260,10 @@ fn run_polonius<'tcx>(
 
     // Populate `cfg_edge`
     for (bb, bb_data) in mir.basic_blocks().iter_enumerated() {
-        eprintln!("{:?}:", bb);
+        trace!("{:?}:", bb);
 
         for idx in 0..bb_data.statements.len() {
-            eprintln!("  {}: {:?}", idx, bb_data.statements[idx]);
+            trace!("  {}: {:?}", idx, bb_data.statements[idx]);
             let start = maps.point(bb, idx, SubPoint::Start);
             let mid = maps.point(bb, idx, SubPoint::Mid);
             let next_start = maps.point(bb, idx + 1, SubPoint::Start);
@@ -269,7 +272,7 @@ fn run_polonius<'tcx>(
         }
 
         let term_idx = bb_data.statements.len();
-        eprintln!("  {}: {:?}", term_idx, bb_data.terminator());
+        trace!("  {}: {:?}", term_idx, bb_data.terminator());
         let term_start = maps.point(bb, term_idx, SubPoint::Start);
         let term_mid = maps.point(bb, term_idx, SubPoint::Mid);
         facts.cfg_edge.push((term_start, term_mid));
@@ -7,11 +7,12 @@ use crate::pointer_id::PointerTableMut;
 use crate::util::{self, ty_callee, Callee};
 use assert_matches::assert_matches;
 use indexmap::IndexMap;
+use log::trace;
 use rustc_hir::def_id::DefId;
 use rustc_index::vec::IndexVec;
 use rustc_middle::mir::{
-    AggregateKind, BinOp, Body, BorrowKind, CastKind, Field, Local, LocalDecl, Location, Operand,
-    Place, Rvalue, Statement, StatementKind, Terminator, TerminatorKind,
+    AggregateKind, BinOp, Body, BorrowKind, CastKind, Local, LocalDecl, Location, Operand, Place,
+    Rvalue, Statement, StatementKind, Terminator, TerminatorKind,
 };
 use rustc_middle::ty::adjustment::PointerCast;
 use rustc_middle::ty::{AdtDef, FieldDef, RegionKind, TyKind};
@@ -42,10 +43,14 @@ impl<'tcx> TypeChecker<'tcx, '_> {
         )
     }
 
-    pub fn field_lty(&self, base_lty: LTy<'tcx>, base_adt_def: AdtDef, field: Field) -> LTy<'tcx> {
+    pub fn field_lty(
+        &self,
+        base_lty: LTy<'tcx>,
+        base_adt_def: AdtDef,
+        field_def: &FieldDef,
+    ) -> LTy<'tcx> {
         let base_origin_param_map: IndexMap<OriginParam, Origin> =
             IndexMap::from_iter(base_lty.label.origin_params.to_vec());
-        let field_def: &FieldDef = &base_adt_def.non_enum_variant().fields[field.index()];
         let perm = self.field_permissions[&field_def.did];
         let base_metadata = &self.acx.gacx.adt_metadata.table[&base_adt_def.did()];
         let field_metadata = &base_metadata.field_info[&field_def.did];
@@ -66,7 +71,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                                 OriginParam::try_from(&oa).unwrap()
                             })
                             .and_then(|o| {
-                                eprintln!(
+                                trace!(
                                     "finding {o:?} in {base_adt_def:?} {base_origin_param_map:?}"
                                 );
                                 base_origin_param_map.get(&o)
@@ -107,7 +112,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                             `'0`) and `Foo` lifetime parameter `'a`. This mapping is created below.
                         */
                         let mut field_origin_param_map = vec![];
-                        eprintln!("{:?}", fadt_def.did());
+                        trace!("{:?}", fadt_def.did());
                         let field_adt_metadata = if let Some(field_adt_metadata) = self.acx.gacx.adt_metadata.table.get(&fadt_def.did()) {
                             field_adt_metadata
                         } else {
@@ -127,7 +132,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                             field_lifetime_param.and_then(|field_lifetime_param| {
                                 base_origin_param_map.get_key_value(&field_lifetime_param)
                             }).map(|(base_lifetime_param, og)| {
-                                eprintln!(
+                                trace!(
                                     "mapping {base_adt_def:?} lifetime parameter {base_lifetime_param:?} to \
                                     {base_adt_def:?}.{:} struct definition lifetime parameter {field_struct_lifetime_param:?}, \
                                     corresponding to its lifetime parameter {field_lifetime_param:?} within {base_adt_def:?}",
@@ -157,10 +162,14 @@ impl<'tcx> TypeChecker<'tcx, '_> {
 
     pub fn visit_place(&self, pl: Place<'tcx>) -> LTy<'tcx> {
         let mut lty: LTy = self.local_ltys[pl.local.index()];
+        let mut variant = None;
         for proj in pl.projection {
-            lty = util::lty_project(lty, &proj, &mut |lty, adt, f| self.field_lty(lty, adt, f));
+            lty = util::lty_project(lty, &proj, variant, &mut |lty, adt, f| {
+                self.field_lty(lty, adt, f)
+            });
+            variant = util::downcast_variant(&proj);
         }
-        eprintln!("final label for {pl:?}: {:?}", lty);
+        trace!("final label for {pl:?}: {:?}", lty);
         lty
     }
 
@@ -188,19 +197,19 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                                 };
                                 if let Some(origin) = l.label.origin {
                                     // constrain this origin to be 'static
-                                    eprintln!("constraining origin {origin:?} to 'static lifetime");
+                                    trace!("constraining origin {origin:?} to 'static lifetime");
                                     add_subset_base(static_origin, origin);
                                     add_subset_base(origin, static_origin)
                                 }
                                 for (op, origin) in l.label.origin_params {
                                     // constrain this origin to be 'static
-                                    eprintln!("constraining origin {op:?} ({origin:?}) to 'static lifetime");
+                                    trace!("constraining origin {op:?} ({origin:?}) to 'static lifetime");
                                     add_subset_base(static_origin, *origin);
                                     add_subset_base(*origin, static_origin);
                                 }
                             }
 
-                            eprintln!("NEW STATIC LTY: {:?}", c.ty().kind());
+                            trace!("NEW STATIC LTY: {:?}", c.ty().kind());
                             let pointer_id = self.acx.type_of(op).label;
                             let perm = self.hypothesis[pointer_id];
                             let args = self.ltcx.mk_slice(&[lty]);
@@ -240,7 +249,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
             .push((path, loan, borrow_kind));
         let point = self.current_point(SubPoint::Mid);
         self.facts.loan_issued_at.push((origin, loan, point));
-        eprintln!("issued loan {:?} = {:?} ({:?})", loan, pl, borrow_kind);
+        trace!("issued loan {:?} = {:?} ({:?})", loan, pl, borrow_kind);
         origin
     }
 
@@ -413,7 +422,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                     }
                     expect_ty
                 }
-                AggregateKind::Adt(adt_did, ..) => {
+                AggregateKind::Adt(adt_did, variant_idx, ..) => {
                     /*
                         Generic types are not yet supported because of situations such as the
                         following:
@@ -444,14 +453,15 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                     assert_eq!(expect_ty.args.len(), 0, "Generic types not yet supported.");
 
                     let adt_def = tcx.adt_def(adt_did);
-                    for (fid, op) in ops.iter().enumerate() {
-                        let field_lty = self.field_lty(expect_ty, adt_def, Field::from(fid));
+                    let fields = &adt_def.variant(variant_idx).fields;
+                    for (field_def, op) in fields.iter().zip(ops.iter()) {
+                        let field_lty = self.field_lty(expect_ty, adt_def, field_def);
                         let op_lty = self.visit_operand(op);
-                        eprintln!("pseudo-assigning fields {field_lty:?} = {op_lty:?}");
+                        trace!("pseudo-assigning fields {field_lty:?} = {op_lty:?}");
                         self.do_assign(field_lty, op_lty);
                     }
 
-                    eprintln!("Aggregate literal label: {expect_ty:?}");
+                    trace!("Aggregate literal label: {expect_ty:?}");
                     expect_ty
                 }
                 _ => panic!("unsupported rvalue AggregateKind {:?}", kind),
@@ -466,7 +476,10 @@ impl<'tcx> TypeChecker<'tcx, '_> {
 
             Rvalue::Repeat(ref op, _) => {
                 if op.ty(self.local_decls, tcx).is_any_ptr() {
-                    todo!("Repeat types over pointers not yet implemented");
+                    // Pseudo-assign from the operand to the element type of the array.
+                    let op_lty = self.visit_operand(op);
+                    self.do_assign(expect_ty.args[0], op_lty);
+                    return expect_ty;
                 }
                 let ty = rv.ty(self.local_decls, *self.ltcx);
                 // TODO: create fresh origins for all pointers in `ty`, and generate subset
@@ -478,7 +491,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
     }
 
     fn do_assign(&mut self, pl_lty: LTy<'tcx>, rv_lty: LTy<'tcx>) {
-        eprintln!("assign {:?} = {:?}", pl_lty, rv_lty);
+        trace!("assign {:?} = {:?}", pl_lty, rv_lty);
 
         match (pl_lty.ty.kind(), rv_lty.ty.kind()) {
             // exempt pointer casts such as `PointerCast::MutToConstPointer`
@@ -529,7 +542,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
     }
 
     pub fn visit_terminator(&mut self, term: &Terminator<'tcx>) {
-        eprintln!("borrowck: visit_terminator({:?})", term.kind);
+        trace!("borrowck: visit_terminator({:?})", term.kind);
         let _g = panic_detail::set_current_span(term.source_info.span);
         // TODO(spernsteiner): other `TerminatorKind`s will be handled in the future
         #[allow(clippy::single_match)]
@@ -543,7 +556,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
             } => {
                 let func_ty = func.ty(self.local_decls, *self.ltcx);
                 let callee = ty_callee(*self.ltcx, func_ty);
-                eprintln!("callee = {callee:?}");
+                trace!("callee = {callee:?}");
                 match callee {
                     Callee::Trivial => {}
                     Callee::UnknownDef { .. } => {
@@ -558,6 +571,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                         assert!(args.len() == 2);
                         let rv_lty = self.visit_operand(&args[0]);
                         self.do_assign(pl_lty, rv_lty);
+                        self.visit_operand(&args[1]);
                     }
                     Callee::SliceAsPtr { .. } => {
                         // TODO: handle this like a cast
@@ -583,13 +597,62 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                             self.visit_operand(p)
                         });
                     }
-                    Callee::Memcpy => {
+                    Callee::Strdup | Callee::Strndup => {
+                        // The result is a new allocation, like the result of `malloc`.
+                        let _pl_lty = self.visit_place(destination);
+                        for arg in args {
+                            self.visit_operand(arg);
+                        }
+                    }
+                    Callee::Sprintf | Callee::Snprintf | Callee::Printf | Callee::Fprintf => {
+                        let _pl_lty = self.visit_place(destination);
+                        for arg in args {
+                            self.visit_operand(arg);
+                        }
+                    }
+                    Callee::Memcpy | Callee::Memmove => {
                         let _pl_lty = self.visit_place(destination);
                         assert_matches!(&args[..], [dest, src, _] => {
                             self.visit_operand(dest);
                             self.visit_operand(src);
                         });
                     }
+                    Callee::Memcmp => {
+                        let _pl_lty = self.visit_place(destination);
+                        assert_matches!(&args[..], [lhs, rhs, _] => {
+                            self.visit_operand(lhs);
+                            self.visit_operand(rhs);
+                        });
+                    }
+                    Callee::Memchr => {
+                        // The result points into the searched buffer, so we handle this like
+                        // `PtrOffset`.
+                        let pl_lty = self.visit_place(destination);
+                        let rv_lty = assert_matches!(&args[..], [p, c, n] => {
+                            self.visit_operand(c);
+                            self.visit_operand(n);
+                            self.visit_operand(p)
+                        });
+                        self.do_assign(pl_lty, rv_lty);
+                    }
+                    Callee::Qsort => {
+                        let _pl_lty = self.visit_place(destination);
+                        for arg in args {
+                            self.visit_operand(arg);
+                        }
+                    }
+                    Callee::Bsearch => {
+                        // The result points into the searched buffer, as with `memchr`.
+                        let pl_lty = self.visit_place(destination);
+                        let rv_lty = assert_matches!(&args[..], [key, base, n, size, cmp] => {
+                            self.visit_operand(key);
+                            self.visit_operand(n);
+                            self.visit_operand(size);
+                            self.visit_operand(cmp);
+                            self.visit_operand(base)
+                        });
+                        self.do_assign(pl_lty, rv_lty);
+                    }
                     Callee::Memset => {
                         let _pl_lty = self.visit_place(destination);
                         let _rv_lty = assert_matches!(&args[..], [dest, ..] => {
@@ -607,6 +670,9 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                         // there's no need to call `do_assign` to set up subset relations.
                         let _pl_lty = self.visit_place(destination);
                     }
+                    Callee::OptionFnPtrUnwrap => {
+                        // TODO
+                    }
                 }
             }
             // TODO(spernsteiner): handle other `TerminatorKind`s
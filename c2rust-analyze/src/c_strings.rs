@@ -0,0 +1,223 @@
+//! Inference of the pointers that can be rewritten to `CString` or `&CStr`, for `--c-strings`.
+//!
+//! Every pointer to a character type that isn't `FIXED`, written through, or offset backward
+//! starts out with [`FlagSet::C_STRING`].  Then the flag is removed from each pointer that's used
+//! in a way `CString` and `&CStr` don't support, such as a dereference, an offset, or a cast.
+//! Since those types can't be converted to or from slices, the flag is also removed from every
+//! pointer that exchanges values with one that doesn't have it (see
+//! [`DataflowConstraints::propagate_c_string`]).  The remaining strings can be passed to `strlen`,
+//! `strcmp`, `strdup`, `free`, and other foreign functions, and can be copied between locals,
+//! fields, and the arguments and results of local functions.
+//!
+//! [`DataflowConstraints::propagate_c_string`]: crate::dataflow::DataflowConstraints::propagate_c_string
+
+use crate::context::{AnalysisCtxt, Assignment, FlagSet, GlobalAnalysisCtxt, GlobalAssignment};
+use crate::context::{LTy, PermissionSet, PointerId};
+use crate::pointer_id::PointerTable;
+use crate::util::{self, ty_callee, Callee, UnknownDefCallee};
+use rustc_middle::mir::visit::{PlaceContext, Visitor};
+use rustc_middle::mir::{
+    Body, Location, Operand, Place, ProjectionElem, Rvalue, StatementKind, Terminator,
+    TerminatorKind,
+};
+use rustc_middle::ty::TyKind;
+
+/// Whether the pointer `lty`, which has `perms` and `flags`, may be a string.
+fn is_candidate(lty: LTy, perms: PermissionSet, flags: FlagSet) -> bool {
+    is_raw_candidate(lty, perms, flags) && util::is_c_char(lty.args[0].ty)
+}
+
+/// Like [`is_candidate`], but for a pointer of any pointee type.
+fn is_raw_candidate(lty: LTy, perms: PermissionSet, flags: FlagSet) -> bool {
+    !lty.label.is_none()
+        && matches!(lty.ty.kind(), TyKind::RawPtr(..))
+        && !flags.contains(FlagSet::FIXED)
+        && !perms.intersects(PermissionSet::WRITE | PermissionSet::OFFSET_SUB)
+}
+
+/// Add `C_STRING` to the candidate pointers in the signatures of functions and in the types of
+/// fields and statics.
+pub fn mark_global_candidates(gacx: &GlobalAnalysisCtxt, gasn: &mut GlobalAssignment) {
+    let sig_ltys = gacx
+        .fn_sigs
+        .values()
+        .flat_map(|lsig| lsig.inputs_and_output());
+    let ltys = sig_ltys
+        .chain(gacx.field_ltys.values().copied())
+        .chain(gacx.static_tys.values().copied());
+    for lty in ltys.flat_map(|lty| lty.iter()) {
+        if lty.label.is_none() {
+            continue;
+        }
+        let ptr = lty.label;
+        if is_candidate(lty, gasn.perms[ptr], gasn.flags[ptr]) {
+            gasn.flags[ptr].insert(FlagSet::C_STRING);
+        }
+    }
+}
+
+/// Add `C_STRING` to the candidate pointers in the types of the locals of the current function,
+/// then remove it from the pointers that `mir` uses in unsupported ways.
+pub fn mark_local_candidates<'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    mir: &Body<'tcx>,
+    asn: &mut Assignment,
+) {
+    let (perms, mut flags) = asn.all_mut();
+    let perms = perms.borrow();
+    for lty in acx.local_tys.iter().flat_map(|lty| lty.iter()) {
+        if lty.label.is_none() || !lty.label.is_local() {
+            continue;
+        }
+        let ptr = lty.label;
+        if is_candidate(lty, perms[ptr], flags[ptr]) {
+            flags[ptr].insert(FlagSet::C_STRING);
+        }
+    }
+
+    // Strings are usually freed through a cast to `*mut c_void`, as in `free(s as *mut c_void)`,
+    // so the result of such a cast is a candidate too.  The cast is removed during rewriting.
+    let tcx = acx.tcx();
+    for bb_data in mir.basic_blocks().iter() {
+        for stmt in &bb_data.statements {
+            let (pl, op) = match stmt.kind {
+                StatementKind::Assign(ref x) => match x.1 {
+                    Rvalue::Cast(_, ref op, _) => (x.0, op),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            let op_lty = acx.type_of(op);
+            let pl_lty = acx.type_of(pl);
+            if op_lty.label.is_none() || !flags[op_lty.label].contains(FlagSet::C_STRING) {
+                continue;
+            }
+            if !pl_lty.label.is_none()
+                && pl_lty.label.is_local()
+                && is_raw_candidate(pl_lty, perms[pl_lty.label], flags[pl_lty.label])
+                && util::is_c_void(tcx, pl_lty.args[0].ty)
+            {
+                flags[pl_lty.label].insert(FlagSet::C_STRING);
+            }
+        }
+    }
+
+    let mut checker = UseChecker {
+        acx,
+        mir,
+        perms,
+        unsupported: Vec::new(),
+    };
+    checker.visit_body(mir);
+    for ptr in checker.unsupported {
+        flags[ptr].remove(FlagSet::C_STRING);
+    }
+}
+
+/// Collects the pointers that are used in ways that strings don't support.
+struct UseChecker<'a, 'tcx> {
+    acx: &'a AnalysisCtxt<'a, 'tcx>,
+    mir: &'a Body<'tcx>,
+    perms: PointerTable<'a, PermissionSet>,
+    unsupported: Vec<PointerId>,
+}
+
+impl<'tcx> UseChecker<'_, 'tcx> {
+    fn reject(&mut self, lty: LTy<'tcx>) {
+        if !lty.label.is_none() {
+            self.unsupported.push(lty.label);
+        }
+    }
+
+    fn reject_operand(&mut self, op: &Operand<'tcx>) {
+        self.reject(self.acx.type_of(op));
+    }
+}
+
+impl<'tcx> Visitor<'tcx> for UseChecker<'_, 'tcx> {
+    fn visit_place(&mut self, place: &Place<'tcx>, context: PlaceContext, location: Location) {
+        // Strings are only accessed through the functions that operate on them, never directly.
+        for (base, elem) in place.iter_projections() {
+            if elem == ProjectionElem::Deref {
+                self.reject(self.acx.type_of(base));
+            }
+        }
+        self.super_place(place, context, location);
+    }
+
+    fn visit_assign(&mut self, pl: &Place<'tcx>, rv: &Rvalue<'tcx>, location: Location) {
+        match *rv {
+            // Copies of a string are connected to it by dataflow constraints, which keep their
+            // flags in sync.
+            Rvalue::Use(Operand::Copy(_) | Operand::Move(_))
+            | Rvalue::CopyForDeref(_)
+            | Rvalue::Aggregate(..) => {}
+            // The same goes for pointer-to-pointer casts.  A cast to any type other than `c_void`
+            // produces a pointer without the flag, which removes it from the string as well.
+            Rvalue::Cast(_, ref op, ty)
+                if util::is_transmutable_ptr_cast(op.ty(self.mir, self.acx.tcx()), ty)
+                    .is_some() => {}
+            Rvalue::Use(ref op)
+            | Rvalue::Repeat(ref op, _)
+            | Rvalue::Cast(_, ref op, _)
+            | Rvalue::UnaryOp(_, ref op)
+            | Rvalue::ShallowInitBox(ref op, _) => {
+                self.reject(self.acx.type_of(pl));
+                self.reject_operand(op);
+            }
+            Rvalue::BinaryOp(_, ref ops) | Rvalue::CheckedBinaryOp(_, ref ops) => {
+                self.reject(self.acx.type_of(pl));
+                self.reject_operand(&ops.0);
+                self.reject_operand(&ops.1);
+            }
+            // Borrows and the remaining `Rvalue`s never produce a pointer to a string.
+            _ => self.reject(self.acx.type_of(pl)),
+        }
+        self.super_assign(pl, rv, location);
+    }
+
+    fn visit_terminator(&mut self, terminator: &Terminator<'tcx>, location: Location) {
+        if let TerminatorKind::Call {
+            ref func,
+            ref args,
+            destination,
+            ..
+        } = terminator.kind
+        {
+            let tcx = self.acx.tcx();
+            let supported = match ty_callee(tcx, func.ty(self.mir, tcx)) {
+                // Arguments and results are connected by dataflow constraints, as for copies.
+                Callee::LocalDef { .. } | Callee::UnknownDef(UnknownDefCallee::Indirect { .. }) => {
+                    true
+                }
+                // `strdup` produces a `CString` only if its result is freed, as for slices.
+                Callee::Strdup | Callee::Strndup => {
+                    let dest_lty = self.acx.type_of(destination);
+                    if !dest_lty.label.is_none()
+                        && !self.perms[dest_lty.label].contains(PermissionSet::FREE)
+                    {
+                        self.reject(dest_lty);
+                    }
+                    true
+                }
+                Callee::Trivial | Callee::Free | Callee::IsNull | Callee::Null { .. } => true,
+                // Strings are passed to foreign functions as raw pointers, but the results of
+                // foreign functions aren't known to be strings.
+                Callee::UnknownDef(UnknownDefCallee::Direct {
+                    is_foreign: true, ..
+                }) => {
+                    self.reject(self.acx.type_of(destination));
+                    true
+                }
+                _ => false,
+            };
+            if !supported {
+                self.reject(self.acx.type_of(destination));
+                for arg in args {
+                    self.reject_operand(arg);
+                }
+            }
+        }
+        self.super_terminator(terminator, location);
+    }
+}
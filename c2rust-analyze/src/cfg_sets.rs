@@ -0,0 +1,239 @@
+//! Analysis of crates under several sets of `cfg` options.
+//!
+//! With `--cfg-set`, the `cargo` wrapper first analyzes the crate once per set of `cfg` options,
+//! without rewriting.  Each of these runs writes `<dir>/<crate>.json`, which maps the path of each
+//! function to a description of its rewrites.  The wrapper then compares the reports with
+//! [`find_divergent_fns`].  Functions whose rewrites are the same under every configuration that
+//! compiles them are compatible, and are rewritten as usual in a final run.  The rest are
+//! reported, and the final run leaves them unrewritten by reading the list written by
+//! [`write_divergent_fns`], since no single rewrite of their source is valid for every
+//! configuration.
+//!
+//! The final run compiles the crate under the first set only, so functions that only exist under
+//! the other sets are never rewritten.  [`find_missing_fns`] lists the ones that would have been,
+//! so they can be reported as well.
+
+use crate::context::GlobalAnalysisCtxt;
+use rustc_hir::def_id::{DefId, LocalDefId, LOCAL_CRATE};
+use rustc_middle::ty::TyCtxt;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+/// The rewrites of each function of a crate, keyed by def path.
+pub type FnRewrites = BTreeMap<String, Vec<String>>;
+
+/// Placeholder description for a function that the analysis declined to rewrite.
+const NOT_REWRITTEN: &str = "(not rewritten)";
+
+/// A function whose rewrites differ between two configurations.
+#[derive(Clone, Debug)]
+pub struct DivergentFn {
+    pub crate_name: String,
+    pub def_path: String,
+    /// Indices of the first two configurations whose rewrites differ.
+    pub configs: (usize, usize),
+}
+
+/// Write the rewrites of all functions in `all_fn_ldids` to `<dir>/<crate name>.json`.
+/// `fn_rewrites` holds the description of each rewrite of each rewritten function.
+pub(crate) fn write_fn_rewrites<'tcx>(
+    dir: &Path,
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    all_fn_ldids: &[LocalDefId],
+    fn_rewrites: &HashMap<LocalDefId, Vec<String>>,
+) -> io::Result<()> {
+    let tcx = gacx.tcx;
+    let mut report = FnRewrites::new();
+    for &ldid in all_fn_ldids {
+        let descs = if gacx.dont_rewrite_fn(ldid.to_def_id()) {
+            vec![NOT_REWRITTEN.to_owned()]
+        } else {
+            fn_rewrites.get(&ldid).cloned().unwrap_or_default()
+        };
+        report.insert(tcx.def_path_str(ldid.to_def_id()), descs);
+    }
+
+    fs::create_dir_all(dir)?;
+    let file = File::create(dir.join(format!("{}.json", tcx.crate_name(LOCAL_CRATE))))?;
+    serde_json::to_writer_pretty(file, &report)?;
+    Ok(())
+}
+
+/// Read the reports of all crates in `dir`, keyed by crate name.
+pub fn read_fn_rewrites(dir: &Path) -> io::Result<BTreeMap<String, FnRewrites>> {
+    let mut reports = BTreeMap::new();
+    if !dir.exists() {
+        return Ok(reports);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "json") {
+            continue;
+        }
+        let crate_name = match path.file_stem().and_then(|s| s.to_str()) {
+            Some(x) => x.to_owned(),
+            None => continue,
+        };
+        let report: FnRewrites = serde_json::from_reader(File::open(&path)?)?;
+        reports.insert(crate_name, report);
+    }
+    Ok(reports)
+}
+
+/// Compare the reports of each configuration, in the order of the `--cfg-set` options, and
+/// return the functions whose rewrites differ between two configurations that both compile
+/// them.  A function that only exists under some configurations is not divergent by itself.
+pub fn find_divergent_fns(reports: &[BTreeMap<String, FnRewrites>]) -> Vec<DivergentFn> {
+    let mut first_seen = BTreeMap::<(&str, &str), (usize, &[String])>::new();
+    let mut divergent = Vec::new();
+    let mut reported = HashSet::new();
+    for (i, report) in reports.iter().enumerate() {
+        for (crate_name, fns) in report {
+            for (def_path, rws) in fns {
+                let key = (crate_name.as_str(), def_path.as_str());
+                let &mut (j, first_rws) = first_seen.entry(key).or_insert((i, &rws[..]));
+                if first_rws != &rws[..] && reported.insert(key) {
+                    divergent.push(DivergentFn {
+                        crate_name: crate_name.clone(),
+                        def_path: def_path.clone(),
+                        configs: (j, i),
+                    });
+                }
+            }
+        }
+    }
+    divergent
+}
+
+/// A function that would be rewritten under some configuration, but that the first configuration
+/// doesn't compile.
+#[derive(Clone, Debug)]
+pub struct MissingFn {
+    pub crate_name: String,
+    pub def_path: String,
+    /// Index of the first configuration that rewrites the function.
+    pub config: usize,
+}
+
+/// Return the functions that the first configuration doesn't compile, but that another
+/// configuration would rewrite.  The final run only rewrites under the first configuration, so
+/// these functions stay unrewritten.
+pub fn find_missing_fns(reports: &[BTreeMap<String, FnRewrites>]) -> Vec<MissingFn> {
+    let first = match reports.first() {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let mut missing = Vec::new();
+    let mut reported = HashSet::new();
+    for (i, report) in reports.iter().enumerate().skip(1) {
+        for (crate_name, fns) in report {
+            for (def_path, rws) in fns {
+                if first
+                    .get(crate_name)
+                    .map_or(false, |fns| fns.contains_key(def_path))
+                {
+                    continue;
+                }
+                if rws.is_empty() || rws.iter().all(|rw| rw == NOT_REWRITTEN) {
+                    continue;
+                }
+                if reported.insert((crate_name.as_str(), def_path.as_str())) {
+                    missing.push(MissingFn {
+                        crate_name: crate_name.clone(),
+                        def_path: def_path.clone(),
+                        config: i,
+                    });
+                }
+            }
+        }
+    }
+    missing
+}
+
+/// Write the list of divergent functions for [`read_divergent_fns`], one `<crate> <def path>`
+/// pair per line.
+pub fn write_divergent_fns(path: &Path, fns: &[DivergentFn]) -> io::Result<()> {
+    let mut out = File::create(path)?;
+    for f in fns {
+        writeln!(out, "{} {}", f.crate_name, f.def_path)?;
+    }
+    Ok(())
+}
+
+/// Add the functions of the current crate listed in the file at `path` to `fixed_defs`.
+pub(crate) fn read_divergent_fns(
+    tcx: TyCtxt,
+    fixed_defs: &mut HashSet<DefId>,
+    path: &str,
+) -> io::Result<()> {
+    let crate_name = tcx.crate_name(LOCAL_CRATE);
+    let mut paths = HashSet::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if let Some((krate, def_path)) = line.trim().split_once(' ') {
+            if krate == crate_name.as_str() {
+                paths.insert(def_path.to_owned());
+            }
+        }
+    }
+    for ldid in tcx.hir().body_owners() {
+        let def_id = ldid.to_def_id();
+        if paths.contains(&tcx.def_path_str(def_id)) {
+            fixed_defs.insert(def_id);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn report(fns: &[(&str, &[&str])]) -> BTreeMap<String, FnRewrites> {
+        let fns = fns
+            .iter()
+            .map(|&(path, rws)| {
+                let rws = rws.iter().map(|&rw| rw.to_owned()).collect();
+                (path.to_owned(), rws)
+            })
+            .collect();
+        BTreeMap::from([("krate".to_owned(), fns)])
+    }
+
+    #[test]
+    fn divergent_fns() {
+        let reports = [
+            report(&[("same", &["a"]), ("differ", &["a"]), ("unix_only", &["a"])]),
+            report(&[
+                ("same", &["a"]),
+                ("differ", &["b"]),
+                ("windows_only", &["b"]),
+            ]),
+            report(&[("same", &["a"]), ("differ", &["c"])]),
+        ];
+        let divergent = find_divergent_fns(&reports);
+        assert_eq!(divergent.len(), 1);
+        assert_eq!(divergent[0].def_path, "differ");
+        assert_eq!(divergent[0].configs, (0, 1));
+    }
+
+    #[test]
+    fn missing_fns() {
+        let reports = [
+            report(&[("same", &["a"]), ("unix_only", &["a"])]),
+            report(&[
+                ("same", &["a"]),
+                ("windows_only", &["b"]),
+                ("unchanged", &[]),
+                ("skipped", &[NOT_REWRITTEN]),
+            ]),
+            report(&[("windows_only", &["b"])]),
+        ];
+        let missing = find_missing_fns(&reports);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].def_path, "windows_only");
+        assert_eq!(missing[0].config, 1);
+    }
+}
@@ -0,0 +1,485 @@
+//! A simple interprocedural propagation of integer constants through function arguments.
+//!
+//! C code often computes buffer sizes from `#define` constants and passes them down through
+//! several calls before they reach `memcpy` or `memset`.  Rewrites of those calls can produce
+//! simpler code when the size is known, so here we compute, for each local function, which of
+//! its integer arguments receive the same constant value at every call site.
+//!
+//! Within a function, an operand is considered constant if it is:
+//! * an integer literal or named constant,
+//! * an integer-to-integer cast of a constant that doesn't change its value,
+//! * a local that is assigned exactly once, from a constant operand, and never borrowed, or
+//! * an argument that is never reassigned or borrowed and receives the same constant at every
+//!   call site.
+//!
+//! Only direct calls are tracked.  Functions whose address is taken and functions that can be
+//! called from outside the crate (such as `#[no_mangle]` functions) are assumed to receive
+//! arbitrary arguments.
+//!
+//! The same resolver also recovers the contents of constant C strings, such as the format strings
+//! passed to `sprintf`, and the functions passed as callbacks, such as the comparator passed to
+//! `qsort`.
+
+use crate::util::{self, Callee};
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_index::vec::IndexVec;
+use rustc_middle::mir::interpret::{ConstValue, GlobalAlloc, Scalar};
+use rustc_middle::mir::visit::{PlaceContext, Visitor};
+use rustc_middle::mir::{
+    AggregateKind, Body, CastKind, ConstantKind, Local, Location, Operand, ProjectionElem, Rvalue,
+    StatementKind, Terminator, TerminatorKind,
+};
+use rustc_middle::ty::adjustment::PointerCast;
+use rustc_middle::ty::{ParamEnv, Ty, TyCtxt, TyKind, WithOptConstParam};
+use rustc_span::sym;
+use std::collections::{HashMap, HashSet};
+
+/// The maximum number of assignments to follow when resolving a local to a constant.
+const MAX_DEPTH: usize = 8;
+
+/// The known constant values of the arguments of each local function.  Functions with no known
+/// constant arguments may be omitted.
+#[derive(Clone, Debug, Default)]
+pub struct ArgConsts {
+    fns: HashMap<DefId, Vec<Option<u128>>>,
+}
+
+impl ArgConsts {
+    /// Get the known constant values of the arguments of `did`, indexed by argument position.
+    pub fn get(&self, did: DefId) -> &[Option<u128>] {
+        self.fns.get(&did).map_or(&[], |v| v)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ArgValue {
+    /// No call site has been seen yet.
+    Unknown,
+    /// Every call site seen so far passes this value.
+    Const(u128),
+    /// Call sites pass different or non-constant values.
+    Varying,
+}
+
+impl ArgValue {
+    fn meet(self, val: Option<u128>) -> ArgValue {
+        match (self, val) {
+            (ArgValue::Unknown, Some(x)) => ArgValue::Const(x),
+            (ArgValue::Const(x), Some(y)) if x == y => ArgValue::Const(x),
+            _ => ArgValue::Varying,
+        }
+    }
+
+    fn to_option(self) -> Option<u128> {
+        match self {
+            ArgValue::Const(x) => Some(x),
+            ArgValue::Unknown | ArgValue::Varying => None,
+        }
+    }
+}
+
+/// Compute the constant arguments of all functions in `all_fn_ldids`, which should be in
+/// callgraph postorder, as returned by `fn_body_owners_postorder`.
+pub fn propagate_arg_consts(tcx: TyCtxt, all_fn_ldids: &[LocalDefId]) -> ArgConsts {
+    let mut values = HashMap::with_capacity(all_fn_ldids.len());
+    for &ldid in all_fn_ldids {
+        let did = ldid.to_def_id();
+        let num_args = tcx.fn_sig(did).skip_binder().inputs().len();
+        let init = if tcx.codegen_fn_attrs(did).contains_extern_indicator() {
+            ArgValue::Varying
+        } else {
+            ArgValue::Unknown
+        };
+        values.insert(did, vec![init; num_args]);
+    }
+
+    for did in collect_addr_taken_fns(tcx, all_fn_ldids) {
+        if let Some(args) = values.get_mut(&did) {
+            args.fill(ArgValue::Varying);
+        }
+    }
+
+    // Visit callers before callees, so that most arguments are resolved in a single pass.  Values
+    // only move from `Unknown` to `Const` to `Varying`, so this terminates even with recursion.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &ldid in all_fn_ldids.iter().rev() {
+            let did = ldid.to_def_id();
+            let mir = tcx.mir_built(WithOptConstParam::unknown(ldid));
+            let mir = mir.borrow();
+            let caller_args = values[&did]
+                .iter()
+                .map(|v| v.to_option())
+                .collect::<Vec<_>>();
+            let resolver = ConstResolver::new(tcx, &mir, &caller_args);
+            for bb_data in mir.basic_blocks().iter() {
+                let (func, args) = match bb_data.terminator().kind {
+                    TerminatorKind::Call {
+                        ref func, ref args, ..
+                    } => (func, args),
+                    _ => continue,
+                };
+                let callee = match util::ty_callee(tcx, func.ty(&*mir, tcx)) {
+                    Callee::LocalDef { def_id, .. } => def_id,
+                    _ => continue,
+                };
+                let callee_values = match values.get_mut(&callee) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                for (value, arg) in callee_values.iter_mut().zip(args.iter()) {
+                    if *value == ArgValue::Varying {
+                        continue;
+                    }
+                    let new = value.meet(resolver.operand_const(arg));
+                    if new != *value {
+                        *value = new;
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    let fns = values
+        .into_iter()
+        .filter_map(|(did, args)| {
+            let args = args
+                .into_iter()
+                .map(ArgValue::to_option)
+                .collect::<Vec<_>>();
+            if args.iter().all(|x| x.is_none()) {
+                return None;
+            }
+            Some((did, args))
+        })
+        .collect();
+    ArgConsts { fns }
+}
+
+/// Collect the local functions that are referenced by the bodies of `all_fn_ldids` other than as
+/// the callee of a direct call, such as by being converted to a function pointer.
+pub fn collect_addr_taken_fns(tcx: TyCtxt, all_fn_ldids: &[LocalDefId]) -> HashSet<DefId> {
+    let mut addr_taken = HashSet::new();
+    for &ldid in all_fn_ldids {
+        let mir = tcx.mir_built(WithOptConstParam::unknown(ldid));
+        let mir = mir.borrow();
+        FnRefVisitor {
+            tcx,
+            mir: &mir,
+            addr_taken: &mut addr_taken,
+        }
+        .visit_body(&mir);
+    }
+    addr_taken
+}
+
+/// Records local functions that are referenced other than as the callee of a direct call.
+struct FnRefVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    mir: &'a Body<'tcx>,
+    addr_taken: &'a mut HashSet<DefId>,
+}
+
+impl<'tcx> Visitor<'tcx> for FnRefVisitor<'_, 'tcx> {
+    fn visit_terminator(&mut self, terminator: &Terminator<'tcx>, location: Location) {
+        if let TerminatorKind::Call { ref args, .. } = terminator.kind {
+            // Skip `func`, which is a use of the callee but doesn't take its address.
+            for arg in args {
+                self.visit_operand(arg, location);
+            }
+            return;
+        }
+        self.super_terminator(terminator, location);
+    }
+
+    fn visit_operand(&mut self, operand: &Operand<'tcx>, location: Location) {
+        if let TyKind::FnDef(did, _) = *operand.ty(self.mir, self.tcx).kind() {
+            if did.is_local() && matches!(self.tcx.def_kind(did), DefKind::Fn | DefKind::AssocFn) {
+                self.addr_taken.insert(did);
+            }
+        }
+        self.super_operand(operand, location);
+    }
+}
+
+/// Resolves operands within a single function body to integer constants.
+pub struct ConstResolver<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    mir: &'a Body<'tcx>,
+    /// Known constant values of the function's arguments.
+    arg_consts: &'a [Option<u128>],
+    /// For each local, the locations of all writes to it.
+    defs: IndexVec<Local, Vec<Location>>,
+    /// For each local, whether it is ever borrowed.
+    borrowed: IndexVec<Local, bool>,
+}
+
+impl<'a, 'tcx> ConstResolver<'a, 'tcx> {
+    pub fn new(
+        tcx: TyCtxt<'tcx>,
+        mir: &'a Body<'tcx>,
+        arg_consts: &'a [Option<u128>],
+    ) -> ConstResolver<'a, 'tcx> {
+        let mut v = DefsVisitor {
+            defs: IndexVec::from_elem(Vec::new(), &mir.local_decls),
+            borrowed: IndexVec::from_elem(false, &mir.local_decls),
+        };
+        v.visit_body(mir);
+        ConstResolver {
+            tcx,
+            mir,
+            arg_consts,
+            defs: v.defs,
+            borrowed: v.borrowed,
+        }
+    }
+
+    /// Returns the value of `op` if it's a known non-negative integer constant.
+    pub fn operand_const(&self, op: &Operand<'tcx>) -> Option<u128> {
+        self.operand_const_depth(op, 0)
+    }
+
+    fn operand_const_depth(&self, op: &Operand<'tcx>, depth: usize) -> Option<u128> {
+        match *op {
+            Operand::Constant(ref c) => {
+                let ty = c.ty();
+                let bits = c
+                    .literal
+                    .try_eval_bits(self.tcx, ParamEnv::reveal_all(), ty)?;
+                self.fits(bits, ty).then_some(bits)
+            }
+            Operand::Copy(pl) | Operand::Move(pl) => {
+                let local = pl.as_local()?;
+                self.local_const(local, depth)
+            }
+        }
+    }
+
+    fn local_const(&self, local: Local, depth: usize) -> Option<u128> {
+        if depth >= MAX_DEPTH || self.borrowed[local] {
+            return None;
+        }
+        let defs = &self.defs[local];
+        if local.index() >= 1 && local.index() <= self.mir.arg_count {
+            if !defs.is_empty() {
+                return None;
+            }
+            return self.arg_consts.get(local.index() - 1).copied().flatten();
+        }
+
+        let loc = match defs[..] {
+            [loc] => loc,
+            _ => return None,
+        };
+        let stmt = self.mir.basic_blocks()[loc.block]
+            .statements
+            .get(loc.statement_index)?;
+        let rv = match stmt.kind {
+            StatementKind::Assign(ref x) if x.0.as_local() == Some(local) => &x.1,
+            _ => return None,
+        };
+        match *rv {
+            Rvalue::Use(ref op) => self.operand_const_depth(op, depth + 1),
+            Rvalue::Cast(CastKind::Misc, ref op, ty) => {
+                if !op.ty(self.mir, self.tcx).is_integral() {
+                    return None;
+                }
+                let val = self.operand_const_depth(op, depth + 1)?;
+                self.fits(val, ty).then_some(val)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the characters of the NUL-terminated string that `op` points to, not including the
+    /// NUL, if `op` is derived from a constant such as `b"%d\0" as *const u8 as *const c_char`.
+    pub fn operand_c_str(&self, op: &Operand<'tcx>) -> Option<Vec<u8>> {
+        self.operand_c_str_depth(op, 0)
+    }
+
+    fn operand_c_str_depth(&self, op: &Operand<'tcx>, depth: usize) -> Option<Vec<u8>> {
+        let (alloc, start, end) = match *op {
+            Operand::Constant(ref c) => match c.literal {
+                ConstantKind::Val(ConstValue::Scalar(Scalar::Ptr(ptr, _)), _) => {
+                    let (alloc_id, offset) = ptr.into_parts();
+                    let alloc = match self.tcx.try_get_global_alloc(alloc_id)? {
+                        GlobalAlloc::Memory(alloc) => alloc,
+                        _ => return None,
+                    };
+                    let end = alloc.inner().len();
+                    (alloc, offset.bytes_usize(), end)
+                }
+                ConstantKind::Val(ConstValue::Slice { data, start, end }, _) => (data, start, end),
+                _ => return None,
+            },
+            Operand::Copy(pl) | Operand::Move(pl) => {
+                let local = pl.as_local()?;
+                return self.local_c_str(local, depth);
+            }
+        };
+        let bytes = alloc
+            .inner()
+            .inspect_with_uninit_and_ptr_outside_interpreter(start..end);
+        let len = bytes.iter().position(|&b| b == 0)?;
+        Some(bytes[..len].to_vec())
+    }
+
+    fn local_c_str(&self, local: Local, depth: usize) -> Option<Vec<u8>> {
+        if depth >= MAX_DEPTH {
+            return None;
+        }
+        match *self.single_def(local)? {
+            Rvalue::Use(ref op) | Rvalue::Cast(_, ref op, _) => {
+                self.operand_c_str_depth(op, depth + 1)
+            }
+            // `&raw const *p`, as produced by casting a reference to an array to a raw pointer.
+            Rvalue::AddressOf(_, pl) | Rvalue::Ref(_, _, pl)
+                if pl.projection[..] == [ProjectionElem::Deref] =>
+            {
+                self.local_c_str(pl.local, depth + 1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the static that `op` was loaded from, if `op` is a copy of a static such as the
+    /// `stderr` in `fprintf(stderr, ...)`.
+    pub fn operand_static_load(&self, op: &Operand<'tcx>) -> Option<DefId> {
+        let mut pl = op.place()?;
+        if let Some(local) = pl.as_local() {
+            match *self.single_def(local)? {
+                Rvalue::Use(Operand::Copy(x) | Operand::Move(x)) => pl = x,
+                _ => return None,
+            }
+        }
+        if pl.projection[..] != [ProjectionElem::Deref] {
+            return None;
+        }
+        let c = match *self.single_def(pl.local)? {
+            Rvalue::Use(Operand::Constant(ref c)) => c,
+            _ => return None,
+        };
+        match c.literal {
+            ConstantKind::Val(ConstValue::Scalar(Scalar::Ptr(ptr, _)), _) => {
+                match self.tcx.try_get_global_alloc(ptr.provenance)? {
+                    GlobalAlloc::Static(did) => Some(did),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the function that `op` points to, if `op` is derived from a function item, as in
+    /// `Some(cmp as unsafe extern "C" fn(*const c_void, *const c_void) -> c_int)`.
+    pub fn operand_fn_def(&self, op: &Operand<'tcx>) -> Option<DefId> {
+        self.operand_fn_def_depth(op, 0)
+    }
+
+    fn operand_fn_def_depth(&self, op: &Operand<'tcx>, depth: usize) -> Option<DefId> {
+        if let TyKind::FnDef(did, _) = *op.ty(self.mir, self.tcx).kind() {
+            return Some(did);
+        }
+        if depth >= MAX_DEPTH {
+            return None;
+        }
+        let local = op.place()?.as_local()?;
+        match *self.single_def(local)? {
+            Rvalue::Use(ref op)
+            | Rvalue::Cast(
+                CastKind::Pointer(PointerCast::ReifyFnPointer | PointerCast::UnsafeFnPointer),
+                ref op,
+                _,
+            ) => self.operand_fn_def_depth(op, depth + 1),
+            // `Some(f)`, as used for nullable function pointers.
+            Rvalue::Aggregate(ref kind, ref ops) => match (&**kind, &ops[..]) {
+                (&AggregateKind::Adt(adt_did, ..), [op])
+                    if self.tcx.is_diagnostic_item(sym::Option, adt_did) =>
+                {
+                    self.operand_fn_def_depth(op, depth + 1)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if `local` is an argument that is never reassigned or borrowed, so that it
+    /// holds the value passed by the caller throughout the function.
+    pub fn is_unmodified_arg(&self, local: Local) -> bool {
+        local.index() >= 1
+            && local.index() <= self.mir.arg_count
+            && self.defs[local].is_empty()
+            && !self.borrowed[local]
+    }
+
+    /// Returns the call whose result is stored in `local`, if `local` is assigned exactly once, by
+    /// that call, and never borrowed.
+    pub fn single_call_def(&self, local: Local) -> Option<&'a Terminator<'tcx>> {
+        if self.borrowed[local] {
+            return None;
+        }
+        let loc = match self.defs[local][..] {
+            [loc] => loc,
+            _ => return None,
+        };
+        let bb_data = &self.mir.basic_blocks()[loc.block];
+        if loc.statement_index != bb_data.statements.len() {
+            return None;
+        }
+        let term = bb_data.terminator();
+        match term.kind {
+            TerminatorKind::Call { destination, .. } if destination.as_local() == Some(local) => {
+                Some(term)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the rvalue assigned to `local`, if it's assigned exactly once and never borrowed.
+    pub fn single_def(&self, local: Local) -> Option<&'a Rvalue<'tcx>> {
+        if self.borrowed[local] {
+            return None;
+        }
+        let loc = match self.defs[local][..] {
+            [loc] => loc,
+            _ => return None,
+        };
+        let stmt = self.mir.basic_blocks()[loc.block]
+            .statements
+            .get(loc.statement_index)?;
+        match stmt.kind {
+            StatementKind::Assign(ref x) if x.0.as_local() == Some(local) => Some(&x.1),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if `val` is a non-negative value of the integer type `ty`.
+    fn fits(&self, val: u128, ty: Ty<'tcx>) -> bool {
+        let bits = match *ty.kind() {
+            TyKind::Int(ity) => ity.bit_width(),
+            TyKind::Uint(uty) => uty.bit_width(),
+            _ => return false,
+        };
+        let bits = bits.unwrap_or_else(|| self.tcx.data_layout.pointer_size.bits());
+        val >> (bits - 1) == 0
+    }
+}
+
+struct DefsVisitor {
+    defs: IndexVec<Local, Vec<Location>>,
+    borrowed: IndexVec<Local, bool>,
+}
+
+impl<'tcx> Visitor<'tcx> for DefsVisitor {
+    fn visit_local(&mut self, local: Local, context: PlaceContext, location: Location) {
+        if context.is_borrow() || context.is_address_of() {
+            self.borrowed[local] = true;
+        } else if context.is_mutating_use() {
+            self.defs[local].push(location);
+        }
+    }
+}
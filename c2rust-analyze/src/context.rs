@@ -1,6 +1,7 @@
 use crate::analyze::fn_body_owners_postorder;
 use crate::analyze::AssignPointerIds;
 use crate::borrowck::{AdtMetadata, FieldMetadata, OriginArg, OriginParam};
+use crate::const_prop::ArgConsts;
 use crate::known_fn::{all_known_fns, KnownFn};
 use crate::labeled_ty::{LabeledTy, LabeledTyCtxt};
 use crate::panic_detail::PanicDetail;
@@ -8,9 +9,12 @@ use crate::pointer_id::{
     GlobalPointerTable, LocalPointerTable, NextGlobalPointerId, NextLocalPointerId, PointerTable,
     PointerTableMut,
 };
+use crate::returned_slice_len::ReturnedSliceLen;
+use crate::summary::FnSummary;
 use crate::util::{self, describe_rvalue, PhantomLifetime, RvalueDesc};
 use assert_matches::assert_matches;
 use bitflags::bitflags;
+use either::Either;
 use indexmap::IndexSet;
 use log::*;
 use rustc_ast::Mutability;
@@ -19,8 +23,8 @@ use rustc_hir::def_id::{DefId, LocalDefId};
 use rustc_index::vec::IndexVec;
 use rustc_middle::mir::interpret::{self, AllocId, ConstValue, GlobalAlloc};
 use rustc_middle::mir::{
-    Body, Constant, ConstantKind, Field, HasLocalDecls, Local, LocalDecls, Location, Operand,
-    Place, PlaceElem, PlaceRef, Rvalue,
+    Body, Constant, ConstantKind, HasLocalDecls, Local, LocalDecls, Location, Operand, Place,
+    PlaceElem, PlaceRef, Rvalue,
 };
 use rustc_middle::ty::tls;
 use rustc_middle::ty::AdtDef;
@@ -33,6 +37,7 @@ use rustc_middle::ty::RegionKind;
 use rustc_middle::ty::Ty;
 use rustc_middle::ty::TyCtxt;
 use rustc_middle::ty::TyKind;
+use rustc_target::abi::VariantIdx;
 use rustc_type_ir::RegionKind::{ReEarlyBound, ReStatic};
 use std::collections::hash_map::{Entry, HashMap};
 use std::collections::HashSet;
@@ -186,13 +191,33 @@ bitflags! {
         /// cross an FFI boundary, and for arguments and return values of functions we can't
         /// rewrite.
         const FIXED = 0x0002;
+
+        /// This pointer is a NUL-terminated string of characters, to be rewritten to `CString` or
+        /// `&CStr`.  This is only set with `--c-strings`, for pointers whose uses are all
+        /// compatible with the invariants of those types.
+        const C_STRING = 0x0004;
+
+        /// This pointer owns a growable buffer, to be rewritten to `Vec<T>` rather than
+        /// `Box<[T]>`.  This is only set with `--realloc-vecs`, for owned slices that are resized
+        /// with `realloc` or exchange values with ones that are.
+        const VEC = 0x0008;
+
+        /// This pointer refers to a heap object that's aliased and mutated, to be rewritten to
+        /// `Rc<RefCell<T>>`.  This is only set with `--rc-refcell`, for single-object pointers
+        /// connected to an owning pointer that isn't `UNIQUE`.
+        const REFCELL = 0x0010;
+
+        /// This pointer refers to a heap object that's shared with another thread, to be rewritten
+        /// to `Arc<Mutex<T>>`.  This is only set with `--arc-mutex`, for single-object pointers
+        /// connected to a pointer passed to a new thread.
+        const MUTEX = 0x0020;
     }
 }
 
 bitflags! {
     /// Flags indicating reasons why a function isn't being rewritten.
     #[derive(Default)]
-    pub struct DontRewriteFnReason: u16 {
+    pub struct DontRewriteFnReason: u32 {
         /// The user requested that this function be left unchanged.
         const USER_REQUEST = 1 << 0;
         /// The function contains an unsupported int-to-pointer cast.
@@ -211,22 +236,37 @@ bitflags! {
         /// Calling this function from non-rewritten code requires a shim, but shim generation
         /// failed.
         const SHIM_GENERATION_FAILED = 1 << 7;
+        /// The function contains code generated by a macro or included from a generated file,
+        /// which can't be rewritten in place.
+        const MACRO_EXPANSION = 1 << 8;
+        /// The function contains inline assembly that uses pointers whose types would change.
+        const INLINE_ASM = 1 << 9;
+        /// The function may return a borrow of an owned pointer in a local, which would outlive
+        /// the local.
+        const ESCAPING_REBORROW = 1 << 17;
+        /// The function is a method whose raw pointer `self` parameter would be rewritten to a
+        /// type that can't be a receiver, such as `Option<&Self>` or `&Cell<Self>`.
+        const NON_RECEIVER_SELF = 1 << 18;
+        /// The analysis panicked while processing this function, in a pass that has no more
+        /// specific `*_INVALID` reason below.
+        const ANALYSIS_PANIC = 1 << 10;
 
         /// Pointee analysis results for this function are invalid.
-        const POINTEE_INVALID = 1 << 10;
+        const POINTEE_INVALID = 1 << 11;
         /// Dataflow analysis results for this function are invalid.
-        const DATAFLOW_INVALID = 1 << 11;
+        const DATAFLOW_INVALID = 1 << 12;
         /// Borrowcheck/Polonius analysis results for this function are invalid.
-        const BORROWCK_INVALID = 1 << 12;
+        const BORROWCK_INVALID = 1 << 13;
         /// Results of some other analysis for this function are invalid.
-        const MISC_ANALYSIS_INVALID = 1 << 13;
+        const MISC_ANALYSIS_INVALID = 1 << 14;
         /// The set of rewrites generated for this function is invalid or incomplete.
-        const REWRITE_INVALID = 1 << 14;
+        const REWRITE_INVALID = 1 << 15;
         /// Analysis results for this function are valid, but were marked as invalid anyway in
         /// order to test error recovery.
-        const FAKE_INVALID_FOR_TESTING = 1 << 15;
+        const FAKE_INVALID_FOR_TESTING = 1 << 16;
 
-        const ANALYSIS_INVALID_MASK = Self::POINTEE_INVALID.bits
+        const ANALYSIS_INVALID_MASK = Self::ANALYSIS_PANIC.bits
+            | Self::POINTEE_INVALID.bits
             | Self::DATAFLOW_INVALID.bits
             | Self::BORROWCK_INVALID.bits
             | Self::MISC_ANALYSIS_INVALID.bits
@@ -254,6 +294,9 @@ bitflags! {
         const USER_REQUEST = 0x0001;
         /// The field is used in a function that isn't being rewritten.
         const NON_REWRITTEN_USE = 0x0002;
+        /// The field is the pointer inside a `#[repr(transparent)]` wrapper, and the wrapper
+        /// appears in the type of an item that isn't being rewritten.
+        const WRAPPER_NOT_REWRITTEN = 0x0004;
     }
 }
 
@@ -400,6 +443,10 @@ pub struct GlobalAnalysisCtxt<'tcx> {
     /// [`name`]: KnownFn::name
     known_fns: HashMap<&'static str, &'static KnownFn>,
 
+    /// Permission summaries of functions exported by other crates of the workspace, keyed by
+    /// symbol name.  See [`crate::summary`].
+    pub fn_summaries: HashMap<String, FnSummary>,
+
     pub dont_rewrite_fns: FlagMap<DefId, DontRewriteFnReason>,
     pub dont_rewrite_statics: FlagMap<DefId, DontRewriteStaticReason>,
     pub dont_rewrite_fields: FlagMap<DefId, DontRewriteFieldReason>,
@@ -419,6 +466,15 @@ pub struct GlobalAnalysisCtxt<'tcx> {
     pub fn_origins: FnOriginMap<'tcx>,
 
     pub foreign_mentioned_tys: HashSet<DefId>,
+
+    /// Integer arguments that receive the same constant at every call site.  See
+    /// [`crate::const_prop`].
+    pub arg_consts: ArgConsts,
+
+    /// Functions whose returned slice is fused with the length they report through an
+    /// out-parameter.  The out-parameter is removed from the rewritten signature.  See
+    /// [`crate::returned_slice_len`].
+    pub returned_slice_lens: HashMap<DefId, ReturnedSliceLen<'tcx>>,
 }
 
 pub struct AnalysisCtxt<'a, 'tcx> {
@@ -780,6 +836,7 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
                 .iter()
                 .map(|known_fn| (known_fn.name, known_fn))
                 .collect(),
+            fn_summaries: HashMap::new(),
             dont_rewrite_fns: FlagMap::new(),
             dont_rewrite_statics: FlagMap::new(),
             dont_rewrite_fields: FlagMap::new(),
@@ -791,6 +848,8 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
             adt_metadata: AdtMetadataTable::default(),
             fn_origins: FnOriginMap::default(),
             foreign_mentioned_tys: HashSet::new(),
+            arg_consts: ArgConsts::default(),
+            returned_slice_lens: HashMap::new(),
         }
     }
 
@@ -846,6 +905,7 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
             ref mut fn_sigs,
             fn_fields_used: _,
             known_fns: _,
+            fn_summaries: _,
             dont_rewrite_fns: _,
             dont_rewrite_statics: _,
             dont_rewrite_fields: _,
@@ -857,6 +917,8 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
             adt_metadata: _,
             fn_origins: _,
             foreign_mentioned_tys: _,
+            arg_consts: _,
+            returned_slice_lens: _,
         } = *self;
 
         *ptr_info = remap_global_ptr_info(ptr_info, map, counter.num_pointers());
@@ -907,6 +969,13 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
         }
     }
 
+    /// If `lty` is a `#[repr(transparent)]` pointer wrapper (see
+    /// [`util::transparent_ptr_field`]), return the `LTy` of the wrapped pointer field.
+    pub fn transparent_ptr_lty(&self, lty: LTy<'tcx>) -> Option<LTy<'tcx>> {
+        let field = util::transparent_ptr_field(self.tcx, lty.ty)?;
+        Some(self.field_ltys[&field.did])
+    }
+
     pub fn mark_fn_failed(&mut self, did: DefId, reason: DontRewriteFnReason, detail: PanicDetail) {
         self.dont_rewrite_fns.add(did, reason);
         // Insert `detail` if there isn't yet an entry for this `DefId`.
@@ -923,13 +992,26 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
         self.known_fns.get(symbol.name).copied()
     }
 
+    /// Look up the [`FnSummary`] of the function exported by another crate under the symbol
+    /// name of `def_id`, if there is one.
+    pub fn fn_summary(&self, def_id: DefId) -> Option<&FnSummary> {
+        let symbol = self.tcx.symbol_name(Instance::mono(self.tcx, def_id));
+        self.fn_summaries.get(symbol.name)
+    }
+
+    /// Check whether the signature of the foreign function `def_id` has fixed permissions, from
+    /// either a [`KnownFn`] or an [`FnSummary`], so calls to it can be treated like local calls.
+    pub fn has_known_perms(&self, def_id: DefId) -> bool {
+        self.known_fn(def_id).is_some() || self.fn_summary(def_id).is_some()
+    }
+
     /// Determine the [`PermissionSet`]s that should constrain [`PointerId`]s
-    /// contained in the signatures of [`KnownFn`]s.
+    /// contained in the signatures of [`KnownFn`]s and of summarized foreign `fn`s.
     ///
     /// This is determined by iterating through the [`LFnSig`]s in `self.fn_sigs`,
     /// filtering out the foreign ones ([`gather_foreign_sigs`] adds them to `fn_sigs`),
-    /// looking up the [`KnownFn`] for that foreign `fn`, if it exists,
-    /// and then `flat_map`ping that to each [`KnownFn::ptr_perms`].
+    /// looking up the [`KnownFn`] or else the [`FnSummary`] for that foreign `fn`, if it exists,
+    /// and then `flat_map`ping that to each [`KnownFn::ptr_perms`] or [`FnSummary::ptr_perms`].
     ///
     /// [`gather_foreign_sigs`]: crate::analyze::gather_foreign_sigs
     pub fn known_fn_ptr_perms<'a>(
@@ -940,8 +1022,20 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
             .filter(|(def_id, _)| {
                 self.tcx.def_kind(self.tcx.parent(**def_id)) == DefKind::ForeignMod
             })
-            .filter_map(|(&def_id, fn_sig)| Some((fn_sig, self.known_fn(def_id)?)))
-            .flat_map(|(fn_sig, known_fn)| known_fn.ptr_perms(fn_sig))
+            .flat_map(move |(&def_id, fn_sig)| {
+                // A `KnownFn` takes precedence over a summary of the same symbol.
+                if let Some(known_fn) = self.known_fn(def_id) {
+                    return Either::Left(known_fn.ptr_perms(fn_sig));
+                }
+                let summary_perms = self
+                    .fn_summary(def_id)
+                    .into_iter()
+                    .flat_map(move |summary| {
+                        let symbol = self.tcx.symbol_name(Instance::mono(self.tcx, def_id));
+                        summary.ptr_perms(symbol.name, fn_sig)
+                    });
+                Either::Right(summary_perms)
+            })
     }
 
     /// Check whether the function with the given `def_id` has been marked as non-rewritable.
@@ -1102,12 +1196,14 @@ impl<'a, 'tcx> AnalysisCtxt<'a, 'tcx> {
                 let (pointee_lty, proj, ptr) = match desc {
                     RvalueDesc::Project { base, proj } => {
                         let base_lty = self.type_of(base);
-                        eprintln!(
+                        trace!(
                             "rvalue = {:?}, desc = {:?}, base_lty = {:?}",
-                            rv, desc, base_lty
+                            rv,
+                            desc,
+                            base_lty
                         );
                         (
-                            self.projection_lty(base_lty, &PlaceElem::Deref),
+                            self.projection_lty(base_lty, None, &PlaceElem::Deref),
                             proj,
                             base_lty.label,
                         )
@@ -1118,8 +1214,10 @@ impl<'a, 'tcx> AnalysisCtxt<'a, 'tcx> {
                 };
 
                 let mut pointee_lty = pointee_lty;
+                let mut variant = None;
                 for p in proj {
-                    pointee_lty = self.projection_lty(pointee_lty, p);
+                    pointee_lty = self.projection_lty(pointee_lty, variant, p);
+                    variant = util::downcast_variant(p);
                 }
 
                 let ty = rv.ty(self, self.tcx());
@@ -1172,17 +1270,24 @@ impl<'a, 'tcx> AnalysisCtxt<'a, 'tcx> {
         ty
     }
 
-    pub fn projection_lty(&self, lty: LTy<'tcx>, proj: &PlaceElem<'tcx>) -> LTy<'tcx> {
-        let projection_lty = |_lty: LTy, adt_def: AdtDef, field: Field| {
-            let field_def = &adt_def.non_enum_variant().fields[field.index()];
+    /// Compute the `LTy` resulting from applying `proj` to a place of type `lty`.  `variant` is
+    /// the enum variant selected by the previous projection, as given by
+    /// [`util::downcast_variant`].
+    pub fn projection_lty(
+        &self,
+        lty: LTy<'tcx>,
+        variant: Option<VariantIdx>,
+        proj: &PlaceElem<'tcx>,
+    ) -> LTy<'tcx> {
+        let projection_lty = |_lty: LTy, adt_def: AdtDef, field_def: &FieldDef| {
             let field_def_name = field_def.name;
-            eprintln!("projecting into {adt_def:?}.{field_def_name:}");
+            trace!("projecting into {adt_def:?}.{field_def_name:}");
             let field_lty: LTy = self.gacx.field_ltys.get(&field_def.did).unwrap_or_else(|| {
                 panic!("Could not find {adt_def:?}.{field_def_name:?} in field type map")
             });
             field_lty
         };
-        util::lty_project(lty, proj, projection_lty)
+        util::lty_project(lty, proj, variant, projection_lty)
     }
 }
 
@@ -1316,8 +1421,10 @@ impl<'tcx> TypeOf<'tcx> for Place<'tcx> {
 impl<'tcx> TypeOf<'tcx> for PlaceRef<'tcx> {
     fn type_of(&self, acx: &AnalysisCtxt<'_, 'tcx>) -> LTy<'tcx> {
         let mut ty = acx.type_of(self.local);
+        let mut variant = None;
         for proj in self.projection {
-            ty = acx.projection_lty(ty, proj);
+            ty = acx.projection_lty(ty, variant, proj);
+            variant = util::downcast_variant(proj);
         }
         ty
     }
@@ -1,9 +1,12 @@
-use std::mem;
+use std::collections::{HashSet, VecDeque};
+use std::iter;
 
 use crate::context::{AnalysisCtxt, Assignment, FlagSet, PermissionSet, PointerId};
 use crate::pointee_type::PointeeTypes;
 use crate::pointer_id::{OwnedPointerTable, PointerTable, PointerTableMut};
+use crate::realloc_vecs;
 use crate::recent_writes::RecentWrites;
+use log::trace;
 use rustc_middle::mir::Body;
 
 mod type_check;
@@ -31,6 +34,9 @@ enum Constraint {
     AllPerms(PointerId, PermissionSet),
     /// Pointer `.0` must not have any of the permissions in `.1`.
     NoPerms(PointerId, PermissionSet),
+    /// If pointer `.0` has the `FREE` permission, pointer `.1` must have all the permissions in
+    /// `.2`.
+    AllPermsIfFree(PointerId, PointerId, PermissionSet),
 }
 
 #[derive(Clone, Debug, Default)]
@@ -56,6 +62,15 @@ impl DataflowConstraints {
         self.constraints.push(Constraint::NoPerms(ptr, perms));
     }
 
+    fn add_all_perms_if_free(&mut self, cond: PointerId, ptr: PointerId, perms: PermissionSet) {
+        self.constraints
+            .push(Constraint::AllPermsIfFree(cond, ptr, perms));
+    }
+
+    pub fn num_constraints(&self) -> usize {
+        self.constraints.len()
+    }
+
     /// Update the pointer permissions in `hypothesis` to satisfy these constraints.
     ///
     /// If `restrict_updates[ptr]` has some flags set, then those flags will be left unchanged in
@@ -65,14 +80,14 @@ impl DataflowConstraints {
         hypothesis: &mut PointerTableMut<PermissionSet>,
         updates_forbidden: &PointerTable<PermissionSet>,
     ) -> bool {
-        eprintln!("=== propagating ===");
-        eprintln!("constraints:");
+        trace!("=== propagating ===");
+        trace!("constraints:");
         for c in &self.constraints {
-            eprintln!("  {:?}", c);
+            trace!("  {:?}", c);
         }
-        eprintln!("hypothesis:");
+        trace!("hypothesis:");
         for (id, p) in hypothesis.iter() {
-            eprintln!("  {}: {:?}", id, p);
+            trace!("  {}: {:?}", id, p);
         }
 
         struct PropagatePerms;
@@ -153,6 +168,20 @@ impl DataflowConstraints {
                 *val & !perms
             }
 
+            fn all_perms_if_free(
+                &mut self,
+                cond_val: &PermissionSet,
+                _ptr: PointerId,
+                perms: PermissionSet,
+                val: &PermissionSet,
+            ) -> PermissionSet {
+                if cond_val.contains(PermissionSet::FREE) {
+                    *val | perms
+                } else {
+                    *val
+                }
+            }
+
             fn restrict_updates(
                 &mut self,
                 old: &PermissionSet,
@@ -188,7 +217,7 @@ impl DataflowConstraints {
         T: PartialEq,
         R: PropagateRules<T>,
     {
-        let mut xs = TrackedPointerTable::new(xs.borrow_mut());
+        let mut xs = xs.borrow_mut();
 
         let restrict_updates = |rules: &mut R, ptr, old: &T, new: T| {
             if let Some(updates_forbidden) = updates_forbidden {
@@ -198,73 +227,74 @@ impl DataflowConstraints {
             }
         };
 
+        // The constraints that mention each pointer.  When a pointer's value changes, only these
+        // constraints need to be checked again.
+        let mut users = OwnedPointerTable::<Vec<usize>>::with_len_of(&xs.borrow());
+        for (i, c) in self.constraints.iter().enumerate() {
+            for ptr in c.pointers() {
+                users[ptr].push(i);
+            }
+        }
+
+        // Every constraint is checked once, and then again each time one of its pointers changes.
+        let mut worklist = Worklist::new(self.constraints.len());
         let mut changed = false;
-        let mut i = 0;
-        loop {
-            if i > xs.len() + self.constraints.len() {
+        let mut set = |xs: &mut PointerTableMut<T>, worklist: &mut Worklist, ptr, x: T| {
+            if x != xs[ptr] {
+                xs[ptr] = x;
+                for &i in &users[ptr] {
+                    worklist.push(i);
+                }
+                changed = true;
+            }
+        };
+
+        // Well-behaved rules reach a fixpoint long before this limit.  Exceeding it means the
+        // rules keep changing some pointer back and forth.
+        let max_steps =
+            (xs.len() + self.constraints.len() + 1).saturating_mul(self.constraints.len());
+        let mut steps = 0;
+        while let Some(i) = worklist.pop() {
+            steps += 1;
+            if steps > max_steps {
                 return Err("infinite loop in dataflow edges".to_string());
             }
-            i += 1;
-
-            for c in &self.constraints {
-                match *c {
-                    Constraint::Subset(a, b) => {
-                        if !xs.dirty(a) && !xs.dirty(b) {
-                            continue;
-                        }
-
-                        let old_a = xs.get(a);
-                        let old_b = xs.get(b);
-                        let (new_a, new_b) = rules.subset(a, old_a, b, old_b);
-                        let new_a = restrict_updates(rules, a, old_a, new_a);
-                        let new_b = restrict_updates(rules, b, old_b, new_b);
-                        xs.set(a, new_a);
-                        xs.set(b, new_b);
-                    }
-
-                    Constraint::SubsetExcept(a, b, except) => {
-                        if !xs.dirty(a) && !xs.dirty(b) {
-                            continue;
-                        }
-
-                        let old_a = xs.get(a);
-                        let old_b = xs.get(b);
-                        let (new_a, new_b) = rules.subset_except(a, old_a, b, old_b, except);
-                        let new_a = restrict_updates(rules, a, old_a, new_a);
-                        let new_b = restrict_updates(rules, b, old_b, new_b);
-                        xs.set(a, new_a);
-                        xs.set(b, new_b);
-                    }
-
-                    Constraint::AllPerms(ptr, perms) => {
-                        if !xs.dirty(ptr) {
-                            continue;
-                        }
-
-                        let old = xs.get(ptr);
-                        let new = rules.all_perms(ptr, perms, old);
-                        let new = restrict_updates(rules, ptr, old, new);
-                        xs.set(ptr, new);
-                    }
-
-                    Constraint::NoPerms(ptr, perms) => {
-                        if !xs.dirty(ptr) {
-                            continue;
-                        }
-
-                        let old = xs.get(ptr);
-                        let new = rules.no_perms(ptr, perms, old);
-                        let new = restrict_updates(rules, ptr, old, new);
-                        xs.set(ptr, new);
-                    }
+
+            match self.constraints[i] {
+                Constraint::Subset(a, b) => {
+                    let (new_a, new_b) = rules.subset(a, &xs[a], b, &xs[b]);
+                    let new_a = restrict_updates(rules, a, &xs[a], new_a);
+                    let new_b = restrict_updates(rules, b, &xs[b], new_b);
+                    set(&mut xs, &mut worklist, a, new_a);
+                    set(&mut xs, &mut worklist, b, new_b);
+                }
+
+                Constraint::SubsetExcept(a, b, except) => {
+                    let (new_a, new_b) = rules.subset_except(a, &xs[a], b, &xs[b], except);
+                    let new_a = restrict_updates(rules, a, &xs[a], new_a);
+                    let new_b = restrict_updates(rules, b, &xs[b], new_b);
+                    set(&mut xs, &mut worklist, a, new_a);
+                    set(&mut xs, &mut worklist, b, new_b);
                 }
-            }
 
-            if !xs.any_new_dirty() {
-                break;
+                Constraint::AllPerms(ptr, perms) => {
+                    let new = rules.all_perms(ptr, perms, &xs[ptr]);
+                    let new = restrict_updates(rules, ptr, &xs[ptr], new);
+                    set(&mut xs, &mut worklist, ptr, new);
+                }
+
+                Constraint::NoPerms(ptr, perms) => {
+                    let new = rules.no_perms(ptr, perms, &xs[ptr]);
+                    let new = restrict_updates(rules, ptr, &xs[ptr], new);
+                    set(&mut xs, &mut worklist, ptr, new);
+                }
+
+                Constraint::AllPermsIfFree(cond, ptr, perms) => {
+                    let new = rules.all_perms_if_free(&xs[cond], ptr, perms, &xs[ptr]);
+                    let new = restrict_updates(rules, ptr, &xs[ptr], new);
+                    set(&mut xs, &mut worklist, ptr, new);
+                }
             }
-            xs.swap_dirty();
-            changed = true;
         }
 
         Ok(changed)
@@ -343,6 +373,16 @@ impl DataflowConstraints {
                 *val
             }
 
+            fn all_perms_if_free(
+                &mut self,
+                _cond_val: &FlagSet,
+                _ptr: PointerId,
+                _perms: PermissionSet,
+                val: &FlagSet,
+            ) -> FlagSet {
+                *val
+            }
+
             fn restrict_updates(
                 &mut self,
                 old: &FlagSet,
@@ -361,9 +401,169 @@ impl DataflowConstraints {
             }
         }
     }
+
+    /// Remove `C_STRING` from every pointer that's connected to a pointer without it, so that
+    /// each value is either a string at both ends of an assignment or at neither.  A string can be
+    /// converted to a `FIXED` raw pointer, and a `FIXED` raw pointer can be converted to `&CStr`,
+    /// but not to `CString`, which must own its allocation.
+    ///
+    /// Returns an error if the flags never reach a fixpoint.
+    pub fn propagate_c_string(&self, asn: &mut Assignment) -> Result<(), String> {
+        let (perms, mut flags) = asn.all_mut();
+        let perms = perms.borrow();
+
+        self.propagate_flags(&mut flags, |_a_ptr, mut a_flags, b_ptr, mut b_flags| {
+            if a_flags.contains(FlagSet::FIXED) {
+                if perms[b_ptr].contains(PermissionSet::FREE) {
+                    b_flags.remove(FlagSet::C_STRING);
+                }
+            } else if !b_flags.contains(FlagSet::FIXED)
+                && !(a_flags & b_flags).contains(FlagSet::C_STRING)
+            {
+                a_flags.remove(FlagSet::C_STRING);
+                b_flags.remove(FlagSet::C_STRING);
+            }
+            (a_flags, b_flags)
+        })
+    }
+
+    /// Add `VEC` to every owned slice that's connected to a pointer with it, so that a buffer
+    /// resized by `realloc` keeps its `Vec` type as it's copied between pointers.  Pointers that
+    /// can't be `Vec`s, such as borrowed slices, are unaffected.
+    ///
+    /// Returns an error if the flags never reach a fixpoint.
+    pub fn propagate_vec(&self, asn: &mut Assignment) -> Result<(), String> {
+        self.propagate_shared(
+            asn,
+            FlagSet::VEC,
+            realloc_vecs::is_candidate,
+            &HashSet::new(),
+        )
+    }
+
+    /// Add `flag` (`REFCELL` or `MUTEX`) to every pointer that satisfies `can_share` and exchanges
+    /// values with a pointer that has `flag`, so that all the pointers to a shared object become
+    /// `Rc<RefCell<T>>` or `Arc<Mutex<T>>`.  Pointers in `excluded` never get the flag.
+    ///
+    /// Returns an error if the flags never reach a fixpoint.
+    pub fn propagate_shared(
+        &self,
+        asn: &mut Assignment,
+        flag: FlagSet,
+        can_share: fn(PermissionSet, FlagSet) -> bool,
+        excluded: &HashSet<PointerId>,
+    ) -> Result<(), String> {
+        let (perms, mut flags) = asn.all_mut();
+        let perms = perms.borrow();
+
+        let can_share = |ptr: PointerId, flags: FlagSet| {
+            can_share(perms[ptr], flags) && !excluded.contains(&ptr)
+        };
+        self.propagate_flags(&mut flags, |a_ptr, mut a_flags, b_ptr, mut b_flags| {
+            if (a_flags | b_flags).contains(flag) {
+                if can_share(a_ptr, a_flags) {
+                    a_flags.insert(flag);
+                }
+                if can_share(b_ptr, b_flags) {
+                    b_flags.insert(flag);
+                }
+            }
+            (a_flags, b_flags)
+        })
+    }
+
+    /// Update `flags` along the `Subset` and `SubsetExcept` constraints, using `subset` to compute
+    /// the new flags at both ends of each assignment.  The other constraints only concern
+    /// permissions, so they leave the flags unchanged.
+    fn propagate_flags<F>(
+        &self,
+        flags: &mut PointerTableMut<FlagSet>,
+        subset: F,
+    ) -> Result<(), String>
+    where
+        F: FnMut(PointerId, FlagSet, PointerId, FlagSet) -> (FlagSet, FlagSet),
+    {
+        struct Rules<F>(F);
+        impl<F> PropagateRules<FlagSet> for Rules<F>
+        where
+            F: FnMut(PointerId, FlagSet, PointerId, FlagSet) -> (FlagSet, FlagSet),
+        {
+            fn subset(
+                &mut self,
+                a_ptr: PointerId,
+                a_val: &FlagSet,
+                b_ptr: PointerId,
+                b_val: &FlagSet,
+            ) -> (FlagSet, FlagSet) {
+                (self.0)(a_ptr, *a_val, b_ptr, *b_val)
+            }
+
+            fn subset_except(
+                &mut self,
+                a_ptr: PointerId,
+                a_val: &FlagSet,
+                b_ptr: PointerId,
+                b_val: &FlagSet,
+                _except: PermissionSet,
+            ) -> (FlagSet, FlagSet) {
+                self.subset(a_ptr, a_val, b_ptr, b_val)
+            }
+
+            fn all_perms(
+                &mut self,
+                _ptr: PointerId,
+                _perms: PermissionSet,
+                val: &FlagSet,
+            ) -> FlagSet {
+                *val
+            }
+
+            fn no_perms(
+                &mut self,
+                _ptr: PointerId,
+                _perms: PermissionSet,
+                val: &FlagSet,
+            ) -> FlagSet {
+                *val
+            }
+
+            fn all_perms_if_free(
+                &mut self,
+                _cond_val: &FlagSet,
+                _ptr: PointerId,
+                _perms: PermissionSet,
+                val: &FlagSet,
+            ) -> FlagSet {
+                *val
+            }
+
+            fn restrict_updates(
+                &mut self,
+                old: &FlagSet,
+                new: &FlagSet,
+                updates_forbidden: &FlagSet,
+            ) -> FlagSet {
+                let (old, new, updates_forbidden) = (*old, *new, *updates_forbidden);
+                (new & !updates_forbidden) | (old & updates_forbidden)
+            }
+        }
+
+        self.propagate_inner(flags, &mut Rules(subset), None)
+            .map(|_changed| ())
+    }
 }
 
 impl Constraint {
+    /// The pointers whose values this constraint reads or updates.
+    fn pointers(&self) -> impl Iterator<Item = PointerId> {
+        let (a, b) = match *self {
+            Constraint::Subset(a, b) | Constraint::SubsetExcept(a, b, _) => (a, Some(b)),
+            Constraint::AllPerms(ptr, _) | Constraint::NoPerms(ptr, _) => (ptr, None),
+            Constraint::AllPermsIfFree(cond, ptr, _) => (cond, Some(ptr)),
+        };
+        iter::once(a).chain(b)
+    }
+
     pub fn remap_pointers(&mut self, map: PointerTable<PointerId>) {
         *self = match *self {
             Constraint::Subset(a, b) => Constraint::Subset(map[a], map[b]),
@@ -372,6 +572,9 @@ impl Constraint {
             }
             Constraint::AllPerms(ptr, perms) => Constraint::AllPerms(map[ptr], perms),
             Constraint::NoPerms(ptr, perms) => Constraint::NoPerms(map[ptr], perms),
+            Constraint::AllPermsIfFree(cond, ptr, perms) => {
+                Constraint::AllPermsIfFree(map[cond], map[ptr], perms)
+            }
         };
     }
 }
@@ -384,55 +587,32 @@ impl DataflowConstraints {
     }
 }
 
-struct TrackedPointerTable<'a, T> {
-    xs: PointerTableMut<'a, T>,
-    dirty: OwnedPointerTable<bool>,
-    new_dirty: OwnedPointerTable<bool>,
-    any_new_dirty: bool,
+/// A queue of constraint indices, each of which is queued at most once at a time.
+struct Worklist {
+    queue: VecDeque<usize>,
+    queued: Vec<bool>,
 }
 
-impl<'a, T: PartialEq> TrackedPointerTable<'a, T> {
-    pub fn new(xs: PointerTableMut<'a, T>) -> TrackedPointerTable<'a, T> {
-        let mut dirty = OwnedPointerTable::with_len_of(&xs.borrow());
-        let mut new_dirty = OwnedPointerTable::with_len_of(&xs.borrow());
-        dirty.fill(true);
-        new_dirty.fill(false);
-        TrackedPointerTable {
-            xs,
-            dirty,
-            new_dirty,
-            any_new_dirty: false,
+impl Worklist {
+    /// Create a worklist holding all of the constraints `0..len`, in order.
+    pub fn new(len: usize) -> Worklist {
+        Worklist {
+            queue: (0..len).collect(),
+            queued: vec![true; len],
         }
     }
 
-    pub fn len(&self) -> usize {
-        self.xs.len()
-    }
-
-    pub fn get(&self, id: PointerId) -> &T {
-        &self.xs[id]
-    }
-
-    pub fn dirty(&self, id: PointerId) -> bool {
-        self.dirty[id]
-    }
-
-    pub fn any_new_dirty(&self) -> bool {
-        self.any_new_dirty
-    }
-
-    pub fn set(&mut self, id: PointerId, x: T) {
-        if x != self.xs[id] {
-            self.xs[id] = x;
-            self.new_dirty[id] = true;
-            self.any_new_dirty = true;
+    pub fn push(&mut self, i: usize) {
+        if !self.queued[i] {
+            self.queued[i] = true;
+            self.queue.push_back(i);
         }
     }
 
-    pub fn swap_dirty(&mut self) {
-        mem::swap(&mut self.dirty, &mut self.new_dirty);
-        self.new_dirty.fill(false);
-        self.any_new_dirty = false;
+    pub fn pop(&mut self) -> Option<usize> {
+        let i = self.queue.pop_front()?;
+        self.queued[i] = false;
+        Some(i)
     }
 }
 
@@ -448,6 +628,13 @@ trait PropagateRules<T> {
     ) -> (T, T);
     fn all_perms(&mut self, ptr: PointerId, perms: PermissionSet, val: &T) -> T;
     fn no_perms(&mut self, ptr: PointerId, perms: PermissionSet, val: &T) -> T;
+    fn all_perms_if_free(
+        &mut self,
+        cond_val: &T,
+        ptr: PointerId,
+        perms: PermissionSet,
+        val: &T,
+    ) -> T;
     /// Apply a filter to restrict updates.  The result is similar to `new`, but all flags marked
     /// in `updates_forbidden` are adjusted to match their `old` values.
     fn restrict_updates(&mut self, old: &T, new: &T, updates_forbidden: &T) -> T;
@@ -461,3 +648,191 @@ pub fn generate_constraints<'tcx>(
 ) -> (DataflowConstraints, Vec<(PointerId, PointerId)>) {
     self::type_check::visit(acx, mir, recent_writes, pointee_types)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pointer_id::{GlobalPointerTable, LocalPointerTable};
+    use std::time::Instant;
+
+    /// Propagates `READ` from the subset to the superset of each `Subset` constraint, and
+    /// `NON_NULL` in the other direction.
+    struct Rules;
+
+    impl PropagateRules<PermissionSet> for Rules {
+        fn subset(
+            &mut self,
+            _a_ptr: PointerId,
+            a_val: &PermissionSet,
+            _b_ptr: PointerId,
+            b_val: &PermissionSet,
+        ) -> (PermissionSet, PermissionSet) {
+            let remove_a = !*b_val & PermissionSet::NON_NULL;
+            let add_b = *a_val & PermissionSet::READ;
+            (*a_val & !remove_a, *b_val | add_b)
+        }
+
+        fn subset_except(
+            &mut self,
+            _a_ptr: PointerId,
+            _a_val: &PermissionSet,
+            _b_ptr: PointerId,
+            _b_val: &PermissionSet,
+            _except: PermissionSet,
+        ) -> (PermissionSet, PermissionSet) {
+            unreachable!()
+        }
+
+        fn all_perms(
+            &mut self,
+            _ptr: PointerId,
+            _perms: PermissionSet,
+            _val: &PermissionSet,
+        ) -> PermissionSet {
+            unreachable!()
+        }
+
+        fn no_perms(
+            &mut self,
+            _ptr: PointerId,
+            _perms: PermissionSet,
+            _val: &PermissionSet,
+        ) -> PermissionSet {
+            unreachable!()
+        }
+
+        fn all_perms_if_free(
+            &mut self,
+            _cond_val: &PermissionSet,
+            _ptr: PointerId,
+            _perms: PermissionSet,
+            _val: &PermissionSet,
+        ) -> PermissionSet {
+            unreachable!()
+        }
+
+        fn restrict_updates(
+            &mut self,
+            _old: &PermissionSet,
+            new: &PermissionSet,
+            _updates_forbidden: &PermissionSet,
+        ) -> PermissionSet {
+            *new
+        }
+    }
+
+    /// The solver that the worklist replaced: check every constraint in each round, until a round
+    /// changes nothing.
+    fn propagate_rounds(constraints: &DataflowConstraints, xs: &mut [PermissionSet]) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for c in &constraints.constraints {
+                let (a, b) = match *c {
+                    Constraint::Subset(a, b) => (a, b),
+                    _ => unreachable!(),
+                };
+                let (ai, bi) = (a.index() as usize, b.index() as usize);
+                let (new_a, new_b) = Rules.subset(a, &xs[ai], b, &xs[bi]);
+                if new_a != xs[ai] || new_b != xs[bi] {
+                    xs[ai] = new_a;
+                    xs[bi] = new_b;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    fn propagate_worklist(
+        constraints: &DataflowConstraints,
+        xs: Vec<PermissionSet>,
+    ) -> Vec<PermissionSet> {
+        let mut global = GlobalPointerTable::new(0);
+        let mut local = LocalPointerTable::from_raw(xs);
+        let mut table = PointerTableMut::new(&mut global, &mut local);
+        constraints
+            .propagate_inner(&mut table, &mut Rules, None)
+            .unwrap();
+        local.into_raw()
+    }
+
+    /// A chain of `n` pointers, where each flows into the next, with the constraints listed in
+    /// reverse order.  The first pointer is `READ`, and every pointer but the last is `NON_NULL`.
+    /// Solving it in rounds takes `n` rounds, since each round moves `READ` one step forward.
+    fn chain(n: u32) -> (DataflowConstraints, Vec<PermissionSet>) {
+        let mut constraints = DataflowConstraints::default();
+        for i in (0..n - 1).rev() {
+            constraints.add_subset(PointerId::local(i), PointerId::local(i + 1));
+        }
+        let mut xs = vec![PermissionSet::NON_NULL; n as usize];
+        xs[0].insert(PermissionSet::READ);
+        xs[n as usize - 1] = PermissionSet::empty();
+        (constraints, xs)
+    }
+
+    /// A random graph of `n` pointers and `m` `Subset` constraints, with some pointers `READ` and
+    /// the rest `NON_NULL`.
+    fn random_graph(n: u32, m: usize) -> (DataflowConstraints, Vec<PermissionSet>) {
+        // A linear congruential generator, so the graph is the same on every run.
+        let mut state = 0x2545_f491_u64;
+        let mut next = |bound: u32| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((state >> 33) % bound as u64) as u32
+        };
+        let mut constraints = DataflowConstraints::default();
+        for _ in 0..m {
+            let (a, b) = (next(n), next(n));
+            constraints.add_subset(PointerId::local(a), PointerId::local(b));
+        }
+        let xs = (0..n)
+            .map(|_| match next(8) {
+                0 => PermissionSet::READ,
+                _ => PermissionSet::NON_NULL,
+            })
+            .collect();
+        (constraints, xs)
+    }
+
+    #[test]
+    fn chain_fixpoint() {
+        let (constraints, xs) = chain(60_000);
+        let xs = propagate_worklist(&constraints, xs);
+        assert!(xs.iter().all(|&p| p == PermissionSet::READ));
+    }
+
+    #[test]
+    fn same_fixpoint_as_rounds() {
+        for (n, m) in [(10, 20), (1_000, 1_500), (1_000, 5_000)] {
+            let (constraints, xs) = random_graph(n, m);
+            let mut expected = xs.clone();
+            propagate_rounds(&constraints, &mut expected);
+            assert_eq!(propagate_worklist(&constraints, xs), expected);
+        }
+    }
+
+    /// Compare the time taken by the worklist solver and the round-based one on graphs with more
+    /// than 50k pointers.  Run with `cargo test --release -- --ignored --nocapture bench_`.
+    #[test]
+    #[ignore]
+    fn bench_propagate() {
+        let graphs = [
+            ("chain", chain(60_000)),
+            ("random", random_graph(60_000, 120_000)),
+        ];
+        for (name, (constraints, xs)) in graphs {
+            let start = Instant::now();
+            let mut expected = xs.clone();
+            propagate_rounds(&constraints, &mut expected);
+            let rounds_time = start.elapsed();
+
+            let start = Instant::now();
+            let actual = propagate_worklist(&constraints, xs);
+            let worklist_time = start.elapsed();
+
+            assert_eq!(actual, expected);
+            eprintln!("{name}: rounds {rounds_time:?}, worklist {worklist_time:?}");
+        }
+    }
+}
@@ -10,10 +10,11 @@ use crate::util::{
 };
 use assert_matches::assert_matches;
 use either::Either;
+use log::trace;
 use rustc_hir::def_id::DefId;
 use rustc_middle::mir::{
-    AggregateKind, BinOp, Body, CastKind, Location, Mutability, Operand, Place, PlaceRef,
-    ProjectionElem, Rvalue, Statement, StatementKind, Terminator, TerminatorKind,
+    AggregateKind, BinOp, Body, BorrowKind, CastKind, Location, Mutability, Operand, Place,
+    PlaceRef, ProjectionElem, Rvalue, Statement, StatementKind, Terminator, TerminatorKind,
 };
 use rustc_middle::ty::adjustment::PointerCast;
 use rustc_middle::ty::{SubstsRef, Ty, TyKind};
@@ -68,7 +69,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
     }
 
     fn record_access(&mut self, ptr: PointerId, mutbl: Mutability) {
-        eprintln!("record_access({:?}, {:?})", ptr, mutbl);
+        trace!("record_access({:?}, {:?})", ptr, mutbl);
         if ptr == PointerId::NONE {
             return;
         }
@@ -89,6 +90,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
 
     pub fn visit_place_ref(&mut self, pl: PlaceRef<'tcx>, mutbl: Mutability) {
         let mut lty = self.acx.type_of(pl.local);
+        let mut variant = None;
         let mut prev_deref_ptr = None;
 
         for proj in pl.projection {
@@ -103,7 +105,8 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 }
                 prev_deref_ptr = Some(lty.label);
             }
-            lty = self.acx.projection_lty(lty, proj);
+            lty = self.acx.projection_lty(lty, variant, proj);
+            variant = util::downcast_variant(proj);
         }
 
         if let Some(ptr) = prev_deref_ptr.take() {
@@ -111,6 +114,31 @@ impl<'tcx> TypeChecker<'tcx, '_> {
         }
     }
 
+    /// Record that the pointer `ptr` is loaded from `pl`.  If `ptr` is an owned pointer, the
+    /// load is rewritten to move it out with `mem::replace(&mut pl, Err(()))`, so that elements
+    /// of arrays and slices of owned pointers, like object pools and free lists, can be taken.
+    /// This requires the pointers dereferenced to reach `pl` to be mutable.
+    fn record_take(&mut self, pl: PlaceRef<'tcx>, ptr: PointerId) {
+        if ptr == PointerId::NONE {
+            return;
+        }
+        let mut lty = self.acx.type_of(pl.local);
+        let mut variant = None;
+        for proj in pl.projection {
+            if let ProjectionElem::Deref = proj {
+                if lty.label != PointerId::NONE {
+                    self.constraints.add_all_perms_if_free(
+                        ptr,
+                        lty.label,
+                        PermissionSet::READ | PermissionSet::WRITE,
+                    );
+                }
+            }
+            lty = self.acx.projection_lty(lty, variant, proj);
+            variant = util::downcast_variant(proj);
+        }
+    }
+
     fn visit_cast(&mut self, cast_kind: CastKind, op: &Operand<'tcx>, to_lty: LTy<'tcx>) {
         let to_ty = to_lty.ty;
         let from_lty = self.acx.type_of(op);
@@ -143,8 +171,25 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 // it ensures if [`PointerCast`] is changed in a future `rustc` version,
                 // this won't compile until we've checked that this reasoning is still accurate.
                 match ptr_cast {
-                    PointerCast::ReifyFnPointer => {}
-                    PointerCast::UnsafeFnPointer => {}
+                    PointerCast::ReifyFnPointer => {
+                        // The pointer types in the signature of `f as fn(..)` are those of `f`,
+                        // so that calls through the pointer are consistent with `f` after
+                        // rewriting.
+                        if let TyKind::FnDef(def_id, _) = *from_ty.kind() {
+                            if let Some(&lsig) = self.acx.gacx.fn_sigs.get(&def_id) {
+                                let (&output_lty, input_ltys) = to_lty.args.split_last().unwrap();
+                                for (&lty, &sig_lty) in input_ltys.iter().zip(lsig.inputs) {
+                                    self.do_unify(lty, sig_lty);
+                                }
+                                self.do_unify(output_lty, lsig.output);
+                            }
+                        }
+                    }
+                    PointerCast::UnsafeFnPointer => {
+                        for (&to_arg, &from_arg) in to_lty.args.iter().zip(from_lty.args.iter()) {
+                            self.do_unify(to_arg, from_arg);
+                        }
+                    }
                     PointerCast::ClosureFnPointer(_) => {}
                     PointerCast::MutToConstPointer => {}
                     PointerCast::ArrayToPointer => {}
@@ -174,13 +219,21 @@ impl<'tcx> TypeChecker<'tcx, '_> {
 
     pub fn visit_rvalue(&mut self, rv: &Rvalue<'tcx>, rvalue_lty: LTy<'tcx>) {
         let rv_desc = describe_rvalue(rv);
-        eprintln!("visit_rvalue({rv:?}), desc = {rv_desc:?}");
+        trace!("visit_rvalue({rv:?}), desc = {rv_desc:?}");
 
         if let Some(desc) = rv_desc {
             match desc {
                 RvalueDesc::Project { base, proj: _ } => {
-                    // TODO: mutability should probably depend on mutability of the output ref/ptr
-                    self.visit_place_ref(base, Mutability::Not);
+                    // Creating a mutable reference or raw pointer to a place, such as with
+                    // `addr_of_mut!((**pp).field)`, requires the pointers loaded along the way to
+                    // be mutable, just like writing to the place would.  The base pointer itself
+                    // is related to the result by `do_assign` below.
+                    let mutbl = match *rv {
+                        Rvalue::Ref(_, BorrowKind::Mut { .. }, _)
+                        | Rvalue::AddressOf(Mutability::Mut, _) => Mutability::Mut,
+                        _ => Mutability::Not,
+                    };
+                    self.visit_place_ref(base, mutbl);
                 }
                 RvalueDesc::AddrOfLocal { .. } => {}
             }
@@ -256,9 +309,9 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                             self.do_assign(elem_lty, op_lty);
                         }
                     }
-                    AggregateKind::Adt(adt_did, ..) => {
+                    AggregateKind::Adt(adt_did, variant_idx, ..) => {
                         let base_adt_def = self.acx.tcx().adt_def(adt_did);
-                        let fields = &base_adt_def.non_enum_variant().fields;
+                        let fields = &base_adt_def.variant(variant_idx).fields;
                         for (field, op) in fields.iter().zip(ops.iter()) {
                             let op_lty = self.acx.type_of(op);
                             let unresolved_field_lty = self.acx.gacx.field_ltys[&field.did];
@@ -350,7 +403,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
     /// For example, given
     ///
     /// ```
-    /// # fn(
+    /// # fn f(
     /// lty1: *mut /*l1*/ *const /*l2*/ u8,
     /// lty2: *mut /*l3*/ *const /*l4*/ u8,
     /// # ) {}
@@ -363,7 +416,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
             self.acx.tcx().erase_regions(lty2.ty)
         );
         for (sub_lty1, sub_lty2) in lty1.iter().zip(lty2.iter()) {
-            eprintln!("equate {:?} = {:?}", sub_lty1, sub_lty2);
+            trace!("equate {:?} = {:?}", sub_lty1, sub_lty2);
             if sub_lty1.label != PointerId::NONE || sub_lty2.label != PointerId::NONE {
                 assert!(sub_lty1.label != PointerId::NONE);
                 assert!(sub_lty2.label != PointerId::NONE);
@@ -373,12 +426,11 @@ impl<'tcx> TypeChecker<'tcx, '_> {
     }
 
     pub fn visit_statement(&mut self, stmt: &Statement<'tcx>, loc: Location) {
-        eprintln!("visit_statement({:?})", stmt);
+        trace!("visit_statement({:?})", stmt);
 
         let _g = panic_detail::set_current_span(stmt.source_info.span);
 
         // TODO(spernsteiner): other `StatementKind`s will be handled in the future
-        #[allow(clippy::single_match)]
         match stmt.kind {
             StatementKind::Assign(ref x) => {
                 let (pl, ref rv) = **x;
@@ -387,6 +439,9 @@ impl<'tcx> TypeChecker<'tcx, '_> {
 
                 let rv_lty = self.acx.type_of_rvalue(rv, loc);
                 self.visit_rvalue(rv, rv_lty);
+                if let Rvalue::Use(Operand::Copy(rv_pl) | Operand::Move(rv_pl)) = *rv {
+                    self.record_take(rv_pl.as_ref(), rv_lty.label);
+                }
 
                 if self.acx.has_field_projection(rv) {
                     // Fields don't get offset permissions propagated to their base pointer
@@ -399,14 +454,18 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                     self.do_assign(pl_lty, rv_lty);
                 }
             }
+            StatementKind::SetDiscriminant { ref place, .. } => {
+                // Setting the discriminant writes to `place`, which requires `WRITE` permission
+                // on any pointers dereferenced to reach it.
+                self.visit_place(**place, Mutability::Mut);
+            }
             // TODO(spernsteiner): handle other `StatementKind`s
             _ => (),
         }
     }
 
     pub fn visit_terminator(&mut self, term: &Terminator<'tcx>, loc: Location) {
-        eprintln!("visit_terminator({:?})", term.kind);
-        let tcx = self.acx.tcx();
+        trace!("visit_terminator({:?})", term.kind);
         let _g = panic_detail::set_current_span(term.source_info.span);
         // TODO(spernsteiner): other `TerminatorKind`s will be handled in the future
         #[allow(clippy::single_match)]
@@ -418,7 +477,6 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 target: _,
                 ..
             } => {
-                let func = func.ty(self.mir, tcx);
                 self.visit_call(loc, func, args, destination);
             }
             // TODO(spernsteiner): handle other `TerminatorKind`s
@@ -429,13 +487,13 @@ impl<'tcx> TypeChecker<'tcx, '_> {
     pub fn visit_call(
         &mut self,
         loc: Location,
-        func: Ty<'tcx>,
+        func: &Operand<'tcx>,
         args: &[Operand<'tcx>],
         destination: Place<'tcx>,
     ) {
         let tcx = self.acx.tcx();
-        let callee = ty_callee(tcx, func);
-        eprintln!("callee = {callee:?}");
+        let callee = ty_callee(tcx, func.ty(self.mir, tcx));
+        trace!("callee = {callee:?}");
         match callee {
             Callee::Trivial => {}
             Callee::LocalDef { def_id, substs } => {
@@ -446,10 +504,14 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 def_id,
                 substs,
                 is_foreign: true,
-            }) if self.acx.gacx.known_fn(def_id).is_some() => {
-                // As this is actually a known `fn`, we can treat it as a normal local call.
+            }) if self.acx.gacx.has_known_perms(def_id) => {
+                // As this is actually a known or summarized `fn`, we can treat it as a normal local
+                // call.
                 self.visit_local_call(def_id, substs, args, destination);
             }
+            Callee::UnknownDef(UnknownDefCallee::Indirect { .. }) => {
+                self.visit_fn_ptr_call(func, args, destination);
+            }
             Callee::UnknownDef(_) => {
                 log::error!("TODO: visit Callee::{callee:?}");
             }
@@ -463,6 +525,8 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 self.do_assign(pl_lty, rv_lty);
                 let perms = PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB;
                 self.constraints.add_all_perms(rv_lty.label, perms);
+                // The offset itself may be read from a field through a pointer.
+                self.visit_operand(&args[1]);
             }
 
             Callee::SliceAsPtr { elem_ty, .. } => {
@@ -474,11 +538,16 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 self.visit_operand(&args[0]);
                 let rv_lty = self.acx.type_of(&args[0]);
 
-                // Map `rv_lty = &[i32]` to `rv_elem_lty = i32`
+                // Map `rv_lty = &[i32]` or `&Vec<i32>` to `rv_elem_lty = i32`
                 let rv_pointee_lty = rv_lty.args[0];
                 let rv_elem_lty = match *rv_pointee_lty.kind() {
                     TyKind::Array(..) | TyKind::Slice(..) => rv_pointee_lty.args[0],
-                    TyKind::Str => self.acx.lcx().label(elem_ty, &mut |_| PointerId::NONE),
+                    // `Vec<T>`: the element type is the first type argument.
+                    TyKind::Adt(..) if rv_pointee_lty.args.len() > 0 => rv_pointee_lty.args[0],
+                    // `str` and `CStr`: the element type is `u8`, which has no pointers.
+                    TyKind::Str | TyKind::Adt(..) => {
+                        self.acx.lcx().label(elem_ty, &mut |_| PointerId::NONE)
+                    }
                     _ => unreachable!(),
                 };
 
@@ -521,7 +590,66 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 let perms = PermissionSet::FREE;
                 self.constraints.add_all_perms(rv_lty.label, perms);
             }
-            Callee::Memcpy => {
+            Callee::Strdup | Callee::Strndup => {
+                self.visit_place(destination, Mutability::Mut);
+                let pl_lty = self.acx.type_of(destination);
+                let src_ptr = args[0]
+                    .place()
+                    .expect("Casts to/from null pointer are not yet supported");
+                self.visit_place(src_ptr, Mutability::Not);
+                let rv_lty = self.acx.type_of(src_ptr);
+
+                // input needs READ permission, and is read as a slice up to its NUL
+                let perms = PermissionSet::READ | PermissionSet::OFFSET_ADD;
+                self.constraints.add_all_perms(rv_lty.label, perms);
+
+                // The result is a new allocation holding a copy of the string, which is a slice
+                // like the input.
+                self.constraints
+                    .add_all_perms(pl_lty.label, PermissionSet::OFFSET_ADD);
+            }
+            Callee::Sprintf | Callee::Snprintf => {
+                self.visit_place(destination, Mutability::Mut);
+                let dest_ptr = args[0]
+                    .place()
+                    .expect("Casts to/from null pointer are not yet supported");
+                self.visit_place(dest_ptr, Mutability::Mut);
+                let dest_lty = self.acx.type_of(dest_ptr);
+
+                // output needs WRITE permission, and is written as a slice
+                let perms = PermissionSet::WRITE | PermissionSet::OFFSET_ADD;
+                self.constraints.add_all_perms(dest_lty.label, perms);
+
+                // The format string and any pointer arguments, which are printed with `%s`, are
+                // read as strings.
+                for arg in &args[1..] {
+                    self.visit_operand(arg);
+                    let arg_lty = self.acx.type_of(arg);
+                    if !arg_lty.label.is_none() {
+                        let perms = PermissionSet::READ | PermissionSet::OFFSET_ADD;
+                        self.constraints.add_all_perms(arg_lty.label, perms);
+                    }
+                }
+            }
+            Callee::Printf | Callee::Fprintf => {
+                self.visit_place(destination, Mutability::Mut);
+                // The stream passed to `fprintf` is only used by the foreign function, and the
+                // format string and the strings printed with `%s` are read as by `sprintf`.
+                let fmt_idx = if matches!(callee, Callee::Printf) {
+                    0
+                } else {
+                    1
+                };
+                for (i, arg) in args.iter().enumerate() {
+                    self.visit_operand(arg);
+                    let arg_lty = self.acx.type_of(arg);
+                    if i >= fmt_idx && !arg_lty.label.is_none() {
+                        let perms = PermissionSet::READ | PermissionSet::OFFSET_ADD;
+                        self.constraints.add_all_perms(arg_lty.label, perms);
+                    }
+                }
+            }
+            Callee::Memcpy | Callee::Memmove => {
                 let out_ptr = destination;
 
                 let dest_ptr = args[0]
@@ -542,7 +670,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                         maybe_offset_perm = PermissionSet::empty();
                     }
                 }
-                eprintln!("memcpy at {:?} needs offset? {:?}", loc, maybe_offset_perm);
+                trace!("memcpy at {:?} needs offset? {:?}", loc, maybe_offset_perm);
 
                 // input needs WRITE permission
                 let perms = PermissionSet::WRITE | maybe_offset_perm;
@@ -567,6 +695,114 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 let src_ptr_lty = self.acx.type_of(src_ptr);
                 self.do_equivalence_nested(dest_ptr_lty.args[0], src_ptr_lty.args[0]);
             }
+            Callee::Memcmp => {
+                self.visit_place(destination, Mutability::Mut);
+                assert!(args.len() == 3);
+                let lhs_ptr = args[0]
+                    .place()
+                    .expect("Casts to/from null pointer are not yet supported");
+                let rhs_ptr = args[1]
+                    .place()
+                    .expect("Casts to/from null pointer are not yet supported");
+                self.visit_place(lhs_ptr, Mutability::Not);
+                self.visit_place(rhs_ptr, Mutability::Not);
+                let lhs_lty = self.acx.type_of(lhs_ptr);
+                let rhs_lty = self.acx.type_of(rhs_ptr);
+
+                // Figure out whether we're comparing one element or (possibly) several.
+                let mut maybe_offset_perm = PermissionSet::OFFSET_ADD;
+                if let Some(pointee_lty) = self.pointee_types[lhs_lty.label].get_sole_lty() {
+                    if self.operand_is_size_of_t(loc, &args[2], pointee_lty.ty) {
+                        maybe_offset_perm = PermissionSet::empty();
+                    }
+                }
+                trace!("memcmp at {:?} needs offset? {:?}", loc, maybe_offset_perm);
+
+                // both inputs need READ permission
+                let perms = PermissionSet::READ | maybe_offset_perm;
+                self.constraints.add_all_perms(lhs_lty.label, perms);
+                self.constraints.add_all_perms(rhs_lty.label, perms);
+            }
+            Callee::Memchr => {
+                self.visit_place(destination, Mutability::Mut);
+                let pl_lty = self.acx.type_of(destination);
+                assert!(args.len() == 3);
+                let src_ptr = args[0]
+                    .place()
+                    .expect("Casts to/from null pointer are not yet supported");
+                self.visit_place(src_ptr, Mutability::Not);
+                let rv_lty = self.acx.type_of(src_ptr);
+
+                // The result points somewhere into the searched buffer, so it flows from the
+                // input like the result of `offset`.  It's null if the byte isn't found, which
+                // shouldn't make the input nullable as well.
+                self.do_assign_pointer_ids_except(
+                    pl_lty.label,
+                    rv_lty.label,
+                    PermissionSet::NON_NULL,
+                );
+                self.constraints
+                    .add_no_perms(pl_lty.label, PermissionSet::NON_NULL);
+
+                // input needs READ permission, and is always searched as a slice
+                let perms = PermissionSet::READ | PermissionSet::OFFSET_ADD;
+                self.constraints.add_all_perms(rv_lty.label, perms);
+            }
+            Callee::Qsort => {
+                self.visit_place(destination, Mutability::Mut);
+                assert!(args.len() == 4);
+                let base_ptr = args[0]
+                    .place()
+                    .expect("Casts to/from null pointer are not yet supported");
+                self.visit_place(base_ptr, Mutability::Not);
+                let base_lty = self.acx.type_of(base_ptr);
+
+                // The elements are compared and swapped in place, and the base is always sorted
+                // as a slice.
+                let perms = PermissionSet::READ | PermissionSet::WRITE | PermissionSet::OFFSET_ADD;
+                self.constraints.add_all_perms(base_lty.label, perms);
+
+                // The comparator is visited like any other operand.  Its signature is unified
+                // with the function pointer type where the function is converted to a pointer.
+                for arg in &args[1..] {
+                    self.visit_operand(arg);
+                }
+            }
+            Callee::Bsearch => {
+                self.visit_place(destination, Mutability::Mut);
+                let pl_lty = self.acx.type_of(destination);
+                assert!(args.len() == 5);
+                let key_ptr = args[0]
+                    .place()
+                    .expect("Casts to/from null pointer are not yet supported");
+                let base_ptr = args[1]
+                    .place()
+                    .expect("Casts to/from null pointer are not yet supported");
+                self.visit_place(key_ptr, Mutability::Not);
+                self.visit_place(base_ptr, Mutability::Not);
+                let key_lty = self.acx.type_of(key_ptr);
+                let base_lty = self.acx.type_of(base_ptr);
+
+                // As with `memchr`, the result points into the searched buffer, and it's null if
+                // no element matches.
+                self.do_assign_pointer_ids_except(
+                    pl_lty.label,
+                    base_lty.label,
+                    PermissionSet::NON_NULL,
+                );
+                self.constraints
+                    .add_no_perms(pl_lty.label, PermissionSet::NON_NULL);
+
+                // The key is read by the comparator, and the buffer is always searched as a slice.
+                self.constraints
+                    .add_all_perms(key_lty.label, PermissionSet::READ);
+                let perms = PermissionSet::READ | PermissionSet::OFFSET_ADD;
+                self.constraints.add_all_perms(base_lty.label, perms);
+
+                for arg in &args[2..] {
+                    self.visit_operand(arg);
+                }
+            }
             Callee::Memset => {
                 let dest_ptr = args[0]
                     .place()
@@ -586,7 +822,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                         maybe_offset_perm = PermissionSet::empty();
                     }
                 }
-                eprintln!("memset at {:?} needs offset? {:?}", loc, maybe_offset_perm);
+                trace!("memset at {:?} needs offset? {:?}", loc, maybe_offset_perm);
 
                 let perms = PermissionSet::WRITE | maybe_offset_perm;
                 self.constraints.add_all_perms(rv_lty.label, perms);
@@ -611,9 +847,42 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 self.constraints
                     .add_no_perms(pl_lty.label, PermissionSet::NON_NULL);
             }
+            Callee::OptionFnPtrUnwrap => {
+                // Unwrapping `Option<fn(..)>` passes the function pointer through unchanged, like
+                // an assignment.
+                self.visit_operand(&args[0]);
+                self.visit_place(destination, Mutability::Mut);
+                let pl_lty = self.acx.type_of(destination);
+                let rv_lty = self.acx.type_of(&args[0]);
+                self.do_assign(pl_lty, rv_lty.args[0]);
+            }
         }
     }
 
+    /// Visit a call through a function pointer.  The labeled type of the pointer serves as the
+    /// signature; its pointers are unified with those of each function converted to that pointer
+    /// type (see `visit_cast`), so the call is treated like a call to any of them.
+    fn visit_fn_ptr_call(
+        &mut self,
+        func: &Operand<'tcx>,
+        args: &[Operand<'tcx>],
+        dest: Place<'tcx>,
+    ) {
+        self.visit_operand(func);
+        let func_lty = self.acx.type_of(func);
+        let (&output_lty, input_ltys) = func_lty.args.split_last().unwrap();
+
+        for (arg_op, &input_lty) in args.iter().zip(input_ltys.iter()) {
+            self.visit_operand(arg_op);
+            let arg_lty = self.acx.type_of(arg_op);
+            self.do_assign(input_lty, arg_lty);
+        }
+
+        self.visit_place(dest, Mutability::Mut);
+        let dest_lty = self.acx.type_of(dest);
+        self.do_assign(dest_lty, output_lty);
+    }
+
     /// Visit a local call, where local means
     /// local to the current crate with a static, known definition.
     ///
@@ -0,0 +1,124 @@
+//! Policies for rewriting `free` calls on pointers that may not own their allocation.
+//!
+//! A pointer passed to `free` gets the `FREE` permission, but the analysis can't prove that it
+//! still owns its allocation when `free` is called, since ownership may have moved to another
+//! pointer in the meantime.  Such pointers are rewritten to `DynOwned<Box<T>>`, which tracks
+//! ownership at runtime.  The [`FreePolicy`] chosen for each `free` call decides how the call is
+//! rewritten.
+//!
+//! `C2RUST_ANALYZE_FREE_POLICY` is a comma-separated list of entries.  An entry `POLICY` sets the
+//! default policy, and an entry `SITE=POLICY` overrides it for one site.  A `SITE` is either the
+//! path of a function, such as `foo::bar`, which covers every `free` call in that function, or a
+//! path followed by `:LINE`, which covers only the calls on that line.  The policy chosen for each
+//! site is recorded and printed, in the same `SITE` format, at the end of the analysis.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum FreePolicy {
+    /// Unwrap the `DynOwned` and drop the `Box`.  This panics at runtime if the pointer doesn't
+    /// own its allocation.  This is the default.
+    DynOwned,
+    /// Leave the function containing the call unrewritten.
+    Skip,
+    /// Keep the call to `free`, passing it the raw pointer from `Box::into_raw`, or null if the
+    /// pointer doesn't own its allocation.  This releases memory allocated by `Box` through the C
+    /// allocator, which is sound only if the two allocators agree.
+    Unsafe,
+}
+
+impl FromStr for FreePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FreePolicy, String> {
+        match s {
+            "dyn-owned" => Ok(FreePolicy::DynOwned),
+            "skip" => Ok(FreePolicy::Skip),
+            "unsafe" => Ok(FreePolicy::Unsafe),
+            _ => Err(format!("unknown free policy {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for FreePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            FreePolicy::DynOwned => "dyn-owned",
+            FreePolicy::Skip => "skip",
+            FreePolicy::Unsafe => "unsafe",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Default)]
+struct Config {
+    default: Option<FreePolicy>,
+    sites: HashMap<String, FreePolicy>,
+}
+
+thread_local! {
+    static CONFIG: RefCell<Config> = RefCell::new(Config::default());
+    /// The policy chosen for each site, keyed by `fn_path:line`.
+    static DECISIONS: RefCell<BTreeMap<String, FreePolicy>> = RefCell::new(BTreeMap::new());
+}
+
+/// Read the `C2RUST_ANALYZE_FREE_POLICY` setting.
+pub fn init() {
+    let mut config = Config::default();
+    if let Ok(val) = env::var("C2RUST_ANALYZE_FREE_POLICY") {
+        // Empty entries are allowed, as in `C2RUST_ANALYZE_REWRITE_PATHS`.
+        for entry in val.split(',').filter(|entry| !entry.is_empty()) {
+            let parse = |s: &str| {
+                s.parse::<FreePolicy>().unwrap_or_else(|e| {
+                    panic!("bad C2RUST_ANALYZE_FREE_POLICY entry {entry:?}: {e}")
+                })
+            };
+            match entry.rsplit_once('=') {
+                Some((site, policy)) => {
+                    config.sites.insert(site.to_owned(), parse(policy));
+                }
+                None => config.default = Some(parse(entry)),
+            }
+        }
+    }
+    CONFIG.with(|cell| *cell.borrow_mut() = config);
+    DECISIONS.with(|cell| cell.borrow_mut().clear());
+}
+
+/// Choose the policy for a `free` call on line `line` of the function at `fn_path`, and record the
+/// decision for [`report`].  An override for the line takes precedence over one for the whole
+/// function, which takes precedence over the default.
+pub fn decide(fn_path: &str, line: usize) -> FreePolicy {
+    let site = format!("{fn_path}:{line}");
+    let policy = CONFIG.with(|cell| {
+        let config = cell.borrow();
+        config
+            .sites
+            .get(&site)
+            .or_else(|| config.sites.get(fn_path))
+            .copied()
+            .or(config.default)
+            .unwrap_or(FreePolicy::DynOwned)
+    });
+    DECISIONS.with(|cell| cell.borrow_mut().insert(site, policy));
+    policy
+}
+
+/// Print the policy chosen for each `free` site.
+pub fn report() {
+    DECISIONS.with(|cell| {
+        let decisions = cell.borrow();
+        if decisions.is_empty() {
+            return;
+        }
+        eprintln!("\nfree policy decisions:");
+        for (site, policy) in decisions.iter() {
+            eprintln!("  {site}: {policy}");
+        }
+    });
+}
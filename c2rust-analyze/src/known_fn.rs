@@ -1322,6 +1322,11 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 ct: *const c_char: [READ | OFFSET_ADD | NON_NULL],
             ) -> c_int;
 
+            fn strcpy(
+                dest: *mut c_char: [READ | WRITE | OFFSET_ADD | NON_NULL],
+                src: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+            ) -> *mut c_char: [READ | WRITE | OFFSET_ADD];
+
             fn strcspn(
                 cs: *const c_char: [READ | OFFSET_ADD | NON_NULL],
                 ct: *const c_char: [READ | OFFSET_ADD | NON_NULL],
@@ -1360,6 +1365,17 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 n: size_t,
             ) -> c_int;
 
+            fn strncpy(
+                dest: *mut c_char: [READ | WRITE | OFFSET_ADD | NON_NULL],
+                src: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                n: size_t,
+            ) -> *mut c_char: [READ | WRITE | OFFSET_ADD];
+
+            fn strndup(
+                cs: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                n: size_t,
+            ) -> *mut c_char: [READ | WRITE | OFFSET_ADD | FREE];
+
             fn strrchr(
                 // `WRITE` because the return type is derived from `cs`'s provenance.
                 cs: *const c_char: [READ | WRITE | OFFSET_ADD | NON_NULL],
@@ -0,0 +1,57 @@
+#![feature(rustc_private)]
+//! Library interface to `c2rust-analyze`.
+//!
+//! The `c2rust-analyze` binary is a thin `cargo`/`rustc` wrapper around this crate.  External
+//! tools can use [`api::analyze_crate`] to run the analysis on a crate programmatically and
+//! inspect the results without applying any rewrites.
+extern crate either;
+extern crate rustc_arena;
+extern crate rustc_ast;
+extern crate rustc_const_eval;
+extern crate rustc_data_structures;
+extern crate rustc_driver;
+extern crate rustc_hir;
+extern crate rustc_index;
+extern crate rustc_interface;
+extern crate rustc_middle;
+extern crate rustc_mir_build;
+extern crate rustc_session;
+extern crate rustc_span;
+extern crate rustc_target;
+extern crate rustc_type_ir;
+
+mod analyze;
+mod annotate;
+pub mod api;
+mod arc_mutex;
+mod assignment_file;
+mod borrowck;
+mod c_strings;
+pub mod cfg_sets;
+mod const_prop;
+mod context;
+mod dataflow;
+mod equiv;
+mod free_policy;
+mod known_fn;
+mod labeled_ty;
+mod log;
+mod panic_detail;
+mod pointee_type;
+mod pointer_id;
+mod profile;
+mod progress;
+mod rc_refcell;
+mod realloc_vecs;
+mod recent_writes;
+mod returned_slice_len;
+mod rewrite;
+mod summary;
+mod trivial;
+mod type_desc;
+mod util;
+
+pub use crate::analyze::AnalysisCallbacks;
+pub use crate::api::resolve_sysroot;
+pub use crate::log::init_logger;
+pub use crate::panic_detail::set_hook as set_panic_hook;
@@ -1,3 +1,4 @@
+use std::env;
 use std::fmt::{self, Display, Formatter};
 use std::io::Write;
 
@@ -29,11 +30,21 @@ impl Display for DisplayRecord<'_> {
 ///
 /// so by default, `log::error!` panics,
 /// but setting `RUST_LOG_PANIC=off` turns them into just being logged.
+///
+/// The per-statement traces of the MIR visitors are logged at the `trace` level,
+/// so they are hidden by default.  Setting `$C2RUST_ANALYZE_LOG_MIR` (as `--log-mir` does)
+/// enables them for all of `c2rust_analyze`, in addition to the filters in `$RUST_LOG`.
 pub fn init_logger() {
-    let log_env = Env::default().default_filter_or(LevelFilter::Debug.as_str());
+    let mut log_filters =
+        env::var("RUST_LOG").unwrap_or_else(|_| LevelFilter::Debug.as_str().to_owned());
+    if env::var_os("C2RUST_ANALYZE_LOG_MIR").is_some() {
+        log_filters.push_str(",c2rust_analyze=trace");
+    }
     let panic_env = Env::default().filter_or("RUST_LOG_PANIC", LevelFilter::Error.as_str());
 
-    let log_logger = env_logger::Builder::from_env(log_env).build();
+    let log_logger = env_logger::Builder::new()
+        .parse_filters(&log_filters)
+        .build();
     let panic_logger = env_logger::Builder::from_env(panic_env).build();
 
     // Create the actual [`Logger`] to log everything ([`LevelFilter::max()]`),
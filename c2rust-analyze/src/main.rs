@@ -1,43 +1,12 @@
 #![feature(rustc_private)]
-extern crate either;
-extern crate rustc_arena;
-extern crate rustc_ast;
-extern crate rustc_const_eval;
-extern crate rustc_data_structures;
 extern crate rustc_driver;
-extern crate rustc_hir;
-extern crate rustc_index;
-extern crate rustc_interface;
-extern crate rustc_middle;
-extern crate rustc_mir_build;
 extern crate rustc_session;
-extern crate rustc_span;
-extern crate rustc_target;
-extern crate rustc_type_ir;
-
-mod analyze;
-mod annotate;
-mod borrowck;
-mod context;
-mod dataflow;
-mod equiv;
-mod known_fn;
-mod labeled_ty;
-mod log;
-mod panic_detail;
-mod pointee_type;
-mod pointer_id;
-mod recent_writes;
-mod rewrite;
-mod trivial;
-mod type_desc;
-mod util;
-
-use crate::log::init_logger;
-use analyze::AnalysisCallbacks;
+
 use anyhow::anyhow;
-use anyhow::ensure;
-use anyhow::Context;
+use c2rust_analyze::cfg_sets;
+use c2rust_analyze::init_logger;
+use c2rust_analyze::resolve_sysroot;
+use c2rust_analyze::AnalysisCallbacks;
 use clap::{ArgAction, Parser, ValueEnum};
 use rustc_driver::RunCompiler;
 use rustc_driver::TimePassesCallbacks;
@@ -97,6 +66,99 @@ struct Args {
     #[clap(long)]
     use_manual_shims: bool,
 
+    /// Don't trust the pointers passed to `#[no_mangle]` and other exported functions by their
+    /// external callers.  With the default `POLICY`, `check`, those pointers are never assumed to
+    /// be non-null, so they're rewritten to `Option`s, and shims check every raw pointer for null
+    /// before converting it to a reference, instead of assuming it's valid.  With `fixed`, the
+    /// pointer arguments of exported functions are left as raw pointers.  In both cases, a shim
+    /// never converts a raw pointer to a slice, since its length is unknown.  Use as
+    /// `--untrusted-extern-inputs` or `--untrusted-extern-inputs=POLICY`.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        require_equals(true),
+        default_missing_value("check")
+    )]
+    untrusted_extern_inputs: Option<UntrustedInputPolicy>,
+
+    /// Rewrite owned, NUL-terminated character buffers to `CString` and borrowed ones to `&CStr`,
+    /// instead of to `Box<[c_char]>` and `&[c_char]`.  This applies only to strings that are never
+    /// written, dereferenced, or offset, and whose only uses are copies, calls to `strdup`,
+    /// `strlen`, `strcmp`, and `free`, and calls to other functions.  Strings are converted to and
+    /// from raw pointers at FFI boundaries.
+    #[clap(long)]
+    c_strings: bool,
+
+    /// Rewrite calls to `printf`, and to `fprintf` on `stdout` or `stderr`, that have constant
+    /// format strings into safe formatting with `format_args!`, with the output written to
+    /// `std::io::stdout()` or `std::io::stderr()`.  Note that these streams are buffered
+    /// separately from those of the C library, so the output of rewritten calls may be reordered
+    /// relative to that of remaining C calls that print to the same stream.
+    #[clap(long)]
+    rewrite_printf: bool,
+
+    /// Rewrite structs that implement a growable array with a pointer, a length, and a capacity
+    /// into structs holding a `Vec`, when the struct has a `push` function that grows the array
+    /// with `realloc` and its fields are otherwise used only in ways that a `Vec` supports.
+    #[clap(long)]
+    rewrite_vecs: bool,
+
+    /// Rewrite owned buffers that are resized with `realloc` to `Vec<T>` instead of to `Box<[T]>`.
+    /// Each `realloc` then becomes a `resize_with` of the `Vec` in place, instead of a copy into a
+    /// new `Box<[T]>`.  Buffers that are copied to or from a resized one become `Vec<T>` too.
+    #[clap(long)]
+    realloc_vecs: bool,
+
+    /// Rewrite heap objects that are mutated while other pointers alias them to `Rc<RefCell<T>>`,
+    /// instead of leaving the functions that use them unrewritten.  Each access through one of
+    /// these pointers borrows the `RefCell` for the rest of the statement.
+    #[clap(long)]
+    rc_refcell: bool,
+
+    /// Rewrite heap objects that are passed to a new thread with `pthread_create` to
+    /// `Arc<Mutex<T>>`, shared by the spawning thread and the thread's start routine.  Each access
+    /// through one of these pointers locks the `Mutex` for the rest of the statement.
+    #[clap(long)]
+    arc_mutex: bool,
+
+    /// Turn destructor functions, which free some pointer fields of a struct and then the struct
+    /// itself, into `Drop` impls on the struct.  Calls to a destructor in rewritten code then drop
+    /// the struct instead.
+    #[clap(long)]
+    drop_impls: bool,
+
+    /// Rewrite `static mut` items that are used by only one function, as produced for C
+    /// function-local `static` variables, into atomics declared inside that function.  Only integer
+    /// and `bool` statics are rewritten.
+    #[clap(long)]
+    rewrite_local_statics: bool,
+
+    /// Rewrite `memcpy`, `memset`, and `realloc` calls on slices into calls to generic helper
+    /// functions, instead of expanding each call inline.  The helpers are defined in a
+    /// `c2rust_helpers` module that is added to the end of the crate root.
+    #[clap(long)]
+    helper_module: bool,
+
+    /// Rewrite single-object `malloc` calls whose pointee type has no zero value to
+    /// allocate an uninitialized `Box<MaybeUninit<T>>`, when the allocation is fully written
+    /// immediately afterward.  The write stores the value without dropping the uninitialized
+    /// contents.  Without this option, such calls are left unrewritten.
+    #[clap(long)]
+    malloc_uninit: bool,
+
+    /// How to rewrite a `free` call whose argument may not own its allocation, which the rewritten
+    /// code checks at runtime.  With the default `POLICY`, `dyn-owned`, the call drops the
+    /// pointer's `Box`, panicking if the pointer doesn't own its allocation.  With `skip`, the
+    /// function containing the call is left unrewritten.  With `unsafe`, the call to `free` is
+    /// kept and passed the raw pointer from `Box::into_raw`, or null if the pointer doesn't own
+    /// its allocation.  Use as `--free-policy=POLICY` to set the default, or as
+    /// `--free-policy=SITE=POLICY` to override it for the `free` calls in one function, given by
+    /// its path, or on one line of a function, given as `PATH:LINE`.  The policy chosen for each
+    /// site is printed at the end of the analysis.
+    #[clap(long, action(ArgAction::Append), value_name = "[SITE=]POLICY")]
+    free_policy: Vec<String>,
+
     /// Read a list of defs that should be marked non-rewritable (`FIXED`) from this file path.
     /// Run `c2rust-analyze` without this option and check the debug output for a full list of defs
     /// in the crate being analyzed; the file passed to this option should list a subset of those
@@ -104,10 +166,80 @@ struct Args {
     #[clap(long)]
     fixed_defs_list: Option<PathBuf>,
 
+    /// Write the inferred permissions and flags of every pointer to this file path after the
+    /// analysis reaches a fixpoint.  The file can be edited by hand and passed back in with
+    /// `--load-assignment`.
+    #[clap(long)]
+    dump_assignment: Option<PathBuf>,
+
+    /// Skip the dataflow and borrowck analysis, and instead read the permissions and flags of
+    /// every pointer from this file path, which should have been produced by `--dump-assignment`
+    /// on the same input.  Only the rewrite phase is run with the loaded assignment.
+    #[clap(long)]
+    load_assignment: Option<PathBuf>,
+
+    /// Write a JSON report of the statically inferred permissions of each pointer-typed local of
+    /// each function to this file path.  Pass the report to `c2rust-pdg --compare-static` to list
+    /// the pointers whose dynamic behavior contradicts the static analysis.
+    #[clap(long)]
+    dump_local_perms: Option<PathBuf>,
+
+    /// Share the permission summaries of exported functions between the crates of a workspace
+    /// through this directory.  Each crate writes the summaries of its `#[no_mangle]` functions
+    /// here, and calls to `extern "C"` declarations of summarized functions in the crates
+    /// analyzed after it are constrained by them.  Since `cargo` builds dependencies first,
+    /// analyzing a whole workspace with `build --workspace` propagates the summaries downstream.
+    #[clap(long)]
+    summary_dir: Option<PathBuf>,
+
+    /// Log every MIR statement, rvalue, and constraint visited by the analysis and rewriter.
+    /// These traces are hidden by default, as they slow down the analysis of large crates.  They
+    /// can also be enabled for individual modules through `$RUST_LOG`, such as
+    /// `RUST_LOG=c2rust_analyze::dataflow=trace`.
+    #[clap(long)]
+    log_mir: bool,
+
+    /// Analyze the crate once under each of these sets of `cfg` options before rewriting, for
+    /// crates with `#[cfg]`-gated function bodies.  Each set is a comma-separated list of `rustc
+    /// --cfg` specs, such as `unix,feature="foo"`; an empty set selects the default
+    /// configuration.  Functions whose rewrites are the same under every set that compiles them
+    /// are rewritten as usual, under the first set.  Functions whose rewrites differ, and
+    /// functions that the first set doesn't compile, are reported and left unrewritten.
+    #[clap(long, action(ArgAction::Append))]
+    cfg_set: Vec<String>,
+
+    /// Report the time taken and the number of constraints generated by each pass of the analysis
+    /// (pointee types, dataflow, borrowck, `mir_op`, and HIR rewriting), followed by the `N`
+    /// slowest functions (10 by default).  Use as `--profile` or `--profile=N`.
+    #[clap(long, value_name = "N", require_equals(true))]
+    profile: Option<Option<usize>>,
+
+    /// Emit `expect("...")` instead of `unwrap()` where the rewritten code must unwrap a nullable or
+    /// possibly-moved pointer, so that a panic names the expression that caused it.  The optional
+    /// `FORMAT` is the message to use: `{expr}` is replaced with the source text of the unwrapped
+    /// expression, and `{loc}` with its `file:line:col` location.  Use as `--expect-message` or
+    /// `--expect-message=FORMAT`.
+    #[clap(
+        long,
+        value_name = "FORMAT",
+        require_equals(true),
+        default_missing_value(DEFAULT_EXPECT_MESSAGE)
+    )]
+    expect_message: Option<String>,
+
+    /// Don't print progress status lines, which otherwise appear every few seconds during long
+    /// passes of the analysis, or the listings of defs, statics, and equivalence classes printed
+    /// for debugging.  Errors and the final labeling are still printed.
+    #[clap(long)]
+    quiet: bool,
+
     /// `cargo` args.
     cargo_args: Vec<OsString>,
 }
 
+/// The default format for `--expect-message`.
+const DEFAULT_EXPECT_MESSAGE: &str = "unexpected null or moved pointer `{expr}` at {loc}";
+
 /// `cargo` args that we intercept.
 #[derive(Debug, Parser)]
 #[clap(ignore_errors = true)]
@@ -136,63 +268,18 @@ enum RewriteMode {
     Pointwise,
 }
 
-fn exit_with_status(status: ExitStatus) {
-    process::exit(status.code().unwrap_or(1))
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum UntrustedInputPolicy {
+    /// Treat pointer arguments as nullable, and check them for null in shims.
+    #[value(name = "check")]
+    Check,
+    /// Keep pointer arguments as raw pointers.
+    #[value(name = "fixed")]
+    Fixed,
 }
 
-/// Resolve the current `rustc` sysroot using `rustc --print sysroot`.
-///
-/// Normally, `rustc` looks up the sysroot by the location of its own binary.
-/// This works because the `rustc` on `$PATH` is actually `rustup`,
-/// and `rustup` invokes the real `rustc`, which is in a location relative to the sysroot.
-/// As we invoke `rustc_driver` directly here, we are `rustc`,
-/// and thus we have to explicitly specify the sysroot that the real `rustc` would normally use.
-///
-/// Note that the sysroot contains the toolchain and host target name,
-/// but this has no effect on cross-compiling.
-/// Every toolchain's `rustc` is able to itself cross-compile.
-/// It's unclear why the host target needs to be in the sysroot directory name, but it is.
-///
-/// Also note that this sysroot lookup should be done at runtime,
-/// not at compile-time in the `build.rs`,
-/// as the toolchain locations could be different
-/// from where this binary was compiled and where it is running
-/// (it could be on a different machine with a different `$RUSTUP_HOME`).
-///
-/// TODO(kkysen) deduplicate this with `c2rust_build_paths::SysRoot::resolve`
-fn resolve_sysroot() -> anyhow::Result<PathBuf> {
-    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
-    let output = Command::new(rustc)
-        .args(&["--print", "sysroot"])
-        .output()
-        .context("could not invoke `rustc` to find rust sysroot")?;
-    // trim, but `str::trim` doesn't exist on `[u8]`
-    let path = output
-        .stdout
-        .as_slice()
-        .split(|c| c.is_ascii_whitespace())
-        .next()
-        .unwrap_or_default();
-    #[cfg(unix)]
-    let path = {
-        use std::os::unix::ffi::OsStrExt;
-
-        OsStr::from_bytes(path)
-    };
-    #[cfg(not(unix))]
-    let path = {
-        // Windows is hard, so just require UTF-8
-        let path = std::str::from_utf8(path).context("`rustc --print sysroot` is not UTF-8")?;
-        OsStr::new(path)
-    };
-    let path = Path::new(path).to_owned();
-    // `rustc` reports a million errors if the sysroot is wrong, so try to check first.
-    ensure!(
-        path.is_dir(),
-        "invalid sysroot (not a dir): {}",
-        path.display()
-    );
-    Ok(path)
+fn exit_with_status(status: ExitStatus) {
+    process::exit(status.code().unwrap_or(1))
 }
 
 struct Cargo {
@@ -323,7 +410,7 @@ fn rustc_wrapper() -> anyhow::Result<()> {
     let result = if is_primary_compilation {
         let dont_catch = env::var_os("C2RUST_ANALYZE_TEST_DONT_CATCH_PANIC").is_some();
         if !dont_catch {
-            panic_detail::set_hook();
+            c2rust_analyze::set_panic_hook();
         }
 
         RunCompiler::new(&at_args, &mut AnalysisCallbacks).run()
@@ -390,10 +477,38 @@ fn cargo_wrapper(rustc_wrapper: &Path) -> anyhow::Result<()> {
         mut rewrite_mode,
         rewrite_in_place,
         use_manual_shims,
+        untrusted_extern_inputs,
+        c_strings,
+        rewrite_printf,
+        rewrite_vecs,
+        realloc_vecs,
+        rc_refcell,
+        arc_mutex,
+        drop_impls,
+        rewrite_local_statics,
+        helper_module,
+        malloc_uninit,
+        free_policy,
         fixed_defs_list,
+        dump_assignment,
+        load_assignment,
+        dump_local_perms,
+        summary_dir,
+        log_mir,
+        cfg_set,
+        profile,
+        expect_message,
+        quiet,
         cargo_args,
     } = Args::parse();
 
+    // `rustc` is run from the directory of each crate, so the summary directory must be absolute
+    // for all of them to share it.
+    let summary_dir = match summary_dir {
+        Some(dir) => Some(env::current_dir()?.join(dir)),
+        None => None,
+    };
+
     let args_for_cargo =
         iter::once(OsStr::new("cargo")).chain(cargo_args.iter().map(OsString::as_os_str));
     let InterceptedCargoArgs {
@@ -419,46 +534,200 @@ fn cargo_wrapper(rustc_wrapper: &Path) -> anyhow::Result<()> {
 
     let cargo = Cargo::new();
 
-    cargo.run(|cmd| {
-        let rustflags = [
-            env::var_os("RUSTFLAGS"),
-            Some("-A warnings".into()),
-            rustflags,
-        ]
-        .into_iter()
-        .flatten()
-        .join(OsStr::new(" "));
-
-        cmd.args(cargo_args)
-            .env(RUSTC_WRAPPER_VAR, rustc_wrapper)
-            .env(RUST_SYSROOT_VAR, &sysroot)
-            .env("RUSTFLAGS", &rustflags);
-
-        if let Some(ref fixed_defs_list) = fixed_defs_list {
-            cmd.env("C2RUST_ANALYZE_FIXED_DEFS_LIST", fixed_defs_list);
-        }
+    // Run the analysis with `cfgs` enabled.  Passes that only report the rewrites of each function
+    // for comparison across `cfg` sets set `rewrite` to `false`, which skips rewriting and the
+    // other outputs.
+    let run = |cfgs: &[&str], rewrite: bool, envs: &[(&str, &OsStr)]| {
+        cargo.run(|cmd| {
+            let cfg_flags = cfgs
+                .iter()
+                .map(|cfg| OsString::from(format!("--cfg {cfg}")))
+                .collect::<Vec<_>>();
+            let rustflags = [
+                env::var_os("RUSTFLAGS"),
+                Some("-A warnings".into()),
+                rustflags.clone(),
+            ]
+            .into_iter()
+            .flatten()
+            .chain(cfg_flags)
+            .join(OsStr::new(" "));
+
+            cmd.args(&cargo_args)
+                .env(RUSTC_WRAPPER_VAR, rustc_wrapper)
+                .env(RUST_SYSROOT_VAR, &sysroot)
+                .env("RUSTFLAGS", &rustflags)
+                .envs(envs.iter().copied());
+
+            if let Some(ref fixed_defs_list) = fixed_defs_list {
+                cmd.env("C2RUST_ANALYZE_FIXED_DEFS_LIST", fixed_defs_list);
+            }
 
-        if !rewrite_paths.is_empty() {
-            let rewrite_paths = rewrite_paths.join(OsStr::new(","));
-            cmd.env("C2RUST_ANALYZE_REWRITE_PATHS", rewrite_paths);
-        }
+            if let (true, Some(dump_assignment)) = (rewrite, &dump_assignment) {
+                cmd.env("C2RUST_ANALYZE_DUMP_ASSIGNMENT", dump_assignment);
+            }
 
-        if let Some(rewrite_mode) = rewrite_mode {
-            let val = match rewrite_mode {
-                RewriteMode::None => "none",
-                RewriteMode::InPlace => "inplace",
-                RewriteMode::Alongside => "alongside",
-                RewriteMode::Pointwise => "pointwise",
-            };
-            cmd.env("C2RUST_ANALYZE_REWRITE_MODE", val);
-        }
+            if let Some(ref load_assignment) = load_assignment {
+                cmd.env("C2RUST_ANALYZE_LOAD_ASSIGNMENT", load_assignment);
+            }
 
-        if use_manual_shims {
-            cmd.env("C2RUST_ANALYZE_USE_MANUAL_SHIMS", "1");
-        }
+            if let (true, Some(dump_local_perms)) = (rewrite, &dump_local_perms) {
+                cmd.env("C2RUST_ANALYZE_DUMP_LOCAL_PERMS", dump_local_perms);
+            }
 
-        Ok(())
-    })?;
+            if let Some(ref summary_dir) = summary_dir {
+                cmd.env("C2RUST_ANALYZE_SUMMARY_DIR", summary_dir);
+            }
+
+            if !rewrite_paths.is_empty() {
+                let rewrite_paths = rewrite_paths.join(OsStr::new(","));
+                cmd.env("C2RUST_ANALYZE_REWRITE_PATHS", rewrite_paths);
+            }
+
+            if let (true, Some(rewrite_mode)) = (rewrite, rewrite_mode) {
+                let val = match rewrite_mode {
+                    RewriteMode::None => "none",
+                    RewriteMode::InPlace => "inplace",
+                    RewriteMode::Alongside => "alongside",
+                    RewriteMode::Pointwise => "pointwise",
+                };
+                cmd.env("C2RUST_ANALYZE_REWRITE_MODE", val);
+            }
+
+            if use_manual_shims {
+                cmd.env("C2RUST_ANALYZE_USE_MANUAL_SHIMS", "1");
+            }
+
+            if let Some(policy) = untrusted_extern_inputs {
+                let val = match policy {
+                    UntrustedInputPolicy::Check => "check",
+                    UntrustedInputPolicy::Fixed => "fixed",
+                };
+                cmd.env("C2RUST_ANALYZE_UNTRUSTED_EXTERN_INPUTS", val);
+            }
+
+            if c_strings {
+                cmd.env("C2RUST_ANALYZE_C_STRINGS", "1");
+            }
+
+            if rewrite_printf {
+                cmd.env("C2RUST_ANALYZE_REWRITE_PRINTF", "1");
+            }
+
+            if rewrite_vecs {
+                cmd.env("C2RUST_ANALYZE_REWRITE_VECS", "1");
+            }
+
+            if realloc_vecs {
+                cmd.env("C2RUST_ANALYZE_REALLOC_VECS", "1");
+            }
+
+            if rc_refcell {
+                cmd.env("C2RUST_ANALYZE_RC_REFCELL", "1");
+            }
+
+            if arc_mutex {
+                cmd.env("C2RUST_ANALYZE_ARC_MUTEX", "1");
+            }
+
+            if drop_impls {
+                cmd.env("C2RUST_ANALYZE_DROP_IMPLS", "1");
+            }
+
+            if rewrite_local_statics {
+                cmd.env("C2RUST_ANALYZE_REWRITE_LOCAL_STATICS", "1");
+            }
+
+            if helper_module {
+                cmd.env("C2RUST_ANALYZE_HELPER_MODULE", "1");
+            }
+
+            if malloc_uninit {
+                cmd.env("C2RUST_ANALYZE_MALLOC_UNINIT", "1");
+            }
+
+            if !free_policy.is_empty() {
+                cmd.env("C2RUST_ANALYZE_FREE_POLICY", free_policy.join(","));
+            }
+
+            if log_mir {
+                cmd.env("C2RUST_ANALYZE_LOG_MIR", "1");
+            }
+
+            if let (true, Some(top_n)) = (rewrite, profile) {
+                cmd.env("C2RUST_ANALYZE_PROFILE", top_n.unwrap_or(10).to_string());
+            }
+
+            if let Some(ref format) = expect_message {
+                cmd.env("C2RUST_ANALYZE_EXPECT_MESSAGE", format);
+            }
+
+            if quiet {
+                cmd.env("C2RUST_ANALYZE_QUIET", "1");
+            }
+
+            Ok(())
+        })
+    };
+
+    let cfg_sets = cfg_set
+        .iter()
+        .map(|set| set.split(',').filter(|cfg| !cfg.is_empty()).collect())
+        .collect::<Vec<Vec<_>>>();
+    if cfg_sets.is_empty() {
+        return run(&[], true, &[]);
+    }
+
+    // Removed when dropped, including when one of the runs fails.
+    let report_dir = tempfile::Builder::new()
+        .prefix("c2rust-analyze-cfg-")
+        .tempdir()?;
+    let mut reports = Vec::with_capacity(cfg_sets.len());
+    for (i, cfgs) in cfg_sets.iter().enumerate() {
+        let dir = report_dir.path().join(i.to_string());
+        // Use a separate target directory for each set, so that `cargo` doesn't consider the
+        // crate fresh and skip the analysis.
+        let target_dir = dir.join("target");
+        run(
+            cfgs,
+            false,
+            &[
+                ("C2RUST_ANALYZE_CFG_REPORT", dir.as_os_str()),
+                ("CARGO_TARGET_DIR", target_dir.as_os_str()),
+            ],
+        )?;
+        reports.push(cfg_sets::read_fn_rewrites(&dir)?);
+    }
+
+    let divergent = cfg_sets::find_divergent_fns(&reports);
+    for f in &divergent {
+        let (a, b) = f.configs;
+        eprintln!(
+            "warning: rewrites of `{}` in crate `{}` differ between --cfg-set {:?} and {:?}; \
+             leaving it unrewritten",
+            f.def_path, f.crate_name, cfg_set[a], cfg_set[b],
+        );
+    }
+    // The final run compiles the crate under the first set only.
+    let missing = cfg_sets::find_missing_fns(&reports);
+    for f in &missing {
+        eprintln!(
+            "warning: `{}` in crate `{}` is compiled under --cfg-set {:?} but not {:?}; \
+             leaving it unrewritten",
+            f.def_path, f.crate_name, cfg_set[f.config], cfg_set[0],
+        );
+    }
+    let divergent_fns_path = report_dir.path().join("divergent_fns.txt");
+    cfg_sets::write_divergent_fns(&divergent_fns_path, &divergent)?;
+
+    run(
+        &cfg_sets[0],
+        true,
+        &[(
+            "C2RUST_ANALYZE_CFG_DIVERGENT_FNS",
+            divergent_fns_path.as_os_str(),
+        )],
+    )?;
 
     Ok(())
 }
@@ -1,13 +1,13 @@
 use super::constraint_set::{CTy, ConstraintSet};
 use crate::context::{AnalysisCtxt, LTy, PointerId};
 use crate::panic_detail;
-use crate::util::{describe_rvalue, ty_callee, Callee, RvalueDesc, UnknownDefCallee};
+use crate::util::{self, describe_rvalue, ty_callee, Callee, RvalueDesc, UnknownDefCallee};
 use log::*;
 use rustc_middle::mir::{
     BinOp, Body, Location, Operand, Place, PlaceRef, ProjectionElem, Rvalue, Statement,
     StatementKind, Terminator, TerminatorKind,
 };
-use rustc_middle::ty::{Ty, TyKind};
+use rustc_middle::ty::TyKind;
 
 struct TypeChecker<'tcx, 'a> {
     acx: &'a AnalysisCtxt<'a, 'tcx>,
@@ -64,6 +64,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
     pub fn visit_place_ref(&mut self, pl: PlaceRef<'tcx>) -> LTy<'tcx> {
         trace!("visit_place_ref({pl:?})");
         let mut lty = self.acx.type_of(pl.local);
+        let mut variant = None;
         for proj in pl.projection {
             match proj {
                 ProjectionElem::Deref => {
@@ -76,7 +77,8 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 }
                 _ => {}
             }
-            lty = self.acx.projection_lty(lty, proj);
+            lty = self.acx.projection_lty(lty, variant, proj);
+            variant = util::downcast_variant(proj);
         }
         debug_assert_eq!(lty, self.acx.type_of(pl));
         lty
@@ -141,10 +143,20 @@ impl<'tcx> TypeChecker<'tcx, '_> {
             }
             Rvalue::Aggregate(_, ref ops) => {
                 // FIXME: Needs dataflow edges between `ops` types and the rvalue's `lty`, similar
-                // to the corresponding case in `dataflow::type_check`.
+                // to the corresponding case in `dataflow::type_check`.  Only `#[repr(transparent)]`
+                // pointer wrappers are handled so far.
                 for op in ops {
                     self.visit_operand(op);
                 }
+
+                if let Some(field_lty) = self.acx.gacx.transparent_ptr_lty(lty) {
+                    // The wrapped pointer is the only operand with a `PointerId`; the rest are
+                    // `PhantomData`.
+                    for op in ops {
+                        let op_lty = self.acx.type_of(op);
+                        self.assign(field_lty.label, op_lty.label);
+                    }
+                }
             }
             Rvalue::ShallowInitBox(ref op, _) => self.visit_operand(op),
             Rvalue::CopyForDeref(pl) => {
@@ -183,6 +195,9 @@ impl<'tcx> TypeChecker<'tcx, '_> {
 
                 self.assign(pl_lty.label, rv_lty.label);
             }
+            StatementKind::SetDiscriminant { ref place, .. } => {
+                self.visit_place(**place);
+            }
             _ => (),
         }
     }
@@ -194,7 +209,6 @@ impl<'tcx> TypeChecker<'tcx, '_> {
             term.source_info.span
         );
         let _g = panic_detail::set_current_span(term.source_info.span);
-        let tcx = self.acx.tcx();
 
         match term.kind {
             TerminatorKind::Call {
@@ -209,17 +223,21 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 }
                 let dest_lty = self.visit_place(destination);
 
-                let func = func.ty(self.mir, tcx);
                 self.visit_call(func, args, dest_lty);
             }
             _ => (),
         }
     }
 
-    pub fn visit_call(&mut self, func: Ty<'tcx>, args: &[Operand<'tcx>], dest_lty: LTy<'tcx>) {
+    pub fn visit_call(
+        &mut self,
+        func: &Operand<'tcx>,
+        args: &[Operand<'tcx>],
+        dest_lty: LTy<'tcx>,
+    ) {
         let tcx = self.acx.tcx();
-        let callee = ty_callee(tcx, func);
-        eprintln!("callee = {callee:?}");
+        let callee = ty_callee(tcx, func.ty(self.mir, tcx));
+        trace!("callee = {callee:?}");
         match callee {
             Callee::Trivial => {}
             Callee::LocalDef { def_id, substs } => {
@@ -248,10 +266,21 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 def_id,
                 substs: _,
                 is_foreign: true,
-            }) if self.acx.gacx.known_fn(def_id).is_some() => {
+            }) if self.acx.gacx.has_known_perms(def_id) => {
                 // TODO: no good handling for this currently - might need to expand KnownFn to
                 // include information about expected/required pointee types
             }
+            Callee::UnknownDef(UnknownDefCallee::Indirect { .. }) => {
+                // A call through a function pointer.  The labeled type of the pointer serves as
+                // the signature.
+                let func_lty = self.acx.type_of(func);
+                let (&output_lty, input_ltys) = func_lty.args.split_last().unwrap();
+                for (arg_op, &input_lty) in args.iter().zip(input_ltys.iter()) {
+                    let arg_lty = self.acx.type_of(arg_op);
+                    self.assign(input_lty.label, arg_lty.label);
+                }
+                self.assign(dest_lty.label, output_lty.label);
+            }
             Callee::UnknownDef(_) => {
                 log::error!("TODO: visit Callee::{callee:?}");
             }
@@ -271,16 +300,23 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 self.assign(dest_lty.label, arg_lty.label);
             }
 
-            Callee::SliceAsPtr { .. } => {
+            Callee::SliceAsPtr { elem_ty, .. } => {
                 // The input is a `Ref`, so its underlying type is known precisely.
                 assert_eq!(args.len(), 1);
                 let arg_lty = self.acx.type_of(&args[0]);
                 assert!(matches!(arg_lty.ty.kind(), TyKind::Ref(..)));
                 assert_eq!(arg_lty.args.len(), 1);
                 let slice_lty = arg_lty.args[0];
-                assert!(matches!(slice_lty.ty.kind(), TyKind::Slice(..)));
-                assert_eq!(slice_lty.args.len(), 1);
-                let elem_lty = slice_lty.args[0];
+                let elem_lty = match *slice_lty.ty.kind() {
+                    TyKind::Array(..) | TyKind::Slice(..) => slice_lty.args[0],
+                    // `Vec<T>`: the element type is the first type argument.
+                    TyKind::Adt(..) if slice_lty.args.len() > 0 => slice_lty.args[0],
+                    // `str` and `CStr`: the element type is `u8`, which has no pointers.
+                    TyKind::Str | TyKind::Adt(..) => {
+                        self.acx.lcx().label(elem_ty, &mut |_| PointerId::NONE)
+                    }
+                    _ => unreachable!("unexpected `as_ptr` receiver {:?}", slice_lty),
+                };
                 self.define_pointer_with_type(dest_lty.label, elem_lty);
             }
 
@@ -310,7 +346,25 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 self.use_pointer_at_type(arg_lty.label, var);
             }
 
-            Callee::Memcpy => {
+            Callee::Strdup | Callee::Strndup => {
+                // The result is a copy of the string, so it has the same pointee type as the
+                // input.
+                let arg_lty = self.acx.type_of(&args[0]);
+                self.assign(dest_lty.label, arg_lty.label);
+            }
+
+            Callee::Sprintf | Callee::Snprintf | Callee::Printf | Callee::Fprintf => {
+                // The destination or stream and the strings printed with `%s` are used at their
+                // declared pointee types.
+                for arg in args {
+                    let arg_lty = self.acx.type_of(arg);
+                    if !arg_lty.label.is_none() {
+                        self.use_pointer_at_type(arg_lty.label, arg_lty.args[0]);
+                    }
+                }
+            }
+
+            Callee::Memcpy | Callee::Memmove => {
                 // We treat the `memcpy` as loading from `*src` and then storing to `*dest`.  The
                 // type of the load and store is unknown at this point (it definitely isn't the
                 // actual type of `*src`/`*dest`, which is `void`), so we introduce a new inference
@@ -325,6 +379,44 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 self.use_pointer_at_type(src_arg_lty.label, var);
                 self.assign(dest_lty.label, dest_arg_lty.label);
             }
+            Callee::Memcmp => {
+                // We treat this much like `memcpy`, but with a load from each pointer and no
+                // store.
+                let var = self.constraints.fresh_var();
+                assert_eq!(args.len(), 3);
+                let lhs_arg_lty = self.acx.type_of(&args[0]);
+                let rhs_arg_lty = self.acx.type_of(&args[1]);
+                self.use_pointer_at_type(lhs_arg_lty.label, var);
+                self.use_pointer_at_type(rhs_arg_lty.label, var);
+            }
+            Callee::Memchr => {
+                // We treat this as a load from `*src`, at a type to be solved for later like in
+                // `memcpy`.  The result points into the same buffer.
+                let var = self.constraints.fresh_var();
+                assert_eq!(args.len(), 3);
+                let src_arg_lty = self.acx.type_of(&args[0]);
+                self.use_pointer_at_type(src_arg_lty.label, var);
+                self.assign(dest_lty.label, src_arg_lty.label);
+            }
+            Callee::Qsort => {
+                // We treat this as a load from and a store to `*base`, at a type to be solved for
+                // later like in `memcpy`.
+                let var = self.constraints.fresh_var();
+                assert_eq!(args.len(), 4);
+                let base_arg_lty = self.acx.type_of(&args[0]);
+                self.use_pointer_at_type(base_arg_lty.label, var);
+            }
+            Callee::Bsearch => {
+                // We treat this as a load from `*key` and `*base` at the same type, to be solved
+                // for later like in `memcpy`.  The result points into the same buffer as `base`.
+                let var = self.constraints.fresh_var();
+                assert_eq!(args.len(), 5);
+                let key_arg_lty = self.acx.type_of(&args[0]);
+                let base_arg_lty = self.acx.type_of(&args[1]);
+                self.use_pointer_at_type(key_arg_lty.label, var);
+                self.use_pointer_at_type(base_arg_lty.label, var);
+                self.assign(dest_lty.label, base_arg_lty.label);
+            }
             Callee::Memset => {
                 // We treat this much like `memcpy`, but with only a store, not a load.
                 //
@@ -342,6 +434,9 @@ impl<'tcx> TypeChecker<'tcx, '_> {
             Callee::Null { .. } => {
                 // No constraints.
             }
+            Callee::OptionFnPtrUnwrap => {
+                // No constraints.  The function pointer itself has no `PointerId`.
+            }
         }
     }
 }
@@ -0,0 +1,184 @@
+//! Timing and size statistics for the passes of the analysis.
+//!
+//! When `C2RUST_ANALYZE_PROFILE` is set, each pass run on a function is wrapped in [`time`], which
+//! records how long it took, and the pass reports the number of constraints (or rewrites) it
+//! produced through [`count`].  [`report`] prints the totals for each pass, followed by the
+//! functions that took the longest across all passes.  The value of the variable is the number of
+//! functions to list.  Profiling is off by default, in which case [`time`] and [`count`] do
+//! nothing.
+
+use crate::progress;
+use rustc_hir::def_id::LocalDefId;
+use rustc_middle::ty::TyCtxt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pass {
+    /// Generating and solving pointee type constraints.
+    PointeeTypes,
+    /// Generating dataflow constraints and propagating permissions through them.
+    Dataflow,
+    /// Polonius borrow checking.  Counts the `subset_base` facts of each polonius run.
+    Borrowck,
+    /// Generating MIR rewrites.  Counts the rewrites produced.
+    MirOp,
+    /// Converting MIR rewrites to HIR rewrites.  Counts the rewrites produced.
+    HirRewrite,
+}
+
+impl Pass {
+    const ALL: [Pass; 5] = [
+        Pass::PointeeTypes,
+        Pass::Dataflow,
+        Pass::Borrowck,
+        Pass::MirOp,
+        Pass::HirRewrite,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Pass::PointeeTypes => "pointee types",
+            Pass::Dataflow => "dataflow",
+            Pass::Borrowck => "borrowck",
+            Pass::MirOp => "mir_op",
+            Pass::HirRewrite => "HIR rewrite",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Stats {
+    time: Duration,
+    runs: usize,
+    count: usize,
+}
+
+struct Profile {
+    top_n: usize,
+    passes: [Stats; Pass::ALL.len()],
+    fns: HashMap<LocalDefId, [Duration; Pass::ALL.len()]>,
+    /// The pass whose [`time`] call is currently running, for attributing [`count`]s.
+    current: Option<Pass>,
+}
+
+thread_local! {
+    static PROFILE: RefCell<Option<Profile>> = RefCell::new(None);
+}
+
+/// Enable profiling if `C2RUST_ANALYZE_PROFILE` is set.
+pub fn init() {
+    let top_n = match env::var("C2RUST_ANALYZE_PROFILE") {
+        Ok(val) => val
+            .parse()
+            .unwrap_or_else(|_| panic!("bad value for C2RUST_ANALYZE_PROFILE: {val:?}")),
+        Err(_) => return,
+    };
+    PROFILE.with(|cell| {
+        *cell.borrow_mut() = Some(Profile {
+            top_n,
+            passes: Default::default(),
+            fns: HashMap::new(),
+            current: None,
+        });
+    });
+}
+
+fn enabled() -> bool {
+    PROFILE.with(|cell| cell.borrow().is_some())
+}
+
+/// Run `f`, recording its running time under `pass` and `ldid`.  `f` may panic, in which case
+/// nothing is recorded.
+pub fn time<R>(pass: Pass, ldid: LocalDefId, f: impl FnOnce() -> R) -> R {
+    if !enabled() {
+        return f();
+    }
+    PROFILE.with(|cell| cell.borrow_mut().as_mut().unwrap().current = Some(pass));
+    let start = Instant::now();
+    let r = f();
+    let elapsed = start.elapsed();
+    PROFILE.with(|cell| {
+        let mut profile = cell.borrow_mut();
+        let profile = profile.as_mut().unwrap();
+        let i = pass as usize;
+        profile.passes[i].time += elapsed;
+        profile.passes[i].runs += 1;
+        profile.fns.entry(ldid).or_default()[i] += elapsed;
+        profile.current = None;
+    });
+    r
+}
+
+/// Add `n` to the constraint count of the pass being [`time`]d.  The count is also shown in the
+/// [`progress`](crate::progress) status lines, even when profiling is off.
+pub fn count(n: usize) {
+    progress::count(n);
+    PROFILE.with(|cell| {
+        if let Some(ref mut profile) = *cell.borrow_mut() {
+            if let Some(pass) = profile.current {
+                profile.passes[pass as usize].count += n;
+            }
+        }
+    });
+}
+
+/// Print the collected statistics, if profiling is enabled.
+pub fn report(tcx: TyCtxt) {
+    PROFILE.with(|cell| {
+        let profile = cell.borrow();
+        let profile = match *profile {
+            Some(ref x) => x,
+            None => return,
+        };
+
+        eprintln!("=== profile ===");
+        eprintln!(
+            "{:<16} {:>12} {:>8} {:>12}",
+            "pass", "time (ms)", "runs", "constraints"
+        );
+        let mut total = Duration::ZERO;
+        for pass in Pass::ALL {
+            let stats = profile.passes[pass as usize];
+            total += stats.time;
+            eprintln!(
+                "{:<16} {:>12.3} {:>8} {:>12}",
+                pass.name(),
+                stats.time.as_secs_f64() * 1000.,
+                stats.runs,
+                stats.count,
+            );
+        }
+        eprintln!("{:<16} {:>12.3}", "total", total.as_secs_f64() * 1000.);
+
+        let mut fns = profile
+            .fns
+            .iter()
+            .map(|(&ldid, times)| (times.iter().sum::<Duration>(), ldid, times))
+            .collect::<Vec<_>>();
+        fns.sort_by(|a, b| b.0.cmp(&a.0));
+        eprintln!();
+        eprintln!(
+            "slowest {} of {} functions:",
+            profile.top_n.min(fns.len()),
+            fns.len()
+        );
+        for (time, ldid, times) in fns.into_iter().take(profile.top_n) {
+            let per_pass = Pass::ALL
+                .iter()
+                .map(|&pass| {
+                    let ms = times[pass as usize].as_secs_f64() * 1000.;
+                    format!("{} {ms:.3}", pass.name())
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            eprintln!(
+                "{:>12.3} ms  {}  ({per_pass})",
+                time.as_secs_f64() * 1000.,
+                tcx.def_path_str(ldid.to_def_id()),
+            );
+        }
+    });
+}
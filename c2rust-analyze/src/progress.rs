@@ -0,0 +1,142 @@
+//! Progress reporting for long analyses.
+//!
+//! Each pass over the functions of the crate calls [`start`] with the number of functions, [`step`]
+//! before visiting each one, and [`finish`] at the end.  While a pass runs, a status line giving
+//! the pass, the number of functions visited, the number of constraints generated or solved so
+//! far (as reported through [`profile::count`]), and an estimate of the remaining time is printed
+//! to stderr every [`INTERVAL`].  Passes that finish sooner than that print nothing, so small
+//! crates don't get any extra output.
+//!
+//! Setting `C2RUST_ANALYZE_QUIET=1` disables the status lines, along with the listings of defs,
+//! statics, and equivalence classes that the analysis otherwise prints for debugging.
+//!
+//! [`profile::count`]: crate::profile::count
+
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::time::{Duration, Instant};
+
+/// The minimum time between two status lines.
+const INTERVAL: Duration = Duration::from_secs(5);
+
+struct Progress {
+    pass: &'static str,
+    done: usize,
+    total: usize,
+    constraints: usize,
+    start: Instant,
+    /// The time the last status line was printed, or `None` if none has been printed yet during
+    /// this pass.
+    last_report: Option<Instant>,
+}
+
+thread_local! {
+    static QUIET: Cell<bool> = Cell::new(false);
+    static PROGRESS: RefCell<Option<Progress>> = RefCell::new(None);
+}
+
+/// Read the `C2RUST_ANALYZE_QUIET` setting.
+pub fn init() {
+    let quiet = env::var("C2RUST_ANALYZE_QUIET").as_deref() == Ok("1");
+    QUIET.with(|cell| cell.set(quiet));
+}
+
+/// Returns `true` if progress and debug listings should be suppressed.
+pub fn quiet() -> bool {
+    QUIET.with(|cell| cell.get())
+}
+
+/// Begin a pass named `pass` over `total` functions.
+pub fn start(pass: &'static str, total: usize) {
+    if quiet() {
+        return;
+    }
+    PROGRESS.with(|cell| {
+        *cell.borrow_mut() = Some(Progress {
+            pass,
+            done: 0,
+            total,
+            constraints: 0,
+            start: Instant::now(),
+            last_report: None,
+        });
+    });
+}
+
+/// Record that the current pass is moving on to its next function, printing a status line if
+/// [`INTERVAL`] has passed since the last one.
+pub fn step() {
+    PROGRESS.with(|cell| {
+        let mut progress = cell.borrow_mut();
+        let progress = match *progress {
+            Some(ref mut x) => x,
+            None => return,
+        };
+        let now = Instant::now();
+        let since = progress.last_report.unwrap_or(progress.start);
+        if now - since >= INTERVAL {
+            eprintln!("{}", progress.status_line(now));
+            progress.last_report = Some(now);
+        }
+        progress.done += 1;
+    });
+}
+
+/// Add `n` to the constraint count of the current pass.
+pub fn count(n: usize) {
+    PROGRESS.with(|cell| {
+        if let Some(ref mut progress) = *cell.borrow_mut() {
+            progress.constraints += n;
+        }
+    });
+}
+
+/// End the current pass.  If any status lines were printed for it, also print a final summary.
+pub fn finish() {
+    PROGRESS.with(|cell| {
+        let progress = match cell.borrow_mut().take() {
+            Some(x) => x,
+            None => return,
+        };
+        if progress.last_report.is_some() {
+            eprintln!(
+                "[c2rust-analyze] {}: done, {} functions, {} constraints in {}",
+                progress.pass,
+                progress.total,
+                progress.constraints,
+                fmt_duration(progress.start.elapsed()),
+            );
+        }
+    });
+}
+
+impl Progress {
+    fn status_line(&self, now: Instant) -> String {
+        let elapsed = now - self.start;
+        let eta = if self.done == 0 {
+            "unknown".to_owned()
+        } else {
+            let remaining = self.total.saturating_sub(self.done) as u32;
+            fmt_duration(elapsed / self.done as u32 * remaining)
+        };
+        format!(
+            "[c2rust-analyze] {}: {}/{} functions ({}%), {} constraints, {} elapsed, ETA {}",
+            self.pass,
+            self.done,
+            self.total,
+            self.done * 100 / self.total.max(1),
+            self.constraints,
+            fmt_duration(elapsed),
+            eta,
+        )
+    }
+}
+
+fn fmt_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{:.1}s", d.as_secs_f64())
+    }
+}
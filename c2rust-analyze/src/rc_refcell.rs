@@ -0,0 +1,68 @@
+//! Inference of the heap objects that can be rewritten to `Rc<RefCell<T>>`, for `--rc-refcell`.
+//!
+//! An owning pointer that isn't `UNIQUE` refers to an object that's mutated while other pointers
+//! alias it.  Neither `Box<T>` nor `&mut T` can express this, and `Cell` only supports replacing
+//! the whole object, so the function would otherwise be left unrewritten.  Such pointers start
+//! out with [`FlagSet::REFCELL`], which then spreads to every single-object pointer that exchanges
+//! values with one that has it (see [`DataflowConstraints::propagate_shared`]), so the owner and
+//! its aliases all become handles to the same `Rc<RefCell<T>>`.
+//!
+//! Pointers created by borrowing a place, as in `&mut (*p).field`, refer to part of an object
+//! rather than sharing it, so they never get the flag.  Neither do the signatures of functions
+//! that won't be rewritten.
+//!
+//! [`DataflowConstraints::propagate_shared`]: crate::dataflow::DataflowConstraints::propagate_shared
+
+use crate::context::{AnalysisCtxt, Assignment, FlagSet, PermissionSet};
+use crate::pointer_id::PointerId;
+use rustc_middle::mir::{Body, Rvalue, StatementKind};
+use std::collections::HashSet;
+
+/// Whether a pointer with `perms` and `flags` refers to a single object that may be shared through
+/// an `Rc`.
+pub fn can_share(perms: PermissionSet, flags: FlagSet) -> bool {
+    !perms.intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB)
+        && !flags.intersects(FlagSet::FIXED | FlagSet::C_STRING | FlagSet::VEC)
+}
+
+/// Whether a pointer with `perms` and `flags` owns an object that's aliased while it's mutated.
+pub fn is_shared_owner(perms: PermissionSet, flags: FlagSet) -> bool {
+    perms.contains(PermissionSet::FREE)
+        && !perms.contains(PermissionSet::UNIQUE)
+        && can_share(perms, flags)
+}
+
+/// Add to `excluded` the pointers in `mir` that are created by borrowing a place, such as `q` in
+/// `q = &mut (*p).field`.
+pub fn collect_borrows<'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    mir: &Body<'tcx>,
+    excluded: &mut HashSet<PointerId>,
+) {
+    for bb_data in mir.basic_blocks().iter() {
+        for stmt in &bb_data.statements {
+            let (pl, rv) = match stmt.kind {
+                StatementKind::Assign(ref x) => (x.0, &x.1),
+                _ => continue,
+            };
+            if matches!(*rv, Rvalue::Ref(..) | Rvalue::AddressOf(..)) {
+                let ptr = acx.type_of(pl).label;
+                if !ptr.is_none() {
+                    excluded.insert(ptr);
+                }
+            }
+        }
+    }
+}
+
+/// Add `REFCELL` to every owning pointer in `asn` that's aliased while it's mutated, except those
+/// in `excluded`.
+pub fn mark_shared_owners(asn: &mut Assignment, excluded: &HashSet<PointerId>) {
+    let (perms, mut flags) = asn.all_mut();
+    let perms = perms.borrow();
+    for ((ptr, &p), (_, f)) in perms.iter().zip(flags.iter_mut()) {
+        if is_shared_owner(p, *f) && !excluded.contains(&ptr) {
+            f.insert(FlagSet::REFCELL);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Inference of the owned slices that can be rewritten to `Vec<T>`, for `--realloc-vecs`.
+//!
+//! An owned slice that's passed to or returned from `realloc` is a growable buffer.  As a
+//! `Box<[T]>`, it's copied into a new allocation on every `realloc`, but as a `Vec<T>`, it can be
+//! resized in place.  The input and output of each `realloc` of an owned slice start out with
+//! [`FlagSet::VEC`], which then spreads to every owned slice that exchanges values with one that
+//! has it (see [`DataflowConstraints::propagate_vec`]), so the buffer keeps its type as it moves
+//! between locals, fields, and functions.  Owned slices that don't get the flag remain `Box<[T]>`,
+//! and are converted to and from `Vec<T>` where they meet ones that do.
+//!
+//! [`DataflowConstraints::propagate_vec`]: crate::dataflow::DataflowConstraints::propagate_vec
+
+use crate::context::{AnalysisCtxt, Assignment, FlagSet, PermissionSet};
+use crate::util::{ty_callee, Callee};
+use rustc_middle::mir::{Body, TerminatorKind};
+
+/// Whether a pointer with `perms` and `flags` is an owned slice, which may become a `Vec`.
+pub fn is_candidate(perms: PermissionSet, flags: FlagSet) -> bool {
+    perms.contains(PermissionSet::FREE)
+        && perms.intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB)
+        && !flags.intersects(FlagSet::FIXED | FlagSet::C_STRING)
+}
+
+/// Add `VEC` to the input and output of each call in `mir` that `realloc`s an owned slice into
+/// another owned slice.
+pub fn mark_realloc_buffers<'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    mir: &Body<'tcx>,
+    asn: &mut Assignment,
+) {
+    let tcx = acx.tcx();
+    let (perms, mut flags) = asn.all_mut();
+    let perms = perms.borrow();
+    for bb_data in mir.basic_blocks().iter() {
+        let (func, args, destination) = match bb_data.terminator().kind {
+            TerminatorKind::Call {
+                ref func,
+                ref args,
+                destination,
+                ..
+            } => (func, args, destination),
+            _ => continue,
+        };
+        if !matches!(ty_callee(tcx, func.ty(mir, tcx)), Callee::Realloc) {
+            continue;
+        }
+        let ptrs = [acx.type_of(&args[0]).label, acx.type_of(destination).label];
+        if ptrs
+            .iter()
+            .all(|&ptr| !ptr.is_none() && is_candidate(perms[ptr], flags[ptr]))
+        {
+            for ptr in ptrs {
+                flags[ptr].insert(FlagSet::VEC);
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use log::error;
 use rustc_index::vec::IndexVec;
 use rustc_middle::mir::{
-    BasicBlock, Body, Local, Location, Place, Rvalue, StatementKind, TerminatorKind,
+    BasicBlock, Body, InlineAsmOperand, Local, Location, Place, Rvalue, StatementKind,
+    TerminatorKind,
 };
 use std::collections::HashMap;
 
@@ -310,7 +310,20 @@ fn scan_blocks(mir: &Body, rw: &mut RecentWrites) {
             }
             TerminatorKind::Call { destination, .. } => rw.record_place_written(loc, destination),
             TerminatorKind::Yield { resume_arg, .. } => rw.record_place_written(loc, resume_arg),
-            TerminatorKind::InlineAsm { .. } => error!("recent_writes InlineAsm NYI"),
+            TerminatorKind::InlineAsm { ref operands, .. } => {
+                for op in operands {
+                    match *op {
+                        InlineAsmOperand::Out {
+                            place: Some(place), ..
+                        }
+                        | InlineAsmOperand::InOut {
+                            out_place: Some(place),
+                            ..
+                        } => rw.record_place_written(loc, place),
+                        _ => {}
+                    }
+                }
+            }
             _ => {}
         }
     }
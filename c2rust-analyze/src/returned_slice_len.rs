@@ -0,0 +1,371 @@
+//! Detection of functions that return a new buffer and report its length through an out-parameter,
+//! as in `int *make_buf(size_t n, size_t *out_len)`.
+//!
+//! When the returned pointer is rewritten to a slice, the slice carries its own length, so the
+//! out-parameter is redundant.  The rewritten function drops the out-parameter, and each rewritten
+//! caller sets its length variable from the returned slice instead (see
+//! [`RewriteKind::CallReturnedSliceLen`]).  Callers that aren't rewritten reach the function
+//! through its shim, which does the same.
+//!
+//! This is only valid when the value stored through the out-parameter is known to be the length of
+//! the returned slice.  We require the returned pointer to come directly from a
+//! `malloc(n * size_of::<T>())` or `calloc(n, size_of::<T>())` call, and every store through the
+//! out-parameter to store that same `n`, where `n` is a constant or an argument that's never
+//! reassigned.  A buffer that's offset or reassigned before it's returned doesn't qualify, and
+//! neither does a length computed some other way, such as `n - 1`.
+//!
+//! [`RewriteKind::CallReturnedSliceLen`]: crate::rewrite::RewriteKind::CallReturnedSliceLen
+
+use crate::const_prop::ConstResolver;
+use crate::context::{AnalysisCtxt, FlagSet, PermissionSet};
+use crate::pointer_id::PointerTable;
+use crate::type_desc::{self, Ownership, Quantity};
+use crate::util::{ty_callee, Callee};
+use log::debug;
+use rustc_ast::Mutability;
+use rustc_middle::mir::visit::{PlaceContext, Visitor};
+use rustc_middle::mir::{
+    BinOp, Body, CastKind, Local, Location, Operand, PlaceElem, Rvalue, StatementKind, Terminator,
+    TerminatorKind, VarDebugInfoContents, RETURN_PLACE,
+};
+use rustc_middle::ty::{ParamEnv, Ty, TyKind};
+
+/// The maximum number of copies and casts to follow when tracing a value back to its source.
+const MAX_DEPTH: usize = 8;
+
+/// A function whose returned slice is fused with the length it reports through an out-parameter.
+#[derive(Clone, Debug)]
+pub struct ReturnedSliceLen<'tcx> {
+    /// The position of the out-parameter among the function's arguments.
+    pub arg_index: usize,
+    /// The name of the out-parameter in the source code.
+    pub name: String,
+    /// The type the out-parameter points to.
+    pub len_ty: Ty<'tcx>,
+}
+
+/// The source of an integer value, for checking that two values are equal.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum IntSource {
+    /// A known constant.
+    Const(u128),
+    /// The value of an argument that's never reassigned.
+    Arg(Local),
+}
+
+/// Check whether `mir` returns a newly allocated buffer and stores its length through an integer
+/// out-parameter, so that the length can be taken from the returned slice instead.  The return
+/// value must be rewritten to a non-optional slice, and there must be exactly one `*mut` integer
+/// argument, which is only ever written through and is written on every path to a return.
+pub fn find_returned_slice_len<'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    perms: &PointerTable<PermissionSet>,
+    flags: &PointerTable<FlagSet>,
+    mir: &Body<'tcx>,
+) -> Option<ReturnedSliceLen<'tcx>> {
+    let tcx = acx.tcx();
+    let ret_lty = acx.local_tys[RETURN_PLACE];
+    let elem_ty = match *ret_lty.ty.kind() {
+        TyKind::RawPtr(mt) if !ret_lty.label.is_none() => mt.ty,
+        _ => return None,
+    };
+    let ret_ptr = ret_lty.label;
+    if flags[ret_ptr].contains(FlagSet::FIXED) {
+        return None;
+    }
+    let ret_desc = type_desc::perms_to_desc(ret_lty.ty, perms[ret_ptr], flags[ret_ptr]);
+    if ret_desc.qty != Quantity::Slice || ret_desc.option || ret_desc.dyn_owned {
+        return None;
+    }
+    if !matches!(
+        ret_desc.own,
+        Ownership::Imm | Ownership::Mut | Ownership::Box | Ownership::Vec
+    ) {
+        return None;
+    }
+
+    let mut candidates = mir.args_iter().filter(|&local| {
+        let lty = acx.local_tys[local];
+        let pointee_is_int = match *lty.ty.kind() {
+            TyKind::RawPtr(mt) => {
+                mt.mutbl == Mutability::Mut && mt.ty.is_integral() && !lty.label.is_none()
+            }
+            _ => false,
+        };
+        if !pointee_is_int {
+            return false;
+        }
+        let ptr = lty.label;
+        if flags[ptr].contains(FlagSet::FIXED) {
+            return true;
+        }
+        let desc = type_desc::perms_to_desc(lty.ty, perms[ptr], flags[ptr]);
+        desc.own == Ownership::Mut && desc.qty == Quantity::Single && !desc.option
+    });
+    let len_local = candidates.next()?;
+    if candidates.next().is_some() {
+        // Ambiguous: we can't tell which argument holds the length.
+        return None;
+    }
+    let len_ty = match *acx.local_tys[len_local].ty.kind() {
+        TyKind::RawPtr(mt) => mt.ty,
+        _ => unreachable!(),
+    };
+
+    let resolver = ConstResolver::new(tcx, mir, acx.gacx.arg_consts.get(mir.source.def_id()));
+    let int_source = |op: &Operand<'tcx>| -> Option<IntSource> {
+        if let Some(x) = resolver.operand_const(op) {
+            return Some(IntSource::Const(x));
+        }
+        let local = skip_copies(&resolver, op).place()?.as_local()?;
+        resolver
+            .is_unmodified_arg(local)
+            .then_some(IntSource::Arg(local))
+    };
+    let is_size_of_elem = |op: &Operand<'tcx>| -> bool {
+        let op = skip_copies(&resolver, op);
+        if let Some(size) = resolver.operand_const(op) {
+            let layout = match tcx.layout_of(ParamEnv::reveal_all().and(elem_ty)) {
+                Ok(x) => x,
+                Err(_) => return false,
+            };
+            return u128::from(layout.size.bytes()) == size;
+        }
+        let call = match op.place().and_then(|pl| pl.as_local()) {
+            Some(local) => resolver.single_call_def(local),
+            None => None,
+        };
+        match call.map(|term| &term.kind) {
+            Some(TerminatorKind::Call { func, .. }) => {
+                matches!(ty_callee(tcx, func.ty(mir, tcx)), Callee::SizeOf { ty } if ty == elem_ty)
+            }
+            _ => false,
+        }
+    };
+    // The number of `elem_ty`s allocated by the `malloc` or `calloc` call `term`.
+    let alloc_count = |term: &Terminator<'tcx>| -> Option<IntSource> {
+        let (func, args) = match term.kind {
+            TerminatorKind::Call {
+                ref func, ref args, ..
+            } => (func, args),
+            _ => return None,
+        };
+        let count = match ty_callee(tcx, func.ty(mir, tcx)) {
+            Callee::Malloc => {
+                let (a, b) = mul_operands(acx, &resolver, mir, &args[0])?;
+                if is_size_of_elem(b) {
+                    a
+                } else if is_size_of_elem(a) {
+                    b
+                } else {
+                    return None;
+                }
+            }
+            Callee::Calloc if is_size_of_elem(&args[1]) => &args[0],
+            _ => return None,
+        };
+        int_source(count)
+    };
+
+    // Every value assigned to the return place must be a copy or cast of a new allocation, and
+    // all of them must have the same length.
+    let mut count = None;
+    let mut check_count = |x: Option<IntSource>| -> bool {
+        match (x, count) {
+            (None, _) => false,
+            (Some(x), None) => {
+                count = Some(x);
+                true
+            }
+            (Some(x), Some(y)) => x == y,
+        }
+    };
+    for bb_data in mir.basic_blocks().iter() {
+        for stmt in &bb_data.statements {
+            let (pl, rv) = match stmt.kind {
+                StatementKind::Assign(ref x) => (x.0, &x.1),
+                _ => continue,
+            };
+            if pl.local != RETURN_PLACE {
+                continue;
+            }
+            let op = match *rv {
+                Rvalue::Use(ref op) | Rvalue::Cast(CastKind::Misc, ref op, _)
+                    if pl.projection.is_empty() =>
+                {
+                    skip_copies(&resolver, op)
+                }
+                _ => return None,
+            };
+            let call = op
+                .place()
+                .and_then(|pl| pl.as_local())
+                .and_then(|local| resolver.single_call_def(local));
+            if !check_count(call.and_then(&alloc_count)) {
+                return None;
+            }
+        }
+        if let TerminatorKind::Call { destination, .. } = bb_data.terminator().kind {
+            if destination.local == RETURN_PLACE && !check_count(alloc_count(bb_data.terminator()))
+            {
+                return None;
+            }
+        }
+    }
+    let count = count?;
+
+    // The out-parameter may only be written through, and every write must store the length of
+    // the allocation.
+    let mut writes = Vec::new();
+    for (bb, bb_data) in mir.basic_blocks().iter_enumerated() {
+        for (statement_index, stmt) in bb_data.statements.iter().enumerate() {
+            let loc = Location {
+                block: bb,
+                statement_index,
+            };
+            if let StatementKind::Assign(ref x) = stmt.kind {
+                let (pl, rv) = (x.0, &x.1);
+                if pl.local == len_local && pl.projection[..] == [PlaceElem::Deref] {
+                    let stores_count = match *rv {
+                        Rvalue::Use(ref op) => int_source(op) == Some(count),
+                        _ => false,
+                    };
+                    if !stores_count || mentions_local(rv, len_local, loc) {
+                        return None;
+                    }
+                    writes.push(loc);
+                    continue;
+                }
+            }
+            let mut v = LocalMentions {
+                local: len_local,
+                found: false,
+            };
+            v.visit_statement(stmt, loc);
+            if v.found {
+                return None;
+            }
+        }
+        let loc = mir.terminator_loc(bb);
+        let mut v = LocalMentions {
+            local: len_local,
+            found: false,
+        };
+        v.visit_terminator(bb_data.terminator(), loc);
+        if v.found {
+            return None;
+        }
+    }
+
+    let dominators = mir.basic_blocks.dominators();
+    for (bb, bb_data) in mir.basic_blocks().iter_enumerated() {
+        if !matches!(bb_data.terminator().kind, TerminatorKind::Return) {
+            continue;
+        }
+        let loc = mir.terminator_loc(bb);
+        if !writes.iter().any(|w| w.dominates(loc, &dominators)) {
+            return None;
+        }
+    }
+
+    let name = mir.var_debug_info.iter().find_map(|vdi| match vdi.value {
+        VarDebugInfoContents::Place(pl) if pl.as_local() == Some(len_local) => {
+            Some(vdi.name.to_string())
+        }
+        _ => None,
+    })?;
+
+    debug!(
+        "returned slice length for {:?} is reported through argument {:?} ({:?})",
+        tcx.def_path_str(mir.source.def_id()),
+        name,
+        len_local
+    );
+    Some(ReturnedSliceLen {
+        arg_index: len_local.index() - 1,
+        name,
+        len_ty,
+    })
+}
+
+/// Follow copies and casts back from `op` through locals that are assigned exactly once.
+fn skip_copies<'a: 'b, 'b, 'tcx>(
+    resolver: &ConstResolver<'a, 'tcx>,
+    mut op: &'b Operand<'tcx>,
+) -> &'b Operand<'tcx> {
+    for _ in 0..MAX_DEPTH {
+        let local = match op.place().and_then(|pl| pl.as_local()) {
+            Some(x) => x,
+            None => break,
+        };
+        match resolver.single_def(local) {
+            Some(&Rvalue::Use(ref x)) | Some(&Rvalue::Cast(CastKind::Misc, ref x, _)) => op = x,
+            _ => break,
+        }
+    }
+    op
+}
+
+/// If `op` is the product of two operands, computed with `*` or `wrapping_mul`, return the two
+/// factors.
+fn mul_operands<'a, 'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    resolver: &ConstResolver<'a, 'tcx>,
+    mir: &'a Body<'tcx>,
+    op: &Operand<'tcx>,
+) -> Option<(&'a Operand<'tcx>, &'a Operand<'tcx>)> {
+    let tcx = acx.tcx();
+    let pl = skip_copies(resolver, op).place()?;
+    match pl.projection[..] {
+        [] => {}
+        // `(x * y).0`, where the multiplication is checked for overflow.
+        [PlaceElem::Field(f, _)] if f.index() == 0 => {
+            return match *resolver.single_def(pl.local)? {
+                Rvalue::CheckedBinaryOp(BinOp::Mul, ref ops) => Some((&ops.0, &ops.1)),
+                _ => None,
+            };
+        }
+        _ => return None,
+    }
+    if let Some(rv) = resolver.single_def(pl.local) {
+        return match *rv {
+            Rvalue::BinaryOp(BinOp::Mul, ref ops) => Some((&ops.0, &ops.1)),
+            _ => None,
+        };
+    }
+    let term = resolver.single_call_def(pl.local)?;
+    match term.kind {
+        TerminatorKind::Call {
+            ref func, ref args, ..
+        } if args.len() == 2 => match *func.ty(mir, tcx).kind() {
+            TyKind::FnDef(did, _) if tcx.item_name(did).as_str() == "wrapping_mul" => {
+                Some((&args[0], &args[1]))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn mentions_local<'tcx>(rv: &Rvalue<'tcx>, local: Local, loc: Location) -> bool {
+    let mut v = LocalMentions {
+        local,
+        found: false,
+    };
+    v.visit_rvalue(rv, loc);
+    v.found
+}
+
+/// Checks whether a MIR fragment mentions `local`, ignoring debug info.
+struct LocalMentions {
+    local: Local,
+    found: bool,
+}
+
+impl<'tcx> Visitor<'tcx> for LocalMentions {
+    fn visit_local(&mut self, local: Local, context: PlaceContext, _location: Location) {
+        if local == self.local && !matches!(context, PlaceContext::NonUse(_)) {
+            self.found = true;
+        }
+    }
+}
@@ -463,15 +463,26 @@ impl<S: Sink> Emitter<'_, S> {
                 }
                 self.emit_sub(0, span)
             }
+            Rewrite::Const(span) => {
+                self.emit_str("const ")?;
+                self.emit_sub(0, span)
+            }
 
             Rewrite::DefineFn {
                 ref name,
+                ref self_param,
                 ref arg_tys,
                 ref return_ty,
                 ref body,
             } => {
                 self.emit_fmt(format_args!("\nunsafe fn {name}("))?;
+                let mut first_arg = 0;
+                if let Some(self_param) = self_param.as_ref() {
+                    self.emit(self_param, 0)?;
+                    first_arg = 1;
+                }
                 for (i, arg_ty) in arg_tys.iter().enumerate() {
+                    let i = first_arg + i;
                     if i > 0 {
                         self.emit_str(", ")?;
                     }
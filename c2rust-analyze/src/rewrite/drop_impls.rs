@@ -0,0 +1,461 @@
+//! Rewriting of C destructor functions into `Drop` impls.
+//!
+//! C code commonly releases a struct along with the buffers it owns through a single function:
+//!
+//! ```ignore
+//! unsafe fn thing_free(t: *mut Thing) {
+//!     if t.is_null() {
+//!         return;
+//!     }
+//!     free((*t).data as *mut c_void);
+//!     free((*t).name as *mut c_void);
+//!     free(t as *mut c_void);
+//! }
+//! ```
+//!
+//! With `--drop-impls`, [`find_drop_impls`] finds these destructors before the analysis runs.  The
+//! null check is optional, but the body must otherwise consist only of the calls to `free`.  If
+//! the destructor is the only function that frees these fields, or that frees a `Thing` at all,
+//! [`gen_drop_impl_rewrites`] adds an `impl Drop for Thing` that frees the fields, and the
+//! analysis treats each call to the destructor as a call to `free` on its argument.  In rewritten
+//! callers, the call then becomes a `drop` of the `Box<Thing>`, which runs the new impl.  The
+//! destructor itself is kept for callers that aren't rewritten.
+//!
+//! The freed fields are marked `FIXED`, so the `Drop` impl can free them as raw pointers.  Since
+//! a `Drop` type can't be `Copy`, the `Copy` derive is removed from the struct, and structs that
+//! are copied out of a pointer, or held by value in a local, a static, or another type, are left
+//! alone.  Assigning a new value to a `Thing` would drop the old one, which C code may not have
+//! initialized, so each such assignment is rewritten to `std::ptr::write`.
+
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Block, Body, Expr, ExprKind, HirId, Mutability, Pat, PatKind, StmtKind, UnOp};
+use rustc_middle::ty::{Ty, TyCtxt, TyKind};
+use rustc_span::{Span, Symbol};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use super::vec_structs::{copy_derive_span, find_copy_impl, fn_block, Matcher};
+use crate::borrowck::OriginParam;
+use crate::context::GlobalAnalysisCtxt;
+use crate::rewrite::Rewrite;
+
+thread_local! {
+    /// The destructors found by [`find_drop_impls`], as recorded by [`set_drop_fns`].
+    static DROP_FNS: RefCell<HashSet<DefId>> = RefCell::new(HashSet::new());
+}
+
+/// Record the destructors of `drop_impls`, so that calls to them are treated as calls to `free`.
+pub fn set_drop_fns(drop_impls: &[DropImpl]) {
+    DROP_FNS.with(|cell| {
+        let mut drop_fns = cell.borrow_mut();
+        drop_fns.clear();
+        drop_fns.extend(drop_impls.iter().map(|di| di.drop_fn.to_def_id()));
+    });
+}
+
+/// Whether `did` is a destructor that will be replaced by a `Drop` impl.
+pub fn is_drop_fn(did: DefId) -> bool {
+    DROP_FNS.with(|cell| cell.borrow().contains(&did))
+}
+
+/// A struct whose destructor function is turned into a `Drop` impl.
+#[derive(Clone, Debug)]
+pub struct DropImpl {
+    did: DefId,
+    drop_fn: LocalDefId,
+    /// The span of the function `free`, as it's called in the destructor.
+    free_span: Span,
+    /// The span of the struct type in the destructor's parameter type.
+    self_ty_span: Span,
+    fields: Vec<FreedField>,
+    /// Assignments of a whole struct value.  The spans are those of the assignment and of its two
+    /// sides.
+    writes: Vec<(Span, Span, Span)>,
+}
+
+impl DropImpl {
+    /// The destructor and the fields it frees.  These keep their raw pointer types, so the main
+    /// analysis should treat them as fixed.
+    pub fn fixed_defs(&self) -> impl Iterator<Item = DefId> + '_ {
+        let fields = self.fields.iter().map(|f| f.did);
+        std::iter::once(self.drop_fn.to_def_id()).chain(fields)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FreedField {
+    did: DefId,
+    name: Symbol,
+    /// The span of the type the field is cast to when it's passed to `free`, if any.
+    cast_ty_span: Option<Span>,
+}
+
+/// Find the destructors to turn into `Drop` impls with `--drop-impls`.  Structs in `fixed_defs`
+/// or mentioned in `extern` blocks are never changed.
+pub fn find_drop_impls(
+    tcx: TyCtxt,
+    fixed_defs: &HashSet<DefId>,
+    foreign_mentioned_tys: &HashSet<DefId>,
+) -> Vec<DropImpl> {
+    let mut structs = HashMap::<DefId, DropImpl>::new();
+    let mut rejected = HashSet::new();
+    for ldid in tcx.hir().body_owners() {
+        if tcx.def_kind(ldid) != DefKind::Fn || fixed_defs.contains(&ldid.to_def_id()) {
+            continue;
+        }
+        let body = tcx.hir().body(tcx.hir().body_owned_by(ldid));
+        let m = Matcher::new(tcx, body);
+        let di = match match_drop_fn(tcx, &m, ldid, body) {
+            Some(x) => x,
+            None => continue,
+        };
+        if fixed_defs.contains(&di.did)
+            || foreign_mentioned_tys.contains(&di.did)
+            || tcx.adt_destructor(di.did).is_some()
+        {
+            continue;
+        }
+        // A struct with a `Copy` impl that we can't remove can't also implement `Drop`.
+        if find_copy_impl(tcx, di.did)
+            .map_or(false, |impl_did| copy_derive_span(tcx, impl_did).is_none())
+        {
+            continue;
+        }
+        // There can be only one `Drop` impl per struct.
+        let did = di.did;
+        if structs.insert(did, di).is_some() {
+            rejected.insert(did);
+        }
+    }
+
+    // Values of the struct held anywhere other than behind a pointer would now be dropped when
+    // they go out of scope.
+    for ldid in tcx.hir_crate_items(()).definitions() {
+        if matches!(
+            tcx.def_kind(ldid),
+            DefKind::Field | DefKind::Static(_) | DefKind::Const
+        ) {
+            let ty = tcx.type_of(ldid);
+            rejected.extend(structs.keys().filter(|&&did| holds_by_value(ty, did)));
+        }
+    }
+
+    let drop_fns = structs
+        .values()
+        .map(|di| di.drop_fn)
+        .collect::<HashSet<_>>();
+    for owner in tcx.hir().body_owners() {
+        if drop_fns.contains(&owner) {
+            continue;
+        }
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(owner) {
+            Some(x) => x,
+            None => continue,
+        };
+        let body = tcx.hir().body(hir_body_id);
+        let mut v = UseVisitor {
+            m: Matcher::new(tcx, body),
+            structs: &mut structs,
+            rejected: &mut rejected,
+        };
+        v.visit_body(body);
+    }
+
+    let mut drop_impls = structs
+        .into_values()
+        .filter(|di| !rejected.contains(&di.did))
+        .collect::<Vec<_>>();
+    drop_impls.sort_by_key(|di| di.did);
+    drop_impls
+}
+
+/// Whether a value of type `ty` contains a value of the struct `did`, other than behind a pointer.
+fn holds_by_value(ty: Ty, did: DefId) -> bool {
+    match *ty.kind() {
+        TyKind::Adt(adt_def, substs) => {
+            adt_def.did() == did || substs.types().any(|ty| holds_by_value(ty, did))
+        }
+        TyKind::Array(ty, _) | TyKind::Slice(ty) => holds_by_value(ty, did),
+        TyKind::Tuple(tys) => tys.iter().any(|ty| holds_by_value(ty, did)),
+        _ => false,
+    }
+}
+
+/// Match a destructor, as shown in the module docs.
+fn match_drop_fn<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    m: &Matcher<'tcx>,
+    ldid: LocalDefId,
+    body: &'tcx Body<'tcx>,
+) -> Option<DropImpl> {
+    let p_param = match body.params {
+        [p] => p,
+        _ => return None,
+    };
+    let (_, p_id, did) = m.struct_ptr_param(p_param)?;
+    let self_ty_span = match tcx.hir().expect_owner(ldid).fn_decl()?.inputs[0].kind {
+        rustc_hir::TyKind::Ptr(mut_ty) => mut_ty.ty.span,
+        _ => return None,
+    };
+    let block = fn_block(body)?;
+    if block.expr.is_some() {
+        return None;
+    }
+
+    let mut stmts = block.stmts;
+    if let [first, rest @ ..] = stmts {
+        if let StmtKind::Expr(ex) | StmtKind::Semi(ex) = first.kind {
+            if let ExprKind::If(cond, then, None) = ex.kind {
+                if !is_null_check(m, cond, then, p_id) {
+                    return None;
+                }
+                stmts = rest;
+            }
+        }
+    }
+
+    let (last, field_stmts) = stmts.split_last()?;
+    match last.kind {
+        StmtKind::Semi(ex) if free_arg(m, ex).and_then(|(_, arg)| m.local(arg)) == Some(p_id) => {}
+        _ => return None,
+    }
+
+    let adt_def = tcx.adt_def(did);
+    let mut free_span = None;
+    let mut fields = Vec::new();
+    for stmt in field_stmts {
+        let ex = match stmt.kind {
+            StmtKind::Semi(ex) => ex,
+            _ => return None,
+        };
+        let (func_span, arg) = free_arg(m, ex)?;
+        let name = m.param_field(arg, p_id)?;
+        if fields.iter().any(|f: &FreedField| f.name == name) {
+            return None;
+        }
+        let field = adt_def.all_fields().find(|f| f.name == name)?;
+        if !matches!(tcx.type_of(field.did).kind(), TyKind::RawPtr(_)) {
+            return None;
+        }
+        let cast_ty_span = match arg.kind {
+            ExprKind::Cast(_, ty) => Some(ty.span),
+            _ => None,
+        };
+        fields.push(FreedField {
+            did: field.did,
+            name,
+            cast_ty_span,
+        });
+        free_span.get_or_insert(func_span);
+    }
+
+    Some(DropImpl {
+        did,
+        drop_fn: ldid,
+        free_span: free_span?,
+        self_ty_span,
+        fields,
+        writes: Vec::new(),
+    })
+}
+
+/// If `ex` is a call to `free`, return the span of the callee and the argument.
+fn free_arg<'a, 'tcx>(m: &Matcher<'tcx>, ex: &'a Expr<'tcx>) -> Option<(Span, &'a Expr<'tcx>)> {
+    match ex.kind {
+        ExprKind::Call(func, [arg]) if m.is_call_to(ex, "free") => Some((func.span, arg)),
+        _ => None,
+    }
+}
+
+/// Whether `if cond { then }` is `if p.is_null() { return; }`.
+fn is_null_check<'tcx>(
+    m: &Matcher<'tcx>,
+    cond: &Expr<'tcx>,
+    then: &Expr<'tcx>,
+    p_id: HirId,
+) -> bool {
+    match m.peel(cond).kind {
+        ExprKind::MethodCall(ps, [recv], _)
+            if ps.ident.as_str() == "is_null" && m.local(recv) == Some(p_id) => {}
+        _ => return false,
+    }
+    let block = match then.kind {
+        ExprKind::Block(block, None) => block,
+        _ => return false,
+    };
+    let ret = match (block.stmts, block.expr) {
+        ([stmt], None) => match stmt.kind {
+            StmtKind::Expr(ex) | StmtKind::Semi(ex) => ex,
+            _ => return false,
+        },
+        ([], Some(ex)) => ex,
+        _ => return false,
+    };
+    matches!(ret.kind, ExprKind::Ret(None))
+}
+
+/// Generate the rewrites for the structs found by [`find_drop_impls`].  This runs after the
+/// analysis, so the impl can name any lifetime parameters the analysis adds to the struct.
+pub fn gen_drop_impl_rewrites(
+    gacx: &GlobalAnalysisCtxt,
+    drop_impls: &[DropImpl],
+) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let sm = tcx.sess.source_map();
+    let mut rewrites = Vec::new();
+    for di in drop_impls {
+        if let Some(span) = find_copy_impl(tcx, di.did).and_then(|did| copy_derive_span(tcx, did)) {
+            rewrites.push((span, Rewrite::Print(String::new())));
+        }
+
+        let lifetimes = gacx
+            .adt_metadata
+            .table
+            .get(&di.did)
+            .into_iter()
+            .flat_map(|adt| adt.lifetime_params.iter())
+            .filter(|p| matches!(p, OriginParam::Hypothetical(_)))
+            .map(|p| format!("{p:?}"))
+            .collect::<Vec<_>>();
+        // The impl takes the same lifetime parameters as the struct.
+        let params = if lifetimes.is_empty() {
+            String::new()
+        } else {
+            format!("<{}>", lifetimes.join(","))
+        };
+        let self_ty = sm.span_to_snippet(di.self_ty_span).unwrap();
+        let free = sm.span_to_snippet(di.free_span).unwrap();
+        let mut s = format!(
+            "\n\nimpl{params} Drop for {self_ty}{params} {{\n    \
+             fn drop(&mut self) {{\n        unsafe {{\n"
+        );
+        for field in &di.fields {
+            let name = field.name;
+            match field.cast_ty_span {
+                Some(span) => {
+                    let cast_ty = sm.span_to_snippet(span).unwrap();
+                    s.push_str(&format!("            {free}(self.{name} as {cast_ty});\n"));
+                }
+                None => s.push_str(&format!("            {free}(self.{name});\n")),
+            }
+        }
+        s.push_str("        }\n    }\n}");
+        let fn_span = tcx.hir().expect_owner(di.drop_fn).span();
+        rewrites.push((fn_span.shrink_to_hi(), Rewrite::Text(s)));
+
+        for &(span, lhs_span, rhs_span) in &di.writes {
+            // The old value may be uninitialized, as in a struct allocated with `malloc`, so it's
+            // overwritten without being dropped.
+            rewrites.push((
+                span,
+                Rewrite::Call(
+                    "std::ptr::write".into(),
+                    vec![
+                        Rewrite::AddrOf(Box::new(Rewrite::Sub(0, lhs_span)), Mutability::Mut),
+                        Rewrite::Sub(1, rhs_span),
+                    ],
+                ),
+            ));
+        }
+    }
+    rewrites
+}
+
+/// Checks the uses of each struct in `structs` within a single body.  Structs that are used in
+/// ways a `Drop` type doesn't support are added to `rejected`.
+struct UseVisitor<'a, 'tcx> {
+    m: Matcher<'tcx>,
+    structs: &'a mut HashMap<DefId, DropImpl>,
+    rejected: &'a mut HashSet<DefId>,
+}
+
+impl<'a, 'tcx> UseVisitor<'a, 'tcx> {
+    /// If `ty` is one of the structs, return its `DefId`.
+    fn drop_struct(&self, ty: Ty<'tcx>) -> Option<DefId> {
+        match *ty.kind() {
+            TyKind::Adt(adt_def, _) if self.structs.contains_key(&adt_def.did()) => {
+                Some(adt_def.did())
+            }
+            _ => None,
+        }
+    }
+
+    /// Reject the struct if `ex` copies it out of a pointer or another place, which is no longer
+    /// possible once it implements `Drop`.
+    fn check_by_value(&mut self, ex: &Expr<'tcx>) {
+        if !matches!(
+            ex.kind,
+            ExprKind::Unary(UnOp::Deref, _) | ExprKind::Field(..) | ExprKind::Index(..)
+        ) {
+            return;
+        }
+        if let Some(did) = self.drop_struct(self.m.typeck_results.expr_ty(ex)) {
+            self.rejected.insert(did);
+        }
+    }
+
+    /// Reject structs freed by `free(arg)` outside their destructor, either directly or through
+    /// one of the fields the destructor frees.
+    fn check_free(&mut self, arg: &Expr<'tcx>) {
+        let arg = self.m.peel(arg);
+        if let TyKind::RawPtr(mt) = *self.m.typeck_results.expr_ty(arg).kind() {
+            if let Some(did) = self.drop_struct(mt.ty) {
+                self.rejected.insert(did);
+            }
+        }
+        if let Some((did, name, _)) = self.m.struct_field(arg) {
+            let freed = self
+                .structs
+                .get(&did)
+                .map_or(false, |di| di.fields.iter().any(|f| f.name == name));
+            if freed {
+                self.rejected.insert(did);
+            }
+        }
+    }
+}
+
+// Nested bodies, such as closures, are visited separately as their own body owners.
+impl<'a, 'tcx> Visitor<'tcx> for UseVisitor<'a, 'tcx> {
+    fn visit_pat(&mut self, pat: &'tcx Pat<'tcx>) {
+        if let PatKind::Binding(..) = pat.kind {
+            let ty = self.m.typeck_results.pat_ty(pat);
+            self.rejected
+                .extend(self.structs.keys().filter(|&&did| holds_by_value(ty, did)));
+        }
+        intravisit::walk_pat(self, pat);
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            ExprKind::Call(_, [arg]) if self.m.is_call_to(ex, "free") => self.check_free(arg),
+
+            ExprKind::Assign(lhs, rhs, _) => {
+                self.check_by_value(rhs);
+                if let Some(did) = self.drop_struct(self.m.typeck_results.expr_ty(lhs)) {
+                    self.structs
+                        .get_mut(&did)
+                        .unwrap()
+                        .writes
+                        .push((ex.span, lhs.span, rhs.span));
+                }
+            }
+
+            ExprKind::Call(_, args) | ExprKind::MethodCall(_, [_, args @ ..], _) => {
+                for arg in args {
+                    self.check_by_value(arg);
+                }
+            }
+
+            ExprKind::Ret(Some(e)) | ExprKind::Block(&Block { expr: Some(e), .. }, _) => {
+                self.check_by_value(e)
+            }
+
+            _ => {}
+        }
+
+        intravisit::walk_expr(self, ex);
+    }
+}
@@ -0,0 +1,188 @@
+//! Handling of rewrites that fall inside macro expansions.
+//!
+//! The spans of code produced by a macro point either into the macro definition (for tokens that
+//! come from the body of a `macro_rules!` macro) or to some other location, such as the macro call
+//! site or a file pulled in with `include!` (which is how `build.rs`-generated code is usually
+//! brought into a crate).  Applying rewrites to these spans directly would corrupt the macro
+//! definition or edit a generated file, so the rewrites for each function are checked first:
+//!
+//! * Rewrites outside any macro expansion, including rewrites of compiler desugarings such as
+//!   `for` loops, are applied as usual.
+//! * Rewrites of tokens from the body of a `macro_rules!` macro defined in the current crate are
+//!   applied to the macro definition, but only if every expansion of that code receives the same
+//!   rewrite.
+//! * Any other rewrite inside a macro expansion causes the function to be left unrewritten, with
+//!   a diagnostic explaining which macro was responsible.
+
+use crate::rewrite::Rewrite;
+use rustc_hir::def_id::LocalDefId;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::Expr;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::hygiene::{ExpnKind, MacroKind};
+use rustc_span::{Span, SyntaxContext};
+use std::collections::HashMap;
+
+/// Check whether `span` comes from a macro expansion.  Returns `Ok(None)` if it doesn't, or
+/// `Ok(Some(def_span))` if it comes from the body of a local `macro_rules!` macro, where
+/// `def_span` is the span of the corresponding text in the macro definition.  Returns an error
+/// describing the macro if `span` comes from any other kind of expansion.
+fn macro_def_span(span: Span) -> Result<Option<Span>, String> {
+    if !span.from_expansion() {
+        return Ok(None);
+    }
+    let expn_data = span.ctxt().outer_expn_data();
+    match expn_data.kind {
+        ExpnKind::Macro(MacroKind::Bang, _) => {
+            let is_local = expn_data.macro_def_id.map_or(false, |did| did.is_local());
+            let def_span = span.with_ctxt(SyntaxContext::root());
+            if is_local && expn_data.def_site.contains(def_span) {
+                Ok(Some(def_span))
+            } else {
+                Err(format!(
+                    "code at {:?} is generated by {}",
+                    span,
+                    expn_data.kind.descr()
+                ))
+            }
+        }
+        ExpnKind::Macro(..) => Err(format!(
+            "code at {:?} is generated by {}",
+            span,
+            expn_data.kind.descr()
+        )),
+        // Desugarings are fine as long as the desugared code isn't itself inside a macro.
+        ExpnKind::AstPass(_) | ExpnKind::Desugaring(_) => {
+            match macro_def_span(expn_data.call_site)? {
+                None => Ok(None),
+                Some(_) => Err(format!(
+                    "code at {:?} is a {} inside a macro",
+                    span,
+                    expn_data.kind.descr()
+                )),
+            }
+        }
+        ExpnKind::Root | ExpnKind::Inlined => Ok(None),
+    }
+}
+
+struct MacroExprVisitor<'a> {
+    sites: &'a mut HashMap<Span, usize>,
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for MacroExprVisitor<'a> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if let Ok(Some(def_span)) = macro_def_span(ex.span) {
+            *self.sites.entry(def_span).or_insert(0) += 1;
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Count the expressions in all bodies that come from each part of the body of a local
+/// `macro_rules!` macro.  The keys of the map are spans within macro definitions.
+pub fn collect_macro_expr_sites(tcx: TyCtxt) -> HashMap<Span, usize> {
+    let mut sites = HashMap::new();
+    for owner in tcx.hir().body_owners() {
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(owner) {
+            Some(x) => x,
+            None => continue,
+        };
+        let mut v = MacroExprVisitor { sites: &mut sites };
+        v.visit_body(tcx.hir().body(hir_body_id));
+    }
+    sites
+}
+
+/// Split `rws` into rewrites outside of macro expansions and rewrites within the body of a local
+/// `macro_rules!` macro.  The spans of the latter are converted to point at the macro definition.
+/// Returns an error if some rewrite can't be applied, either because it's in some other kind of
+/// macro expansion or because it mixes code from inside and outside the macro.
+#[allow(clippy::type_complexity)]
+pub fn split_macro_rewrites(
+    rws: Vec<(Span, Rewrite)>,
+) -> Result<(Vec<(Span, Rewrite)>, Vec<(Span, Rewrite)>), String> {
+    let mut normal_rws = Vec::new();
+    let mut macro_rws = Vec::new();
+    for (span, rw) in rws {
+        match macro_def_span(span)? {
+            None => {
+                // Any spans mentioned within `rw` must also be outside of macros.
+                rw.try_map_spans(&mut |sub_span| match macro_def_span(sub_span)? {
+                    None => Ok(sub_span),
+                    Some(_) => Err(format!(
+                        "rewrite at {:?} refers to code inside a macro at {:?}",
+                        span, sub_span
+                    )),
+                })?;
+                normal_rws.push((span, rw));
+            }
+            Some(def_span) => {
+                let rw = rw.try_map_spans(&mut |sub_span| match macro_def_span(sub_span)? {
+                    Some(sub_def_span) => Ok(sub_def_span),
+                    None => Err(format!(
+                        "rewrite at {:?} inside a macro refers to code outside the macro at {:?}",
+                        span, sub_span
+                    )),
+                })?;
+                macro_rws.push((def_span, rw));
+            }
+        }
+    }
+    Ok((normal_rws, macro_rws))
+}
+
+/// Rewrites of `macro_rules!` definitions, collected from all functions that use the macros.
+#[derive(Clone, Debug, Default)]
+pub struct MacroDefRewrites {
+    rewrites: HashMap<Span, Vec<(LocalDefId, Rewrite)>>,
+}
+
+impl MacroDefRewrites {
+    pub fn clear(&mut self) {
+        self.rewrites.clear();
+    }
+
+    /// Record the macro definition rewrites produced for function `ldid`.
+    pub fn add(&mut self, ldid: LocalDefId, rws: Vec<(Span, Rewrite)>) {
+        for (span, rw) in rws {
+            self.rewrites.entry(span).or_default().push((ldid, rw));
+        }
+    }
+
+    /// Check that each rewritten part of a macro definition received the same rewrite in every
+    /// expansion, as counted by `collect_macro_expr_sites`.  Returns the rewrites to apply to the
+    /// macro definitions, along with a list of functions that must be left unrewritten because
+    /// their expansions disagree with some other use of the macro.
+    #[allow(clippy::type_complexity)]
+    pub fn finish(
+        &self,
+        expr_sites: &HashMap<Span, usize>,
+    ) -> (Vec<(Span, Rewrite)>, Vec<(LocalDefId, String)>) {
+        let mut spans = self.rewrites.keys().copied().collect::<Vec<_>>();
+        spans.sort_by_key(|span| (span.lo(), span.hi()));
+
+        let mut rws = Vec::new();
+        let mut failed = Vec::new();
+        for span in spans {
+            let uses = &self.rewrites[&span];
+            let (_, ref first_rw) = uses[0];
+            let all_same = uses.iter().all(|&(_, ref rw)| rw == first_rw);
+            let all_expansions = expr_sites.get(&span).copied().unwrap_or(0) == uses.len();
+            if all_same && all_expansions {
+                rws.push((span, first_rw.clone()));
+            } else {
+                for &(ldid, _) in uses {
+                    failed.push((
+                        ldid,
+                        format!(
+                            "macro code at {:?} needs different rewrites in different expansions",
+                            span
+                        ),
+                    ));
+                }
+            }
+        }
+        (rws, failed)
+    }
+}
@@ -1,8 +1,12 @@
 use crate::panic_detail;
 use crate::rewrite::expr::distribute::DistRewrite;
 use crate::rewrite::expr::mir_op::{self, ZeroizeType};
+use crate::rewrite::expr::printf;
+use crate::rewrite::expr::string;
 use crate::rewrite::expr::unlower::MirOriginDesc;
+use crate::rewrite::helpers::{self, HELPER_MODULE_PATH};
 use crate::rewrite::{LifetimeName, Rewrite};
+use crate::type_desc::Ownership;
 use assert_matches::assert_matches;
 use log::*;
 use rustc_hir as hir;
@@ -16,6 +20,7 @@ use rustc_middle::ty::{Ty, TyCtxt, TyKind, TypeckResults};
 use rustc_span::Span;
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fmt::Write as _;
 
 macro_rules! format_rewrite {
@@ -50,6 +55,13 @@ struct ConvertVisitor<'tcx> {
     /// only materialize adjustments within the children (and further descendants) of nodes that
     /// are already being rewritten for some other reason.
     materialize_adjustments: bool,
+    /// The format of the messages passed to `expect` in place of bare `unwrap` calls, or `None`
+    /// to emit `unwrap()`.  `{expr}` in the format is replaced with the source text of the
+    /// expression being unwrapped, and `{loc}` with its `file:line:col` location.
+    expect_format: Option<String>,
+    /// When `true`, slice operations such as `memcpy` are rewritten to calls into the generated
+    /// `c2rust_helpers` module instead of being expanded inline.
+    use_helpers: bool,
 }
 
 impl<'tcx> ConvertVisitor<'tcx> {
@@ -177,6 +189,12 @@ impl<'tcx> ConvertVisitor<'tcx> {
                 self.get_subexpr(ex, 0)
             }
 
+            mir_op::RewriteKind::AsPtrToSlice { method } => {
+                // `vec.as_ptr()` -> `vec.as_slice()`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                Rewrite::MethodCall(method.into(), Box::new(self.get_subexpr(ex, 0)), vec![])
+            }
+
             mir_op::RewriteKind::RemoveCast => {
                 // `x as T` -> `x`
                 match hir_rw {
@@ -224,77 +242,351 @@ impl<'tcx> ConvertVisitor<'tcx> {
             }
 
             mir_op::RewriteKind::MemcpySafe {
-                elem_size,
+                ref elem_ty,
                 dest_single,
                 src_single,
+                byte_len,
             } => {
                 // `memcpy(dest, src, n)` to a `copy_from_slice` call
                 assert!(matches!(hir_rw, Rewrite::Identity));
                 assert!(!dest_single, "&T -> &[T] conversion for memcpy dest NYI");
                 assert!(!src_single, "&T -> &[T] conversion for memcpy src NYI");
+                // A known constant length replaces the original argument.
+                let byte_len = match byte_len {
+                    Some(x) => Rewrite::Text(x.to_string()),
+                    None => self.get_subexpr(ex, 2),
+                };
+                if self.use_helpers {
+                    Rewrite::Call(
+                        format!("{HELPER_MODULE_PATH}::copy"),
+                        vec![
+                            self.get_subexpr(ex, 0),
+                            self.get_subexpr(ex, 1),
+                            Rewrite::Cast(
+                                Box::new(byte_len),
+                                Box::new(Rewrite::Print("usize".into())),
+                            ),
+                        ],
+                    )
+                } else {
+                    Rewrite::Block(
+                        vec![
+                            Rewrite::Let(vec![
+                                ("dest".into(), self.get_subexpr(ex, 0)),
+                                ("src".into(), self.get_subexpr(ex, 1)),
+                                ("byte_len".into(), byte_len),
+                            ]),
+                            format_rewrite!(
+                                "debug_assert_eq!(\
+                                 byte_len as usize % std::mem::size_of::<{elem_ty}>(), 0)"
+                            ),
+                            Rewrite::Let(vec![(
+                                "n".into(),
+                                format_rewrite!(
+                                    "byte_len as usize / std::mem::size_of::<{elem_ty}>()"
+                                ),
+                            )]),
+                            Rewrite::MethodCall(
+                                "copy_from_slice".into(),
+                                Box::new(format_rewrite!("dest[..n]")),
+                                vec![format_rewrite!("&src[..n]")],
+                            ),
+                        ],
+                        Some(Box::new(format_rewrite!("dest"))),
+                    )
+                }
+            }
+
+            mir_op::RewriteKind::MemmoveSafe {
+                ref elem_ty,
+                dest_single,
+                src_single,
+                dest_cell,
+                src_cell,
+            } => {
+                // `memmove(dest, src, n)` to a `copy_from_slice` call, or to a copy through an
+                // intermediate buffer if `dest` and `src` may overlap
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                assert!(!dest_single, "&T -> &[T] conversion for memmove dest NYI");
+                assert!(!src_single, "&T -> &[T] conversion for memmove src NYI");
+                let mut stmts = vec![
+                    Rewrite::Let(vec![
+                        ("dest".into(), self.get_subexpr(ex, 0)),
+                        ("src".into(), self.get_subexpr(ex, 1)),
+                        ("byte_len".into(), self.get_subexpr(ex, 2)),
+                    ]),
+                    Rewrite::Let(vec![(
+                        "n".into(),
+                        format_rewrite!("byte_len as usize / std::mem::size_of::<{elem_ty}>()"),
+                    )]),
+                ];
+                if dest_cell {
+                    let load = if src_cell {
+                        "src[..n].iter().map(std::cell::Cell::get).collect::<Vec<_>>()"
+                    } else {
+                        "src[..n].to_vec()"
+                    };
+                    stmts.push(Rewrite::Let(vec![(
+                        "tmp".into(),
+                        Rewrite::Text(load.into()),
+                    )]));
+                    stmts.push(format_rewrite!(
+                        "for (d, x) in dest[..n].iter().zip(tmp) {{\n    d.set(x);\n}}"
+                    ));
+                } else {
+                    stmts.push(Rewrite::MethodCall(
+                        "copy_from_slice".into(),
+                        Box::new(format_rewrite!("dest[..n]")),
+                        vec![format_rewrite!("&src[..n]")],
+                    ));
+                }
+                Rewrite::Block(stmts, Some(Box::new(format_rewrite!("dest"))))
+            }
+
+            mir_op::RewriteKind::MemcmpSafe {
+                ref elem_ty,
+                ordered,
+            } => {
+                // `memcmp(a, b, n)` to a comparison of `a[..n]` and `b[..n]`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let result = if ordered {
+                    format_rewrite!("a[..n].cmp(&b[..n]) as i32")
+                } else {
+                    format_rewrite!("(a[..n] != b[..n]) as i32")
+                };
                 Rewrite::Block(
                     vec![
                         Rewrite::Let(vec![
-                            ("dest".into(), self.get_subexpr(ex, 0)),
-                            ("src".into(), self.get_subexpr(ex, 1)),
+                            ("a".into(), self.get_subexpr(ex, 0)),
+                            ("b".into(), self.get_subexpr(ex, 1)),
                             ("byte_len".into(), self.get_subexpr(ex, 2)),
                         ]),
                         Rewrite::Let(vec![(
                             "n".into(),
-                            format_rewrite!("byte_len as usize / {elem_size}"),
+                            format_rewrite!("byte_len as usize / std::mem::size_of::<{elem_ty}>()"),
                         )]),
-                        Rewrite::MethodCall(
-                            "copy_from_slice".into(),
-                            Box::new(format_rewrite!("dest[..n]")),
-                            vec![format_rewrite!("&src[..n]")],
+                    ],
+                    Some(Box::new(result)),
+                )
+            }
+
+            mir_op::RewriteKind::CallReturnedSliceLen {
+                len_arg,
+                ref len_ty,
+            } => {
+                // `f(a, len)` to `f(a)`, followed by storing the length of the result to `*len`.
+                // All arguments are evaluated first, in their original order.
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let (func, num_args) = match ex.kind {
+                    hir::ExprKind::Call(func, args) => (func, args.len()),
+                    _ => panic!("expected a call for CallReturnedSliceLen, but got {:?}", ex),
+                };
+                let func_str = self
+                    .tcx
+                    .sess
+                    .source_map()
+                    .span_to_snippet(func.span)
+                    .unwrap();
+                let bindings = (0..num_args)
+                    .map(|i| (format!("arg{i}"), self.get_subexpr(ex, i)))
+                    .collect();
+                let call_args = (0..num_args)
+                    .filter(|&i| i != len_arg)
+                    .map(|i| format_rewrite!("arg{i}"))
+                    .collect();
+                Rewrite::Block(
+                    vec![
+                        Rewrite::Let(bindings),
+                        Rewrite::Let1(
+                            "result".into(),
+                            Box::new(Rewrite::Call(func_str, call_args)),
                         ),
+                        format_rewrite!("*arg{len_arg} = result.len() as {len_ty}"),
                     ],
-                    Some(Box::new(format_rewrite!("dest"))),
+                    Some(Box::new(format_rewrite!("result"))),
                 )
             }
 
+            mir_op::RewriteKind::MemchrSafe { ref elem_ty, mutbl } => {
+                // `memchr(p, c, n)` to a search of `p[..n]`, keeping the rest of `p` from the
+                // first match
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let ref_ = if mutbl { "&mut " } else { "&" };
+                Rewrite::Block(
+                    vec![Rewrite::Let(vec![
+                        ("p".into(), self.get_subexpr(ex, 0)),
+                        ("c".into(), self.get_subexpr(ex, 1)),
+                        ("n".into(), self.get_subexpr(ex, 2)),
+                    ])],
+                    Some(Box::new(format_rewrite!(
+                        "match p[..n as usize].iter().position(|&x| x == c as {elem_ty}) {{\n    \
+                            Some(i) => Some({ref_}p[i..]),\n    \
+                            None => None,\n\
+                        }}"
+                    ))),
+                )
+            }
+
+            mir_op::RewriteKind::QsortSafe {
+                ref elem_ty,
+                ref cmp_fn,
+                raw_cmp_args,
+            } => {
+                // `qsort(base, n, size, Some(cmp))` to a `sort_by` on `base[..n]` that orders
+                // elements by the sign of `cmp`'s result
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let cmp_args = if raw_cmp_args {
+                    format!("a as *const {elem_ty} as _, b as *const {elem_ty} as _")
+                } else {
+                    "a, b".to_owned()
+                };
+                Rewrite::Block(
+                    vec![Rewrite::Let(vec![
+                        ("base".into(), self.get_subexpr(ex, 0)),
+                        ("n".into(), self.get_subexpr(ex, 1)),
+                    ])],
+                    Some(Box::new(format_rewrite!(
+                        "base[..n as usize].sort_by(|a, b| {cmp_fn}({cmp_args}).cmp(&0))"
+                    ))),
+                )
+            }
+
+            mir_op::RewriteKind::BsearchSafe {
+                ref elem_ty,
+                ref cmp_fn,
+                raw_cmp_args,
+                mutbl,
+            } => {
+                // `bsearch(key, base, n, size, Some(cmp))` to a `binary_search_by` on `base[..n]`,
+                // keeping the rest of `base` from the match.  `cmp` compares the key to an
+                // element, so its result is reversed to order the element relative to the key.
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let ref_ = if mutbl { "&mut " } else { "&" };
+                let elem_arg = if raw_cmp_args {
+                    format!("e as *const {elem_ty} as _")
+                } else {
+                    "e".to_owned()
+                };
+                Rewrite::Block(
+                    vec![Rewrite::Let(vec![
+                        ("key".into(), self.get_subexpr(ex, 0)),
+                        ("base".into(), self.get_subexpr(ex, 1)),
+                        ("n".into(), self.get_subexpr(ex, 2)),
+                    ])],
+                    Some(Box::new(format_rewrite!(
+                        "match base[..n as usize]\
+                            .binary_search_by(|e| {cmp_fn}(key, {elem_arg}).cmp(&0).reverse()) {{\n    \
+                            Ok(i) => Some({ref_}base[i..]),\n    \
+                            Err(_) => None,\n\
+                        }}"
+                    ))),
+                )
+            }
+
+            mir_op::RewriteKind::StrSafe {
+                str_fn,
+                ref c_strs,
+                ref ret_ty,
+            } => {
+                // `strlen(s)` and the like to operations on slices
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let args = (0..str_fn.num_args())
+                    .map(|i| self.get_subexpr(ex, i))
+                    .collect();
+                string::convert_str_call(str_fn, args, c_strs, ret_ty)
+            }
+
+            mir_op::RewriteKind::SprintfSafe {
+                bounded,
+                ref pieces,
+                ref ret_ty,
+            } => {
+                // `sprintf(dest, fmt, ...)` to formatting into `dest` with `format_args!`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let num_args = printf::format_arg_index(bounded)
+                    + 1
+                    + pieces.iter().filter(|p| p.is_conversion()).count();
+                let args = (0..num_args).map(|i| self.get_subexpr(ex, i)).collect();
+                printf::convert_sprintf_call(bounded, pieces, args, ret_ty)
+            }
+
+            mir_op::RewriteKind::PrintfSafe {
+                fprintf,
+                stderr,
+                ref pieces,
+                ref ret_ty,
+            } => {
+                // `printf(fmt, ...)` to formatting into a buffer that's written to `stdout`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let num_args =
+                    fprintf as usize + 1 + pieces.iter().filter(|p| p.is_conversion()).count();
+                let args = (0..num_args).map(|i| self.get_subexpr(ex, i)).collect();
+                printf::convert_printf_call(fprintf, stderr, pieces, args, ret_ty)
+            }
+
             mir_op::RewriteKind::MemsetZeroize {
                 ref zero_ty,
-                elem_size,
+                ref elem_ty,
                 dest_single,
             } => {
                 // `memset(dest, 0, n)` to assignments that zero out each field of `*dest`
                 assert!(matches!(hir_rw, Rewrite::Identity));
-                let zeroize_body = if dest_single {
-                    Rewrite::Text(generate_zeroize_code(zero_ty, "(*dest)"))
+                if self.use_helpers && !dest_single {
+                    Rewrite::Call(
+                        format!("{HELPER_MODULE_PATH}::zeroize"),
+                        vec![
+                            self.get_subexpr(ex, 0),
+                            self.get_subexpr(ex, 1),
+                            Rewrite::Cast(
+                                Box::new(self.get_subexpr(ex, 2)),
+                                Box::new(Rewrite::Print("usize".into())),
+                            ),
+                            format_rewrite!("|| {}", generate_zeroize_expr(zero_ty)),
+                        ],
+                    )
                 } else {
-                    format_rewrite!(
-                        "for i in 0..n {{\n    {};\n}}",
-                        generate_zeroize_code(zero_ty, "(*dest)[i]")
+                    let zeroize_body = if dest_single {
+                        Rewrite::Text(generate_zeroize_code(zero_ty, "(*dest)"))
+                    } else {
+                        format_rewrite!(
+                            "for i in 0..n {{\n    {};\n}}",
+                            generate_zeroize_code(zero_ty, "(*dest)[i]")
+                        )
+                    };
+                    Rewrite::Block(
+                        vec![
+                            Rewrite::Let(vec![
+                                ("dest".into(), self.get_subexpr(ex, 0)),
+                                ("val".into(), self.get_subexpr(ex, 1)),
+                                ("byte_len".into(), self.get_subexpr(ex, 2)),
+                            ]),
+                            Rewrite::Let(vec![(
+                                "n".into(),
+                                format_rewrite!(
+                                    "byte_len as usize / std::mem::size_of::<{elem_ty}>()"
+                                ),
+                            )]),
+                            format_rewrite!("assert_eq!(val, 0, \"non-zero memset NYI\")"),
+                            zeroize_body,
+                        ],
+                        Some(Box::new(format_rewrite!("dest"))),
                     )
-                };
-                Rewrite::Block(
-                    vec![
-                        Rewrite::Let(vec![
-                            ("dest".into(), self.get_subexpr(ex, 0)),
-                            ("val".into(), self.get_subexpr(ex, 1)),
-                            ("byte_len".into(), self.get_subexpr(ex, 2)),
-                        ]),
-                        Rewrite::Let(vec![(
-                            "n".into(),
-                            format_rewrite!("byte_len as usize / {elem_size}"),
-                        )]),
-                        format_rewrite!("assert_eq!(val, 0, \"non-zero memset NYI\")"),
-                        zeroize_body,
-                    ],
-                    Some(Box::new(format_rewrite!("dest"))),
-                )
+                }
             }
 
             mir_op::RewriteKind::MallocSafe {
                 ref zero_ty,
-                elem_size,
+                ref elem_ty,
                 single,
+                option,
+                vec,
             }
             | mir_op::RewriteKind::CallocSafe {
                 ref zero_ty,
-                elem_size,
+                ref elem_ty,
                 single,
+                option,
+                vec,
             } => {
                 // `malloc(n)` -> `Box::new(z)` or similar
                 assert!(matches!(hir_rw, Rewrite::Identity));
@@ -304,7 +596,9 @@ impl<'tcx> ConvertVisitor<'tcx> {
                         Rewrite::Let(vec![("byte_len".into(), self.get_subexpr(ex, 0))]),
                         Rewrite::Let1(
                             "n".into(),
-                            Box::new(format_rewrite!("byte_len as usize / {elem_size}")),
+                            Box::new(format_rewrite!(
+                                "byte_len as usize / std::mem::size_of::<{elem_ty}>()"
+                            )),
                         ),
                     ],
                     mir_op::RewriteKind::CallocSafe { .. } => vec![
@@ -312,12 +606,21 @@ impl<'tcx> ConvertVisitor<'tcx> {
                             ("count".into(), self.get_subexpr(ex, 0)),
                             ("size".into(), self.get_subexpr(ex, 1)),
                         ]),
-                        format_rewrite!("assert_eq!(size, {elem_size})"),
+                        format_rewrite!(
+                            "assert_eq!(size as usize, std::mem::size_of::<{elem_ty}>())"
+                        ),
                         Rewrite::Let1("n".into(), Box::new(format_rewrite!("count as usize"))),
                     ],
                     _ => unreachable!(),
                 };
-                let expr = if single {
+                let expr = if single && option {
+                    // A zero-size allocation produces `None`, like a null return from `malloc`.
+                    format_rewrite!(
+                        "if n == 0 {{\n    None\n}} else {{\n    assert_eq!(n, 1);\n    \
+                         Some(Box::new({}))\n}}",
+                        zeroize_expr,
+                    )
+                } else if single {
                     stmts.push(Rewrite::Text("assert_eq!(n, 1)".into()));
                     format_rewrite!("Box::new({})", zeroize_expr)
                 } else {
@@ -329,26 +632,187 @@ impl<'tcx> ConvertVisitor<'tcx> {
                         "for i in 0..n {{\n    v.push({});\n}}",
                         zeroize_expr,
                     ));
-                    Rewrite::Text("v.into_boxed_slice()".into())
+                    if vec {
+                        Rewrite::Text("v".into())
+                    } else {
+                        Rewrite::Text("v.into_boxed_slice()".into())
+                    }
+                };
+                Rewrite::Block(stmts, Some(Box::new(expr)))
+            }
+
+            mir_op::RewriteKind::MallocUninit {
+                ref elem_ty,
+                option,
+            } => {
+                // `malloc(n)` -> `Box::new(MaybeUninit::uninit())`, converted to `Box<T>`.  The
+                // allocation is initialized by the `WriteUninit` that follows.
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let mut stmts = vec![
+                    Rewrite::Let(vec![("byte_len".into(), self.get_subexpr(ex, 0))]),
+                    Rewrite::Let1(
+                        "n".into(),
+                        Box::new(format_rewrite!(
+                            "byte_len as usize / std::mem::size_of::<{elem_ty}>()"
+                        )),
+                    ),
+                ];
+                let alloc = format!(
+                    "Box::from_raw(\n    \
+                     Box::into_raw(Box::new(std::mem::MaybeUninit::<{elem_ty}>::uninit()))\n        \
+                     .cast::<{elem_ty}>(),\n)"
+                );
+                let expr = if option {
+                    format_rewrite!(
+                        "if n == 0 {{\n    None\n}} else {{\n    assert_eq!(n, 1);\n    \
+                         Some({alloc})\n}}"
+                    )
+                } else {
+                    stmts.push(Rewrite::Text("assert_eq!(n, 1)".into()));
+                    Rewrite::Text(alloc)
                 };
                 Rewrite::Block(stmts, Some(Box::new(expr)))
             }
 
-            mir_op::RewriteKind::FreeSafe { single: _ } => {
+            mir_op::RewriteKind::FreeSafe { single: _, option } => {
                 // `free(p)` -> `drop(p)`
                 assert!(matches!(hir_rw, Rewrite::Identity));
-                Rewrite::Call("std::mem::drop".to_string(), vec![self.get_subexpr(ex, 0)])
+                if option {
+                    // `free(NULL)` is a no-op.  This moves out of `p` rather than calling
+                    // `p.take()`, which would require `p` to be a mutable place.
+                    Rewrite::Block(
+                        vec![
+                            Rewrite::Let1("ptr".into(), Box::new(self.get_subexpr(ex, 0))),
+                            Rewrite::Text("if let Some(b) = ptr { drop(b) }".into()),
+                        ],
+                        None,
+                    )
+                } else {
+                    Rewrite::Call("std::mem::drop".to_string(), vec![self.get_subexpr(ex, 0)])
+                }
+            }
+
+            mir_op::RewriteKind::ReallocVec {
+                ref zero_ty,
+                ref elem_ty,
+                src_option,
+            } => {
+                // `realloc(p, n)` -> `p.resize_with(...)`, reusing the `Vec`'s allocation
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let zeroize_expr = generate_zeroize_expr(zero_ty);
+                let mut stmts = vec![
+                    Rewrite::Let(vec![
+                        ("src_ptr".into(), self.get_subexpr(ex, 0)),
+                        ("dest_byte_len".into(), self.get_subexpr(ex, 1)),
+                    ]),
+                    Rewrite::Let1(
+                        "dest_n".into(),
+                        Box::new(format_rewrite!(
+                            "dest_byte_len as usize / std::mem::size_of::<{elem_ty}>()"
+                        )),
+                    ),
+                ];
+                // `realloc(NULL, n)` acts like `malloc(n)`, as in `ReallocSafe`.
+                let src_ptr = if src_option {
+                    "src_ptr.unwrap_or_default()"
+                } else {
+                    "src_ptr"
+                };
+                stmts.push(Rewrite::Let1(
+                    "mut dest_ptr".into(),
+                    Box::new(Rewrite::Text(src_ptr.into())),
+                ));
+                stmts.push(format_rewrite!(
+                    "dest_ptr.resize_with(dest_n, || {})",
+                    zeroize_expr,
+                ));
+                Rewrite::Block(stmts, Some(Box::new(Rewrite::Text("dest_ptr".into()))))
+            }
+
+            mir_op::RewriteKind::StrdupSafe {
+                bounded,
+                src_c_str,
+                c_string,
+            } if src_c_str || c_string => {
+                // `strdup(s)` -> `s.to_owned()` and the like, for `CStr` and `CString`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let num_args = if bounded { 2 } else { 1 };
+                let args = (0..num_args).map(|i| self.get_subexpr(ex, i)).collect();
+                string::convert_c_strdup_call(bounded, src_c_str, c_string, args)
+            }
+            mir_op::RewriteKind::StrdupSafe { bounded, .. } => {
+                // `strdup(s)` -> `Box::from(&s[..=len])`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                if bounded {
+                    // At most `n` characters are copied, followed by a NUL.
+                    Rewrite::Block(
+                        vec![
+                            Rewrite::Let(vec![
+                                ("s".into(), self.get_subexpr(ex, 0)),
+                                ("n".into(), self.get_subexpr(ex, 1)),
+                            ]),
+                            Rewrite::Let1("n".into(), Box::new(format_rewrite!("n as usize"))),
+                            Rewrite::Let1(
+                                "len".into(),
+                                Box::new(format_rewrite!(
+                                    "s.iter().take(n).position(|&c| c == 0).unwrap_or(n)"
+                                )),
+                            ),
+                            Rewrite::Let1(
+                                "mut v".into(),
+                                Box::new(format_rewrite!("s[..len].to_vec()")),
+                            ),
+                            format_rewrite!("v.push(0)"),
+                        ],
+                        Some(Box::new(format_rewrite!("v.into_boxed_slice()"))),
+                    )
+                } else {
+                    Rewrite::Block(
+                        vec![
+                            Rewrite::Let(vec![("s".into(), self.get_subexpr(ex, 0))]),
+                            Rewrite::Let1(
+                                "len".into(),
+                                Box::new(format_rewrite!(
+                                    "s.iter().position(|&c| c == 0).unwrap()"
+                                )),
+                            ),
+                        ],
+                        Some(Box::new(format_rewrite!("Box::from(&s[..=len])"))),
+                    )
+                }
             }
 
             mir_op::RewriteKind::ReallocSafe {
                 ref zero_ty,
-                elem_size,
+                ref elem_ty,
                 src_single,
                 dest_single,
+                src_option,
+                dest_option,
             } => {
                 // `realloc(p, n)` -> `Box::new(...)`
                 assert!(matches!(hir_rw, Rewrite::Identity));
                 let zeroize_expr = generate_zeroize_expr(zero_ty);
+                if self.use_helpers && !src_single && !dest_single {
+                    let src_ptr = self.get_subexpr(ex, 0);
+                    let src_ptr = if src_option {
+                        // `realloc(NULL, n)` acts like `malloc(n)`, as below.
+                        Rewrite::MethodCall("unwrap_or_default".into(), Box::new(src_ptr), vec![])
+                    } else {
+                        src_ptr
+                    };
+                    return Rewrite::Call(
+                        format!("{HELPER_MODULE_PATH}::realloc"),
+                        vec![
+                            src_ptr,
+                            Rewrite::Cast(
+                                Box::new(self.get_subexpr(ex, 1)),
+                                Box::new(Rewrite::Print("usize".into())),
+                            ),
+                            format_rewrite!("|| {}", zeroize_expr),
+                        ],
+                    );
+                }
                 let mut stmts = vec![
                     Rewrite::Let(vec![
                         ("src_ptr".into(), self.get_subexpr(ex, 0)),
@@ -356,10 +820,19 @@ impl<'tcx> ConvertVisitor<'tcx> {
                     ]),
                     Rewrite::Let1(
                         "dest_n".into(),
-                        Box::new(format_rewrite!("dest_byte_len as usize / {elem_size}")),
+                        Box::new(format_rewrite!(
+                            "dest_byte_len as usize / std::mem::size_of::<{elem_ty}>()"
+                        )),
                     ),
                 ];
-                if dest_single {
+                if src_option && !src_single {
+                    // `realloc(NULL, n)` acts like `malloc(n)`, so treat `None` as an empty slice.
+                    stmts.push(Rewrite::Let1(
+                        "src_ptr".into(),
+                        Box::new(Rewrite::Text("src_ptr.unwrap_or_default()".into())),
+                    ));
+                }
+                if dest_single && !dest_option {
                     stmts.push(Rewrite::Text("assert_eq!(dest_n, 1)".into()));
                 }
                 let expr = match (src_single, dest_single) {
@@ -372,10 +845,10 @@ impl<'tcx> ConvertVisitor<'tcx> {
                             "dest_ptr.resize_with(dest_n, || {})",
                             zeroize_expr,
                         ));
-                        Rewrite::Text("dest_ptr.into_boxed_slice()".into())
+                        "dest_ptr.into_boxed_slice()".to_owned()
                     }
                     (false, true) => {
-                        format_rewrite!(
+                        format!(
                             "src_ptr.into_iter().next().unwrap_or_else(|| {})",
                             zeroize_expr
                         )
@@ -385,39 +858,93 @@ impl<'tcx> ConvertVisitor<'tcx> {
                             "mut dest_ptr".into(),
                             Box::new(Rewrite::Text("Vec::with_capacity(dest_n)".into())),
                         ));
-                        stmts.push(Rewrite::Text(
-                            "if dest_n >= 1 { dest_ptr.push(*src_ptr); }".into(),
-                        ));
+                        if src_option {
+                            stmts.push(Rewrite::Text(
+                                "if let Some(src_ptr) = src_ptr {\n    \
+                                 if dest_n >= 1 { dest_ptr.push(*src_ptr); }\n}"
+                                    .into(),
+                            ));
+                        } else {
+                            stmts.push(Rewrite::Text(
+                                "if dest_n >= 1 { dest_ptr.push(*src_ptr); }".into(),
+                            ));
+                        }
                         stmts.push(format_rewrite!(
                             "dest_ptr.resize_with(dest_n, || {})",
                             zeroize_expr,
                         ));
-                        Rewrite::Text("dest_ptr.into_boxed_slice()".into())
+                        "dest_ptr.into_boxed_slice()".to_owned()
+                    }
+                    (true, true) if src_option => {
+                        format!("src_ptr.unwrap_or_else(|| Box::new({}))", zeroize_expr)
                     }
-                    (true, true) => Rewrite::Text("src_ptr".into()),
+                    (true, true) => "src_ptr".to_owned(),
                 };
-                Rewrite::Block(stmts, Some(Box::new(expr)))
+                let expr = if dest_option {
+                    // `realloc(p, 0)` frees `p` and may return null.
+                    format!(
+                        "if dest_n == 0 {{\n    None\n}} else {{\n    \
+                         assert_eq!(dest_n, 1);\n    Some({expr})\n}}"
+                    )
+                } else {
+                    expr
+                };
+                Rewrite::Block(stmts, Some(Box::new(Rewrite::Text(expr))))
             }
 
-            mir_op::RewriteKind::CellGet => {
-                // `*x` to `Cell::get(x)`
+            mir_op::RewriteKind::CellGet { slice } => {
+                // `*x` to `Cell::get(x)`, or `x[0].get()` if `x` is a slice of `Cell`s
                 assert!(matches!(hir_rw, Rewrite::Identity));
-                Rewrite::MethodCall("get".to_string(), Box::new(self.get_subexpr(ex, 0)), vec![])
+                let cell = self.get_subexpr(ex, 0);
+                let cell = if slice { first_elem(cell) } else { cell };
+                Rewrite::MethodCall("get".to_string(), Box::new(cell), vec![])
             }
 
-            mir_op::RewriteKind::CellSet => {
-                // `*x` to `Cell::set(x)`
+            mir_op::RewriteKind::CellSet { slice } => {
+                // `*x` to `Cell::set(x)`, or `x[0].set(..)` if `x` is a slice of `Cell`s
                 assert!(matches!(hir_rw, Rewrite::Identity));
                 let deref_lhs = assert_matches!(ex.kind, ExprKind::Assign(lhs, ..) => lhs);
                 let lhs = self.get_subexpr(deref_lhs, 0);
+                let lhs = if slice { first_elem(lhs) } else { lhs };
                 let rhs = self.get_subexpr(ex, 1);
                 Rewrite::MethodCall("set".to_string(), Box::new(lhs), vec![rhs])
             }
 
-            _ => convert_cast_rewrite(rw, hir_rw),
+            mir_op::RewriteKind::WriteUninit => {
+                // `*p = x` -> `std::ptr::write(&mut *p, x)`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let deref_lhs = assert_matches!(ex.kind, ExprKind::Assign(lhs, ..) => lhs);
+                let ptr = self.get_subexpr(deref_lhs, 0);
+                let rhs = self.get_subexpr(ex, 1);
+                let dest = Rewrite::Ref(
+                    Box::new(Rewrite::Deref(Box::new(ptr))),
+                    hir::Mutability::Mut,
+                );
+                Rewrite::Call("std::ptr::write".to_string(), vec![dest, rhs])
+            }
+
+            _ => convert_cast_rewrite_with_message(rw, hir_rw, self.expect_message(ex).as_deref()),
         }
     }
 
+    /// Build the `expect` message for an unwrap of `ex`, if `--expect-message` is enabled.
+    fn expect_message(&self, ex: &'tcx hir::Expr<'tcx>) -> Option<String> {
+        let format = self.expect_format.as_ref()?;
+        let source_map = self.tcx.sess.source_map();
+        let expr = match source_map.span_to_snippet(ex.span) {
+            Ok(snippet) => snippet.split_whitespace().collect::<Vec<_>>().join(" "),
+            Err(_) => "<expr>".to_owned(),
+        };
+        let pos = source_map.lookup_char_pos(ex.span.lo());
+        let loc = format!(
+            "{}:{}:{}",
+            pos.file.name.prefer_local(),
+            pos.line,
+            pos.col.0 + 1
+        );
+        Some(format.replace("{expr}", &expr).replace("{loc}", &loc))
+    }
+
     /// Generate an `Option::map` call from the rewrites in `mir_rws`.  After seeing an
     /// `OptionMapBegin` in a list of MIR rewrites, pass the remaining rewrites to this method.  If
     /// it returns `Ok((new_hir_rw, remaining_mir_rws))`, then the `OptionMapBegin` and some
@@ -678,7 +1205,14 @@ fn materialize_adjustments<'tcx>(
 fn generate_zeroize_code(zero_ty: &ZeroizeType, lv: &str) -> String {
     match *zero_ty {
         ZeroizeType::Int => format!("{lv} = 0"),
+        ZeroizeType::Float => format!("{lv} = 0.0"),
         ZeroizeType::Bool => format!("{lv} = false"),
+        // Unions are also assigned as a whole, which is safe, unlike writing one of their fields.
+        ZeroizeType::NullPtr { .. }
+        | ZeroizeType::None
+        | ZeroizeType::EmptySlice(_)
+        | ZeroizeType::Enum(_)
+        | ZeroizeType::Union(..) => format!("{lv} = {}", generate_zeroize_expr(zero_ty)),
         ZeroizeType::Array(ref elem_zero_ty) => format!(
             "
             {{
@@ -711,7 +1245,24 @@ fn generate_zeroize_code(zero_ty: &ZeroizeType, lv: &str) -> String {
 fn generate_zeroize_expr(zero_ty: &ZeroizeType) -> String {
     match *zero_ty {
         ZeroizeType::Int => format!("0"),
+        ZeroizeType::Float => format!("0.0"),
         ZeroizeType::Bool => format!("false"),
+        ZeroizeType::NullPtr { mutbl: false } => format!("std::ptr::null()"),
+        ZeroizeType::NullPtr { mutbl: true } => format!("std::ptr::null_mut()"),
+        ZeroizeType::None => format!("None"),
+        ZeroizeType::EmptySlice(own) => match own {
+            Ownership::Imm => format!("&[]"),
+            Ownership::Mut => format!("&mut []"),
+            Ownership::Box => format!("Box::new([])"),
+            _ => panic!("no empty slice for ownership {own:?}"),
+        },
+        ZeroizeType::Enum(ref path) => path.clone(),
+        ZeroizeType::Union(ref name, ref field, ref field_zero_ty) => format!(
+            "{} {{ {}: {} }}",
+            name,
+            field,
+            generate_zeroize_expr(field_zero_ty)
+        ),
         ZeroizeType::Array(ref elem_zero_ty) => format!(
             "std::array::from_fn(|| {})",
             generate_zeroize_expr(elem_zero_ty)
@@ -739,6 +1290,16 @@ fn take_prefix_while<'a, T>(slice: &mut &'a [T], mut pred: impl FnMut(&'a T) ->
 /// on rewrites that modify the original expression; only rewrites that wrap the expression in some
 /// kind of cast or conversion are supported.
 pub fn convert_cast_rewrite(kind: &mir_op::RewriteKind, hir_rw: Rewrite) -> Rewrite {
+    convert_cast_rewrite_with_message(kind, hir_rw, None)
+}
+
+/// Like `convert_cast_rewrite`, but unwraps of `Option`s and `Result`s are emitted as
+/// `expect(msg)` instead of `unwrap()` when `expect_msg` is provided.
+fn convert_cast_rewrite_with_message(
+    kind: &mir_op::RewriteKind,
+    hir_rw: Rewrite,
+    expect_msg: Option<&str>,
+) -> Rewrite {
     match *kind {
         mir_op::RewriteKind::SliceFirst { mutbl } => {
             // `p` -> `&p[0]`
@@ -760,7 +1321,20 @@ pub fn convert_cast_rewrite(kind: &mir_op::RewriteKind, hir_rw: Rewrite) -> Rewr
 
         mir_op::RewriteKind::OptionUnwrap => {
             // `p` -> `p.unwrap()`
-            Rewrite::MethodCall("unwrap".to_string(), Box::new(hir_rw), vec![])
+            unwrap_rewrite(hir_rw, expect_msg)
+        }
+        mir_op::RewriteKind::OptionUnwrapOrNull { mutbl } => {
+            // `p` -> `p.unwrap_or(std::ptr::null())`
+            let null = if mutbl {
+                "std::ptr::null_mut()"
+            } else {
+                "std::ptr::null()"
+            };
+            Rewrite::MethodCall(
+                "unwrap_or".to_string(),
+                Box::new(hir_rw),
+                vec![Rewrite::Text(null.into())],
+            )
         }
         mir_op::RewriteKind::OptionSome => {
             // `p` -> `Some(p)`
@@ -795,9 +1369,67 @@ pub fn convert_cast_rewrite(kind: &mir_op::RewriteKind, hir_rw: Rewrite) -> Rewr
             Rewrite::MethodCall(ref_method, Box::new(hir_rw), vec![])
         }
 
-        mir_op::RewriteKind::DynOwnedUnwrap => {
-            Rewrite::MethodCall("unwrap".to_string(), Box::new(hir_rw), vec![])
+        mir_op::RewriteKind::Clone => {
+            // `p` -> `p.clone()`
+            Rewrite::MethodCall("clone".to_string(), Box::new(hir_rw), vec![])
+        }
+
+        mir_op::RewriteKind::RcRefCellFromBox => {
+            // `p` -> `std::rc::Rc::new(std::cell::RefCell::new(*p))`
+            let hir_rw = Rewrite::Call(
+                "std::cell::RefCell::new".to_string(),
+                vec![Rewrite::Deref(Box::new(hir_rw))],
+            );
+            Rewrite::Call("std::rc::Rc::new".to_string(), vec![hir_rw])
+        }
+        mir_op::RewriteKind::RefCellBorrow { mutbl } => {
+            // `p` -> `&*p.borrow()` or `&mut *p.borrow_mut()`
+            let method = if mutbl { "borrow_mut" } else { "borrow" };
+            let hir_rw = Rewrite::MethodCall(method.to_string(), Box::new(hir_rw), vec![]);
+            Rewrite::Ref(
+                Box::new(Rewrite::Deref(Box::new(hir_rw))),
+                mutbl_from_bool(mutbl),
+            )
+        }
+
+        mir_op::RewriteKind::ArcMutexFromBox => {
+            // `p` -> `std::sync::Arc::new(std::sync::Mutex::new(*p))`
+            let hir_rw = Rewrite::Call(
+                "std::sync::Mutex::new".to_string(),
+                vec![Rewrite::Deref(Box::new(hir_rw))],
+            );
+            Rewrite::Call("std::sync::Arc::new".to_string(), vec![hir_rw])
         }
+        mir_op::RewriteKind::MutexLock { mutbl } => {
+            // `p` -> `&*p.lock().unwrap()` or `&mut *p.lock().unwrap()`
+            let hir_rw = Rewrite::MethodCall("lock".to_string(), Box::new(hir_rw), vec![]);
+            let hir_rw = Rewrite::MethodCall("unwrap".to_string(), Box::new(hir_rw), vec![]);
+            Rewrite::Ref(
+                Box::new(Rewrite::Deref(Box::new(hir_rw))),
+                mutbl_from_bool(mutbl),
+            )
+        }
+        mir_op::RewriteKind::ArcIntoRaw => {
+            // `p` -> `std::sync::Arc::into_raw(p).cast_mut()`
+            let hir_rw = Rewrite::Call("std::sync::Arc::into_raw".to_string(), vec![hir_rw]);
+            Rewrite::MethodCall("cast_mut".to_string(), Box::new(hir_rw), vec![])
+        }
+        mir_op::RewriteKind::ArcFromRaw { ref ty } => {
+            // `p` -> `std::sync::Arc::from_raw(p as *const std::sync::Mutex<T>)`
+            let ptr_ty = Rewrite::TyPtr(
+                Box::new(Rewrite::TyCtor(
+                    "std::sync::Mutex".to_string(),
+                    vec![Rewrite::Print(ty.clone())],
+                )),
+                hir::Mutability::Not,
+            );
+            Rewrite::Call(
+                "std::sync::Arc::from_raw".to_string(),
+                vec![Rewrite::Cast(Box::new(hir_rw), Box::new(ptr_ty))],
+            )
+        }
+
+        mir_op::RewriteKind::DynOwnedUnwrap => unwrap_rewrite(hir_rw, expect_msg),
         mir_op::RewriteKind::DynOwnedTake => {
             // `p` -> `mem::replace(&mut p, Err(()))`
             Rewrite::Call(
@@ -819,7 +1451,49 @@ pub fn convert_cast_rewrite(kind: &mir_op::RewriteKind, hir_rw: Rewrite) -> Rewr
                 "as_deref".into()
             };
             let hir_rw = Rewrite::MethodCall(ref_method, Box::new(hir_rw), vec![]);
-            Rewrite::MethodCall("unwrap".into(), Box::new(hir_rw), vec![])
+            unwrap_rewrite(hir_rw, expect_msg)
+        }
+
+        mir_op::RewriteKind::FreeUnsafeArg {
+            option,
+            c_string,
+            vec,
+        } => {
+            // `p` -> `p.map_or(std::ptr::null_mut(), |b| Box::into_raw(b).cast())`.  A pointer
+            // that doesn't own its allocation becomes null, and `free(NULL)` is a no-op.
+            let hir_rw = if option {
+                Rewrite::MethodCall(
+                    "and_then".to_string(),
+                    Box::new(hir_rw),
+                    vec![Rewrite::Text("std::result::Result::ok".into())],
+                )
+            } else {
+                hir_rw
+            };
+            let into_raw = if c_string {
+                "std::ffi::CString::into_raw(b)"
+            } else if vec {
+                "Box::into_raw(b.into_boxed_slice())"
+            } else {
+                "Box::into_raw(b)"
+            };
+            Rewrite::MethodCall(
+                "map_or".to_string(),
+                Box::new(hir_rw),
+                vec![
+                    Rewrite::Text("std::ptr::null_mut()".into()),
+                    Rewrite::Text(format!("|b| {into_raw}.cast()")),
+                ],
+            )
+        }
+
+        mir_op::RewriteKind::VecFromBox => {
+            // `p` -> `Vec::from(p)`
+            Rewrite::Call("Vec::from".to_string(), vec![hir_rw])
+        }
+        mir_op::RewriteKind::VecIntoBoxedSlice => {
+            // `p` -> `p.into_boxed_slice()`
+            Rewrite::MethodCall("into_boxed_slice".to_string(), Box::new(hir_rw), vec![])
         }
 
         mir_op::RewriteKind::CastRefToRaw { mutbl } => {
@@ -832,6 +1506,10 @@ pub fn convert_cast_rewrite(kind: &mir_op::RewriteKind, hir_rw: Rewrite) -> Rewr
             let method = if to_mutbl { "cast_mut" } else { "cast_const" };
             Rewrite::MethodCall(method.to_string(), Box::new(hir_rw), vec![])
         }
+        mir_op::RewriteKind::CastRawToVoid => {
+            // `p` -> `p.cast()`, with the `c_void` pointee inferred from the call's signature
+            Rewrite::MethodCall("cast".to_string(), Box::new(hir_rw), vec![])
+        }
         mir_op::RewriteKind::UnsafeCastRawToRef { mutbl } => {
             let rw_pl = Rewrite::Deref(Box::new(hir_rw));
             Rewrite::Ref(Box::new(rw_pl), mutbl_from_bool(mutbl))
@@ -846,10 +1524,33 @@ pub fn convert_cast_rewrite(kind: &mir_op::RewriteKind, hir_rw: Rewrite) -> Rewr
             // `x` to `Cell::from_mut(x)`
             Rewrite::Call("std::cell::Cell::from_mut".to_string(), vec![hir_rw])
         }
+        mir_op::RewriteKind::AsSliceOfCells => {
+            // `x` to `x.as_slice_of_cells()`
+            Rewrite::MethodCall("as_slice_of_cells".to_string(), Box::new(hir_rw), vec![])
+        }
         mir_op::RewriteKind::AsPtr => {
             // `x` to `x.as_ptr()`
             Rewrite::MethodCall("as_ptr".to_string(), Box::new(hir_rw), vec![])
         }
+        mir_op::RewriteKind::CStrAsPtr { ref elem_ty } => {
+            // `s` to `s.as_ptr()` or `s.as_ptr().cast::<T>()`
+            let rw = Rewrite::MethodCall("as_ptr".to_string(), Box::new(hir_rw), vec![]);
+            match *elem_ty {
+                Some(ref elem_ty) => {
+                    Rewrite::MethodCall(format!("cast::<{elem_ty}>"), Box::new(rw), vec![])
+                }
+                None => rw,
+            }
+        }
+        mir_op::RewriteKind::UnsafeCStrFromPtr { cast } => {
+            // `p` to `CStr::from_ptr(p)` or `CStr::from_ptr(p.cast())`
+            let hir_rw = if cast {
+                Rewrite::MethodCall("cast".to_string(), Box::new(hir_rw), vec![])
+            } else {
+                hir_rw
+            };
+            Rewrite::Call("std::ffi::CStr::from_ptr".to_string(), vec![hir_rw])
+        }
         mir_op::RewriteKind::CastRawMutToCellPtr { ref ty } => Rewrite::Cast(
             Box::new(hir_rw),
             Box::new(Rewrite::TyPtr(
@@ -900,6 +1601,23 @@ fn fold_mut_to_imm(rw: Rewrite) -> Result<Rewrite, Rewrite> {
     })
 }
 
+/// `s` -> `s[0]`
+fn first_elem(rw: Rewrite) -> Rewrite {
+    Rewrite::Index(Box::new(rw), Box::new(Rewrite::LitZero))
+}
+
+/// `p` -> `p.unwrap()`, or `p.expect("msg")` if `expect_msg` is provided.
+fn unwrap_rewrite(hir_rw: Rewrite, expect_msg: Option<&str>) -> Rewrite {
+    match expect_msg {
+        Some(msg) => Rewrite::MethodCall(
+            "expect".to_string(),
+            Box::new(hir_rw),
+            vec![format_rewrite!("{:?}", msg)],
+        ),
+        None => Rewrite::MethodCall("unwrap".to_string(), Box::new(hir_rw), vec![]),
+    }
+}
+
 /// Convert the MIR rewrites attached to each HIR node into `Span`-based `rewrite::Rewrite`s.
 pub fn convert_rewrites(
     tcx: TyCtxt,
@@ -917,6 +1635,8 @@ pub fn convert_rewrites(
         rewrites: HashMap::new(),
         subsumed_child_rewrites: RefCell::new(HashSet::new()),
         materialize_adjustments: false,
+        expect_format: env::var("C2RUST_ANALYZE_EXPECT_MESSAGE").ok(),
+        use_helpers: helpers::use_helper_module(),
     };
     v.visit_body(hir);
 
@@ -7,21 +7,31 @@
 //! all adjustments, as this would make even non-rewritten code extremely verbose, so we try to
 //! materialize adjustments only on code that's subject to some rewrite.
 
+use crate::const_prop::ConstResolver;
 use crate::context::{AnalysisCtxt, Assignment, DontRewriteFnReason, FlagSet, LTy, PermissionSet};
+use crate::free_policy::{self, FreePolicy};
 use crate::panic_detail;
 use crate::pointee_type::PointeeTypes;
 use crate::pointer_id::{PointerId, PointerTable};
-use crate::type_desc::{self, Ownership, Quantity, TypeDesc};
-use crate::util::{self, ty_callee, Callee};
-use log::{error, trace};
+use crate::rewrite::expr::printf::{self, FmtPiece};
+use crate::rewrite::expr::string::StrFn;
+use crate::rewrite::ty;
+use crate::type_desc::{self, Ownership, PtrDesc, Quantity, TypeDesc};
+use crate::util::{self, ty_callee, Callee, UnknownDefCallee};
+use log::trace;
 use rustc_ast::Mutability;
+use rustc_middle::mir::visit::{PlaceContext, Visitor};
 use rustc_middle::mir::{
-    BasicBlock, Body, BorrowKind, Location, Operand, Place, PlaceElem, PlaceRef, Rvalue, Statement,
-    StatementKind, Terminator, TerminatorKind,
+    AggregateKind, BasicBlock, Body, BorrowKind, InlineAsmOperand, Local, Location, Operand, Place,
+    PlaceElem, PlaceRef, Rvalue, Statement, StatementKind, Terminator, TerminatorKind,
+    RETURN_PLACE,
 };
 use rustc_middle::ty::print::{FmtPrinter, PrettyPrinter, Print};
-use rustc_middle::ty::{ParamEnv, Ty, TyCtxt, TyKind};
-use std::collections::HashMap;
+use rustc_middle::ty::subst::SubstsRef;
+use rustc_middle::ty::{FieldDef, IntTy, ParamEnv, Ty, TyCtxt, TyKind, TypeAndMut, UintTy};
+use rustc_span::{sym, Span, DUMMY_SP};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
 use std::ops::Index;
 
 use rustc_hir::def::Namespace;
@@ -61,6 +71,9 @@ pub enum RewriteKind {
     Reborrow { mutbl: bool },
     /// Remove a call to `as_ptr` or `as_mut_ptr`.
     RemoveAsPtr,
+    /// Replace a call to `as_ptr` or `as_mut_ptr` on a container with a call to `method`, which
+    /// returns the contents as a slice.  For example, `v.as_ptr()` becomes `v.as_slice()`.
+    AsPtrToSlice { method: &'static str },
     /// Remove a cast, changing `x as T` to just `x`.
     RemoveCast,
     /// Replace &raw with & or &raw mut with &mut
@@ -77,46 +90,172 @@ pub enum RewriteKind {
     ZeroAsPtrToNone,
 
     /// Replace a call to `memcpy(dest, src, n)` with a safe copy operation that works on slices
-    /// instead of raw pointers.  `elem_size` is the size of the original, unrewritten pointee
-    /// type, which is used to convert the byte length `n` to an element count.  `dest_single` and
+    /// instead of raw pointers.  `elem_ty` is the pointee type, printed as Rust source, whose
+    /// `size_of` is used to convert the byte length `n` to an element count.  `dest_single` and
     /// `src_single` are set when `dest`/`src` is a pointer to a single item rather than a slice.
+    /// If `n` is a known constant, possibly passed in by every caller, `byte_len` is its value,
+    /// and the copy uses it in place of `n`.  The element count is still computed from
+    /// `size_of::<elem_ty>()`, so it stays correct if the layout of `elem_ty` changes.
     MemcpySafe {
-        elem_size: u64,
+        elem_ty: String,
         dest_single: bool,
         src_single: bool,
+        byte_len: Option<u64>,
     },
-    /// Replace a call to `memset(ptr, 0, n)` with a safe zeroize operation.  `elem_size` is the
-    /// size of the type being zeroized, which is used to convert the byte length `n` to an element
-    /// count.  `dest_single` is set when `dest` is a pointer to a single item rather than a slice.
+    /// Replace a call to `memmove(dest, src, n)` with a safe copy operation on slices, with fields
+    /// as in `MemcpySafe`.  `dest_cell` and `src_cell` are set when `dest`/`src` is rewritten to a
+    /// slice of `Cell`s, which is how overlapping `dest` and `src` pointers are rewritten.  In that
+    /// case, the elements are copied through an intermediate buffer.
+    MemmoveSafe {
+        elem_ty: String,
+        dest_single: bool,
+        src_single: bool,
+        dest_cell: bool,
+        src_cell: bool,
+    },
+    /// Replace a call to `memcmp(a, b, n)` with a comparison of slices of `elem_ty`.  If `ordered`
+    /// is set, comparing the elements gives the same order as comparing their bytes, so the slices
+    /// are compared with `cmp`; otherwise, they're only compared for equality.
+    MemcmpSafe { elem_ty: String, ordered: bool },
+    /// Replace a call to `memchr(p, c, n)` with a search of the first `n` elements of the slice
+    /// `p`, producing an `Option` of the rest of `p` starting at the first element equal to `c`.
+    /// `elem_ty` is the element type of `p`, printed as Rust source.  If `mutbl` is set, `p` and
+    /// the result are mutable slices.
+    MemchrSafe { elem_ty: String, mutbl: bool },
+    /// Replace a call to `qsort(base, n, size, Some(cmp))` with a call to `sort_by` on the first
+    /// `n` elements of the slice `base`.  `cmp_fn` is the path of the comparator, and `elem_ty` is
+    /// the element type of `base`, printed as Rust source.  If `raw_cmp_args` is set, the
+    /// comparator still takes raw pointers, so the elements are cast to raw pointers before being
+    /// passed to it.
+    QsortSafe {
+        elem_ty: String,
+        cmp_fn: String,
+        raw_cmp_args: bool,
+    },
+    /// Replace a call to `bsearch(key, base, n, size, Some(cmp))` with a call to
+    /// `binary_search_by` on the first `n` elements of the slice `base`, producing an `Option` of
+    /// the rest of `base` starting at the matching element.  The fields are as in `QsortSafe`,
+    /// with the key passed to the comparator like an element.  If `mutbl` is set, `base` and the
+    /// result are mutable slices.
+    BsearchSafe {
+        elem_ty: String,
+        cmp_fn: String,
+        raw_cmp_args: bool,
+        mutbl: bool,
+    },
+    /// Replace a call to one of the `str*` functions with a safe operation on slices of
+    /// characters.  The string arguments for which `c_strs` is set are `&CStr`s instead of
+    /// slices.  `ret_ty` is the original return type, printed as Rust source.
+    StrSafe {
+        str_fn: StrFn,
+        c_strs: Vec<bool>,
+        ret_ty: String,
+    },
+    /// Replace a call to `memset(ptr, 0, n)` with a safe zeroize operation.  `elem_ty` is the type
+    /// being zeroized, printed as Rust source, whose `size_of` is used to convert the byte length
+    /// `n` to an element count.  `dest_single` is set when `dest` is a pointer to a single item
+    /// rather than a slice.
     MemsetZeroize {
         zero_ty: ZeroizeType,
-        elem_size: u64,
+        elem_ty: String,
         dest_single: bool,
     },
 
     /// Replace a call to `malloc(n)` with a safe `Box::new` operation.  The new allocation will be
-    /// zero-initialized.
+    /// zero-initialized.  If `option` is set, the result is an `Option<Box<T>>`, which is `None`
+    /// when `n` is zero.  If `vec` is set, the result is a `Vec<T>` rather than a `Box<[T]>`.
     MallocSafe {
         zero_ty: ZeroizeType,
-        elem_size: u64,
+        elem_ty: String,
         single: bool,
+        option: bool,
+        vec: bool,
     },
-    /// Replace a call to `free(p)` with a safe `drop` operation.
-    FreeSafe { single: bool },
+    /// Replace a call to `malloc(n)` with an uninitialized `Box<MaybeUninit<T>>`, converted to
+    /// `Box<T>`.  This is used with `--malloc-uninit` for types that have no `ZeroizeType`, only
+    /// when the allocation is fully written by a `WriteUninit` before any other use.  If `option`
+    /// is set, the result is an `Option<Box<T>>`, which is `None` when `n` is zero.
+    MallocUninit { elem_ty: String, option: bool },
+    /// Replace the first write `*p = x` to a `MallocUninit` allocation with
+    /// `ptr::write(&mut *p, x)`, which initializes the pointee without dropping its old,
+    /// uninitialized value.
+    WriteUninit,
+    /// Replace a call to `free(p)` with a safe `drop` operation.  If `option` is set, `p` is an
+    /// `Option`, and `free(NULL)` is a no-op.
+    FreeSafe { single: bool, option: bool },
+    /// Convert the `DynOwned<Box<T>>` argument `p` of a `free` call kept under
+    /// [`FreePolicy::Unsafe`] into a raw pointer: `Box::into_raw(b)` if `p` owns its allocation,
+    /// and null otherwise.  If `option` is set, `p` is an `Option`, and `None` also becomes null.
+    /// If `c_string` is set, `p` holds a `CString` rather than a `Box`, and if `vec` is set, it
+    /// holds a `Vec`, which is converted to a `Box<[T]>` first.
+    FreeUnsafeArg {
+        option: bool,
+        c_string: bool,
+        vec: bool,
+    },
+    /// Replace a call to `realloc(p, n)` with safe operations on `Box`es.  If `src_option` is set,
+    /// `p` is an `Option`, and `None` is treated as an empty allocation, so that `realloc(NULL, n)`
+    /// acts like `malloc(n)`.  If `dest_option` is set, the result is an `Option<Box<T>>`, which
+    /// is `None` when `n` is zero.
     ReallocSafe {
         zero_ty: ZeroizeType,
-        elem_size: u64,
+        elem_ty: String,
         src_single: bool,
         dest_single: bool,
+        src_option: bool,
+        dest_option: bool,
+    },
+    /// Replace a call to `realloc(p, n)`, where `p` and the result are `Vec<T>`s, with a
+    /// `resize_with` of `p` in place.  If `src_option` is set, `p` is an `Option`, and `None` is
+    /// treated as an empty `Vec`, as in `ReallocSafe`.
+    ReallocVec {
+        zero_ty: ZeroizeType,
+        elem_ty: String,
+        src_option: bool,
     },
+    /// Replace a call to `calloc(count, size)` with a safe `Box::new` operation, as in
+    /// `MallocSafe`.
     CallocSafe {
         zero_ty: ZeroizeType,
-        elem_size: u64,
+        elem_ty: String,
         single: bool,
+        option: bool,
+        vec: bool,
+    },
+    /// Replace a call to `strdup(s)` or `strndup(s, n)` with a copy of the string in a new
+    /// `Box<[T]>`, including its terminating NUL, or in a new `CString` if `c_string` is set.  If
+    /// `bounded` is set, at most `n` characters are copied, as in `strndup`.  If `src_c_str` is
+    /// set, `s` is a `&CStr` rather than a slice.
+    StrdupSafe {
+        bounded: bool,
+        src_c_str: bool,
+        c_string: bool,
+    },
+
+    /// Replace a call to `sprintf` or `snprintf` with a constant format string, parsed into
+    /// `pieces`, with safe formatting into the destination slice.  `bounded` is set for
+    /// `snprintf`.  `ret_ty` is the original return type of the call, printed as Rust source.
+    SprintfSafe {
+        bounded: bool,
+        pieces: Vec<FmtPiece>,
+        ret_ty: String,
+    },
+
+    /// Replace a call to `printf`, or to `fprintf` if `fprintf` is set, with a constant format
+    /// string, parsed into `pieces`, with formatting into a buffer that's then written to
+    /// `stderr` if `stderr` is set, and otherwise to `stdout`.  `ret_ty` is the original return
+    /// type of the call, printed as Rust source.
+    PrintfSafe {
+        fprintf: bool,
+        stderr: bool,
+        pieces: Vec<FmtPiece>,
+        ret_ty: String,
     },
 
     /// Convert `Option<T>` to `T` by calling `.unwrap()`.
     OptionUnwrap,
+    /// Convert `Option<*const T>` or `Option<*mut T>` to a raw pointer, mapping `None` to null.
+    OptionUnwrapOrNull { mutbl: bool },
     /// Convert `T` to `Option<T>` by wrapping the value in `Some`.
     OptionSome,
     /// Begin an `Option::map` operation, converting `Option<T>` to `T`.
@@ -143,6 +282,32 @@ pub enum RewriteKind {
     /// `as_deref()`/`as_deref_mut()` and `unwrap`.
     DynOwnedDowngrade { mutbl: bool },
 
+    /// Convert `p` to `p.clone()`, so the rewrites that follow consume a copy of a non-`Copy`
+    /// value, such as an `Option<Rc<T>>`, rather than the original.
+    Clone,
+
+    /// Convert `Box<T>` to `Rc<RefCell<T>>` by moving the value out of the `Box`.
+    RcRefCellFromBox,
+    /// Borrow the contents of an `Rc<RefCell<T>>` as `&*p.borrow()` or `&mut *p.borrow_mut()`.
+    /// The `RefCell` stays borrowed until the end of the enclosing statement.
+    RefCellBorrow { mutbl: bool },
+    /// Convert `Box<T>` to `Arc<Mutex<T>>` by moving the value out of the `Box`.
+    ArcMutexFromBox,
+    /// Lock an `Arc<Mutex<T>>` and borrow its contents as `&*p.lock().unwrap()` or
+    /// `&mut *p.lock().unwrap()`.  The lock is held until the end of the enclosing statement.
+    MutexLock { mutbl: bool },
+    /// Convert `Arc<Mutex<T>>` to `*mut Mutex<T>` with `Arc::into_raw`, to pass it to a new thread.
+    /// The thread takes over the reference held by the `Arc`.
+    ArcIntoRaw,
+    /// Convert a raw pointer received by a new thread back to the `Arc<Mutex<T>>` passed to
+    /// `ArcIntoRaw`, using `Arc::from_raw`.  `ty` is `T`.
+    ArcFromRaw { ty: String },
+
+    /// Convert `Box<[T]>` to `Vec<T>` with `Vec::from`.
+    VecFromBox,
+    /// Convert `Vec<T>` to `Box<[T]>` with `into_boxed_slice()`.
+    VecIntoBoxedSlice,
+
     /// Cast `&T` to `*const T` or `&mut T` to `*mut T`.
     CastRefToRaw { mutbl: bool },
     /// Cast `*const T` to `*mut T` or vice versa.  If `to_mutbl` is true, we are casting to
@@ -152,30 +317,63 @@ pub enum RewriteKind {
     UnsafeCastRawToRef { mutbl: bool },
     /// Cast *mut T to *const Cell<T>
     CastRawMutToCellPtr { ty: String },
+    /// Cast `*const T` to `*const c_void` or `*mut T` to `*mut c_void`, for passing to a libc
+    /// function that is left unrewritten.
+    CastRawToVoid,
 
     /// Replace `y` in `let x = y` with `Cell::new(y)`, i.e. `let x = Cell::new(y)`
     /// TODO: ensure `y` implements `Copy`
     CellNew,
-    /// Replace `*y` with `Cell::get(y)` where `y` is a pointer
-    CellGet,
-    /// Replace `*y = x` with `Cell::set(x)` where `y` is a pointer
-    CellSet,
+    /// Replace `*y` with `Cell::get(y)` where `y` is a pointer.  If `slice` is set, `y` is a
+    /// `&[Cell<T>]`, and `*y` is replaced with `y[0].get()`.
+    CellGet { slice: bool },
+    /// Replace `*y = x` with `Cell::set(x)` where `y` is a pointer.  If `slice` is set, `y` is a
+    /// `&[Cell<T>]`, and `*y = x` is replaced with `y[0].set(x)`.
+    CellSet { slice: bool },
     /// Wrap `&mut T` in `Cell::from_mut` to get `&Cell<T>`.
     CellFromMut,
+    /// `x` to `x.as_slice_of_cells()`, converting `&Cell<[T]>` to `&[Cell<T>]`.
+    AsSliceOfCells,
     /// `x` to `x.as_ptr()`
     AsPtr,
+    /// Convert `&CStr` to a raw pointer with `x.as_ptr()`.  If `elem_ty` is set, the pointer is
+    /// cast from `*const c_char` to a pointer to `elem_ty`, printed as Rust source.
+    CStrAsPtr { elem_ty: Option<String> },
+    /// Convert a raw pointer to `&CStr` with `CStr::from_ptr(x)`.  If `cast` is set, the pointer is
+    /// first cast to `*const c_char`.
+    UnsafeCStrFromPtr { cast: bool },
+    /// Replace a call `f(a, len)` to a function whose returned slice is fused with its length
+    /// out-parameter, which is argument `len_arg` of the call, with a call that omits the
+    /// out-parameter followed by a store of the length of the returned slice, cast to `len_ty`,
+    /// through the out-parameter.
+    CallReturnedSliceLen { len_arg: usize, len_ty: String },
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum ZeroizeType {
     /// Zeroize by storing the literal `0`.
     Int,
+    /// Zeroize by storing the literal `0.0`.
+    Float,
     /// Zeroize by storing the literal `false`.
     Bool,
+    /// Zeroize a raw pointer by storing `ptr::null_mut()` if `mutbl` is set, or `ptr::null()`
+    /// otherwise.
+    NullPtr { mutbl: bool },
+    /// Zeroize an `Option`, or a pointer rewritten to one, by storing `None`.
+    None,
+    /// Zeroize a pointer rewritten to a slice by storing an empty slice: `&[]`, `&mut []`, or
+    /// `Box::new([])`, depending on the `Ownership`.
+    EmptySlice(Ownership),
     /// Iterate over `x.iter_mut()` and zeroize each element.
     Array(Box<ZeroizeType>),
     /// Zeroize each named field.
     Struct(String, Vec<(String, ZeroizeType)>),
+    /// Zeroize a fieldless enum by storing its variant with discriminant zero, given by its path.
+    Enum(String),
+    /// Zeroize a union by storing a zeroized value of its largest field, so that every byte of
+    /// the union is zero.  Holds the union's path, the field name, and the field's zeroize type.
+    Union(String, String, Box<ZeroizeType>),
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -221,6 +419,17 @@ struct ExprRewriteVisitor<'a, 'tcx> {
     loc: Location,
     sub_loc: Vec<SubLoc>,
     errors: DontRewriteFnReason,
+    consts: ConstResolver<'a, 'tcx>,
+    /// For each `malloc` call rewritten with `MallocUninit`, the location of the statement that
+    /// initializes the allocation.
+    uninit_writes: HashMap<Location, Location>,
+    /// Locals whose values may be returned from the function.  A borrow of one of these locals
+    /// would outlive it.
+    returned_locals: HashSet<Local>,
+    /// The location and mutability of each `RefCellBorrow` emitted for a deref.
+    refcell_borrows: Vec<(Location, bool)>,
+    /// The location of each `MutexLock` emitted for a deref.
+    mutex_locks: Vec<(Location, ())>,
 }
 
 impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
@@ -233,7 +442,7 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
     ) -> ExprRewriteVisitor<'a, 'tcx> {
         let perms = asn.perms();
         let flags = asn.flags();
-        ExprRewriteVisitor {
+        let mut v = ExprRewriteVisitor {
             acx,
             perms,
             flags,
@@ -246,7 +455,34 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
             },
             sub_loc: Vec::new(),
             errors: DontRewriteFnReason::empty(),
+            consts: ConstResolver::new(
+                acx.tcx(),
+                mir,
+                acx.gacx.arg_consts.get(mir.source.def_id()),
+            ),
+            uninit_writes: HashMap::new(),
+            returned_locals: find_returned_locals(mir),
+            refcell_borrows: Vec::new(),
+            mutex_locks: Vec::new(),
+        };
+        if malloc_uninit() {
+            for (bb, bb_data) in mir.basic_blocks().iter_enumerated() {
+                let loc = mir.terminator_loc(bb);
+                if let Some(write_loc) = v.malloc_uninit_write(&bb_data.terminator().kind) {
+                    v.uninit_writes.insert(loc, write_loc);
+                }
+            }
         }
+        v
+    }
+
+    /// Choose the [`FreePolicy`] for the `free` call at the current location.
+    fn free_policy(&self) -> FreePolicy {
+        let tcx = self.acx.tcx();
+        let fn_path = tcx.def_path_str(self.mir.source.def_id());
+        let span = self.mir.source_info(self.loc).span.source_callsite();
+        let line = tcx.sess.source_map().lookup_char_pos(span.lo()).line;
+        free_policy::decide(&fn_path, line)
     }
 
     fn err(&mut self, reason: DontRewriteFnReason) {
@@ -300,21 +536,77 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
     /// if one is available, or the pointee type as represented in `lty` itself otherwise.  Returns
     /// `None` if `lty` is not a `RawPtr` or `Ref` type.
     ///
-    /// TODO: This does not yet have any pointer-to-pointer support.  For example, if `lty` is
-    /// `*mut *mut c_void` where the inner pointer is known to point to `u8`, this method will
-    /// still return `*mut c_void` instead of `*mut u8`.
+    /// If the pointee is itself a pointer, its pointee type is resolved the same way, so for
+    /// example `*mut *mut c_void`, where the inner pointer is known to point to `u8`, produces
+    /// `*mut u8`.  The labels of the result may differ from those of the original pointee, so
+    /// results should be compared with `same_rewritten_lty`.
     fn pointee_lty(&self, lty: LTy<'tcx>) -> Option<LTy<'tcx>> {
+        self.pointee_lty_depth(lty, 0)
+    }
+
+    fn pointee_lty_depth(&self, lty: LTy<'tcx>, depth: usize) -> Option<LTy<'tcx>> {
         if !matches!(lty.kind(), TyKind::Ref(..) | TyKind::RawPtr(..)) {
             return None;
         }
         debug_assert_eq!(lty.args.len(), 1);
         let ptr = lty.label;
-        if !ptr.is_none() {
-            if let Some(pointee_lty) = self.pointee_types[ptr].get_sole_lty() {
-                return Some(pointee_lty);
-            }
+        let pointee_lty = if ptr.is_none() {
+            None
+        } else {
+            self.pointee_types[ptr].get_sole_lty()
+        };
+        let pointee_lty = pointee_lty.unwrap_or(lty.args[0]);
+
+        // Pointee types can be cyclic, as in a linked list built from `void*` pointers, so only
+        // a bounded number of levels are resolved.
+        if depth >= MAX_POINTEE_DEPTH {
+            return Some(pointee_lty);
         }
-        Some(lty.args[0])
+        let inner_lty = match self.pointee_lty_depth(pointee_lty, depth + 1) {
+            Some(x) if x.ty != pointee_lty.args[0].ty => x,
+            _ => return Some(pointee_lty),
+        };
+        let tcx = self.acx.tcx();
+        let ty = match *pointee_lty.kind() {
+            TyKind::RawPtr(tm) => tcx.mk_ptr(TypeAndMut {
+                ty: inner_lty.ty,
+                mutbl: tm.mutbl,
+            }),
+            TyKind::Ref(rg, _, mutbl) => tcx.mk_ref(
+                rg,
+                TypeAndMut {
+                    ty: inner_lty.ty,
+                    mutbl,
+                },
+            ),
+            _ => unreachable!(),
+        };
+        let lcx = self.acx.lcx();
+        Some(lcx.mk(ty, lcx.mk_slice(&[inner_lty]), pointee_lty.label))
+    }
+
+    /// Returns `true` if `a` and `b` have the same type, and each pointer in `a` will be rewritten
+    /// to the same type as the corresponding pointer in `b`.
+    fn same_rewritten_lty(&self, a: LTy<'tcx>, b: LTy<'tcx>) -> bool {
+        if a.ty != b.ty {
+            return false;
+        }
+        a.iter().zip(b.iter()).all(|(x, y)| {
+            if x.label == y.label {
+                return true;
+            }
+            if x.label.is_none() || y.label.is_none() {
+                return false;
+            }
+            let desc = |l: LTy<'tcx>| {
+                let flags = self.flags[l.label];
+                if flags.contains(FlagSet::FIXED) {
+                    return None;
+                }
+                Some(type_desc::perms_to_desc(l.ty, self.perms[l.label], flags))
+            };
+            desc(x) == desc(y)
+        })
     }
 
     fn is_nullable(&self, ptr: PointerId) -> bool {
@@ -339,11 +631,370 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         desc.dyn_owned
     }
 
+    /// Warn about source lines where a `RefCell` is borrowed mutably by one expression and a
+    /// `RefCell` is borrowed by another.  Each borrow lasts until the end of its statement, so if
+    /// both borrows are of the same object, the second one panics at run time.
+    fn warn_refcell_conflicts(&self) {
+        let sm = self.acx.tcx().sess.source_map();
+        for borrows in self.group_by_line(&self.refcell_borrows) {
+            let conflict = borrows.iter().any(|&(span1, mutbl1)| {
+                borrows
+                    .iter()
+                    .any(|&(span2, mutbl2)| span1 != span2 && (mutbl1 || mutbl2))
+            });
+            if conflict {
+                eprintln!(
+                    "warning: {}: `RefCell` borrows in this statement may overlap and panic",
+                    sm.span_to_diagnostic_string(borrows[0].0)
+                );
+            }
+        }
+    }
+
+    /// Warn about source lines where two expressions lock a `Mutex`.  Each lock is held until the
+    /// end of its statement, so if both locks are of the same object, the second one deadlocks.
+    fn warn_mutex_conflicts(&self) {
+        let sm = self.acx.tcx().sess.source_map();
+        for locks in self.group_by_line(&self.mutex_locks) {
+            let conflict = locks
+                .iter()
+                .any(|&(span1, ())| locks.iter().any(|&(span2, ())| span1 != span2));
+            if conflict {
+                eprintln!(
+                    "warning: {}: `Mutex` locks in this statement may overlap and deadlock",
+                    sm.span_to_diagnostic_string(locks[0].0)
+                );
+            }
+        }
+    }
+
+    /// Group `items` by the source line of their `Location`, in source order.
+    fn group_by_line<T: Copy>(&self, items: &[(Location, T)]) -> Vec<Vec<(Span, T)>> {
+        let sm = self.acx.tcx().sess.source_map();
+        let mut lines = BTreeMap::<_, Vec<(Span, T)>>::new();
+        for &(loc, x) in items {
+            let span = self.mir.source_info(loc).span;
+            let pos = sm.lookup_char_pos(span.lo());
+            lines
+                .entry((pos.file.start_pos, pos.line))
+                .or_default()
+                .push((span, x));
+        }
+        lines.into_values().collect()
+    }
+
+    /// Check whether `lty` is a pointer that will be rewritten to `Rc<RefCell<T>>`.
+    fn is_rc_refcell(&self, lty: LTy) -> bool {
+        matches!(lty.kind(), TyKind::Ref(..) | TyKind::RawPtr(..))
+            && !lty.label.is_none()
+            && self.flags[lty.label].contains(FlagSet::REFCELL)
+    }
+
+    /// Check whether `lty` is a pointer that will be rewritten to `Arc<Mutex<T>>`.
+    fn is_arc_mutex(&self, lty: LTy) -> bool {
+        matches!(lty.kind(), TyKind::Ref(..) | TyKind::RawPtr(..))
+            && !lty.label.is_none()
+            && self.flags[lty.label].contains(FlagSet::MUTEX)
+    }
+
+    /// Returns the `PlaceAccess` to use when `pl` is read by an `Operand`.  This is
+    /// `PlaceAccess::Imm` if the value of `pl` will be `Copy` after rewriting, so that reading it
+    /// doesn't move out of the place, and `PlaceAccess::Move` otherwise.
+    fn operand_access(&self, pl: Place<'tcx>) -> PlaceAccess {
+        let lty = self.acx.type_of(pl);
+        let is_copy = if lty.ty.is_any_ptr() && !lty.label.is_none() {
+            let flags = self.flags[lty.label];
+            if flags.contains(FlagSet::FIXED) {
+                lty.ty
+                    .is_copy_modulo_regions(self.acx.tcx().at(DUMMY_SP), ParamEnv::reveal_all())
+            } else {
+                let desc = type_desc::perms_to_desc(lty.ty, self.perms[lty.label], flags);
+                desc.own.is_copy() && !desc.dyn_owned
+            }
+        } else {
+            // Non-pointer types, and pointers with no `PointerId`, keep their type, except that
+            // aggregates may contain rewritten pointers.  Only trust primitive types here.
+            lty.ty.is_primitive() || matches!(lty.kind(), TyKind::RawPtr(..))
+        };
+        if is_copy {
+            PlaceAccess::Imm
+        } else {
+            PlaceAccess::Move
+        }
+    }
+
+    /// Returns `true` if the rewrite will change the type of any pointer in `lty`.
+    fn changes_pointer_types(&self, lty: LTy) -> bool {
+        lty.iter().any(|lty| {
+            let ptr = lty.label;
+            let mutbl = match *lty.kind() {
+                TyKind::Ref(..) => return !self.flags[ptr].contains(FlagSet::FIXED),
+                TyKind::RawPtr(tm) => tm.mutbl,
+                _ => return false,
+            };
+            if ptr.is_none() || self.flags[ptr].contains(FlagSet::FIXED) {
+                return false;
+            }
+            let desc = type_desc::perms_to_desc(lty.ty, self.perms[ptr], self.flags[ptr]);
+            let raw_own = match mutbl {
+                Mutability::Not => Ownership::Raw,
+                Mutability::Mut => Ownership::RawMut,
+            };
+            desc.own != raw_own || desc.qty != Quantity::Single || desc.option || desc.dyn_owned
+        })
+    }
+
+    /// If `pl` is reached by dereferencing a non-`FIXED` pointer with `Cell` ownership, such as
+    /// `*x`, `*(*s).x`, or `(*x).f`, returns the `TypeDesc` of the last such pointer, along with
+    /// `true` if `pl` is the whole pointee (`*x`) rather than a part of it (`(*x).f`).
+    fn cell_deref(&self, pl: Place<'tcx>) -> Option<(TypeDesc<'tcx>, bool)> {
+        let (base, _) = pl
+            .iter_projections()
+            .rev()
+            .find(|&(_, elem)| elem == PlaceElem::Deref)?;
+        let ptr_lty = self.acx.type_of(base);
+        if !ptr_lty.ty.is_any_ptr() {
+            return None;
+        }
+        let ptr = ptr_lty.label;
+        let flags = self.flags[ptr];
+        if flags.contains(FlagSet::FIXED) {
+            return None;
+        }
+        let desc = type_desc::perms_to_desc(ptr_lty.ty, self.perms[ptr], flags);
+        if desc.own != Ownership::Cell {
+            return None;
+        }
+        let whole = pl.projection.len() == base.projection.len() + 1;
+        Some((desc, whole))
+    }
+
+    /// Get the `ZeroizeType` for a value of type `ty`, or `None` if it has no zero value.  `lty`,
+    /// if known, is the `LTy` of `ty`, which is used to zeroize each pointer according to its
+    /// rewritten type: `None` for `Option`s, null for raw pointers, and no zero value for
+    /// non-nullable references.  Pointers without an `LTy` are assumed to stay raw.
+    fn zeroize_type(&self, ty: Ty<'tcx>, lty: Option<LTy<'tcx>>) -> Option<ZeroizeType> {
+        let tcx = self.acx.tcx();
+        let value_path = |did| {
+            let printer = FmtPrinter::new(tcx, Namespace::ValueNS);
+            printer.print_value_path(did, &[]).unwrap().into_buffer()
+        };
+        // The `LTy` of a field of a non-generic struct or union.
+        let field_lty = |field: &FieldDef, substs: SubstsRef<'tcx>| {
+            lty?;
+            if !substs.is_empty() {
+                return None;
+            }
+            self.acx.gacx.field_ltys.get(&field.did).copied()
+        };
+
+        Some(match *ty.kind() {
+            TyKind::Int(_) | TyKind::Uint(_) => ZeroizeType::Int,
+            TyKind::Float(_) => ZeroizeType::Float,
+            TyKind::Bool => ZeroizeType::Bool,
+            TyKind::RawPtr(..) | TyKind::Ref(..) => {
+                // Zeroize according to the pointer's type after rewriting.  Pointers that aren't
+                // rewritten keep their original type.
+                let rw_ty = match lty {
+                    Some(lty)
+                        if !lty.label.is_none()
+                            && !self.flags[lty.label].contains(FlagSet::FIXED) =>
+                    {
+                        let desc = type_desc::perms_to_desc(
+                            lty.ty,
+                            self.perms[lty.label],
+                            self.flags[lty.label],
+                        );
+                        ty::desc_to_ty(tcx, desc)
+                    }
+                    _ => ty,
+                };
+                match *rw_ty.kind() {
+                    TyKind::RawPtr(tm) => ZeroizeType::NullPtr {
+                        mutbl: tm.mutbl == Mutability::Mut,
+                    },
+                    TyKind::Adt(adt_def, _)
+                        if tcx.is_diagnostic_item(sym::Option, adt_def.did()) =>
+                    {
+                        ZeroizeType::None
+                    }
+                    TyKind::Ref(_, pointee_ty, mutbl) if pointee_ty.is_slice() => {
+                        ZeroizeType::EmptySlice(match mutbl {
+                            Mutability::Not => Ownership::Imm,
+                            Mutability::Mut => Ownership::Mut,
+                        })
+                    }
+                    TyKind::Adt(adt_def, substs)
+                        if adt_def.is_box() && substs.type_at(0).is_slice() =>
+                    {
+                        ZeroizeType::EmptySlice(Ownership::Box)
+                    }
+                    // Other references and owning pointers can't be null.
+                    _ => return None,
+                }
+            }
+            TyKind::Adt(adt_def, _) if tcx.is_diagnostic_item(sym::Option, adt_def.did()) => {
+                ZeroizeType::None
+            }
+            TyKind::Adt(adt_def, substs) if adt_def.is_struct() => {
+                let variant = adt_def.non_enum_variant();
+                let mut fields = Vec::with_capacity(variant.fields.len());
+                for field in &variant.fields {
+                    let name = field.name.to_string();
+                    let zero =
+                        self.zeroize_type(field.ty(tcx, substs), field_lty(field, substs))?;
+                    fields.push((name, zero));
+                }
+                ZeroizeType::Struct(value_path(adt_def.did()), fields)
+            }
+            TyKind::Adt(adt_def, _) if adt_def.is_enum() => {
+                if !adt_def.is_payloadfree() {
+                    return None;
+                }
+                let (zero_idx, _) = adt_def
+                    .discriminants(tcx)
+                    .find(|&(_, discr)| discr.val == 0)?;
+                ZeroizeType::Enum(value_path(adt_def.variant(zero_idx).def_id))
+            }
+            TyKind::Adt(adt_def, substs) if adt_def.is_union() => {
+                // Zeroizing the largest field zeroes every byte of the union, as `memset` does.
+                let size_of = |ty| {
+                    tcx.layout_of(ParamEnv::reveal_all().and(ty))
+                        .ok()
+                        .map(|layout| layout.size)
+                };
+                let field = adt_def
+                    .non_enum_variant()
+                    .fields
+                    .iter()
+                    .max_by_key(|field| size_of(field.ty(tcx, substs)))?;
+                let zero = self.zeroize_type(field.ty(tcx, substs), field_lty(field, substs))?;
+                ZeroizeType::Union(
+                    value_path(adt_def.did()),
+                    field.name.to_string(),
+                    Box::new(zero),
+                )
+            }
+            TyKind::Array(elem_ty, _) => {
+                let elem_lty = lty.map(|lty| lty.args[0]);
+                let elem_zero = self.zeroize_type(elem_ty, elem_lty)?;
+                ZeroizeType::Array(Box::new(elem_zero))
+            }
+            _ => return None,
+        })
+    }
+
+    /// If `term` is a single-object `malloc` call that can be rewritten with `MallocUninit`,
+    /// returns the location of the statement that initializes the allocation.  The pointee type
+    /// must have no `ZeroizeType`, and the first statement of the call's target block that uses
+    /// the result, other than copies and casts of the pointer into other locals, must be a write
+    /// `*p = x` of the whole pointee.
+    fn malloc_uninit_write(&self, term: &TerminatorKind<'tcx>) -> Option<Location> {
+        let tcx = self.acx.tcx();
+        let (func, destination, target) = match *term {
+            TerminatorKind::Call {
+                ref func,
+                destination,
+                target: Some(target),
+                ..
+            } => (func, destination, target),
+            _ => return None,
+        };
+        if !matches!(ty_callee(tcx, func.ty(self.mir, tcx)), Callee::Malloc) {
+            return None;
+        }
+        let dest_lty = self.acx.type_of(destination);
+        if dest_lty.label.is_none() {
+            return None;
+        }
+        let pointee_lty = self.pointee_lty(dest_lty)?;
+        if self
+            .zeroize_type(pointee_lty.ty, Some(pointee_lty))
+            .is_some()
+            || self.perms[dest_lty.label]
+                .intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB)
+        {
+            return None;
+        }
+
+        // Locals holding a copy of the pointer returned by `malloc`.
+        let mut ptrs = vec![destination.as_local()?];
+        for (statement_index, stmt) in self.mir.basic_blocks()[target]
+            .statements
+            .iter()
+            .enumerate()
+        {
+            let loc = Location {
+                block: target,
+                statement_index,
+            };
+            match stmt.kind {
+                StatementKind::Assign(ref x) => {
+                    let (pl, ref rv) = **x;
+                    let src = match *rv {
+                        Rvalue::Use(ref op) | Rvalue::Cast(_, ref op, _) => op.place(),
+                        _ => None,
+                    };
+                    if let (Some(dest), Some(src)) =
+                        (pl.as_local(), src.and_then(|pl| pl.as_local()))
+                    {
+                        if ptrs.contains(&src) {
+                            ptrs.push(dest);
+                            continue;
+                        }
+                    }
+                    if ptrs.contains(&pl.local)
+                        && pl.projection[..] == [PlaceElem::Deref]
+                        && pl.ty(self.mir, tcx).ty == pointee_lty.ty
+                    {
+                        let mut v = MentionsLocals {
+                            locals: &ptrs,
+                            found: false,
+                        };
+                        v.visit_rvalue(rv, loc);
+                        return if v.found { None } else { Some(loc) };
+                    }
+                }
+                // These don't access the pointee.
+                StatementKind::FakeRead(..)
+                | StatementKind::StorageLive(..)
+                | StatementKind::StorageDead(..) => continue,
+                _ => {}
+            }
+            let mut v = MentionsLocals {
+                locals: &ptrs,
+                found: false,
+            };
+            v.visit_statement(stmt, loc);
+            if v.found {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// Visit a place used by an inline assembly operand.  The asm code can't be rewritten, so if
+    /// the place or any pointer dereferenced to reach it would change type, the function can't be
+    /// rewritten either.
+    fn visit_asm_place(&mut self, pl: Place<'tcx>, access: PlaceAccess) {
+        let changed = pl
+            .iter_projections()
+            .any(|(base, _)| self.changes_pointer_types(self.acx.type_of(base)))
+            || self.changes_pointer_types(self.acx.type_of(pl));
+        if changed {
+            self.err(DontRewriteFnReason::INLINE_ASM);
+            return;
+        }
+        self.visit_place(pl, access);
+    }
+
     fn visit_statement(&mut self, stmt: &Statement<'tcx>, loc: Location) {
         let _g = panic_detail::set_current_span(stmt.source_info.span);
-        eprintln!(
+        trace!(
             "mir_op::visit_statement: {:?} @ {:?}: {:?}",
-            loc, stmt.source_info.span, stmt
+            loc,
+            stmt.source_info.span,
+            stmt
         );
         self.loc = loc;
         debug_assert!(self.sub_loc.is_empty());
@@ -354,26 +1005,26 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
 
                 let pl_lty = self.acx.type_of(pl);
 
-                // FIXME: Needs changes to handle CELL pointers in struct fields.  Suppose `pl` is
-                // something like `*(_1.0)`, where the `.0` field is CELL.  This should be
-                // converted to a `Cell::get` call, but we would fail to enter this case because
-                // `_1` fails the `is_any_ptr()` check.
-                if pl.is_indirect() && self.acx.local_tys[pl.local].ty.is_any_ptr() {
-                    let local_lty = self.acx.local_tys[pl.local];
-                    let local_ptr = local_lty.label;
-                    let perms = self.perms[local_ptr];
-                    let flags = self.flags[local_ptr];
-                    if !flags.contains(FlagSet::FIXED) {
-                        let desc = type_desc::perms_to_desc(local_lty.ty, perms, flags);
-                        if desc.own == Ownership::Cell {
-                            if pl.projection.len() > 1 || desc.qty != Quantity::Single {
-                                // NYI: `Cell` inside structs, arrays, or ptr-to-ptr
-                                self.err(DontRewriteFnReason::COMPLEX_CELL);
-                            }
-                            // this is an assignment like `*x = 2` but `x` has CELL permissions
-                            self.emit(RewriteKind::CellSet);
-                        }
+                if let Some((desc, whole)) = self.cell_deref(pl) {
+                    if !whole || desc.qty == Quantity::Array {
+                        // NYI: assignments to part of a `Cell`'s contents, such as `(*x).f = 2`,
+                        // and pointers to whole arrays of `Cell`s
+                        self.err(DontRewriteFnReason::COMPLEX_CELL);
                     }
+                    // this is an assignment like `*x = 2` or `*(*s).x = 2` but `x` has CELL
+                    // permissions
+                    self.emit(RewriteKind::CellSet {
+                        slice: desc.qty != Quantity::Single,
+                    });
+                }
+
+                if self
+                    .uninit_writes
+                    .values()
+                    .any(|&write_loc| write_loc == loc)
+                {
+                    // this is the first write `*p = x` to a `MallocUninit` allocation
+                    self.emit(RewriteKind::WriteUninit);
                 }
 
                 #[allow(clippy::single_match)]
@@ -393,23 +1044,18 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                             self.enter_rvalue(|v| v.emit(RewriteKind::CellNew))
                         }
 
-                        if let Some(rv_place) = rv_op.place() {
-                            if rv_place.is_indirect()
-                                && self.acx.local_tys[rv_place.local].ty.is_any_ptr()
-                            {
-                                let local_lty = self.acx.local_tys[rv_place.local];
-                                let local_ptr = local_lty.label;
-                                let flags = self.flags[local_ptr];
-                                if !flags.contains(FlagSet::FIXED) && flags.contains(FlagSet::CELL)
-                                {
-                                    // this is an assignment like `let x = *y` but `y` has CELL permissions
-                                    if pl.projection.len() > 1 || desc.qty != Quantity::Single {
-                                        // NYI: `Cell` inside structs, arrays, or ptr-to-ptr
-                                        self.err(DontRewriteFnReason::COMPLEX_CELL);
-                                    }
-                                    self.enter_rvalue(|v| v.emit(RewriteKind::CellGet))
-                                }
+                        if let Some((rv_desc, whole)) =
+                            rv_op.place().and_then(|rv_place| self.cell_deref(rv_place))
+                        {
+                            // this is an assignment like `let x = *y` or `let x = *(*s).y` but `y`
+                            // has CELL permissions
+                            if !whole || rv_desc.qty == Quantity::Array {
+                                // NYI: reads of part of a `Cell`'s contents, and pointers to whole
+                                // arrays of `Cell`s
+                                self.err(DontRewriteFnReason::COMPLEX_CELL);
                             }
+                            let slice = rv_desc.qty != Quantity::Single;
+                            self.enter_rvalue(|v| v.emit(RewriteKind::CellGet { slice }))
                         }
                     }
                     _ => {}
@@ -453,14 +1099,27 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                         return;
                     }
 
+                    // A borrow of an owned pointer in a local that's returned from the function
+                    // would outlive the local.  The function keeps its raw pointers instead.
+                    if v.escaping_reborrow(pl, rv, pl_lty) {
+                        v.err(DontRewriteFnReason::ESCAPING_REBORROW);
+                    }
+
                     // Normal case: just `visit_rvalue` and emit a cast if needed.
                     v.visit_rvalue(rv, Some(rv_lty));
-                    v.emit_cast_lty_lty(rv_lty, pl_lty)
+                    v.emit_cast_lty_lty(rv_lty, pl_lty);
+                    if let Rvalue::Use(ref op) = *rv {
+                        v.emit_shared_clone(op, rv_lty, pl_lty);
+                    }
                 });
                 self.enter_dest(|v| v.visit_place(pl, PlaceAccess::Mut));
             }
             StatementKind::FakeRead(..) => {}
-            StatementKind::SetDiscriminant { .. } => todo!("statement {:?}", stmt),
+            StatementKind::SetDiscriminant { ref place, .. } => {
+                // This writes to `place` like the destination of an `Assign`, so any pointers
+                // dereferenced along the way must be accessed mutably.
+                self.enter_dest(|v| v.visit_place(**place, PlaceAccess::Mut));
+            }
             StatementKind::Deinit(..) => {}
             StatementKind::StorageLive(..) => {}
             StatementKind::StorageDead(..) => {}
@@ -500,26 +1159,43 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                 // Special cases for particular functions.
                 match ty_callee(tcx, func_ty) {
                     Callee::PtrOffset { .. } => {
-                        self.visit_ptr_offset(&args[0], pl_ty);
+                        self.visit_ptr_offset(&args[0], &args[1], pl_ty);
                     }
-                    Callee::SliceAsPtr { elem_ty, .. } => {
-                        self.visit_slice_as_ptr(elem_ty, &args[0], pl_ty);
+                    Callee::SliceAsPtr {
+                        elem_ty,
+                        mutbl,
+                        slice_method,
+                        ..
+                    } => {
+                        self.visit_slice_as_ptr(elem_ty, mutbl, slice_method, &args[0], pl_ty);
                     }
 
                     Callee::LocalDef { def_id, substs: _ } => {
                         // TODO: handle substs (if nonempty)
                         if let Some(lsig) = self.acx.gacx.fn_sigs.get(&def_id) {
                             self.enter_rvalue(|v| {
+                                // The callee no longer takes the out-parameter for the length of
+                                // its returned slice, so take the length from the slice instead.
+                                // This must come before any cast of the result.
+                                if let Some(rsl) = v.acx.gacx.returned_slice_lens.get(&def_id) {
+                                    v.emit(RewriteKind::CallReturnedSliceLen {
+                                        len_arg: rsl.arg_index,
+                                        len_ty: print_ty(tcx, rsl.len_ty),
+                                    });
+                                }
+
                                 for (i, op) in args.iter().enumerate() {
                                     if let Some(&lty) = lsig.inputs.get(i) {
                                         v.enter_call_arg(i, |v| v.visit_operand(op, Some(lty)));
                                     } else {
                                         // This is a call to a variadic function, and we've gone
-                                        // past the end of the declared arguments.
-                                        // TODO: insert a cast to turn `op` back into its original
-                                        // declared type (i.e. upcast the chosen reference type
-                                        // back to a raw pointer)
-                                        continue;
+                                        // past the end of the declared arguments.  The callee
+                                        // reads these with their original types, so cast any
+                                        // rewritten pointer back to a raw pointer.
+                                        v.enter_call_arg(i, |v| {
+                                            v.visit_operand(op, None);
+                                            v.emit_cast_lty_raw(v.acx.type_of(op));
+                                        });
                                     }
                                 }
 
@@ -530,39 +1206,97 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                         }
                     }
 
-                    Callee::Memcpy => {
+                    Callee::UnknownDef(UnknownDefCallee::Indirect { .. }) => {
+                        // A call through a function pointer, such as an entry of a dispatch
+                        // table.  The pointer's signature has been unified with the signatures of
+                        // the functions stored into it, so cast the arguments and result the same
+                        // way as for a direct call to one of those functions.
+                        let func_lty = self.acx.type_of(func);
+                        if let Some((&output_lty, input_ltys)) = func_lty.args.split_last() {
+                            self.enter_rvalue(|v| {
+                                for (i, (op, &lty)) in args.iter().zip(input_ltys).enumerate() {
+                                    v.enter_call_arg(i, |v| v.visit_operand(op, Some(lty)));
+                                }
+
+                                if !pl_ty.label.is_none() {
+                                    v.emit_cast_lty_lty(output_lty, pl_ty);
+                                }
+                            });
+                        }
+                    }
+
+                    ref callee @ (Callee::Memcpy | Callee::Memmove) => {
                         self.enter_rvalue(|v| {
-                            // TODO: Only emit `MemcpySafe` if the rewritten argument types and
-                            // pointees are suitable.  Specifically, the `src` and `dest` arguments
-                            // must both be rewritten to safe references, their pointee types must
-                            // be the same, and the pointee type must implement `Copy`.  If these
-                            // conditions don't hold, leave the `memcpy` call intact and emit casts
-                            // back to `void*` on the `dest` and `src` arguments.
+                            // `MemcpySafe` requires the pointee types of `src` and `dest` to be the
+                            // same after rewriting, and the pointee type must implement `Copy`.
+                            // If these conditions don't hold, leave the `memcpy` call intact and
+                            // cast the `dest` and `src` arguments back to `void*`.  The same goes
+                            // for `memmove` and `MemmoveSafe`.
                             let dest_lty = v.acx.type_of(&args[0]);
                             let dest_pointee = v.pointee_lty(dest_lty);
                             let src_lty = v.acx.type_of(&args[1]);
                             let src_pointee = v.pointee_lty(src_lty);
-                            let common_pointee = dest_pointee.filter(|&x| Some(x) == src_pointee);
+                            let common_pointee = dest_pointee.filter(|&x| {
+                                src_pointee.map_or(false, |y| v.same_rewritten_lty(x, y))
+                            });
+                            let span = term.source_info.span;
                             let pointee_lty = match common_pointee {
-                                Some(x) => x,
-                                // TODO: emit void* casts before bailing out, as described above
-                                None => return,
+                                Some(x)
+                                    if x.ty.is_copy_modulo_regions(
+                                        tcx.at(span),
+                                        ParamEnv::reveal_all(),
+                                    ) =>
+                                {
+                                    x
+                                }
+                                _ => {
+                                    for (i, op) in args.iter().enumerate().take(2) {
+                                        v.enter_call_arg(i, |v| {
+                                            v.emit_cast_lty_void(v.acx.type_of(op))
+                                        });
+                                    }
+                                    return;
+                                }
                             };
 
                             let orig_pointee_ty = pointee_lty.ty;
-                            let ty_layout = tcx
-                                .layout_of(ParamEnv::reveal_all().and(orig_pointee_ty))
-                                .unwrap();
-                            let elem_size = ty_layout.layout.size().bytes();
+                            let elem_ty = print_ty(tcx, orig_pointee_ty);
                             let dest_single = !v.perms[dest_lty.label]
                                 .intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB);
                             let src_single = !v.perms[src_lty.label]
                                 .intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB);
-                            v.emit(RewriteKind::MemcpySafe {
-                                elem_size,
-                                src_single,
-                                dest_single,
-                            });
+                            if matches!(callee, Callee::Memmove) {
+                                let is_cell = |lty: LTy<'tcx>| {
+                                    let desc = type_desc::perms_to_desc(
+                                        lty.ty,
+                                        v.perms[lty.label],
+                                        v.flags[lty.label],
+                                    );
+                                    desc.own == Ownership::Cell
+                                };
+                                let dest_cell = is_cell(dest_lty);
+                                let src_cell = is_cell(src_lty);
+                                v.emit(RewriteKind::MemmoveSafe {
+                                    elem_ty,
+                                    dest_single,
+                                    src_single,
+                                    dest_cell,
+                                    src_cell,
+                                });
+                            } else {
+                                // Sizes computed from constants, possibly in a caller, are
+                                // substituted for the length argument.
+                                let byte_len = v
+                                    .consts
+                                    .operand_const(&args[2])
+                                    .and_then(|n| u64::try_from(n).ok());
+                                v.emit(RewriteKind::MemcpySafe {
+                                    elem_ty,
+                                    src_single,
+                                    dest_single,
+                                    byte_len,
+                                });
+                            }
 
                             if !pl_ty.label.is_none()
                                 && v.perms[pl_ty.label].intersects(PermissionSet::USED)
@@ -573,6 +1307,118 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                         });
                     }
 
+                    Callee::Memcmp => {
+                        self.enter_rvalue(|v| {
+                            // `MemcmpSafe` requires both arguments to be rewritten to slices of
+                            // the same integer type, for which comparing elements agrees with
+                            // comparing bytes.  Otherwise, leave the `memcmp` call intact and cast
+                            // the arguments back to `void*`.
+                            let lhs_lty = v.acx.type_of(&args[0]);
+                            let lhs_pointee = v.pointee_lty(lhs_lty);
+                            let rhs_lty = v.acx.type_of(&args[1]);
+                            let rhs_pointee = v.pointee_lty(rhs_lty);
+                            let common_pointee = lhs_pointee.filter(|&x| {
+                                rhs_pointee.map_or(false, |y| v.same_rewritten_lty(x, y))
+                            });
+                            let is_slice = |lty: LTy<'tcx>| {
+                                v.perms[lty.label].intersects(
+                                    PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB,
+                                )
+                            };
+                            let pointee_lty = match common_pointee {
+                                Some(x)
+                                    if x.ty.is_integral()
+                                        && is_slice(lhs_lty)
+                                        && is_slice(rhs_lty) =>
+                                {
+                                    x
+                                }
+                                _ => {
+                                    for (i, op) in args.iter().enumerate().take(2) {
+                                        v.enter_call_arg(i, |v| {
+                                            v.emit_cast_lty_void(v.acx.type_of(op))
+                                        });
+                                    }
+                                    return;
+                                }
+                            };
+
+                            let elem_ty = print_ty(tcx, pointee_lty.ty);
+                            // Bytes compare as unsigned, so only single-byte unsigned elements
+                            // order the same way.
+                            let ordered = matches!(pointee_lty.ty.kind(), TyKind::Uint(UintTy::U8));
+                            v.emit(RewriteKind::MemcmpSafe { elem_ty, ordered });
+                        });
+                    }
+
+                    Callee::Memchr => {
+                        self.enter_rvalue(|v| {
+                            // `MemchrSafe` requires the searched buffer and the result to be
+                            // rewritten to references to the same byte type, so that comparing
+                            // elements is the same as comparing bytes.  Otherwise, leave the
+                            // `memchr` call intact and cast the buffer back to `void*`.
+                            let src_lty = v.acx.type_of(&args[0]);
+                            let src_pointee = v.pointee_lty(src_lty);
+                            let dest_pointee = v.pointee_lty(pl_ty);
+                            let common_pointee = src_pointee.filter(|&x| {
+                                dest_pointee.map_or(false, |y| v.same_rewritten_lty(x, y))
+                            });
+                            let is_fixed = |lty: LTy<'tcx>| {
+                                lty.label.is_none() || v.flags[lty.label].contains(FlagSet::FIXED)
+                            };
+                            let pointee_lty = match common_pointee {
+                                Some(x)
+                                    if matches!(
+                                        x.ty.kind(),
+                                        TyKind::Int(IntTy::I8) | TyKind::Uint(UintTy::U8)
+                                    ) && !is_fixed(src_lty)
+                                        && !is_fixed(pl_ty) =>
+                                {
+                                    x
+                                }
+                                _ => {
+                                    v.enter_call_arg(0, |v| v.emit_cast_lty_void(src_lty));
+                                    return;
+                                }
+                            };
+                            let result_desc = type_desc::perms_to_desc_with_pointee(
+                                tcx,
+                                pointee_lty.ty,
+                                pl_ty.ty,
+                                v.perms[pl_ty.label],
+                                v.flags[pl_ty.label],
+                            );
+                            let mutbl = match result_desc.own {
+                                Ownership::Imm => false,
+                                Ownership::Mut => true,
+                                _ => {
+                                    v.enter_call_arg(0, |v| v.emit_cast_lty_void(src_lty));
+                                    return;
+                                }
+                            };
+
+                            // The buffer is searched as a slice, and `MemchrSafe` returns the rest
+                            // of it as an `Option`, which is then cast to the result type.
+                            let slice_desc = TypeDesc {
+                                own: result_desc.own,
+                                qty: Quantity::Slice,
+                                dyn_owned: false,
+                                option: false,
+                                pointee_ty: pointee_lty.ty,
+                            };
+                            v.enter_call_arg(0, |v| v.visit_operand_desc(&args[0], slice_desc));
+                            let elem_ty = print_ty(tcx, pointee_lty.ty);
+                            v.emit(RewriteKind::MemchrSafe { elem_ty, mutbl });
+                            v.emit_cast_desc_desc(
+                                TypeDesc {
+                                    option: true,
+                                    ..slice_desc
+                                },
+                                result_desc,
+                            );
+                        });
+                    }
+
                     Callee::Memset => {
                         self.enter_rvalue(|v| {
                             // TODO: Only emit `MemsetSafe` if the rewritten argument type and
@@ -589,16 +1435,11 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                             };
 
                             let orig_pointee_ty = pointee_lty.ty;
-                            let ty_layout = tcx
-                                .layout_of(ParamEnv::reveal_all().and(orig_pointee_ty))
-                                .unwrap();
-                            let elem_size = ty_layout.layout.size().bytes();
+                            let elem_ty = print_ty(tcx, orig_pointee_ty);
                             let dest_single = !v.perms[dest_lty.label]
                                 .intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB);
 
-                            // TODO: use rewritten types here, so that the `ZeroizeType` will
-                            // reflect the actual types and fields after rewriting.
-                            let zero_ty = match ZeroizeType::from_ty(tcx, orig_pointee_ty) {
+                            let zero_ty = match v.zeroize_type(orig_pointee_ty, Some(pointee_lty)) {
                                 Some(x) => x,
                                 // TODO: emit void* cast before bailing out, as described above
                                 None => return,
@@ -606,7 +1447,7 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
 
                             v.emit(RewriteKind::MemsetZeroize {
                                 zero_ty,
-                                elem_size,
+                                elem_ty,
                                 dest_single,
                             });
 
@@ -657,48 +1498,58 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                             };
 
                             let orig_pointee_ty = pointee_lty.ty;
-                            let ty_layout = tcx
-                                .layout_of(ParamEnv::reveal_all().and(orig_pointee_ty))
-                                .unwrap();
-                            let elem_size = ty_layout.layout.size().bytes();
+                            let elem_ty = print_ty(tcx, orig_pointee_ty);
                             let single = !v.perms[dest_lty.label]
                                 .intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB);
-
-                            // TODO: use rewritten types here, so that the `ZeroizeType` will
-                            // reflect the actual types and fields after rewriting.
-                            let zero_ty = match ZeroizeType::from_ty(tcx, orig_pointee_ty) {
-                                Some(x) => x,
+                            // A zero-size allocation may return null.  A slice result can be
+                            // empty instead, but a single-item result must be `None`, which is
+                            // only possible if the destination is nullable.
+                            let option = single
+                                && !v.perms[dest_lty.label].contains(PermissionSet::NON_NULL);
+                            // A growable buffer is allocated as a `Vec` directly.
+                            let vec = !single && v.flags[dest_lty.label].contains(FlagSet::VEC);
+
+                            let rw = match v.zeroize_type(orig_pointee_ty, Some(pointee_lty)) {
+                                Some(zero_ty) => match *callee {
+                                    Callee::Malloc => RewriteKind::MallocSafe {
+                                        zero_ty,
+                                        elem_ty,
+                                        single,
+                                        option,
+                                        vec,
+                                    },
+                                    Callee::Calloc => RewriteKind::CallocSafe {
+                                        zero_ty,
+                                        elem_ty,
+                                        single,
+                                        option,
+                                        vec,
+                                    },
+                                    _ => unreachable!(),
+                                },
+                                // With `--malloc-uninit`, a type with no zero value can still be
+                                // allocated if it's fully initialized before any other use.
+                                None if v.uninit_writes.contains_key(&v.loc) => {
+                                    RewriteKind::MallocUninit { elem_ty, option }
+                                }
                                 // TODO: emit void* cast before bailing out
                                 None => return,
                             };
-
-                            let rw = match *callee {
-                                Callee::Malloc => RewriteKind::MallocSafe {
-                                    zero_ty,
-                                    elem_size,
-                                    single,
-                                },
-                                Callee::Calloc => RewriteKind::CallocSafe {
-                                    zero_ty,
-                                    elem_size,
-                                    single,
-                                },
-                                _ => unreachable!(),
-                            };
                             v.emit(rw);
 
-                            // `MallocSafe` produces either `Box<T>` or `Box<[T]>`.  Emit a cast
-                            // from that type to the required output type.
+                            // `MallocSafe` and `MallocUninit` produce either `Box<T>` or `Box<[T]>`, possibly
+                            // wrapped in `Option`, or `Vec<T>`.  Emit a cast from that type to the
+                            // required output type.
                             v.emit_cast_adjust_lty(
                                 |desc| TypeDesc {
-                                    own: Ownership::Box,
+                                    own: if vec { Ownership::Vec } else { Ownership::Box },
                                     qty: if single {
                                         Quantity::Single
                                     } else {
                                         Quantity::Slice
                                     },
                                     dyn_owned: false,
-                                    option: false,
+                                    option,
                                     pointee_ty: desc.pointee_ty,
                                 },
                                 dest_lty,
@@ -718,13 +1569,49 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                             let single = !v.perms[src_lty.label]
                                 .intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB);
 
-                            // Cast to either `Box<T>` or `Box<[T]>` (depending on `single`).  This
+                            // A `DynOwned` pointer may have given up ownership of its allocation
+                            // before reaching `free`, so the configured policy applies.
+                            if v.is_dyn_owned(src_lty) {
+                                match v.free_policy() {
+                                    FreePolicy::DynOwned => {}
+                                    FreePolicy::Skip => {
+                                        v.err(DontRewriteFnReason::USER_REQUEST);
+                                        return;
+                                    }
+                                    FreePolicy::Unsafe => {
+                                        let desc = type_desc::perms_to_desc(
+                                            src_lty.ty,
+                                            v.perms[src_lty.label],
+                                            v.flags[src_lty.label],
+                                        );
+                                        v.enter_call_arg(0, |v| {
+                                            v.emit(RewriteKind::FreeUnsafeArg {
+                                                option: desc.option,
+                                                c_string: desc.qty == Quantity::CStr,
+                                                vec: desc.own == Ownership::Vec,
+                                            })
+                                        });
+                                        return;
+                                    }
+                                }
+                            }
+
+                            // Cast to either `Box<T>` or `Box<[T]>` (depending on `single`), or
+                            // to `CString` for a string or `Vec<T>` for a growable buffer.  This
                             // ensures a panic occurs when `free`ing a pointer that no longer has
-                            // ownership.
+                            // ownership.  A shared `Rc<RefCell<T>>` or `Arc<Mutex<T>>` is dropped
+                            // as is, and its object is freed once no other pointer refers to it.
                             v.enter_call_arg(0, |v| {
                                 v.emit_cast_lty_adjust(src_lty, |desc| TypeDesc {
-                                    own: Ownership::Box,
-                                    qty: if single {
+                                    own: match desc.own {
+                                        Ownership::Vec => Ownership::Vec,
+                                        Ownership::RcRefCell => Ownership::RcRefCell,
+                                        Ownership::ArcMutex => Ownership::ArcMutex,
+                                        _ => Ownership::Box,
+                                    },
+                                    qty: if desc.qty == Quantity::CStr {
+                                        Quantity::CStr
+                                    } else if single {
                                         Quantity::Single
                                     } else {
                                         Quantity::Slice
@@ -735,17 +1622,31 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                                 });
                             });
 
-                            v.emit(RewriteKind::FreeSafe { single });
+                            // The cast above preserves `Option`, so a nullable argument reaches
+                            // `FreeSafe` as an `Option`.
+                            let option = !v.perms[src_lty.label].contains(PermissionSet::NON_NULL);
+                            v.emit(RewriteKind::FreeSafe { single, option });
                         });
                     }
 
+                    Callee::Printf => self.visit_printf_call(false, args, pl_ty),
+                    Callee::Fprintf => self.visit_printf_call(true, args, pl_ty),
+                    Callee::Sprintf => self.visit_sprintf_call(false, args, pl_ty),
+                    Callee::Snprintf => self.visit_sprintf_call(true, args, pl_ty),
+                    Callee::Strdup => self.visit_strdup_call(false, args, pl_ty),
+                    Callee::Strndup => self.visit_strdup_call(true, args, pl_ty),
+                    Callee::Qsort => self.visit_qsort_call(args),
+                    Callee::Bsearch => self.visit_bsearch_call(args, pl_ty),
+
                     Callee::Realloc => {
                         self.enter_rvalue(|v| {
                             let src_lty = v.acx.type_of(&args[0]);
                             let src_pointee = v.pointee_lty(src_lty);
                             let dest_lty = v.acx.type_of(destination);
                             let dest_pointee = v.pointee_lty(dest_lty);
-                            let common_pointee = dest_pointee.filter(|&x| Some(x) == src_pointee);
+                            let common_pointee = dest_pointee.filter(|&x| {
+                                src_pointee.map_or(false, |y| v.same_rewritten_lty(x, y))
+                            });
                             let pointee_lty = match common_pointee {
                                 Some(x) => x,
                                 // TODO: emit void* cast before bailing out
@@ -753,23 +1654,58 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                             };
 
                             let orig_pointee_ty = pointee_lty.ty;
-                            let ty_layout = tcx
-                                .layout_of(ParamEnv::reveal_all().and(orig_pointee_ty))
-                                .unwrap();
-                            let elem_size = ty_layout.layout.size().bytes();
+                            let elem_ty = print_ty(tcx, orig_pointee_ty);
                             let dest_single = !v.perms[dest_lty.label]
                                 .intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB);
                             let src_single = !v.perms[src_lty.label]
                                 .intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB);
-
-                            // TODO: use rewritten types here, so that the `ZeroizeType` will
-                            // reflect the actual types and fields after rewriting.
-                            let zero_ty = match ZeroizeType::from_ty(tcx, orig_pointee_ty) {
+                            // The cast of the input below preserves its `Option`, so a nullable
+                            // input reaches `ReallocSafe` as an `Option`.
+                            let src_option =
+                                !v.perms[src_lty.label].contains(PermissionSet::NON_NULL);
+                            // `realloc(p, 0)` may return null, as in `malloc`.
+                            let dest_option = dest_single
+                                && !v.perms[dest_lty.label].contains(PermissionSet::NON_NULL);
+
+                            let zero_ty = match v.zeroize_type(orig_pointee_ty, Some(pointee_lty)) {
                                 Some(x) => x,
                                 // TODO: emit void* cast before bailing out
                                 None => return,
                             };
 
+                            // A growable buffer is a `Vec` on both sides, and is resized in place.
+                            if !src_single
+                                && !dest_single
+                                && v.flags[src_lty.label].contains(FlagSet::VEC)
+                                && v.flags[dest_lty.label].contains(FlagSet::VEC)
+                            {
+                                v.enter_call_arg(0, |v| {
+                                    v.emit_cast_lty_adjust(src_lty, |desc| TypeDesc {
+                                        own: Ownership::Vec,
+                                        qty: Quantity::Slice,
+                                        dyn_owned: false,
+                                        option: desc.option,
+                                        pointee_ty: desc.pointee_ty,
+                                    });
+                                });
+                                v.emit(RewriteKind::ReallocVec {
+                                    zero_ty,
+                                    elem_ty,
+                                    src_option,
+                                });
+                                v.emit_cast_adjust_lty(
+                                    |desc| TypeDesc {
+                                        own: Ownership::Vec,
+                                        qty: Quantity::Slice,
+                                        dyn_owned: false,
+                                        option: false,
+                                        pointee_ty: desc.pointee_ty,
+                                    },
+                                    dest_lty,
+                                );
+                                return;
+                            }
+
                             // Cast input to either `Box<T>` or `Box<[T]>`, as in `free`.
                             v.enter_call_arg(0, |v| {
                                 v.emit_cast_lty_adjust(src_lty, |desc| TypeDesc {
@@ -787,9 +1723,11 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
 
                             v.emit(RewriteKind::ReallocSafe {
                                 zero_ty,
-                                elem_size,
+                                elem_ty,
                                 src_single,
                                 dest_single,
+                                src_option,
+                                dest_option,
                             });
 
                             // Cast output from `Box<T>`/`Box<[T]>` to the target type, as in
@@ -803,7 +1741,7 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                                         Quantity::Slice
                                     },
                                     dyn_owned: false,
-                                    option: false,
+                                    option: dest_option,
                                     pointee_ty: desc.pointee_ty,
                                 },
                                 dest_lty,
@@ -811,6 +1749,42 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                         });
                     }
 
+                    Callee::UnknownDef(UnknownDefCallee::Direct {
+                        def_id,
+                        is_foreign: true,
+                        ..
+                    }) => {
+                        if let Some(str_fn) = StrFn::from_def_id(tcx, def_id) {
+                            self.visit_str_call(str_fn, args, pl_ty);
+                        } else {
+                            // Other foreign functions take strings as raw pointers.  An object
+                            // shared with a new thread, as by `pthread_create`, is handed over as
+                            // a raw pointer to its `Mutex`, which the thread converts back with
+                            // `ArcFromRaw`.
+                            self.enter_rvalue(|v| {
+                                for (i, op) in args.iter().enumerate() {
+                                    let lty = v.acx.type_of(op);
+                                    if !lty.label.is_none()
+                                        && v.flags[lty.label].contains(FlagSet::C_STRING)
+                                    {
+                                        v.enter_call_arg(i, |v| {
+                                            v.emit_cast_lty_raw(lty);
+                                        });
+                                    } else if v.is_arc_mutex(lty) {
+                                        let nullable = v.is_nullable(lty.label);
+                                        v.enter_call_arg(i, |v| {
+                                            if nullable {
+                                                v.emit(RewriteKind::OptionUnwrap);
+                                            }
+                                            v.emit(RewriteKind::ArcIntoRaw);
+                                            v.emit(RewriteKind::CastRawToVoid);
+                                        });
+                                    }
+                                }
+                            });
+                        }
+                    }
+
                     _ => {}
                 }
             }
@@ -819,20 +1793,63 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
             TerminatorKind::GeneratorDrop => {}
             TerminatorKind::FalseEdge { .. } => {}
             TerminatorKind::FalseUnwind { .. } => {}
-            TerminatorKind::InlineAsm { .. } => todo!("terminator {:?}", term),
+            TerminatorKind::InlineAsm { ref operands, .. } => {
+                for (i, op) in operands.iter().enumerate() {
+                    match *op {
+                        InlineAsmOperand::In { ref value, .. } => match *value {
+                            Operand::Copy(pl) | Operand::Move(pl) => {
+                                self.enter_call_arg(i, |v| v.visit_asm_place(pl, PlaceAccess::Imm));
+                            }
+                            Operand::Constant(..) => {
+                                if self.changes_pointer_types(self.acx.type_of(value)) {
+                                    self.err(DontRewriteFnReason::INLINE_ASM);
+                                }
+                            }
+                        },
+                        InlineAsmOperand::Out {
+                            place: Some(pl), ..
+                        } => {
+                            self.enter_call_arg(i, |v| v.visit_asm_place(pl, PlaceAccess::Mut));
+                        }
+                        InlineAsmOperand::InOut {
+                            ref in_value,
+                            out_place,
+                            ..
+                        } => {
+                            if let Some(pl) = in_value.place() {
+                                self.enter_call_arg(i, |v| v.visit_asm_place(pl, PlaceAccess::Imm));
+                            }
+                            if let Some(pl) = out_place {
+                                self.enter_call_arg(i, |v| v.visit_asm_place(pl, PlaceAccess::Mut));
+                            }
+                        }
+                        InlineAsmOperand::Out { place: None, .. }
+                        | InlineAsmOperand::Const { .. }
+                        | InlineAsmOperand::SymFn { .. }
+                        | InlineAsmOperand::SymStatic { .. } => {}
+                    }
+                }
+            }
         }
     }
 
     /// Visit an `Rvalue`.  If `expect_ty` is `Some`, also emit whatever casts are necessary to
     /// make the `Rvalue` produce a value of type `expect_ty`.
     fn visit_rvalue(&mut self, rv: &Rvalue<'tcx>, expect_ty: Option<LTy<'tcx>>) {
-        eprintln!("mir_op::visit_rvalue: {:?}, expect {:?}", rv, expect_ty);
+        trace!("mir_op::visit_rvalue: {:?}, expect {:?}", rv, expect_ty);
         match *rv {
             Rvalue::Use(ref op) => {
                 self.enter_rvalue_operand(0, |v| v.visit_operand(op, expect_ty));
             }
             Rvalue::Repeat(ref op, _) => {
-                self.enter_rvalue_operand(0, |v| v.visit_operand(op, None));
+                // The operand becomes each element of the array, so cast it to the element type.
+                // This gives `[ptr::null_mut(); N]` the same null-to-`None` rewrites as an
+                // assignment of `ptr::null_mut()` to a single element.
+                let elem_ty = expect_ty.map(|lty| {
+                    assert!(lty.ty.is_array());
+                    lty.args[0]
+                });
+                self.enter_rvalue_operand(0, |v| v.visit_operand(op, elem_ty));
             }
             Rvalue::Ref(_rg, kind, pl) => {
                 let mutbl = match kind {
@@ -890,12 +1907,20 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                 self.enter_rvalue_operand(0, |v| v.visit_operand(op, None));
                 if let Some(rv_lty) = expect_ty {
                     let op_lty = self.acx.type_of(op);
+                    if self.is_arc_mutex(rv_lty) && !self.is_arc_mutex(op_lty) {
+                        // A thread's start routine converting its argument back to the object
+                        // shared with it.  `arc_mutex` only allows this for the argument.
+                        let ty = print_ty(self.acx.tcx(), rv_lty.args[0].ty);
+                        self.emit(RewriteKind::ArcFromRaw { ty });
+                        return;
+                    }
                     let op_pointee = self.pointee_lty(op_lty);
                     let rv_pointee = self.pointee_lty(rv_lty);
-                    // TODO: Check `pointee_types` recursively to handle pointer-to-pointer cases.
-                    // For example `op_pointee = *mut /*p1*/ c_void` and `rv_pointee = *mut /*p2*/
-                    // c_void`, where `p1` and `p2` both have `pointee_types` entries of `u8`.
-                    let common_pointee = op_pointee.filter(|&x| Some(x) == rv_pointee);
+                    // The labels of pointer-to-pointer pointees differ even when both resolve to the
+                    // same type, as with `*mut /*p1*/ c_void` and `*mut /*p2*/ c_void`, where `p1`
+                    // and `p2` both point to `u8`, so compare how they will be rewritten instead.
+                    let common_pointee = op_pointee
+                        .filter(|&x| rv_pointee.map_or(false, |y| self.same_rewritten_lty(x, y)));
                     if let Some(pointee_lty) = common_pointee {
                         let op_desc = type_desc::perms_to_desc_with_pointee(
                             self.acx.tcx(),
@@ -911,12 +1936,15 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                             self.perms[rv_lty.label],
                             self.flags[rv_lty.label],
                         );
-                        eprintln!("Cast with common pointee {:?}:\n  op_desc = {:?}\n  rv_desc = {:?}\n  matches? {}",
+                        trace!("Cast with common pointee {:?}:\n  op_desc = {:?}\n  rv_desc = {:?}\n  matches? {}",
                             pointee_lty, op_desc, rv_desc, op_desc == rv_desc);
                         if op_desc == rv_desc {
                             // After rewriting, the input and output types of the cast will be
                             // identical.  This means we can delete the cast.
                             self.emit(RewriteKind::RemoveCast);
+                            self.enter_rvalue_operand(0, |v| {
+                                v.emit_shared_clone(op, op_lty, rv_lty)
+                            });
                         }
                     }
                 }
@@ -936,9 +1964,29 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
             Rvalue::Discriminant(pl) => {
                 self.enter_rvalue_place(0, |v| v.visit_place(pl, PlaceAccess::Imm));
             }
-            Rvalue::Aggregate(ref _kind, ref ops) => {
-                for (i, op) in ops.iter().enumerate() {
-                    self.enter_rvalue_operand(i, |v| v.visit_operand(op, None));
+            Rvalue::Aggregate(ref kind, ref ops) => {
+                // Cast each operand to the type of the array element, struct field, or tuple
+                // element it initializes, as in the pseudo-assignments of the dataflow analysis.
+                let op_expect_tys = match (expect_ty, &**kind) {
+                    (Some(lty), AggregateKind::Array(..)) => vec![Some(lty.args[0]); ops.len()],
+                    (Some(lty), &AggregateKind::Adt(adt_did, variant_idx, _, _, None)) => {
+                        let adt_def = self.acx.tcx().adt_def(adt_did);
+                        let fields = &adt_def.variant(variant_idx).fields;
+                        fields
+                            .iter()
+                            .map(|field| {
+                                let unresolved_field_lty = self.acx.gacx.field_ltys[&field.did];
+                                Some(self.acx.lcx().subst(unresolved_field_lty, lty.args))
+                            })
+                            .collect()
+                    }
+                    (Some(lty), AggregateKind::Tuple) => {
+                        lty.args.iter().map(|&elem_lty| Some(elem_lty)).collect()
+                    }
+                    _ => vec![None; ops.len()],
+                };
+                for (i, (op, op_expect_ty)) in ops.iter().zip(op_expect_tys).enumerate() {
+                    self.enter_rvalue_operand(i, |v| v.visit_operand(op, op_expect_ty));
                 }
             }
             Rvalue::ShallowInitBox(ref op, _ty) => {
@@ -955,13 +2003,18 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
     fn visit_operand(&mut self, op: &Operand<'tcx>, expect_ty: Option<LTy<'tcx>>) {
         match *op {
             Operand::Copy(pl) | Operand::Move(pl) => {
-                // TODO: should this be Move, Imm, or dependent on the type?
-                self.enter_operand_place(|v| v.visit_place(pl, PlaceAccess::Move));
+                let access = self.operand_access(pl);
+                self.enter_operand_place(|v| v.visit_place(pl, access));
 
                 if let Some(expect_ty) = expect_ty {
                     let ptr_lty = self.acx.type_of(pl);
                     if !ptr_lty.label.is_none() {
                         self.emit_cast_lty_lty(ptr_lty, expect_ty);
+                        // An assignment's operand is expected to have its own type, and the
+                        // assignment clones it if needed after casting to the destination.
+                        if expect_ty.label != ptr_lty.label {
+                            self.emit_shared_clone(op, ptr_lty, expect_ty);
+                        }
                     }
                 }
             }
@@ -969,12 +2022,38 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         }
     }
 
+    /// If `op` copies an `Rc<RefCell<T>>` or `Arc<Mutex<T>>` to another pointer of the same type,
+    /// emit a `Clone` so the original isn't moved.  Casts that change the type already clone the
+    /// `Rc` or `Arc`.
+    fn emit_shared_clone(&mut self, op: &Operand<'tcx>, from_lty: LTy<'tcx>, to_lty: LTy<'tcx>) {
+        if !matches!(*op, Operand::Copy(..)) {
+            return;
+        }
+        let shared = |lty| self.is_rc_refcell(lty) || self.is_arc_mutex(lty);
+        if !shared(from_lty) || !shared(to_lty) {
+            return;
+        }
+        let from = type_desc::perms_to_desc(
+            from_lty.ty,
+            self.perms[from_lty.label],
+            self.flags[from_lty.label],
+        );
+        let to = type_desc::perms_to_desc(
+            to_lty.ty,
+            self.perms[to_lty.label],
+            self.flags[to_lty.label],
+        );
+        if PtrDesc::from(from) == PtrDesc::from(to) {
+            self.emit(RewriteKind::Clone);
+        }
+    }
+
     /// Like [`Self::visit_operand`], but takes an expected `TypeDesc` instead of an expected `LTy`.
     fn visit_operand_desc(&mut self, op: &Operand<'tcx>, expect_desc: TypeDesc<'tcx>) {
         match *op {
             Operand::Copy(pl) | Operand::Move(pl) => {
-                // TODO: should this be Move, Imm, or dependent on the type?
-                self.visit_place(pl, PlaceAccess::Move);
+                let access = self.operand_access(pl);
+                self.visit_place(pl, access);
 
                 let ptr_lty = self.acx.type_of(pl);
                 if !ptr_lty.label.is_none() {
@@ -985,127 +2064,637 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         }
     }
 
-    fn visit_place(&mut self, pl: Place<'tcx>, access: PlaceAccess) {
-        let mut ltys = Vec::with_capacity(1 + pl.projection.len());
-        ltys.push(self.acx.type_of(pl.local));
-        for proj in pl.projection {
-            let prev_lty = ltys.last().copied().unwrap();
-            ltys.push(self.acx.projection_lty(prev_lty, &proj));
+    fn visit_place(&mut self, pl: Place<'tcx>, access: PlaceAccess) {
+        let mut ltys = Vec::with_capacity(1 + pl.projection.len());
+        ltys.push(self.acx.type_of(pl.local));
+        let mut variant = None;
+        for proj in pl.projection {
+            let prev_lty = ltys.last().copied().unwrap();
+            ltys.push(self.acx.projection_lty(prev_lty, variant, &proj));
+            variant = util::downcast_variant(&proj);
+        }
+        self.visit_place_ref(pl.as_ref(), &ltys, access);
+    }
+
+    /// Generate rewrites for a `Place` represented as a `PlaceRef`.  `proj_ltys` gives the `LTy`
+    /// for the `Local` and after each projection.  `access` describes how the place is being used:
+    /// immutably, mutably, or being moved out of.
+    fn visit_place_ref(
+        &mut self,
+        pl: PlaceRef<'tcx>,
+        proj_ltys: &[LTy<'tcx>],
+        access: PlaceAccess,
+    ) {
+        let (&last_proj, rest) = match pl.projection.split_last() {
+            Some(x) => x,
+            None => return,
+        };
+
+        debug_assert!(pl.projection.len() >= 1);
+        // `LTy` of the base place, before the last projection.
+        let base_lty = proj_ltys[pl.projection.len() - 1];
+        // `LTy` resulting from applying `last_proj` to `base_lty`.
+        let proj_lty = proj_ltys[pl.projection.len()];
+
+        let base_pl = PlaceRef {
+            local: pl.local,
+            projection: rest,
+        };
+        match last_proj {
+            PlaceElem::Deref => {
+                self.enter_place_deref_pointer(|v| {
+                    v.visit_place_ref(base_pl, proj_ltys, access);
+                    if v.is_nullable(base_lty.label) {
+                        // If the pointer type is non-copy, downgrade (borrow) before calling
+                        // `unwrap()`.
+                        let desc = type_desc::perms_to_desc(
+                            base_lty.ty,
+                            v.perms[base_lty.label],
+                            v.flags[base_lty.label],
+                        );
+                        if matches!(desc.own, Ownership::RcRefCell | Ownership::ArcMutex) {
+                            // `Rc` and `Arc` have no `as_deref_mut`, but the `RefCell` or `Mutex`
+                            // is borrowed mutably below through a shared reference.
+                            v.emit(RewriteKind::OptionDowngrade {
+                                mutbl: false,
+                                deref: false,
+                            });
+                        } else if !desc.own.is_copy() {
+                            v.emit(RewriteKind::OptionDowngrade {
+                                mutbl: access == PlaceAccess::Mut,
+                                deref: true,
+                            });
+                        }
+                        v.emit(RewriteKind::OptionUnwrap);
+                    }
+                    if v.is_dyn_owned(base_lty) {
+                        v.emit(RewriteKind::DynOwnedDowngrade {
+                            mutbl: access == PlaceAccess::Mut,
+                        });
+                    }
+                    if v.is_rc_refcell(base_lty) {
+                        let mutbl = access == PlaceAccess::Mut;
+                        v.emit(RewriteKind::RefCellBorrow { mutbl });
+                        v.refcell_borrows.push((v.loc, mutbl));
+                    }
+                    if v.is_arc_mutex(base_lty) {
+                        let mutbl = access == PlaceAccess::Mut;
+                        v.emit(RewriteKind::MutexLock { mutbl });
+                        v.mutex_locks.push((v.loc, ()));
+                    }
+                });
+            }
+            PlaceElem::Field(_idx, _ty) => {
+                self.enter_place_field_base(|v| v.visit_place_ref(base_pl, proj_ltys, access));
+            }
+            PlaceElem::Index(_) | PlaceElem::ConstantIndex { .. } | PlaceElem::Subslice { .. } => {
+                self.enter_place_index_array(|v| v.visit_place_ref(base_pl, proj_ltys, access));
+            }
+            PlaceElem::Downcast(_, _) => {
+                // The downcast itself has no effect on the place expression or its type.  Enum
+                // payload fields are accessed through the `Field` projection that follows it,
+                // whose `LTy` in `proj_ltys` is the field of the variant selected here (see
+                // `visit_place`), so pointers in the payload get the same deref and unwrap
+                // rewrites as pointers in struct fields.
+                debug_assert_eq!(proj_lty, base_lty);
+                self.visit_place_ref(base_pl, proj_ltys, access);
+            }
+        }
+    }
+
+    fn visit_ptr_offset(&mut self, op: &Operand<'tcx>, idx: &Operand<'tcx>, result_ty: LTy<'tcx>) {
+        // Compute the expected type for the argument, and emit a cast if needed.
+        let result_ptr = result_ty.label;
+        let result_desc =
+            type_desc::perms_to_desc(result_ty.ty, self.perms[result_ptr], self.flags[result_ptr]);
+
+        let arg_expect_desc = TypeDesc {
+            own: result_desc.own,
+            qty: match result_desc.qty {
+                Quantity::Single => Quantity::Slice,
+                Quantity::Slice => Quantity::Slice,
+                Quantity::OffsetPtr => Quantity::OffsetPtr,
+                Quantity::Array => unreachable!("perms_to_desc should not return Quantity::Array"),
+                Quantity::CStr => unreachable!("c_strings should not give C_STRING to an offset"),
+            },
+            dyn_owned: result_desc.dyn_owned,
+            option: result_desc.option,
+            pointee_ty: result_desc.pointee_ty,
+        };
+
+        self.enter_rvalue(|v| {
+            v.enter_call_arg(0, |v| v.visit_operand_desc(op, arg_expect_desc));
+            // The index may be read from a field, as in `buf.offset((*s).used)`, in which case the
+            // pointers dereferenced to reach it need rewriting too.
+            v.enter_call_arg(1, |v| v.visit_operand(idx, None));
+
+            // Emit `OffsetSlice` for the offset itself.
+            let mutbl = matches!(result_desc.own, Ownership::Mut);
+            if !result_desc.option {
+                v.emit(RewriteKind::OffsetSlice { mutbl });
+            } else {
+                v.emit(RewriteKind::OptionMapOffsetSlice { mutbl });
+            }
+
+            // The `OffsetSlice` operation returns something of the same type as its input.
+            // Afterward, we must cast the result to the `result_ty`/`result_desc`.
+            v.emit_cast_desc_desc(arg_expect_desc, result_desc);
+        });
+    }
+
+    fn visit_str_call(&mut self, str_fn: StrFn, args: &[Operand<'tcx>], result_ty: LTy<'tcx>) {
+        let tcx = self.acx.tcx();
+        // Each string argument must be rewritten to a slice of characters, which is mutable if
+        // it's written through.  Otherwise, leave the call intact and cast the arguments back to
+        // raw pointers.
+        let str_args = &args[..str_fn.num_str_args()];
+        let descs = str_args
+            .iter()
+            .enumerate()
+            .map(|(i, op)| {
+                self.str_arg_desc(op, str_fn.writes_arg(i))
+                    .or_else(|| self.c_str_arg_desc(op).filter(|_| str_fn.accepts_c_str()))
+            })
+            .collect::<Option<Vec<_>>>();
+
+        self.enter_rvalue(|v| {
+            let descs = match descs {
+                Some(x) => x,
+                None => {
+                    for (i, op) in str_args.iter().enumerate() {
+                        v.enter_call_arg(i, |v| {
+                            v.emit_cast_lty_raw(v.acx.type_of(op));
+                        });
+                    }
+                    return;
+                }
+            };
+
+            for (i, (op, &desc)) in str_args.iter().zip(&descs).enumerate() {
+                v.enter_call_arg(i, |v| v.visit_operand_desc(op, desc));
+            }
+            let c_strs = descs.iter().map(|d| d.qty == Quantity::CStr).collect();
+            let ret_ty = print_ty(tcx, result_ty.ty);
+            v.emit(RewriteKind::StrSafe {
+                str_fn,
+                c_strs,
+                ret_ty,
+            });
+
+            // `strcpy` and `strncpy` return `dest`, which is usually ignored.
+            if str_fn.returns_dest()
+                && !result_ty.label.is_none()
+                && v.perms[result_ty.label].intersects(PermissionSet::USED)
+            {
+                v.emit_cast_desc_lty(descs[0], result_ty);
+            }
+        });
+    }
+
+    /// Get the slice type that a string argument `op` is passed as, or `None` if it isn't
+    /// rewritten to a slice of characters that's mutable when `writes` is set.
+    fn str_arg_desc(&self, op: &Operand<'tcx>, writes: bool) -> Option<TypeDesc<'tcx>> {
+        let lty = self.acx.type_of(op);
+        if lty.label.is_none() || self.flags[lty.label].contains(FlagSet::FIXED) {
+            return None;
+        }
+        let pointee_ty = self.pointee_lty(lty)?.ty;
+        if !util::is_c_char(pointee_ty) {
+            return None;
+        }
+        let desc = type_desc::perms_to_desc_with_pointee(
+            self.acx.tcx(),
+            pointee_ty,
+            lty.ty,
+            self.perms[lty.label],
+            self.flags[lty.label],
+        );
+        let own = match desc.own {
+            Ownership::Mut => Ownership::Mut,
+            Ownership::Imm if !writes => Ownership::Imm,
+            _ => return None,
+        };
+        if desc.qty != Quantity::Slice {
+            return None;
+        }
+        Some(TypeDesc {
+            own,
+            qty: Quantity::Slice,
+            dyn_owned: false,
+            option: false,
+            pointee_ty,
+        })
+    }
+
+    /// Get the `&CStr` type that a string argument `op` is passed as, or `None` if it isn't
+    /// rewritten to `&CStr` or `CString`.
+    fn c_str_arg_desc(&self, op: &Operand<'tcx>) -> Option<TypeDesc<'tcx>> {
+        let lty = self.acx.type_of(op);
+        if lty.label.is_none() || !self.flags[lty.label].contains(FlagSet::C_STRING) {
+            return None;
+        }
+        let pointee_ty = self.pointee_lty(lty)?.ty;
+        Some(TypeDesc::new(Ownership::Imm, Quantity::CStr, pointee_ty))
+    }
+
+    fn visit_strdup_call(&mut self, bounded: bool, args: &[Operand<'tcx>], result_ty: LTy<'tcx>) {
+        // The string must be rewritten to a slice or `&CStr`, and the result to a `Box` or
+        // `CString` that owns the copy.  Otherwise, leave the call intact and cast the string back
+        // to a raw pointer.
+        let src_desc = self
+            .str_arg_desc(&args[0], false)
+            .or_else(|| self.c_str_arg_desc(&args[0]));
+        let result_owned = !result_ty.label.is_none()
+            && !self.flags[result_ty.label].contains(FlagSet::FIXED)
+            && self
+                .pointee_lty(result_ty)
+                .map_or(false, |x| util::is_c_char(x.ty))
+            && self.perms[result_ty.label].contains(PermissionSet::FREE);
+        let c_string = result_owned && self.flags[result_ty.label].contains(FlagSet::C_STRING);
+
+        self.enter_rvalue(|v| {
+            let src_desc = match src_desc {
+                Some(x) if result_owned => x,
+                _ => {
+                    v.enter_call_arg(0, |v| v.emit_cast_lty_raw(v.acx.type_of(&args[0])));
+                    return;
+                }
+            };
+
+            v.enter_call_arg(0, |v| v.visit_operand_desc(&args[0], src_desc));
+            v.emit(RewriteKind::StrdupSafe {
+                bounded,
+                src_c_str: src_desc.qty == Quantity::CStr,
+                c_string,
+            });
+
+            // `StrdupSafe` produces a `Box<[T]>` or `CString`.  Emit a cast from that type to the
+            // required output type.
+            v.emit_cast_adjust_lty(
+                |desc| TypeDesc {
+                    own: Ownership::Box,
+                    qty: if c_string {
+                        Quantity::CStr
+                    } else {
+                        Quantity::Slice
+                    },
+                    dyn_owned: false,
+                    option: false,
+                    pointee_ty: desc.pointee_ty,
+                },
+                result_ty,
+            );
+        });
+    }
+
+    fn visit_sprintf_call(&mut self, bounded: bool, args: &[Operand<'tcx>], result_ty: LTy<'tcx>) {
+        let tcx = self.acx.tcx();
+        // The format string must be a constant, and the destination and each string printed with
+        // `%s` must be rewritten to slices of characters.  Otherwise, leave the call intact and
+        // cast the pointer arguments back to raw pointers.
+        let fmt_idx = printf::format_arg_index(bounded);
+        let pieces = self
+            .consts
+            .operand_c_str(&args[fmt_idx])
+            .and_then(|fmt| printf::parse_format(&fmt));
+        let descs = pieces.as_ref().and_then(|pieces| {
+            let mut descs = vec![(0, self.str_arg_desc(&args[0], true)?)];
+            descs.extend(self.format_arg_descs(pieces, args, fmt_idx)?);
+            Some(descs)
+        });
+
+        self.enter_rvalue(|v| {
+            let (pieces, descs) = match (pieces, descs) {
+                (Some(pieces), Some(descs)) => (pieces, descs),
+                _ => {
+                    v.cast_args_to_raw(args);
+                    return;
+                }
+            };
+
+            for (i, desc) in descs {
+                v.enter_call_arg(i, |v| v.visit_operand_desc(&args[i], desc));
+            }
+            let ret_ty = print_ty(tcx, result_ty.ty);
+            v.emit(RewriteKind::SprintfSafe {
+                bounded,
+                pieces,
+                ret_ty,
+            });
+        });
+    }
+
+    fn visit_printf_call(&mut self, fprintf: bool, args: &[Operand<'tcx>], result_ty: LTy<'tcx>) {
+        let tcx = self.acx.tcx();
+        // As for `sprintf`, the format string must be a constant and each string printed with `%s`
+        // must be rewritten to a slice.  `fprintf` is only rewritten when printing to `stdout` or
+        // `stderr`.
+        let stderr = if fprintf {
+            let stream = self
+                .consts
+                .operand_static_load(&args[0])
+                .map(|did| tcx.item_name(did));
+            match stream.as_ref().map(|name| name.as_str()) {
+                Some("stdout") => Some(false),
+                Some("stderr") => Some(true),
+                _ => None,
+            }
+        } else {
+            Some(false)
+        };
+        let fmt_idx = if fprintf { 1 } else { 0 };
+        let pieces = stderr
+            .and_then(|_| self.consts.operand_c_str(&args[fmt_idx]))
+            .and_then(|fmt| printf::parse_format(&fmt));
+        let descs = pieces
+            .as_ref()
+            .and_then(|pieces| self.format_arg_descs(pieces, args, fmt_idx));
+
+        self.enter_rvalue(|v| {
+            let (stderr, pieces, descs) = match (stderr, pieces, descs) {
+                (Some(stderr), Some(pieces), Some(descs)) => (stderr, pieces, descs),
+                _ => {
+                    v.cast_args_to_raw(args);
+                    return;
+                }
+            };
+
+            for (i, desc) in descs {
+                v.enter_call_arg(i, |v| v.visit_operand_desc(&args[i], desc));
+            }
+            let ret_ty = print_ty(tcx, result_ty.ty);
+            v.emit(RewriteKind::PrintfSafe {
+                fprintf,
+                stderr,
+                pieces,
+                ret_ty,
+            });
+        });
+    }
+
+    /// Compute the descriptors of the strings printed with `%s` by a call to a `printf`-like
+    /// function whose format string, parsed into `pieces`, is argument `fmt_idx`.  Returns `None`
+    /// if the arguments don't match the format or some string can't be rewritten to a slice.
+    fn format_arg_descs(
+        &self,
+        pieces: &[FmtPiece],
+        args: &[Operand<'tcx>],
+        fmt_idx: usize,
+    ) -> Option<Vec<(usize, TypeDesc<'tcx>)>> {
+        let conversions = pieces.iter().filter(|p| p.is_conversion());
+        if conversions.clone().count() != args.len() - fmt_idx - 1 {
+            return None;
+        }
+        let mut descs = Vec::new();
+        for (i, piece) in (fmt_idx + 1..).zip(conversions) {
+            let is_ptr = !self.acx.type_of(&args[i]).label.is_none();
+            match *piece {
+                FmtPiece::Str { .. } => descs.push((i, self.str_arg_desc(&args[i], false)?)),
+                _ if is_ptr => return None,
+                _ => {}
+            }
+        }
+        Some(descs)
+    }
+
+    /// Cast each pointer argument of a call that's left unchanged back to its original raw
+    /// pointer type.
+    fn cast_args_to_raw(&mut self, args: &[Operand<'tcx>]) {
+        for (i, op) in args.iter().enumerate() {
+            let lty = self.acx.type_of(op);
+            if !lty.label.is_none() {
+                self.enter_call_arg(i, |v| {
+                    v.emit_cast_lty_raw(lty);
+                });
+            }
         }
-        self.visit_place_ref(pl.as_ref(), &ltys, access);
     }
 
-    /// Generate rewrites for a `Place` represented as a `PlaceRef`.  `proj_ltys` gives the `LTy`
-    /// for the `Local` and after each projection.  `access` describes how the place is being used:
-    /// immutably, mutably, or being moved out of.
-    fn visit_place_ref(
-        &mut self,
-        pl: PlaceRef<'tcx>,
-        proj_ltys: &[LTy<'tcx>],
-        access: PlaceAccess,
-    ) {
-        let (&last_proj, rest) = match pl.projection.split_last() {
-            Some(x) => x,
-            None => return,
-        };
+    fn visit_qsort_call(&mut self, args: &[Operand<'tcx>]) {
+        self.enter_rvalue(|v| {
+            // `QsortSafe` requires `base` to be rewritten to a mutable slice and the comparator
+            // to be a known function.  Otherwise, leave the `qsort` call intact and cast `base`
+            // back to `void*`.
+            match v.qsort_safe_rewrite(args) {
+                Some(rw) => v.emit(rw),
+                None => v.enter_call_arg(0, |v| v.emit_cast_lty_void(v.acx.type_of(&args[0]))),
+            }
+        });
+    }
 
-        // TODO: downgrade Move to Imm if the new type is Copy
+    fn qsort_safe_rewrite(&self, args: &[Operand<'tcx>]) -> Option<RewriteKind> {
+        let tcx = self.acx.tcx();
+        let base_lty = self.acx.type_of(&args[0]);
+        if base_lty.label.is_none() || self.flags[base_lty.label].contains(FlagSet::FIXED) {
+            return None;
+        }
+        let pointee_lty = self.pointee_lty(base_lty)?;
+        let base_desc = type_desc::perms_to_desc_with_pointee(
+            tcx,
+            pointee_lty.ty,
+            base_lty.ty,
+            self.perms[base_lty.label],
+            self.flags[base_lty.label],
+        );
+        if base_desc.own != Ownership::Mut
+            || base_desc.qty != Quantity::Slice
+            || base_desc.option
+            || base_desc.dyn_owned
+        {
+            return None;
+        }
 
-        debug_assert!(pl.projection.len() >= 1);
-        // `LTy` of the base place, before the last projection.
-        let base_lty = proj_ltys[pl.projection.len() - 1];
-        // `LTy` resulting from applying `last_proj` to `base_lty`.
-        let _proj_lty = proj_ltys[pl.projection.len()];
+        let (cmp_fn, raw_cmp_args) = self.comparator(&args[3], pointee_lty)?;
+        Some(RewriteKind::QsortSafe {
+            elem_ty: print_ty(tcx, pointee_lty.ty),
+            cmp_fn,
+            raw_cmp_args,
+        })
+    }
 
-        let base_pl = PlaceRef {
-            local: pl.local,
-            projection: rest,
-        };
-        match last_proj {
-            PlaceElem::Deref => {
-                self.enter_place_deref_pointer(|v| {
-                    v.visit_place_ref(base_pl, proj_ltys, access);
-                    if v.is_nullable(base_lty.label) {
-                        // If the pointer type is non-copy, downgrade (borrow) before calling
-                        // `unwrap()`.
-                        let desc = type_desc::perms_to_desc(
-                            base_lty.ty,
-                            v.perms[base_lty.label],
-                            v.flags[base_lty.label],
-                        );
-                        if !desc.own.is_copy() {
-                            v.emit(RewriteKind::OptionDowngrade {
-                                mutbl: access == PlaceAccess::Mut,
-                                deref: true,
-                            });
-                        }
-                        v.emit(RewriteKind::OptionUnwrap);
-                    }
-                    if v.is_dyn_owned(base_lty) {
-                        v.emit(RewriteKind::DynOwnedDowngrade {
-                            mutbl: access == PlaceAccess::Mut,
-                        });
+    fn visit_bsearch_call(&mut self, args: &[Operand<'tcx>], result_ty: LTy<'tcx>) {
+        self.enter_rvalue(|v| {
+            // `BsearchSafe` requires the searched buffer and the result to be rewritten to
+            // references to the same element type, and the comparator to be a known function.
+            // Otherwise, leave the `bsearch` call intact and cast the key and the buffer back to
+            // `void*`.
+            let (rw, pointee_lty, result_desc) = match v.bsearch_safe_rewrite(args, result_ty) {
+                Some(x) => x,
+                None => {
+                    for (i, op) in args.iter().enumerate().take(2) {
+                        v.enter_call_arg(i, |v| v.emit_cast_lty_void(v.acx.type_of(op)));
                     }
-                });
-            }
-            PlaceElem::Field(_idx, _ty) => {
-                self.enter_place_field_base(|v| v.visit_place_ref(base_pl, proj_ltys, access));
-            }
-            PlaceElem::Index(_) | PlaceElem::ConstantIndex { .. } | PlaceElem::Subslice { .. } => {
-                self.enter_place_index_array(|v| v.visit_place_ref(base_pl, proj_ltys, access));
+                    return;
+                }
+            };
+
+            // A comparator that takes raw pointers gets the key unchanged, and one that takes
+            // references gets a reference to the key.
+            let key_lty = v.acx.type_of(&args[0]);
+            match rw {
+                RewriteKind::BsearchSafe {
+                    raw_cmp_args: true, ..
+                } => v.enter_call_arg(0, |v| v.emit_cast_lty_void(key_lty)),
+                _ => {
+                    let key_desc = TypeDesc {
+                        own: Ownership::Imm,
+                        qty: Quantity::Single,
+                        dyn_owned: false,
+                        option: false,
+                        pointee_ty: pointee_lty.ty,
+                    };
+                    v.enter_call_arg(0, |v| v.visit_operand_desc(&args[0], key_desc));
+                }
             }
-            PlaceElem::Downcast(_, _) => {}
-        }
-    }
 
-    fn visit_ptr_offset(&mut self, op: &Operand<'tcx>, result_ty: LTy<'tcx>) {
-        // Compute the expected type for the argument, and emit a cast if needed.
-        let result_ptr = result_ty.label;
-        let result_desc =
-            type_desc::perms_to_desc(result_ty.ty, self.perms[result_ptr], self.flags[result_ptr]);
+            // The buffer is searched as a slice, and `BsearchSafe` returns the rest of it
+            // starting at the match as an `Option`, which is then cast to the result type.
+            let slice_desc = TypeDesc {
+                own: result_desc.own,
+                qty: Quantity::Slice,
+                dyn_owned: false,
+                option: false,
+                pointee_ty: pointee_lty.ty,
+            };
+            v.enter_call_arg(1, |v| v.visit_operand_desc(&args[1], slice_desc));
+            v.emit(rw);
+            v.emit_cast_desc_desc(
+                TypeDesc {
+                    option: true,
+                    ..slice_desc
+                },
+                result_desc,
+            );
+        });
+    }
 
-        let arg_expect_desc = TypeDesc {
-            own: result_desc.own,
-            qty: match result_desc.qty {
-                Quantity::Single => Quantity::Slice,
-                Quantity::Slice => Quantity::Slice,
-                Quantity::OffsetPtr => Quantity::OffsetPtr,
-                Quantity::Array => unreachable!("perms_to_desc should not return Quantity::Array"),
-            },
-            dyn_owned: result_desc.dyn_owned,
-            option: result_desc.option,
-            pointee_ty: result_desc.pointee_ty,
+    fn bsearch_safe_rewrite(
+        &self,
+        args: &[Operand<'tcx>],
+        result_ty: LTy<'tcx>,
+    ) -> Option<(RewriteKind, LTy<'tcx>, TypeDesc<'tcx>)> {
+        let tcx = self.acx.tcx();
+        let is_fixed =
+            |lty: LTy<'tcx>| lty.label.is_none() || self.flags[lty.label].contains(FlagSet::FIXED);
+        let base_lty = self.acx.type_of(&args[1]);
+        if is_fixed(base_lty) || is_fixed(result_ty) {
+            return None;
+        }
+        let pointee_lty = self.pointee_lty(base_lty)?;
+        let result_pointee = self.pointee_lty(result_ty)?;
+        if !self.same_rewritten_lty(pointee_lty, result_pointee) {
+            return None;
+        }
+        let result_desc = type_desc::perms_to_desc_with_pointee(
+            tcx,
+            pointee_lty.ty,
+            result_ty.ty,
+            self.perms[result_ty.label],
+            self.flags[result_ty.label],
+        );
+        let mutbl = match result_desc.own {
+            Ownership::Imm => false,
+            Ownership::Mut => true,
+            _ => return None,
         };
 
-        self.enter_rvalue(|v| {
-            v.enter_call_arg(0, |v| v.visit_operand_desc(op, arg_expect_desc));
+        let (cmp_fn, raw_cmp_args) = self.comparator(&args[4], pointee_lty)?;
+        if !raw_cmp_args {
+            // The key is passed to the comparator as a reference to an element.
+            let key_lty = self.acx.type_of(&args[0]);
+            let key_pointee = self.pointee_lty(key_lty)?;
+            if is_fixed(key_lty) || !self.same_rewritten_lty(key_pointee, pointee_lty) {
+                return None;
+            }
+        }
 
-            // Emit `OffsetSlice` for the offset itself.
-            let mutbl = matches!(result_desc.own, Ownership::Mut);
-            if !result_desc.option {
-                v.emit(RewriteKind::OffsetSlice { mutbl });
-            } else {
-                v.emit(RewriteKind::OptionMapOffsetSlice { mutbl });
+        let rw = RewriteKind::BsearchSafe {
+            elem_ty: print_ty(tcx, pointee_lty.ty),
+            cmp_fn,
+            raw_cmp_args,
+            mutbl,
+        };
+        Some((rw, pointee_lty, result_desc))
+    }
+
+    /// Find the comparator passed as `cmp_op` to `qsort` or `bsearch`, which compares elements of
+    /// type `elem_lty`.  Returns the comparator's path and whether it still takes raw pointers.
+    /// Its arguments must either stay raw pointers, or be rewritten to shared references to the
+    /// element type, so that the elements can be passed to it directly.
+    fn comparator(&self, cmp_op: &Operand<'tcx>, elem_lty: LTy<'tcx>) -> Option<(String, bool)> {
+        let tcx = self.acx.tcx();
+        let cmp_did = self.consts.operand_fn_def(cmp_op)?;
+        let cmp_sig = self.acx.gacx.fn_sigs.get(&cmp_did)?;
+        if cmp_sig.inputs.len() != 2 {
+            return None;
+        }
+        let mut raw_cmp_args = None;
+        for &input_lty in cmp_sig.inputs {
+            let raw = input_lty.label.is_none()
+                || self.flags[input_lty.label].contains(FlagSet::FIXED)
+                || self.acx.gacx.dont_rewrite_fn(cmp_did);
+            if !raw {
+                let input_pointee = self.pointee_lty(input_lty)?;
+                let desc = type_desc::perms_to_desc_with_pointee(
+                    tcx,
+                    input_pointee.ty,
+                    input_lty.ty,
+                    self.perms[input_lty.label],
+                    self.flags[input_lty.label],
+                );
+                if desc.own != Ownership::Imm
+                    || desc.qty != Quantity::Single
+                    || desc.option
+                    || desc.dyn_owned
+                    || !self.same_rewritten_lty(input_pointee, elem_lty)
+                {
+                    return None;
+                }
             }
+            if *raw_cmp_args.get_or_insert(raw) != raw {
+                return None;
+            }
+        }
 
-            // The `OffsetSlice` operation returns something of the same type as its input.
-            // Afterward, we must cast the result to the `result_ty`/`result_desc`.
-            v.emit_cast_desc_desc(arg_expect_desc, result_desc);
-        });
+        let cmp_fn = FmtPrinter::new(tcx, Namespace::ValueNS)
+            .print_value_path(cmp_did, &[])
+            .unwrap()
+            .into_buffer();
+        Some((cmp_fn, raw_cmp_args?))
     }
 
-    fn visit_slice_as_ptr(&mut self, elem_ty: Ty<'tcx>, op: &Operand<'tcx>, result_lty: LTy<'tcx>) {
+    fn visit_slice_as_ptr(
+        &mut self,
+        elem_ty: Ty<'tcx>,
+        mutbl: Mutability,
+        slice_method: Option<&'static str>,
+        op: &Operand<'tcx>,
+        result_lty: LTy<'tcx>,
+    ) {
         let op_lty = self.acx.type_of(op);
         let op_ptr = op_lty.label;
         let result_ptr = result_lty.label;
 
-        let op_desc = type_desc::perms_to_desc_with_pointee(
-            self.acx.tcx(),
-            elem_ty,
-            op_lty.ty,
-            self.perms[op_ptr],
-            self.flags[op_ptr],
-        );
+        let op_desc = match slice_method {
+            // The container's slice view is always a plain reference to a slice.
+            Some(_) => TypeDesc {
+                own: match mutbl {
+                    Mutability::Not => Ownership::Imm,
+                    Mutability::Mut => Ownership::Mut,
+                },
+                qty: Quantity::Slice,
+                dyn_owned: false,
+                option: false,
+                pointee_ty: elem_ty,
+            },
+            None => type_desc::perms_to_desc_with_pointee(
+                self.acx.tcx(),
+                elem_ty,
+                op_lty.ty,
+                self.perms[op_ptr],
+                self.flags[op_ptr],
+            ),
+        };
 
         let result_desc = type_desc::perms_to_desc_with_pointee(
             self.acx.tcx(),
@@ -1118,7 +2707,10 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         self.enter_rvalue(|v| {
             // Generate a cast of our own, replacing the `as_ptr` call.
             // TODO: leave the `as_ptr` in place if we can't produce a working cast
-            v.emit(RewriteKind::RemoveAsPtr);
+            match slice_method {
+                Some(method) => v.emit(RewriteKind::AsPtrToSlice { method }),
+                None => v.emit(RewriteKind::RemoveAsPtr),
+            }
             v.emit_cast_desc_desc(op_desc, result_desc);
         });
     }
@@ -1147,7 +2739,6 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         builder.build_cast_lty_desc(from_lty, to);
     }
 
-    #[allow(dead_code)]
     fn emit_cast_desc_lty(&mut self, from: TypeDesc<'tcx>, to_lty: LTy<'tcx>) {
         let perms = self.perms;
         let flags = self.flags;
@@ -1162,6 +2753,103 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         builder.build_cast_lty_lty(from_lty, to_lty);
     }
 
+    /// Check whether the assignment `pl = rv` borrows an owned pointer stored in a local, and
+    /// the borrow may be returned from the function, where it would outlive the local.  This
+    /// covers a copy or cast of the owned pointer into a non-owning one, which the rewriter emits
+    /// as a reborrow, and an explicit reborrow such as `&mut *p`.  The owned pointer may be a
+    /// field of the local, and the destination may be a field of a returned local.
+    fn escaping_reborrow(&self, pl: Place<'tcx>, rv: &Rvalue<'tcx>, pl_lty: LTy<'tcx>) -> bool {
+        if !self.returned_locals.contains(&pl.local) {
+            return false;
+        }
+        // The owned pointer, which must be stored in the local itself rather than behind another
+        // pointer.
+        let owner = match *rv {
+            Rvalue::Use(ref op) | Rvalue::Cast(_, ref op, _) => match op.place() {
+                Some(src) if !src.is_indirect() => src.as_ref(),
+                _ => return false,
+            },
+            Rvalue::Ref(_, _, src) | Rvalue::AddressOf(_, src) => {
+                match src
+                    .iter_projections()
+                    .find(|&(_, elem)| elem == PlaceElem::Deref)
+                {
+                    Some((base, _)) => base,
+                    None => return false,
+                }
+            }
+            _ => return false,
+        };
+        let owner_lty = self.acx.type_of(owner);
+        if owner_lty.label.is_none() || pl_lty.label.is_none() {
+            return false;
+        }
+        if self.flags[owner_lty.label].contains(FlagSet::FIXED)
+            || self.flags[pl_lty.label].contains(FlagSet::FIXED)
+        {
+            return false;
+        }
+        let from = type_desc::perms_to_desc(
+            owner_lty.ty,
+            self.perms[owner_lty.label],
+            self.flags[owner_lty.label],
+        );
+        let to = type_desc::perms_to_desc(
+            pl_lty.ty,
+            self.perms[pl_lty.label],
+            self.flags[pl_lty.label],
+        );
+        let owned = |own| {
+            matches!(
+                own,
+                Ownership::Box
+                    | Ownership::Vec
+                    | Ownership::Rc
+                    | Ownership::RcRefCell
+                    | Ownership::ArcMutex
+            )
+        };
+        owned(from.own) && !owned(to.own)
+    }
+
+    /// Cast `lty`, whose original type is a raw pointer, back to that raw pointer type after
+    /// rewriting.  If the rewritten pointer is nullable, `None` is converted to a null pointer.
+    /// Returns `false` if `lty` is left unrewritten, in which case no cast is needed.
+    fn emit_cast_lty_raw(&mut self, lty: LTy<'tcx>) -> bool {
+        let mutbl = match *lty.ty.kind() {
+            TyKind::RawPtr(tm) => tm.mutbl == Mutability::Mut,
+            _ => return false,
+        };
+        if lty.label.is_none() || self.flags[lty.label].contains(FlagSet::FIXED) {
+            return false;
+        }
+        let option =
+            type_desc::perms_to_desc(lty.ty, self.perms[lty.label], self.flags[lty.label]).option;
+        let own = if mutbl {
+            Ownership::RawMut
+        } else {
+            Ownership::Raw
+        };
+        self.emit_cast_lty_adjust(lty, |desc| TypeDesc {
+            own,
+            qty: Quantity::Single,
+            dyn_owned: false,
+            option,
+            pointee_ty: desc.pointee_ty,
+        });
+        if option {
+            self.emit(RewriteKind::OptionUnwrapOrNull { mutbl });
+        }
+        true
+    }
+
+    /// Like [`Self::emit_cast_lty_raw`], but casts the result on to a `void` pointer.
+    fn emit_cast_lty_void(&mut self, lty: LTy<'tcx>) {
+        if self.emit_cast_lty_raw(lty) {
+            self.emit(RewriteKind::CastRawToVoid);
+        }
+    }
+
     /// Cast `from_lty` to an adjusted version of itself.  If `from_desc` is the `TypeDesc`
     /// corresponding to `from_lty`, this emits a cast from `from_desc` to `to_adjust(from_desc)`.
     fn emit_cast_lty_adjust(
@@ -1189,41 +2877,14 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
     }
 }
 
-impl ZeroizeType {
-    fn from_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<ZeroizeType> {
-        Some(match *ty.kind() {
-            TyKind::Int(_) | TyKind::Uint(_) => ZeroizeType::Int,
-            TyKind::Bool => ZeroizeType::Bool,
-            TyKind::Adt(adt_def, substs) => {
-                if !adt_def.is_struct() {
-                    return None;
-                }
-                let variant = adt_def.non_enum_variant();
-                let mut fields = Vec::with_capacity(variant.fields.len());
-                for field in &variant.fields {
-                    let name = field.name.to_string();
-                    let ty = field.ty(tcx, substs);
-                    let zero = ZeroizeType::from_ty(tcx, ty)?;
-                    fields.push((name, zero));
-                }
-
-                let name_printer = FmtPrinter::new(tcx, Namespace::ValueNS);
-                let name = name_printer
-                    .print_value_path(adt_def.did(), &[])
-                    .unwrap()
-                    .into_buffer();
-
-                ZeroizeType::Struct(name, fields)
-            }
-            TyKind::Array(elem_ty, _) => {
-                let elem_zero = ZeroizeType::from_ty(tcx, elem_ty)?;
-                ZeroizeType::Array(Box::new(elem_zero))
-            }
-            _ => return None,
-        })
-    }
-}
-
+/// Builds the sequence of [`RewriteKind`]s that casts a value from one pointer type to another,
+/// such as unwrapping an `Option`, reborrowing a `Box` or `&mut`, taking the first element of a
+/// slice, or casting between references and raw pointers.  Each step is passed to the `emit`
+/// callback in the order it applies to the value.
+///
+/// `perms` and `flags` are only used to compute the [`TypeDesc`] of the [`LTy`]s passed to the
+/// `build_cast_lty_*` methods.  For casts between two [`TypeDesc`]s, see
+/// [`api::build_cast`](crate::api::build_cast).
 pub struct CastBuilder<'a, 'tcx, PT1, PT2, F> {
     tcx: TyCtxt<'tcx>,
     perms: &'a PT1,
@@ -1251,6 +2912,7 @@ where
         }
     }
 
+    /// Build a cast from `from` to `to`, panicking if it is unsupported.
     pub fn build_cast_desc_desc(&mut self, from: TypeDesc<'tcx>, to: TypeDesc<'tcx>) {
         self.try_build_cast_desc_desc(from, to).unwrap()
     }
@@ -1285,12 +2947,31 @@ where
             return Ok(());
         }
 
-        if from.option && from.own != to.own {
+        if matches!(
+            from.own,
+            Ownership::Rc | Ownership::RcRefCell | Ownership::ArcMutex
+        ) && from.own == to.own
+        {
+            // Casting between different `Rc` types, as in `Option<Rc<T>>` to `Rc<T>`, consumes
+            // the input.  `p.clone()` allows using the `Rc` without consuming the original.  The
+            // same goes for `Arc`.
+            (self.emit)(RewriteKind::Clone);
+        } else if from.option && matches!(from.own, Ownership::RcRefCell | Ownership::ArcMutex) {
+            // Borrow the `Rc` with `p.as_ref()` before unwrapping it, so the `RefCell` can then be
+            // borrowed in place.  Likewise for the `Mutex` in an `Arc`.
+            (self.emit)(RewriteKind::OptionDowngrade {
+                mutbl: false,
+                deref: false,
+            });
+        } else if from.option && from.own != to.own {
             // Downgrade ownership before unwrapping the `Option` when possible.  This can avoid
             // moving/consuming the input.  For example, if the `from` type is `Option<Box<T>>` and
             // `to` is `&mut T`, we start by calling `p.as_deref_mut()`, which produces
             // `Option<&mut T>` without consuming `p`.
-            if !from.own.is_copy() {
+            //
+            // `Option<DynOwned<T>>` has no `as_deref`, so in that case the `DynOwned` is
+            // downgraded after unwrapping the `Option` instead.
+            if !from.own.is_copy() && !from.dyn_owned {
                 // Note that all non-`Copy` ownership types are also safe.  We don't reach this
                 // code when `from.own` is `Raw` or `RawMut`.
                 match to.own {
@@ -1301,18 +2982,16 @@ where
                         });
                         from.own = Ownership::Imm;
                     }
-                    Ownership::RawMut | Ownership::Cell | Ownership::Mut => {
+                    // `Rc` can't be borrowed mutably.
+                    Ownership::RawMut | Ownership::Cell | Ownership::Mut
+                        if from.own != Ownership::Rc =>
+                    {
                         (self.emit)(RewriteKind::OptionDowngrade {
                             mutbl: true,
                             deref: true,
                         });
                         from.own = Ownership::Mut;
                     }
-                    Ownership::Rc if from.own == Ownership::Rc => {
-                        // `p.clone()` allows using an `Option<Rc<T>>` without consuming the
-                        // original.  However, `RewriteKind::Clone` is not yet implemented.
-                        error!("Option<Rc> -> Option<Rc> clone rewrite NYI");
-                    }
                     _ => {
                         // Remaining cases don't have a valid downgrade operation.  We leave them
                         // as is, and the `unwrap`/`map` operations below will consume the original
@@ -1344,6 +3023,14 @@ where
                     to.pointee_ty
                 );
             }
+            if matches!(from.own, Ownership::RcRefCell | Ownership::ArcMutex) && to.own != from.own
+            {
+                // A `RefCell` borrowed or a `Mutex` locked inside the closure passed to `map`
+                // would be released before the result is used.
+                return Err(format!(
+                    "can't borrow the contents of {from:?} inside `Option::map`"
+                ));
+            }
             (self.emit)(RewriteKind::OptionMapBegin);
             from.option = false;
             in_option_map = true;
@@ -1357,7 +3044,11 @@ where
                 Ownership::RawMut | Ownership::Cell | Ownership::Mut => {
                     (self.emit)(RewriteKind::DynOwnedDowngrade { mutbl: true });
                 }
-                Ownership::Rc | Ownership::Box => {
+                Ownership::Rc
+                | Ownership::Box
+                | Ownership::Vec
+                | Ownership::RcRefCell
+                | Ownership::ArcMutex => {
                     (self.emit)(RewriteKind::DynOwnedUnwrap);
                 }
             }
@@ -1387,6 +3078,30 @@ where
                     return Err(format!("TODO: cast Array to {:?}", to.qty));
                     //from.qty = Quantity::Slice;
                 }
+                // `CStr` converts only to and from raw pointers to its first character.
+                // `CStr::as_ptr` returns `*const c_char`, so a pointer to some other character
+                // type is cast.
+                (Quantity::CStr, Quantity::Single)
+                    if from.own == Ownership::Imm
+                        && matches!(to.own, Ownership::Raw | Ownership::RawMut) =>
+                {
+                    let elem_ty = (to.pointee_ty != self.tcx.types.i8)
+                        .then(|| print_ty(self.tcx, to.pointee_ty));
+                    (self.emit)(RewriteKind::CStrAsPtr { elem_ty });
+                    from.own = Ownership::Raw;
+                    from.qty = Quantity::Single;
+                }
+                (Quantity::Single, Quantity::CStr)
+                    if matches!(from.own, Ownership::Raw | Ownership::RawMut)
+                        && to.own == Ownership::Imm =>
+                {
+                    let cast = from.pointee_ty != self.tcx.types.i8;
+                    (self.emit)(RewriteKind::UnsafeCStrFromPtr { cast });
+                    from.own = Ownership::Imm;
+                    from.qty = Quantity::CStr;
+                }
+                (Quantity::CStr, _) | (_, Quantity::CStr) => break,
+
                 // Bidirectional conversions between `Slice` and `OffsetPtr`.
                 (Quantity::Slice, Quantity::OffsetPtr) | (Quantity::OffsetPtr, Quantity::Slice) => {
                     // Currently a no-op, since `Slice` and `OffsetPtr` are identical.
@@ -1470,6 +3185,35 @@ where
     ) -> Result<Option<Ownership>, String> {
         Ok(match from.own {
             Ownership::Box => match to.own {
+                Ownership::Raw | Ownership::Imm => {
+                    (self.emit)(RewriteKind::Reborrow { mutbl: false });
+                    Some(Ownership::Imm)
+                }
+                // `CString` can only be borrowed immutably, as `&CStr`.
+                Ownership::RawMut if from.qty == Quantity::CStr => {
+                    (self.emit)(RewriteKind::Reborrow { mutbl: false });
+                    Some(Ownership::Imm)
+                }
+                Ownership::RawMut | Ownership::Mut | Ownership::Cell => {
+                    (self.emit)(RewriteKind::Reborrow { mutbl: true });
+                    Some(Ownership::Mut)
+                }
+                Ownership::Vec if matches!(from.qty, Quantity::Slice | Quantity::OffsetPtr) => {
+                    (self.emit)(RewriteKind::VecFromBox);
+                    Some(Ownership::Vec)
+                }
+                Ownership::RcRefCell if from.qty == Quantity::Single => {
+                    (self.emit)(RewriteKind::RcRefCellFromBox);
+                    Some(Ownership::RcRefCell)
+                }
+                Ownership::ArcMutex if from.qty == Quantity::Single => {
+                    (self.emit)(RewriteKind::ArcMutexFromBox);
+                    Some(Ownership::ArcMutex)
+                }
+                _ => None,
+            },
+            // `Vec<T>` derefs to `[T]`, so it's reborrowed like `Box<[T]>`.
+            Ownership::Vec => match to.own {
                 Ownership::Raw | Ownership::Imm => {
                     (self.emit)(RewriteKind::Reborrow { mutbl: false });
                     Some(Ownership::Imm)
@@ -1478,12 +3222,42 @@ where
                     (self.emit)(RewriteKind::Reborrow { mutbl: true });
                     Some(Ownership::Mut)
                 }
+                Ownership::Box => {
+                    (self.emit)(RewriteKind::VecIntoBoxedSlice);
+                    Some(Ownership::Box)
+                }
+                _ => None,
+            },
+            // The contents of `Rc<RefCell<T>>` are borrowed through the `RefCell`, which can't be
+            // converted back to an owning pointer.
+            Ownership::RcRefCell => match to.own {
+                Ownership::Raw | Ownership::Imm => {
+                    (self.emit)(RewriteKind::RefCellBorrow { mutbl: false });
+                    Some(Ownership::Imm)
+                }
+                Ownership::RawMut | Ownership::Mut | Ownership::Cell => {
+                    (self.emit)(RewriteKind::RefCellBorrow { mutbl: true });
+                    Some(Ownership::Mut)
+                }
+                _ => None,
+            },
+            // Likewise, the contents of `Arc<Mutex<T>>` are borrowed by locking the `Mutex`.
+            Ownership::ArcMutex => match to.own {
+                Ownership::Raw | Ownership::Imm => {
+                    (self.emit)(RewriteKind::MutexLock { mutbl: false });
+                    Some(Ownership::Imm)
+                }
+                Ownership::RawMut | Ownership::Mut | Ownership::Cell => {
+                    (self.emit)(RewriteKind::MutexLock { mutbl: true });
+                    Some(Ownership::Mut)
+                }
                 _ => None,
             },
+            // `Rc` can only be borrowed immutably.
             Ownership::Rc => match to.own {
-                Ownership::Imm | Ownership::Raw | Ownership::RawMut => {
-                    return Err("TODO: cast Rc to Imm".to_string());
-                    //Some(Ownership::Imm)
+                Ownership::Imm | Ownership::Raw => {
+                    (self.emit)(RewriteKind::Reborrow { mutbl: false });
+                    Some(Ownership::Imm)
                 }
                 _ => None,
             },
@@ -1494,6 +3268,10 @@ where
                 }
                 Ownership::Cell => {
                     (self.emit)(RewriteKind::CellFromMut);
+                    if matches!(from.qty, Quantity::Slice | Quantity::OffsetPtr) {
+                        // `Cell` + `Slice` is `&[Cell<T>]`, not `&Cell<[T]>`.
+                        (self.emit)(RewriteKind::AsSliceOfCells);
+                    }
                     Some(Ownership::Cell)
                 }
                 Ownership::RawMut if !early => {
@@ -1660,6 +3438,73 @@ where
     }
 }
 
+/// Whether `--malloc-uninit` is set.  Otherwise, `malloc` of a type with no `ZeroizeType` is left
+/// unrewritten.
+fn malloc_uninit() -> bool {
+    env::var("C2RUST_ANALYZE_MALLOC_UNINIT").as_deref() == Ok("1")
+}
+
+/// Checks whether a MIR item mentions any of `locals`.
+struct MentionsLocals<'a> {
+    locals: &'a [Local],
+    found: bool,
+}
+
+impl<'tcx> Visitor<'tcx> for MentionsLocals<'_> {
+    fn visit_local(&mut self, local: Local, _context: PlaceContext, _location: Location) {
+        if self.locals.contains(&local) {
+            self.found = true;
+        }
+    }
+}
+
+/// The maximum number of levels of pointer-to-pointer types resolved by `pointee_lty`.
+const MAX_POINTEE_DEPTH: usize = 4;
+
+/// Print `ty` as Rust source, for use in `size_of::<T>()` expressions in the rewritten code.
+fn print_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> String {
+    let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+    ty.print(printer).unwrap().into_buffer()
+}
+
+/// Find the locals whose values may be returned from `mir`: the return place, and every local
+/// whose value, or a field of it, is copied, moved, cast, or borrowed into a returned local or a
+/// field of one.  A value stored behind a pointer in the source isn't part of the local, so it's
+/// only followed for borrows, which keep the local borrowed.
+fn find_returned_locals(mir: &Body) -> HashSet<Local> {
+    let mut returned = HashSet::new();
+    returned.insert(RETURN_PLACE);
+    loop {
+        let mut changed = false;
+        for bb_data in mir.basic_blocks().iter() {
+            for stmt in &bb_data.statements {
+                let (pl, rv) = match stmt.kind {
+                    StatementKind::Assign(ref x) => (x.0, &x.1),
+                    _ => continue,
+                };
+                if !returned.contains(&pl.local) {
+                    continue;
+                }
+                let direct = |op: &Operand| op.place().filter(|pl| !pl.is_indirect());
+                let srcs: Vec<Place> = match *rv {
+                    Rvalue::Use(ref op) | Rvalue::Cast(_, ref op, _) => {
+                        direct(op).into_iter().collect()
+                    }
+                    Rvalue::Aggregate(_, ref ops) => ops.iter().filter_map(direct).collect(),
+                    Rvalue::Ref(_, _, src) | Rvalue::AddressOf(_, src) => vec![src],
+                    _ => continue,
+                };
+                for src in srcs {
+                    changed |= returned.insert(src.local);
+                }
+            }
+        }
+        if !changed {
+            return returned;
+        }
+    }
+}
+
 pub fn gen_mir_rewrites<'tcx>(
     acx: &AnalysisCtxt<'_, 'tcx>,
     asn: &Assignment,
@@ -1688,6 +3533,9 @@ pub fn gen_mir_rewrites<'tcx>(
         }
     }
 
+    v.warn_refcell_conflicts();
+    v.warn_mutex_conflicts();
+
     let errors = v.errors;
     (out, errors)
 }
@@ -1,9 +1,11 @@
-use self::mir_op::MirRewrite;
 use self::unlower::{MirOrigin, PreciseLoc};
-use crate::context::{AnalysisCtxt, Assignment};
+use crate::context::{AnalysisCtxt, Assignment, DontRewriteFnReason};
+use crate::panic_detail::PanicDetail;
 use crate::pointee_type::PointeeTypes;
 use crate::pointer_id::PointerTable;
+use crate::profile::{self, Pass};
 use crate::rewrite::Rewrite;
+use log::{error, log_enabled, trace, Level};
 use rustc_hir::def_id::DefId;
 use rustc_hir::BodyId;
 use rustc_middle::mir::{Body, Location};
@@ -15,12 +17,19 @@ mod convert;
 mod distribute;
 mod hir_only_casts;
 mod mir_op;
+mod option_regions;
+mod printf;
+mod string;
 mod unlower;
+mod validate;
 
 // Helpers used by the shim builder.
 pub use self::convert::convert_cast_rewrite;
 pub use self::mir_op::CastBuilder;
 
+pub use self::mir_op::{MirRewrite, RewriteKind, SubLoc, ZeroizeType};
+
+#[allow(clippy::type_complexity)]
 pub fn gen_expr_rewrites<'tcx>(
     acx: &mut AnalysisCtxt<'_, 'tcx>,
     asn: &Assignment,
@@ -28,30 +37,51 @@ pub fn gen_expr_rewrites<'tcx>(
     def_id: DefId,
     mir: &Body<'tcx>,
     hir_body_id: BodyId,
-) -> Vec<(Span, Rewrite)> {
-    let (mir_rewrites, errors) = mir_op::gen_mir_rewrites(acx, asn, pointee_types, mir);
+) -> (Vec<(Span, Rewrite)>, HashMap<Location, Vec<MirRewrite>>) {
+    let ldid = def_id.expect_local();
+    let (mir_rewrites, errors) = profile::time(Pass::MirOp, ldid, || {
+        let (mir_rewrites, errors) = mir_op::gen_mir_rewrites(acx, asn, pointee_types, mir);
+        profile::count(mir_rewrites.values().map(|rws| rws.len()).sum());
+        (mir_rewrites, errors)
+    });
     if !errors.is_empty() {
         acx.gacx.dont_rewrite_fns.add(def_id, errors);
     }
-    let unlower_map = unlower::unlower(acx.tcx(), mir, hir_body_id);
-    debug_print_unlower_map(acx.tcx(), mir, &unlower_map, &mir_rewrites);
-    let rewrites_by_expr = distribute::distribute(acx.tcx(), unlower_map, mir_rewrites);
+    if let Err(msg) = validate::validate_mir_rewrites(acx.tcx(), mir, &mir_rewrites) {
+        error!("{msg}");
+        acx.gacx.mark_fn_failed(
+            def_id,
+            DontRewriteFnReason::REWRITE_INVALID,
+            PanicDetail::new(msg),
+        );
+    }
+    let tcx = acx.tcx();
+    let hir_rewrites = profile::time(Pass::HirRewrite, ldid, || {
+        let unlower_map = unlower::unlower(tcx, mir, hir_body_id);
+        debug_print_unlower_map(tcx, mir, &unlower_map, &mir_rewrites);
+        let mut rewrites_by_expr = distribute::distribute(tcx, unlower_map, mir_rewrites.clone());
 
-    eprintln!("distributed rewrites:");
-    for (&hir_id, dist_rws) in &rewrites_by_expr {
-        let ex = acx.tcx().hir().expect_expr(hir_id);
-        eprintln!("  {:?}:", ex.span);
-        for rw in dist_rws {
-            eprintln!("    {rw:?}");
+        trace!("distributed rewrites:");
+        for (&hir_id, dist_rws) in &rewrites_by_expr {
+            let ex = tcx.hir().expect_expr(hir_id);
+            trace!("  {:?}:", ex.span);
+            for rw in dist_rws {
+                trace!("    {rw:?}");
+            }
         }
-    }
 
-    let address_of_rewrites = hir_only_casts::remove_hir_only_casts(acx.tcx(), hir_body_id, |ex| {
-        rewrites_by_expr.contains_key(&ex.hir_id)
+        let region_rewrites =
+            option_regions::rewrite_option_regions(tcx, hir_body_id, &mut rewrites_by_expr);
+        let address_of_rewrites = hir_only_casts::remove_hir_only_casts(tcx, hir_body_id, |ex| {
+            rewrites_by_expr.contains_key(&ex.hir_id)
+        });
+        let mut hir_rewrites = convert::convert_rewrites(tcx, hir_body_id, rewrites_by_expr);
+        hir_rewrites.extend(region_rewrites);
+        hir_rewrites.extend(address_of_rewrites);
+        profile::count(hir_rewrites.len());
+        hir_rewrites
     });
-    let mut hir_rewrites = convert::convert_rewrites(acx.tcx(), hir_body_id, rewrites_by_expr);
-    hir_rewrites.extend(address_of_rewrites);
-    hir_rewrites
+    (hir_rewrites, mir_rewrites)
 }
 
 fn debug_print_unlower_map<'tcx>(
@@ -60,6 +90,10 @@ fn debug_print_unlower_map<'tcx>(
     unlower_map: &BTreeMap<PreciseLoc, MirOrigin>,
     mir_rewrites: &HashMap<Location, Vec<MirRewrite>>,
 ) {
+    if !log_enabled!(Level::Trace) {
+        return;
+    }
+
     let print_for_loc = |loc| {
         let mut rewrites_by_subloc = HashMap::new();
         for rw in mir_rewrites.get(&loc).map_or(&[] as &[_], |x| x) {
@@ -75,30 +109,30 @@ fn debug_print_unlower_map<'tcx>(
             }
             let sublocs = &k.sub;
             let ex = tcx.hir().expect_expr(v.hir_id);
-            eprintln!("      {sublocs:?}: {:?}, {:?}", v.desc, ex.span);
+            trace!("      {sublocs:?}: {:?}, {:?}", v.desc, ex.span);
             for rw_kind in rewrites_by_subloc.remove(&sublocs).unwrap_or_default() {
-                eprintln!("        {rw_kind:?}");
+                trace!("        {rw_kind:?}");
             }
         }
 
         for (sublocs, rw_kinds) in rewrites_by_subloc {
-            eprintln!("      {sublocs:?} (missing unlowering)");
+            trace!("      {sublocs:?} (missing unlowering)");
             for rw_kind in rw_kinds {
-                eprintln!("        {rw_kind:?}");
+                trace!("        {rw_kind:?}");
             }
         }
     };
 
-    eprintln!("unlowering for {:?}:", mir.source);
+    trace!("unlowering for {:?}:", mir.source);
     for (bb_id, bb) in mir.basic_blocks().iter_enumerated() {
-        eprintln!("  block {bb_id:?}:");
+        trace!("  block {bb_id:?}:");
         for (i, stmt) in bb.statements.iter().enumerate() {
             let loc = Location {
                 block: bb_id,
                 statement_index: i,
             };
 
-            eprintln!("    {loc:?}: {stmt:?}");
+            trace!("    {loc:?}: {stmt:?}");
             print_for_loc(loc);
         }
 
@@ -109,7 +143,7 @@ fn debug_print_unlower_map<'tcx>(
                 statement_index: bb.statements.len(),
             };
 
-            eprintln!("    {loc:?}: {term:?}");
+            trace!("    {loc:?}: {term:?}");
             print_for_loc(loc);
         }
     }
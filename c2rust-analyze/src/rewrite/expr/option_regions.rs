@@ -0,0 +1,245 @@
+//! Rewriting of null-checked regions into `if let Some(p) = p { ... }`.
+//!
+//! A nullable pointer `p` is rewritten to an `Option`, and each dereference of `p` gets its own
+//! `OptionUnwrap` rewrite (preceded by an `OptionDowngrade` when `p` isn't `Copy`), so code
+//! like `if !p.is_null() { *p = 1; *p.add(1) = 2; }` becomes a series of
+//! `p.as_deref_mut().unwrap()` calls.  The `OptionMapBegin`/`OptionMapEnd` pairing in
+//! `convert` only applies within the rewrites of a single expression, so it can't help here.
+//!
+//! Instead, this pass looks for `if` and `while` expressions whose condition is `!p.is_null()`
+//! and whose body uses `p` only through such unwraps.  It replaces the condition with
+//! `let Some(p) = p.as_deref_mut()`, which shadows `p` with the unwrapped pointer for the whole
+//! body, and drops the unwraps from the uses of `p` in the body.  The checks that the condition
+//! already performs make the `None` case unreachable inside the body, so this doesn't change
+//! behavior.
+
+use crate::rewrite::expr::distribute::DistRewrite;
+use crate::rewrite::expr::mir_op::RewriteKind;
+use crate::rewrite::Rewrite;
+use log::debug;
+use rustc_hir as hir;
+use rustc_hir::def::Res;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::HirId;
+use rustc_middle::hir::nested_filter;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use std::collections::HashMap;
+
+/// If `ex` is a path expression referring to a local variable, return the `HirId` of the
+/// variable's binding.
+fn local_var(ex: &hir::Expr) -> Option<HirId> {
+    match ex.kind {
+        hir::ExprKind::Path(hir::QPath::Resolved(None, path)) => match path.res {
+            Res::Local(id) => Some(id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// If `cond` has the form `!p.is_null()`, return the `!p.is_null()` expression without any
+/// desugaring wrappers, the `is_null` call, and the binding of `p`.
+fn match_not_null_check<'tcx>(
+    cond: &'tcx hir::Expr<'tcx>,
+) -> Option<(&'tcx hir::Expr<'tcx>, &'tcx hir::Expr<'tcx>, HirId)> {
+    let mut cond = cond;
+    while let hir::ExprKind::DropTemps(e) = cond.kind {
+        cond = e;
+    }
+    let call = match cond.kind {
+        hir::ExprKind::Unary(hir::UnOp::Not, e) => e,
+        _ => return None,
+    };
+    match call.kind {
+        hir::ExprKind::MethodCall(seg, [recv], _) if seg.ident.as_str() == "is_null" => {
+            Some((cond, call, local_var(recv)?))
+        }
+        _ => None,
+    }
+}
+
+/// Collects the `HirId`s of all expressions within an expression, and of the uses of one
+/// local variable among them.
+struct UseCollector<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    var: HirId,
+    exprs: Vec<HirId>,
+    uses: Vec<HirId>,
+}
+
+impl<'tcx> Visitor<'tcx> for UseCollector<'tcx> {
+    type NestedFilter = nested_filter::OnlyBodies;
+
+    fn nested_visit_map(&mut self) -> Self::Map {
+        self.tcx.hir()
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx hir::Expr<'tcx>) {
+        self.exprs.push(ex.hir_id);
+        if local_var(ex) == Some(self.var) {
+            self.uses.push(ex.hir_id);
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+fn collect_uses<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    var: HirId,
+    ex: &'tcx hir::Expr<'tcx>,
+) -> UseCollector<'tcx> {
+    let mut v = UseCollector {
+        tcx,
+        var,
+        exprs: Vec::new(),
+        uses: Vec::new(),
+    };
+    v.visit_expr(ex);
+    v
+}
+
+/// If `rws` starts by unwrapping an `Option`, return the number of rewrites involved and the
+/// `OptionDowngrade` that precedes the unwrap, if any.
+fn leading_unwrap(rws: &[DistRewrite]) -> Option<(usize, Option<(bool, bool)>)> {
+    match rws {
+        [DistRewrite {
+            rw: RewriteKind::OptionUnwrap,
+            ..
+        }, ..] => Some((1, None)),
+        [DistRewrite {
+            rw: RewriteKind::OptionDowngrade { mutbl, deref },
+            ..
+        }, DistRewrite {
+            rw: RewriteKind::OptionUnwrap,
+            ..
+        }, ..] => Some((2, Some((*mutbl, *deref)))),
+        _ => None,
+    }
+}
+
+struct OptionRegionVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    mir_rewrites: &'a mut HashMap<HirId, Vec<DistRewrite>>,
+    rewrites: Vec<(Span, Rewrite)>,
+}
+
+impl<'a, 'tcx> OptionRegionVisitor<'a, 'tcx> {
+    /// Try to rewrite `if cond { then }` as `if let Some(p) = p { then }`.  Returns `true` on
+    /// success.
+    fn try_rewrite_region(
+        &mut self,
+        cond: &'tcx hir::Expr<'tcx>,
+        then: &'tcx hir::Expr<'tcx>,
+    ) -> bool {
+        let (cond, is_null_call, var) = match match_not_null_check(cond) {
+            Some(x) => x,
+            None => return false,
+        };
+        let converts_to_is_none =
+            self.mir_rewrites
+                .get(&is_null_call.hir_id)
+                .map_or(false, |rws| {
+                    matches!(
+                        rws[..],
+                        [DistRewrite {
+                            rw: RewriteKind::IsNullToIsNone,
+                            ..
+                        }]
+                    )
+                });
+        if !converts_to_is_none {
+            return false;
+        }
+
+        // Every use of `p` in the body must unwrap it in the same way.
+        let body_uses = collect_uses(self.tcx, var, then).uses;
+        if body_uses.is_empty() {
+            return false;
+        }
+        let mut downgrades = Vec::with_capacity(body_uses.len());
+        for &use_id in &body_uses {
+            match self
+                .mir_rewrites
+                .get(&use_id)
+                .and_then(|rws| leading_unwrap(rws))
+            {
+                Some((_, downgrade)) => downgrades.push(downgrade),
+                None => return false,
+            }
+        }
+        let deref = downgrades[0].map(|(_, deref)| deref);
+        if downgrades
+            .iter()
+            .any(|d| d.map(|(_, deref)| deref) != deref)
+        {
+            return false;
+        }
+        let mutbl = downgrades.iter().any(|d| matches!(d, Some((true, _))));
+
+        let name = self.tcx.hir().name(var);
+        let unwrapped = match (deref, mutbl) {
+            (None, _) => name.to_string(),
+            (Some(false), false) => format!("{name}.as_ref()"),
+            (Some(false), true) => format!("{name}.as_mut()"),
+            (Some(true), false) => format!("{name}.as_deref()"),
+            (Some(true), true) => format!("{name}.as_deref_mut()"),
+        };
+        debug!(
+            "rewriting null-checked region at {:?} into `if let Some({name}) = {unwrapped}`",
+            then.span
+        );
+
+        for use_id in body_uses {
+            let rws = self.mir_rewrites.get_mut(&use_id).unwrap();
+            let (n, _) = leading_unwrap(rws).unwrap();
+            rws.drain(..n);
+            if rws.is_empty() {
+                self.mir_rewrites.remove(&use_id);
+            }
+        }
+        // The whole condition is replaced, so drop any rewrites within it.
+        for ex_id in collect_uses(self.tcx, var, cond).exprs {
+            self.mir_rewrites.remove(&ex_id);
+        }
+        self.rewrites.push((
+            cond.span,
+            Rewrite::Text(format!("let Some({name}) = {unwrapped}")),
+        ));
+        true
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for OptionRegionVisitor<'a, 'tcx> {
+    type NestedFilter = nested_filter::OnlyBodies;
+
+    fn nested_visit_map(&mut self) -> Self::Map {
+        self.tcx.hir()
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx hir::Expr<'tcx>) {
+        // `while cond { body }` is desugared to `loop { if cond { body } else { break } }`, so
+        // this handles both `if` and `while`.
+        if let hir::ExprKind::If(cond, then, _) = ex.kind {
+            self.try_rewrite_region(cond, then);
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Rewrite the null-checked regions of the body `hir_body_id`, updating `mir_rewrites` to
+/// remove the unwraps that are no longer needed.  Returns the rewrites of the conditions.
+pub fn rewrite_option_regions<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    hir_body_id: hir::BodyId,
+    mir_rewrites: &mut HashMap<HirId, Vec<DistRewrite>>,
+) -> Vec<(Span, Rewrite)> {
+    let hir = tcx.hir().body(hir_body_id);
+    let mut v = OptionRegionVisitor {
+        tcx,
+        mir_rewrites,
+        rewrites: Vec::new(),
+    };
+    v.visit_body(hir);
+    v.rewrites
+}
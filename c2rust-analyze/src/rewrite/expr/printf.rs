@@ -0,0 +1,517 @@
+//! Rewriting of calls to `sprintf`, `snprintf`, `printf`, and `fprintf`.
+//!
+//! When the format string is a constant, `mir_op` parses it with [`parse_format`].  If every
+//! conversion in it is supported, and the destination and each string printed with `%s` are
+//! rewritten to slices, `mir_op` emits [`RewriteKind::SprintfSafe`], and `convert` uses
+//! [`convert_sprintf_call`] to build the replacement: the output is formatted into a `Vec<u8>`
+//! with `format_args!` and then copied into the destination slice, so that overflowing the
+//! destination panics instead of writing out of bounds.  Calls with any other format string are
+//! left unchanged.  Integer arguments are cast to the type given by the conversion and its length
+//! modifier, and `%f` prints NaN and infinity as C does, so the output matches the original.
+//!
+//! With `--rewrite-printf`, calls to `printf`, and to `fprintf` on `stdout` or `stderr`, are
+//! handled the same way, except that `mir_op` emits [`RewriteKind::PrintfSafe`] and the output is
+//! written to [`std::io::stdout`] or [`std::io::stderr`] by [`convert_printf_call`].  The output
+//! isn't printed with `print!`, since the strings printed with `%s` need not be UTF-8.
+//!
+//! [`RewriteKind::SprintfSafe`]: super::mir_op::RewriteKind::SprintfSafe
+//! [`RewriteKind::PrintfSafe`]: super::mir_op::RewriteKind::PrintfSafe
+
+use crate::rewrite::Rewrite;
+use std::fmt::Write as _;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// One piece of a parsed `printf` format string.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum FmtPiece {
+    /// Literal text, in which `%%` has been replaced with `%`.
+    Lit(String),
+    /// An integer or character conversion, which consumes one argument and prints it with the Rust
+    /// format spec `spec`, such as `:5` or `:x`.  If `char` is set, the argument is an `int`
+    /// holding a character, as for `%c`.  If `cast` is set, the argument is first cast to that
+    /// type, as C converts it to the type given by the conversion and its length modifier.
+    Arg {
+        spec: String,
+        char: bool,
+        cast: Option<&'static str>,
+    },
+    /// A `%f` or `%F` conversion, which prints a finite argument with the Rust format spec `spec`.
+    /// Rust and C print NaN and infinity differently, so those are printed as `nan` and `inf`, or
+    /// as `NAN` and `INF` if `upper` is set, padded with spaces to `width` on the right, or on the
+    /// left if `left` is set.
+    Float {
+        spec: String,
+        width: Option<usize>,
+        left: bool,
+        upper: bool,
+    },
+    /// A `%s` conversion, which prints the characters of a string argument up to its NUL, but at
+    /// most `max` of them.
+    Str { max: Option<usize> },
+}
+
+impl FmtPiece {
+    /// Whether this piece consumes an argument.
+    pub fn is_conversion(&self) -> bool {
+        !matches!(self, FmtPiece::Lit(_))
+    }
+}
+
+fn parse_num(chars: &mut Peekable<Chars>) -> Option<usize> {
+    let mut num = None;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        chars.next();
+        num = Some(num.unwrap_or(0) * 10 + digit as usize);
+    }
+    num
+}
+
+/// Parse a `printf` format string.  Returns `None` if it isn't UTF-8 or uses a conversion or flag
+/// that can't be expressed with a Rust format spec, such as `%p`, `%+d`, or `%*d`.
+pub fn parse_format(fmt: &[u8]) -> Option<Vec<FmtPiece>> {
+    let fmt = std::str::from_utf8(fmt).ok()?;
+    let mut pieces = Vec::new();
+    let mut lit = String::new();
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            lit.push(c);
+            continue;
+        }
+
+        let (mut left, mut zero) = (false, false);
+        loop {
+            match chars.peek() {
+                Some('-') => left = true,
+                Some('0') => zero = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        let width = parse_num(&mut chars);
+        let precision = if chars.peek() == Some(&'.') {
+            chars.next();
+            Some(parse_num(&mut chars).unwrap_or(0))
+        } else {
+            None
+        };
+        let mut length = String::new();
+        while let Some(&c @ ('h' | 'l' | 'j' | 'z' | 't')) = chars.peek() {
+            length.push(c);
+            chars.next();
+        }
+
+        let conv = chars.next()?;
+        let piece = match conv {
+            '%' if !left && !zero && width.is_none() && precision.is_none() => {
+                lit.push('%');
+                continue;
+            }
+            // Padding a string would require counting its characters first.
+            's' if !left && !zero && width.is_none() => FmtPiece::Str { max: precision },
+            'd' | 'i' | 'u' | 'x' | 'X' | 'o' | 'c' | 'f' | 'F' => {
+                // The precision of an integer is a minimum number of digits, which Rust format
+                // specs can't express.
+                if precision.is_some() && !matches!(conv, 'f' | 'F') {
+                    return None;
+                }
+                if zero && conv == 'c' {
+                    return None;
+                }
+                let mut spec = String::from(":");
+                // Rust right-aligns numbers by default, and C ignores `0` when `-` is present.
+                if left {
+                    spec.push('<');
+                } else if zero {
+                    spec.push('0');
+                }
+                if let Some(width) = width {
+                    write!(spec, "{width}").unwrap();
+                }
+                if matches!(conv, 'f' | 'F') {
+                    write!(spec, ".{}", precision.unwrap_or(6)).unwrap();
+                }
+                if matches!(conv, 'x' | 'X' | 'o') {
+                    spec.push(conv);
+                }
+                if spec == ":" {
+                    spec.clear();
+                }
+                if matches!(conv, 'f' | 'F') {
+                    FmtPiece::Float {
+                        spec,
+                        width,
+                        left,
+                        upper: conv == 'F',
+                    }
+                } else {
+                    FmtPiece::Arg {
+                        spec,
+                        char: conv == 'c',
+                        cast: int_cast(conv, &length)?,
+                    }
+                }
+            }
+            _ => return None,
+        };
+        if !lit.is_empty() {
+            pieces.push(FmtPiece::Lit(std::mem::take(&mut lit)));
+        }
+        pieces.push(piece);
+    }
+    if !lit.is_empty() {
+        pieces.push(FmtPiece::Lit(lit));
+    }
+    Some(pieces)
+}
+
+/// The type that C converts the argument of the integer conversion `conv` with the length modifier
+/// `length` to before printing it, if the argument's Rust type might differ from it.  An unsigned
+/// conversion of a signed argument prints it as unsigned, and `h` and `hh` truncate the argument.
+/// Returns `None` if the length modifier isn't valid for the conversion.
+fn int_cast(conv: char, length: &str) -> Option<Option<&'static str>> {
+    let signed = matches!(conv, 'd' | 'i');
+    let unsigned = matches!(conv, 'u' | 'x' | 'X' | 'o');
+    let cast = match length {
+        "" if unsigned => Some("std::os::raw::c_uint"),
+        "h" if signed => Some("std::os::raw::c_short"),
+        "h" if unsigned => Some("std::os::raw::c_ushort"),
+        "hh" if signed => Some("std::os::raw::c_schar"),
+        "hh" if unsigned => Some("std::os::raw::c_uchar"),
+        "l" if unsigned => Some("std::os::raw::c_ulong"),
+        "ll" if unsigned => Some("std::os::raw::c_ulonglong"),
+        "j" if unsigned => Some("u64"),
+        "z" | "t" if unsigned => Some("usize"),
+        "" | "l" | "ll" | "j" | "z" | "t" => None,
+        _ => return None,
+    };
+    Some(cast)
+}
+
+/// The index of the format string among the arguments of `sprintf`, or of `snprintf` if `bounded`
+/// is set.
+pub fn format_arg_index(bounded: bool) -> usize {
+    if bounded {
+        2
+    } else {
+        1
+    }
+}
+
+/// Build the replacement for a call to `sprintf`, or to `snprintf` if `bounded` is set, with the
+/// format `pieces` and the arguments `args`, including the format string itself, which is unused.
+/// The destination and the strings printed with `%s` have been rewritten to slices.  `ret_ty` is
+/// the original return type of the call, printed as Rust source.
+pub fn convert_sprintf_call(
+    bounded: bool,
+    pieces: &[FmtPiece],
+    args: Vec<Rewrite>,
+    ret_ty: &str,
+) -> Rewrite {
+    let fmt_idx = format_arg_index(bounded);
+    assert_eq!(
+        args.len(),
+        fmt_idx + 1 + pieces.iter().filter(|p| p.is_conversion()).count()
+    );
+    let mut args = args.into_iter();
+    let mut bindings = vec![("dest".to_owned(), args.next().unwrap())];
+    if bounded {
+        bindings.push(("n".to_owned(), args.next().unwrap()));
+    }
+    bindings.extend(
+        args.skip(1)
+            .enumerate()
+            .map(|(i, rw)| (format!("a{i}"), rw)),
+    );
+    let mut stmts = vec![Rewrite::Let(bindings)];
+    format_into_out(pieces, &mut stmts);
+
+    if bounded {
+        // At most `n - 1` characters are written, followed by a NUL, and the result is the length
+        // of the whole output.
+        stmts.push(Rewrite::Let(vec![(
+            "n".into(),
+            Rewrite::Text("n as usize".into()),
+        )]));
+        stmts.push(Rewrite::Text(
+            "if n > 0 {\n    \
+             let len = out.len().min(n - 1);\n    \
+             for (d, &b) in dest[..len].iter_mut().zip(&out) {\n        *d = b as _;\n    }\n    \
+             dest[len] = 0;\n}"
+                .into(),
+        ));
+    } else {
+        stmts.push(Rewrite::Text(
+            "for (d, &b) in dest[..out.len()].iter_mut().zip(&out) {\n    *d = b as _;\n}".into(),
+        ));
+        stmts.push(Rewrite::Text("dest[out.len()] = 0".into()));
+    }
+    let result = Rewrite::Text(format!("out.len() as {ret_ty}"));
+    Rewrite::Block(stmts, Some(Box::new(result)))
+}
+
+/// Build the replacement for a call to `printf`, or to `fprintf` if `fprintf` is set, with the
+/// format `pieces` and the arguments `args`, including the stream and the format string, which are
+/// unused.  The strings printed with `%s` have been rewritten to slices.  The output is written to
+/// `stderr` if `stderr` is set, and otherwise to `stdout`.  `ret_ty` is the original return type
+/// of the call, printed as Rust source.
+pub fn convert_printf_call(
+    fprintf: bool,
+    stderr: bool,
+    pieces: &[FmtPiece],
+    args: Vec<Rewrite>,
+    ret_ty: &str,
+) -> Rewrite {
+    let fmt_idx = fprintf as usize;
+    assert_eq!(
+        args.len(),
+        fmt_idx + 1 + pieces.iter().filter(|p| p.is_conversion()).count()
+    );
+    let bindings = args
+        .into_iter()
+        .skip(fmt_idx + 1)
+        .enumerate()
+        .map(|(i, rw)| (format!("a{i}"), rw))
+        .collect::<Vec<_>>();
+    let mut stmts = Vec::new();
+    if !bindings.is_empty() {
+        stmts.push(Rewrite::Let(bindings));
+    }
+    format_into_out(pieces, &mut stmts);
+
+    let stream = if stderr { "stderr" } else { "stdout" };
+    stmts.push(Rewrite::Text(format!(
+        "std::io::Write::write_all(&mut std::io::{stream}(), &out).unwrap()"
+    )));
+    let result = Rewrite::Text(format!("out.len() as {ret_ty}"));
+    Rewrite::Block(stmts, Some(Box::new(result)))
+}
+
+/// Append statements to `stmts` that format `pieces` into a new `Vec<u8>` named `out`.  The
+/// arguments consumed by the conversions must be bound to `a0`, `a1`, and so on.
+fn format_into_out(pieces: &[FmtPiece], stmts: &mut Vec<Rewrite>) {
+    stmts.push(Rewrite::Let1(
+        "mut out".into(),
+        Box::new(Rewrite::Text("Vec::<u8>::new()".into())),
+    ));
+
+    // Consecutive literals and numeric conversions are printed by a single `format_args!`.
+    let mut fmt = String::new();
+    let mut fmt_args = String::new();
+    let flush = |stmts: &mut Vec<Rewrite>, fmt: &mut String, fmt_args: &mut String| {
+        if !fmt.is_empty() {
+            stmts.push(Rewrite::Text(format!(
+                "std::io::Write::write_fmt(&mut out, format_args!({:?}{})).unwrap()",
+                fmt, fmt_args
+            )));
+            fmt.clear();
+            fmt_args.clear();
+        }
+    };
+    let mut arg_idx = 0;
+    for piece in pieces {
+        match *piece {
+            FmtPiece::Lit(ref s) => fmt.push_str(&s.replace('{', "{{").replace('}', "}}")),
+            FmtPiece::Arg {
+                ref spec,
+                char,
+                cast,
+            } => {
+                write!(fmt, "{{{spec}}}").unwrap();
+                if char {
+                    write!(fmt_args, ", a{arg_idx} as u8 as char").unwrap();
+                } else if let Some(ty) = cast {
+                    write!(fmt_args, ", a{arg_idx} as {ty}").unwrap();
+                } else {
+                    write!(fmt_args, ", a{arg_idx}").unwrap();
+                }
+                arg_idx += 1;
+            }
+            FmtPiece::Float {
+                ref spec,
+                width,
+                left,
+                upper,
+            } => {
+                flush(stmts, &mut fmt, &mut fmt_args);
+                let align = if left { '<' } else { '>' };
+                let pad = width.map_or(String::new(), |width| format!(":{align}{width}"));
+                let (nan, inf) = if upper {
+                    ("NAN", "INF")
+                } else {
+                    ("nan", "inf")
+                };
+                stmts.push(Rewrite::Text(format!(
+                    "if a{arg_idx}.is_finite() {{\n    \
+                     std::io::Write::write_fmt(&mut out, format_args!(\"{{{spec}}}\", a{arg_idx})).unwrap()\n\
+                     }} else {{\n    \
+                     let s = match (a{arg_idx}.is_nan(), a{arg_idx}.is_sign_negative()) {{\n        \
+                     (true, false) => \"{nan}\",\n        \
+                     (true, true) => \"-{nan}\",\n        \
+                     (false, false) => \"{inf}\",\n        \
+                     (false, true) => \"-{inf}\",\n    \
+                     }};\n    \
+                     std::io::Write::write_fmt(&mut out, format_args!(\"{{{pad}}}\", s)).unwrap()\n\
+                     }}"
+                )));
+                arg_idx += 1;
+            }
+            FmtPiece::Str { max } => {
+                flush(stmts, &mut fmt, &mut fmt_args);
+                let take = max.map_or(String::new(), |max| format!(".take({max})"));
+                stmts.push(Rewrite::Text(format!(
+                    "out.extend(a{arg_idx}.iter(){take}.take_while(|&&c| c != 0).map(|&c| c as u8))"
+                )));
+                arg_idx += 1;
+            }
+        }
+    }
+    flush(stmts, &mut fmt, &mut fmt_args);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn arg(spec: &str) -> FmtPiece {
+        FmtPiece::Arg {
+            spec: spec.to_owned(),
+            char: false,
+            cast: None,
+        }
+    }
+
+    fn cast_arg(spec: &str, ty: &'static str) -> FmtPiece {
+        FmtPiece::Arg {
+            spec: spec.to_owned(),
+            char: false,
+            cast: Some(ty),
+        }
+    }
+
+    #[test]
+    fn parse_conversions() {
+        assert_eq!(
+            parse_format(b"x = %d, %-5ld%%, %08.3f; %x %c %.4s").unwrap(),
+            [
+                FmtPiece::Lit("x = ".into()),
+                arg(""),
+                FmtPiece::Lit(", ".into()),
+                arg(":<5"),
+                FmtPiece::Lit("%, ".into()),
+                FmtPiece::Float {
+                    spec: ":08.3".into(),
+                    width: Some(8),
+                    left: false,
+                    upper: false,
+                },
+                FmtPiece::Lit("; ".into()),
+                cast_arg(":x", "std::os::raw::c_uint"),
+                FmtPiece::Lit(" ".into()),
+                FmtPiece::Arg {
+                    spec: String::new(),
+                    char: true,
+                    cast: None,
+                },
+                FmtPiece::Lit(" ".into()),
+                FmtPiece::Str { max: Some(4) },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_unsupported() {
+        assert_eq!(parse_format(b"%p"), None);
+        assert_eq!(parse_format(b"%+d"), None);
+        assert_eq!(parse_format(b"%*d"), None);
+        assert_eq!(parse_format(b"%.3d"), None);
+        assert_eq!(parse_format(b"%10s"), None);
+        assert_eq!(parse_format(b"100%"), None);
+        assert_eq!(parse_format(b"%hhhd"), None);
+        assert_eq!(parse_format(b"%hc"), None);
+    }
+
+    #[test]
+    fn unsigned_casts() {
+        // A signed argument printed with an unsigned conversion is cast to the unsigned type that
+        // matches the length modifier, so that `-1` prints as `4294967295` or `ffffffff`.
+        assert_eq!(
+            parse_format(b"%u %lx %llX %ho %hhu %zu %d %ld %hd").unwrap(),
+            [
+                cast_arg("", "std::os::raw::c_uint"),
+                FmtPiece::Lit(" ".into()),
+                cast_arg(":x", "std::os::raw::c_ulong"),
+                FmtPiece::Lit(" ".into()),
+                cast_arg(":X", "std::os::raw::c_ulonglong"),
+                FmtPiece::Lit(" ".into()),
+                cast_arg(":o", "std::os::raw::c_ushort"),
+                FmtPiece::Lit(" ".into()),
+                cast_arg("", "std::os::raw::c_uchar"),
+                FmtPiece::Lit(" ".into()),
+                cast_arg("", "usize"),
+                FmtPiece::Lit(" ".into()),
+                arg(""),
+                FmtPiece::Lit(" ".into()),
+                arg(""),
+                FmtPiece::Lit(" ".into()),
+                cast_arg("", "std::os::raw::c_short"),
+            ]
+        );
+
+        let mut stmts = Vec::new();
+        format_into_out(&parse_format(b"%u %x").unwrap(), &mut stmts);
+        assert_eq!(
+            stmts[1],
+            Rewrite::Text(
+                "std::io::Write::write_fmt(&mut out, format_args!(\"{} {:x}\", \
+                 a0 as std::os::raw::c_uint, a1 as std::os::raw::c_uint)).unwrap()"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn non_finite_floats() {
+        // C prints NaN and infinity as `nan` and `inf`, padded with spaces even with `0`, while
+        // Rust would print `NaN` and zero-pad `inf`.
+        let mut stmts = Vec::new();
+        format_into_out(&parse_format(b"%08.2f|%-5F").unwrap(), &mut stmts);
+        assert_eq!(
+            stmts[1..],
+            [
+                Rewrite::Text(
+                    "if a0.is_finite() {\n    \
+                     std::io::Write::write_fmt(&mut out, format_args!(\"{:08.2}\", a0)).unwrap()\n\
+                     } else {\n    \
+                     let s = match (a0.is_nan(), a0.is_sign_negative()) {\n        \
+                     (true, false) => \"nan\",\n        \
+                     (true, true) => \"-nan\",\n        \
+                     (false, false) => \"inf\",\n        \
+                     (false, true) => \"-inf\",\n    \
+                     };\n    \
+                     std::io::Write::write_fmt(&mut out, format_args!(\"{:>8}\", s)).unwrap()\n\
+                     }"
+                    .into()
+                ),
+                Rewrite::Text(
+                    "std::io::Write::write_fmt(&mut out, format_args!(\"|\")).unwrap()".into()
+                ),
+                Rewrite::Text(
+                    "if a1.is_finite() {\n    \
+                     std::io::Write::write_fmt(&mut out, format_args!(\"{:<5.6}\", a1)).unwrap()\n\
+                     } else {\n    \
+                     let s = match (a1.is_nan(), a1.is_sign_negative()) {\n        \
+                     (true, false) => \"NAN\",\n        \
+                     (true, true) => \"-NAN\",\n        \
+                     (false, false) => \"INF\",\n        \
+                     (false, true) => \"-INF\",\n    \
+                     };\n    \
+                     std::io::Write::write_fmt(&mut out, format_args!(\"{:<5}\", s)).unwrap()\n\
+                     }"
+                    .into()
+                ),
+            ]
+        );
+    }
+}
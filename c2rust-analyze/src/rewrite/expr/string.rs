@@ -0,0 +1,202 @@
+//! Rewriting of calls to the `str*` functions of libc.
+//!
+//! A `*mut c_char` that's rewritten to a slice almost always points to a NUL-terminated string.
+//! Calls to `strlen`, `strcpy`, `strncpy`, and `strcmp` on such slices are replaced with safe
+//! operations on the slices that stop at the first NUL, as the C functions do.  `mir_op` decides
+//! whether a call's arguments are suitable and emits [`RewriteKind::StrSafe`], and `convert` uses
+//! [`convert_str_call`] to build the replacement expression.
+//!
+//! With `--c-strings`, owned strings whose uses allow it are rewritten to `CString` and borrowed
+//! ones to `&CStr` instead.  `strlen` and `strcmp` also accept such strings, and `strdup` and
+//! `strndup` can produce them, using [`convert_c_strdup_call`].
+//!
+//! [`RewriteKind::StrSafe`]: super::mir_op::RewriteKind::StrSafe
+
+use crate::rewrite::Rewrite;
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::DefId;
+use rustc_middle::ty::TyCtxt;
+
+/// A libc function operating on NUL-terminated strings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StrFn {
+    /// `strlen(s)`
+    Strlen,
+    /// `strcpy(dest, src)`
+    Strcpy,
+    /// `strncpy(dest, src, n)`
+    Strncpy,
+    /// `strcmp(a, b)`
+    Strcmp,
+}
+
+impl StrFn {
+    /// Recognize a call to one of the `str*` functions declared in an `extern` block.
+    pub fn from_def_id(tcx: TyCtxt, def_id: DefId) -> Option<StrFn> {
+        if !matches!(tcx.def_kind(tcx.parent(def_id)), DefKind::ForeignMod) {
+            return None;
+        }
+        Some(match tcx.item_name(def_id).as_str() {
+            "strlen" => StrFn::Strlen,
+            "strcpy" => StrFn::Strcpy,
+            "strncpy" => StrFn::Strncpy,
+            "strcmp" => StrFn::Strcmp,
+            _ => return None,
+        })
+    }
+
+    /// The number of arguments of the function.
+    pub fn num_args(self) -> usize {
+        match self {
+            StrFn::Strlen => 1,
+            StrFn::Strcpy | StrFn::Strcmp => 2,
+            StrFn::Strncpy => 3,
+        }
+    }
+
+    /// The number of leading arguments that are pointers to strings.
+    pub fn num_str_args(self) -> usize {
+        match self {
+            StrFn::Strlen => 1,
+            StrFn::Strcpy | StrFn::Strncpy | StrFn::Strcmp => 2,
+        }
+    }
+
+    /// Whether the string argument at index `i` is written through.
+    pub fn writes_arg(self, i: usize) -> bool {
+        matches!(self, StrFn::Strcpy | StrFn::Strncpy) && i == 0
+    }
+
+    /// Whether the string arguments may be `&CStr`s instead of slices.
+    pub fn accepts_c_str(self) -> bool {
+        matches!(self, StrFn::Strlen | StrFn::Strcmp)
+    }
+
+    /// Whether the function returns its first argument.
+    pub fn returns_dest(self) -> bool {
+        matches!(self, StrFn::Strcpy | StrFn::Strncpy)
+    }
+}
+
+/// Build the replacement for a call to `str_fn` with the arguments `args`, in which the strings
+/// have been rewritten to slices, or to `&CStr`s where `c_strs` is set.  `ret_ty` is the original
+/// return type of a `strlen` or `strcmp` call, printed as Rust source.
+pub fn convert_str_call(
+    str_fn: StrFn,
+    args: Vec<Rewrite>,
+    c_strs: &[bool],
+    ret_ty: &str,
+) -> Rewrite {
+    assert_eq!(args.len(), str_fn.num_args());
+    assert_eq!(c_strs.len(), str_fn.num_str_args());
+    assert!(str_fn.accepts_c_str() || !c_strs.contains(&true));
+    let names: &[&str] = match str_fn {
+        StrFn::Strlen => &["s"],
+        StrFn::Strcpy => &["dest", "src"],
+        StrFn::Strncpy => &["dest", "src", "n"],
+        StrFn::Strcmp => &["a", "b"],
+    };
+    let mut stmts = vec![Rewrite::Let(
+        names
+            .iter()
+            .map(|&name| name.to_owned())
+            .zip(args)
+            .collect(),
+    )];
+    let result = match str_fn {
+        StrFn::Strlen if c_strs[0] => Rewrite::Text(format!("s.to_bytes().len() as {ret_ty}")),
+        StrFn::Strlen => Rewrite::Text(format!(
+            "s.iter().take_while(|&&c| c != 0).count() as {ret_ty}"
+        )),
+        StrFn::Strcpy => {
+            // The terminating NUL is copied too.
+            stmts.push(Rewrite::Let(vec![(
+                "n".into(),
+                Rewrite::Text("src.iter().position(|&c| c == 0).unwrap() + 1".into()),
+            )]));
+            stmts.push(Rewrite::Text("dest[..n].copy_from_slice(&src[..n])".into()));
+            Rewrite::Text("dest".into())
+        }
+        StrFn::Strncpy => {
+            // At most `n` characters are copied, and the rest of the `n` are filled with NULs.
+            stmts.push(Rewrite::Let(vec![(
+                "n".into(),
+                Rewrite::Text("n as usize".into()),
+            )]));
+            stmts.push(Rewrite::Let(vec![(
+                "len".into(),
+                Rewrite::Text("src.iter().take(n).position(|&c| c == 0).unwrap_or(n)".into()),
+            )]));
+            stmts.push(Rewrite::Text(
+                "dest[..len].copy_from_slice(&src[..len])".into(),
+            ));
+            stmts.push(Rewrite::Text("dest[len..n].fill(0)".into()));
+            Rewrite::Text("dest".into())
+        }
+        StrFn::Strcmp => {
+            // Characters compare as `unsigned char`, and a string that's a prefix of the other
+            // compares less, as its NUL does.
+            let chars = |s: &str, c_str: bool| {
+                if c_str {
+                    format!("{s}.to_bytes().iter().copied()")
+                } else {
+                    format!("{s}.iter().take_while(|&&c| c != 0).map(|&c| c as u8)")
+                }
+            };
+            Rewrite::Text(format!(
+                "{}.cmp({}) as {ret_ty}",
+                chars("a", c_strs[0]),
+                chars("b", c_strs[1])
+            ))
+        }
+    };
+    Rewrite::Block(stmts, Some(Box::new(result)))
+}
+
+/// Build the replacement for a call to `strdup(s)`, or to `strndup(s, n)` if `bounded` is set,
+/// where the string `s` is a `&CStr` if `src_c_str` is set, and the result is a `CString` if
+/// `c_string` is set.  Otherwise, `s` is a slice and the result is a `Box<[T]>` including the
+/// terminating NUL.  At least one of `src_c_str` and `c_string` must be set.
+pub fn convert_c_strdup_call(
+    bounded: bool,
+    src_c_str: bool,
+    c_string: bool,
+    args: Vec<Rewrite>,
+) -> Rewrite {
+    assert!(src_c_str || c_string);
+    let mut args = args.into_iter();
+    let s = args.next().unwrap();
+    if src_c_str && c_string && !bounded {
+        return Rewrite::MethodCall("to_owned".to_string(), Box::new(s), vec![]);
+    }
+
+    let mut bindings = vec![("s".to_owned(), s)];
+    let mut chars = if src_c_str {
+        String::from("s.to_bytes().iter()")
+    } else {
+        String::from("s.iter()")
+    };
+    if bounded {
+        bindings.push(("n".to_owned(), args.next().unwrap()));
+        chars.push_str(".take(n as usize)");
+    }
+    if !src_c_str {
+        chars.push_str(".take_while(|&&c| c != 0)");
+    }
+    let mut stmts = vec![Rewrite::Let(bindings)];
+    let result = if c_string {
+        Rewrite::Text(format!(
+            "std::ffi::CString::new({chars}.map(|&c| c as u8).collect::<Vec<u8>>()).unwrap()"
+        ))
+    } else {
+        stmts.push(Rewrite::Let1(
+            "mut v".into(),
+            Box::new(Rewrite::Text(format!(
+                "{chars}.map(|&c| c as _).collect::<Vec<_>>()"
+            ))),
+        ));
+        stmts.push(Rewrite::Text("v.push(0)".into()));
+        Rewrite::Text("v.into_boxed_slice()".into())
+    };
+    Rewrite::Block(stmts, Some(Box::new(result)))
+}
@@ -0,0 +1,447 @@
+//! Validation of the planned [`MirRewrite`]s before they are distributed to HIR expressions.
+//!
+//! The rewrites at each `(Location, sub_loc)` are applied in order to a single value, so we
+//! evaluate them against an abstract model of that value's pointer type.  The model starts out
+//! fully unknown, and each rewrite both refines it (an `OptionUnwrap` shows that its input was an
+//! `Option`) and checks it against what earlier rewrites established (two `OptionUnwrap`s in a
+//! row can't both apply).  Rewrites that aren't modeled reset the model to unknown, so only
+//! sequences that are inconsistent on their own are rejected.  This catches unbalanced
+//! `OptionMapBegin`/`OptionMapEnd` pairs and impossible cast chains with a precise location,
+//! instead of as a type error in the rewritten code.
+
+use crate::rewrite::expr::mir_op::{MirRewrite, RewriteKind, SubLoc};
+use crate::type_desc::Ownership;
+use rustc_middle::mir::{Body, Location};
+use rustc_middle::ty::TyCtxt;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+/// What is known about the type of the value being rewritten.  `None` means unknown.
+#[derive(Clone, Copy, Debug, Default)]
+struct ValueModel {
+    option: Option<bool>,
+    dyn_owned: Option<bool>,
+    own: Option<Ownership>,
+    /// The value is known to be a `*const Cell<T>`, produced by `CastRawMutToCellPtr`.
+    raw_cell: bool,
+}
+
+struct Evaluator {
+    value: ValueModel,
+    /// The number of `OptionMapBegin`s not yet closed by an `OptionMapEnd`.
+    map_depth: usize,
+}
+
+fn check_flag(name: &str, known: Option<bool>, expected: bool) -> Result<(), String> {
+    match known {
+        Some(x) if x != expected => Err(format!(
+            "expected {}{}, but the value is {}{}",
+            if expected { "" } else { "non-" },
+            name,
+            if x { "" } else { "non-" },
+            name,
+        )),
+        _ => Ok(()),
+    }
+}
+
+impl Evaluator {
+    fn new() -> Evaluator {
+        Evaluator {
+            value: ValueModel::default(),
+            map_depth: 0,
+        }
+    }
+
+    fn check_own(&self, allowed: &[Ownership]) -> Result<(), String> {
+        if self.value.raw_cell {
+            return Err("the value is a `*const Cell<T>`".to_string());
+        }
+        match self.value.own {
+            Some(own) if !allowed.contains(&own) => Err(format!(
+                "expected ownership {allowed:?}, but the value is {own:?}"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that the value is a plain pointer, not wrapped in `Option` or `DynOwned`.
+    fn check_unwrapped(&self) -> Result<(), String> {
+        check_flag("`Option`", self.value.option, false)?;
+        check_flag("`DynOwned`", self.value.dyn_owned, false)
+    }
+
+    /// Apply `rk` to the model, after checking that it's consistent with the model.
+    fn apply(&mut self, rk: &RewriteKind) -> Result<(), String> {
+        use Ownership::*;
+        let v = &mut self.value;
+        match *rk {
+            RewriteKind::OptionSome => {
+                check_flag("`Option`", v.option, false)?;
+                v.option = Some(true);
+            }
+            RewriteKind::OptionUnwrap => {
+                check_flag("`Option`", v.option, true)?;
+                v.option = Some(false);
+            }
+            RewriteKind::OptionUnwrapOrNull { mutbl } => {
+                check_flag("`Option`", v.option, true)?;
+                self.check_own(if mutbl { &[RawMut] } else { &[Raw] })?;
+                self.value.option = Some(false);
+            }
+            RewriteKind::OptionMapBegin => {
+                check_flag("`Option`", v.option, true)?;
+                v.option = Some(false);
+                self.map_depth += 1;
+            }
+            RewriteKind::OptionMapEnd => {
+                if self.map_depth == 0 {
+                    return Err("`OptionMapEnd` without a matching `OptionMapBegin`".to_string());
+                }
+                check_flag("`Option`", v.option, false)?;
+                v.option = Some(true);
+                self.map_depth -= 1;
+            }
+            RewriteKind::OptionDowngrade { mutbl, deref: _ } => {
+                check_flag("`Option`", v.option, true)?;
+                // `Option<DynOwned<T>>` has no `as_deref`.
+                check_flag("`DynOwned`", v.dyn_owned, false)?;
+                let allowed: &[_] = if mutbl {
+                    &[Box, Vec, Mut]
+                } else {
+                    &[Box, Vec, Rc, RcRefCell, ArcMutex, Mut, Imm]
+                };
+                self.check_own(allowed)?;
+                // `Option<Rc<RefCell<T>>>::as_ref` produces an `Option<&Rc<RefCell<T>>>`, whose
+                // `RefCell` can still be borrowed, and likewise for `Arc<Mutex<T>>`.
+                if !matches!(self.value.own, Some(RcRefCell | ArcMutex)) {
+                    self.value.own = Some(if mutbl { Mut } else { Imm });
+                }
+            }
+            // A clone has the same type as the original.
+            RewriteKind::Clone => {}
+            RewriteKind::RcRefCellFromBox => {
+                self.check_unwrapped()?;
+                self.check_own(&[Box])?;
+                self.value.own = Some(RcRefCell);
+            }
+            RewriteKind::RefCellBorrow { mutbl } => {
+                self.check_unwrapped()?;
+                self.check_own(&[RcRefCell])?;
+                self.value.own = Some(if mutbl { Mut } else { Imm });
+            }
+            RewriteKind::ArcMutexFromBox => {
+                self.check_unwrapped()?;
+                self.check_own(&[Box])?;
+                self.value.own = Some(ArcMutex);
+            }
+            RewriteKind::MutexLock { mutbl } => {
+                self.check_unwrapped()?;
+                self.check_own(&[ArcMutex])?;
+                self.value.own = Some(if mutbl { Mut } else { Imm });
+            }
+            RewriteKind::ArcIntoRaw => {
+                self.check_unwrapped()?;
+                self.check_own(&[ArcMutex])?;
+                self.value.own = Some(RawMut);
+            }
+            RewriteKind::DynOwnedWrap => {
+                check_flag("`Option`", v.option, false)?;
+                check_flag("`DynOwned`", v.dyn_owned, false)?;
+                v.dyn_owned = Some(true);
+            }
+            RewriteKind::DynOwnedUnwrap | RewriteKind::DynOwnedTake => {
+                check_flag("`Option`", v.option, false)?;
+                check_flag("`DynOwned`", v.dyn_owned, true)?;
+                if let RewriteKind::DynOwnedUnwrap = *rk {
+                    v.dyn_owned = Some(false);
+                }
+            }
+            RewriteKind::DynOwnedDowngrade { mutbl } => {
+                check_flag("`Option`", v.option, false)?;
+                check_flag("`DynOwned`", v.dyn_owned, true)?;
+                v.dyn_owned = Some(false);
+                v.own = Some(if mutbl { Mut } else { Imm });
+            }
+            RewriteKind::Reborrow { mutbl } => {
+                self.check_unwrapped()?;
+                let allowed: &[_] = if mutbl {
+                    &[Box, Vec, Mut]
+                } else {
+                    &[Box, Vec, Rc, Mut, Imm]
+                };
+                self.check_own(allowed)?;
+                self.value = ValueModel {
+                    option: Some(false),
+                    dyn_owned: Some(false),
+                    own: Some(if mutbl { Mut } else { Imm }),
+                    raw_cell: false,
+                };
+            }
+            RewriteKind::VecFromBox => {
+                self.check_unwrapped()?;
+                self.check_own(&[Box])?;
+                self.value.own = Some(Vec);
+            }
+            RewriteKind::VecIntoBoxedSlice => {
+                self.check_unwrapped()?;
+                self.check_own(&[Vec])?;
+                self.value.own = Some(Box);
+            }
+            RewriteKind::CellFromMut => {
+                self.check_unwrapped()?;
+                self.check_own(&[Mut])?;
+                self.value.own = Some(Cell);
+            }
+            RewriteKind::AsSliceOfCells => {
+                self.check_unwrapped()?;
+                self.check_own(&[Cell])?;
+            }
+            RewriteKind::AsPtr => {
+                self.check_unwrapped()?;
+                self.check_own(&[Cell])?;
+                self.value.own = Some(RawMut);
+            }
+            RewriteKind::CStrAsPtr { .. } => {
+                self.check_unwrapped()?;
+                self.check_own(&[Imm])?;
+                self.value.own = Some(Raw);
+            }
+            RewriteKind::UnsafeCStrFromPtr { .. } => {
+                self.check_unwrapped()?;
+                self.check_own(&[Raw, RawMut])?;
+                self.value.own = Some(Imm);
+            }
+            RewriteKind::CastRefToRaw { mutbl } => {
+                self.check_unwrapped()?;
+                self.check_own(if mutbl { &[Mut] } else { &[Imm, Mut] })?;
+                self.value.own = Some(if mutbl { RawMut } else { Raw });
+            }
+            RewriteKind::CastRawToRaw { to_mutbl } => {
+                self.check_unwrapped()?;
+                self.check_own(&[Raw, RawMut])?;
+                self.value.own = Some(if to_mutbl { RawMut } else { Raw });
+            }
+            RewriteKind::CastRawToVoid => {
+                self.check_unwrapped()?;
+                self.check_own(&[Raw, RawMut])?;
+            }
+            RewriteKind::CastRawMutToCellPtr { .. } => {
+                self.check_unwrapped()?;
+                self.check_own(&[RawMut])?;
+                self.value.own = Some(Raw);
+                self.value.raw_cell = true;
+            }
+            RewriteKind::UnsafeCastRawToRef { mutbl } => {
+                self.check_unwrapped()?;
+                if self.value.raw_cell {
+                    if mutbl {
+                        return Err("the value is a `*const Cell<T>`".to_string());
+                    }
+                    self.value.raw_cell = false;
+                    self.value.own = Some(Cell);
+                } else {
+                    self.check_own(if mutbl { &[RawMut] } else { &[Raw, RawMut] })?;
+                    self.value.own = Some(if mutbl { Mut } else { Imm });
+                }
+            }
+            RewriteKind::SliceFirst { mutbl } => {
+                self.check_unwrapped()?;
+                self.check_own(if mutbl { &[Mut] } else { &[Imm, Cell, Mut] })?;
+            }
+            RewriteKind::PtrNullToNone | RewriteKind::ZeroAsPtrToNone => {
+                self.value = ValueModel {
+                    option: Some(true),
+                    ..ValueModel::default()
+                };
+            }
+            _ => {
+                // Rewrites that aren't modeled replace the value with something we know nothing
+                // about.
+                self.value = ValueModel::default();
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<(), String> {
+        if self.map_depth != 0 {
+            return Err("`OptionMapBegin` without a matching `OptionMapEnd`".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Check the rewrites at a single `(Location, sub_loc)`.  On failure, returns the index of the
+/// offending rewrite (or `rws.len()` for an error at the end) and a description of the problem.
+fn validate_rewrites(rws: &[&RewriteKind]) -> Result<(), (usize, String)> {
+    let mut eval = Evaluator::new();
+    for (i, rk) in rws.iter().enumerate() {
+        eval.apply(rk).map_err(|msg| (i, msg))?;
+    }
+    eval.finish().map_err(|msg| (rws.len(), msg))
+}
+
+/// Check the planned rewrites of `mir` for sequences that can't apply to any value.  Returns a
+/// description of every problem found, with the span of the MIR statement and the full sequence
+/// of rewrites involved.
+pub fn validate_mir_rewrites<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &Body<'tcx>,
+    mir_rewrites: &HashMap<Location, Vec<MirRewrite>>,
+) -> Result<(), String> {
+    let mut locs = mir_rewrites.keys().copied().collect::<Vec<_>>();
+    locs.sort();
+
+    let mut errors = String::new();
+    for loc in locs {
+        // Group the rewrites by `sub_loc`, preserving their order.
+        let mut groups: Vec<(&[SubLoc], Vec<&RewriteKind>)> = Vec::new();
+        for rw in &mir_rewrites[&loc] {
+            match groups
+                .iter_mut()
+                .find(|(sub_loc, _)| *sub_loc == &rw.sub_loc[..])
+            {
+                Some((_, rws)) => rws.push(&rw.kind),
+                None => groups.push((&rw.sub_loc, vec![&rw.kind])),
+            }
+        }
+
+        for (sub_loc, rws) in groups {
+            if let Err((i, msg)) = validate_rewrites(&rws) {
+                let span = mir.source_info(loc).span;
+                writeln!(
+                    errors,
+                    "invalid rewrites at {:?} {:?} ({}): rewrite #{} of {:?}: {}",
+                    loc,
+                    sub_loc,
+                    tcx.sess.source_map().span_to_diagnostic_string(span),
+                    i,
+                    rws,
+                    msg,
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RewriteKind::*;
+
+    fn validate(rws: &[RewriteKind]) -> Result<(), (usize, String)> {
+        validate_rewrites(&rws.iter().collect::<Vec<_>>())
+    }
+
+    fn err(i: usize, msg: &str) -> Result<(), (usize, String)> {
+        Err((i, msg.to_owned()))
+    }
+
+    #[test]
+    fn valid_sequences() {
+        assert_eq!(validate(&[]), Ok(()));
+        // `p.as_ref().map(|p| &*p)`
+        assert_eq!(
+            validate(&[OptionMapBegin, Reborrow { mutbl: false }, OptionMapEnd]),
+            Ok(())
+        );
+        assert_eq!(
+            validate(&[RcRefCellFromBox, RefCellBorrow { mutbl: true }]),
+            Ok(())
+        );
+        assert_eq!(
+            validate(&[
+                CastRefToRaw { mutbl: true },
+                CastRawToRaw { to_mutbl: false },
+                UnsafeCastRawToRef { mutbl: false },
+            ]),
+            Ok(())
+        );
+        // A `*const Cell<T>` becomes a `&Cell<T>`, which has `as_ptr`.
+        assert_eq!(
+            validate(&[
+                CastRawMutToCellPtr { ty: "u8".into() },
+                UnsafeCastRawToRef { mutbl: false },
+                AsPtr,
+            ]),
+            Ok(())
+        );
+        assert_eq!(
+            validate(&[DynOwnedWrap, DynOwnedTake, DynOwnedUnwrap]),
+            Ok(())
+        );
+        assert_eq!(validate(&[PtrNullToNone, OptionUnwrap]), Ok(()));
+        // `OffsetSlice` isn't modeled, so nothing is known about the value after it.
+        assert_eq!(
+            validate(&[OptionUnwrap, OffsetSlice { mutbl: false }, OptionUnwrap]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn invalid_options() {
+        assert_eq!(
+            validate(&[OptionUnwrap, OptionUnwrap]),
+            err(1, "expected `Option`, but the value is non-`Option`")
+        );
+        assert_eq!(
+            validate(&[OptionSome, Reborrow { mutbl: false }]),
+            err(1, "expected non-`Option`, but the value is `Option`")
+        );
+        assert_eq!(
+            validate(&[OptionMapEnd]),
+            err(0, "`OptionMapEnd` without a matching `OptionMapBegin`")
+        );
+        assert_eq!(
+            validate(&[OptionMapBegin, Reborrow { mutbl: false }]),
+            err(2, "`OptionMapBegin` without a matching `OptionMapEnd`")
+        );
+        assert_eq!(
+            validate(&[
+                DynOwnedWrap,
+                OptionDowngrade {
+                    mutbl: false,
+                    deref: false
+                }
+            ]),
+            err(1, "expected non-`DynOwned`, but the value is `DynOwned`")
+        );
+    }
+
+    #[test]
+    fn invalid_ownership() {
+        assert_eq!(
+            validate(&[
+                CastRefToRaw { mutbl: false },
+                UnsafeCastRawToRef { mutbl: true }
+            ]),
+            err(1, "expected ownership [RawMut], but the value is Raw")
+        );
+        assert_eq!(
+            validate(&[VecFromBox, RcRefCellFromBox]),
+            err(1, "expected ownership [Box], but the value is Vec")
+        );
+        assert_eq!(
+            validate(&[
+                CastRawMutToCellPtr { ty: "u8".into() },
+                UnsafeCastRawToRef { mutbl: true }
+            ]),
+            err(1, "the value is a `*const Cell<T>`")
+        );
+        assert_eq!(
+            validate(&[
+                CastRawMutToCellPtr { ty: "u8".into() },
+                CastRawToRaw { to_mutbl: true }
+            ]),
+            err(1, "the value is a `*const Cell<T>`")
+        );
+    }
+}
@@ -0,0 +1,70 @@
+//! Generation of the `c2rust_helpers` module.
+//!
+//! The safe replacements for `memcpy`, `memset`, and `realloc` on slices expand to several
+//! statements at each call site.  When `C2RUST_ANALYZE_HELPER_MODULE=1` is set, those rewrites
+//! instead call generic functions in a `c2rust_helpers` module, which is added once to the end of
+//! the crate root.  This keeps the rewritten code small, and the helpers can be reviewed in one
+//! place.
+
+use crate::rewrite::Rewrite;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use std::env;
+
+/// The path of the helper module, as used in calls emitted by the rewrites.
+pub const HELPER_MODULE_PATH: &str = "crate::c2rust_helpers";
+
+/// The source of the helper module.  Each byte length is converted to an element count of the
+/// slice's element type, as in the inline rewrites.
+const HELPER_MODULE_SRC: &str = r#"
+
+#[allow(dead_code)]
+pub mod c2rust_helpers {
+    /// Safe replacement for `memcpy(dest, src, byte_len)` on slices.
+    pub fn copy<'a, T: Copy>(dest: &'a mut [T], src: &[T], byte_len: usize) -> &'a mut [T] {
+        debug_assert_eq!(byte_len % std::mem::size_of::<T>(), 0);
+        let n = byte_len / std::mem::size_of::<T>();
+        dest[..n].copy_from_slice(&src[..n]);
+        dest
+    }
+
+    /// Safe replacement for `memset(dest, val, byte_len)` on slices, for `val == 0` only.  `zero`
+    /// produces the zero value of `T`.
+    pub fn zeroize<T>(
+        dest: &mut [T],
+        val: i32,
+        byte_len: usize,
+        mut zero: impl FnMut() -> T,
+    ) -> &mut [T] {
+        assert_eq!(val, 0, "non-zero memset NYI");
+        let n = byte_len / std::mem::size_of::<T>();
+        for x in &mut dest[..n] {
+            *x = zero();
+        }
+        dest
+    }
+
+    /// Safe replacement for `realloc(src, byte_len)` on boxed slices.  New elements are
+    /// initialized with `zero`.
+    pub fn realloc<T>(src: Box<[T]>, byte_len: usize, zero: impl FnMut() -> T) -> Box<[T]> {
+        let n = byte_len / std::mem::size_of::<T>();
+        let mut v = Vec::from(src);
+        v.resize_with(n, zero);
+        v.into_boxed_slice()
+    }
+}
+"#;
+
+/// Returns `true` if rewrites should call the helper module instead of expanding inline.
+pub fn use_helper_module() -> bool {
+    env::var("C2RUST_ANALYZE_HELPER_MODULE").as_deref() == Ok("1")
+}
+
+/// Generate a rewrite that adds the helper module to the end of the crate root.
+pub fn gen_helper_module_rewrite(tcx: TyCtxt) -> (Span, Rewrite) {
+    let root_span = tcx.hir().root_module().spans.inner_span;
+    (
+        root_span.shrink_to_hi(),
+        Rewrite::Text(HELPER_MODULE_SRC.to_owned()),
+    )
+}
@@ -0,0 +1,160 @@
+//! Cleanup of the warnings that rewriting tends to trigger.
+//!
+//! Rewritten functions often end up with `mut` bindings and `unsafe` blocks that are no longer
+//! needed, and with borrows that `clippy` considers needless, such as the `&mut *p` produced by a
+//! reborrow of an argument that is already `&mut`.  For each rewritten function, this pass
+//! removes `mut` from bindings that are provably never mutated, and inserts an `#[allow]` for
+//! the remaining lints on the function itself.  `unsafe` is left in place: deciding whether a
+//! block is still needed requires type checking the rewritten code, which we can't do here.
+
+use crate::rewrite::Rewrite;
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::LocalDefId;
+use rustc_middle::mir::visit::{MutatingUseContext, PlaceContext, Visitor};
+use rustc_middle::mir::{
+    BindingForm, Body, ClearCrossCrate, Local, LocalInfo, Location, Mutability, Place,
+    ProjectionElem,
+};
+use rustc_middle::ty::{GenericArgKind, TyCtxt};
+use rustc_span::Span;
+
+/// The lints allowed on each rewritten function.
+const ALLOWED_LINTS: &str = "unused_mut, unused_unsafe, clippy::needless_borrow";
+
+/// Counts the ways each local is mutated, ignoring writes through pointers stored in the local,
+/// which don't require a `mut` binding.
+struct MutationCounter<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    mir: &'a Body<'tcx>,
+    /// The number of assignments to the whole local.
+    assignments: Vec<usize>,
+    /// Whether the local is mutably borrowed, or assigned in part.
+    other_mutation: Vec<bool>,
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for MutationCounter<'a, 'tcx> {
+    fn visit_place(&mut self, place: &Place<'tcx>, context: PlaceContext, _location: Location) {
+        let ctx = match context {
+            PlaceContext::MutatingUse(ctx) => ctx,
+            _ => return,
+        };
+        if let MutatingUseContext::Drop = ctx {
+            return;
+        }
+        let local = place.local.as_usize();
+
+        // Writing through a raw pointer or reference doesn't mutate the local itself.  Writing
+        // through a `Box` does.
+        if let Some(i) = place
+            .projection
+            .iter()
+            .position(|elem| matches!(elem, ProjectionElem::Deref))
+        {
+            let base_ty =
+                Place::ty_from(place.local, &place.projection[..i], self.mir, self.tcx).ty;
+            if !base_ty.is_box() {
+                return;
+            }
+        }
+
+        match ctx {
+            MutatingUseContext::Store
+            | MutatingUseContext::Call
+            | MutatingUseContext::AsmOutput
+            | MutatingUseContext::Yield
+                if place.projection.is_empty() =>
+            {
+                self.assignments[local] += 1;
+            }
+            _ => {
+                self.other_mutation[local] = true;
+            }
+        }
+    }
+}
+
+/// Return the span of the pattern that binds `local`, if it's a `mut` user variable binding such
+/// as `let mut x` or a `mut x` parameter.
+fn mut_binding_span(mir: &Body, local: Local) -> Option<Span> {
+    let decl = &mir.local_decls[local];
+    if decl.mutability != Mutability::Mut {
+        return None;
+    }
+    match **decl.local_info.as_ref()? {
+        LocalInfo::User(ClearCrossCrate::Set(BindingForm::Var(ref v))) => Some(v.pat_span),
+        _ => None,
+    }
+}
+
+/// Generate the warning cleanup rewrites for `ldid`, whose other rewrites are at
+/// `fn_rewrite_spans`.  Bindings whose type contains a raw pointer or an ADT (which may have pointer
+/// fields) are never changed, since the other rewrites of their uses (such as `Option::as_mut`)
+/// may rely on the `mut`.
+pub fn gen_lint_rewrites<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ldid: LocalDefId,
+    mir: &Body<'tcx>,
+    fn_rewrite_spans: &[Span],
+) -> Vec<(Span, Rewrite)> {
+    let mut rewrites = Vec::new();
+    if !matches!(tcx.def_kind(ldid), DefKind::Fn | DefKind::AssocFn) {
+        return rewrites;
+    }
+
+    let mut counter = MutationCounter {
+        tcx,
+        mir,
+        assignments: vec![0; mir.local_decls.len()],
+        other_mutation: vec![false; mir.local_decls.len()],
+    };
+    counter.visit_body(mir);
+
+    let sm = tcx.sess.source_map();
+    for local in mir.local_decls.indices() {
+        let span = match mut_binding_span(mir, local) {
+            Some(x) => x,
+            None => continue,
+        };
+        let decl = &mir.local_decls[local];
+        if span.from_expansion()
+            || decl.ty.walk().any(|arg| match arg.unpack() {
+                GenericArgKind::Type(ty) => ty.is_unsafe_ptr() || ty.is_adt(),
+                _ => false,
+            })
+        {
+            continue;
+        }
+        // Arguments are initialized by the caller, and other locals by their first assignment.
+        let is_arg = local.as_usize() <= mir.arg_count;
+        let max_assignments = if is_arg { 0 } else { 1 };
+        let i = local.as_usize();
+        if counter.other_mutation[i] || counter.assignments[i] > max_assignments {
+            continue;
+        }
+        if fn_rewrite_spans.iter().any(|sp| sp.overlaps(span)) {
+            continue;
+        }
+        // Only handle the simple `mut x` form, not `ref mut x` or patterns with subpatterns.
+        let snippet = match sm.span_to_snippet(span) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        let name = match snippet.strip_prefix("mut ") {
+            Some(name) if name.chars().all(|c| c.is_alphanumeric() || c == '_') => name,
+            _ => continue,
+        };
+        rewrites.push((span, Rewrite::Text(name.to_owned())));
+    }
+
+    let item_span = tcx.hir().expect_owner(ldid).span();
+    let indent = sm.lookup_char_pos(item_span.lo()).col_display;
+    rewrites.push((
+        item_span.shrink_to_lo(),
+        Rewrite::Text(format!(
+            "#[allow({ALLOWED_LINTS})]\n{:indent$}",
+            "",
+            indent = indent
+        )),
+    ));
+    rewrites
+}
@@ -31,17 +31,33 @@ use std::fmt;
 use std::fs;
 
 mod apply;
+mod drop_impls;
+mod expansion;
 mod expr;
+mod helpers;
+mod lint;
 mod shim;
 mod span_index;
 mod statics;
 mod ty;
+mod vec_structs;
 
+pub use self::drop_impls::{
+    find_drop_impls, gen_drop_impl_rewrites, is_drop_fn, set_drop_fns, DropImpl,
+};
+pub use self::expansion::{collect_macro_expr_sites, split_macro_rewrites, MacroDefRewrites};
 pub use self::expr::gen_expr_rewrites;
-pub use self::shim::{gen_shim_call_rewrites, gen_shim_definition_rewrite, ManualShimCasts};
-pub use self::statics::gen_static_rewrites;
+pub use self::expr::CastBuilder;
+pub use self::expr::{MirRewrite, RewriteKind, SubLoc, ZeroizeType};
+pub use self::helpers::{gen_helper_module_rewrite, use_helper_module};
+pub use self::lint::gen_lint_rewrites;
+pub use self::shim::{
+    gen_shim_call_rewrites, gen_shim_definition_rewrite, ManualShimCasts, ShimNullChecks,
+};
+pub use self::statics::{collect_static_uses, gen_local_static_rewrites, gen_static_rewrites};
 pub use self::ty::dump_rewritten_local_tys;
-pub use self::ty::{gen_adt_ty_rewrites, gen_ty_rewrites};
+pub use self::ty::{gen_adt_ty_rewrites, gen_static_fn_ptr_rewrites, gen_ty_rewrites};
+pub use self::vec_structs::{find_vec_structs, gen_vec_struct_rewrites, VecStruct};
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum LifetimeName {
@@ -120,11 +136,15 @@ pub enum Rewrite<S = Span> {
     // `static` builders
     /// `static` mutability (`static` <-> `static mut`)
     StaticMut(Mutability, S),
+    /// Turn a `static` into a `const`: `static mut X: T = e;` -> `const X: T = e;`
+    Const(S),
 
     // `fn` builders
-    /// Define a function.
+    /// Define a function.  If `self_param` is set, it's emitted as the first parameter, and the
+    /// remaining parameters are numbered starting from 1 instead of 0.
     DefineFn {
         name: String,
+        self_param: Option<Box<Rewrite>>,
         arg_tys: Vec<Rewrite>,
         return_ty: Option<Box<Rewrite>>,
         body: Box<Rewrite>,
@@ -208,14 +228,17 @@ impl Rewrite {
             TyCtor(ref name, ref tys) => TyCtor(String::clone(name), try_subst_vec(tys)?),
             _TyGenericParams(ref tys) => _TyGenericParams(try_subst_vec(tys)?),
             StaticMut(mutbl, span) => StaticMut(mutbl, span),
+            Const(span) => Const(span),
 
             DefineFn {
                 ref name,
+                ref self_param,
                 ref arg_tys,
                 ref return_ty,
                 ref body,
             } => DefineFn {
                 name: String::clone(name),
+                self_param: try_subst_option(self_param)?,
                 arg_tys: try_subst_vec(arg_tys)?,
                 return_ty: try_subst_option(return_ty)?,
                 body: try_subst(body)?,
@@ -225,6 +248,92 @@ impl Rewrite {
     }
 }
 
+impl Rewrite {
+    /// Apply `f` to every `Span` mentioned in `self`, producing a new rewrite with the updated
+    /// spans.  Fails if `f` fails on any span.
+    fn try_map_spans<E>(&self, f: &mut dyn FnMut(Span) -> Result<Span, E>) -> Result<Rewrite, E> {
+        use self::Rewrite::*;
+
+        fn map_box<E>(
+            rw: &Rewrite,
+            f: &mut dyn FnMut(Span) -> Result<Span, E>,
+        ) -> Result<Box<Rewrite>, E> {
+            Ok(Box::new(rw.try_map_spans(f)?))
+        }
+        fn map_option<E>(
+            rw: &Option<Box<Rewrite>>,
+            f: &mut dyn FnMut(Span) -> Result<Span, E>,
+        ) -> Result<Option<Box<Rewrite>>, E> {
+            rw.as_ref().map(|rw| map_box(rw, f)).transpose()
+        }
+        fn map_vec<E>(
+            rws: &[Rewrite],
+            f: &mut dyn FnMut(Span) -> Result<Span, E>,
+        ) -> Result<Vec<Rewrite>, E> {
+            rws.iter().map(|rw| rw.try_map_spans(f)).collect()
+        }
+
+        Ok(match *self {
+            Identity => Identity,
+            Sub(idx, span) => Sub(idx, f(span)?),
+
+            Text(ref s) => Text(String::clone(s)),
+            Extract(span) => Extract(f(span)?),
+
+            Ref(ref rw, mutbl) => Ref(map_box(rw, f)?, mutbl),
+            AddrOf(ref rw, mutbl) => AddrOf(map_box(rw, f)?, mutbl),
+            Deref(ref rw) => Deref(map_box(rw, f)?),
+            Index(ref arr, ref idx) => Index(map_box(arr, f)?, map_box(idx, f)?),
+            SliceRange(ref arr, ref lo, ref hi) => {
+                SliceRange(map_box(arr, f)?, map_option(lo, f)?, map_option(hi, f)?)
+            }
+            Cast(ref expr, ref ty) => Cast(map_box(expr, f)?, map_box(ty, f)?),
+            RemovedCast(ref rw) => RemovedCast(map_box(rw, f)?),
+            LitZero => LitZero,
+            Call(ref func, ref args) => Call(String::clone(func), map_vec(args, f)?),
+            MethodCall(ref func, ref receiver, ref args) => MethodCall(
+                String::clone(func),
+                map_box(receiver, f)?,
+                map_vec(args, f)?,
+            ),
+            Block(ref stmts, ref expr) => Block(map_vec(stmts, f)?, map_option(expr, f)?),
+            Let(ref vars) => {
+                let mut new_vars = Vec::with_capacity(vars.len());
+                for (ref name, ref rw) in vars {
+                    new_vars.push((String::clone(name), rw.try_map_spans(f)?));
+                }
+                Let(new_vars)
+            }
+            Let1(ref name, ref rw) => Let1(String::clone(name), map_box(rw, f)?),
+            Closure1(ref name, ref rw) => Closure1(String::clone(name), map_box(rw, f)?),
+
+            Print(ref s) => Print(String::clone(s)),
+            TyPtr(ref rw, mutbl) => TyPtr(map_box(rw, f)?, mutbl),
+            TyRef(ref lt, ref rw, mutbl) => TyRef(LifetimeName::clone(lt), map_box(rw, f)?, mutbl),
+            TySlice(ref rw) => TySlice(map_box(rw, f)?),
+            TyCtor(ref name, ref tys) => TyCtor(String::clone(name), map_vec(tys, f)?),
+            _TyGenericParams(ref tys) => _TyGenericParams(map_vec(tys, f)?),
+            StaticMut(mutbl, span) => StaticMut(mutbl, f(span)?),
+            Const(span) => Const(f(span)?),
+
+            DefineFn {
+                ref name,
+                ref self_param,
+                ref arg_tys,
+                ref return_ty,
+                ref body,
+            } => DefineFn {
+                name: String::clone(name),
+                self_param: map_option(self_param, f)?,
+                arg_tys: map_vec(arg_tys, f)?,
+                return_ty: map_option(return_ty, f)?,
+                body: map_box(body, f)?,
+            },
+            FnArg(idx) => FnArg(idx),
+        })
+    }
+}
+
 struct FormatterSink<'a, 'b>(&'a mut fmt::Formatter<'b>);
 
 impl apply::Sink for FormatterSink<'_, '_> {
@@ -3,7 +3,7 @@ use crate::context::{FlagSet, GlobalAnalysisCtxt, GlobalAssignment};
 use crate::rewrite::expr::{self, CastBuilder};
 use crate::rewrite::ty;
 use crate::rewrite::Rewrite;
-use crate::type_desc::{self, TypeDesc};
+use crate::type_desc::{self, Ownership, TypeDesc};
 use rustc_hir::def::{DefKind, Res};
 use rustc_hir::def_id::DefId;
 use rustc_hir::intravisit::{self, Visitor};
@@ -182,11 +182,20 @@ pub enum ManualShimCasts {
     Yes,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum ShimNullChecks {
+    No,
+    /// Check each raw pointer argument for null before converting it to a reference.  A null
+    /// pointer becomes `None` if the safe argument type is an `Option`, and panics otherwise.
+    Yes,
+}
+
 pub fn gen_shim_definition_rewrite<'tcx>(
     gacx: &GlobalAnalysisCtxt<'tcx>,
     gasn: &GlobalAssignment,
     def_id: DefId,
     manual_casts: ManualShimCasts,
+    null_checks: ShimNullChecks,
 ) -> (Span, Rewrite) {
     let tcx = gacx.tcx;
 
@@ -194,9 +203,23 @@ pub fn gen_shim_definition_rewrite<'tcx>(
     let insert_span = owner_node.span().shrink_to_hi();
 
     let fn_decl = owner_node.fn_decl().unwrap();
+
+    // For methods with a `self` parameter, the shim takes `self` in exactly the same form as the
+    // original method, so that method-call syntax in the callers continues to work.
+    let has_self_param = tcx.def_kind(def_id) == DefKind::AssocFn
+        && tcx.associated_item(def_id).fn_has_self_parameter;
+    let self_param = if has_self_param {
+        let hir_body_id = tcx.hir().body_owned_by(def_id.as_local().unwrap());
+        let self_span = tcx.hir().body(hir_body_id).params[0].span;
+        Some(Box::new(Rewrite::Extract(self_span)))
+    } else {
+        None
+    };
+
     let arg_tys = fn_decl
         .inputs
         .iter()
+        .skip(if has_self_param { 1 } else { 0 })
         .map(|ty| Rewrite::Extract(ty.span))
         .collect::<Vec<_>>();
     let return_ty = match fn_decl.output {
@@ -207,21 +230,53 @@ pub fn gen_shim_definition_rewrite<'tcx>(
     // `def_id` should always refer to a rewritten function, and all rewritten functions have
     // valid `fn_sigs` entries.
     let lsig = gacx.fn_sigs[&def_id];
+    // The rewritten function may no longer take the out-parameter for the length of its returned
+    // slice, in which case the shim stores the length of the slice through it instead.
+    let returned_slice_len = gacx.returned_slice_lens.get(&def_id);
 
     // 1 cast per arg, 1 call, 1 cast for the result.  The final result is returned using the
     // trailing expression of the block.
-    let mut stmts = Vec::with_capacity(arg_tys.len() + 2);
+    let mut stmts = Vec::with_capacity(lsig.inputs.len() + 2);
 
     // Generate `let safe_arg0 = arg0 as ...;` for each argument.
-    let mut arg_exprs = Vec::with_capacity(arg_tys.len());
+    let mut arg_exprs = Vec::with_capacity(lsig.inputs.len());
     for (i, arg_lty) in lsig.inputs.iter().enumerate() {
-        let mut hir_rw = Rewrite::FnArg(i);
+        let mut hir_rw = if i == 0 && has_self_param {
+            Rewrite::Text("self".to_owned())
+        } else {
+            Rewrite::FnArg(i)
+        };
 
         if let Some((arg_desc, fixed_desc)) = lty_to_desc_pair(tcx, gasn, arg_lty) {
+            let arg_name = hir_rw.to_string();
+            let null_check = null_checks == ShimNullChecks::Yes
+                && matches!(fixed_desc.own, Ownership::Raw | Ownership::RawMut)
+                && !matches!(arg_desc.own, Ownership::Raw | Ownership::RawMut);
+            // With null checks, the cast converts a non-null pointer, and the `Option` is added
+            // by the check below.
+            let cast_desc = if null_check {
+                TypeDesc {
+                    option: false,
+                    ..arg_desc
+                }
+            } else {
+                arg_desc
+            };
             let mut cast_builder = CastBuilder::new(tcx, &gasn.perms, &gasn.flags, |rk| {
                 hir_rw = expr::convert_cast_rewrite(&rk, mem::take(&mut hir_rw));
             });
-            match cast_builder.try_build_cast_desc_desc(fixed_desc, arg_desc) {
+            match cast_builder.try_build_cast_desc_desc(fixed_desc, cast_desc) {
+                Ok(()) if null_check && arg_desc.option => {
+                    hir_rw = Rewrite::Text(format!(
+                        "if {arg_name}.is_null() {{ None }} else {{ Some({hir_rw}) }}"
+                    ));
+                }
+                Ok(()) if null_check => {
+                    stmts.push(Rewrite::Text(format!(
+                        r#"assert!(!{arg_name}.is_null(), "null pointer passed as {arg_name} of {fn_name}")"#,
+                        fn_name = tcx.item_name(def_id),
+                    )));
+                }
                 Ok(()) => {}
                 Err(e) => {
                     if manual_casts == ManualShimCasts::Yes {
@@ -242,12 +297,26 @@ pub fn gen_shim_definition_rewrite<'tcx>(
 
         let safe_name = format!("safe_arg{}", i);
         stmts.push(Rewrite::Let1(safe_name.clone(), Box::new(hir_rw)));
+        if returned_slice_len.map_or(false, |rsl| rsl.arg_index == i) {
+            continue;
+        }
         arg_exprs.push(Rewrite::Print(safe_name));
     }
 
     // Generate the call: `let safe_result = f(safe_arg0, safe_arg1);`
-    let call_rw = Rewrite::Call(owner_node.ident().unwrap().as_str().to_owned(), arg_exprs);
+    let fn_name = owner_node.ident().unwrap().as_str().to_owned();
+    let call_rw = if tcx.def_kind(def_id) == DefKind::AssocFn {
+        Rewrite::Call(format!("Self::{}", fn_name), arg_exprs)
+    } else {
+        Rewrite::Call(fn_name, arg_exprs)
+    };
     stmts.push(Rewrite::Let1("safe_result".into(), Box::new(call_rw)));
+    if let Some(rsl) = returned_slice_len {
+        stmts.push(Rewrite::Text(format!(
+            "*safe_arg{} = safe_result.len() as {}",
+            rsl.arg_index, rsl.len_ty
+        )));
+    }
 
     // Generate `let result = safe_result as ...;`
     let mut result_rw = Rewrite::Print("safe_result".into());
@@ -277,6 +346,7 @@ pub fn gen_shim_definition_rewrite<'tcx>(
 
     let rw = Rewrite::DefineFn {
         name: format!("{}_shim", owner_node.ident().unwrap().as_str()),
+        self_param,
         arg_tys,
         return_ty,
         body: Box::new(body_rw),
@@ -1,25 +1,40 @@
-use crate::context::GlobalAssignment;
 use crate::context::{FlagSet, PermissionSet};
+use crate::context::{GlobalAnalysisCtxt, GlobalAssignment};
 use crate::pointer_id::PointerId;
 use crate::rewrite::Rewrite;
-use rustc_hir::def_id::DefId;
-use rustc_hir::{ItemKind, Mutability, Node};
-use rustc_middle::ty::TyCtxt;
-use rustc_span::Span;
+use rustc_hir::def::{DefKind, Namespace, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BinOpKind, Expr, ExprKind, ItemKind, Mutability, Node};
+use rustc_middle::ty::adjustment::{Adjust, AutoBorrow, AutoBorrowMutability};
+use rustc_middle::ty::print::{FmtPrinter, Print};
+use rustc_middle::ty::{GenericArgKind, ParamEnv, Ty, TyCtxt, TyKind, TypeckResults};
+use rustc_middle::ty::{IntTy, UintTy};
+use rustc_span::{BytePos, Span};
+use std::collections::{HashMap, HashSet};
 
 /// For every static, if its write permission does not match its declared mutability, emit a rewrite
 /// changing the declaration to match observed/analyzed usage.
+///
+/// A `static mut` is considered read-only if the analysis found no writes through its address and
+/// `uses` contains no direct writes or mutable borrows.  Read-only statics become plain `static`s;
+/// read-only arrays that are only ever read by value (such as `TABLE[i]`) become `const`s.  Calls
+/// to `NAME.as_mut_ptr()` on a read-only array are changed to `(NAME.as_ptr() as *mut T)` in
+/// functions that won't otherwise be rewritten.
 pub fn gen_static_rewrites<'tcx>(
-    tcx: TyCtxt<'tcx>,
+    gacx: &GlobalAnalysisCtxt<'tcx>,
     gasn: &GlobalAssignment,
+    uses: &StaticUseMap,
     def_id: DefId,
     ptr: PointerId,
-) -> Option<(Span, Rewrite)> {
+) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+
     // If the `addr_of_static` `PointerId` is `FIXED`, then we're forbidden from emitting this
     // rewrite.
     let flags = gasn.flags[ptr];
     if flags.contains(FlagSet::FIXED) {
-        return None;
+        return Vec::new();
     }
 
     // The map of statics and their ty + permissions tracks statics by DefId; map this to an Item
@@ -33,26 +48,430 @@ pub fn gen_static_rewrites<'tcx>(
         ItemKind::Static(_ty, mutbl, _body_id) => mutbl == Mutability::Mut,
         _ => panic!("expected item {:?} to be a `static`", item),
     };
+    let static_uses = uses.get(&def_id).map_or(&[][..], |v| &v[..]);
+
+    // `as_mut_ptr()` calls can only be patched up outside of functions that are being rewritten,
+    // since the rewrites for the function body may overlap the call.
+    let owner_is_rewritten = |owner: LocalDefId| {
+        matches!(tcx.def_kind(owner), DefKind::Fn | DefKind::AssocFn)
+            && !gacx.dont_rewrite_fn(owner.to_def_id())
+    };
+
     let perms = gasn.perms[ptr];
-    let written_to = perms.contains(PermissionSet::WRITE);
-    if written_to != is_mutable {
-        let ident = tcx
-            .opt_item_ident(def_id)
-            .expect("def_id has no ident when trying to generate rewrite for static item");
-        // Generate a span from beginning of ident to end of body.
-        let span = ident.span.with_hi(item.span.hi());
-        Some((
-            item.span,
-            Rewrite::StaticMut(
-                if written_to {
-                    Mutability::Mut
-                } else {
-                    Mutability::Not
-                },
-                span,
-            ),
-        ))
+    let written_to = perms.contains(PermissionSet::WRITE)
+        || static_uses
+            .iter()
+            .any(|&(owner, static_use)| match static_use {
+                StaticUse::Write(..)
+                | StaticUse::Update(..)
+                | StaticUse::ProjectionWrite
+                | StaticUse::Borrow(Mutability::Mut) => true,
+                StaticUse::AsMutPtr(..) => owner_is_rewritten(owner),
+                StaticUse::Read(_) | StaticUse::Borrow(Mutability::Not) => false,
+            });
+
+    let ident = tcx
+        .opt_item_ident(def_id)
+        .expect("def_id has no ident when trying to generate rewrite for static item");
+    // Generate a span from beginning of ident to end of body.
+    let span = ident.span.with_hi(item.span.hi());
+
+    if written_to {
+        if is_mutable {
+            return Vec::new();
+        }
+        return vec![(item.span, Rewrite::StaticMut(Mutability::Mut, span))];
+    }
+
+    if !is_mutable {
+        return Vec::new();
+    }
+
+    let ty = tcx.type_of(def_id);
+    let mut rewrites = Vec::new();
+    for &(owner, static_use) in static_uses {
+        if let StaticUse::AsMutPtr(method_span, call_span) = static_use {
+            debug_assert!(!owner_is_rewritten(owner));
+            // `*mut _` would leave the pointee type unknown to a method call on the result, such
+            // as `.offset(i)`, so the element type is spelled out.
+            let elem_ty = match *ty.kind() {
+                TyKind::Array(elem_ty, _) => elem_ty,
+                _ => unreachable!("`as_mut_ptr` use of non-array static {def_id:?}"),
+            };
+            let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+            let elem_ty = elem_ty.print(printer).unwrap().into_buffer();
+            rewrites.push((call_span.shrink_to_lo(), Rewrite::Print("(".into())));
+            rewrites.push((method_span, Rewrite::Print("as_ptr".into())));
+            rewrites.push((
+                call_span.shrink_to_hi(),
+                Rewrite::Print(format!(" as *mut {elem_ty})")),
+            ));
+        }
+    }
+
+    let only_read_by_value = static_uses
+        .iter()
+        .all(|&(_, static_use)| matches!(static_use, StaticUse::Read(_)));
+    // A `const` can't refer to other statics in its initializer.
+    let init_mentions_statics = uses
+        .values()
+        .flatten()
+        .any(|&(owner, _)| owner.to_def_id() == def_id);
+    let has_ptrs = ty.walk().any(|arg| match arg.unpack() {
+        GenericArgKind::Type(ty) => ty.is_any_ptr(),
+        _ => false,
+    });
+    if ty.is_array()
+        && only_read_by_value
+        && !init_mentions_statics
+        && !has_ptrs
+        && ty.is_freeze(tcx.at(item.span), ParamEnv::reveal_all())
+    {
+        rewrites.push((item.span, Rewrite::Const(span)));
     } else {
-        None
+        rewrites.push((item.span, Rewrite::StaticMut(Mutability::Not, span)));
+    }
+    rewrites
+}
+
+/// Find all mentions of local `static`s in the bodies of all functions and other items.
+pub fn collect_static_uses(tcx: TyCtxt) -> StaticUseMap {
+    let mut uses = HashMap::new();
+    for owner in tcx.hir().body_owners() {
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(owner) {
+            Some(x) => x,
+            None => continue,
+        };
+        let mut v = StaticUseVisitor {
+            typeck_results: tcx.typeck_body(hir_body_id),
+            owner,
+            uses: &mut uses,
+        };
+        v.visit_body(tcx.hir().body(hir_body_id));
+    }
+    uses
+}
+
+/// A single mention of a `static` within a body.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StaticUse {
+    /// A read of the entire value.  The span is the span of the path expression.
+    Read(Span),
+    /// `NAME = rhs`.  The spans are those of the assignment expression and the RHS.
+    Write(Span, Span),
+    /// `NAME op= rhs`.  The spans are those of the assignment expression and the RHS.
+    Update(Span, BinOpKind, Span),
+    /// An assignment to a field or element of the static, such as `NAME[i] = x`.
+    ProjectionWrite,
+    /// `NAME.as_mut_ptr()`, where `NAME` is an array.  The spans are those of the method name
+    /// and the entire call expression.
+    AsMutPtr(Span, Span),
+    /// Any other borrow of the static or one of its fields, either explicit (`&NAME`,
+    /// `addr_of_mut!(NAME.x)`) or implicit (autoref of a method receiver).
+    Borrow(Mutability),
+}
+
+/// All mentions of each local `static`, along with the body where each one occurs.
+pub type StaticUseMap = HashMap<DefId, Vec<(LocalDefId, StaticUse)>>;
+
+struct StaticUseVisitor<'a, 'tcx> {
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    owner: LocalDefId,
+    uses: &'a mut StaticUseMap,
+}
+
+impl<'a, 'tcx> StaticUseVisitor<'a, 'tcx> {
+    /// If `ex` is a path that refers to a local `static`, return the `DefId` of the static.
+    fn static_path(&self, ex: &Expr<'tcx>) -> Option<DefId> {
+        let qp = match ex.kind {
+            ExprKind::Path(ref qp) => qp,
+            _ => return None,
+        };
+        match self.typeck_results.qpath_res(qp, ex.hir_id) {
+            Res::Def(DefKind::Static(_), def_id) if def_id.is_local() => Some(def_id),
+            _ => None,
+        }
+    }
+
+    /// If `ex` is a place expression whose base is a local `static`, such as `NAME.field[i]`,
+    /// return the `DefId` of the static.
+    fn place_base_static(&self, ex: &Expr<'tcx>) -> Option<DefId> {
+        match ex.kind {
+            ExprKind::Field(base, _) | ExprKind::Index(base, _) => self.place_base_static(base),
+            _ => self.static_path(ex),
+        }
+    }
+
+    fn add_use(&mut self, def_id: DefId, static_use: StaticUse) {
+        self.uses
+            .entry(def_id)
+            .or_default()
+            .push((self.owner, static_use));
+    }
+}
+
+// Nested bodies, such as closures, are visited separately as their own body owners.
+impl<'a, 'tcx> Visitor<'tcx> for StaticUseVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if let ExprKind::MethodCall(ps, [receiver, args @ ..], _) = ex.kind {
+            let is_as_mut_ptr = ps.ident.as_str() == "as_mut_ptr"
+                && self.typeck_results.expr_ty(receiver).is_array();
+            if is_as_mut_ptr {
+                if let Some(def_id) = self.static_path(receiver) {
+                    self.add_use(def_id, StaticUse::AsMutPtr(ps.ident.span, ex.span));
+                    for arg in args {
+                        self.visit_expr(arg);
+                    }
+                    return;
+                }
+            }
+        }
+
+        // Any autoref of a static or one of its fields, such as a method call with a `&self`
+        // receiver, counts as a borrow.
+        for adj in self.typeck_results.expr_adjustments(ex) {
+            let mutbl = match adj.kind {
+                Adjust::Borrow(AutoBorrow::Ref(_, AutoBorrowMutability::Mut { .. })) => {
+                    Mutability::Mut
+                }
+                Adjust::Borrow(AutoBorrow::Ref(_, AutoBorrowMutability::Not)) => Mutability::Not,
+                Adjust::Borrow(AutoBorrow::RawPtr(mutbl)) => mutbl,
+                _ => continue,
+            };
+            if let Some(def_id) = self.place_base_static(ex) {
+                self.add_use(def_id, StaticUse::Borrow(mutbl));
+            }
+        }
+
+        match ex.kind {
+            ExprKind::Assign(lhs, rhs, _) => {
+                if let Some(def_id) = self.static_path(lhs) {
+                    self.add_use(def_id, StaticUse::Write(ex.span, rhs.span));
+                    self.visit_expr(rhs);
+                    return;
+                }
+                if let Some(def_id) = self.place_base_static(lhs) {
+                    self.add_use(def_id, StaticUse::ProjectionWrite);
+                }
+            }
+            ExprKind::AssignOp(op, lhs, rhs) => {
+                if let Some(def_id) = self.static_path(lhs) {
+                    self.add_use(def_id, StaticUse::Update(ex.span, op.node, rhs.span));
+                    self.visit_expr(rhs);
+                    return;
+                }
+                if let Some(def_id) = self.place_base_static(lhs) {
+                    self.add_use(def_id, StaticUse::ProjectionWrite);
+                }
+            }
+            ExprKind::AddrOf(_, mutbl, inner) => {
+                if let Some(def_id) = self.place_base_static(inner) {
+                    self.add_use(def_id, StaticUse::Borrow(mutbl));
+                }
+            }
+            ExprKind::Path(_) => {
+                if let Some(def_id) = self.static_path(ex) {
+                    self.add_use(def_id, StaticUse::Read(ex.span));
+                }
+            }
+            _ => {}
+        }
+
+        intravisit::walk_expr(self, ex);
     }
 }
+
+/// Return the name of the atomic type to use for a static of type `ty`, such as `AtomicI32`, or
+/// `None` if there's no atomic equivalent of `ty`.
+fn local_static_atomic_ty(ty: Ty) -> Option<String> {
+    let suffix = match *ty.kind() {
+        TyKind::Bool => "Bool".to_owned(),
+        TyKind::Int(ity) if ity != IntTy::I128 => ity.name_str().to_owned(),
+        TyKind::Uint(uty) if uty != UintTy::U128 => uty.name_str().to_owned(),
+        _ => return None,
+    };
+    // `i32` -> `I32`, `isize` -> `Isize`
+    let mut chars = suffix.chars();
+    let first = chars.next().unwrap().to_ascii_uppercase();
+    Some(format!("Atomic{}{}", first, chars.as_str()))
+}
+
+/// Return the path of the `core::ops` trait method that implements `op`.
+fn binop_trait_method(op: BinOpKind) -> Option<&'static str> {
+    Some(match op {
+        BinOpKind::Add => "core::ops::Add::add",
+        BinOpKind::Sub => "core::ops::Sub::sub",
+        BinOpKind::Mul => "core::ops::Mul::mul",
+        BinOpKind::Div => "core::ops::Div::div",
+        BinOpKind::Rem => "core::ops::Rem::rem",
+        BinOpKind::BitAnd => "core::ops::BitAnd::bitand",
+        BinOpKind::BitOr => "core::ops::BitOr::bitor",
+        BinOpKind::BitXor => "core::ops::BitXor::bitxor",
+        BinOpKind::Shl => "core::ops::Shl::shl",
+        BinOpKind::Shr => "core::ops::Shr::shr",
+        _ => return None,
+    })
+}
+
+const RELAXED: &str = "core::sync::atomic::Ordering::Relaxed";
+
+/// Generate the rewrite for a single use of a static that's being rewritten to an atomic.
+fn local_static_use_rewrite(name: &str, static_use: StaticUse) -> (Span, Rewrite) {
+    let print = |s: &str| Rewrite::Print(s.to_owned());
+    // Read the current value of the static.
+    let load = |receiver: Rewrite| {
+        Rewrite::MethodCall("load".into(), Box::new(receiver), vec![print(RELAXED)])
+    };
+    // Overwrite the value of the static with `value`.
+    let store = |value: Rewrite| {
+        Rewrite::MethodCall(
+            "store".into(),
+            Box::new(print(name)),
+            vec![value, print(RELAXED)],
+        )
+    };
+
+    match static_use {
+        StaticUse::Read(span) => (span, load(Rewrite::Identity)),
+        StaticUse::Write(span, rhs_span) => (span, store(Rewrite::Sub(1, rhs_span))),
+        StaticUse::Update(span, op, rhs_span) => {
+            // `NAME op= rhs` becomes a `fetch_update` rather than `fetch_add` and friends, which
+            // wrap on overflow, so that the update is a single atomic step and overflow still
+            // panics in debug builds as `op=` did.  `rhs` is evaluated once, before the update,
+            // since the closure may run more than once.
+            let op_fn = binop_trait_method(op).unwrap();
+            let rhs_var = if name == "rhs" { "rhs_" } else { "rhs" };
+            let new_value = Rewrite::Call(op_fn.into(), vec![print("old"), print(rhs_var)]);
+            let closure = Rewrite::Closure1(
+                "old".into(),
+                Box::new(Rewrite::Call("Some".into(), vec![new_value])),
+            );
+            let fetch_update = Rewrite::MethodCall(
+                "fetch_update".into(),
+                Box::new(print(name)),
+                vec![print(RELAXED), print(RELAXED), closure],
+            );
+            // The closure always returns `Some`, so `fetch_update` can't fail.
+            let stmts = vec![
+                Rewrite::Let1(rhs_var.into(), Box::new(Rewrite::Sub(1, rhs_span))),
+                Rewrite::MethodCall("unwrap".into(), Box::new(fetch_update), vec![]),
+            ];
+            (span, Rewrite::Block(stmts, None))
+        }
+        StaticUse::ProjectionWrite | StaticUse::AsMutPtr(..) | StaticUse::Borrow(_) => {
+            unreachable!()
+        }
+    }
+}
+
+/// Rewrite integer and `bool` `static mut` items that are only used by a single function, as is
+/// typical for C function-local `static` variables, into atomics.  The new declaration is placed
+/// at the start of the function that uses it.  Every access becomes a `Relaxed` load, store, or
+/// `fetch_update`, so the value is still shared by all threads and updates are atomic and keep
+/// their overflow checks.  Statics of other types are left alone: `thread_local!` would give each
+/// thread its own copy, and `OnceCell` only fits statics that are written once.  Statics that are
+/// borrowed or are modified through a field or index projection are also left for the main
+/// analysis to handle.
+///
+/// Returns the generated rewrites and the set of statics that were rewritten.  The caller should
+/// not generate any other rewrites for the declarations of those statics.
+pub fn gen_local_static_rewrites<'tcx>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    gasn: &GlobalAssignment,
+    uses: &StaticUseMap,
+    fixed_defs: &HashSet<DefId>,
+) -> (Vec<(Span, Rewrite)>, HashSet<DefId>) {
+    let tcx = gacx.tcx;
+
+    let mut rewrites = Vec::new();
+    let mut rewritten_statics = HashSet::new();
+    let mut static_dids = gacx.addr_of_static.keys().copied().collect::<Vec<_>>();
+    static_dids.sort();
+    for did in static_dids {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        if fixed_defs.contains(&did) || gacx.dont_rewrite_statics.contains(did) {
+            continue;
+        }
+        if gasn.flags[gacx.addr_of_static[&did]].contains(FlagSet::FIXED) {
+            continue;
+        }
+        let item = tcx.hir().expect_item(ldid);
+        let init_body_id = match item.kind {
+            ItemKind::Static(_, Mutability::Mut, body_id) => body_id,
+            _ => continue,
+        };
+        // Exported statics and statics with attributes such as `#[no_mangle]` may be accessed
+        // from outside the crate.
+        if tcx.visibility(did).is_public() || !tcx.hir().attrs(item.hir_id()).is_empty() {
+            continue;
+        }
+
+        let static_uses = match uses.get(&did) {
+            Some(x) => x,
+            None => continue,
+        };
+        let user = static_uses[0].0;
+        if static_uses.iter().any(|&(owner, ref static_use)| {
+            owner != user
+                || matches!(
+                    *static_use,
+                    StaticUse::ProjectionWrite | StaticUse::AsMutPtr(..) | StaticUse::Borrow(_)
+                )
+        }) {
+            continue;
+        }
+        if !matches!(tcx.def_kind(user), DefKind::Fn | DefKind::AssocFn)
+            || gacx.dont_rewrite_fn(user.to_def_id())
+        {
+            continue;
+        }
+
+        let atomic_ty = match local_static_atomic_ty(tcx.type_of(did)) {
+            Some(x) => x,
+            None => continue,
+        };
+        if static_uses
+            .iter()
+            .any(|&(_, ref static_use)| match *static_use {
+                StaticUse::Update(_, op, _) => binop_trait_method(op).is_none(),
+                _ => false,
+            })
+        {
+            continue;
+        }
+
+        let sm = tcx.sess.source_map();
+        let name = item.ident.as_str().to_owned();
+        let init_str = sm
+            .span_to_snippet(tcx.hir().body(init_body_id).value.span)
+            .unwrap();
+        let decl = format!(
+            "static {name}: core::sync::atomic::{atomic_ty} = \
+             core::sync::atomic::{atomic_ty}::new({init_str});"
+        );
+
+        // If the static is already declared inside the function, rewrite it in place.
+        // Otherwise, move it to the start of the function body.
+        if tcx.hir().get_parent_item(item.hir_id()) == user {
+            rewrites.push((item.span, Rewrite::Print(decl)));
+        } else {
+            rewrites.push((item.span, Rewrite::Print(String::new())));
+            let hir_body_id = tcx.hir().body_owned_by(user);
+            let body_span = tcx.hir().body(hir_body_id).value.span;
+            let insert_span = body_span
+                .with_lo(body_span.lo() + BytePos(1))
+                .shrink_to_lo();
+            rewrites.push((insert_span, Rewrite::Print(format!("\n    {decl}"))));
+        }
+
+        for &(_, static_use) in static_uses {
+            rewrites.push(local_static_use_rewrite(&name, static_use));
+        }
+        rewritten_statics.insert(did);
+    }
+
+    (rewrites, rewritten_statics)
+}
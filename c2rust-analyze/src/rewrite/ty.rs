@@ -34,7 +34,7 @@ use rustc_middle::ty::print::{FmtPrinter, Print};
 use rustc_middle::ty::{self, AdtDef, GenericArg, GenericArgKind, List, ReErased, TyCtxt};
 use rustc_middle::ty::{Ty, TyKind, TypeAndMut};
 use rustc_span::symbol::Symbol;
-use rustc_span::Span;
+use rustc_span::{BytePos, Span};
 
 use super::LifetimeName;
 
@@ -248,8 +248,26 @@ fn deconstruct_hir_ty<'a, 'tcx>(
             }
         }
 
+        // `Self` inside an `impl` block.  `Self` has no generic args of its own, so we can only
+        // descend into it when the ADT has no type arguments either.
+        (
+            &ty::TyKind::Adt(_, substs),
+            &hir::TyKind::Path(hir::QPath::Resolved(
+                _,
+                Path {
+                    res: Res::SelfTy { .. },
+                    ..
+                },
+            )),
+        ) => {
+            if substs.types().next().is_none() {
+                Some(Vec::new())
+            } else {
+                None
+            }
+        }
         (&ty::TyKind::Adt(adt_def, substs), &hir::TyKind::Path(hir::QPath::Resolved(_, path)))
-            if path.res.def_id() == adt_def.did() =>
+            if path.res.opt_def_id() == Some(adt_def.did()) =>
         {
             hir_generic_ty_args(hir_ty).map(|type_args| {
                 if type_args.len() < substs.types().count() {
@@ -448,10 +466,13 @@ pub fn desc_parts_to_ty<'tcx>(
 
     ty = match qty {
         Quantity::Single => ty,
+        // `Vec<T>` takes the element type directly.
+        Quantity::Slice | Quantity::OffsetPtr if own == Ownership::Vec => ty,
         Quantity::Slice => tcx.mk_slice(ty),
         // TODO: This should generate `OffsetPtr<T>` rather than `&[T]`, but `OffsetPtr` is NYI
         Quantity::OffsetPtr => tcx.mk_slice(ty),
         Quantity::Array => panic!("can't mk_rewritten_ty with Quantity::Array"),
+        Quantity::CStr => mk_adt_with_generic_args(tcx, "std::ffi::CStr", []),
     };
 
     ty = match own {
@@ -460,8 +481,23 @@ pub fn desc_parts_to_ty<'tcx>(
         Ownership::Imm => tcx.mk_imm_ref(tcx.mk_region(ReErased), ty),
         Ownership::Cell => tcx.mk_imm_ref(tcx.mk_region(ReErased), ty),
         Ownership::Mut => tcx.mk_mut_ref(tcx.mk_region(ReErased), ty),
-        Ownership::Rc => todo!(),
+        Ownership::Rc => mk_adt_with_arg(tcx, "std::rc::Rc", ty),
+        // The owned form of `CStr` is `CString` rather than `Box<CStr>`.
+        Ownership::Box if qty == Quantity::CStr => {
+            mk_adt_with_generic_args(tcx, "std::ffi::CString", [])
+        }
         Ownership::Box => tcx.mk_box(ty),
+        Ownership::Vec => mk_adt_with_arg(tcx, "std::vec::Vec", ty),
+        Ownership::RcRefCell => mk_adt_with_arg(
+            tcx,
+            "std::rc::Rc",
+            mk_adt_with_arg(tcx, "core::cell::RefCell", ty),
+        ),
+        Ownership::ArcMutex => mk_adt_with_arg(
+            tcx,
+            "std::sync::Arc",
+            mk_adt_with_arg(tcx, "std::sync::Mutex", ty),
+        ),
     };
 
     if dyn_owned {
@@ -574,11 +610,13 @@ fn rewrite_ty<'tcx>(
 
             rw = match qty {
                 Quantity::Single => rw,
+                Quantity::Slice | Quantity::OffsetPtr if own == Ownership::Vec => rw,
                 Quantity::Slice => Rewrite::TySlice(Box::new(rw)),
                 // TODO: This should generate `OffsetPtr<T>` rather than `&[T]`, but `OffsetPtr` is
                 // NYI
                 Quantity::OffsetPtr => Rewrite::TySlice(Box::new(rw)),
                 Quantity::Array => panic!("can't rewrite to Quantity::Array"),
+                Quantity::CStr => Rewrite::Print("std::ffi::CStr".into()),
             };
 
             rw = match own {
@@ -587,8 +625,20 @@ fn rewrite_ty<'tcx>(
                 Ownership::Imm => Rewrite::TyRef(lifetime_type, Box::new(rw), Mutability::Not),
                 Ownership::Cell => Rewrite::TyRef(lifetime_type, Box::new(rw), Mutability::Not),
                 Ownership::Mut => Rewrite::TyRef(lifetime_type, Box::new(rw), Mutability::Mut),
-                Ownership::Rc => todo!(),
+                Ownership::Rc => Rewrite::TyCtor("std::rc::Rc".into(), vec![rw]),
+                Ownership::Box if qty == Quantity::CStr => {
+                    Rewrite::Print("std::ffi::CString".into())
+                }
                 Ownership::Box => Rewrite::TyCtor("std::boxed::Box".into(), vec![rw]),
+                Ownership::Vec => Rewrite::TyCtor("std::vec::Vec".into(), vec![rw]),
+                Ownership::RcRefCell => Rewrite::TyCtor(
+                    "std::rc::Rc".into(),
+                    vec![Rewrite::TyCtor("core::cell::RefCell".into(), vec![rw])],
+                ),
+                Ownership::ArcMutex => Rewrite::TyCtor(
+                    "std::sync::Arc".into(),
+                    vec![Rewrite::TyCtor("std::sync::Mutex".into(), vec![rw])],
+                ),
             };
 
             if dyn_owned {
@@ -685,13 +735,53 @@ impl<'tcx, 'a> intravisit::Visitor<'tcx> for HirTyVisitor<'a, 'tcx> {
     }
 }
 
+/// Compute the rewrite for an explicit `self: *const Self` or `self: *mut Self` parameter.  If the
+/// pointer is being rewritten to a reference, this returns a rewrite of the entire parameter to
+/// `&self` or `&mut self`.  Returns `None` if the normal type rewrite should be applied instead,
+/// which is the case for raw pointers and `Box<Self>`, both of which are valid receiver types.
+/// Returns an error if the rewritten type can't be used as a receiver, such as `Option<&Self>` or
+/// `&Cell<Self>`.
+fn self_param_rewrite(rw_lty: RwLTy) -> Result<Option<Rewrite>, String> {
+    let ptr_desc = match rw_lty.label.ty_desc {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    let PtrDesc {
+        own,
+        qty,
+        dyn_owned,
+        option,
+    } = ptr_desc;
+    let non_receiver =
+        || format!("`self` parameter would be rewritten to a non-receiver type ({ptr_desc:?})");
+    if qty != Quantity::Single || dyn_owned || option || rw_lty.label.pointee_ty.is_some() {
+        return Err(non_receiver());
+    }
+
+    let lifetime = match rw_lty.label.lifetime {
+        [lifetime] => format!("{lifetime:?} "),
+        [] => String::new(),
+        _ => panic!("Pointer or reference type cannot have multiple lifetime parameters"),
+    };
+    match own {
+        Ownership::Raw | Ownership::RawMut | Ownership::Box | Ownership::Vec => Ok(None),
+        Ownership::Imm => Ok(Some(Rewrite::Print(format!("&{lifetime}self")))),
+        Ownership::Mut => Ok(Some(Rewrite::Print(format!("&{lifetime}mut self")))),
+        Ownership::Cell | Ownership::Rc | Ownership::RcRefCell | Ownership::ArcMutex => {
+            Err(non_receiver())
+        }
+    }
+}
+
+/// Generate rewrites for the types in the signature and body of `ldid`.  Returns an error if the
+/// function is a method whose `self` parameter can't be rewritten (see `self_param_rewrite`).
 pub fn gen_ty_rewrites<'tcx>(
     acx: &AnalysisCtxt<'_, 'tcx>,
     asn: &Assignment,
     pointee_types: PointerTable<PointeeTypes<'tcx>>,
     mir: &Body<'tcx>,
     ldid: LocalDefId,
-) -> Vec<(Span, Rewrite)> {
+) -> Result<Vec<(Span, Rewrite)>, String> {
     let mut span_to_mir_local = HashMap::new();
     for (local, local_decl) in mir.local_decls.iter_enumerated() {
         span_to_mir_local.insert(local_decl.source_info.span, local);
@@ -726,14 +816,36 @@ pub fn gen_ty_rewrites<'tcx>(
     let generics = hir_generics.unwrap_or(Generics::empty());
     gen_generics_rws(&mut v.hir_rewrites, generics, origin_params.iter());
 
+    let hir_body_id = acx.tcx().hir().body_owned_by(ldid);
+    let body = acx.tcx().hir().body(hir_body_id);
+
+    let has_self_param = acx.tcx().def_kind(ldid) == DefKind::AssocFn
+        && acx
+            .tcx()
+            .associated_item(ldid.to_def_id())
+            .fn_has_self_parameter;
+
+    let returned_slice_len = acx.gacx.returned_slice_lens.get(&ldid.to_def_id());
+
     let lty_sig = acx.gacx.fn_sigs.get(&ldid.to_def_id()).unwrap();
     assert_eq!(lty_sig.inputs.len(), hir_sig.decl.inputs.len());
-    for ((&lty, hir_ty), origin_args) in lty_sig
+    for (i, ((&lty, hir_ty), origin_args)) in lty_sig
         .inputs
         .iter()
         .zip(hir_sig.decl.inputs.iter())
         .zip(input_origin_args.iter())
+        .enumerate()
     {
+        if i == 0 && has_self_param && hir_sig.decl.implicit_self.has_implicit_self() {
+            // `self`, `&self`, and `&mut self` have no raw pointer at the top level, and the HIR
+            // type's span covers the `self` keyword, so there's nothing we can rewrite here.
+            continue;
+        }
+        if returned_slice_len.map_or(false, |rsl| rsl.arg_index == i) {
+            // This parameter is removed below.
+            continue;
+        }
+
         let rw_lty =
             rw_lcx.zip_labels_with(lty, origin_args, &mut |pointer_lty, lifetime_lty, args| {
                 create_rewrite_label(
@@ -747,6 +859,13 @@ pub fn gen_ty_rewrites<'tcx>(
                 )
             });
 
+        if i == 0 && has_self_param {
+            if let Some(rw) = self_param_rewrite(rw_lty)? {
+                v.hir_rewrites.push((body.params[0].span, rw));
+                continue;
+            }
+        }
+
         v.handle_ty(rw_lty, hir_ty);
     }
 
@@ -770,13 +889,42 @@ pub fn gen_ty_rewrites<'tcx>(
         v.handle_ty(output_rw_lty, hir_ty);
     }
 
-    let hir_body_id = acx.tcx().hir().body_owned_by(ldid);
-    let body = acx.tcx().hir().body(hir_body_id);
+    // Remove the out-parameter for the length of the returned slice, since callers now take the
+    // length from the slice itself.  The body still stores the length through the out-parameter,
+    // so it's replaced with a local of the same name.
+    if let Some(rsl) = returned_slice_len {
+        let i = rsl.arg_index;
+        let params = body.params;
+        let remove_span = if i > 0 {
+            params[i - 1].span.shrink_to_hi().to(params[i].span)
+        } else if params.len() > 1 {
+            params[0].span.until(params[1].span)
+        } else {
+            params[0].span
+        };
+        v.hir_rewrites
+            .push((remove_span, Rewrite::Print(String::new())));
+
+        let body_span = body.value.span;
+        let insert_span = body_span
+            .with_lo(body_span.lo() + BytePos(1))
+            .shrink_to_lo();
+        let decl = format!("\n    let {}: &mut {} = &mut 0;", rsl.name, rsl.len_ty);
+        v.hir_rewrites.push((insert_span, Rewrite::Print(decl)));
+    }
+
     intravisit::Visitor::visit_body(&mut v, body);
 
-    // TODO: update cast RHS types
+    rewrite_fn_ptr_casts(
+        acx.gacx,
+        &asn.perms(),
+        &asn.flags(),
+        &pointee_types,
+        hir_body_id,
+        &mut v.hir_rewrites,
+    );
 
-    v.hir_rewrites
+    Ok(v.hir_rewrites)
 }
 
 pub fn gen_generics_rws<'p, 'tcx>(
@@ -873,10 +1021,20 @@ pub fn gen_adt_ty_rewrites<'tcx>(
 
     let field_ltys = &gacx.field_ltys;
 
-    let (field_defs, generics) = match item.kind {
-        ItemKind::Struct(VariantData::Struct(ref fd, _), ref g) => (fd, g),
-        ItemKind::Union(VariantData::Struct(ref fd, _), ref g) => (fd, g),
-        ItemKind::Struct(..) | ItemKind::Enum(..) | ItemKind::Union(..) => {
+    let (field_defs, generics): (Vec<&hir::FieldDef>, _) = match item.kind {
+        ItemKind::Struct(VariantData::Struct(ref fd, _), ref g) => (fd.iter().collect(), g),
+        ItemKind::Union(VariantData::Struct(ref fd, _), ref g) => (fd.iter().collect(), g),
+        // Enums translated from tagged unions carry their pointers in the variant payloads, so
+        // rewrite the fields of every variant, whether struct-like or tuple-like.
+        ItemKind::Enum(ref enum_def, ref g) => (
+            enum_def
+                .variants
+                .iter()
+                .flat_map(|v| v.data.fields())
+                .collect(),
+            g,
+        ),
+        ItemKind::Struct(..) | ItemKind::Union(..) => {
             warn!("unsupported item kind {:?}", item.kind);
             return Vec::new();
         }
@@ -891,7 +1049,7 @@ pub fn gen_adt_ty_rewrites<'tcx>(
         gacx.adt_metadata.table[&did].lifetime_params.iter(),
     );
 
-    for field_def in field_defs.iter() {
+    for field_def in field_defs {
         let fdid = tcx.hir().local_def_id(field_def.hir_id).to_def_id();
         let field_metadata = &adt_metadata.field_info[&fdid];
         let f_lty = field_ltys[&fdid];
@@ -924,6 +1082,136 @@ pub fn gen_adt_ty_rewrites<'tcx>(
     hir_rewrites
 }
 
+/// Rewrite the function pointer types within `hir_ty`, leaving the rest of the type unchanged.
+fn rewrite_fn_ptr_tys<'tcx>(
+    rw_lcx: LabeledTyCtxt<'tcx, RewriteLabel<'tcx>>,
+    hir_rewrites: &mut Vec<(Span, Rewrite)>,
+    rw_lty: RwLTy<'tcx>,
+    hir_ty: &hir::Ty<'tcx>,
+    adt_metadata: &AdtMetadataTable,
+) {
+    if rw_lty.ty.is_fn_ptr() {
+        rewrite_ty(rw_lcx, hir_rewrites, rw_lty, hir_ty, adt_metadata);
+        return;
+    }
+    if let Some(hir_args) = deconstruct_hir_ty(rw_lty.ty, hir_ty) {
+        for (&arg_rw_lty, arg_hir_ty) in rw_lty.args.iter().zip(hir_args) {
+            rewrite_fn_ptr_tys(rw_lcx, hir_rewrites, arg_rw_lty, arg_hir_ty, adt_metadata);
+        }
+    }
+}
+
+/// Collects casts `f as T` of a function item `f` to a function pointer type `T`.
+struct FnPtrCastVisitor<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx ty::TypeckResults<'tcx>,
+    casts: Vec<(DefId, Ty<'tcx>, &'tcx hir::Ty<'tcx>)>,
+}
+
+impl<'tcx> intravisit::Visitor<'tcx> for FnPtrCastVisitor<'tcx> {
+    type NestedFilter = nested_filter::OnlyBodies;
+
+    fn nested_visit_map(&mut self) -> Self::Map {
+        self.tcx.hir()
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx hir::Expr<'tcx>) {
+        if let hir::ExprKind::Cast(src, hir_ty) = ex.kind {
+            let fn_ptr_ty = self.typeck_results.expr_ty(ex);
+            if let TyKind::FnDef(def_id, _) = *self.typeck_results.expr_ty(src).kind() {
+                if fn_ptr_ty.is_fn_ptr() {
+                    self.casts.push((def_id, fn_ptr_ty, hir_ty));
+                }
+            }
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Rewrite the target types of casts `f as unsafe extern "C" fn(..)` in `body_id` to match the
+/// rewritten signature of `f`.  Without this, a function pointer taken from a rewritten function
+/// (for example, an entry of a dispatch table) would no longer typecheck.
+fn rewrite_fn_ptr_casts<'tcx, P, F, PT>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    perms: &P,
+    flags: &F,
+    pointee_types: &PT,
+    body_id: hir::BodyId,
+    hir_rewrites: &mut Vec<(Span, Rewrite)>,
+) where
+    P: Index<PointerId, Output = PermissionSet>,
+    F: Index<PointerId, Output = FlagSet>,
+    PT: Index<PointerId, Output = PointeeTypes<'tcx>>,
+{
+    let tcx = gacx.tcx;
+    let mut v = FnPtrCastVisitor {
+        tcx,
+        typeck_results: tcx.typeck_body(body_id),
+        casts: Vec::new(),
+    };
+    intravisit::Visitor::visit_body(&mut v, tcx.hir().body(body_id));
+
+    let rw_lcx = LabeledTyCtxt::new(tcx);
+    for (def_id, fn_ptr_ty, hir_ty) in v.casts {
+        let lsig = match gacx.fn_sigs.get(&def_id) {
+            Some(x) => x,
+            None => continue,
+        };
+        let args = lsig.inputs_and_output().collect::<Vec<_>>();
+        if args.len() != fn_ptr_ty.fn_sig(tcx).skip_binder().inputs_and_output.len() {
+            continue;
+        }
+        let lty = gacx
+            .lcx
+            .mk(fn_ptr_ty, gacx.lcx.mk_slice(&args), PointerId::NONE);
+        let rw_lty = relabel_rewrites(perms, flags, pointee_types, rw_lcx, lty, gacx);
+        rewrite_ty(rw_lcx, hir_rewrites, rw_lty, hir_ty, &gacx.adt_metadata);
+    }
+}
+
+/// Rewrite the function pointer types in the declared type and initializer of the static `did`,
+/// such as the element type of a dispatch table
+/// `static HANDLERS: [Option<unsafe extern "C" fn(*mut T)>; N]`.  Other parts of the static's
+/// type are left unchanged.
+pub fn gen_static_fn_ptr_rewrites<'tcx>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    gasn: &GlobalAssignment,
+    pointee_types: &GlobalPointerTable<PointeeTypes<'tcx>>,
+    did: DefId,
+) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let mut hir_rewrites = Vec::new();
+    let (hir_ty, body_id) = match tcx.hir().get_if_local(did) {
+        Some(Node::Item(hir::Item {
+            kind: ItemKind::Static(hir_ty, _, body_id),
+            ..
+        })) => (hir_ty, *body_id),
+        _ => return hir_rewrites,
+    };
+
+    let lty = gacx.static_tys[&did];
+    let rw_lcx = LabeledTyCtxt::new(tcx);
+    let rw_lty = relabel_rewrites(&gasn.perms, &gasn.flags, pointee_types, rw_lcx, lty, gacx);
+    rewrite_fn_ptr_tys(
+        rw_lcx,
+        &mut hir_rewrites,
+        rw_lty,
+        hir_ty,
+        &gacx.adt_metadata,
+    );
+
+    rewrite_fn_ptr_casts(
+        gacx,
+        &gasn.perms,
+        &gasn.flags,
+        pointee_types,
+        body_id,
+        &mut hir_rewrites,
+    );
+
+    hir_rewrites
+}
+
 /// Print the rewritten types for all locals in `mir`.  This is used for tests and debugging, as it
 /// reveals the inference results even for temporaries and other locals with no type annotation in
 /// the HIR.
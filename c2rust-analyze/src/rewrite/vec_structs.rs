@@ -0,0 +1,1004 @@
+//! Rewriting of hand-written growable arrays into `Vec`s.
+//!
+//! C code commonly implements a growable array as a struct holding a pointer to the elements, a
+//! length, and a capacity, along with a function that appends an element, doubling the capacity
+//! with `realloc` when the array is full:
+//!
+//! ```ignore
+//! unsafe fn push(v: *mut IntVec, x: c_int) {
+//!     if (*v).len == (*v).cap {
+//!         (*v).cap = if (*v).cap == 0 { 4 } else { (*v).cap * 2 };
+//!         (*v).data = realloc((*v).data as *mut c_void, (*v).cap * 4) as *mut c_int;
+//!     }
+//!     *(*v).data.offset((*v).len as isize) = x;
+//!     (*v).len += 1;
+//! }
+//! ```
+//!
+//! With `--rewrite-vecs`, [`find_vec_structs`] finds these `push` functions and the matching `pop`
+//! functions before the analysis runs.  If every other use of the three fields is one that a
+//! `Vec` supports, the struct is rewritten to hold a `Vec<T>` in place of the three fields, the
+//! bodies of the `push` and `pop` functions are replaced with calls to `Vec::push` and
+//! `Vec::pop`, and the other uses are rewritten by [`gen_vec_struct_rewrites`]:
+//!
+//! * Reads of the length become `data.len()`.
+//! * Elements accessed with `*data.offset(i)` are accessed with `data[i as usize]`.
+//! * `free(data)` drops the elements, and resetting the fields to null and zero, in a struct
+//!   literal or by assignment, creates an empty `Vec`.
+//!
+//! The struct and the functions that use its fields are excluded from the main analysis, which
+//! would otherwise rewrite the same code.  Since `Vec` isn't `Copy`, the `Copy` derive is removed
+//! from the struct, and structs that are copied out of a pointer or another place are left alone.
+
+use rustc_ast::ast::LitKind;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{
+    BinOpKind, Block, Body, Expr, ExprKind, HirId, Local, Mutability, Node, PatKind, StmtKind, UnOp,
+};
+use rustc_middle::ty::{TyCtxt, TyKind, TypeckResults};
+use rustc_span::{BytePos, Span, Symbol};
+use std::collections::{HashMap, HashSet};
+
+use crate::rewrite::Rewrite;
+
+/// A struct that implements a growable array, to be rewritten to hold a `Vec`.
+#[derive(Clone, Debug)]
+pub struct VecStruct {
+    did: DefId,
+    fields: VecFields,
+    /// Functions whose bodies are replaced by `Vec::push` or `Vec::pop`.
+    helpers: Vec<(LocalDefId, Helper)>,
+    /// Other uses of the fields.
+    uses: Vec<VecFieldUse>,
+}
+
+impl VecStruct {
+    /// The struct itself and every body that mentions its fields.  These are all rewritten by
+    /// [`gen_vec_struct_rewrites`], so the main analysis should treat them as fixed.
+    pub fn fixed_defs(&self) -> impl Iterator<Item = DefId> + '_ {
+        let helpers = self.helpers.iter().map(|&(ldid, _)| ldid.to_def_id());
+        let users = self.uses.iter().map(|u| u.owner.to_def_id());
+        std::iter::once(self.did).chain(helpers).chain(users)
+    }
+}
+
+/// The names of the fields of a growable array struct.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct VecFields {
+    data: Symbol,
+    len: Symbol,
+    cap: Symbol,
+}
+
+#[derive(Clone, Debug)]
+enum Helper {
+    /// `push(v, x)`.  The body's span is replaced with a call to `Vec::push`.
+    Push {
+        body_span: Span,
+        vec: Symbol,
+        elem: Symbol,
+    },
+    /// `pop(v)`.  `empty` is the span of the value returned when the array is empty, if the
+    /// function checks for that case.
+    Pop {
+        body_span: Span,
+        vec: Symbol,
+        empty: Option<Span>,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+struct VecFieldUse {
+    owner: LocalDefId,
+    kind: VecFieldUseKind,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum VecFieldUseKind {
+    /// A read of the length field.  The spans are those of the field expression and of the field
+    /// name.
+    LenRead(Span, Span),
+    /// `*base.data.offset(idx)`.  The spans are those of the whole expression, of `base.data`,
+    /// and of `idx`.
+    Elem(Span, Span, Span),
+    /// `free(base.data)`.  The spans are those of the call and of `base.data`.
+    Free(Span, Span),
+    /// `base.data = null`.  The spans are those of the assignment and of `base.data`.
+    DataReset(Span, Span),
+    /// `base.len = 0`.  The spans are those of the assignment, of `base.len`, and of the field
+    /// name.
+    LenReset(Span, Span, Span),
+    /// `base.cap = 0`.  The span is that of the assignment.
+    CapReset(Span),
+    /// The null pointer assigned to `data` in a struct literal.
+    DataInit(Span),
+    /// The `len` or `cap` field of a struct literal, which is removed.
+    InitField(Span),
+}
+
+/// Find the structs to rewrite with `--rewrite-vecs`.  Structs in `fixed_defs` or mentioned in
+/// `extern` blocks are never rewritten.
+pub fn find_vec_structs(
+    tcx: TyCtxt,
+    fixed_defs: &HashSet<DefId>,
+    foreign_mentioned_tys: &HashSet<DefId>,
+) -> Vec<VecStruct> {
+    let fn_bodies = tcx
+        .hir()
+        .body_owners()
+        .filter(|&ldid| matches!(tcx.def_kind(ldid), DefKind::Fn | DefKind::AssocFn))
+        .map(|ldid| (ldid, tcx.hir().body(tcx.hir().body_owned_by(ldid))))
+        .collect::<Vec<_>>();
+
+    // Find the `push` functions first, since they determine the roles of the fields.
+    let mut structs = HashMap::<DefId, VecStruct>::new();
+    let mut rejected = HashSet::new();
+    for &(ldid, body) in &fn_bodies {
+        let m = Matcher::new(tcx, body);
+        let (did, fields, helper) = match m.match_push(body) {
+            Some(x) => x,
+            None => continue,
+        };
+        if fixed_defs.contains(&did) || foreign_mentioned_tys.contains(&did) {
+            continue;
+        }
+        let vs = structs.entry(did).or_insert_with(|| VecStruct {
+            did,
+            fields,
+            helpers: Vec::new(),
+            uses: Vec::new(),
+        });
+        if vs.fields != fields {
+            rejected.insert(did);
+        }
+        vs.helpers.push((ldid, helper));
+    }
+
+    for &(ldid, body) in &fn_bodies {
+        let m = Matcher::new(tcx, body);
+        if structs
+            .values()
+            .any(|vs| vs.helpers.iter().any(|&(x, _)| x == ldid))
+        {
+            continue;
+        }
+        if let Some((did, helper)) = m.match_pop(body, &structs) {
+            structs.get_mut(&did).unwrap().helpers.push((ldid, helper));
+        }
+    }
+
+    let helper_fns = structs
+        .values()
+        .flat_map(|vs| vs.helpers.iter().map(|&(ldid, _)| ldid))
+        .collect::<HashSet<_>>();
+    for owner in tcx.hir().body_owners() {
+        if helper_fns.contains(&owner) {
+            continue;
+        }
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(owner) {
+            Some(x) => x,
+            None => continue,
+        };
+        let body = tcx.hir().body(hir_body_id);
+        let mut v = UseVisitor {
+            m: Matcher::new(tcx, body),
+            owner,
+            structs: &mut structs,
+            rejected: &mut rejected,
+        };
+        v.visit_body(body);
+    }
+
+    let mut structs = structs
+        .into_values()
+        .filter(|vs| !rejected.contains(&vs.did))
+        .collect::<Vec<_>>();
+    structs.sort_by_key(|vs| vs.did);
+    structs
+}
+
+/// Generate the rewrites for the structs found by [`find_vec_structs`].
+pub fn gen_vec_struct_rewrites(tcx: TyCtxt, structs: &[VecStruct]) -> Vec<(Span, Rewrite)> {
+    let sm = tcx.sess.source_map();
+    let mut rewrites = Vec::new();
+    for vs in structs {
+        let VecFields { data, len, cap } = vs.fields;
+        let adt_def = tcx.adt_def(vs.did);
+        let mut len_ty = String::new();
+        for field in adt_def.all_fields() {
+            let field_def = match tcx.hir().get_if_local(field.did) {
+                Some(Node::Field(x)) => x,
+                _ => panic!("expected a local field for {:?}", field.did),
+            };
+            if field.name == data {
+                let elem_ty = match field_def.ty.kind {
+                    rustc_hir::TyKind::Ptr(mut_ty) => sm.span_to_snippet(mut_ty.ty.span).unwrap(),
+                    _ => panic!("expected a pointer type for {:?}", field.did),
+                };
+                rewrites.push((field_def.ty.span, Rewrite::Print(format!("Vec<{elem_ty}>"))));
+            } else if field.name == len || field.name == cap {
+                if field.name == len {
+                    len_ty = sm.span_to_snippet(field_def.ty.span).unwrap();
+                }
+                rewrites.push((
+                    with_trailing_comma(tcx, field_def.span),
+                    Rewrite::Print(String::new()),
+                ));
+            }
+        }
+
+        if let Some(span) = find_copy_impl(tcx, vs.did).and_then(|did| copy_derive_span(tcx, did)) {
+            rewrites.push((span, Rewrite::Print(String::new())));
+        }
+
+        for &(_, ref helper) in &vs.helpers {
+            let (body_span, rw) = match *helper {
+                Helper::Push {
+                    body_span,
+                    vec,
+                    elem,
+                } => {
+                    let push = Rewrite::MethodCall(
+                        "push".into(),
+                        Box::new(Rewrite::Text(format!("(*{vec}).{data}"))),
+                        vec![Rewrite::Text(elem.to_string())],
+                    );
+                    (body_span, Rewrite::Block(vec![push], None))
+                }
+                Helper::Pop {
+                    body_span,
+                    vec,
+                    empty,
+                } => {
+                    let pop = Rewrite::MethodCall(
+                        "pop".into(),
+                        Box::new(Rewrite::Text(format!("(*{vec}).{data}"))),
+                        vec![],
+                    );
+                    let unwrap = match empty {
+                        Some(span) => Rewrite::MethodCall(
+                            "unwrap_or".into(),
+                            Box::new(pop),
+                            vec![Rewrite::Extract(span)],
+                        ),
+                        None => Rewrite::MethodCall("unwrap".into(), Box::new(pop), vec![]),
+                    };
+                    (body_span, Rewrite::Block(vec![], Some(Box::new(unwrap))))
+                }
+            };
+            rewrites.push((body_span, rw));
+        }
+
+        for u in &vs.uses {
+            match u.kind {
+                VecFieldUseKind::LenRead(span, name_span) => {
+                    rewrites.push((
+                        span,
+                        Rewrite::Cast(
+                            Box::new(Rewrite::Identity),
+                            Box::new(Rewrite::Print(len_ty.clone())),
+                        ),
+                    ));
+                    rewrites.push((name_span, Rewrite::Print(format!("{data}.len()"))));
+                }
+                VecFieldUseKind::Elem(span, data_span, idx_span) => {
+                    rewrites.push((
+                        span,
+                        Rewrite::Index(
+                            Box::new(Rewrite::Sub(0, data_span)),
+                            Box::new(Rewrite::Cast(
+                                Box::new(Rewrite::Sub(1, idx_span)),
+                                Box::new(Rewrite::Print("usize".into())),
+                            )),
+                        ),
+                    ));
+                }
+                VecFieldUseKind::Free(span, data_span) => {
+                    let take = Rewrite::Call(
+                        "std::mem::take".into(),
+                        vec![Rewrite::Ref(
+                            Box::new(Rewrite::Sub(0, data_span)),
+                            Mutability::Mut,
+                        )],
+                    );
+                    rewrites.push((span, Rewrite::Call("drop".into(), vec![take])));
+                }
+                VecFieldUseKind::DataReset(span, data_span) => {
+                    // The old value may be uninitialized, as in a struct allocated with `malloc`,
+                    // so it's overwritten without being dropped.
+                    rewrites.push((
+                        span,
+                        Rewrite::Call(
+                            "std::ptr::write".into(),
+                            vec![
+                                Rewrite::AddrOf(
+                                    Box::new(Rewrite::Sub(0, data_span)),
+                                    Mutability::Mut,
+                                ),
+                                Rewrite::Text("Vec::new()".into()),
+                            ],
+                        ),
+                    ));
+                }
+                VecFieldUseKind::LenReset(span, len_span, name_span) => {
+                    rewrites.push((
+                        span,
+                        Rewrite::MethodCall(
+                            "clear".into(),
+                            Box::new(Rewrite::Sub(0, len_span)),
+                            vec![],
+                        ),
+                    ));
+                    rewrites.push((name_span, Rewrite::Print(data.to_string())));
+                }
+                VecFieldUseKind::CapReset(span) => {
+                    rewrites.push((span, Rewrite::Text("()".into())));
+                }
+                VecFieldUseKind::DataInit(span) => {
+                    rewrites.push((span, Rewrite::Text("Vec::new()".into())));
+                }
+                VecFieldUseKind::InitField(span) => {
+                    rewrites.push((
+                        with_trailing_comma(tcx, span),
+                        Rewrite::Print(String::new()),
+                    ));
+                }
+            }
+        }
+    }
+    rewrites
+}
+
+/// Extend `span` to include the comma that follows it, if there is one.
+fn with_trailing_comma(tcx: TyCtxt, span: Span) -> Span {
+    let next = span.shrink_to_hi().with_hi(span.hi() + BytePos(1));
+    match tcx.sess.source_map().span_to_snippet(next) {
+        Ok(s) if s == "," => span.with_hi(next.hi()),
+        _ => span,
+    }
+}
+
+pub(super) fn find_copy_impl(tcx: TyCtxt, did: DefId) -> Option<DefId> {
+    let copy_trait = tcx.lang_items().copy_trait()?;
+    let mut copy_impl = None;
+    tcx.for_each_relevant_impl(copy_trait, tcx.type_of(did), |impl_did| {
+        copy_impl = Some(impl_did);
+    });
+    copy_impl
+}
+
+/// Find the `Copy` in the `#[derive(Copy, Clone)]` that produced `impl_did`, along with the comma
+/// and space that follow it.
+pub(super) fn copy_derive_span(tcx: TyCtxt, impl_did: DefId) -> Option<Span> {
+    let sm = tcx.sess.source_map();
+    let span = tcx.def_span(impl_did).ctxt().outer_expn_data().call_site;
+    if sm.span_to_snippet(span).ok()? != "Copy" {
+        return None;
+    }
+    let next = span.shrink_to_hi().with_hi(span.hi() + BytePos(2));
+    match sm.span_to_snippet(next) {
+        Ok(s) if s == ", " => Some(span.with_hi(next.hi())),
+        _ => Some(span),
+    }
+}
+
+/// Matches the expressions of a single body against the patterns used by growable arrays.  Also
+/// used to find the destructors in `drop_impls`.
+pub(super) struct Matcher<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    pub(super) typeck_results: &'tcx TypeckResults<'tcx>,
+}
+
+/// The index of an element stored by a `push` function: either the length field itself or a
+/// local holding a copy of it, as in `let fresh0 = (*v).len;`.
+enum StoreIndex {
+    Field(Symbol),
+    Local(HirId),
+}
+
+impl<'tcx> Matcher<'tcx> {
+    pub(super) fn new(tcx: TyCtxt<'tcx>, body: &'tcx Body<'tcx>) -> Matcher<'tcx> {
+        Matcher {
+            tcx,
+            typeck_results: tcx.typeck_body(body.id()),
+        }
+    }
+
+    /// Remove casts from `ex`.
+    pub(super) fn peel<'a>(&self, mut ex: &'a Expr<'a>) -> &'a Expr<'a> {
+        loop {
+            match ex.kind {
+                ExprKind::Cast(inner, _) | ExprKind::DropTemps(inner) => ex = inner,
+                _ => return ex,
+            }
+        }
+    }
+
+    /// If `ex` refers to a local variable, return its `HirId`.
+    pub(super) fn local(&self, ex: &Expr<'tcx>) -> Option<HirId> {
+        match self.peel(ex).kind {
+            ExprKind::Path(ref qp) => match self.typeck_results.qpath_res(qp, ex.hir_id) {
+                Res::Local(hir_id) => Some(hir_id),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// If `ex` is `(*p).field`, where `p` is the local `p_id`, return the field name.
+    pub(super) fn param_field(&self, ex: &Expr<'tcx>, p_id: HirId) -> Option<Symbol> {
+        match self.peel(ex).kind {
+            ExprKind::Field(base, ident) => match base.kind {
+                ExprKind::Unary(UnOp::Deref, p) if self.local(p) == Some(p_id) => Some(ident.name),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// If `ex` is a field of a struct, return the struct's `DefId` and the field name.
+    pub(super) fn struct_field<'a>(
+        &self,
+        ex: &'a Expr<'tcx>,
+    ) -> Option<(DefId, Symbol, &'a Expr<'tcx>)> {
+        match ex.kind {
+            ExprKind::Field(base, ident) => match *self.typeck_results.expr_ty(base).kind() {
+                TyKind::Adt(adt_def, _) if adt_def.is_struct() => {
+                    Some((adt_def.did(), ident.name, base))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn is_zero(&self, ex: &Expr<'tcx>) -> bool {
+        match self.peel(ex).kind {
+            ExprKind::Lit(ref lit) => matches!(lit.node, LitKind::Int(0, _)),
+            _ => false,
+        }
+    }
+
+    fn is_one(&self, ex: &Expr<'tcx>) -> bool {
+        match self.peel(ex).kind {
+            ExprKind::Lit(ref lit) => matches!(lit.node, LitKind::Int(1, _)),
+            _ => false,
+        }
+    }
+
+    /// Whether `ex` calls the function named `name`, such as `realloc`.
+    pub(super) fn is_call_to(&self, ex: &Expr<'tcx>, name: &str) -> bool {
+        match self.peel(ex).kind {
+            ExprKind::Call(func, _) => match func.kind {
+                ExprKind::Path(ref qp) => match self.typeck_results.qpath_res(qp, func.hir_id) {
+                    Res::Def(DefKind::Fn | DefKind::AssocFn, did) => {
+                        self.tcx.item_name(did).as_str() == name
+                    }
+                    _ => false,
+                },
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether `ex` is a null pointer, such as `0 as *mut T` or `ptr::null_mut()`.
+    fn is_null(&self, ex: &Expr<'tcx>) -> bool {
+        self.is_zero(ex) || self.is_call_to(ex, "null_mut") || self.is_call_to(ex, "null")
+    }
+
+    /// If `ex` is `field + 1` or `field.wrapping_add(1)`, or the same with subtraction if `add` is
+    /// not set, return the field name.
+    fn step_of(&self, ex: &Expr<'tcx>, p_id: HirId, add: bool) -> Option<Symbol> {
+        let (op, method) = if add {
+            (BinOpKind::Add, "wrapping_add")
+        } else {
+            (BinOpKind::Sub, "wrapping_sub")
+        };
+        match self.peel(ex).kind {
+            ExprKind::Binary(bop, lhs, rhs) if bop.node == op && self.is_one(rhs) => {
+                self.param_field(lhs, p_id)
+            }
+            ExprKind::MethodCall(ps, [recv, arg], _)
+                if ps.ident.as_str() == method && self.is_one(arg) =>
+            {
+                self.param_field(recv, p_id)
+            }
+            _ => None,
+        }
+    }
+
+    /// If `ex` is `field += 1`, `field = field + 1`, or the same with subtraction if `add` is not
+    /// set, return the field name.
+    fn update_of(&self, ex: &Expr<'tcx>, p_id: HirId, add: bool) -> Option<Symbol> {
+        let op = if add { BinOpKind::Add } else { BinOpKind::Sub };
+        match ex.kind {
+            ExprKind::AssignOp(bop, lhs, rhs) if bop.node == op && self.is_one(rhs) => {
+                self.param_field(lhs, p_id)
+            }
+            ExprKind::Assign(lhs, rhs, _) => {
+                let field = self.param_field(lhs, p_id)?;
+                (self.step_of(rhs, p_id, add) == Some(field)).then_some(field)
+            }
+            _ => None,
+        }
+    }
+
+    /// If `ex` is `*(*p).data.offset(idx)`, return the field name and `idx`.
+    fn elem_of<'a>(&self, ex: &'a Expr<'tcx>, p_id: HirId) -> Option<(Symbol, &'a Expr<'tcx>)> {
+        match ex.kind {
+            ExprKind::Unary(UnOp::Deref, ptr) => match ptr.kind {
+                ExprKind::MethodCall(ps, [recv, idx], _)
+                    if matches!(ps.ident.as_str(), "offset" | "add") =>
+                {
+                    Some((self.param_field(recv, p_id)?, idx))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// If the parameter `param` is a simple binding of type `*mut S`, return its name, its
+    /// `HirId`, and the `DefId` of the struct `S`.
+    pub(super) fn struct_ptr_param(
+        &self,
+        param: &rustc_hir::Param<'tcx>,
+    ) -> Option<(Symbol, HirId, DefId)> {
+        let (hir_id, name) = match param.pat.kind {
+            PatKind::Binding(_, hir_id, ident, None) => (hir_id, ident.name),
+            _ => return None,
+        };
+        let ty = self.typeck_results.pat_ty(param.pat);
+        match *ty.kind() {
+            TyKind::RawPtr(mt) if mt.mutbl == Mutability::Mut => match *mt.ty.kind() {
+                TyKind::Adt(adt_def, substs)
+                    if adt_def.is_struct() && adt_def.did().is_local() && substs.is_empty() =>
+                {
+                    Some((name, hir_id, adt_def.did()))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Match a `push` function, as shown in the module docs.  Returns the struct, the roles of its
+    /// fields, and the replacement for the function body.
+    fn match_push(&self, body: &'tcx Body<'tcx>) -> Option<(DefId, VecFields, Helper)> {
+        let (p_param, x_param) = match body.params {
+            [p, x] => (p, x),
+            _ => return None,
+        };
+        let (vec, p_id, did) = self.struct_ptr_param(p_param)?;
+        let (elem, x_id) = match x_param.pat.kind {
+            PatKind::Binding(_, hir_id, ident, None) => (ident.name, hir_id),
+            _ => return None,
+        };
+        let block = fn_block(body)?;
+        if block.expr.is_some() {
+            return None;
+        }
+
+        let mut grow = None;
+        let mut aliases = Vec::new();
+        let mut store = None;
+        let mut incr = None;
+        for stmt in block.stmts {
+            match stmt.kind {
+                StmtKind::Local(&Local {
+                    pat,
+                    init: Some(init),
+                    els: None,
+                    ..
+                }) if store.is_none() && incr.is_none() => {
+                    let hir_id = match pat.kind {
+                        PatKind::Binding(_, hir_id, _, None) => hir_id,
+                        _ => return None,
+                    };
+                    aliases.push((hir_id, self.param_field(init, p_id)?));
+                }
+                StmtKind::Expr(ex) | StmtKind::Semi(ex) => {
+                    if let ExprKind::If(cond, then, None) = ex.kind {
+                        if grow.is_some() || store.is_some() || incr.is_some() {
+                            return None;
+                        }
+                        grow = Some(self.match_grow(cond, then, p_id)?);
+                    } else if let Some(field) = self.update_of(ex, p_id, true) {
+                        if incr.replace(field).is_some() {
+                            return None;
+                        }
+                    } else if let ExprKind::Assign(lhs, rhs, _) = ex.kind {
+                        let (data, idx) = self.elem_of(lhs, p_id)?;
+                        if self.local(rhs) != Some(x_id) {
+                            return None;
+                        }
+                        let idx = match self.param_field(idx, p_id) {
+                            // Storing at the length field is only correct before it's incremented.
+                            Some(field) if incr.is_none() => StoreIndex::Field(field),
+                            Some(_) => return None,
+                            None => StoreIndex::Local(self.local(idx)?),
+                        };
+                        if store.replace((data, idx)).is_some() {
+                            return None;
+                        }
+                    } else {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+        }
+
+        let ((a, b, grown), (data, idx), len) = (grow?, store?, incr?);
+        let idx_field = match idx {
+            StoreIndex::Field(field) => field,
+            StoreIndex::Local(hir_id) => aliases.iter().find(|&&(x, _)| x == hir_id)?.1,
+        };
+        let cap = if a == len { b } else { a };
+        if idx_field != len
+            || (a != len && b != len)
+            || cap == len
+            || data == len
+            || data == cap
+            || !grown.contains(&data)
+            || grown.iter().any(|&f| f != data && f != cap)
+        {
+            return None;
+        }
+
+        // The element type of `data` must be the type of `x`.
+        let adt_def = self.tcx.adt_def(did);
+        let data_field = adt_def.all_fields().find(|f| f.name == data)?;
+        let elem_ty = match *self.tcx.type_of(data_field.did).kind() {
+            TyKind::RawPtr(mt) => mt.ty,
+            _ => return None,
+        };
+        if self.typeck_results.pat_ty(x_param.pat) != elem_ty {
+            return None;
+        }
+
+        let fields = VecFields { data, len, cap };
+        let helper = Helper::Push {
+            body_span: body.value.span,
+            vec,
+            elem,
+        };
+        Some((did, fields, helper))
+    }
+
+    /// Match the `if` that grows the array in a `push` function.  Returns the two fields compared
+    /// in the condition and the fields assigned in the body, one of which must be assigned the
+    /// result of `realloc`.
+    fn match_grow(
+        &self,
+        cond: &Expr<'tcx>,
+        then: &Expr<'tcx>,
+        p_id: HirId,
+    ) -> Option<(Symbol, Symbol, Vec<Symbol>)> {
+        let (a, b) = match self.peel(cond).kind {
+            ExprKind::Binary(bop, lhs, rhs)
+                if matches!(bop.node, BinOpKind::Eq | BinOpKind::Ge | BinOpKind::Le) =>
+            {
+                (self.param_field(lhs, p_id)?, self.param_field(rhs, p_id)?)
+            }
+            _ => return None,
+        };
+        let block = match then.kind {
+            ExprKind::Block(block, None) if block.expr.is_none() => block,
+            _ => return None,
+        };
+        let mut assigned = Vec::new();
+        let mut reallocated = false;
+        for stmt in block.stmts {
+            let (lhs, rhs) = match stmt.kind {
+                StmtKind::Semi(&Expr {
+                    kind: ExprKind::Assign(lhs, rhs, _),
+                    ..
+                }) => (lhs, rhs),
+                _ => return None,
+            };
+            assigned.push(self.param_field(lhs, p_id)?);
+            reallocated |= self.is_call_to(rhs, "realloc");
+        }
+        reallocated.then_some((a, b, assigned))
+    }
+
+    /// Match a `pop` function for one of `structs`:
+    ///
+    /// ```ignore
+    /// unsafe fn pop(v: *mut IntVec) -> c_int {
+    ///     if (*v).len == 0 {
+    ///         return -1;
+    ///     }
+    ///     (*v).len -= 1;
+    ///     *(*v).data.offset((*v).len as isize)
+    /// }
+    /// ```
+    ///
+    /// The check for an empty array is optional.
+    fn match_pop(
+        &self,
+        body: &'tcx Body<'tcx>,
+        structs: &HashMap<DefId, VecStruct>,
+    ) -> Option<(DefId, Helper)> {
+        let p_param = match body.params {
+            [p] => p,
+            _ => return None,
+        };
+        let (vec, p_id, did) = self.struct_ptr_param(p_param)?;
+        let VecFields { data, len, .. } = structs.get(&did)?.fields;
+        let block = fn_block(body)?;
+
+        let mut stmts = block.stmts;
+        let mut empty = None;
+        if let [first, rest @ ..] = stmts {
+            if let StmtKind::Expr(ex) | StmtKind::Semi(ex) = first.kind {
+                if let ExprKind::If(cond, then, None) = ex.kind {
+                    empty = Some(self.match_empty_check(cond, then, p_id, len)?);
+                    stmts = rest;
+                }
+            }
+        }
+
+        let (decr, result) = match (stmts, block.expr) {
+            ([decr], Some(result)) => (decr, result),
+            ([decr, last], None) => match last.kind {
+                StmtKind::Semi(&Expr {
+                    kind: ExprKind::Ret(Some(result)),
+                    ..
+                }) => (decr, result),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        match decr.kind {
+            StmtKind::Semi(ex) if self.update_of(ex, p_id, false) == Some(len) => {}
+            _ => return None,
+        }
+        let (elem_data, idx) = self.elem_of(result, p_id)?;
+        if elem_data != data || self.param_field(idx, p_id) != Some(len) {
+            return None;
+        }
+
+        let helper = Helper::Pop {
+            body_span: body.value.span,
+            vec,
+            empty,
+        };
+        Some((did, helper))
+    }
+
+    /// Match `if (*p).len == 0 { return e; }`, returning the span of `e`.
+    fn match_empty_check(
+        &self,
+        cond: &Expr<'tcx>,
+        then: &Expr<'tcx>,
+        p_id: HirId,
+        len: Symbol,
+    ) -> Option<Span> {
+        match self.peel(cond).kind {
+            ExprKind::Binary(bop, lhs, rhs)
+                if bop.node == BinOpKind::Eq
+                    && ((self.param_field(lhs, p_id) == Some(len) && self.is_zero(rhs))
+                        || (self.is_zero(lhs) && self.param_field(rhs, p_id) == Some(len))) => {}
+            _ => return None,
+        }
+        let block = match then.kind {
+            ExprKind::Block(block, None) => block,
+            _ => return None,
+        };
+        let ret = match (block.stmts, block.expr) {
+            ([stmt], None) => match stmt.kind {
+                StmtKind::Expr(ex) | StmtKind::Semi(ex) => ex,
+                _ => return None,
+            },
+            ([], Some(ex)) => ex,
+            _ => return None,
+        };
+        match ret.kind {
+            ExprKind::Ret(Some(e)) => Some(e.span),
+            _ => None,
+        }
+    }
+}
+
+pub(super) fn fn_block<'tcx>(body: &'tcx Body<'tcx>) -> Option<&'tcx Block<'tcx>> {
+    match body.value.kind {
+        ExprKind::Block(block, None) => Some(block),
+        _ => None,
+    }
+}
+
+/// Collects the uses of the fields of each struct in `structs` within a single body.  Structs
+/// whose fields are used in unsupported ways are added to `rejected`.
+struct UseVisitor<'a, 'tcx> {
+    m: Matcher<'tcx>,
+    owner: LocalDefId,
+    structs: &'a mut HashMap<DefId, VecStruct>,
+    rejected: &'a mut HashSet<DefId>,
+}
+
+impl<'a, 'tcx> UseVisitor<'a, 'tcx> {
+    /// If `ex` is a field of one of the structs being rewritten, return the struct's `DefId`, the
+    /// field's role, and the base expression.
+    fn vec_field<'b>(&self, ex: &'b Expr<'tcx>) -> Option<(DefId, FieldRole, &'b Expr<'tcx>)> {
+        let (did, name, base) = self.m.struct_field(ex)?;
+        let fields = self.structs.get(&did)?.fields;
+        let role = if name == fields.data {
+            FieldRole::Data
+        } else if name == fields.len {
+            FieldRole::Len
+        } else if name == fields.cap {
+            FieldRole::Cap
+        } else {
+            return None;
+        };
+        Some((did, role, base))
+    }
+
+    fn add_use(&mut self, did: DefId, kind: VecFieldUseKind) {
+        let owner = self.owner;
+        self.structs
+            .get_mut(&did)
+            .unwrap()
+            .uses
+            .push(VecFieldUse { owner, kind });
+    }
+
+    /// Reject the struct if `ex` copies it out of a pointer or another place, which is no longer
+    /// possible once it holds a `Vec`.
+    fn check_by_value(&mut self, ex: &Expr<'tcx>) {
+        if !matches!(
+            ex.kind,
+            ExprKind::Unary(UnOp::Deref, _) | ExprKind::Field(..) | ExprKind::Index(..)
+        ) {
+            return;
+        }
+        if let TyKind::Adt(adt_def, _) = *self.m.typeck_results.expr_ty(ex).kind() {
+            if self.structs.contains_key(&adt_def.did()) {
+                self.rejected.insert(adt_def.did());
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FieldRole {
+    Data,
+    Len,
+    Cap,
+}
+
+// Nested bodies, such as closures, are visited separately as their own body owners.
+impl<'a, 'tcx> Visitor<'tcx> for UseVisitor<'a, 'tcx> {
+    fn visit_local(&mut self, local: &'tcx Local<'tcx>) {
+        if let Some(init) = local.init {
+            self.check_by_value(init);
+        }
+        intravisit::walk_local(self, local);
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            ExprKind::Unary(UnOp::Deref, ptr) => {
+                if let ExprKind::MethodCall(ps, [recv, idx], _) = ptr.kind {
+                    if let Some((did, FieldRole::Data, base)) = self.vec_field(recv) {
+                        if matches!(ps.ident.as_str(), "offset" | "add") {
+                            self.add_use(did, VecFieldUseKind::Elem(ex.span, recv.span, idx.span));
+                            self.visit_expr(base);
+                            self.visit_expr(idx);
+                            return;
+                        }
+                    }
+                }
+            }
+
+            ExprKind::Call(_, [arg]) if self.m.is_call_to(ex, "free") => {
+                let data = self.m.peel(arg);
+                if let Some((did, FieldRole::Data, base)) = self.vec_field(data) {
+                    self.add_use(did, VecFieldUseKind::Free(ex.span, data.span));
+                    self.visit_expr(base);
+                    return;
+                }
+            }
+
+            ExprKind::Assign(lhs, rhs, _) => {
+                self.check_by_value(rhs);
+                if let Some((did, role, base)) = self.vec_field(lhs) {
+                    let kind = match (role, lhs.kind) {
+                        (FieldRole::Data, _) if self.m.is_null(rhs) => {
+                            Some(VecFieldUseKind::DataReset(ex.span, lhs.span))
+                        }
+                        (FieldRole::Len, ExprKind::Field(_, ident)) if self.m.is_zero(rhs) => {
+                            Some(VecFieldUseKind::LenReset(ex.span, lhs.span, ident.span))
+                        }
+                        (FieldRole::Cap, _) if self.m.is_zero(rhs) => {
+                            Some(VecFieldUseKind::CapReset(ex.span))
+                        }
+                        _ => None,
+                    };
+                    match kind {
+                        Some(kind) => self.add_use(did, kind),
+                        None => {
+                            self.rejected.insert(did);
+                        }
+                    }
+                    self.visit_expr(base);
+                    return;
+                }
+            }
+
+            ExprKind::Struct(_, fields, base) => {
+                if let TyKind::Adt(adt_def, _) = *self.m.typeck_results.expr_ty(ex).kind() {
+                    let did = adt_def.did();
+                    if let Some(vs_fields) = self.structs.get(&did).map(|vs| vs.fields) {
+                        if base.is_some() {
+                            self.rejected.insert(did);
+                        }
+                        for field in fields {
+                            self.check_by_value(field.expr);
+                            let name = field.ident.name;
+                            if name == vs_fields.data {
+                                if self.m.is_null(field.expr) {
+                                    self.add_use(did, VecFieldUseKind::DataInit(field.expr.span));
+                                    continue;
+                                }
+                                self.rejected.insert(did);
+                            } else if name == vs_fields.len || name == vs_fields.cap {
+                                if self.m.is_zero(field.expr) {
+                                    self.add_use(did, VecFieldUseKind::InitField(field.span));
+                                    continue;
+                                }
+                                self.rejected.insert(did);
+                            }
+                            self.visit_expr(field.expr);
+                        }
+                        if let Some(base) = base {
+                            self.visit_expr(base);
+                        }
+                        return;
+                    }
+                }
+            }
+
+            ExprKind::Field(_, ident) => {
+                if let Some((did, role, base)) = self.vec_field(ex) {
+                    match role {
+                        FieldRole::Len => {
+                            self.add_use(did, VecFieldUseKind::LenRead(ex.span, ident.span));
+                        }
+                        // The pointer and capacity can't be used directly.
+                        FieldRole::Data | FieldRole::Cap => {
+                            self.rejected.insert(did);
+                        }
+                    }
+                    self.visit_expr(base);
+                    return;
+                }
+            }
+
+            ExprKind::AssignOp(_, lhs, _) | ExprKind::AddrOf(_, _, lhs) => {
+                if let Some((did, _, _)) = self.vec_field(lhs) {
+                    self.rejected.insert(did);
+                }
+            }
+
+            ExprKind::Call(_, args) | ExprKind::MethodCall(_, [_, args @ ..], _) => {
+                for arg in args {
+                    self.check_by_value(arg);
+                }
+            }
+
+            ExprKind::Ret(Some(e)) | ExprKind::Block(&Block { expr: Some(e), .. }, _) => {
+                self.check_by_value(e)
+            }
+
+            _ => {}
+        }
+
+        intravisit::walk_expr(self, ex);
+    }
+}
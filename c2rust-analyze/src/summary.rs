@@ -0,0 +1,147 @@
+//! Permission summaries of exported functions, shared between the crates of a workspace.
+//!
+//! When `--summary-dir` is set, the analysis of each crate writes `<dir>/<crate>.json`, which
+//! maps the symbol name of each exported (`#[no_mangle]` or `#[export_name]`) function to the
+//! inferred permissions of the pointers in its signature.  Cargo builds the crates of a workspace
+//! in dependency order, so by the time a downstream crate is analyzed, the summaries of the
+//! crates it links against are already present.  Calls to `extern "C"` declarations whose symbol
+//! name has a summary are then treated like calls to [`KnownFn`]s, so the permissions required
+//! by the upstream definition (such as `WRITE` and `OFFSET_ADD` on a buffer argument that became
+//! `&mut [u8]`) propagate to the downstream callers.
+//!
+//! [`KnownFn`]: crate::known_fn::KnownFn
+
+use crate::assignment_file;
+use crate::context::{GlobalAnalysisCtxt, GlobalAssignment, LFnSig, PermissionSet};
+use crate::pointer_id::PointerId;
+use crate::util::PhantomLifetime;
+use log::warn;
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_middle::ty::{Instance, TyCtxt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+/// The summary of one exported function.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FnSummary {
+    /// The path of the function in its defining crate, for diagnostics only.
+    pub def_path: String,
+    /// The permissions of each pointer in the signature, in the order of
+    /// [`LFnSig::inputs_and_output`] and then of [`LabeledTy::iter`].
+    ///
+    /// [`LabeledTy::iter`]: crate::labeled_ty::LabeledTy::iter
+    pub perms: Vec<String>,
+}
+
+/// Iterate over the [`PointerId`]s in `fn_sig`, in the order used by [`FnSummary::perms`].
+fn sig_pointers<'a, 'tcx>(
+    fn_sig: &'a LFnSig<'tcx>,
+) -> impl Iterator<Item = PointerId> + PhantomLifetime<'tcx> + 'a {
+    fn_sig
+        .inputs_and_output()
+        .flat_map(|lty| lty.iter())
+        .map(|lty| lty.label)
+        .filter(|ptr| !ptr.is_none())
+}
+
+impl FnSummary {
+    /// Match the pointers of `fn_sig`, the signature of an `extern "C"` declaration, to the
+    /// permissions in this summary.  If the number of pointers differs, the declaration doesn't
+    /// match the definition, and we skip it with a warning.
+    pub fn ptr_perms<'a, 'tcx>(
+        &'a self,
+        symbol: &'a str,
+        fn_sig: &'a LFnSig<'tcx>,
+    ) -> impl Iterator<Item = (PointerId, PermissionSet)> + PhantomLifetime<'tcx> + 'a {
+        let ptrs = sig_pointers(fn_sig).collect::<Vec<_>>();
+        let matching = ptrs.len() == self.perms.len();
+        if !matching {
+            warn!(
+                "declared `extern \"C\" fn {}` does not match the summary of {} \
+                 in number of pointers: {} != {}",
+                symbol,
+                self.def_path,
+                ptrs.len(),
+                self.perms.len(),
+            );
+        }
+        ptrs.into_iter()
+            .zip(&self.perms)
+            .filter(move |_| matching)
+            .filter_map(
+                move |(ptr, perms)| match assignment_file::parse_perms(perms) {
+                    Ok(perms) => Some((ptr, perms)),
+                    Err(e) => {
+                        warn!("bad permissions in the summary of {}: {}", self.def_path, e);
+                        None
+                    }
+                },
+            )
+    }
+}
+
+fn summary_file_name(tcx: TyCtxt) -> String {
+    format!("{}.json", tcx.crate_name(LOCAL_CRATE))
+}
+
+/// Read the summaries of all crates in `dir` other than the current one, keyed by symbol name.
+/// A missing directory is treated as empty, as when analyzing the first crate of a workspace.
+pub fn read_summaries(tcx: TyCtxt, dir: &Path) -> io::Result<HashMap<String, FnSummary>> {
+    let own_file = summary_file_name(tcx);
+    let mut summaries = HashMap::new();
+    if !dir.exists() {
+        return Ok(summaries);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        // Skip the summary left by a previous run on this crate, which may be stale.
+        if path.extension().map_or(true, |ext| ext != "json")
+            || path.file_name().map_or(false, |name| *name == *own_file)
+        {
+            continue;
+        }
+        let file = File::open(&path)?;
+        let crate_summaries: BTreeMap<String, FnSummary> = serde_json::from_reader(file)?;
+        summaries.extend(crate_summaries);
+    }
+    Ok(summaries)
+}
+
+/// Write the summaries of the exported functions of the current crate to
+/// `<dir>/<crate name>.json`.  Functions that won't be rewritten are skipped, so that their
+/// callers in other crates are left unconstrained.
+pub fn write_summaries<'tcx>(
+    dir: &Path,
+    tcx: TyCtxt<'tcx>,
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    gasn: &GlobalAssignment,
+) -> io::Result<()> {
+    let mut summaries = BTreeMap::new();
+    for (&def_id, fn_sig) in &gacx.fn_sigs {
+        if !def_id.is_local()
+            || gacx.dont_rewrite_fn(def_id)
+            || !tcx.codegen_fn_attrs(def_id).contains_extern_indicator()
+        {
+            continue;
+        }
+        let symbol = tcx.symbol_name(Instance::mono(tcx, def_id)).name.to_owned();
+        let perms = sig_pointers(fn_sig)
+            .map(|ptr| assignment_file::format_perms(gasn.perms[ptr]))
+            .collect();
+        summaries.insert(
+            symbol,
+            FnSummary {
+                def_path: tcx.def_path_str(def_id),
+                perms,
+            },
+        );
+    }
+
+    fs::create_dir_all(dir)?;
+    let file = File::create(dir.join(summary_file_name(tcx)))?;
+    serde_json::to_writer_pretty(file, &summaries)?;
+    Ok(())
+}
@@ -1,4 +1,5 @@
 use crate::context::{FlagSet, PermissionSet};
+use crate::util;
 use rustc_middle::mir::Mutability;
 use rustc_middle::ty::{AdtDef, Ty, TyCtxt, TyKind};
 
@@ -19,6 +20,13 @@ pub enum Ownership {
     Rc,
     /// E.g. `Box<T>`
     Box,
+    /// E.g. `Vec<T>`.  This is used only with `Quantity::Slice` or `Quantity::OffsetPtr`, and the
+    /// rewritten type has no separate slice, as in `Vec<T>` rather than `Vec<[T]>`.
+    Vec,
+    /// E.g. `Rc<RefCell<T>>`.  This is used only with `Quantity::Single`.
+    RcRefCell,
+    /// E.g. `Arc<Mutex<T>>`.  This is used only with `Quantity::Single`.
+    ArcMutex,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
@@ -29,12 +37,16 @@ pub enum Quantity {
     Slice,
     /// E.g. `OffsetPtr<T>`
     OffsetPtr,
+    /// A NUL-terminated string, such as `&CStr` or `CString`.  The pointee type is a character
+    /// type, which doesn't appear in the rewritten type.
+    CStr,
 
     /// E.g. `&[T; 10]`.  This is used only for existing `FIXED` pointers; `perms_to_desc` on a raw
     /// pointer never produces `Array`.
     Array,
 }
 
+/// Description of a safe or raw pointer type to `pointee_ty`, such as `Option<&mut [T]>`.
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub struct TypeDesc<'tcx> {
     pub own: Ownership,
@@ -93,11 +105,46 @@ impl PtrDesc {
     }
 }
 
+impl<'tcx> TypeDesc<'tcx> {
+    /// Build a `TypeDesc` for a pointer to `pointee_ty` that is neither `option` nor
+    /// `dyn_owned`, such as `&mut [T]` for `Mut` and `Slice`.
+    pub fn new(own: Ownership, qty: Quantity, pointee_ty: Ty<'tcx>) -> TypeDesc<'tcx> {
+        TypeDesc {
+            own,
+            qty,
+            dyn_owned: false,
+            option: false,
+            pointee_ty,
+        }
+    }
+
+    /// Wrap this type in `Option`.
+    pub fn with_option(self) -> TypeDesc<'tcx> {
+        TypeDesc {
+            option: true,
+            ..self
+        }
+    }
+
+    /// Wrap this type for dynamic ownership tracking.
+    pub fn with_dyn_owned(self) -> TypeDesc<'tcx> {
+        TypeDesc {
+            dyn_owned: true,
+            ..self
+        }
+    }
+}
+
 impl Ownership {
     pub fn is_copy(&self) -> bool {
         match *self {
             Ownership::Raw | Ownership::RawMut | Ownership::Imm | Ownership::Cell => true,
-            Ownership::Mut | Ownership::Rc | Ownership::Box => false,
+            Ownership::Mut
+            | Ownership::Rc
+            | Ownership::Box
+            | Ownership::Vec
+            | Ownership::RcRefCell
+            | Ownership::ArcMutex => false,
         }
     }
 }
@@ -105,9 +152,21 @@ impl Ownership {
 fn perms_to_ptr_desc(perms: PermissionSet, flags: FlagSet) -> PtrDesc {
     let mut dyn_owned = false;
 
-    let own = if perms.contains(PermissionSet::FREE) {
+    // `rc_refcell` and `arc_mutex` only give `REFCELL` and `MUTEX` to single-object pointers.
+    // Shared ownership is tracked by the `Rc` or `Arc`, so it's never `dyn_owned`.  An object
+    // shared with another thread needs the `Mutex` even if it's also aliased locally.
+    let own = if flags.contains(FlagSet::MUTEX) {
+        Ownership::ArcMutex
+    } else if flags.contains(FlagSet::REFCELL) {
+        Ownership::RcRefCell
+    } else if perms.contains(PermissionSet::FREE) {
         dyn_owned = true;
-        Ownership::Box
+        // `realloc_vecs` only gives `VEC` to pointers that are offset, which become slices below.
+        if flags.contains(FlagSet::VEC) {
+            Ownership::Vec
+        } else {
+            Ownership::Box
+        }
     } else if perms.contains(PermissionSet::UNIQUE | PermissionSet::WRITE) {
         Ownership::Mut
     } else if flags.contains(FlagSet::CELL) {
@@ -118,7 +177,12 @@ fn perms_to_ptr_desc(perms: PermissionSet, flags: FlagSet) -> PtrDesc {
         Ownership::Imm
     };
 
-    let qty = if perms.contains(PermissionSet::OFFSET_SUB) {
+    // There's no string type for the other `Ownership`s, but `c_strings` never gives `C_STRING`
+    // to a pointer that's written through.
+    let qty = if flags.contains(FlagSet::C_STRING) && matches!(own, Ownership::Box | Ownership::Imm)
+    {
+        Quantity::CStr
+    } else if perms.contains(PermissionSet::OFFSET_SUB) {
         Quantity::OffsetPtr
     } else if perms.contains(PermissionSet::OFFSET_ADD) {
         Quantity::Slice
@@ -202,6 +266,12 @@ pub fn unpack_pointer_type<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>, pointee_ty: Ty
     let mut steps = Vec::new();
     let mut cur_ty = ty;
     while cur_ty != pointee_ty {
+        // A `#[repr(transparent)]` wrapper around a pointer is unpacked as the pointer itself.
+        if let Some(field) = util::transparent_ptr_field(tcx, cur_ty) {
+            cur_ty = tcx.type_of(field.did);
+            continue;
+        }
+
         let (step, new_ty) = match *cur_ty.kind() {
             TyKind::Ref(_, inner_ty, mutbl) => (Step::Ref(mutbl), inner_ty),
             TyKind::RawPtr(tm) => (Step::RawPtr(tm.mutbl), tm.ty),
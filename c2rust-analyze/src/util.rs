@@ -5,14 +5,16 @@ use rustc_const_eval::interpret::Scalar;
 use rustc_hir::def::DefKind;
 use rustc_hir::def_id::{DefId, LocalDefId, CRATE_DEF_INDEX};
 use rustc_middle::mir::{
-    Body, Constant, Field, Local, Mutability, Operand, PlaceElem, PlaceRef, ProjectionElem, Rvalue,
+    Body, Constant, Local, Mutability, Operand, PlaceElem, PlaceRef, ProjectionElem, Rvalue,
 };
 use rustc_middle::ty::{
-    self, AdtDef, DefIdTree, EarlyBinder, FnSig, GenericArg, List, Subst, SubstsRef, Ty, TyCtxt,
-    TyKind, UintTy,
+    self, AdtDef, DefIdTree, EarlyBinder, FieldDef, FnSig, GenericArg, List, Subst, SubstsRef, Ty,
+    TyCtxt, TyKind, UintTy,
 };
 use rustc_span::symbol::{sym, Symbol};
+use rustc_target::abi::VariantIdx;
 use rustc_type_ir::IntTy;
+use std::env;
 use std::fmt::Debug;
 
 #[derive(Debug)]
@@ -158,9 +160,12 @@ pub enum Callee<'tcx> {
         mutbl: Mutability,
     },
 
-    /// `<[T]>::as_ptr` and `<[T]>::as_mut_ptr` methods.  Also covers the array and str versions.
+    /// `<[T]>::as_ptr` and `<[T]>::as_mut_ptr` methods.  Also covers the array and str versions,
+    /// along with the same methods on `Vec` and `CStr`.  `String` and `CString` reach these
+    /// through `Deref` to `str` and `CStr`.
     SliceAsPtr {
-        /// The pointee type.  This is either `TyKind::Slice`, `TyKind::Array`, or `TyKind::Str`.
+        /// The pointee type.  This is either `TyKind::Slice`, `TyKind::Array`, `TyKind::Str`, or
+        /// a container ADT (`Vec` or `CStr`).
         pointee_ty: Ty<'tcx>,
 
         /// The slice element type.  For `str`, this is `u8`.
@@ -168,6 +173,10 @@ pub enum Callee<'tcx> {
 
         /// Mutability of the output pointer.
         mutbl: Mutability,
+
+        /// For container ADTs, the method that views the contents as a slice, such as
+        /// `Vec::as_slice`.  `None` if the pointee is already a slice, array, or `str`.
+        slice_method: Option<&'static str>,
     },
 
     /// libc::malloc
@@ -182,12 +191,45 @@ pub enum Callee<'tcx> {
     /// libc::memcpy
     Memcpy,
 
-    /// libc::free
+    /// libc::memmove
+    Memmove,
+
+    /// libc::memcmp
+    Memcmp,
+
+    /// libc::memchr
+    Memchr,
+
+    /// libc::qsort
+    Qsort,
+
+    /// libc::bsearch
+    Bsearch,
+
+    /// libc::free, or a destructor replaced by a `Drop` impl with `--drop-impls`
     Free,
 
     /// libc::realloc
     Realloc,
 
+    /// libc::strdup
+    Strdup,
+
+    /// libc::strndup
+    Strndup,
+
+    /// libc::sprintf
+    Sprintf,
+
+    /// libc::snprintf
+    Snprintf,
+
+    /// libc::printf, recognized only with `--rewrite-printf`
+    Printf,
+
+    /// libc::fprintf, recognized only with `--rewrite-printf`
+    Fprintf,
+
     /// core::ptr::is_null
     IsNull,
 
@@ -196,6 +238,10 @@ pub enum Callee<'tcx> {
 
     /// `core::mem::size_of<T>`
     SizeOf { ty: Ty<'tcx> },
+
+    /// `Option::<fn(..)>::unwrap` or `Option::<fn(..)>::expect`, as used to call a nullable
+    /// function pointer, such as an entry of a dispatch table.
+    OptionFnPtrUnwrap,
 }
 
 pub fn ty_callee<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Callee<'tcx> {
@@ -209,6 +255,10 @@ pub fn ty_callee<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Callee<'tcx> {
         ty::FnDef(def_id, substs) => {
             if let Some(callee) = builtin_callee(tcx, def_id, substs) {
                 callee
+            } else if crate::rewrite::is_drop_fn(def_id) {
+                // With `--drop-impls`, the destructor's work is done by the struct's `Drop` impl,
+                // so a call to it just frees its argument.
+                Callee::Free
             } else if is_trivial() {
                 Callee::Trivial
             } else {
@@ -237,6 +287,12 @@ pub fn ty_callee<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Callee<'tcx> {
     }
 }
 
+/// Whether `--rewrite-printf` is set.  Otherwise, `printf` and `fprintf` are treated like any
+/// other foreign function.
+pub fn rewrite_printf() -> bool {
+    env::var("C2RUST_ANALYZE_REWRITE_PRINTF").as_deref() == Ok("1")
+}
+
 fn builtin_callee<'tcx>(tcx: TyCtxt<'tcx>, did: DefId, substs: SubstsRef<'tcx>) -> Option<Callee> {
     let name = tcx.item_name(did);
 
@@ -259,7 +315,8 @@ fn builtin_callee<'tcx>(tcx: TyCtxt<'tcx>, did: DefId, substs: SubstsRef<'tcx>)
         }
 
         name @ "as_ptr" | name @ "as_mut_ptr" => {
-            // The `as_ptr` and `as_mut_ptr` inherent methods of `[T]`, `[T; n]`, and `str`.
+            // The `as_ptr` and `as_mut_ptr` inherent methods of `[T]`, `[T; n]`, `str`, `Vec<T>`,
+            // and `CStr`.
             let parent_did = tcx.parent(did);
             if tcx.def_kind(parent_did) != DefKind::Impl {
                 return None;
@@ -268,21 +325,42 @@ fn builtin_callee<'tcx>(tcx: TyCtxt<'tcx>, did: DefId, substs: SubstsRef<'tcx>)
                 return None;
             }
             let parent_impl_ty = EarlyBinder(tcx.type_of(parent_did)).subst(tcx, substs);
-            let elem_ty = match *parent_impl_ty.kind() {
-                TyKind::Array(ty, _) => ty,
-                TyKind::Slice(ty) => ty,
-                TyKind::Str => tcx.mk_mach_uint(UintTy::U8),
-                _ => return None,
-            };
             let mutbl = match name {
                 "as_ptr" => Mutability::Not,
                 "as_mut_ptr" => Mutability::Mut,
                 _ => unreachable!(),
             };
+            let (elem_ty, slice_method) = match *parent_impl_ty.kind() {
+                TyKind::Array(ty, _) => (ty, None),
+                TyKind::Slice(ty) => (ty, None),
+                TyKind::Str => (tcx.mk_mach_uint(UintTy::U8), None),
+                TyKind::Adt(adt_def, adt_substs)
+                    if tcx.is_diagnostic_item(sym::Vec, adt_def.did()) =>
+                {
+                    let method = match mutbl {
+                        Mutability::Not => "as_slice",
+                        Mutability::Mut => "as_mut_slice",
+                    };
+                    (adt_substs.type_at(0), Some(method))
+                }
+                TyKind::Adt(adt_def, _) if is_std_adt(tcx, adt_def, "CStr") => {
+                    // `CStr::as_ptr` returns `*const c_char`, but the only safe view of the
+                    // contents is `to_bytes_with_nul(): &[u8]`.  On targets where `c_char` is
+                    // signed, there's no safe equivalent, so we leave the call alone.
+                    let elem_ty = tcx.mk_mach_uint(UintTy::U8);
+                    let out_ty = tcx.fn_sig(did).skip_binder().output();
+                    if !matches!(*out_ty.kind(), TyKind::RawPtr(tm) if tm.ty == elem_ty) {
+                        return None;
+                    }
+                    (elem_ty, Some("to_bytes_with_nul"))
+                }
+                _ => return None,
+            };
             Some(Callee::SliceAsPtr {
                 pointee_ty: parent_impl_ty,
                 elem_ty,
                 mutbl,
+                slice_method,
             })
         }
 
@@ -314,6 +392,45 @@ fn builtin_callee<'tcx>(tcx: TyCtxt<'tcx>, did: DefId, substs: SubstsRef<'tcx>)
             None
         }
 
+        "strdup" => {
+            if matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
+                return Some(Callee::Strdup);
+            }
+            None
+        }
+
+        "strndup" => {
+            if matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
+                return Some(Callee::Strndup);
+            }
+            None
+        }
+
+        "sprintf" => {
+            if matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
+                return Some(Callee::Sprintf);
+            }
+            None
+        }
+
+        "snprintf" => {
+            if matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
+                return Some(Callee::Snprintf);
+            }
+            None
+        }
+
+        name @ "printf" | name @ "fprintf" => {
+            if rewrite_printf() && matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
+                return Some(if name == "printf" {
+                    Callee::Printf
+                } else {
+                    Callee::Fprintf
+                });
+            }
+            None
+        }
+
         "memset" => {
             if matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
                 return Some(Callee::Memset);
@@ -327,6 +444,36 @@ fn builtin_callee<'tcx>(tcx: TyCtxt<'tcx>, did: DefId, substs: SubstsRef<'tcx>)
             }
             None
         }
+        "memmove" => {
+            if matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
+                return Some(Callee::Memmove);
+            }
+            None
+        }
+        "memcmp" => {
+            if matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
+                return Some(Callee::Memcmp);
+            }
+            None
+        }
+        "memchr" => {
+            if matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
+                return Some(Callee::Memchr);
+            }
+            None
+        }
+        "qsort" => {
+            if matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
+                return Some(Callee::Qsort);
+            }
+            None
+        }
+        "bsearch" => {
+            if matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
+                return Some(Callee::Bsearch);
+            }
+            None
+        }
 
         "is_null" => {
             // The `offset` inherent method of `*const T` and `*mut T`.
@@ -389,6 +536,27 @@ fn builtin_callee<'tcx>(tcx: TyCtxt<'tcx>, did: DefId, substs: SubstsRef<'tcx>)
             Some(Callee::SizeOf { ty })
         }
 
+        "unwrap" | "expect" => {
+            // The `unwrap` and `expect` inherent methods of `Option<fn(..)>`.
+            let parent_did = tcx.parent(did);
+            if tcx.def_kind(parent_did) != DefKind::Impl {
+                return None;
+            }
+            if tcx.impl_trait_ref(parent_did).is_some() {
+                return None;
+            }
+            let parent_impl_ty = EarlyBinder(tcx.type_of(parent_did)).subst(tcx, substs);
+            match *parent_impl_ty.kind() {
+                TyKind::Adt(adt_def, substs)
+                    if tcx.is_diagnostic_item(sym::Option, adt_def.did())
+                        && substs.type_at(0).is_fn_ptr() =>
+                {
+                    Some(Callee::OptionFnPtrUnwrap)
+                }
+                _ => None,
+            }
+        }
+
         _ => {
             eprintln!("name: {name:?}");
             None
@@ -396,10 +564,62 @@ fn builtin_callee<'tcx>(tcx: TyCtxt<'tcx>, did: DefId, substs: SubstsRef<'tcx>)
     }
 }
 
+/// Returns `true` if `adt_def` is the standard library type named `name`, such as `CStr`.
+fn is_std_adt(tcx: TyCtxt, adt_def: AdtDef, name: &str) -> bool {
+    let did = adt_def.did();
+    matches!(tcx.crate_name(did.krate).as_str(), "core" | "alloc" | "std")
+        && tcx.item_name(did).as_str() == name
+}
+
+/// Whether `ty` is a C character type, so that a pointer to it can point to a string.
+pub fn is_c_char(ty: Ty) -> bool {
+    matches!(ty.kind(), TyKind::Int(IntTy::I8) | TyKind::Uint(UintTy::U8))
+}
+
+/// Whether `ty` is `c_void`, the pointee type of untyped pointers such as the argument of `free`.
+pub fn is_c_void(tcx: TyCtxt, ty: Ty) -> bool {
+    match *ty.kind() {
+        TyKind::Adt(adt_def, _) => is_std_adt(tcx, adt_def, "c_void"),
+        _ => false,
+    }
+}
+
+/// If `ty` is a local, non-generic `#[repr(transparent)]` struct wrapping a raw pointer, such as
+/// `struct Handle(*mut Thing)`, return the pointer field.  Other fields must be `PhantomData`.
+///
+/// The wrapper itself has no `PointerId`, so the analysis treats the `PointerId` of this field as
+/// the wrapper's.
+pub fn transparent_ptr_field<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<&'tcx FieldDef> {
+    let adt_def = match *ty.kind() {
+        TyKind::Adt(adt_def, substs)
+            if adt_def.did().is_local()
+                && adt_def.is_struct()
+                && adt_def.repr().transparent()
+                && substs.is_empty() =>
+        {
+            adt_def
+        }
+        _ => return None,
+    };
+    let mut fields = adt_def
+        .all_fields()
+        .filter(|field| !tcx.type_of(field.did).is_phantom_data());
+    let field = fields.next()?;
+    if fields.next().is_some() || !tcx.type_of(field.did).is_unsafe_ptr() {
+        return None;
+    }
+    Some(field)
+}
+
+/// Apply the projection `proj` to `lty`.  `variant` is the enum variant selected by the
+/// preceding `Downcast` projection, if any (see [`downcast_variant`]); it's used to find the
+/// field accessed by a `Field` projection on an enum.  `field_lty` is called to compute the
+/// `LabeledTy` of an ADT field.
 pub fn lty_project<'tcx, L: Debug>(
     lty: LabeledTy<'tcx, L>,
     proj: &PlaceElem<'tcx>,
-    mut field_lty: impl FnMut(LabeledTy<'tcx, L>, AdtDef<'tcx>, Field) -> LabeledTy<'tcx, L>,
+    variant: Option<VariantIdx>,
+    mut field_lty: impl FnMut(LabeledTy<'tcx, L>, AdtDef<'tcx>, &'tcx FieldDef) -> LabeledTy<'tcx, L>,
 ) -> LabeledTy<'tcx, L> {
     match *proj {
         ProjectionElem::Deref => {
@@ -409,7 +629,13 @@ pub fn lty_project<'tcx, L: Debug>(
         }
         ProjectionElem::Field(f, _) => match lty.kind() {
             TyKind::Tuple(_) => lty.args[f.index()],
-            TyKind::Adt(def, _) => field_lty(lty, *def, f),
+            TyKind::Adt(def, _) => {
+                let variant_def = match variant {
+                    Some(v) => def.variant(v),
+                    None => def.non_enum_variant(),
+                };
+                field_lty(lty, *def, &variant_def.fields[f.index()])
+            }
             _ => panic!("Field projection is unsupported on type {:?}", lty),
         },
         ProjectionElem::Index(..) | ProjectionElem::ConstantIndex { .. } => {
@@ -418,7 +644,21 @@ pub fn lty_project<'tcx, L: Debug>(
             lty.args[0]
         }
         ProjectionElem::Subslice { .. } => todo!("type_of Subslice"),
-        ProjectionElem::Downcast(..) => todo!("type_of Downcast"),
+        // The downcast place has the same type as the enum.  The selected variant is tracked by
+        // the caller and passed back in when projecting the following `Field`.
+        ProjectionElem::Downcast(..) => {
+            assert!(matches!(lty.kind(), TyKind::Adt(def, _) if def.is_enum()));
+            lty
+        }
+    }
+}
+
+/// Returns the enum variant selected by `proj`, if it is a `Downcast`.  Callers that apply
+/// projections one at a time pass this to the next call to [`lty_project`].
+pub fn downcast_variant(proj: &PlaceElem) -> Option<VariantIdx> {
+    match *proj {
+        ProjectionElem::Downcast(_, v) => Some(v),
+        _ => None,
     }
 }
 
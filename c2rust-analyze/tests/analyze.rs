@@ -6,7 +6,7 @@ use crate::common::Analyze;
 use crate::common::CrateOptions;
 use crate::common::CrateType;
 use fs_err::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 #[test]
@@ -43,6 +43,243 @@ define_tests! {
     string_casts,
 }
 
+#[test]
+fn assignment_dump_load() {
+    let analyze = Analyze::resolve();
+    let path = test_dir_for(file!(), true).join("assignment_dump_load.rs");
+    let dump_path = path.with_extension("rs.assignment.txt");
+    let reload_path = path.with_extension("rs.reloaded.txt");
+
+    analyze.run_with(
+        &path,
+        |cmd| {
+            cmd.env("C2RUST_ANALYZE_DUMP_ASSIGNMENT", &dump_path);
+        },
+        None,
+    );
+    let dumped = fs_err::read_to_string(&dump_path).unwrap();
+    assert!(dumped.contains("WRITE"), "no WRITE permission in:\n{dumped}");
+
+    // Loading the dumped assignment and dumping it again should produce the same file.
+    analyze.run_with(
+        &path,
+        |cmd| {
+            cmd.env("C2RUST_ANALYZE_LOAD_ASSIGNMENT", &dump_path)
+                .env("C2RUST_ANALYZE_DUMP_ASSIGNMENT", &reload_path);
+        },
+        None,
+    );
+    let reloaded = fs_err::read_to_string(&reload_path).unwrap();
+    assert_eq!(dumped, reloaded);
+}
+
+#[test]
+fn assignment_load_error() {
+    let analyze = Analyze::resolve();
+    let path = test_dir_for(file!(), true).join("assignment_load_error.rs");
+    let dump_path = path.with_extension("rs.assignment.txt");
+
+    analyze.run_with(
+        &path,
+        |cmd| {
+            cmd.env("C2RUST_ANALYZE_DUMP_ASSIGNMENT", &dump_path);
+        },
+        None,
+    );
+    let dumped = fs_err::read_to_string(&dump_path).unwrap();
+
+    // Claim one more local pointer than each function has, as if the file were stale.
+    let add_pointer = |line: &str| match line.strip_prefix("fn ").and_then(|l| l.split_once(' ')) {
+        Some((count, def_id)) => format!("fn {} {def_id}", count.parse::<usize>().unwrap() + 1),
+        None => line.to_owned(),
+    };
+    let edited = dumped
+        .lines()
+        .map(add_pointer)
+        .collect::<Vec<_>>()
+        .join("\n");
+    fs_err::write(&dump_path, edited).unwrap();
+
+    // The file is rejected with an error instead of a panic.
+    let output_path = analyze.run_with(
+        &path,
+        |cmd| {
+            cmd.env("C2RUST_ANALYZE_LOAD_ASSIGNMENT", &dump_path);
+        },
+        None,
+    );
+    let output = fs_err::read_to_string(output_path).unwrap();
+    assert!(
+        output.contains("error: failed to load assignment file")
+            && output.contains("was it generated from a different version of the input?"),
+        "expected an assignment file error in:\n{output}"
+    );
+    assert!(!output.contains("panicked"), "{output}");
+}
+
+/// Analyze `summary/<crate_name>.rs`, sharing permission summaries through `summary_dir`, and
+/// return the analysis output and the `--dump-local-perms` report.
+fn run_with_summaries(
+    analyze: &Analyze,
+    summary_dir: &Path,
+    crate_name: &str,
+) -> (String, Vec<serde_json::Value>) {
+    let path = test_dir_for(file!(), true)
+        .join("summary")
+        .join(format!("{crate_name}.rs"));
+    let perms_path = path.with_extension("rs.perms.json");
+    let output_path = analyze.run_with(
+        &path,
+        |cmd| {
+            cmd.env("C2RUST_ANALYZE_SUMMARY_DIR", summary_dir)
+                .env("C2RUST_ANALYZE_DUMP_LOCAL_PERMS", &perms_path);
+        },
+        None,
+    );
+    let output = fs_err::read_to_string(output_path).unwrap();
+    let report = fs_err::read_to_string(&perms_path).unwrap();
+    (output, serde_json::from_str(&report).unwrap())
+}
+
+/// The permissions of local `_<local>` of `function` in a `--dump-local-perms` report.
+fn perms_of<'a>(report: &'a [serde_json::Value], function: &str, local: u64) -> &'a str {
+    report
+        .iter()
+        .find(|entry| entry["function"] == function && entry["local"] == local)
+        .and_then(|entry| entry["perms"].as_str())
+        .unwrap_or_else(|| panic!("no local _{local} of {function} in {report:?}"))
+}
+
+/// An empty summary directory named `name`.
+fn fresh_summary_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    if dir.exists() {
+        fs_err::remove_dir_all(&dir).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn summary_two_crates() {
+    let analyze = Analyze::resolve();
+    let summary_dir = fresh_summary_dir("summary_two_crates");
+
+    // `fill` writes through and offsets `buf`, and its summary records that.
+    let (_, upstream) = run_with_summaries(&analyze, &summary_dir, "upstream");
+    let fill_perms = perms_of(&upstream, "fill", 1);
+    assert!(
+        fill_perms.contains("WRITE") && fill_perms.contains("OFFSET_ADD"),
+        "{fill_perms}"
+    );
+    let summary = fs_err::read_to_string(summary_dir.join("upstream.json")).unwrap();
+    assert!(summary.contains("\"fill\""), "{summary}");
+
+    // The caller in the downstream crate has to pass a buffer that allows the same.
+    let (_, downstream) = run_with_summaries(&analyze, &summary_dir, "downstream");
+    let clear_perms = perms_of(&downstream, "clear", 1);
+    assert!(
+        clear_perms.contains("WRITE") && clear_perms.contains("OFFSET_ADD"),
+        "{clear_perms}"
+    );
+}
+
+#[test]
+fn summary_missing_or_stale() {
+    let analyze = Analyze::resolve();
+    let summary_dir = fresh_summary_dir("summary_missing_or_stale");
+
+    // Without a summary for `fill`, as when the upstream crate wasn't analyzed, the call doesn't
+    // constrain `buf`.
+    let (_, downstream) = run_with_summaries(&analyze, &summary_dir, "downstream");
+    assert!(!perms_of(&downstream, "clear", 1).contains("WRITE"));
+
+    // A summary from an older version of `fill`, with a different number of pointers, is ignored
+    // with a warning.  So is one left in the downstream crate's own file by an earlier run.
+    fs_err::write(
+        summary_dir.join("upstream.json"),
+        r#"{"fill": {"def_path": "fill", "perms": ["WRITE", "WRITE"]}}"#,
+    )
+    .unwrap();
+    fs_err::write(
+        summary_dir.join("downstream.json"),
+        r#"{"fill": {"def_path": "fill", "perms": ["WRITE|OFFSET_ADD"]}}"#,
+    )
+    .unwrap();
+    let (output, downstream) = run_with_summaries(&analyze, &summary_dir, "downstream");
+    assert!(
+        output.contains("does not match the summary of fill in number of pointers: 1 != 2"),
+        "expected a summary mismatch warning in:\n{output}"
+    );
+    assert!(!perms_of(&downstream, "clear", 1).contains("WRITE"));
+}
+
+/// Analyze `profile.rs`, with `C2RUST_ANALYZE_PROFILE` set to `profile` if it's given, and return
+/// the analysis's stdout, its stderr, and the `--dump-local-perms` report.
+fn run_profile(analyze: &Analyze, profile: Option<&str>) -> (String, String, String) {
+    let path = test_dir_for(file!(), true).join("profile.rs");
+    let suffix = if profile.is_some() { "on" } else { "off" };
+    let stdout_path = path.with_extension(format!("rs.{suffix}.stdout.txt"));
+    let stderr_path = path.with_extension(format!("rs.{suffix}.stderr.txt"));
+    let perms_path = path.with_extension(format!("rs.{suffix}.perms.json"));
+    analyze.run_with(
+        &path,
+        |cmd| {
+            cmd.stdout(File::create(&stdout_path).unwrap().into_parts().0)
+                .stderr(File::create(&stderr_path).unwrap().into_parts().0)
+                .env("C2RUST_ANALYZE_DUMP_LOCAL_PERMS", &perms_path)
+                .env_remove("C2RUST_ANALYZE_PROFILE");
+            if let Some(profile) = profile {
+                cmd.env("C2RUST_ANALYZE_PROFILE", profile);
+            }
+        },
+        None,
+    );
+    let read = |path: &Path| fs_err::read_to_string(path).unwrap();
+    (read(&stdout_path), read(&stderr_path), read(&perms_path))
+}
+
+#[test]
+fn profile() {
+    let analyze = Analyze::resolve();
+    let (stdout, stderr, perms) = run_profile(&analyze, None);
+    assert!(!stderr.contains("=== profile ==="), "{stderr}");
+
+    let (profiled_stdout, profiled_stderr, profiled_perms) = run_profile(&analyze, Some("2"));
+
+    // The report goes to stderr, and profiling doesn't change the results of the analysis or the
+    // rewritten code, which is printed to stdout.
+    assert!(
+        !profiled_stdout.contains("=== profile ==="),
+        "{profiled_stdout}"
+    );
+    assert_eq!(stdout, profiled_stdout);
+    assert_eq!(perms, profiled_perms);
+
+    let report = profiled_stderr
+        .split_once("=== profile ===\n")
+        .unwrap_or_else(|| panic!("no profile report in:\n{profiled_stderr}"))
+        .1;
+    let lines = report.lines().collect::<Vec<_>>();
+    assert!(lines[0].starts_with("pass "), "{report}");
+    let passes = [
+        "pointee types",
+        "dataflow",
+        "borrowck",
+        "mir_op",
+        "HIR rewrite",
+    ];
+    for (line, pass) in lines[1..].iter().zip(passes) {
+        assert!(line.starts_with(pass), "expected {pass:?} in:\n{report}");
+    }
+    assert!(lines[6].starts_with("total "), "{report}");
+    // Only the 2 slowest of the 3 functions are listed.
+    assert_eq!(lines[8], "slowest 2 of 3 functions:", "{report}");
+    for line in &lines[9..11] {
+        let is_fn = |f: &str| line.contains(&format!(" ms  {f}  ("));
+        assert!(is_fn("set") || is_fn("set_all") || is_fn("get"), "{report}");
+    }
+}
+
 #[test]
 fn lighttpd_minimal() {
     let analyze = Analyze::resolve();
@@ -70,7 +307,6 @@ fn lighttpd_minimal() {
 
 #[test]
 fn with_pdg_file() {
-    use std::path::PathBuf;
     let pdg_path: PathBuf = "../analysis/tests/minimal/reference_pdg.bc".into();
     println!("{:?}", std::env::current_dir());
     let pdg_path = pdg_path.canonicalize().unwrap();
@@ -0,0 +1,3 @@
+pub unsafe fn set(p: *mut i32) {
+    *p = 1;
+}
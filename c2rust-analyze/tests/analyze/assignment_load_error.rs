@@ -0,0 +1,5 @@
+//! --allow-crash
+
+pub unsafe fn set(p: *mut i32) {
+    *p = 1;
+}
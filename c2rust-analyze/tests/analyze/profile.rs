@@ -0,0 +1,13 @@
+pub unsafe fn set(p: *mut i32) {
+    *p = 1;
+}
+
+pub unsafe fn set_all(p: *mut i32, n: usize) {
+    for i in 0..n {
+        set(p.add(i));
+    }
+}
+
+pub unsafe fn get(p: *const i32) -> i32 {
+    *p
+}
@@ -0,0 +1,10 @@
+//! The downstream crate of the `summary_*` tests, which calls `fill` from `upstream.rs` through an
+//! `extern "C"` declaration.
+
+extern "C" {
+    fn fill(buf: *mut u8, len: usize);
+}
+
+pub unsafe fn clear(buf: *mut u8, len: usize) {
+    fill(buf, len);
+}
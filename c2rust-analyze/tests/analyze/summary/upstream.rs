@@ -0,0 +1,9 @@
+//! The upstream crate of the `summary_*` tests, which exports a function that needs a writable,
+//! offsettable buffer.
+
+#[no_mangle]
+pub unsafe extern "C" fn fill(buf: *mut u8, len: usize) {
+    for i in 0..len {
+        *buf.add(i) = 0;
+    }
+}
@@ -0,0 +1,35 @@
+use c2rust_analyze::api::{analyze_crate, PermissionSet, RewriteKind};
+use std::path::Path;
+
+#[test]
+fn analyze_crate_reports_results() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib_dir = env!("C2RUST_TARGET_LIB_DIR");
+    let rs_path = dir.join("tests/api/basic.rs");
+    let args = ["-L", lib_dir, "--crate-type", "rlib", "--edition", "2021"].map(String::from);
+    let results = analyze_crate(&rs_path, &args).unwrap();
+
+    let names = results
+        .fns
+        .iter()
+        .map(|f| f.def_path.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["write_one", "read_second"]);
+
+    let write_one = &results.fns[0];
+    assert!(write_one.skip_reasons.is_empty());
+    assert!(write_one.failure.is_none());
+    let arg = write_one.pointers.iter().find(|p| p.local == 1).unwrap();
+    assert!(arg.perms.contains(PermissionSet::WRITE));
+    assert!(!arg.perms.contains(PermissionSet::OFFSET_ADD));
+
+    let read_second = &results.fns[1];
+    let arg = read_second.pointers.iter().find(|p| p.local == 1).unwrap();
+    assert!(arg
+        .perms
+        .contains(PermissionSet::READ | PermissionSet::OFFSET_ADD));
+    assert!(read_second
+        .mir_rewrites
+        .iter()
+        .any(|(_, rw)| matches!(rw.kind, RewriteKind::OffsetSlice { .. })));
+}
@@ -0,0 +1,7 @@
+pub unsafe fn write_one(p: *mut i32) {
+    *p = 1;
+}
+
+pub unsafe fn read_second(p: *const i32) -> i32 {
+    *p.offset(1)
+}
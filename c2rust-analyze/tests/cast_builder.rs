@@ -0,0 +1,658 @@
+#![feature(rustc_private)]
+extern crate rustc_driver;
+extern crate rustc_interface;
+extern crate rustc_middle;
+
+use c2rust_analyze::api::{build_cast, Ownership, Quantity, RewriteKind, TypeDesc};
+use c2rust_analyze::resolve_sysroot;
+use rustc_driver::{Callbacks, Compilation, RunCompiler};
+use rustc_interface::interface::Compiler;
+use rustc_interface::Queries;
+use rustc_middle::ty::{Ty, TyCtxt};
+use std::path::Path;
+
+use Ownership::*;
+use Quantity::*;
+
+struct TcxCallbacks<F>(Option<F>);
+
+impl<F: FnOnce(TyCtxt) + Send> Callbacks for TcxCallbacks<F> {
+    fn after_expansion<'tcx>(
+        &mut self,
+        _compiler: &Compiler,
+        queries: &'tcx Queries<'tcx>,
+    ) -> Compilation {
+        let f = self.0.take().unwrap();
+        queries.global_ctxt().unwrap().peek_mut().enter(f);
+        Compilation::Stop
+    }
+}
+
+/// Run `f` with the `TyCtxt` of a small crate.
+fn with_tcx(f: impl FnOnce(TyCtxt) + Send) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let rs_path = dir.join("tests/api/basic.rs");
+    let sysroot = resolve_sysroot().unwrap();
+    let args = [
+        "rustc",
+        rs_path.to_str().unwrap(),
+        "--crate-type",
+        "rlib",
+        "--edition",
+        "2021",
+        "--sysroot",
+        sysroot.to_str().unwrap(),
+    ]
+    .map(String::from);
+    RunCompiler::new(&args, &mut TcxCallbacks(Some(f)))
+        .run()
+        .unwrap();
+}
+
+#[test]
+fn cast_paths() {
+    with_tcx(|tcx| {
+        let t = tcx.types.i32;
+        let desc = |own, qty| TypeDesc::new(own, qty, t);
+        let cast = |from, to| build_cast(tcx, from, to);
+
+        // Identity
+        assert_eq!(cast(desc(Mut, Single), desc(Mut, Single)), Ok(vec![]));
+
+        // Ownership casts
+        assert_eq!(
+            cast(desc(Box, Single), desc(Imm, Single)),
+            Ok(vec![RewriteKind::Reborrow { mutbl: false }]),
+        );
+        assert_eq!(
+            cast(desc(Box, Single), desc(Mut, Single)),
+            Ok(vec![RewriteKind::Reborrow { mutbl: true }]),
+        );
+        assert_eq!(
+            cast(desc(Mut, Single), desc(Imm, Single)),
+            Ok(vec![RewriteKind::Reborrow { mutbl: false }]),
+        );
+        assert_eq!(
+            cast(desc(Mut, Single), desc(Cell, Single)),
+            Ok(vec![RewriteKind::CellFromMut]),
+        );
+        assert_eq!(
+            cast(desc(Mut, Single), desc(RawMut, Single)),
+            Ok(vec![RewriteKind::CastRefToRaw { mutbl: true }]),
+        );
+        assert_eq!(
+            cast(desc(Imm, Single), desc(Raw, Single)),
+            Ok(vec![RewriteKind::CastRefToRaw { mutbl: false }]),
+        );
+        assert_eq!(
+            cast(desc(Cell, Single), desc(RawMut, Single)),
+            Ok(vec![RewriteKind::AsPtr]),
+        );
+        assert_eq!(
+            cast(desc(RawMut, Single), desc(Raw, Single)),
+            Ok(vec![RewriteKind::CastRawToRaw { to_mutbl: false }]),
+        );
+        assert_eq!(
+            cast(desc(RawMut, Single), desc(Mut, Single)),
+            Ok(vec![RewriteKind::UnsafeCastRawToRef { mutbl: true }]),
+        );
+        assert_eq!(
+            cast(desc(RawMut, Single), desc(Imm, Single)),
+            Ok(vec![
+                RewriteKind::CastRawToRaw { to_mutbl: false },
+                RewriteKind::UnsafeCastRawToRef { mutbl: false },
+            ]),
+        );
+        assert_eq!(
+            cast(desc(RawMut, Single), desc(Cell, Single)),
+            Ok(vec![
+                RewriteKind::CastRawMutToCellPtr { ty: "i32".into() },
+                RewriteKind::UnsafeCastRawToRef { mutbl: false },
+            ]),
+        );
+        assert_eq!(
+            cast(desc(Raw, Single), desc(RawMut, Single)),
+            Ok(vec![RewriteKind::CastRawToRaw { to_mutbl: true }]),
+        );
+        assert_eq!(
+            cast(desc(Raw, Single), desc(Imm, Single)),
+            Ok(vec![RewriteKind::UnsafeCastRawToRef { mutbl: false }]),
+        );
+        assert_eq!(
+            cast(desc(Raw, Single), desc(Mut, Single)),
+            Ok(vec![
+                RewriteKind::CastRawToRaw { to_mutbl: true },
+                RewriteKind::UnsafeCastRawToRef { mutbl: true },
+            ]),
+        );
+
+        // Quantity casts
+        assert_eq!(
+            cast(desc(Mut, Slice), desc(Mut, Single)),
+            Ok(vec![RewriteKind::SliceFirst { mutbl: true }]),
+        );
+        assert_eq!(
+            cast(desc(Box, Slice), desc(Imm, Single)),
+            Ok(vec![
+                RewriteKind::Reborrow { mutbl: false },
+                RewriteKind::SliceFirst { mutbl: false },
+            ]),
+        );
+        assert_eq!(cast(desc(Imm, Slice), desc(Imm, OffsetPtr)), Ok(vec![]));
+        assert_eq!(cast(desc(Imm, OffsetPtr), desc(Imm, Slice)), Ok(vec![]));
+
+        // `Option` casts
+        assert_eq!(
+            cast(desc(Mut, Single).with_option(), desc(Mut, Single)),
+            Ok(vec![RewriteKind::OptionUnwrap]),
+        );
+        assert_eq!(
+            cast(desc(Mut, Single), desc(Mut, Single).with_option()),
+            Ok(vec![RewriteKind::OptionSome]),
+        );
+        assert_eq!(
+            cast(desc(Mut, Single).with_option(), desc(Imm, Single)),
+            Ok(vec![
+                RewriteKind::OptionDowngrade {
+                    mutbl: false,
+                    deref: true,
+                },
+                RewriteKind::OptionUnwrap,
+            ]),
+        );
+        assert_eq!(
+            cast(desc(Box, Single).with_option(), desc(Mut, Single)),
+            Ok(vec![
+                RewriteKind::OptionDowngrade {
+                    mutbl: true,
+                    deref: true,
+                },
+                RewriteKind::OptionUnwrap,
+            ]),
+        );
+        assert_eq!(
+            cast(
+                desc(Mut, Single).with_option(),
+                desc(Imm, Single).with_option(),
+            ),
+            Ok(vec![
+                RewriteKind::OptionDowngrade {
+                    mutbl: false,
+                    deref: true,
+                },
+                RewriteKind::OptionMapBegin,
+                RewriteKind::OptionMapEnd,
+            ]),
+        );
+        assert_eq!(
+            cast(
+                desc(Imm, Slice).with_option(),
+                desc(Imm, Single).with_option(),
+            ),
+            Ok(vec![
+                RewriteKind::OptionMapBegin,
+                RewriteKind::SliceFirst { mutbl: false },
+                RewriteKind::OptionMapEnd,
+            ]),
+        );
+
+        // Dynamic ownership casts
+        assert_eq!(
+            cast(desc(Box, Single).with_dyn_owned(), desc(Imm, Single)),
+            Ok(vec![
+                RewriteKind::DynOwnedDowngrade { mutbl: false },
+                RewriteKind::Reborrow { mutbl: false },
+            ]),
+        );
+        assert_eq!(
+            cast(desc(Box, Single).with_dyn_owned(), desc(Box, Single)),
+            Ok(vec![RewriteKind::DynOwnedUnwrap]),
+        );
+        assert_eq!(
+            cast(desc(Box, Single), desc(Box, Single).with_dyn_owned()),
+            Ok(vec![RewriteKind::DynOwnedWrap]),
+        );
+
+        // `Rc` casts
+        assert_eq!(
+            cast(desc(Rc, Single), desc(Imm, Single)),
+            Ok(vec![RewriteKind::Reborrow { mutbl: false }]),
+        );
+        assert_eq!(
+            cast(desc(Rc, Single), desc(Raw, Single)),
+            Ok(vec![
+                RewriteKind::Reborrow { mutbl: false },
+                RewriteKind::CastRefToRaw { mutbl: false },
+            ]),
+        );
+        assert_eq!(
+            cast(desc(Rc, Single).with_option(), desc(Rc, Single)),
+            Ok(vec![RewriteKind::Clone, RewriteKind::OptionUnwrap]),
+        );
+
+        // `Vec` casts
+        assert_eq!(
+            cast(desc(Box, Slice), desc(Vec, Slice)),
+            Ok(vec![RewriteKind::VecFromBox]),
+        );
+        assert_eq!(
+            cast(desc(Vec, Slice), desc(Box, Slice)),
+            Ok(vec![RewriteKind::VecIntoBoxedSlice]),
+        );
+        assert_eq!(
+            cast(desc(Vec, Slice).with_dyn_owned(), desc(Mut, Slice)),
+            Ok(vec![
+                RewriteKind::DynOwnedDowngrade { mutbl: true },
+                RewriteKind::Reborrow { mutbl: true },
+            ]),
+        );
+
+        // `Rc<RefCell<T>>` casts
+        assert_eq!(
+            cast(desc(Box, Single), desc(RcRefCell, Single)),
+            Ok(vec![RewriteKind::RcRefCellFromBox]),
+        );
+        assert_eq!(
+            cast(desc(RcRefCell, Single), desc(Mut, Single)),
+            Ok(vec![RewriteKind::RefCellBorrow { mutbl: true }]),
+        );
+        assert_eq!(
+            cast(desc(RcRefCell, Single).with_option(), desc(Imm, Single)),
+            Ok(vec![
+                RewriteKind::OptionDowngrade {
+                    mutbl: false,
+                    deref: false,
+                },
+                RewriteKind::OptionUnwrap,
+                RewriteKind::RefCellBorrow { mutbl: false },
+            ]),
+        );
+        assert_eq!(
+            cast(
+                desc(RcRefCell, Single).with_option(),
+                desc(RcRefCell, Single)
+            ),
+            Ok(vec![RewriteKind::Clone, RewriteKind::OptionUnwrap]),
+        );
+
+        // `Arc<Mutex<T>>` casts
+        assert_eq!(
+            cast(desc(Box, Single), desc(ArcMutex, Single)),
+            Ok(vec![RewriteKind::ArcMutexFromBox]),
+        );
+        assert_eq!(
+            cast(desc(ArcMutex, Single), desc(Mut, Single)),
+            Ok(vec![RewriteKind::MutexLock { mutbl: true }]),
+        );
+        assert_eq!(
+            cast(desc(ArcMutex, Single).with_option(), desc(Imm, Single)),
+            Ok(vec![
+                RewriteKind::OptionDowngrade {
+                    mutbl: false,
+                    deref: false,
+                },
+                RewriteKind::OptionUnwrap,
+                RewriteKind::MutexLock { mutbl: false },
+            ]),
+        );
+
+        // Unsupported casts
+        assert!(cast(desc(Mut, Single), TypeDesc::new(Mut, Single, tcx.types.u8)).is_err());
+        assert!(cast(desc(Mut, Single), desc(Mut, Slice)).is_err());
+        assert!(cast(desc(Imm, Array), desc(Imm, Slice)).is_err());
+        assert!(cast(desc(Rc, Single), desc(Mut, Single)).is_err());
+        assert!(cast(desc(Box, Slice), desc(Box, Single)).is_err());
+        assert!(cast(desc(RcRefCell, Single), desc(Box, Single)).is_err());
+        assert!(cast(desc(ArcMutex, Single), desc(Box, Single)).is_err());
+        assert!(cast(desc(ArcMutex, Single), desc(RcRefCell, Single)).is_err());
+        assert!(cast(
+            desc(RcRefCell, Single).with_option(),
+            desc(Imm, Single).with_option()
+        )
+        .is_err());
+    });
+}
+
+const OWNERSHIPS: [Ownership; 10] = [
+    Raw, RawMut, Imm, Cell, Mut, Rc, Box, Vec, RcRefCell, ArcMutex,
+];
+const QUANTITIES: [Quantity; 4] = [Single, Slice, OffsetPtr, Array];
+
+/// Whether `own` and `qty` can describe a pointer.  `Vec` is only used for slices, and
+/// `RcRefCell` and `ArcMutex` only for single objects.
+fn valid_desc(own: Ownership, qty: Quantity) -> bool {
+    match own {
+        Vec => matches!(qty, Slice | OffsetPtr),
+        RcRefCell | ArcMutex => qty == Single,
+        _ => true,
+    }
+}
+
+/// All `TypeDesc`s for pointers to `pointee_ty`.  Only `Box` and `Vec` can be `dyn_owned`.
+fn all_descs(pointee_ty: Ty) -> std::vec::Vec<TypeDesc> {
+    let mut descs = std::vec::Vec::new();
+    for own in OWNERSHIPS {
+        for qty in QUANTITIES {
+            if !valid_desc(own, qty) {
+                continue;
+            }
+            for (option, dyn_owned) in [(false, false), (true, false), (false, true), (true, true)]
+            {
+                if dyn_owned && !matches!(own, Box | Vec) {
+                    continue;
+                }
+                descs.push(TypeDesc {
+                    option,
+                    dyn_owned,
+                    ..TypeDesc::new(own, qty, pointee_ty)
+                });
+            }
+        }
+    }
+    descs
+}
+
+/// The state of a value while simulating the rewrites of a cast.
+struct CastSim<'tcx> {
+    desc: TypeDesc<'tcx>,
+    /// Set by `CastRawMutToCellPtr`: the value is a `*const Cell<T>`, which `desc` can't
+    /// describe.  Its `desc.own` is `Raw`.
+    raw_cell: bool,
+    /// The number of `OptionMapBegin`s not yet closed by an `OptionMapEnd`.
+    map_depth: usize,
+}
+
+impl<'tcx> CastSim<'tcx> {
+    fn new(desc: TypeDesc<'tcx>) -> CastSim<'tcx> {
+        CastSim {
+            desc,
+            raw_cell: false,
+            map_depth: 0,
+        }
+    }
+
+    /// The value is a plain pointer, not wrapped in `Option` or `DynOwned`.
+    fn check_unwrapped(&self, rk: &RewriteKind) -> Result<(), String> {
+        if self.desc.option || self.desc.dyn_owned {
+            return Err(format!("{rk:?} applied to wrapped {:?}", self.desc));
+        }
+        Ok(())
+    }
+
+    fn check_own(&self, rk: &RewriteKind, allowed: &[Ownership]) -> Result<(), String> {
+        if !allowed.contains(&self.desc.own) || self.raw_cell {
+            return Err(format!("{rk:?} applied to {:?}", self.desc));
+        }
+        Ok(())
+    }
+
+    /// Apply the type-level effect of `rk`, or return `Err` if `rk` can't be applied to a value
+    /// of the current type.
+    fn apply(&mut self, rk: &RewriteKind) -> Result<(), String> {
+        let d = &mut self.desc;
+        let bad = |d: &TypeDesc| Err(format!("{rk:?} applied to {d:?}"));
+        match *rk {
+            RewriteKind::OptionSome => {
+                if d.option {
+                    return bad(d);
+                }
+                d.option = true;
+            }
+            RewriteKind::OptionUnwrap => {
+                if !d.option {
+                    return bad(d);
+                }
+                d.option = false;
+            }
+            RewriteKind::OptionMapBegin => {
+                if !d.option {
+                    return bad(d);
+                }
+                d.option = false;
+                self.map_depth += 1;
+            }
+            RewriteKind::OptionMapEnd => {
+                if d.option || self.map_depth == 0 {
+                    return bad(d);
+                }
+                d.option = true;
+                self.map_depth -= 1;
+            }
+            RewriteKind::OptionDowngrade { mutbl, deref: _ } => {
+                if !d.option || d.dyn_owned {
+                    return bad(d);
+                }
+                let allowed: &[_] = if mutbl {
+                    &[Box, Vec, Mut]
+                } else {
+                    &[Box, Vec, Rc, RcRefCell, ArcMutex, Mut, Imm]
+                };
+                self.check_own(rk, allowed)?;
+                // `as_ref()` on `Option<Rc<RefCell<T>>>` leaves a `RefCell` to borrow, and
+                // likewise for a `Mutex` to lock.
+                if !matches!(self.desc.own, RcRefCell | ArcMutex) {
+                    self.desc.own = if mutbl { Mut } else { Imm };
+                }
+            }
+            RewriteKind::Clone => {}
+            RewriteKind::RcRefCellFromBox => {
+                self.check_unwrapped(rk)?;
+                self.check_own(rk, &[Box])?;
+                if !valid_desc(RcRefCell, self.desc.qty) {
+                    return bad(&self.desc);
+                }
+                self.desc.own = RcRefCell;
+            }
+            RewriteKind::RefCellBorrow { mutbl } => {
+                self.check_unwrapped(rk)?;
+                self.check_own(rk, &[RcRefCell])?;
+                self.desc.own = if mutbl { Mut } else { Imm };
+            }
+            RewriteKind::ArcMutexFromBox => {
+                self.check_unwrapped(rk)?;
+                self.check_own(rk, &[Box])?;
+                if !valid_desc(ArcMutex, self.desc.qty) {
+                    return bad(&self.desc);
+                }
+                self.desc.own = ArcMutex;
+            }
+            RewriteKind::MutexLock { mutbl } => {
+                self.check_unwrapped(rk)?;
+                self.check_own(rk, &[ArcMutex])?;
+                self.desc.own = if mutbl { Mut } else { Imm };
+            }
+            RewriteKind::DynOwnedWrap => {
+                if d.option || d.dyn_owned {
+                    return bad(d);
+                }
+                d.dyn_owned = true;
+            }
+            RewriteKind::DynOwnedUnwrap => {
+                if d.option || !d.dyn_owned {
+                    return bad(d);
+                }
+                d.dyn_owned = false;
+            }
+            RewriteKind::DynOwnedDowngrade { mutbl } => {
+                if d.option || !d.dyn_owned {
+                    return bad(d);
+                }
+                d.dyn_owned = false;
+                self.desc.own = if mutbl { Mut } else { Imm };
+            }
+            RewriteKind::Reborrow { mutbl } => {
+                self.check_unwrapped(rk)?;
+                let allowed: &[_] = if mutbl {
+                    &[Box, Vec, Mut]
+                } else {
+                    &[Box, Vec, Rc, Mut, Imm]
+                };
+                self.check_own(rk, allowed)?;
+                self.desc.own = if mutbl { Mut } else { Imm };
+            }
+            RewriteKind::VecFromBox => {
+                self.check_unwrapped(rk)?;
+                self.check_own(rk, &[Box])?;
+                if !valid_desc(Vec, self.desc.qty) {
+                    return bad(&self.desc);
+                }
+                self.desc.own = Vec;
+            }
+            RewriteKind::VecIntoBoxedSlice => {
+                self.check_unwrapped(rk)?;
+                self.check_own(rk, &[Vec])?;
+                self.desc.own = Box;
+            }
+            RewriteKind::CellFromMut => {
+                self.check_unwrapped(rk)?;
+                self.check_own(rk, &[Mut])?;
+                self.desc.own = Cell;
+            }
+            RewriteKind::AsPtr => {
+                self.check_unwrapped(rk)?;
+                self.check_own(rk, &[Cell])?;
+                self.desc.own = RawMut;
+            }
+            RewriteKind::CastRefToRaw { mutbl } => {
+                self.check_unwrapped(rk)?;
+                self.check_own(rk, if mutbl { &[Mut] } else { &[Imm] })?;
+                self.desc.own = if mutbl { RawMut } else { Raw };
+            }
+            RewriteKind::CastRawToRaw { to_mutbl } => {
+                self.check_unwrapped(rk)?;
+                self.check_own(rk, &[Raw, RawMut])?;
+                self.desc.own = if to_mutbl { RawMut } else { Raw };
+            }
+            RewriteKind::CastRawMutToCellPtr { .. } => {
+                self.check_unwrapped(rk)?;
+                self.check_own(rk, &[RawMut])?;
+                self.desc.own = Raw;
+                self.raw_cell = true;
+            }
+            RewriteKind::UnsafeCastRawToRef { mutbl } => {
+                self.check_unwrapped(rk)?;
+                if self.raw_cell && !mutbl {
+                    self.raw_cell = false;
+                    self.desc.own = Cell;
+                } else {
+                    self.check_own(rk, if mutbl { &[RawMut] } else { &[Raw] })?;
+                    self.desc.own = if mutbl { Mut } else { Imm };
+                }
+            }
+            RewriteKind::SliceFirst { mutbl } => {
+                self.check_unwrapped(rk)?;
+                self.check_own(rk, if mutbl { &[Mut] } else { &[Imm, Cell] })?;
+                if self.desc.qty == Single {
+                    return bad(&self.desc);
+                }
+                self.desc.qty = Single;
+            }
+            _ => return Err(format!("unexpected rewrite {rk:?} in a cast")),
+        }
+        Ok(())
+    }
+
+    /// Check that the simulation ended in a state equivalent to `to`.  `Slice` and `OffsetPtr`
+    /// are currently represented identically.
+    fn finish(self, to: TypeDesc<'tcx>) -> Result<(), String> {
+        let normalize = |mut d: TypeDesc<'tcx>| {
+            if d.qty == OffsetPtr {
+                d.qty = Slice;
+            }
+            d
+        };
+        if self.raw_cell || self.map_depth != 0 || normalize(self.desc) != normalize(to) {
+            return Err(format!("cast produced {:?}, not {to:?}", self.desc));
+        }
+        Ok(())
+    }
+}
+
+/// Check that the cast from `from` to `to` either fails cleanly or produces a sequence of
+/// rewrites that transforms `from` into `to`.
+fn check_cast<'tcx>(tcx: TyCtxt<'tcx>, from: TypeDesc<'tcx>, to: TypeDesc<'tcx>) {
+    let rewrites = match build_cast(tcx, from, to) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+    let mut sim = CastSim::new(from);
+    let result = rewrites
+        .iter()
+        .try_for_each(|rk| sim.apply(rk))
+        .and_then(|()| sim.finish(to));
+    if let Err(e) = result {
+        panic!("bad cast from {from:?} to {to:?}: {rewrites:?}: {e}");
+    }
+}
+
+#[test]
+fn cast_all_pairs() {
+    with_tcx(|tcx| {
+        let descs = all_descs(tcx.types.i32);
+        for &from in &descs {
+            for &to in &descs {
+                check_cast(tcx, from, to);
+            }
+        }
+    });
+}
+
+/// A xorshift generator, so failures are reproducible from the seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn choose<T: Copy>(&mut self, xs: &[T]) -> T {
+        xs[self.next() as usize % xs.len()]
+    }
+
+    fn desc<'tcx>(&mut self, pointee_ty: Ty<'tcx>) -> TypeDesc<'tcx> {
+        let own = self.choose(&OWNERSHIPS);
+        let qty = match own {
+            Vec => self.choose(&[Slice, OffsetPtr]),
+            RcRefCell | ArcMutex => Single,
+            _ => self.choose(&QUANTITIES),
+        };
+        TypeDesc {
+            option: self.choose(&[false, true]),
+            dyn_owned: matches!(own, Box | Vec) && self.choose(&[false, true]),
+            ..TypeDesc::new(own, qty, pointee_ty)
+        }
+    }
+}
+
+/// Check random pairs of `TypeDesc`s with varying pointee types.  Set
+/// `C2RUST_CAST_FUZZ_ITERS` and `C2RUST_CAST_FUZZ_SEED` for longer or different runs.
+#[test]
+fn cast_random_pairs() {
+    let var = |name, default| {
+        std::env::var(name)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(default)
+    };
+    let iters = var("C2RUST_CAST_FUZZ_ITERS", 10_000);
+    let seed = var("C2RUST_CAST_FUZZ_SEED", 0x2545_f491_4f6c_dd1d);
+    with_tcx(|tcx| {
+        let pointee_tys = [tcx.types.i32, tcx.types.u8, tcx.mk_imm_ptr(tcx.types.u8)];
+        let mut rng = Rng(seed);
+        for _ in 0..iters {
+            let from_ty = rng.choose(&pointee_tys);
+            // Mostly cast between equal pointee types, which is the interesting case.
+            let to_ty = if rng.next() % 8 == 0 {
+                rng.choose(&pointee_tys)
+            } else {
+                from_ty
+            };
+            let from = rng.desc(from_ty);
+            let to = rng.desc(to_ty);
+            check_cast(tcx, from, to);
+        }
+    });
+}
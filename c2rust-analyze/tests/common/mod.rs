@@ -71,6 +71,67 @@ struct AnalyzeArgs {
     /// unsupported cast.
     #[clap(long)]
     use_manual_shims: bool,
+
+    /// The policy for pointers passed to exported functions: `check` or `fixed`.
+    #[clap(long)]
+    untrusted_extern_inputs: Option<String>,
+
+    /// Rewrite owned and borrowed strings to `CString` and `&CStr`.
+    #[clap(long)]
+    c_strings: bool,
+
+    /// Rewrite `printf` and `fprintf` calls with constant formats.
+    #[clap(long)]
+    rewrite_printf: bool,
+
+    /// Rewrite hand-written growable array structs into structs holding a `Vec`.
+    #[clap(long)]
+    rewrite_vecs: bool,
+
+    /// Rewrite owned buffers resized with `realloc` to `Vec<T>`.
+    #[clap(long)]
+    realloc_vecs: bool,
+
+    /// Rewrite aliased and mutated heap objects to `Rc<RefCell<T>>`.
+    #[clap(long)]
+    rc_refcell: bool,
+
+    /// Rewrite heap objects passed to other threads to `Arc<Mutex<T>>`.
+    #[clap(long)]
+    arc_mutex: bool,
+
+    /// Turn destructor functions into `Drop` impls.
+    #[clap(long)]
+    drop_impls: bool,
+
+    /// Rewrite integer and `bool` `static mut` items used by only one function into atomics.
+    #[clap(long)]
+    rewrite_local_statics: bool,
+
+    /// Call the generated `c2rust_helpers` module from `memcpy`/`memset`/`realloc` rewrites.
+    #[clap(long)]
+    helper_module: bool,
+
+    /// Rewrite `malloc` of types with no zero value into uninitialized allocations.
+    #[clap(long)]
+    malloc_uninit: bool,
+
+    /// The policy for `free` calls on pointers that may not own their allocation, optionally
+    /// prefixed with `SITE=`.
+    #[clap(long)]
+    free_policy: Vec<String>,
+
+    /// Log the per-statement traces of the analysis, which some tests check.
+    #[clap(long)]
+    log_mir: bool,
+
+    /// Emit `expect` calls with messages in this format in place of `unwrap()`.
+    #[clap(long)]
+    expect_message: Option<String>,
+
+    /// Also compile the rewritten code, to check that the rewrites produce valid Rust.
+    #[clap(long)]
+    compile_output: bool,
 }
 
 impl AnalyzeArgs {
@@ -170,6 +231,8 @@ impl Analyze {
         let rs_path = dir.join(rs_path); // allow relative paths, or override with an absolute path
 
         let crate_options = crate_options.unwrap_or_default();
+        let crate_type: &str = crate_options.crate_type.into();
+        let edition = crate_options.edition.to_string();
         let args = AnalyzeArgs::parse_from_file(&rs_path);
 
         let output_path = {
@@ -194,18 +257,58 @@ impl Analyze {
         if args.use_manual_shims {
             cmd.env("C2RUST_ANALYZE_USE_MANUAL_SHIMS", "1");
         }
+        if let Some(ref policy) = args.untrusted_extern_inputs {
+            cmd.env("C2RUST_ANALYZE_UNTRUSTED_EXTERN_INPUTS", policy);
+        }
+        if args.c_strings {
+            cmd.env("C2RUST_ANALYZE_C_STRINGS", "1");
+        }
+        if args.rewrite_printf {
+            cmd.env("C2RUST_ANALYZE_REWRITE_PRINTF", "1");
+        }
+        if args.rewrite_vecs {
+            cmd.env("C2RUST_ANALYZE_REWRITE_VECS", "1");
+        }
+        if args.realloc_vecs {
+            cmd.env("C2RUST_ANALYZE_REALLOC_VECS", "1");
+        }
+        if args.rc_refcell {
+            cmd.env("C2RUST_ANALYZE_RC_REFCELL", "1");
+        }
+        if args.arc_mutex {
+            cmd.env("C2RUST_ANALYZE_ARC_MUTEX", "1");
+        }
+        if args.drop_impls {
+            cmd.env("C2RUST_ANALYZE_DROP_IMPLS", "1");
+        }
+        if args.rewrite_local_statics {
+            cmd.env("C2RUST_ANALYZE_REWRITE_LOCAL_STATICS", "1");
+        }
+        if args.helper_module {
+            cmd.env("C2RUST_ANALYZE_HELPER_MODULE", "1");
+        }
+        if args.malloc_uninit {
+            cmd.env("C2RUST_ANALYZE_MALLOC_UNINIT", "1");
+        }
+        if !args.free_policy.is_empty() {
+            cmd.env("C2RUST_ANALYZE_FREE_POLICY", args.free_policy.join(","));
+        }
+        if args.log_mir {
+            cmd.env("C2RUST_ANALYZE_LOG_MIR", "1");
+        }
+        if let Some(ref expect_message) = args.expect_message {
+            cmd.env("C2RUST_ANALYZE_EXPECT_MESSAGE", expect_message);
+        }
         if let Some(ref rewrite_paths) = args.rewrite_paths {
             cmd.env("C2RUST_ANALYZE_REWRITE_PATHS", rewrite_paths);
         }
+        if args.compile_output {
+            cmd.env("C2RUST_ANALYZE_REWRITE_MODE", "alongside");
+        }
         cmd.arg(&rs_path)
             .arg("-L")
             .arg(lib_dir)
-            .args([
-                "--crate-type",
-                crate_options.crate_type.into(),
-                "--edition",
-                &crate_options.edition.to_string(),
-            ])
+            .args(["--crate-type", crate_type, "--edition", &edition])
             .stdout(output_stdout)
             .stderr(output_stderr);
         cmd.envs(args.env.iter().map(|EnvVar { var, value }| (var, value)));
@@ -226,9 +329,43 @@ impl Analyze {
                 panic!("\n{message}\n{output}\n{message}");
             };
         }
+        if args.compile_output {
+            self.compile_rewritten(&rs_path, lib_dir, crate_type, &edition);
+        }
         output_path
     }
 
+    /// Compile the rewritten code that `C2RUST_ANALYZE_REWRITE_MODE=alongside` wrote next to
+    /// `rs_path`, running `c2rust-analyze` as a plain `rustc`.  The rewritten file is moved out of
+    /// the test directory first, so it isn't mistaken for a test.
+    fn compile_rewritten(&self, rs_path: &Path, lib_dir: &Path, crate_type: &str, edition: &str) {
+        let new_path = rs_path.with_extension("new.rs");
+        let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("compile_output");
+        fs::create_dir_all(&out_dir).unwrap();
+        // Keep the original file name, which `rustc` uses as the crate name.
+        let out_path = out_dir.join(rs_path.file_name().unwrap());
+        fs::copy(&new_path, &out_path).unwrap();
+        fs::remove_file(&new_path).unwrap();
+
+        let mut cmd = Command::new(&self.path);
+        cmd.env("RUSTC_WRAPPER", &self.path)
+            .env_remove("CARGO_PRIMARY_PACKAGE") // Don't analyze the rewritten code again.
+            .arg("rustc")
+            .arg(&out_path)
+            .arg("-L")
+            .arg(lib_dir)
+            .args(["--crate-type", crate_type, "--edition", edition])
+            .args(["--emit", "metadata", "--out-dir"])
+            .arg(&out_dir);
+        let output = cmd.output().unwrap();
+        assert!(
+            output.status.success(),
+            "\nrewritten code failed to compile with status {}:\n> {cmd:?}\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+
     pub fn run_with(
         &self,
         rs_path: impl AsRef<Path>,
@@ -32,6 +32,7 @@ macro_rules! define_tests {
 
 define_tests! {
     addr_of,
+    addr_of_field,
     adjust_unsize,
     aggregate1,
     algo_md5,
@@ -39,37 +40,75 @@ define_tests! {
     alias2,
     alias3,
     alloc,
+    alloc_zero,
+    arc_mutex,
     as_ptr,
+    bsearch,
+    c_strings,
     call1,
     call_cast,
     cast,
     catch_panic,
     cell,
     clone1,
+    drop_impls,
+    enum_fields,
+    escaping_reborrow,
+    expect_message,
     extern_fn1,
     fields,
     field_temp,
     fixed,
+    fn_ptr_table,
     foreign,
+    free_list,
+    free_policy,
+    helper_module,
+    inline_asm,
     insertion_sort,
     insertion_sort_driver,
     insertion_sort_rewrites,
     known_fn,
+    lint_cleanup,
+    local_statics,
+    macros,
+    malloc_uninit,
+    memchr,
+    memcmp,
+    memcpy_const_len,
+    memcpy_fallback,
+    memmove,
+    methods,
     non_null,
     non_null_force,
     non_null_rewrites,
     offset1,
     offset2,
+    offset_field,
     pointee,
+    printf,
     ptrptr1,
+    qsort,
+    rc_refcell,
+    readonly_statics,
+    realloc_vecs,
     regions_fixed,
+    returned_slice_len,
     rewrite_paths,
     rewrite_paths_manual_shim,
+    sprintf,
     statics,
+    str_fns,
+    strdup,
     test_attrs,
+    transparent_wrappers,
     trivial,
     type_alias,
     type_annotation_rewrite,
     unrewritten_calls,
     unrewritten_calls_shim_fail,
+    untrusted_extern_inputs,
+    variadic,
+    vec_structs,
+    zeroize_types,
 }
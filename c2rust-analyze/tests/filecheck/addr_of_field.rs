@@ -0,0 +1,30 @@
+// Field pointers created with `addr_of!`/`addr_of_mut!` share the permissions of the pointer to
+// the parent struct.
+pub struct Pair {
+    a: i32,
+    b: i32,
+}
+
+// CHECK-LABEL: final labeling for "write_field"
+// CHECK-DAG: ([[@LINE+1]]: p): &mut Pair
+pub unsafe fn write_field(p: *mut Pair) {
+    // CHECK-DAG: ([[@LINE+1]]: q): &mut i32
+    let q = std::ptr::addr_of_mut!((*p).a);
+    *q = 1;
+}
+
+// CHECK-LABEL: final labeling for "read_field"
+// CHECK-DAG: ([[@LINE+1]]: p): &Pair
+pub unsafe fn read_field(p: *const Pair) -> i32 {
+    // CHECK-DAG: ([[@LINE+1]]: q): &i32
+    let q = std::ptr::addr_of!((*p).b);
+    *q
+}
+
+// CHECK-LABEL: final labeling for "write_field_ptrptr"
+// CHECK-DAG: ([[@LINE+1]]: pp): &mut &mut Pair
+pub unsafe fn write_field_ptrptr(pp: *mut *mut Pair) {
+    // CHECK-DAG: ([[@LINE+1]]: q): &mut i32
+    let q = std::ptr::addr_of_mut!((**pp).b);
+    *q = 2;
+}
@@ -1,3 +1,4 @@
+//! --log-mir
 struct S<'a> {
     y: u32,
     px: *const u32,
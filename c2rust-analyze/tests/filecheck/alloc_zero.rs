@@ -0,0 +1,104 @@
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+use std::ptr;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn realloc(_: *mut libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+    fn calloc(_: libc::c_ulong, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+// `malloc(0)` may return null, so a nullable single-item allocation of size zero becomes `None`
+// instead of failing the `assert_eq!(n, 1)`.
+//
+// CHECK-LABEL: unsafe extern "C" fn malloc_zero_single{{[<(]}}
+pub unsafe extern "C" fn malloc_zero_single(cond: bool, n: libc::c_ulong) {
+    // CHECK: if n == 0 {
+    // CHECK: None
+    // CHECK: assert_eq!(n, 1);
+    // CHECK: Some(Box::new(0))
+    let mut p = malloc(n) as *mut i32;
+    if cond {
+        p = ptr::null_mut();
+    }
+    *p = 1;
+    free(p as *mut libc::c_void);
+}
+
+// CHECK-LABEL: unsafe extern "C" fn calloc_zero_single{{[<(]}}
+pub unsafe extern "C" fn calloc_zero_single(cond: bool, n: libc::c_ulong) {
+    // CHECK: let n = count as usize;
+    // CHECK: if n == 0 {
+    // CHECK: Some(Box::new(0))
+    let mut p = calloc(n, 4) as *mut i32;
+    if cond {
+        p = ptr::null_mut();
+    }
+    *p = 1;
+    free(p as *mut libc::c_void);
+}
+
+// A zero-size slice allocation is an empty boxed slice.
+//
+// CHECK-LABEL: unsafe extern "C" fn malloc_zero_slice{{[<(]}}
+pub unsafe extern "C" fn malloc_zero_slice(n: libc::c_ulong) {
+    // CHECK: let mut v = Vec::with_capacity(n);
+    // CHECK: v.into_boxed_slice()
+    let mut p = malloc(n * 4) as *mut i32;
+    let mut i = 0;
+    while (i as libc::c_ulong) < n {
+        *p.offset(i) = 1;
+        i += 1;
+    }
+    free(p as *mut libc::c_void);
+}
+
+// `realloc(NULL, n)` acts like `malloc(n)`.
+//
+// CHECK-LABEL: unsafe extern "C" fn realloc_null_slice{{[<(]}}
+pub unsafe extern "C" fn realloc_null_slice(n: libc::c_ulong) {
+    let mut p: *mut i32 = ptr::null_mut();
+    // CHECK: let src_ptr = src_ptr.unwrap_or_default();
+    // CHECK: let mut dest_ptr = Vec::from(src_ptr);
+    // CHECK: dest_ptr.resize_with(dest_n, || 0);
+    p = realloc(p as *mut libc::c_void, n * 4) as *mut i32;
+    let mut i = 0;
+    while (i as libc::c_ulong) < n {
+        *p.offset(i) = 1;
+        i += 1;
+    }
+    free(p as *mut libc::c_void);
+}
+
+// CHECK-LABEL: unsafe extern "C" fn realloc_null_single{{[<(]}}
+pub unsafe extern "C" fn realloc_null_single(n: libc::c_ulong) {
+    let mut p: *mut i32 = ptr::null_mut();
+    // `realloc(p, 0)` may return null, so the result is `None` when `n` is zero.
+    //
+    // CHECK: if dest_n == 0 {
+    // CHECK: None
+    // CHECK: assert_eq!(dest_n, 1);
+    // CHECK: Some(src_ptr.unwrap_or_else(|| Box::new(0)))
+    p = realloc(p as *mut libc::c_void, n) as *mut i32;
+    *p = 1;
+    free(p as *mut libc::c_void);
+}
+
+// `free(NULL)` is a no-op, so freeing a nullable pointer must not unwrap it.
+//
+// CHECK-LABEL: unsafe extern "C" fn free_nullable{{[<(]}}
+pub unsafe extern "C" fn free_nullable(cond: bool) {
+    let mut p = malloc(4) as *mut i32;
+    *p = 1;
+    if cond {
+        free(p as *mut libc::c_void);
+        p = ptr::null_mut();
+    }
+    // CHECK: let ptr = {{.*}}p{{.*}};
+    // CHECK: if let Some(b) = ptr { drop(b) };
+    free(p as *mut libc::c_void);
+}
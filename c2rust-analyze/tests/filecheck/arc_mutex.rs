@@ -0,0 +1,65 @@
+//! --arc-mutex
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+    fn pthread_create(
+        thread: *mut libc::pthread_t,
+        attr: *const libc::pthread_attr_t,
+        start_routine: Option<unsafe extern "C" fn(*mut libc::c_void) -> *mut libc::c_void>,
+        arg: *mut libc::c_void,
+    ) -> libc::c_int;
+    fn pthread_join(thread: libc::pthread_t, retval: *mut *mut libc::c_void) -> libc::c_int;
+}
+
+#[derive(Copy, Clone)]
+pub struct Shared {
+    pub count: i32,
+    pub total: i32,
+}
+
+// The worker's argument keeps its `void*` type, and the cast to `*mut Shared` takes back the `Arc`
+// passed by `spawn_worker`.
+// CHECK-LABEL: unsafe extern "C" fn worker{{[<(]}}
+// CHECK-SAME: arg: *mut libc::c_void
+unsafe extern "C" fn worker(arg: *mut libc::c_void) -> *mut libc::c_void {
+    // CHECK: std::sync::Arc::from_raw({{.*}} as *const std::sync::Mutex<Shared>)
+    let s = arg as *mut Shared;
+    // CHECK: (*&mut *s.lock().unwrap()).count += 1;
+    (*s).count += 1;
+    0 as *mut libc::c_void
+}
+
+// CHECK-LABEL: unsafe extern "C" fn spawn_worker{{[<(]}}
+pub unsafe extern "C" fn spawn_worker() -> i32 {
+    // CHECK: std::sync::Arc::new(std::sync::Mutex::new(*
+    let s = malloc(std::mem::size_of::<Shared>() as libc::c_ulong) as *mut Shared;
+    // CHECK: (*&mut *s.lock().unwrap()).count = 0;
+    (*s).count = 0;
+    (*s).total = 0;
+    let mut t: libc::pthread_t = 0;
+    // CHECK: std::sync::Arc::into_raw(s.clone()).cast_mut().cast()
+    pthread_create(&mut t, 0 as *const _, Some(worker), s as *mut libc::c_void);
+    pthread_join(t, 0 as *mut _);
+    // CHECK: (*&*s.lock().unwrap()).count;
+    let n = (*s).count;
+    // CHECK: drop(
+    free(s as *mut libc::c_void);
+    n
+}
+
+// An object that isn't passed to another thread remains a `Box`.
+// CHECK-LABEL: unsafe extern "C" fn unshared{{[<(]}}
+pub unsafe extern "C" fn unshared() -> i32 {
+    // CHECK-NOT: std::sync::Mutex
+    // CHECK: Box::new
+    let s = malloc(std::mem::size_of::<Shared>() as libc::c_ulong) as *mut Shared;
+    (*s).count = 1;
+    let n = (*s).count;
+    free(s as *mut libc::c_void);
+    n
+}
@@ -37,3 +37,21 @@ pub unsafe fn array_as_ptr_offset_load(x: &[i32; 10]) -> i32 {
     let q = p.offset(2);
     *q
 }
+
+// CHECK-LABEL: final labeling for "vec_as_ptr_load"
+// CHECK-LABEL: type assignment for "vec_as_ptr_load"
+pub unsafe fn vec_as_ptr_load(x: &Vec<i32>) -> i32 {
+    // CHECK-DAG: ([[@LINE+1]]: p): &i32
+    let p = x.as_ptr();
+    *p
+}
+
+// CHECK-LABEL: final labeling for "vec_as_mut_ptr_offset_store"
+// CHECK-LABEL: type assignment for "vec_as_mut_ptr_offset_store"
+pub unsafe fn vec_as_mut_ptr_offset_store(x: &mut Vec<i32>) {
+    // CHECK-DAG: ([[@LINE+1]]: p): &mut [i32]
+    let p = x.as_mut_ptr();
+    // CHECK-DAG: ([[@LINE+1]]: q): &mut i32
+    let q = p.offset(2);
+    *q = 1;
+}
@@ -0,0 +1,68 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn bsearch(
+        __key: *const libc::c_void,
+        __base: *const libc::c_void,
+        __nmemb: libc::size_t,
+        __size: libc::size_t,
+        __compar: Option<
+            unsafe extern "C" fn(*const libc::c_void, *const libc::c_void) -> libc::c_int,
+        >,
+    ) -> *mut libc::c_void;
+}
+
+unsafe extern "C" fn cmp_ints(a: *const libc::c_void, b: *const libc::c_void) -> libc::c_int {
+    let x = *(a as *const libc::c_int);
+    let y = *(b as *const libc::c_int);
+    (x > y) as libc::c_int - (x < y) as libc::c_int
+}
+
+// The comparator gets the key first and the element second, as `bsearch` passes them, and its
+// result orders the key relative to the element, so it's reversed for `binary_search_by`.
+// CHECK-LABEL: unsafe fn find_int
+unsafe fn find_int(p: *const libc::c_int, n: libc::size_t, x: libc::c_int) -> libc::c_int {
+    // CHECK: let (key, base, n, ) = ({{.*}}&x{{.*}}, {{.*}}p{{.*}}, n, );
+    // CHECK-NEXT: match base[..n as usize].binary_search_by(|e| cmp_ints(key, e{{.*}}).cmp(&0).reverse()) {
+    // CHECK-NEXT: Ok(i) => Some(&base[i..]),
+    // CHECK-NEXT: Err(_) => None,
+    // CHECK-NOT: bsearch(
+    let found = bsearch(
+        &x as *const libc::c_int as *const libc::c_void,
+        p as *const libc::c_void,
+        n,
+        ::std::mem::size_of::<libc::c_int>() as libc::size_t,
+        Some(
+            cmp_ints
+                as unsafe extern "C" fn(*const libc::c_void, *const libc::c_void) -> libc::c_int,
+        ),
+    ) as *mut libc::c_int;
+    if found.is_null() {
+        -1
+    } else {
+        *found + *p.offset(1)
+    }
+}
+
+// A key that isn't found gives `None`, which takes the place of the null pointer that `bsearch`
+// returns.
+// CHECK-LABEL: unsafe fn contains_int
+unsafe fn contains_int(p: *const libc::c_int, n: libc::size_t, x: libc::c_int) -> bool {
+    // CHECK: .binary_search_by(|e| cmp_ints(key, e{{.*}}).cmp(&0).reverse()) {
+    // CHECK: Err(_) => None,
+    // CHECK-NOT: bsearch(
+    let found = bsearch(
+        &x as *const libc::c_int as *const libc::c_void,
+        p as *const libc::c_void,
+        n,
+        ::std::mem::size_of::<libc::c_int>() as libc::size_t,
+        Some(
+            cmp_ints
+                as unsafe extern "C" fn(*const libc::c_void, *const libc::c_void) -> libc::c_int,
+        ),
+    ) as *mut libc::c_int;
+    // CHECK: !found.is_none()
+    let _ = *p.offset(1);
+    !found.is_null()
+}
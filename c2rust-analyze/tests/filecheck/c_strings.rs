@@ -0,0 +1,30 @@
+//! --c-strings
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn strdup(_: *const libc::c_char) -> *mut libc::c_char;
+    fn strlen(_: *const libc::c_char) -> libc::size_t;
+    fn puts(_: *const libc::c_char) -> libc::c_int;
+    fn free(_: *mut libc::c_void);
+}
+
+// CHECK-LABEL: unsafe fn print_copy
+// CHECK-SAME: std::ffi::CStr
+unsafe fn print_copy(s: *const libc::c_char) -> libc::size_t {
+    // CHECK: .to_owned()
+    let p = strdup(s);
+    // CHECK: .to_bytes().len()
+    let n = strlen(p);
+    // CHECK: .as_ptr()
+    puts(p);
+    // CHECK: drop(
+    free(p as *mut libc::c_void);
+    n
+}
+
+// CHECK-LABEL: unsafe fn first_char
+// CHECK-NOT: CStr
+unsafe fn first_char(s: *const libc::c_char) -> libc::c_char {
+    *s
+}
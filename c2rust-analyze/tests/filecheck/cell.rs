@@ -43,3 +43,43 @@ unsafe extern "C" fn cell_field(mut s: *mut S) {
         i: 0,
     };
 }
+
+struct CellPtrs {
+    a: *mut i32,
+    b: *mut i32,
+}
+
+struct Outer {
+    inner: CellPtrs,
+}
+
+// CHECK-LABEL: fn cell_ptr_fields(
+unsafe fn cell_ptr_fields() {
+    // CHECK-DAG: let mut x = std::cell::Cell::new((1));
+    let mut x = 1;
+    let c = CellPtrs {
+        a: std::ptr::addr_of_mut!(x),
+        b: std::ptr::addr_of_mut!(x),
+    };
+    // CHECK-DAG: (c.a).set((2));
+    *c.a = 2;
+    // CHECK-DAG: (c.b).set(((c.a).get()));
+    *c.b = *c.a;
+    let mut o = Outer { inner: c };
+    // CHECK-DAG: (o.inner.a).set((3));
+    *o.inner.a = 3;
+    let p = std::ptr::addr_of_mut!(o);
+    // CHECK-DAG: ((*p).inner.b).set((4));
+    *(*p).inner.b = 4;
+}
+
+// CHECK-LABEL: fn cell_array{{[<(]}}
+// CHECK-SAME: x: &{{('[^ ]* )?}}[core::cell::Cell<(i32)>]
+unsafe fn cell_array(x: *mut i32) {
+    let p = x;
+    let q = x.offset(1);
+    // CHECK: [0].set({{.*}}[0].get(){{.*}});
+    *q = *p;
+    // CHECK: [0].set((3));
+    *p.offset(2) = 3;
+}
@@ -0,0 +1,80 @@
+//! --drop-impls
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+// CHECK-LABEL: struct Thing
+// CHECK-NOT: Copy
+// CHECK: data: *mut libc::c_int,
+// CHECK: name: *mut libc::c_char,
+#[derive(Copy, Clone)]
+pub struct Thing {
+    pub data: *mut libc::c_int,
+    pub name: *mut libc::c_char,
+    pub len: libc::c_int,
+}
+
+// CHECK-LABEL: unsafe fn thing_init
+unsafe fn thing_init(t: *mut Thing, len: libc::c_int) {
+    // CHECK: std::ptr::write(core::ptr::addr_of_mut!({{.*}}t{{.*}}), (Thing {
+    *t = Thing {
+        data: malloc((len as libc::c_ulong).wrapping_mul(4)) as *mut libc::c_int,
+        name: malloc(16) as *mut libc::c_char,
+        len,
+    };
+}
+
+// The destructor is kept for callers that aren't rewritten, and the `Drop` impl follows it.
+// CHECK-LABEL: unsafe fn thing_free
+// CHECK: free(t as *mut libc::c_void);
+// CHECK: impl Drop for Thing {
+// CHECK-NEXT: fn drop(&mut self) {
+// CHECK-NEXT: unsafe {
+// CHECK-NEXT: free(self.data as *mut libc::c_void);
+// CHECK-NEXT: free(self.name as *mut libc::c_void);
+unsafe fn thing_free(t: *mut Thing) {
+    if t.is_null() {
+        return;
+    }
+    free((*t).data as *mut libc::c_void);
+    free((*t).name as *mut libc::c_void);
+    free(t as *mut libc::c_void);
+}
+
+// CHECK-LABEL: unsafe fn use_thing
+unsafe fn use_thing() -> libc::c_int {
+    // CHECK: Box::new
+    let t = malloc(std::mem::size_of::<Thing>() as libc::c_ulong) as *mut Thing;
+    thing_init(t, 4);
+    let n = (*t).len;
+    // CHECK-NOT: thing_free
+    // CHECK: drop(
+    thing_free(t);
+    n
+}
+
+#[derive(Copy, Clone)]
+pub struct Pair {
+    pub a: *mut libc::c_int,
+    pub b: *mut libc::c_int,
+}
+
+// `Pair::a` is also freed outside the destructor, so `Pair` doesn't get a `Drop` impl.
+unsafe fn pair_clear_a(p: *mut Pair) {
+    free((*p).a as *mut libc::c_void);
+    (*p).a = 0 as *mut libc::c_int;
+}
+
+// CHECK-LABEL: unsafe fn pair_free
+// CHECK-NOT: impl Drop
+unsafe fn pair_free(p: *mut Pair) {
+    free((*p).a as *mut libc::c_void);
+    free((*p).b as *mut libc::c_void);
+    free(p as *mut libc::c_void);
+}
@@ -0,0 +1,17 @@
+// Pointers in the payloads of enum variants, as produced when translating tagged unions, are
+// analyzed and rewritten like struct fields.
+
+// CHECK-LABEL: enum Value<'h0> {
+pub enum Value {
+    Int(i32),
+    // CHECK: Ptr(&'h0 {{.*}}i32{{.*}}),
+    Ptr(*const i32),
+}
+
+// CHECK-LABEL: unsafe fn get<'h0,'h1>(
+pub unsafe fn get(v: *const Value) -> i32 {
+    match *v {
+        Value::Int(i) => i,
+        Value::Ptr(p) => *p,
+    }
+}
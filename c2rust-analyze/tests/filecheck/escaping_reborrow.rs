@@ -0,0 +1,76 @@
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+use std::ptr;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+pub struct Holder {
+    p: *mut i32,
+}
+
+// `p` is freed on one path, so it's owned, but the return value isn't.  Reborrowing `p` into the
+// return value would outlive `p`, so the function keeps its raw pointers.
+// CHECK-LABEL: unsafe extern "C" fn escaping_box(cond: bool) -> *mut i32
+pub unsafe extern "C" fn escaping_box(cond: bool) -> *mut i32 {
+    let mut p = malloc(4) as *mut i32;
+    *p = 1;
+    if cond {
+        free(p as *mut libc::c_void);
+        return ptr::null_mut();
+    }
+    // CHECK-NOT: leak
+    p
+}
+
+// The owned pointer is a field of a local.
+// CHECK-LABEL: unsafe extern "C" fn escaping_field(cond: bool) -> *mut i32
+pub unsafe extern "C" fn escaping_field(cond: bool) -> *mut i32 {
+    let mut h = Holder {
+        p: malloc(4) as *mut i32,
+    };
+    *h.p = 1;
+    if cond {
+        free(h.p as *mut libc::c_void);
+        return ptr::null_mut();
+    }
+    // CHECK-NOT: leak
+    h.p
+}
+
+// The borrow reaches the return value through an explicit reborrow and a cast.
+// CHECK-LABEL: unsafe extern "C" fn escaping_reborrow_chain(cond: bool) -> *mut i32
+pub unsafe extern "C" fn escaping_reborrow_chain(cond: bool) -> *mut i32 {
+    let mut p = malloc(4) as *mut i32;
+    *p = 1;
+    if cond {
+        free(p as *mut libc::c_void);
+        return ptr::null_mut();
+    }
+    let q: *mut i32 = &mut *p;
+    // CHECK-NOT: leak
+    q
+}
+
+// A borrow that doesn't leave the function is still a reborrow.
+// CHECK-LABEL: unsafe extern "C" fn local_borrow{{[<(]}}
+pub unsafe extern "C" fn local_borrow() -> i32 {
+    let mut p = malloc(4) as *mut i32;
+    *p = 1;
+    // CHECK-NOT: leak
+    // CHECK: as_deref
+    let q = p;
+    let x = *q;
+    free(p as *mut libc::c_void);
+    x
+}
+
+// CHECK: analysis of DefId({{.*}}::escaping_box) failed: {{.*}}ESCAPING_REBORROW
+// CHECK: analysis of DefId({{.*}}::escaping_field) failed: {{.*}}ESCAPING_REBORROW
+// CHECK: analysis of DefId({{.*}}::escaping_reborrow_chain) failed: {{.*}}ESCAPING_REBORROW
+// CHECK-NOT: analysis of DefId({{.*}}::local_borrow) failed
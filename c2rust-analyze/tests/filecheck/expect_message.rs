@@ -0,0 +1,23 @@
+//! --expect-message "`{expr}` is null"
+use std::ptr;
+
+// CHECK-LABEL: unsafe fn read_nullable{{[<(]}}
+// CHECK-SAME: p: core::option::Option<&{{('[^ ]* )?}}(i32)>
+unsafe fn read_nullable(cond: bool, mut p: *const i32) -> i32 {
+    if cond {
+        p = ptr::null();
+    }
+    // CHECK: *(p).expect("`p` is null")
+    *p
+}
+
+// CHECK-LABEL: unsafe fn write_nullable{{[<(]}}
+unsafe fn write_nullable(cond: bool, mut p: *mut i32) -> i32 {
+    if cond {
+        p = ptr::null_mut();
+    }
+    *p = 1;
+    // CHECK: let x = *(p).as_deref().expect("`p` is null");
+    let x = *p;
+    x
+}
@@ -1,3 +1,4 @@
+//! --log-mir
 // CHECK-LABEL: === ADT Metadata ===
 // CHECK-DAG: struct Data<'d,'h0,'h1,'h2> {
 pub struct Data<'d> {
@@ -0,0 +1,27 @@
+// Tests rewriting of a dispatch table of nullable function pointers.  The element type of the
+// table and the casts that build it should be rewritten consistently with the handlers' own
+// signatures.
+
+// CHECK-LABEL: fn set_one{{.*}}(p: &{{.*}}mut (i32))
+unsafe extern "C" fn set_one(p: *mut i32) {
+    *p = 1;
+}
+
+// CHECK-LABEL: fn set_two{{.*}}(p: &{{.*}}mut (i32))
+unsafe extern "C" fn set_two(p: *mut i32) {
+    *p = 2;
+}
+
+// CHECK-LABEL: static mut HANDLERS:
+// CHECK-SAME: fn(&mut (i32))
+static mut HANDLERS: [Option<unsafe extern "C" fn(*mut i32)>; 2] = [
+    // CHECK: set_one as {{.*}}fn(&mut (i32))
+    Some(set_one as unsafe extern "C" fn(*mut i32)),
+    // CHECK: set_two as {{.*}}fn(&mut (i32))
+    Some(set_two as unsafe extern "C" fn(*mut i32)),
+];
+
+// CHECK-LABEL: fn dispatch{{.*}}(i: usize, p: &{{.*}}mut (i32))
+unsafe extern "C" fn dispatch(i: usize, p: *mut i32) {
+    HANDLERS[i].expect("non-null function pointer")(p);
+}
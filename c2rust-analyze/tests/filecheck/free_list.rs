@@ -0,0 +1,28 @@
+#![feature(rustc_private)]
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+extern crate libc;
+
+extern "C" {
+    fn free(_: *mut libc::c_void);
+}
+
+// Taking an owned pointer out of an element of a pool of owned pointers replaces the element, so
+// the pool must be writable even though the C code only reads from it.
+
+// CHECK-LABEL: final labeling for "pool_take"
+// CHECK-DAG: ([[@LINE+1]]: pool): {{.*}}type = READ | WRITE | {{.*}}OFFSET_ADD{{.*}}#*mut *mut i32{{\[}}{{[^#]*}}FREE
+pub unsafe extern "C" fn pool_take(pool: *mut *mut i32, i: isize) {
+    let p = *pool.offset(i);
+    let x = *p;
+    free(p as *mut libc::c_void);
+}
+
+// CHECK-LABEL: final labeling for "array_take"
+pub unsafe extern "C" fn array_take(mut slots: [*mut i32; 4], i: usize) {
+    // CHECK-DAG: ([[@LINE+1]]: p): {{.*}}type = READ | UNIQUE | FREE | NON_NULL#
+    let p = slots[i];
+    let x = *p;
+    free(p as *mut libc::c_void);
+}
@@ -0,0 +1,57 @@
+//! --free-policy=unsafe
+//! --free-policy=skip_free=skip
+//! --free-policy=dyn_owned_free=dyn-owned
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+use std::ptr;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+// The default policy, `unsafe`, keeps the call to `free` and passes it the raw pointer, or null
+// if `p` doesn't own its allocation.
+// CHECK-LABEL: unsafe extern "C" fn unsafe_free{{[<(]}}
+pub unsafe extern "C" fn unsafe_free() {
+    let mut p = malloc(4) as *mut i32;
+    *p = 1;
+    // CHECK: free(p.map_or(std::ptr::null_mut(), |b| Box::into_raw(b).cast()));
+    free(p as *mut libc::c_void);
+}
+
+// A nullable pointer is unwrapped first, so both `None` and a non-owning pointer become null.
+// CHECK-LABEL: unsafe extern "C" fn unsafe_free_nullable{{[<(]}}
+pub unsafe extern "C" fn unsafe_free_nullable(cond: bool) {
+    let mut p = malloc(4) as *mut i32;
+    if cond {
+        p = ptr::null_mut();
+    }
+    // CHECK: free(p.and_then(std::result::Result::ok).map_or(std::ptr::null_mut(), |b| Box::into_raw(b).cast()));
+    free(p as *mut libc::c_void);
+}
+
+// CHECK-LABEL: unsafe extern "C" fn dyn_owned_free{{[<(]}}
+pub unsafe extern "C" fn dyn_owned_free() {
+    let mut p = malloc(4) as *mut i32;
+    *p = 1;
+    // CHECK: drop(p.unwrap());
+    free(p as *mut libc::c_void);
+}
+
+// CHECK-LABEL: free policy decisions:
+// CHECK-NEXT: dyn_owned_free:[[#]]: dyn-owned
+// CHECK-NEXT: skip_free:[[#]]: skip
+// CHECK-NEXT: unsafe_free:[[#]]: unsafe
+// CHECK-NEXT: unsafe_free_nullable:[[#]]: unsafe
+
+// The function containing the `free` is left unrewritten.
+// CHECK: analysis of DefId({{.*}} ~ free_policy[{{.*}}]::skip_free) failed: USER_REQUEST
+pub unsafe extern "C" fn skip_free() {
+    let mut p = malloc(4) as *mut i32;
+    *p = 1;
+    free(p as *mut libc::c_void);
+}
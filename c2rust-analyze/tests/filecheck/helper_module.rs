@@ -0,0 +1,18 @@
+//! --helper-module
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn memcpy(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+// CHECK-LABEL: unsafe fn copy_buf
+unsafe fn copy_buf(dest: *mut i32, src: *const i32, n: libc::c_ulong) {
+    // CHECK: crate::c2rust_helpers::copy({{.*}}dest{{.*}}, {{.*}}src{{.*}}, {{.*}}n{{.*}} as usize)
+    memcpy(dest as *mut libc::c_void, src as *const libc::c_void, n);
+    *dest.offset(1) = *src.offset(1);
+}
+
+// The helpers are defined once, at the end of the crate root.
+// CHECK: pub mod c2rust_helpers {
+// CHECK: pub fn copy<'a, T: Copy>(dest: &'a mut [T], src: &[T], byte_len: usize) -> &'a mut [T] {
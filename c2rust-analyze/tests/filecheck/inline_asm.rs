@@ -0,0 +1,20 @@
+use std::arch::asm;
+
+// Inline assembly that only uses non-pointer values doesn't prevent rewriting.
+// CHECK-LABEL: fn asm_int<'h0>(p: &'h0 (usize))
+pub unsafe fn asm_int(p: *const usize) -> usize {
+    let x = *p;
+    let y: usize;
+    asm!("/* {0} {1} */", out(reg) y, in(reg) x);
+    y
+}
+
+// `p` would be rewritten to a reference, but the asm code needs it to stay a raw pointer, so the
+// function is left unchanged.
+// CHECK-LABEL: fn asm_ptr
+pub unsafe fn asm_ptr(p: *const usize) -> usize {
+    asm!("/* {0} */", in(reg) p);
+    *p
+}
+
+// CHECK: analysis of DefId({{.*}}::asm_ptr) failed: {{.*}}INLINE_ASM
@@ -0,0 +1,27 @@
+// Rewritten functions get an `#[allow]` for the lints that rewriting tends to trigger, and lose
+// the `mut` on bindings that are never mutated.
+
+// CHECK: #[allow(unused_mut, unused_unsafe, clippy::needless_borrow)]
+// CHECK-NEXT: pub unsafe fn store_sum{{[<(]}}
+// CHECK-SAME: (n: i32, mut p: &
+pub unsafe fn store_sum(mut n: i32, mut p: *mut i32) {
+    // CHECK: let total = n + 1;
+    let mut total = n + 1;
+    // CHECK: let mut count = 0;
+    let mut count = 0;
+    count += 1;
+    // CHECK: let mut arr = [0; 2];
+    let mut arr = [0; 2];
+    let q = arr.as_mut_ptr();
+    *q = 1;
+    *p = total + count + arr[0];
+}
+
+// Functions without rewrites are left alone.
+//
+// CHECK-LABEL: pub fn unchanged(
+pub fn unchanged(mut n: i32) -> i32 {
+    // CHECK: let mut m = n;
+    let mut m = n;
+    m
+}
@@ -0,0 +1,74 @@
+//! --rewrite-local-statics
+#![allow(dead_code)]
+// Test rewriting of `static mut`s that are used by only one function, as produced when
+// transpiling C function-local `static` variables.
+
+static mut counter: i32 = 0;
+static mut total: f64 = 0.0;
+static mut flag: bool = false;
+static mut mask: u8 = 0;
+// Used by two functions, so it stays a module-level `static mut`.
+static mut shared: i32 = 0;
+// Borrowed, so it can't be rewritten.
+static mut borrowed: i32 = 0;
+
+// CHECK-LABEL: ===== BEGIN
+// CHECK-NOT: static mut counter
+// CHECK-NOT: static mut flag
+// CHECK-NOT: static mut mask
+// There's no atomic `f64`, and `thread_local!` would give each thread its own copy, so it stays a
+// `static mut`.
+// CHECK: static mut total: f64 = 0.0;
+// CHECK: static mut shared: i32 = 0;
+// CHECK: static mut borrowed: i32 = 0;
+
+// CHECK-LABEL: fn next_id() -> i32 {
+// CHECK-NEXT: static counter: core::sync::atomic::AtomicI32 = core::sync::atomic::AtomicI32::new(0);
+unsafe fn next_id() -> i32 {
+    // `+=` panics on overflow in debug builds, so this can't become `fetch_add`, which wraps.
+    // Instead it becomes a `fetch_update`, which is still atomic.
+    // CHECK: let rhs = 1;
+    // CHECK-NEXT: counter.fetch_update(core::sync::atomic::Ordering::Relaxed, core::sync::atomic::Ordering::Relaxed, |old| Some(core::ops::Add::add(old, rhs))).unwrap();
+    counter += 1;
+    // CHECK: counter.load(core::sync::atomic::Ordering::Relaxed)
+    counter
+}
+
+// CHECK-LABEL: fn accumulate(x: f64) -> f64 {
+// CHECK-NOT: thread_local!
+unsafe fn accumulate(x: f64) -> f64 {
+    // CHECK: total += x;
+    total += x;
+    total
+}
+
+// CHECK-LABEL: fn set_flag() {
+// CHECK-NEXT: static flag: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+unsafe fn set_flag() {
+    // CHECK: flag.store(true, core::sync::atomic::Ordering::Relaxed);
+    flag = true;
+}
+
+// CHECK-LABEL: fn toggle(bit: u8) -> u8 {
+// CHECK-NEXT: static mask: core::sync::atomic::AtomicU8 = core::sync::atomic::AtomicU8::new(0);
+unsafe fn toggle(bit: u8) -> u8 {
+    // The right-hand side is evaluated once, outside the closure, which may run repeatedly.
+    // CHECK: let rhs = 1 << bit;
+    // CHECK-NEXT: mask.fetch_update(core::sync::atomic::Ordering::Relaxed, core::sync::atomic::Ordering::Relaxed, |old| Some(core::ops::BitXor::bitxor(old, rhs))).unwrap();
+    mask ^= 1 << bit;
+    // CHECK: mask.load(core::sync::atomic::Ordering::Relaxed)
+    mask
+}
+
+unsafe fn use_shared1() -> i32 {
+    shared
+}
+
+unsafe fn use_shared2() {
+    shared = 1;
+}
+
+unsafe fn use_borrowed() {
+    let p = &mut borrowed;
+    *p = 1;
+}
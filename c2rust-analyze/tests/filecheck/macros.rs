@@ -0,0 +1,37 @@
+// Test rewriting of code produced by `macro_rules!` macros.
+
+// Every expansion of this macro needs the same rewrites, so they're applied to the definition.
+// CHECK-LABEL: macro_rules! second_of_four {
+macro_rules! second_of_four {
+    () => {{
+        let mut buf = [0_i32; 4];
+        let p = buf.as_mut_ptr();
+        // CHECK-NOT: offset
+        *p.offset(1) = 1;
+        *p.offset(1)
+    }};
+}
+
+// CHECK-LABEL: fn use_second1
+unsafe fn use_second1() -> i32 {
+    second_of_four!()
+}
+
+unsafe fn use_second2() -> i32 {
+    second_of_four!()
+}
+
+// The pointer comes from outside the macro, so the rewrite would need to mix code from the
+// definition and from the call site.
+macro_rules! read_second {
+    ($p:expr) => {
+        *$p.offset(1)
+    };
+}
+
+// CHECK-LABEL: fn use_read_second
+unsafe fn use_read_second(p: *mut i32) -> i32 {
+    read_second!(p)
+}
+
+// CHECK: analysis of DefId({{.*}}::use_read_second) failed: {{.*}}MACRO_EXPANSION
@@ -0,0 +1,38 @@
+//! --malloc-uninit
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+// A function pointer can't be null, so `Point` can't be zero-initialized.
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+    pub norm: fn(f64, f64) -> f64,
+}
+
+// The allocation is read before it's written, so it can't be left uninitialized.
+// CHECK-LABEL: unsafe fn read_before_write
+unsafe fn read_before_write() -> f64 {
+    // CHECK-NOT: MaybeUninit
+    let mut p = malloc(::std::mem::size_of::<Point>() as libc::c_ulong) as *mut Point;
+    let y = (*p).y;
+    (*p).y = 1.0;
+    free(p as *mut libc::c_void);
+    y
+}
+
+// CHECK-LABEL: unsafe fn new_point
+unsafe fn new_point(x: f64, y: f64, norm: fn(f64, f64) -> f64) -> f64 {
+    // CHECK: Box::new(std::mem::MaybeUninit::<Point>::uninit())
+    // CHECK: .cast::<Point>()
+    let mut p = malloc(::std::mem::size_of::<Point>() as libc::c_ulong) as *mut Point;
+    // CHECK: std::ptr::write(&mut *{{.*}}p{{.*}}, Point { x, y, norm })
+    *p = Point { x, y, norm };
+    let sum = ((*p).norm)((*p).x, (*p).y);
+    free(p as *mut libc::c_void);
+    sum
+}
@@ -0,0 +1,19 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn memchr(_: *const libc::c_void, _: libc::c_int, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+// The result may be null, so it becomes an `Option` of the rest of the slice.
+// CHECK-LABEL: unsafe fn skip_to_colon
+unsafe fn skip_to_colon(p: *const u8, n: libc::c_ulong) -> *const u8 {
+    // CHECK: match p[..n as usize].iter().position(|&x| x == c as u8) {
+    // CHECK-NEXT: Some(i) => Some(&p[i..]),
+    // CHECK-NEXT: None => None,
+    let q = memchr(p as *const libc::c_void, b':' as libc::c_int, n) as *const u8;
+    if q.is_null() {
+        return p;
+    }
+    q.offset(1)
+}
@@ -0,0 +1,22 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn memcmp(_: *const libc::c_void, _: *const libc::c_void, _: libc::c_ulong) -> libc::c_int;
+}
+
+// Bytes compare the same way as `u8`s, so the slices are ordered with `cmp`.
+// CHECK-LABEL: unsafe fn compare_bytes
+unsafe fn compare_bytes(a: *const u8, b: *const u8, n: libc::c_ulong) -> libc::c_int {
+    // CHECK: a[..n].cmp(&b[..n]) as i32
+    let r = memcmp(a as *const libc::c_void, b as *const libc::c_void, n);
+    r + (*a.offset(1) == *b.offset(1)) as libc::c_int
+}
+
+// Multi-byte integers are only compared for equality.
+// CHECK-LABEL: unsafe fn compare_words
+unsafe fn compare_words(a: *const u32, b: *const u32, n: libc::c_ulong) -> bool {
+    // CHECK: (a[..n] != b[..n]) as i32
+    let r = memcmp(a as *const libc::c_void, b as *const libc::c_void, n);
+    r == 0 && *a.offset(1) == *b.offset(1)
+}
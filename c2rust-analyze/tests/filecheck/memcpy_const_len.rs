@@ -0,0 +1,36 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn memcpy(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+pub const BUF_SIZE: libc::c_int = 16;
+
+// The length passed to `memcpy` comes from a constant in the caller, which replaces the length
+// argument.  The element count is still computed from `size_of`, so it doesn't depend on the
+// layout of the element type at analysis time.
+// CHECK-LABEL: unsafe fn copy_buf
+unsafe fn copy_buf(dest: *mut i32, src: *const i32, n: libc::c_ulong) {
+    // CHECK: let (dest, src, byte_len, ) = ({{.*}}, {{.*}}, 16, );
+    // CHECK: debug_assert_eq!(byte_len as usize % std::mem::size_of::<i32>(), 0)
+    // CHECK: byte_len as usize / std::mem::size_of::<i32>()
+    // CHECK: dest[..n].copy_from_slice(&src[..n])
+    memcpy(dest as *mut libc::c_void, src as *const libc::c_void, n);
+    *dest.offset(1) = *src.offset(1);
+}
+
+// CHECK-LABEL: unsafe fn copy_twice
+pub unsafe fn copy_twice(a: *mut i32, b: *const i32) {
+    copy_buf(a, b, BUF_SIZE as libc::c_ulong);
+    copy_buf(a.offset(4), b.offset(4), BUF_SIZE as libc::c_ulong);
+}
+
+// The size of `usize` depends on the target, which the division by `size_of` accounts for.
+// CHECK-LABEL: unsafe fn copy_words
+unsafe fn copy_words(dest: *mut usize, src: *const usize) {
+    // CHECK: byte_len as usize / std::mem::size_of::<usize>()
+    // CHECK: dest[..n].copy_from_slice(&src[..n])
+    memcpy(dest as *mut libc::c_void, src as *const libc::c_void, 16);
+    *dest.offset(1) = *src.offset(1);
+}
@@ -0,0 +1,16 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn memcpy(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+// The pointee types differ, so the `memcpy` call is kept, and the rewritten arguments are cast
+// back to `void` pointers.
+// CHECK-LABEL: unsafe fn copy_bits
+unsafe fn copy_bits(dest: *mut f32, src: *const u32, n: libc::c_ulong) {
+    // CHECK: memcpy({{.*}}dest{{.*}}.cast(), {{.*}}src{{.*}}.cast(), n);
+    memcpy(dest as *mut libc::c_void, src as *const libc::c_void, n);
+    *dest.offset(1) = *dest;
+    let _ = *src.offset(1);
+}
@@ -0,0 +1,16 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn memmove(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+// `dest` and `src` are distinct slices after rewriting, so they can't overlap, and the elements
+// are copied directly.
+// CHECK-LABEL: unsafe fn move_buf
+unsafe fn move_buf(dest: *mut i32, src: *const i32, n: libc::c_ulong) {
+    // CHECK: byte_len as usize / std::mem::size_of::<i32>()
+    // CHECK: dest[..n].copy_from_slice(&src[..n])
+    memmove(dest as *mut libc::c_void, src as *const libc::c_void, n);
+    *dest.offset(1) = *src.offset(1);
+}
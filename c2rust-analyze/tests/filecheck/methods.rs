@@ -0,0 +1,53 @@
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+#![feature(arbitrary_self_types)]
+// Test rewriting of inherent methods, including raw-pointer `self` parameters.
+
+pub struct Counter {
+    count: i32,
+}
+
+impl Counter {
+    // CHECK-LABEL: fn incr<'h0>(&'h0 mut self)
+    pub unsafe fn incr(self: *mut Self) {
+        (*self).count += 1;
+    }
+    // The shim is inserted directly after the method it wraps.
+    // CHECK-LABEL: unsafe fn incr_shim(self: *mut Self)
+    // CHECK: let safe_arg0 = &mut *self;
+    // CHECK: let safe_result = Self::incr(safe_arg0);
+
+    // CHECK-LABEL: fn get<'h0>(&'h0 self) -> i32
+    pub unsafe fn get(self: *const Self) -> i32 {
+        (*self).count
+    }
+
+    // CHECK-LABEL: fn add_to<'h0>(&self, dest: &'h0 mut (i32))
+    pub unsafe fn add_to(&self, dest: *mut i32) {
+        *dest += self.count;
+    }
+
+    // A nullable `self` would become `Option<&Self>`, which can't be a receiver, so the method
+    // keeps its raw pointer `self` rather than being rewritten.
+    // CHECK-LABEL: fn get_or_zero(self: *const Self) -> i32
+    pub unsafe fn get_or_zero(self: *const Self) -> i32 {
+        if self.is_null() {
+            return 0;
+        }
+        (*self).count
+    }
+}
+
+// CHECK-LABEL: fn use_counter<'h0,'h1>(c: &'h0 mut (Counter), dest: &'h1 mut (i32)) -> i32
+pub unsafe fn use_counter(c: *mut Counter, dest: *mut i32) -> i32 {
+    c.incr();
+    (*c).add_to(dest);
+    c.get()
+}
+
+#[c2rust_analyze_test::fail_before_rewriting]
+// CHECK-LABEL: fn bad(c: *mut Counter)
+unsafe fn bad(c: *mut Counter) {
+    // CHECK: c.incr_shim()
+    c.incr();
+}
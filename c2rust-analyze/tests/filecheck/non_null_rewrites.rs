@@ -38,9 +38,12 @@ unsafe fn call_use_mut(cond: bool) -> i32 {
 // CHECK-LABEL: unsafe fn use_mut{{[<(]}}
 // CHECK-SAME: p: core::option::Option<&{{('[^ ]* )?}}mut (i32)>
 unsafe fn use_mut(mut p: *mut i32) -> i32 {
+    // CHECK: if let Some(p) = p.as_deref_mut() {
     if !p.is_null() {
-        // CHECK: *(p).as_deref_mut().unwrap() = 1;
+        // CHECK: *p = 1;
         *p = 1;
+        // CHECK: *p += 1;
+        *p += 1;
     }
     // CHECK: use_const
     // CHECK-SAME: (p).as_deref()
@@ -189,3 +192,62 @@ unsafe fn null_ptr_special_cases(cond: bool, p: *const i32, i: isize) -> *const
 
     s
 }
+
+// CHECK-LABEL: unsafe fn call_count_down{{[<(]}}
+unsafe fn call_count_down(cond: bool) {
+    let mut x = 3;
+    let p = if cond {
+        ptr::addr_of_mut!(x)
+    } else {
+        ptr::null_mut()
+    };
+    count_down(p);
+}
+
+// CHECK-LABEL: unsafe fn count_down{{[<(]}}
+// CHECK-SAME: p: core::option::Option<&{{('[^ ]* )?}}mut (i32)>
+unsafe fn count_down(mut p: *mut i32) {
+    // CHECK: while let Some(p) = p.as_deref_mut() {
+    while !p.is_null() {
+        // CHECK: *p -= 1;
+        *p -= 1;
+        // CHECK: if *p <= 0 {
+        if *p <= 0 {
+            break;
+        }
+    }
+}
+
+// CHECK-LABEL: unsafe fn repeat_null{{[<(]}}
+unsafe fn repeat_null(p: *const i32) -> i32 {
+    // CHECK: let mut arr = [None; 2];
+    let mut arr = [ptr::null(); 2];
+    arr[1] = p;
+    if arr[1].is_null() {
+        0
+    } else {
+        *arr[1]
+    }
+}
+
+struct NullablePtr {
+    p: *const i32,
+}
+
+// CHECK-LABEL: unsafe fn aggregate_field_cast{{[<(]}}
+unsafe fn aggregate_field_cast(cond: bool) -> i32 {
+    let x = 1;
+    // The field is nullable, so its initializer must be wrapped in `Some`.
+    let mut s = NullablePtr {
+        // CHECK: p: Some(
+        p: ptr::addr_of!(x),
+    };
+    if cond {
+        s.p = ptr::null();
+    }
+    if s.p.is_null() {
+        0
+    } else {
+        *s.p
+    }
+}
@@ -0,0 +1,17 @@
+// Offsets read from a field of the struct that holds the buffer, as in incremental writes into
+// `buf + used`.
+pub struct Writer {
+    buf: *mut u8,
+    used: isize,
+}
+
+// CHECK-LABEL: final labeling for "put_byte"
+pub unsafe fn put_byte(w: *mut Writer, c: u8) {
+    // CHECK: ([[@LINE+1]]: (*w).buf): &mut [u8]
+    *(*w).buf.offset((*w).used) = c;
+    (*w).used += 1;
+}
+
+// CHECK-LABEL: unsafe fn put_byte{{[<(]}}
+// CHECK-SAME: w: &{{('[^ ]* )?}}mut (Writer{{.*}})
+// CHECK: [({{.*}}(*w).used{{.*}} as usize) ..]
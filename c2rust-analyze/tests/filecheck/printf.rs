@@ -0,0 +1,65 @@
+//! --rewrite-printf
+#![feature(rustc_private)]
+#![feature(c_variadic)]
+extern crate libc;
+
+extern "C" {
+    static mut stdout: *mut libc::FILE;
+    static mut stderr: *mut libc::FILE;
+    fn printf(_: *const libc::c_char, _: ...) -> libc::c_int;
+    fn fprintf(_: *mut libc::FILE, _: *const libc::c_char, _: ...) -> libc::c_int;
+}
+
+// CHECK-LABEL: unsafe fn print_int
+unsafe fn print_int(x: libc::c_int) -> libc::c_int {
+    // CHECK: std::io::Write::write_fmt(&mut out, format_args!("x = {:5}!\n", a0)).unwrap()
+    // CHECK: std::io::Write::write_all(&mut std::io::stdout(), &out).unwrap()
+    printf(b"x = %5d!\n\0" as *const u8 as *const libc::c_char, x)
+}
+
+// CHECK-LABEL: unsafe fn print_error
+unsafe fn print_error(name: *const libc::c_char, code: libc::c_int) {
+    // CHECK: format_args!("error in ")
+    // CHECK: out.extend(a0.iter().take_while(|&&c| c != 0).map(|&c| c as u8))
+    // CHECK: format_args!(": {}\n", a1)
+    // CHECK: std::io::Write::write_all(&mut std::io::stderr(), &out).unwrap()
+    fprintf(
+        stderr,
+        b"error in %s: %d\n\0" as *const u8 as *const libc::c_char,
+        name,
+        code,
+    );
+}
+
+// CHECK-LABEL: unsafe fn print_to_stdout
+unsafe fn print_to_stdout() {
+    // CHECK: format_args!("done\n")
+    // CHECK: std::io::Write::write_all(&mut std::io::stdout(), &out).unwrap()
+    fprintf(stdout, b"done\n\0" as *const u8 as *const libc::c_char);
+}
+
+// Unsigned conversions cast their argument to the matching unsigned type, and `%f` prints NaN and
+// infinity the way C does.
+// CHECK-LABEL: unsafe fn print_unsigned_and_float
+unsafe fn print_unsigned_and_float(x: libc::c_int, y: libc::c_long, z: libc::c_double) {
+    // CHECK: format_args!("{} {:x} ", a0 as std::os::raw::c_uint, a1 as std::os::raw::c_ulong)
+    // CHECK: if a2.is_finite() {
+    // CHECK: format_args!("{:.6}", a2)
+    // CHECK: (true, false) => "nan",
+    // CHECK: (false, true) => "-inf",
+    // CHECK: format_args!("{}", s)
+    printf(
+        b"%u %lx %f\n\0" as *const u8 as *const libc::c_char,
+        x,
+        y,
+        z,
+    );
+}
+
+// Streams other than `stdout` and `stderr` are left alone.
+// CHECK-LABEL: unsafe fn print_to_file
+unsafe fn print_to_file(f: *mut libc::FILE, x: libc::c_int) {
+    // CHECK: fprintf(
+    // CHECK-NOT: format_args!
+    fprintf(f, b"%d\n\0" as *const u8 as *const libc::c_char, x);
+}
@@ -0,0 +1,97 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn qsort(
+        __base: *mut libc::c_void,
+        __nmemb: libc::size_t,
+        __size: libc::size_t,
+        __compar: Option<
+            unsafe extern "C" fn(*const libc::c_void, *const libc::c_void) -> libc::c_int,
+        >,
+    );
+}
+
+unsafe extern "C" fn cmp_ints(a: *const libc::c_void, b: *const libc::c_void) -> libc::c_int {
+    let x = *(a as *const libc::c_int);
+    let y = *(b as *const libc::c_int);
+    (x > y) as libc::c_int - (x < y) as libc::c_int
+}
+
+// Returns the difference of the elements rather than -1, 0, or 1.
+unsafe extern "C" fn cmp_diff(a: *const libc::c_void, b: *const libc::c_void) -> libc::c_int {
+    *(a as *const libc::c_int) - *(b as *const libc::c_int)
+}
+
+// CHECK-LABEL: unsafe fn sort_ints
+unsafe fn sort_ints(p: *mut libc::c_int, n: libc::size_t) {
+    // CHECK: base[..n as usize].sort_by(|a, b| cmp_ints({{.*}}).cmp(&0))
+    // CHECK-NOT: qsort(
+    qsort(
+        p as *mut libc::c_void,
+        n,
+        ::std::mem::size_of::<libc::c_int>() as libc::size_t,
+        Some(
+            cmp_ints
+                as unsafe extern "C" fn(*const libc::c_void, *const libc::c_void) -> libc::c_int,
+        ),
+    );
+    *p.offset(1) = 0;
+}
+
+// Only the sign of the comparator's result is used, through `cmp(&0)`, so any negative or positive
+// value orders the elements.
+// CHECK-LABEL: unsafe fn sort_by_diff
+unsafe fn sort_by_diff(p: *mut libc::c_int, n: libc::size_t) {
+    // CHECK: base[..n as usize].sort_by(|a, b| cmp_diff({{.*}}).cmp(&0))
+    // CHECK-NOT: qsort(
+    qsort(
+        p as *mut libc::c_void,
+        n,
+        ::std::mem::size_of::<libc::c_int>() as libc::size_t,
+        Some(
+            cmp_diff
+                as unsafe extern "C" fn(*const libc::c_void, *const libc::c_void) -> libc::c_int,
+        ),
+    );
+    *p.offset(1) = 0;
+}
+
+// The comparator is passed in by the caller, so the call is left alone.
+// CHECK-LABEL: unsafe fn sort_with
+unsafe fn sort_with(
+    p: *mut libc::c_int,
+    n: libc::size_t,
+    cmp: Option<unsafe extern "C" fn(*const libc::c_void, *const libc::c_void) -> libc::c_int>,
+) {
+    // CHECK-NOT: sort_by(
+    // CHECK: qsort(
+    // CHECK: cmp,
+    qsort(
+        p as *mut libc::c_void,
+        n,
+        ::std::mem::size_of::<libc::c_int>() as libc::size_t,
+        cmp,
+    );
+    *p.offset(1) = 0;
+}
+
+// The comparator depends on a runtime condition, so it isn't a known function, and the call is
+// left alone.
+// CHECK-LABEL: unsafe fn sort_either
+unsafe fn sort_either(p: *mut libc::c_int, n: libc::size_t, diff: bool) {
+    let cmp = if diff {
+        cmp_diff as unsafe extern "C" fn(*const libc::c_void, *const libc::c_void) -> libc::c_int
+    } else {
+        cmp_ints as unsafe extern "C" fn(*const libc::c_void, *const libc::c_void) -> libc::c_int
+    };
+    // CHECK-NOT: sort_by(
+    // CHECK: qsort(
+    qsort(
+        p as *mut libc::c_void,
+        n,
+        ::std::mem::size_of::<libc::c_int>() as libc::size_t,
+        Some(cmp),
+    );
+    *p.offset(1) = 0;
+}
@@ -0,0 +1,48 @@
+//! --rc-refcell
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+#[derive(Copy, Clone)]
+pub struct Counter {
+    pub count: i32,
+    pub total: i32,
+}
+
+// `a` and `b` point to the same counter, which is written through each while the other is still
+// in use, so neither is `UNIQUE`.  Both become handles to a shared `Rc<RefCell<Counter>>`.
+// CHECK-LABEL: unsafe extern "C" fn shared{{[<(]}}
+pub unsafe extern "C" fn shared() -> i32 {
+    // CHECK: std::rc::Rc::new(std::cell::RefCell::new(*
+    let a = malloc(std::mem::size_of::<Counter>() as libc::c_ulong) as *mut Counter;
+    // CHECK: = a.clone();
+    let b = a;
+    // CHECK: (*&mut *a.borrow_mut()).count = 1;
+    (*a).count = 1;
+    // CHECK: (*&mut *b.borrow_mut()).count = 2;
+    (*b).count = 2;
+    (*a).total = 3;
+    // CHECK: (*&*b.borrow()).count;
+    let n = (*b).count;
+    // CHECK: drop(
+    free(a as *mut libc::c_void);
+    n
+}
+
+// An object that's never aliased while it's mutated remains a `Box`.
+// CHECK-LABEL: unsafe extern "C" fn unshared{{[<(]}}
+pub unsafe extern "C" fn unshared() -> i32 {
+    // CHECK-NOT: RefCell
+    // CHECK: Box::new
+    let a = malloc(std::mem::size_of::<Counter>() as libc::c_ulong) as *mut Counter;
+    (*a).count = 1;
+    let n = (*a).count;
+    free(a as *mut libc::c_void);
+    n
+}
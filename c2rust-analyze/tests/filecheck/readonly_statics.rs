@@ -0,0 +1,49 @@
+//! --compile-output
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+#![allow(dead_code)]
+// Test rewriting of `static mut` tables that are never written.  The rewritten code is compiled, to
+// check that the `as_mut_ptr` calls on now-immutable statics still type-check.
+
+// Only read by indexing, so it becomes a `const`.
+static mut TABLE: [u8; 4] = [1, 2, 4, 8];
+// Read through a pointer, so it becomes a plain `static`.
+static mut NAMES: [i32; 3] = [10, 20, 30];
+// Written through `as_mut_ptr`, so it stays `static mut`.
+static mut BUF: [i32; 2] = [0, 0];
+// Written by index.
+static mut COUNTS: [i32; 2] = [0, 0];
+
+// CHECK-LABEL: ===== BEGIN
+// CHECK: const TABLE: [u8; 4] = [1, 2, 4, 8];
+// CHECK: static NAMES: [i32; 3] = [10, 20, 30];
+// CHECK: static mut BUF: [i32; 2] = [0, 0];
+// CHECK: static mut COUNTS: [i32; 2] = [0, 0];
+
+unsafe fn lookup(i: usize) -> u8 {
+    // CHECK: TABLE[i]
+    TABLE[i]
+}
+
+#[c2rust_analyze_test::fail_before_rewriting]
+// CHECK-LABEL: fn name_ptr(i: usize) -> *mut i32
+unsafe fn name_ptr(i: usize) -> *mut i32 {
+    // CHECK: (NAMES.as_ptr() as *mut i32).offset(i as isize)
+    NAMES.as_mut_ptr().offset(i as isize)
+}
+
+#[c2rust_analyze_test::fail_before_rewriting]
+// CHECK-LABEL: fn first_name_ptr() -> *mut i32
+unsafe fn first_name_ptr() -> *mut i32 {
+    // CHECK: let p = (NAMES.as_ptr() as *mut i32);
+    let p = NAMES.as_mut_ptr();
+    p
+}
+
+unsafe fn fill() {
+    *BUF.as_mut_ptr() = 1;
+}
+
+unsafe fn count(i: usize) {
+    COUNTS[i] += 1;
+}
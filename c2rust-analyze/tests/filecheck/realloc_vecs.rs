@@ -0,0 +1,57 @@
+//! --realloc-vecs
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn realloc(_: *mut libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+// A buffer that's grown with `realloc` becomes a `Vec`, which is allocated directly and resized in
+// place.
+// CHECK-LABEL: unsafe extern "C" fn grow{{[<(]}}
+pub unsafe extern "C" fn grow(n: libc::c_ulong) {
+    // CHECK: std::vec::Vec<i32>
+    // CHECK: let mut v = Vec::with_capacity(n);
+    // CHECK-NOT: into_boxed_slice
+    // CHECK: v
+    let mut buf: *mut i32 = malloc(2 * std::mem::size_of::<i32>() as libc::c_ulong) as *mut i32;
+    let mut capacity = 2;
+
+    let mut i = 0;
+    while i < n {
+        if i == capacity {
+            capacity *= 2;
+            // CHECK-NOT: Vec::from
+            // CHECK: let mut dest_ptr = src_ptr;
+            // CHECK: dest_ptr.resize_with(dest_n, || 0);
+            // CHECK-NOT: into_boxed_slice
+            buf = realloc(
+                buf as *mut libc::c_void,
+                capacity * std::mem::size_of::<i32>() as libc::c_ulong,
+            ) as *mut i32;
+        }
+        *buf.offset(i as isize) = i as i32;
+        i += 1;
+    }
+
+    // CHECK: drop(
+    free(buf as *mut libc::c_void);
+}
+
+// An owned buffer that's never resized remains a `Box<[T]>`.
+// CHECK-LABEL: unsafe extern "C" fn fixed_size{{[<(]}}
+pub unsafe extern "C" fn fixed_size(n: libc::c_ulong) {
+    // CHECK-NOT: Vec<i32>
+    // CHECK: v.into_boxed_slice()
+    let mut buf: *mut i32 = malloc(n * std::mem::size_of::<i32>() as libc::c_ulong) as *mut i32;
+    let mut i = 0;
+    while i < n {
+        *buf.offset(i as isize) = 0;
+        i += 1;
+    }
+    free(buf as *mut libc::c_void);
+}
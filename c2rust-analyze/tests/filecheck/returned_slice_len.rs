@@ -0,0 +1,54 @@
+// Test fusing a returned pointer with the length reported through an out-parameter.
+#![allow(dead_code)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+// The returned buffer holds exactly `n` elements, so the out-parameter is dropped from the
+// signature and callers take the length from the returned slice instead.
+// CHECK-LABEL: fn make_buf{{.*}}(n: usize) -> Box<[(i32)]>
+unsafe fn make_buf(n: usize, out_len: *mut usize) -> *mut i32 {
+    // CHECK: let out_len: &mut usize = &mut 0;
+    let p = malloc((n as libc::c_ulong).wrapping_mul(std::mem::size_of::<i32>() as libc::c_ulong))
+        as *mut i32;
+    *out_len = n;
+    p
+}
+
+// The length written isn't the length of the returned allocation, so nothing is fused.
+// CHECK-LABEL: fn find_tail{{.*}}(buf: &'h0 mut [(i32)], n: usize, out_len: &'h1 mut (usize)) -> &'h2 mut [(i32)]
+unsafe fn find_tail(buf: *mut i32, n: usize, out_len: *mut usize) -> *mut i32 {
+    *out_len = n - 1;
+    buf.offset(1)
+}
+
+// Two integer out-parameters, so it's unclear which one holds the length.
+// CHECK-LABEL: fn find_ambiguous
+// CHECK-SAME: out_len: &'h1 mut (usize), out_cap: &'h2 mut (usize)
+unsafe fn find_ambiguous(buf: *mut i32, out_len: *mut usize, out_cap: *mut usize) -> *mut i32 {
+    *out_len = 1;
+    *out_cap = 2;
+    buf.offset(1)
+}
+
+// CHECK-LABEL: fn caller
+unsafe fn caller() {
+    let mut len = 0;
+    // CHECK: let result = make_buf(arg0);
+    // CHECK: *arg1 = result.len() as usize;
+    let p = make_buf(4, &mut len);
+    *p.offset(1) = 5;
+    free(p as *mut libc::c_void);
+
+    let mut arr = [0; 4];
+    // CHECK-NOT: result.len()
+    let q = find_tail(arr.as_mut_ptr(), 4, &mut len);
+    *q.offset(1) = 5;
+    let mut cap = 0;
+    let r = find_ambiguous(arr.as_mut_ptr(), &mut len, &mut cap);
+    *r.offset(1) = 5;
+}
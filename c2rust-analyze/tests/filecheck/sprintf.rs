@@ -0,0 +1,56 @@
+#![feature(rustc_private)]
+#![feature(c_variadic)]
+extern crate libc;
+
+extern "C" {
+    fn sprintf(_: *mut libc::c_char, _: *const libc::c_char, _: ...) -> libc::c_int;
+    fn snprintf(
+        _: *mut libc::c_char,
+        _: libc::size_t,
+        _: *const libc::c_char,
+        _: ...
+    ) -> libc::c_int;
+}
+
+// CHECK-LABEL: unsafe fn format_int
+unsafe fn format_int(buf: *mut libc::c_char, x: libc::c_int) -> libc::c_int {
+    // CHECK: std::io::Write::write_fmt(&mut out, format_args!("x = {:5}!", a0)).unwrap()
+    // CHECK: dest[out.len()] = 0
+    sprintf(
+        buf,
+        b"x = %5d!\0" as *const u8 as *const libc::c_char,
+        x,
+    )
+}
+
+// CHECK-LABEL: unsafe fn format_str_bounded
+unsafe fn format_str_bounded(
+    buf: *mut libc::c_char,
+    n: libc::size_t,
+    name: *const libc::c_char,
+    x: libc::c_double,
+) -> libc::c_int {
+    // CHECK: format_args!("hello, ")
+    // CHECK: out.extend(a0.iter().take_while(|&&c| c != 0).map(|&c| c as u8))
+    // CHECK: format_args!(": {:.2}", a1)
+    // CHECK: let len = out.len().min(n - 1);
+    snprintf(
+        buf,
+        n,
+        b"hello, %s: %.2f\0" as *const u8 as *const libc::c_char,
+        name,
+        x,
+    )
+}
+
+// Non-constant format strings are left alone.
+// CHECK-LABEL: unsafe fn format_dynamic
+unsafe fn format_dynamic(
+    buf: *mut libc::c_char,
+    fmt: *const libc::c_char,
+    x: libc::c_int,
+) -> libc::c_int {
+    // CHECK: sprintf(
+    // CHECK-NOT: format_args!
+    sprintf(buf, fmt, x)
+}
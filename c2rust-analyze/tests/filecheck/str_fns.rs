@@ -0,0 +1,34 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn strlen(_: *const libc::c_char) -> libc::size_t;
+    fn strcmp(_: *const libc::c_char, _: *const libc::c_char) -> libc::c_int;
+    fn strcpy(_: *mut libc::c_char, _: *const libc::c_char) -> *mut libc::c_char;
+    fn strncpy(_: *mut libc::c_char, _: *const libc::c_char, _: libc::size_t) -> *mut libc::c_char;
+}
+
+// CHECK-LABEL: unsafe fn string_len
+unsafe fn string_len(s: *const libc::c_char) -> libc::size_t {
+    // CHECK: s.iter().take_while(|&&c| c != 0).count() as usize
+    strlen(s)
+}
+
+// CHECK-LABEL: unsafe fn string_eq
+unsafe fn string_eq(a: *const libc::c_char, b: *const libc::c_char) -> bool {
+    // CHECK: a.iter().take_while(|&&c| c != 0).map(|&c| c as u8).cmp(b.iter()
+    strcmp(a, b) == 0
+}
+
+// CHECK-LABEL: unsafe fn string_copy
+unsafe fn string_copy(dest: *mut libc::c_char, src: *const libc::c_char) {
+    // CHECK: dest[..n].copy_from_slice(&src[..n])
+    strcpy(dest, src);
+}
+
+// CHECK-LABEL: unsafe fn string_copy_n
+unsafe fn string_copy_n(dest: *mut libc::c_char, src: *const libc::c_char, n: libc::size_t) {
+    // CHECK: src.iter().take(n).position(|&c| c == 0).unwrap_or(n)
+    // CHECK: dest[len..n].fill(0)
+    strncpy(dest, src, n);
+}
@@ -0,0 +1,31 @@
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn strdup(_: *const libc::c_char) -> *mut libc::c_char;
+    fn strndup(_: *const libc::c_char, _: libc::size_t) -> *mut libc::c_char;
+    fn free(_: *mut libc::c_void);
+}
+
+// CHECK-LABEL: unsafe fn dup_and_free
+unsafe fn dup_and_free(s: *const libc::c_char) -> libc::c_char {
+    // CHECK: let len = s.iter().position(|&c| c == 0).unwrap();
+    // CHECK: Box::from(&s[..=len])
+    let p = strdup(s);
+    let c = *p.offset(1);
+    // CHECK: drop(
+    free(p as *mut libc::c_void);
+    c
+}
+
+// CHECK-LABEL: unsafe fn dup_n_and_free
+unsafe fn dup_n_and_free(s: *const libc::c_char, n: libc::size_t) -> libc::c_char {
+    // CHECK: let len = s.iter().take(n).position(|&c| c == 0).unwrap_or(n);
+    // CHECK: v.push(0)
+    // CHECK: v.into_boxed_slice()
+    let p = strndup(s, n);
+    let c = *p;
+    // CHECK: drop(
+    free(p as *mut libc::c_void);
+    c
+}
@@ -0,0 +1,40 @@
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+pub struct Thing {
+    pub x: i32,
+}
+
+// The wrapper has no `PointerId` of its own, so its field is rewritten like any other pointer.
+// CHECK-LABEL: pub struct Handle<'h0>(
+// CHECK-SAME: &'h0 (Thing)
+#[repr(transparent)]
+pub struct Handle(*mut Thing);
+
+// CHECK-LABEL: unsafe fn make_handle
+unsafe fn make_handle(t: *mut Thing) -> Handle {
+    // CHECK: Handle(
+    Handle(t)
+}
+
+// CHECK-LABEL: unsafe fn handle_x
+unsafe fn handle_x(h: Handle) -> i32 {
+    (*h.0).x
+}
+
+// `keep` isn't rewritten, so `Kept` keeps its raw pointer even though `kept_x` only reads
+// through it.
+// CHECK-LABEL: pub struct Kept(
+// CHECK-SAME: *mut Thing
+#[repr(transparent)]
+pub struct Kept(*mut Thing);
+
+#[c2rust_analyze_test::fixed_signature]
+unsafe fn keep(k: Kept) -> Kept {
+    k
+}
+
+// CHECK-LABEL: unsafe fn kept_x
+unsafe fn kept_x(k: Kept) -> i32 {
+    (*k.0).x
+}
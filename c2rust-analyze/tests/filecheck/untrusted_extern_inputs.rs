@@ -0,0 +1,32 @@
+//! --untrusted-extern-inputs check --rewrite-paths exported,internal
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+// `caller` is not listed in --rewrite-paths, so it calls the others through shims.
+unsafe fn caller(x: *mut i32) {
+    // CHECK: exported_shim(x)
+    exported(x);
+    // CHECK: internal_shim(x)
+    internal(x);
+}
+
+// Pointers passed by external callers may be null, even if they're never null in this crate.
+// CHECK-LABEL: pub unsafe extern "C" fn exported{{[<(]}}
+// CHECK-SAME: p: core::option::Option<&{{('[^ ]* )?}}mut (i32)>
+#[no_mangle]
+pub unsafe extern "C" fn exported(p: *mut i32) {
+    *p = 1;
+}
+// CHECK-LABEL: unsafe fn exported_shim(arg0: *mut i32)
+// CHECK: let safe_arg0 = if arg0.is_null() { None } else { Some({{.*}}arg0{{.*}}) };
+// CHECK: exported(safe_arg0)
+
+// CHECK-LABEL: unsafe fn internal{{[<(]}}
+// CHECK-SAME: p: &{{('[^ ]* )?}}mut (i32)
+unsafe fn internal(p: *mut i32) {
+    *p = 2;
+}
+// CHECK-LABEL: unsafe fn internal_shim(arg0: *mut i32)
+// CHECK: assert!(!arg0.is_null(), "null pointer passed as arg0 of internal");
+// CHECK: let safe_arg0 = {{.*}}arg0
+// CHECK: internal(safe_arg0)
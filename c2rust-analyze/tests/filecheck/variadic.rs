@@ -0,0 +1,13 @@
+#![feature(c_variadic)]
+
+unsafe extern "C" fn log_ptrs(_n: i32, _args: ...) {}
+
+// Pointers passed through the variadic part of a call are cast back to their original raw types.
+// CHECK-LABEL: unsafe fn call_variadic{{[<(]}}
+// CHECK-SAME: p: &{{('[^ ]* )?}}(i32)
+unsafe fn call_variadic(p: *const i32) -> i32 {
+    let x = *p;
+    // CHECK: log_ptrs(1, {{.*}}addr_of!(*{{.*}}p{{.*}}))
+    log_ptrs(1, p);
+    x
+}
@@ -0,0 +1,113 @@
+//! --rewrite-vecs
+#![feature(rustc_private)]
+extern crate libc;
+
+extern "C" {
+    fn realloc(_: *mut libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+// CHECK-LABEL: struct IntVec
+// CHECK-NOT: Copy
+// CHECK: data: Vec<libc::c_int>
+// CHECK-NOT: cap:
+// CHECK: }
+#[derive(Copy, Clone)]
+pub struct IntVec {
+    pub data: *mut libc::c_int,
+    pub len: libc::c_ulong,
+    pub cap: libc::c_ulong,
+}
+
+// CHECK-LABEL: unsafe fn int_vec_new
+// CHECK: data: Vec::new(),
+// CHECK-NOT: len:
+unsafe fn int_vec_new() -> IntVec {
+    IntVec {
+        data: 0 as *mut libc::c_int,
+        len: 0,
+        cap: 0,
+    }
+}
+
+// CHECK-LABEL: unsafe fn int_vec_push
+// CHECK: (*v).data.push(x)
+// CHECK-NOT: realloc
+// CHECK: }
+unsafe fn int_vec_push(v: *mut IntVec, x: libc::c_int) {
+    if (*v).len == (*v).cap {
+        (*v).cap = if (*v).cap == 0 as libc::c_ulong {
+            4 as libc::c_ulong
+        } else {
+            (*v).cap.wrapping_mul(2 as libc::c_ulong)
+        };
+        (*v).data = realloc(
+            (*v).data as *mut libc::c_void,
+            (*v).cap.wrapping_mul(4 as libc::c_ulong),
+        ) as *mut libc::c_int;
+    }
+    let fresh0 = (*v).len;
+    (*v).len = ((*v).len).wrapping_add(1);
+    *(*v).data.offset(fresh0 as isize) = x;
+}
+
+// CHECK-LABEL: unsafe fn int_vec_pop
+// CHECK: (*v).data.pop().unwrap_or(-1)
+unsafe fn int_vec_pop(v: *mut IntVec) -> libc::c_int {
+    if (*v).len == 0 as libc::c_ulong {
+        return -1;
+    }
+    (*v).len = ((*v).len).wrapping_sub(1);
+    return *(*v).data.offset((*v).len as isize);
+}
+
+// CHECK-LABEL: unsafe fn int_vec_sum
+unsafe fn int_vec_sum(v: *mut IntVec) -> libc::c_int {
+    let mut sum = 0;
+    let mut i = 0;
+    // CHECK: ((*v).data.len()) as libc::c_ulong
+    while (i as libc::c_ulong) < (*v).len {
+        // CHECK: ((*v).data)[(i as isize) as usize]
+        sum += *(*v).data.offset(i as isize);
+        i += 1;
+    }
+    sum
+}
+
+// CHECK-LABEL: unsafe fn int_vec_free
+unsafe fn int_vec_free(v: *mut IntVec) {
+    // CHECK: drop(std::mem::take(&mut ((*v).data)))
+    free((*v).data as *mut libc::c_void);
+    // CHECK: std::ptr::write(core::ptr::addr_of_mut!(((*v).data)), Vec::new())
+    (*v).data = 0 as *mut libc::c_int;
+    // CHECK: ((*v).data).clear()
+    (*v).len = 0;
+    (*v).cap = 0;
+}
+
+// `Pair` is copied out of a pointer, so it keeps its fields.
+// CHECK-LABEL: struct Pair
+// CHECK: data: *mut libc::c_int
+// CHECK: cap: libc::c_ulong
+#[derive(Copy, Clone)]
+pub struct Pair {
+    pub data: *mut libc::c_int,
+    pub len: libc::c_ulong,
+    pub cap: libc::c_ulong,
+}
+
+unsafe fn pair_push(v: *mut Pair, x: libc::c_int) {
+    if (*v).len == (*v).cap {
+        (*v).cap = ((*v).cap).wrapping_add(8);
+        (*v).data = realloc(
+            (*v).data as *mut libc::c_void,
+            (*v).cap.wrapping_mul(4 as libc::c_ulong),
+        ) as *mut libc::c_int;
+    }
+    *(*v).data.offset((*v).len as isize) = x;
+    (*v).len += 1;
+}
+
+unsafe fn pair_copy(v: *mut Pair) -> Pair {
+    *v
+}
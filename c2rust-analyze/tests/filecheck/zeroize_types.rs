@@ -0,0 +1,59 @@
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+use std::ptr;
+
+extern "C" {
+    fn calloc(_: libc::c_ulong, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub enum Color {
+    Green = 1,
+    Red = 0,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub union Num {
+    pub i: i32,
+    pub d: f64,
+}
+
+pub struct Node {
+    pub val: f64,
+    pub next: *mut Node,
+    pub cb: Option<unsafe extern "C" fn(i32) -> i32>,
+    pub color: Color,
+    pub num: Num,
+    pub items: *const i32,
+}
+
+// Each field is zeroized according to its type: floats to `0.0`, the nullable `next` pointer to
+// `None`, the function pointer `Option` to `None`, the enum to its zero variant, and the union
+// through its largest field.  `items` is rewritten to a non-null slice, so it is zeroized to an
+// empty slice.
+// CHECK-LABEL: unsafe extern "C" fn new_node{{[<(]}}
+pub unsafe extern "C" fn new_node() -> f64 {
+    // CHECK: Box::new({{.*}}Node {
+    // CHECK-NEXT: val: 0.0,
+    // CHECK-NEXT: next: None,
+    // CHECK-NEXT: cb: None,
+    // CHECK-NEXT: color: {{.*}}Color::Red,
+    // CHECK-NEXT: num: {{.*}}Num { d: 0.0 },
+    // CHECK-NEXT: items: &[],
+    let mut n = calloc(1, ::std::mem::size_of::<Node>() as libc::c_ulong) as *mut Node;
+    (*n).next = ptr::null_mut();
+    let val = (*n).val;
+    free(n as *mut libc::c_void);
+    val
+}
+
+// CHECK-LABEL: unsafe extern "C" fn second_item{{[<(]}}
+pub unsafe extern "C" fn second_item(n: *const Node) -> i32 {
+    *(*n).items.offset(1)
+}
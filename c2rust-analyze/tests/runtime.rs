@@ -0,0 +1,159 @@
+//! Runtime regression tests for the rewriter.
+//!
+//! Each test takes a crate from `analysis/tests`, copies it to a scratch directory, rewrites the
+//! copy in place with `c2rust-analyze`, and then runs both the original and the rewritten crate.
+//! The two runs must exit with the same status and produce the same output, which checks that
+//! the rewrites preserve behavior and not only that the rewritten code compiles.
+
+pub mod common;
+
+use crate::common::Analyze;
+use fs_err::{self as fs, File};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use toml_edit::Document;
+
+/// Copy the crate at `src` to `dest`, skipping build outputs.  Relative `path` dependencies in
+/// `Cargo.toml` are made absolute so they still resolve from the new location, and an empty
+/// `[workspace]` table is added so the copy, which lives under this repo's `target` directory,
+/// isn't treated as an unlisted member of the root workspace.
+fn copy_crate(src: &Path, dest: &Path) {
+    fn copy_dir(src: &Path, dest: &Path) {
+        fs::create_dir_all(dest).unwrap();
+        for entry in fs::read_dir(src).unwrap() {
+            let entry = entry.unwrap();
+            let name = entry.file_name();
+            if name == "target" {
+                continue;
+            }
+            let path = entry.path();
+            if path.is_dir() {
+                copy_dir(&path, &dest.join(&name));
+            } else {
+                fs::copy(&path, dest.join(&name)).unwrap();
+            }
+        }
+    }
+
+    if dest.exists() {
+        fs::remove_dir_all(dest).unwrap();
+    }
+    copy_dir(src, dest);
+
+    let manifest_path = dest.join("Cargo.toml");
+    let mut manifest = fs::read_to_string(&manifest_path)
+        .unwrap()
+        .parse::<Document>()
+        .unwrap();
+    if let Some(deps) = manifest["dependencies"].as_table_like_mut() {
+        for (_, dep) in deps.iter_mut() {
+            let path = match dep.as_table_like_mut().and_then(|t| t.get_mut("path")) {
+                Some(x) => x,
+                None => continue,
+            };
+            let abs_path = src.join(path.as_str().unwrap()).canonicalize().unwrap();
+            *path = toml_edit::value(abs_path.to_str().unwrap());
+        }
+    }
+    if !manifest.contains_key("workspace") {
+        manifest["workspace"] = toml_edit::table();
+    }
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+}
+
+/// Rewrite the crate at `dir` in place, logging the analysis output to `dir/analysis.txt`.
+fn rewrite_crate(analyze: &Analyze, dir: &Path) {
+    let output_path = dir.join("analysis.txt");
+    let output_stdout = File::create(&output_path).unwrap();
+    let output_stderr = File::try_clone(&output_stdout).unwrap();
+
+    let mut cmd = Command::new(analyze.path());
+    cmd.args([
+        "--rewrite-mode",
+        "inplace",
+        "--",
+        "check",
+        "--manifest-path",
+    ])
+    .arg(dir.join("Cargo.toml"))
+    .env("C2RUST_ANALYZE_TEST_DONT_CATCH_PANIC", "1")
+    .stdout(output_stdout.into_parts().0)
+    .stderr(output_stderr.into_parts().0);
+    let status = cmd.status().unwrap();
+    if !status.success() {
+        let output = fs::read_to_string(&output_path).unwrap();
+        panic!("c2rust-analyze failed with status {status}:\n> {cmd:?}\n{output}");
+    }
+}
+
+/// Build and run the crate at `dir`.  Both the original and the rewritten crate get the same
+/// (empty) stdin and arguments, so any difference in output comes from the rewrites.
+fn run_crate(dir: &Path) -> Output {
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    Command::new(cargo)
+        .args(["run", "--quiet", "--manifest-path"])
+        .arg(dir.join("Cargo.toml"))
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+fn test(crate_name: &str) {
+    let analyze = Analyze::resolve();
+    let orig_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../analysis/tests")
+        .join(crate_name)
+        .canonicalize()
+        .unwrap();
+    let new_dir: PathBuf = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("runtime")
+        .join(crate_name);
+
+    // Run the original first, so a crate that doesn't build or run on its own fails here
+    // instead of being compared against an equally broken rewrite.
+    let expected = run_crate(&orig_dir);
+    assert!(
+        expected.status.success(),
+        "original crate {crate_name} failed with status {}:\n{}",
+        expected.status,
+        String::from_utf8_lossy(&expected.stderr),
+    );
+
+    copy_crate(&orig_dir, &new_dir);
+    rewrite_crate(&analyze, &new_dir);
+
+    let actual = run_crate(&new_dir);
+    let stderr = String::from_utf8_lossy(&actual.stderr);
+    assert_eq!(
+        expected.status, actual.status,
+        "rewritten crate {crate_name} exited differently:\n{stderr}"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&expected.stdout),
+        String::from_utf8_lossy(&actual.stdout),
+        "rewritten crate {crate_name} produced different output:\n{stderr}"
+    );
+}
+
+macro_rules! define_test {
+    ($name:ident, $crate_name:expr) => {
+        #[test]
+        fn $name() {
+            test($crate_name);
+        }
+    };
+}
+
+macro_rules! define_tests {
+    ($($name:ident => $crate_name:expr,)*) => {
+        $(define_test! { $name, $crate_name })*
+    }
+}
+
+define_tests! {
+    lighttpd => "lighttpd",
+    lighttpd_minimal => "lighttpd-minimal",
+    minimal => "minimal",
+    misc => "misc",
+}
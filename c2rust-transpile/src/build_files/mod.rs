@@ -294,6 +294,11 @@ fn emit_cargo_toml<'lcmd>(
             "lib_rs_file": get_lib_rs_file_name(tcfg),
             "binaries": binaries,
             "dependencies": dependencies,
+            "features": tcfg
+                .feature_configs
+                .iter()
+                .map(|config| &config.feature)
+                .collect::<Vec<_>>(),
         });
         json.as_object_mut().unwrap().extend(
             crate_json
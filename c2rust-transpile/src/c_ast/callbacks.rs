@@ -0,0 +1,276 @@
+//! Retyping of `void *` struct fields that only ever hold callbacks.
+//!
+//! C code often stores a callback in a `void *` field and casts it back to its function pointer
+//! type at each call.  When every access to such a field is a store of a function pointer or
+//! null, a null check, or a cast back to a single function pointer type, we give the field that
+//! function pointer type, so that it translates to an `Option<unsafe extern "C" fn(..)>` instead
+//! of a `*mut c_void`.  Stores of functions with a different signature become casts between
+//! function pointer types, which the translator can turn into trampolines.
+//!
+//! This only sees the current translation unit, so a field is retyped only if this translation
+//! unit uses it as a callback.
+
+use crate::c_ast::*;
+use indexmap::IndexMap;
+use std::collections::HashSet;
+
+/// The accesses to a `void *` field.
+#[derive(Default)]
+struct FieldUses {
+    /// The `Member` expressions of the field.
+    members: Vec<CExprId>,
+    /// The `Member` expressions that are part of a recognized use.
+    handled: HashSet<CExprId>,
+    /// The expressions other than `members` whose type changes along with the field's: loads of
+    /// the field, and assignments and stored values.
+    retyped: Vec<CExprId>,
+    /// The function pointer types the field is cast to when loaded.
+    load_types: Vec<CQualTypeId>,
+    /// The function pointer types of the values stored to the field.
+    store_types: Vec<CQualTypeId>,
+    /// Set if a value of an unknown type is stored to the field.
+    unknown_store: bool,
+}
+
+impl TypedAstContext {
+    /// The expression `id` and the `Paren` expressions it is wrapped in, outermost first.
+    fn paren_chain(&self, mut id: CExprId) -> Vec<CExprId> {
+        let mut chain = vec![id];
+        while let Some(&CExprKind::Paren(_, inner)) = self.c_exprs.get(&id).map(|expr| &expr.kind) {
+            id = inner;
+            chain.push(id);
+        }
+        chain
+    }
+
+    /// Is `field` a `void *` field of a struct?
+    fn is_void_ptr_field(&self, field: CFieldId) -> bool {
+        let typ = match self.get_decl(&field).map(|decl| &decl.kind) {
+            Some(&CDeclKind::Field { typ, .. }) => typ,
+            _ => return false,
+        };
+        let is_struct_field = matches!(
+            self.parents.get(&field).map(|&record| &self[record].kind),
+            Some(CDeclKind::Struct { .. })
+        );
+        is_struct_field
+            && self
+                .get_pointee_qual_type(typ.ctype)
+                .map_or(false, |pointee| {
+                    self.resolve_type(pointee.ctype).kind == CTypeKind::Void
+                })
+    }
+
+    /// If `id` accesses a `void *` field of a struct, return the field.
+    fn void_ptr_member(&self, id: CExprId) -> Option<CFieldId> {
+        match self[id].kind {
+            CExprKind::Member(_, _, field, _, _) if self.is_void_ptr_field(field) => Some(field),
+            _ => None,
+        }
+    }
+
+    /// If `id` loads a `void *` field, return the field, the `Member` expression, and the
+    /// expressions of the load.
+    fn void_ptr_load(&self, id: CExprId) -> Option<(CFieldId, CExprId, Vec<CExprId>)> {
+        let mut chain = self.paren_chain(id);
+        let member = match self[id].kind {
+            CExprKind::ImplicitCast(_, member, CastKind::LValueToRValue, _, _) => member,
+            _ => return None,
+        };
+        let field = self.void_ptr_member(member)?;
+        let mut member_chain = self.paren_chain(member);
+        let member = member_chain.pop().unwrap();
+        chain.extend(member_chain);
+        Some((field, member, chain))
+    }
+
+    /// Record a store of `value` to a field, or the initialization of a field with `value`.
+    fn record_callback_store(&self, uses: &mut FieldUses, value: CExprId) {
+        let chain = self.paren_chain(value);
+        let stored = match self[value].kind {
+            _ if self.is_null_expr(value) => None,
+            CExprKind::ImplicitValueInit(..) => None,
+            CExprKind::ImplicitCast(_, inner, CastKind::BitCast, _, _)
+            | CExprKind::ExplicitCast(_, inner, CastKind::BitCast, _, _) => {
+                match self[inner].kind.get_qual_type() {
+                    Some(ty) if self.is_function_pointer(ty.ctype) => Some(ty),
+                    _ => {
+                        uses.unknown_store = true;
+                        return;
+                    }
+                }
+            }
+            _ => {
+                uses.unknown_store = true;
+                return;
+            }
+        };
+        uses.store_types.extend(stored);
+        uses.retyped.extend(chain);
+    }
+
+    /// Find the `void *` struct fields that are only used to hold callbacks of one type, and give
+    /// them that function pointer type.
+    pub fn retype_callback_fields(&mut self) {
+        // The syntactic forms of initializer lists list their initializers in source order rather
+        // than field order, so only look at their semantic forms.
+        let syntactic_init_lists = self
+            .c_exprs
+            .values()
+            .filter_map(|expr| match expr.kind {
+                CExprKind::InitList(_, _, _, syntactic) => syntactic,
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        let mut fields = IndexMap::<CFieldId, FieldUses>::new();
+        // Expressions used as booleans.
+        let mut conditions = Vec::new();
+        for (&id, expr) in &self.c_exprs {
+            match expr.kind {
+                CExprKind::Member(..) => {
+                    if let Some(field) = self.void_ptr_member(id) {
+                        fields.entry(field).or_default().members.push(id);
+                    }
+                }
+
+                // `s->cb = (void *)f`
+                CExprKind::Binary(_, BinOp::Assign, lhs, rhs, _, _) => {
+                    let mut chain = self.paren_chain(lhs);
+                    let member = chain.pop().unwrap();
+                    if let Some(field) = self.void_ptr_member(member) {
+                        let uses = fields.entry(field).or_default();
+                        uses.handled.insert(member);
+                        uses.retyped.push(id);
+                        uses.retyped.extend(chain);
+                        self.record_callback_store(uses, rhs);
+                    }
+                }
+
+                // `((void (*)(int)) s->cb)(x)`
+                CExprKind::ImplicitCast(ty, inner, CastKind::BitCast, _, _)
+                | CExprKind::ExplicitCast(ty, inner, CastKind::BitCast, _, _)
+                    if self.is_function_pointer(ty.ctype) =>
+                {
+                    if let Some((field, member, load)) = self.void_ptr_load(inner) {
+                        let uses = fields.entry(field).or_default();
+                        uses.handled.insert(member);
+                        uses.retyped.extend(load);
+                        uses.load_types.push(ty);
+                    }
+                }
+
+                // `if (s->cb && !t->cb)`
+                CExprKind::ImplicitCast(_, cond, CastKind::PointerToBoolean, _, _)
+                | CExprKind::Unary(_, UnOp::Not, cond, _)
+                | CExprKind::Conditional(_, cond, _, _) => conditions.push(cond),
+                CExprKind::Binary(_, BinOp::And | BinOp::Or, lhs, rhs, _, _) => {
+                    conditions.extend([lhs, rhs])
+                }
+
+                // `s->cb != NULL`
+                CExprKind::Binary(_, BinOp::EqualEqual | BinOp::NotEqual, lhs, rhs, _, _) => {
+                    if self.is_null_expr(rhs) {
+                        conditions.push(lhs);
+                    } else if self.is_null_expr(lhs) {
+                        conditions.push(rhs);
+                    }
+                }
+
+                // `struct ops ops = { (void *)f };`
+                CExprKind::InitList(ty, ref inits, None, _)
+                    if !syntactic_init_lists.contains(&id) =>
+                {
+                    let record = match self.resolve_type(ty.ctype).kind {
+                        CTypeKind::Struct(record) => record,
+                        _ => continue,
+                    };
+                    let record_fields = match self[record].kind {
+                        CDeclKind::Struct {
+                            fields: Some(ref fields),
+                            ..
+                        } => fields,
+                        _ => continue,
+                    };
+                    for (&field, &init) in record_fields.iter().zip(inits) {
+                        if self.is_void_ptr_field(field) {
+                            self.record_callback_store(fields.entry(field).or_default(), init);
+                        }
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        conditions.extend(self.c_stmts.values().filter_map(|stmt| match stmt.kind {
+            CStmtKind::If { scrutinee, .. } => Some(scrutinee),
+            CStmtKind::While { condition, .. } | CStmtKind::DoWhile { condition, .. } => {
+                Some(condition)
+            }
+            CStmtKind::ForLoop { condition, .. } => condition,
+            _ => None,
+        }));
+        // A null check of a function pointer translates to `is_some()` or `is_none()`.
+        for cond in conditions {
+            if let Some((field, member, load)) = self.void_ptr_load(cond) {
+                let uses = fields.entry(field).or_default();
+                uses.handled.insert(member);
+                uses.retyped.extend(load);
+            }
+        }
+
+        let mut retyped = Vec::new();
+        for (field, uses) in fields {
+            if uses.unknown_store || uses.members.iter().any(|m| !uses.handled.contains(m)) {
+                continue;
+            }
+            // Calls must all agree on the type of the callback.  If it is never called here, the
+            // stores must agree instead.
+            let types = if uses.load_types.is_empty() {
+                &uses.store_types
+            } else {
+                &uses.load_types
+            };
+            let fn_ty = match types.first() {
+                Some(&ty) => ty,
+                None => continue,
+            };
+            let fn_ctype = self.resolve_type_id(fn_ty.ctype);
+            if types
+                .iter()
+                .any(|ty| self.resolve_type_id(ty.ctype) != fn_ctype)
+            {
+                continue;
+            }
+            retyped.push((field, fn_ty.ctype, uses));
+        }
+
+        for (field, fn_ctype, uses) in retyped {
+            if let Some(CDeclKind::Field { typ, .. }) =
+                self.c_decls.get_mut(&field).map(|decl| &mut decl.kind)
+            {
+                typ.ctype = fn_ctype;
+            }
+            for id in uses.members.into_iter().chain(uses.retyped) {
+                self.set_expr_ctype(id, fn_ctype);
+            }
+        }
+    }
+
+    /// Replace the type of the expression `id` with `ctype`, keeping its qualifiers.
+    fn set_expr_ctype(&mut self, id: CExprId, ctype: CTypeId) {
+        let ty = match self.c_exprs.get_mut(&id).map(|expr| &mut expr.kind) {
+            Some(
+                CExprKind::Member(ty, ..)
+                | CExprKind::Binary(ty, ..)
+                | CExprKind::ImplicitCast(ty, ..)
+                | CExprKind::ExplicitCast(ty, ..)
+                | CExprKind::Paren(ty, _)
+                | CExprKind::ImplicitValueInit(ty),
+            ) => ty,
+            _ => return,
+        };
+        ty.ctype = ctype;
+    }
+}
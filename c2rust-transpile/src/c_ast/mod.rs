@@ -36,6 +36,7 @@ pub type CEnumConstantId = CDeclId; // Enum's need to point to child 'DeclKind::
 pub use self::conversion::*;
 pub use self::print::Printer;
 
+mod callbacks;
 mod conversion;
 pub mod iterators;
 mod print;
@@ -86,10 +87,26 @@ pub struct TypedAstContext {
     // and the value is the decl id to the corresponding structure
     pub prenamed_decls: IndexMap<CDeclId, CDeclId>,
 
+    // Externally visible functions that are unreachable from the configured entry points, whose
+    // bodies have been pruned
+    pub pruned_functions: HashSet<CDeclId>,
+
     pub va_list_kind: BuiltinVaListKind,
     pub target: String,
 }
 
+/// The externally visible functions that the definitions in a translation unit refer to, for
+/// finding the functions reachable from the entry points across translation units.
+#[derive(Debug, Clone, Default)]
+pub struct ExternalReferences {
+    /// The functions referred to by the definitions that are always kept, like externally
+    /// visible variables.
+    pub from_roots: HashSet<String>,
+    /// The functions referred to by each externally visible function definition, directly or
+    /// through the internal declarations it refers to.
+    pub from_functions: HashMap<String, HashSet<String>>,
+}
+
 /// Comments associated with a typed AST context
 #[derive(Debug, Clone)]
 pub struct CommentContext {
@@ -185,6 +202,7 @@ impl TypedAstContext {
 
             comments: Vec::new(),
             prenamed_decls: IndexMap::new(),
+            pruned_functions: HashSet::new(),
             va_list_kind: BuiltinVaListKind::CharPtrBuiltinVaList,
             target: String::new(),
         }
@@ -526,6 +544,31 @@ impl TypedAstContext {
         }
     }
 
+    /// Pessimistically try to check if an integer expression is a compile-time constant whose
+    /// translation can be used in a Rust const context, such as the length of an array type. If
+    /// it isn't, or we can't tell that it is, return `false`.
+    pub fn is_const_expr(&self, expr: CExprId) -> bool {
+        use CExprKind::*;
+        let is_const = |expr| self.is_const_expr(expr);
+        match self.index(expr).kind {
+            Literal(_, CLiteral::Integer(..)) | ConstantExpr(..) => true,
+
+            // `sizeof` of a variable-length array is evaluated at runtime
+            UnaryType(_, _, _, arg_ty) => !matches!(
+                self.resolve_type(arg_ty.ctype).kind,
+                CTypeKind::VariableArray(..)
+            ),
+
+            ImplicitCast(_, e, _, _, _) | ExplicitCast(_, e, _, _, _) | Paren(_, e) => is_const(e),
+            Unary(_, UnOp::Negate | UnOp::Plus | UnOp::Complement, e, _) => is_const(e),
+
+            Binary(_, op, _, _, _, _) if op.is_assignment() || op == BinOp::Comma => false,
+            Binary(_, _, lhs, rhs, _, _) => is_const(lhs) && is_const(rhs),
+
+            _ => false,
+        }
+    }
+
     // Pessimistically try to check if an expression doesn't return. If it does, or we can't tell
     /// that it doesn't, return `false`.
     pub fn expr_diverges(&self, expr_id: CExprId) -> bool {
@@ -549,7 +592,17 @@ impl TypedAstContext {
         }
     }
 
-    pub fn prune_unwanted_decls(&mut self, want_unused_functions: bool) {
+    /// Prune the declarations that aren't reachable from a root: an externally visible function
+    /// or variable, or any function if `want_unused_functions` is set.
+    ///
+    /// If `entry_points` is given, only the externally visible functions it names are roots.  The
+    /// other externally visible functions that aren't reachable from them are kept as
+    /// declarations, without their bodies, and recorded in [`Self::pruned_functions`].
+    pub fn prune_unwanted_decls(
+        &mut self,
+        want_unused_functions: bool,
+        entry_points: Option<&HashSet<String>>,
+    ) {
         // Starting from a set of root declarations, walk each one to find declarations it
         // depends on. Then walk each of those, recursively.
 
@@ -557,6 +610,8 @@ impl TypedAstContext {
         let mut to_walk: Vec<CDeclId> = Vec::new();
         // Declarations accessible from a root.
         let mut wanted: HashSet<CDeclId> = HashSet::new();
+        // Externally visible functions that aren't entry points.
+        let mut non_entry_points: Vec<CDeclId> = Vec::new();
 
         // Mark all the roots as wanted.  Roots are all top-level functions and variables that might
         // be visible from another compilation unit.
@@ -571,10 +626,19 @@ impl TypedAstContext {
                     is_global: true,
                     is_inline,
                     is_inline_externally_visible,
+                    ref name,
                     ..
                     // Depending on the C specification and dialect, an inlined function
                     // may be externally visible. We rely on clang to determine visibility.
-                } if !is_inline || is_inline_externally_visible => true,
+                } if !is_inline || is_inline_externally_visible => {
+                    let is_entry_point = entry_points.map_or(true, |entry_points| {
+                        entry_points.contains(name)
+                    });
+                    if !is_entry_point {
+                        non_entry_points.push(decl_id);
+                    }
+                    is_entry_point || want_unused_functions
+                }
                 Function {
                     body: Some(_),
                     ..
@@ -598,7 +662,114 @@ impl TypedAstContext {
         // Add all referenced macros to the set of wanted decls
         // wanted.extend(self.macro_expansions.values().flatten());
 
+        self.walk_wanted_decls(&mut to_walk, &mut wanted);
+
+        // Unset c_main if we are not retaining its declaration
+        if let Some(main_id) = self.c_main {
+            if !wanted.contains(&main_id) {
+                self.c_main = None;
+            }
+        }
+
+        // Keep only the declarations of the unreachable externally visible functions, and the
+        // types they depend on
+        for decl_id in non_entry_points {
+            if wanted.contains(&decl_id) {
+                continue;
+            }
+            if let Some(CDeclKind::Function { body, .. }) =
+                self.c_decls.get_mut(&decl_id).map(|decl| &mut decl.kind)
+            {
+                *body = None;
+            }
+            self.pruned_functions.insert(decl_id);
+            wanted.insert(decl_id);
+            to_walk.push(decl_id);
+        }
+        self.walk_wanted_decls(&mut to_walk, &mut wanted);
+
+        // Prune any declaration that isn't considered live
+        self.c_decls
+            .retain(|&decl_id, _decl| wanted.contains(&decl_id));
+
+        // Prune top declarations that are not considered live
+        self.c_decls_top.retain(|x| wanted.contains(x));
+    }
+
+    /// The externally visible functions that the definitions in this translation unit refer to.
+    /// These are the edges of the call graph followed across translation units to find the
+    /// functions reachable from the entry points given to [`Self::prune_unwanted_decls`].
+    pub fn external_references(&self) -> ExternalReferences {
+        let global_function_name = |decl_id: CDeclId| match self.c_decls.get(&decl_id)?.kind {
+            CDeclKind::Function {
+                is_global: true,
+                ref name,
+                ..
+            } => Some(name),
+            _ => None,
+        };
+
+        let mut references = ExternalReferences::default();
+        for &decl_id in &self.c_decls_top {
+            use CDeclKind::*;
+            // The roots of `prune_unwanted_decls` other than the externally visible functions
+            let is_root = match self.index(decl_id).kind {
+                Variable { ref attrs, .. } | Function { ref attrs, .. }
+                    if attrs.contains(&Attribute::Used) =>
+                {
+                    true
+                }
+                Variable {
+                    is_defn: true,
+                    is_externally_visible: true,
+                    ..
+                } => true,
+                Function {
+                    body: Some(_),
+                    is_global: true,
+                    ..
+                } => false,
+                _ => continue,
+            };
+
+            // Other externally visible functions get their own entries, so stop at them.
+            let mut to_walk = vec![decl_id];
+            let mut wanted = HashSet::from([decl_id]);
+            self.walk_wanted_decls_until(&mut to_walk, &mut wanted, |id| {
+                id != decl_id && global_function_name(id).is_some()
+            });
+            let names = wanted.into_iter().filter_map(global_function_name).cloned();
+            if is_root {
+                references.from_roots.extend(names);
+            } else if let Some(name) = global_function_name(decl_id) {
+                references
+                    .from_functions
+                    .entry(name.clone())
+                    .or_default()
+                    .extend(names);
+            }
+        }
+        references
+    }
+
+    /// Mark the declarations that the declarations in `to_walk` depend on as `wanted`,
+    /// recursively.
+    fn walk_wanted_decls(&self, to_walk: &mut Vec<CDeclId>, wanted: &mut HashSet<CDeclId>) {
+        self.walk_wanted_decls_until(to_walk, wanted, |_| false)
+    }
+
+    /// Like [`Self::walk_wanted_decls`], but doesn't look into the declarations for which `stop`
+    /// returns true.
+    fn walk_wanted_decls_until(
+        &self,
+        to_walk: &mut Vec<CDeclId>,
+        wanted: &mut HashSet<CDeclId>,
+        stop: impl Fn(CDeclId) -> bool,
+    ) {
         while let Some(enclosing_decl_id) = to_walk.pop() {
+            if stop(enclosing_decl_id) {
+                continue;
+            }
             for some_id in DFNodes::new(self, SomeId::Decl(enclosing_decl_id)) {
                 use SomeId::*;
                 match some_id {
@@ -657,20 +828,97 @@ impl TypedAstContext {
                 }
             }
         }
+    }
 
-        // Unset c_main if we are not retaining its declaration
-        if let Some(main_id) = self.c_main {
-            if !wanted.contains(&main_id) {
-                self.c_main = None;
-            }
-        }
+    /// The declarations declared in one of `headers`, which must be canonical paths, either
+    /// directly or through a redeclaration, such as the prototype of a function defined elsewhere.
+    pub fn decls_declared_in(&self, headers: &HashSet<PathBuf>) -> HashSet<CDeclId> {
+        let header_files = self
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| {
+                file.path
+                    .as_ref()
+                    .and_then(|path| std::fs::canonicalize(path).ok())
+                    .map_or(false, |path| headers.contains(&path))
+            })
+            .map(|(file_id, _)| file_id)
+            .collect::<HashSet<FileId>>();
 
-        // Prune any declaration that isn't considered live
         self.c_decls
-            .retain(|&decl_id, _decl| wanted.contains(&decl_id));
+            .iter()
+            .filter(|(_, decl)| {
+                self.file_id(decl)
+                    .map_or(false, |file_id| header_files.contains(&file_id))
+            })
+            .map(|(&decl_id, decl)| match decl.kind {
+                CDeclKind::NonCanonicalDecl { canonical_decl } => canonical_decl,
+                _ => decl_id,
+            })
+            .collect()
+    }
+
+    /// The declarations of the types that `decls` expose: the types in the signatures of
+    /// functions, the types of variables and fields, and the targets of typedefs, recursively.
+    /// These have to be as visible as `decls` themselves.
+    pub fn types_exposed_by(&self, decls: &HashSet<CDeclId>) -> HashSet<CDeclId> {
+        let mut exposed = HashSet::new();
+        let mut to_walk = decls.iter().copied().collect::<Vec<_>>();
+        while let Some(decl_id) = to_walk.pop() {
+            let mut types = match self.c_decls.get(&decl_id).map(|decl| &decl.kind) {
+                Some(CDeclKind::Function { typ, .. }) => vec![*typ],
+                Some(
+                    CDeclKind::Variable { typ, .. }
+                    | CDeclKind::Typedef { typ, .. }
+                    | CDeclKind::Field { typ, .. },
+                ) => vec![typ.ctype],
+                Some(
+                    CDeclKind::Struct {
+                        fields: Some(fields),
+                        ..
+                    }
+                    | CDeclKind::Union {
+                        fields: Some(fields),
+                        ..
+                    },
+                ) => {
+                    to_walk.extend(fields);
+                    continue;
+                }
+                _ => continue,
+            };
 
-        // Prune top declarations that are not considered live
-        self.c_decls_top.retain(|x| wanted.contains(x));
+            while let Some(type_id) = types.pop() {
+                use CTypeKind::*;
+                match self.index(type_id).kind {
+                    Struct(decl_id) | Union(decl_id) | Enum(decl_id) | Typedef(decl_id) => {
+                        if exposed.insert(decl_id) {
+                            to_walk.push(decl_id);
+                        }
+                    }
+                    Pointer(qtype)
+                    | Reference(qtype)
+                    | Attributed(qtype, _)
+                    | BlockPointer(qtype)
+                    | Vector(qtype, _) => types.push(qtype.ctype),
+                    Elaborated(ctype)
+                    | Decayed(ctype)
+                    | Paren(ctype)
+                    | TypeOf(ctype)
+                    | Complex(ctype)
+                    | ConstantArray(ctype, _)
+                    | IncompleteArray(ctype)
+                    | VariableArray(ctype, _) => types.push(ctype),
+                    Function(ret, ref params, _, _, _) => {
+                        types.push(ret.ctype);
+                        types.extend(params.iter().map(|param| param.ctype));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        exposed
     }
 
     pub fn sort_top_decls(&mut self) {
@@ -1909,6 +2157,7 @@ mod tests {
             macro_expansion_text: Default::default(),
             comments: vec![],
             prenamed_decls: Default::default(),
+            pruned_functions: Default::default(),
             va_list_kind: BuiltinVaListKind::CharPtrBuiltinVaList,
             target: "".to_string(),
         };
@@ -12,7 +12,7 @@ use strum_macros::{Display, EnumString};
 use crate::c_ast::{ClangAstParseErrorKind, DisplaySrcSpan};
 use c2rust_ast_exporter::get_clang_major_version;
 
-const DEFAULT_WARNINGS: &[Diagnostic] = &[Diagnostic::ClangAst];
+const DEFAULT_WARNINGS: &[Diagnostic] = &[Diagnostic::ClangAst, Diagnostic::Alloca];
 
 #[derive(PartialEq, Eq, Hash, Debug, Display, EnumString, Clone)]
 #[strum(serialize_all = "kebab-case")]
@@ -20,6 +20,8 @@ pub enum Diagnostic {
     All,
     Comments,
     ClangAst,
+    Alloca,
+    Promotions,
 }
 
 macro_rules! diag {
@@ -0,0 +1,159 @@
+//! Translation of a C file under several preprocessor configurations into one Rust module,
+//! enabled by `--feature-matrix`.  Each configuration is a set of extra clang arguments, like
+//! `-DHAVE_EPOLL`, named by a cargo feature.  We translate the file once per configuration and
+//! merge the translations item by item: items that are the same in every configuration are
+//! kept as is, and the others are gated with `#[cfg(feature = ...)]` on the configurations
+//! that produced them.
+
+use indexmap::IndexMap;
+use log::warn;
+use std::fs;
+use std::io;
+use std::path::Path;
+use syn::__private::ToTokens;
+use syn::{AttrStyle, Attribute, Item, ItemForeignMod, NestedMeta};
+
+use c2rust_ast_builder::mk;
+use c2rust_ast_printer::pprust;
+
+use crate::translator::item_attrs;
+use crate::{translate_file, CrateSet, PragmaVec, TranspilerConfig};
+
+/// A preprocessor configuration to translate under, named by the cargo feature that selects it
+#[derive(Debug, Clone)]
+pub struct FeatureConfig {
+    pub feature: String,
+    pub clang_args: Vec<String>,
+}
+
+/// Read the configurations for [`TranspilerConfig::feature_configs`] from a file with a line
+/// `<feature> = <clang args>` per configuration, e.g. `epoll = -DHAVE_EPOLL -DMAX_EVENTS=64`.
+/// Empty lines and lines starting with `#` are ignored.
+pub fn read_feature_matrix(path: &Path) -> io::Result<Vec<FeatureConfig>> {
+    let contents = fs::read_to_string(path)?;
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (feature, clang_args) = line.split_once('=').ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("expected `<feature> = <clang args>`: {line}"),
+                )
+            })?;
+            Ok(FeatureConfig {
+                feature: feature.trim().to_owned(),
+                clang_args: clang_args.split_whitespace().map(String::from).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Translate `input_path` under each of [`TranspilerConfig::feature_configs`] and merge the
+/// translations, along with the pragmas and crates each of them needs.
+pub(crate) fn translate_feature_configs(
+    tcfg: &TranspilerConfig,
+    input_path: &Path,
+    cc_db: &Path,
+    extra_clang_args: &[&str],
+) -> Result<(String, PragmaVec, CrateSet), ()> {
+    let mut translations = Vec::new();
+    let mut pragmas = IndexMap::<&'static str, Vec<&'static str>>::new();
+    let mut crates = CrateSet::new();
+    for config in &tcfg.feature_configs {
+        let mut clang_args = extra_clang_args.to_vec();
+        clang_args.extend(config.clang_args.iter().map(String::as_str));
+        let (translation, config_pragmas, config_crates) =
+            translate_file(tcfg, input_path, cc_db, &clang_args)?;
+        translations.push((config.feature.as_str(), translation));
+        for (key, vals) in config_pragmas {
+            let merged = pragmas.entry(key).or_default();
+            for val in vals {
+                if !merged.contains(&val) {
+                    merged.push(val);
+                }
+            }
+        }
+        crates.extend(config_crates);
+    }
+
+    let translation = merge_translations(&translations).map_err(|e| {
+        warn!(
+            "Failed to merge the configurations of {}: {}",
+            input_path.display(),
+            e
+        )
+    })?;
+    Ok((translation, pragmas.into_iter().collect(), crates))
+}
+
+/// Merge translations of the same file under the configurations named by the given features.
+fn merge_translations(translations: &[(&str, String)]) -> syn::Result<String> {
+    let mut attrs = IndexMap::<String, Attribute>::new();
+    let mut items = IndexMap::<String, (Item, Vec<&str>)>::new();
+    for &(feature, ref translation) in translations {
+        let file = syn::parse_file(translation)?;
+        for attr in file.attrs {
+            let key = attr.to_token_stream().to_string();
+            attrs.entry(key).or_insert(attr);
+        }
+        for item in file.items.into_iter().flat_map(split_foreign_mod) {
+            let key = item.to_token_stream().to_string();
+            let (_, features) = items.entry(key).or_insert_with(|| (item, Vec::new()));
+            features.push(feature);
+        }
+    }
+
+    let items = items
+        .into_values()
+        .map(|(mut item, features)| {
+            if features.len() < translations.len() {
+                if let Some(attrs) = item_attrs(&mut item) {
+                    attrs.push(cfg_features(&features));
+                }
+            }
+            item
+        })
+        .collect();
+    Ok(pprust::to_string(|| syn::File {
+        shebang: None,
+        attrs: attrs.into_values().collect(),
+        items,
+    }))
+}
+
+/// Split an `extern` block into one block per foreign item, so that declarations common to all
+/// configurations are shared even when the rest of the block differs.
+fn split_foreign_mod(item: Item) -> Vec<Item> {
+    match item {
+        Item::ForeignMod(foreign_mod) => foreign_mod
+            .items
+            .iter()
+            .map(|foreign_item| {
+                Item::ForeignMod(ItemForeignMod {
+                    items: vec![foreign_item.clone()],
+                    ..foreign_mod.clone()
+                })
+            })
+            .collect(),
+        item => vec![item],
+    }
+}
+
+/// `#[cfg(feature = "a")]`, or `#[cfg(any(feature = "a", feature = "b"))]` for several features
+fn cfg_features(features: &[&str]) -> Attribute {
+    let mut predicates = features
+        .iter()
+        .map(|feature| NestedMeta::Meta(mk().meta_namevalue("feature", feature.to_string())))
+        .collect::<Vec<_>>();
+    let predicate = if predicates.len() == 1 {
+        predicates.remove(0)
+    } else {
+        NestedMeta::Meta(mk().meta_list("any", predicates))
+    };
+    let meta = mk().meta_list("cfg", vec![predicate]);
+    mk().meta_item_attr(AttrStyle::Outer, meta)
+        .into_attrs()
+        .remove(0)
+}
@@ -7,6 +7,7 @@ pub mod c_ast;
 pub mod cfg;
 mod compile_cmds;
 pub mod convert_type;
+mod feature_configs;
 pub mod renamer;
 pub mod rust_ast;
 pub mod translator;
@@ -20,7 +21,7 @@ use std::path::{Path, PathBuf};
 use std::process;
 
 use crate::compile_cmds::CompileCmd;
-use failure::Error;
+use failure::{format_err, Error};
 use itertools::Itertools;
 use log::{info, warn};
 use regex::Regex;
@@ -29,12 +30,13 @@ use serde_derive::Serialize;
 use crate::c_ast::Printer;
 use crate::c_ast::*;
 pub use crate::diagnostics::Diagnostic;
+pub use crate::feature_configs::{read_feature_matrix, FeatureConfig};
 use c2rust_ast_exporter as ast_exporter;
 
 use crate::build_files::{emit_build_files, get_build_dir, CrateConfig};
 use crate::compile_cmds::get_compile_commands;
 use crate::convert_type::RESERVED_NAMES;
-pub use crate::translator::ReplaceMode;
+pub use crate::translator::{AllocaMode, ReplaceMode};
 use std::prelude::v1::Vec;
 
 type PragmaVec = Vec<(&'static str, Vec<&'static str>)>;
@@ -79,8 +81,28 @@ pub struct TranspilerConfig {
     pub output_dir: Option<PathBuf>,
     pub translate_const_macros: bool,
     pub translate_fn_macros: bool,
+    pub emit_callback_trampolines: bool,
+    /// Names of errno-returning functions whose calls go through a generated `io::Result`
+    /// wrapper
+    pub errno_wrappers: Vec<String>,
+    pub dynamic_alloca: AllocaMode,
+    /// Translate conversions of plain `char` and increments of types narrower than `int` with
+    /// explicit casts matching the C semantics
+    pub explicit_promotions: bool,
+    /// Translate casts between pointers and integers with `expose_addr()` and
+    /// `from_exposed_addr()`, preserving the pointers' provenance
+    pub preserve_provenance: bool,
     pub disable_refactoring: bool,
     pub preserve_unused_functions: bool,
+    /// Names of the functions from which to keep reachable definitions; other externally visible
+    /// functions are only declared, behind `#[cfg(c2rust_pruned)]`
+    pub entry_points: Option<HashSet<String>>,
+    /// Canonical paths of the headers declaring the public API; if any are given, other
+    /// declarations are only visible within the crate
+    pub public_headers: HashSet<PathBuf>,
+    /// Preprocessor configurations to translate each file under, merging the translations into
+    /// one module whose configuration-specific items are gated on cargo features
+    pub feature_configs: Vec<FeatureConfig>,
     pub log_level: log::LevelFilter,
 
     // Options that control build files
@@ -140,6 +162,7 @@ pub enum ExternCrate {
     NumTraits,
     Memoffset,
     Libc,
+    SmallVec,
 }
 
 #[derive(Serialize)]
@@ -170,6 +193,7 @@ impl From<ExternCrate> for ExternCrateDetails {
             ExternCrate::NumTraits => Self::new("num-traits", "0.2", true),
             ExternCrate::Memoffset => Self::new("memoffset", "0.5", true),
             ExternCrate::Libc => Self::new("libc", "0.2", false),
+            ExternCrate::SmallVec => Self::new("smallvec", "1.10", false),
         }
     }
 }
@@ -226,26 +250,190 @@ fn get_module_name(
     file.to_str().map(String::from)
 }
 
-pub fn create_temp_compile_commands(sources: &[PathBuf]) -> PathBuf {
-    let temp_path = std::env::temp_dir().join("compile_commands.json");
-    let compile_commands: Vec<CompileCmd> = sources
+/// Read a list of entry points for [`TranspilerConfig::entry_points`]: either symbol names, one
+/// per line, or a GNU ld map file as written by `-Wl,-Map=<file>`, whose symbol lines are an
+/// address followed by a name.  Lines starting with `#` are ignored.
+pub fn read_entry_points(path: &Path) -> io::Result<HashSet<String>> {
+    let is_ident = |s: &str| {
+        s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    let contents = fs::read_to_string(path)?;
+    let entry_points = contents
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words[..] {
+                [name] => Some(name),
+                [addr, name] if addr.starts_with("0x") => Some(name),
+                _ => None,
+            }
+        })
+        .filter(|name| is_ident(name))
+        .map(String::from)
+        .collect::<HashSet<_>>();
+    if entry_points.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no function names found",
+        ));
+    }
+    Ok(entry_points)
+}
+
+/// The names of the functions reachable from `entry_points`, following the references from
+/// every translation unit.
+fn reachable_functions(
+    entry_points: &HashSet<String>,
+    references: &[ExternalReferences],
+) -> HashSet<String> {
+    let mut reachable = HashSet::new();
+    let mut to_visit = entry_points
+        .iter()
+        .chain(references.iter().flat_map(|refs| &refs.from_roots))
+        .collect::<Vec<_>>();
+    while let Some(name) = to_visit.pop() {
+        if !reachable.insert(name.clone()) {
+            continue;
+        }
+        for refs in references {
+            if let Some(callees) = refs.from_functions.get(name) {
+                to_visit.extend(callees);
+            }
+        }
+    }
+    reachable
+}
+
+/// The external references of `input_path` under each of the configurations it's translated in.
+/// Files that fail to parse are skipped, since translating them reports the error.
+fn external_references(
+    tcfg: &TranspilerConfig,
+    input_path: &Path,
+    cc_db: &Path,
+    extra_clang_args: &[&str],
+) -> Vec<ExternalReferences> {
+    let mut configs_clang_args = tcfg
+        .feature_configs
+        .iter()
+        .map(|config| {
+            let mut clang_args = extra_clang_args.to_vec();
+            clang_args.extend(config.clang_args.iter().map(String::as_str));
+            clang_args
+        })
+        .collect::<Vec<_>>();
+    if configs_clang_args.is_empty() {
+        configs_clang_args.push(extra_clang_args.to_vec());
+    }
+    configs_clang_args
+        .iter()
+        .filter_map(|clang_args| {
+            let untyped_context = ast_exporter::get_untyped_ast(
+                input_path,
+                cc_db,
+                clang_args,
+                tcfg.debug_ast_exporter,
+            )
+            .ok()?;
+            let conv = ConversionContext::new(&untyped_context);
+            Some(conv.typed_context.external_references())
+        })
+        .collect()
+}
+
+/// A small C project described by a TOML file, for translating without a
+/// `compile_commands.json`:
+///
+/// ```toml
+/// sources = ["src/main.c", "src/util.c"]
+/// include_dirs = ["include"]
+/// defines = ["NDEBUG", "VERSION=2"]
+/// flags = ["-std=c99"]
+/// ```
+///
+/// Relative paths are relative to the directory containing the file.
+#[derive(Debug, Default)]
+pub struct ProjectDescription {
+    pub sources: Vec<PathBuf>,
+    /// The clang arguments for the include directories, defines, and other flags
+    pub clang_args: Vec<String>,
+}
+
+pub fn read_project_description(path: &Path) -> Result<ProjectDescription, Error> {
+    let contents = fs::read_to_string(path)?;
+    let doc = contents.parse::<toml_edit::Document>()?;
+    // Make the paths absolute, as the compile commands we generate aren't run from here
+    let base_dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::canonicalize(parent)?,
+        _ => std::env::current_dir()?,
+    };
+    let strings = |key: &str| -> Result<Vec<String>, Error> {
+        let values = match doc.get(key) {
+            Some(item) => item
+                .as_array()
+                .ok_or_else(|| format_err!("`{}` must be an array of strings", key))?,
+            None => return Ok(Vec::new()),
+        };
+        values
+            .iter()
+            .map(|value| {
+                value
+                    .as_str()
+                    .map(String::from)
+                    .ok_or_else(|| format_err!("`{}` must be an array of strings", key))
+            })
+            .collect()
+    };
+
+    let sources = strings("sources")?
+        .into_iter()
+        .map(|source| base_dir.join(source))
+        .collect::<Vec<_>>();
+    if sources.is_empty() {
+        return Err(format_err!("no `sources` in {}", path.display()));
+    }
+    let mut clang_args = Vec::new();
+    for include_dir in strings("include_dirs")? {
+        clang_args.push(format!("-I{}", base_dir.join(include_dir).display()));
+    }
+    for define in strings("defines")? {
+        clang_args.push(format!("-D{}", define));
+    }
+    clang_args.extend(strings("flags")?);
+    Ok(ProjectDescription {
+        sources,
+        clang_args,
+    })
+}
+
+/// The compile commands that compile each of `sources` with `clang_args`.
+fn compile_commands_for(sources: &[PathBuf], clang_args: &[String]) -> Vec<CompileCmd> {
+    sources
         .iter()
         .map(|source_file| {
             let absolute_path = fs::canonicalize(source_file)
                 .unwrap_or_else(|_| panic!("Could not canonicalize {}", source_file.display()));
 
+            let mut arguments = vec!["clang".to_string()];
+            arguments.extend_from_slice(clang_args);
+            arguments.push(absolute_path.to_str().unwrap().to_owned());
             CompileCmd {
                 directory: PathBuf::from("."),
                 file: absolute_path.clone(),
-                arguments: vec![
-                    "clang".to_string(),
-                    absolute_path.to_str().unwrap().to_owned(),
-                ],
+                arguments,
                 command: None,
                 output: None,
             }
         })
-        .collect();
+        .collect()
+}
+
+/// Write a `compile_commands.json` to a temporary directory that compiles each of `sources`
+/// with `clang_args`, and return its path.
+pub fn create_temp_compile_commands(sources: &[PathBuf], clang_args: &[String]) -> PathBuf {
+    let temp_path = std::env::temp_dir().join("compile_commands.json");
+    let compile_commands = compile_commands_for(sources, clang_args);
 
     let json_content = serde_json::to_string(&compile_commands).unwrap();
     let mut file =
@@ -258,7 +446,7 @@ pub fn create_temp_compile_commands(sources: &[PathBuf]) -> PathBuf {
 
 /// Main entry point to transpiler. Called from CLI tools with the result of
 /// clap::App::get_matches().
-pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]) {
+pub fn transpile(mut tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]) {
     diagnostics::init(tcfg.enabled_warnings.clone(), tcfg.log_level);
 
     let build_dir = get_build_dir(&tcfg, cc_db);
@@ -275,6 +463,17 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
     let mut clang_args: Vec<&str> = clang_args.iter().map(AsRef::as_ref).collect();
     clang_args.extend_from_slice(extra_clang_args);
 
+    // A function may only be reachable from the entry points through another translation unit,
+    // so find everything that's reachable across all of them before pruning any of them.
+    if let Some(entry_points) = &tcfg.entry_points {
+        let references = lcmds
+            .iter()
+            .flat_map(|lcmd| &lcmd.cmd_inputs)
+            .flat_map(|cmd| external_references(&tcfg, &cmd.abs_file(), cc_db, &clang_args))
+            .collect::<Vec<_>>();
+        tcfg.entry_points = Some(reachable_functions(entry_points, &references));
+    }
+
     let mut top_level_ccfg = None;
     let mut workspace_members = vec![];
     let mut num_transpiled_files = 0;
@@ -474,7 +673,6 @@ fn transpile_single(
         return Err(());
     }
 
-    let file = input_path.file_name().unwrap().to_str().unwrap();
     if !input_path.exists() {
         warn!(
             "Input C file {} does not exist, skipping!",
@@ -483,13 +681,49 @@ fn transpile_single(
         return Err(());
     }
 
+    let (translated_string, pragmas, crates) = if tcfg.feature_configs.is_empty() {
+        translate_file(tcfg, &input_path, cc_db, extra_clang_args)?
+    } else {
+        feature_configs::translate_feature_configs(tcfg, &input_path, cc_db, extra_clang_args)?
+    };
+
+    let mut file = match File::create(&output_path) {
+        Ok(file) => file,
+        Err(e) => panic!(
+            "Unable to open file {} for writing: {}",
+            output_path.display(),
+            e
+        ),
+    };
+
+    match file.write_all(translated_string.as_bytes()) {
+        Ok(()) => (),
+        Err(e) => panic!(
+            "Unable to write translation to file {}: {}",
+            output_path.display(),
+            e
+        ),
+    };
+
+    Ok((output_path, pragmas, crates))
+}
+
+/// Translate `input_path`, compiled with `extra_clang_args`, to the source of a Rust module.
+fn translate_file(
+    tcfg: &TranspilerConfig,
+    input_path: &Path,
+    cc_db: &Path,
+    extra_clang_args: &[&str],
+) -> Result<(String, PragmaVec, CrateSet), ()> {
+    let file = input_path.file_name().unwrap().to_str().unwrap();
+
     if tcfg.verbose {
         println!("Additional Clang arguments: {}", extra_clang_args.join(" "));
     }
 
     // Extract the untyped AST from the CBOR file
     let untyped_context = match ast_exporter::get_untyped_ast(
-        input_path.as_path(),
+        input_path,
         cc_db,
         extra_clang_args,
         tcfg.debug_ast_exporter,
@@ -532,28 +766,11 @@ fn transpile_single(
     }
 
     // Perform the translation
-    let (translated_string, pragmas, crates) =
-        translator::translate(typed_context, tcfg, input_path);
-
-    let mut file = match File::create(&output_path) {
-        Ok(file) => file,
-        Err(e) => panic!(
-            "Unable to open file {} for writing: {}",
-            output_path.display(),
-            e
-        ),
-    };
-
-    match file.write_all(translated_string.as_bytes()) {
-        Ok(()) => (),
-        Err(e) => panic!(
-            "Unable to write translation to file {}: {}",
-            output_path.display(),
-            e
-        ),
-    };
-
-    Ok((output_path, pragmas, crates))
+    Ok(translator::translate(
+        typed_context,
+        tcfg,
+        input_path.to_path_buf(),
+    ))
 }
 
 fn get_output_path(
@@ -600,3 +817,119 @@ fn get_output_path(
         input_path
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::env;
+
+    fn names(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|&name| name.to_owned()).collect()
+    }
+
+    #[test]
+    fn reachable_across_translation_units() {
+        // a.c: `void (*exit_hook)(void) = cleanup;`, `main` calls `helper`, `unused` calls `leaf`
+        let a = ExternalReferences {
+            from_roots: names(&["cleanup"]),
+            from_functions: HashMap::from([
+                ("main".to_owned(), names(&["helper"])),
+                ("unused".to_owned(), names(&["leaf"])),
+            ]),
+        };
+        // b.c: `helper` calls `leaf`, and `dead` calls `helper`
+        let b = ExternalReferences {
+            from_roots: HashSet::new(),
+            from_functions: HashMap::from([
+                ("helper".to_owned(), names(&["leaf"])),
+                ("leaf".to_owned(), HashSet::new()),
+                ("cleanup".to_owned(), HashSet::new()),
+                ("dead".to_owned(), names(&["helper"])),
+            ]),
+        };
+
+        let reachable = reachable_functions(&names(&["main"]), &[a, b]);
+        assert_eq!(reachable, names(&["main", "helper", "leaf", "cleanup"]));
+    }
+
+    #[test]
+    fn read_entry_points_from_list_and_map() {
+        let path = env::temp_dir().join(format!("c2rust-entry-points-{}", process::id()));
+        fs::write(
+            &path,
+            "# entry points\n\
+             main\n\
+             .text          0x0000000000401126       0x2b /tmp/cc.o\n\
+             \x20               0x0000000000401126                handle_request\n",
+        )
+        .unwrap();
+        let entry_points = read_entry_points(&path);
+        fs::write(&path, "# nothing here\n").unwrap();
+        let empty = read_entry_points(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(entry_points.unwrap(), names(&["main", "handle_request"]));
+        assert_eq!(empty.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn project_description_compile_commands() {
+        let dir = env::temp_dir().join(format!("c2rust-project-{}", process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let dir = fs::canonicalize(dir).unwrap();
+        let project_path = dir.join("c2rust.toml");
+        fs::write(dir.join("src/main.c"), "int main(void) { return 0; }\n").unwrap();
+        fs::write(
+            &project_path,
+            r#"
+            sources = ["src/main.c"]
+            include_dirs = ["include", "/opt/include"]
+            defines = ["NDEBUG", "VERSION=2", 'GREETING="hello, world"']
+            flags = ["-std=c99"]
+            "#,
+        )
+        .unwrap();
+        let project = read_project_description(&project_path).unwrap();
+        let compile_commands = compile_commands_for(&project.sources, &project.clang_args);
+        fs::write(&project_path, "include_dirs = [\"include\"]\n").unwrap();
+        let no_sources = read_project_description(&project_path);
+        fs::write(&project_path, "sources = \"src/main.c\"\n").unwrap();
+        let not_array = read_project_description(&project_path);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Relative include directories are relative to the project file, and each define is one
+        // argument, so values with spaces and quotes need no shell quoting.
+        let main_c = dir.join("src/main.c");
+        assert_eq!(project.sources, [dir.join("src/main.c")]);
+        let json = serde_json::to_value(&compile_commands).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "directory": ".",
+                "file": main_c,
+                "arguments": [
+                    "clang",
+                    format!("-I{}", dir.join("include").display()),
+                    "-I/opt/include",
+                    "-DNDEBUG",
+                    "-DVERSION=2",
+                    "-DGREETING=\"hello, world\"",
+                    "-std=c99",
+                    main_c,
+                ],
+                "command": null,
+                "output": null,
+            }])
+        );
+
+        assert!(no_sources
+            .unwrap_err()
+            .to_string()
+            .starts_with("no `sources` in "));
+        assert_eq!(
+            not_array.unwrap_err().to_string(),
+            "`sources` must be an array of strings"
+        );
+    }
+}
@@ -1,6 +1,7 @@
 #![deny(missing_docs)]
 //! Implementations of clang's builtin functions
 
+use crate::diagnostics::{diag, Diagnostic};
 use crate::format_translation_err;
 
 use super::*;
@@ -16,6 +17,52 @@ enum LibcFnArgType {
     Int,
 }
 
+/// Is `name` one of the MSVC atomic intrinsics from `<intrin.h>`, which we translate like
+/// builtins even though clang only treats them as such when targeting Windows?
+pub fn is_msvc_atomic_intrinsic(name: &str) -> bool {
+    // This must list exactly the names `convert_builtin` handles; any other name would end in
+    // an "Unimplemented builtin" error instead of an ordinary call.
+    const INTRINSICS: &[&str] = &[
+        "_InterlockedIncrement",
+        "_InterlockedIncrement16",
+        "_InterlockedIncrement64",
+        "_InterlockedDecrement",
+        "_InterlockedDecrement16",
+        "_InterlockedDecrement64",
+        "_InterlockedExchangeAdd",
+        "_InterlockedExchangeAdd8",
+        "_InterlockedExchangeAdd16",
+        "_InterlockedExchangeAdd64",
+        "_InterlockedOr",
+        "_InterlockedOr8",
+        "_InterlockedOr16",
+        "_InterlockedOr64",
+        "_InterlockedAnd",
+        "_InterlockedAnd8",
+        "_InterlockedAnd16",
+        "_InterlockedAnd64",
+        "_InterlockedXor",
+        "_InterlockedXor8",
+        "_InterlockedXor16",
+        "_InterlockedXor64",
+        "_InterlockedExchange",
+        "_InterlockedExchange8",
+        "_InterlockedExchange16",
+        "_InterlockedExchange64",
+        "_InterlockedExchangePointer",
+        "_InterlockedCompareExchange",
+        "_InterlockedCompareExchange8",
+        "_InterlockedCompareExchange16",
+        "_InterlockedCompareExchange64",
+        "_InterlockedCompareExchangePointer",
+        "_ReadWriteBarrier",
+        "_ReadBarrier",
+        "_WriteBarrier",
+        "__faststorefence",
+    ];
+    INTRINSICS.contains(&name)
+}
+
 impl<'c> Translation<'c> {
     /// Convert a call to a builtin function to a Rust expression
     pub fn convert_builtin(
@@ -341,21 +388,7 @@ impl<'c> Translation<'c> {
                 Err(TranslationError::generic("Unsupported va_end"))
             }
 
-            "__builtin_alloca" => {
-                let count = self.convert_expr(ctx.used(), args[0])?;
-                count.and_then(|count| {
-                    let alloca_name = self.renamer.borrow_mut().fresh();
-                    let zero_elem = mk().lit_expr(mk().int_unsuffixed_lit(0));
-                    Ok(WithStmts::new(
-                        vec![mk().local_stmt(Box::new(mk().local(
-                            mk().mutbl().ident_pat(&alloca_name),
-                            None,
-                            Some(vec_expr(zero_elem, cast_int(count, "usize", false))),
-                        )))],
-                        mk().method_call_expr(mk().ident_expr(&alloca_name), "as_mut_ptr", vec![]),
-                    ))
-                })
-            }
+            "__builtin_alloca" | "alloca" => self.convert_alloca(ctx, decl_id, src_loc, args[0]),
 
             // SIMD builtins:
             "__builtin_ia32_aeskeygenassist128" => {
@@ -613,6 +646,124 @@ impl<'c> Translation<'c> {
                     )
                 })
             }
+            "_InterlockedIncrement"
+            | "_InterlockedIncrement16"
+            | "_InterlockedIncrement64"
+            | "_InterlockedDecrement"
+            | "_InterlockedDecrement16"
+            | "_InterlockedDecrement64" => {
+                let func_name = if builtin_name.starts_with("_InterlockedIncrement") {
+                    "atomic_xadd_seqcst"
+                } else {
+                    "atomic_xsub_seqcst"
+                };
+                let arg0 = self.convert_expr(ctx.used(), args[0])?;
+                arg0.and_then(|arg0| {
+                    // Like `__sync_add_and_fetch`, these return the new value
+                    let one = mk().lit_expr(mk().int_unsuffixed_lit(1));
+                    self.convert_atomic_op(ctx, func_name, arg0, one, false)
+                })
+            }
+
+            "_InterlockedExchangeAdd"
+            | "_InterlockedExchangeAdd8"
+            | "_InterlockedExchangeAdd16"
+            | "_InterlockedExchangeAdd64"
+            | "_InterlockedOr"
+            | "_InterlockedOr8"
+            | "_InterlockedOr16"
+            | "_InterlockedOr64"
+            | "_InterlockedAnd"
+            | "_InterlockedAnd8"
+            | "_InterlockedAnd16"
+            | "_InterlockedAnd64"
+            | "_InterlockedXor"
+            | "_InterlockedXor8"
+            | "_InterlockedXor16"
+            | "_InterlockedXor64" => {
+                let func_name = if builtin_name.starts_with("_InterlockedExchangeAdd") {
+                    "atomic_xadd_seqcst"
+                } else if builtin_name.starts_with("_InterlockedOr") {
+                    "atomic_or_seqcst"
+                } else if builtin_name.starts_with("_InterlockedAnd") {
+                    "atomic_and_seqcst"
+                } else {
+                    "atomic_xor_seqcst"
+                };
+                let arg0 = self.convert_expr(ctx.used(), args[0])?;
+                let arg1 = self.convert_expr(ctx.used(), args[1])?;
+                arg0.and_then(|arg0| {
+                    arg1.and_then(|arg1| self.convert_atomic_op(ctx, func_name, arg0, arg1, true))
+                })
+            }
+
+            "_InterlockedExchange"
+            | "_InterlockedExchange8"
+            | "_InterlockedExchange16"
+            | "_InterlockedExchange64"
+            | "_InterlockedExchangePointer" => {
+                self.use_feature("core_intrinsics");
+
+                // Emit `atomic_xchg_seqcst(arg0, arg1)`
+                let atomic_func =
+                    mk().abs_path_expr(vec!["core", "intrinsics", "atomic_xchg_seqcst"]);
+                let arg0 = self.convert_expr(ctx.used(), args[0])?;
+                let arg1 = self.convert_expr(ctx.used(), args[1])?;
+                arg0.and_then(|arg0| {
+                    arg1.and_then(|arg1| {
+                        let call_expr = mk().call_expr(atomic_func, vec![arg0, arg1]);
+                        self.convert_side_effects_expr(
+                            ctx,
+                            WithStmts::new_val(call_expr),
+                            "Builtin is not supposed to be used",
+                        )
+                    })
+                })
+            }
+
+            "_InterlockedCompareExchange"
+            | "_InterlockedCompareExchange8"
+            | "_InterlockedCompareExchange16"
+            | "_InterlockedCompareExchange64"
+            | "_InterlockedCompareExchangePointer" => {
+                // Unlike `__sync_val_compare_and_swap`, the new value comes before the expected one
+                let dst = self.convert_expr(ctx.used(), args[0])?;
+                let exchange = self.convert_expr(ctx.used(), args[1])?;
+                let comparand = self.convert_expr(ctx.used(), args[2])?;
+                dst.and_then(|dst| {
+                    exchange.and_then(|exchange| {
+                        comparand.and_then(|comparand| {
+                            self.convert_atomic_cxchg(
+                                ctx,
+                                "atomic_cxchg_seqcst_seqcst",
+                                dst,
+                                comparand,
+                                exchange,
+                                true,
+                            )
+                        })
+                    })
+                })
+            }
+
+            "_ReadWriteBarrier" | "_ReadBarrier" | "_WriteBarrier" | "__faststorefence" => {
+                self.use_feature("core_intrinsics");
+
+                // The `_*Barrier`s only prevent the compiler from reordering memory accesses
+                let fence = if builtin_name == "__faststorefence" {
+                    "atomic_fence_seqcst"
+                } else {
+                    "atomic_singlethreadfence_seqcst"
+                };
+                let atomic_func = mk().abs_path_expr(vec!["core", "intrinsics", fence]);
+                let call_expr = mk().call_expr(atomic_func, vec![]);
+                self.convert_side_effects_expr(
+                    ctx,
+                    WithStmts::new_val(call_expr),
+                    "Builtin is not supposed to be used",
+                )
+            }
+
             // There's currently no way to replicate this functionality in Rust, so we just
             // pass the ptr input param in its place.
             "__builtin_assume_aligned" => Ok(self.convert_expr(ctx.used(), args[0])?),
@@ -740,4 +891,100 @@ impl<'c> Translation<'c> {
             }
         })
     }
+
+    /// Convert a call to `alloca`.  The buffer is a local array if `size` is a compile-time
+    /// constant, and a `Vec` or `SmallVec` otherwise, as chosen by `--dynamic-alloca`.  Unlike the
+    /// memory `alloca` returns, which lives until the function returns, the buffer is dropped at
+    /// the end of the enclosing block.
+    fn convert_alloca(
+        &self,
+        ctx: ExprContext,
+        alloca_id: CDeclId,
+        src_loc: &Option<SrcSpan>,
+        size: CExprId,
+    ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        let ret_ty = match self.ast_context[alloca_id].kind {
+            CDeclKind::Function { typ, .. } => match self.ast_context.resolve_type(typ).kind {
+                CTypeKind::Function(ret, ..) => self.convert_type(ret.ctype)?,
+                _ => {
+                    return Err(TranslationError::generic(
+                        "Expected function type of alloca",
+                    ))
+                }
+            },
+            _ => {
+                return Err(TranslationError::generic(
+                    "Expected function decl of alloca",
+                ))
+            }
+        };
+        let is_const = self.ast_context.is_const_expr(size);
+        diag!(
+            Diagnostic::Alloca,
+            "{}: alloca buffer is freed at the end of the enclosing block, not when the function returns",
+            self.ast_context
+                .display_loc(src_loc)
+                .map_or("unknown location".to_string(), |l| l.to_string()),
+        );
+
+        let size = self.convert_expr(ctx.used(), size)?;
+        size.and_then(|size| {
+            let alloca_name = self.renamer.borrow_mut().fresh();
+            // `alloca` returns memory aligned for any scalar, so allocate it in words.
+            let words = mk().binary_expr(
+                BinOp::Div(Default::default()),
+                mk().binary_expr(
+                    BinOp::Add(Default::default()),
+                    cast_int(size, "usize", false),
+                    mk().lit_expr(mk().int_unsuffixed_lit(7)),
+                ),
+                mk().lit_expr(mk().int_unsuffixed_lit(8)),
+            );
+            let word_ty = mk().path_ty(vec!["u64"]);
+            let zero = mk().lit_expr(mk().int_unsuffixed_lit(0));
+            let (buf_ty, buf) = match self.tcfg.dynamic_alloca {
+                _ if is_const => (
+                    mk().array_ty(word_ty, words.clone()),
+                    mk().repeat_expr(zero, words),
+                ),
+                AllocaMode::Vec => (
+                    mk().path_ty(vec![mk().path_segment_with_args(
+                        "Vec",
+                        mk().angle_bracketed_args(vec![word_ty]),
+                    )]),
+                    vec_expr(zero, words),
+                ),
+                AllocaMode::SmallVec => {
+                    self.use_crate(ExternCrate::SmallVec);
+                    // Up to 256 bytes stay on the stack.
+                    let inline_ty =
+                        mk().array_ty(word_ty, mk().lit_expr(mk().int_unsuffixed_lit(32)));
+                    (
+                        mk().path_ty(mk().abs_path(vec![
+                            mk().path_segment("smallvec"),
+                            mk().path_segment_with_args(
+                                "SmallVec",
+                                mk().angle_bracketed_args(vec![inline_ty]),
+                            ),
+                        ])),
+                        mk().call_expr(
+                            mk().abs_path_expr(vec!["smallvec", "SmallVec", "from_elem"]),
+                            vec![zero, words],
+                        ),
+                    )
+                }
+            };
+            Ok(WithStmts::new(
+                vec![mk().local_stmt(Box::new(mk().local(
+                    mk().mutbl().ident_pat(&alloca_name),
+                    Some(buf_ty),
+                    Some(buf),
+                )))],
+                mk().cast_expr(
+                    mk().method_call_expr(mk().ident_expr(&alloca_name), "as_mut_ptr", vec![]),
+                    ret_ty,
+                ),
+            ))
+        })
+    }
 }
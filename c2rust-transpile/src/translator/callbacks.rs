@@ -0,0 +1,149 @@
+//! This module implements the callback trampolines enabled by
+//! `--emit-callback-trampolines`.  A C function is often registered
+//! as a callback of a slightly different type, such as a comparator
+//! taking `const struct foo *` arguments passed to `qsort`.  Rather
+//! than transmuting the function to the callback type, we generate a
+//! trampoline with exactly the callback's signature, which casts its
+//! arguments and calls the function.
+
+use super::*;
+
+impl<'c> Translation<'c> {
+    fn is_same_type(&self, a: CQualTypeId, b: CQualTypeId) -> bool {
+        self.ast_context.resolve_type_id(a.ctype) == self.ast_context.resolve_type_id(b.ctype)
+    }
+
+    /// Can a trampoline convert between `from` and `to` with an `as` cast?
+    fn is_trampoline_compatible(&self, from: CQualTypeId, to: CQualTypeId) -> bool {
+        let is_data_pointer = |ty: CQualTypeId| {
+            self.ast_context.resolve_type(ty.ctype).kind.is_pointer()
+                && !self.ast_context.is_function_pointer(ty.ctype)
+        };
+        self.is_same_type(from, to) || (is_data_pointer(from) && is_data_pointer(to))
+    }
+
+    /// If `expr` refers to a function that is being cast to the function pointer type
+    /// `target`, and the function's parameter and return types only differ from the ones of
+    /// `target` in the types they point to, return a trampoline of type `target` that calls the
+    /// function, or the function itself if its signature is the same as `target`.
+    pub fn convert_callback_cast(
+        &self,
+        expr: CExprId,
+        target: CTypeId,
+    ) -> TranslationResult<Option<Box<Expr>>> {
+        let func = match self.ast_context[expr].kind {
+            CExprKind::ImplicitCast(_, func, CastKind::FunctionToPointerDecay, _, _)
+            | CExprKind::Unary(_, c_ast::UnOp::AddressOf, func, _) => func,
+            _ => return Ok(None),
+        };
+        let func = match self.ast_context[func].kind {
+            CExprKind::DeclRef(_, decl_id, _) => decl_id,
+            _ => return Ok(None),
+        };
+        let func_ty = match self.ast_context[func].kind {
+            CDeclKind::Function { typ, .. } => typ,
+            _ => return Ok(None),
+        };
+        let (ret, params) = match self.ast_context.resolve_type(func_ty).kind {
+            CTypeKind::Function(ret, ref params, false, false, true) => (ret, params),
+            _ => return Ok(None),
+        };
+        let target_fn = match self.ast_context.get_pointee_qual_type(target) {
+            Some(pointee) => self.ast_context.resolve_type_id(pointee.ctype),
+            None => return Ok(None),
+        };
+        let (target_ret, target_params) = match self.ast_context[target_fn].kind {
+            CTypeKind::Function(ret, ref params, false, false, true) => (ret, params),
+            _ => return Ok(None),
+        };
+        if params.len() != target_params.len()
+            || !self.is_trampoline_compatible(ret, target_ret)
+            || !params
+                .iter()
+                .zip(target_params)
+                .all(|(&param, &target_param)| self.is_trampoline_compatible(target_param, param))
+        {
+            return Ok(None);
+        }
+        let func_name = match self.renamer.borrow().get(&func) {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+
+        // Registering the same callback repeatedly shares one trampoline per module.
+        let key = (self.cur_file(), func, target_fn);
+        let existing = self.callback_trampolines.borrow().get(&key).cloned();
+        let is_same_signature = self.is_same_type(ret, target_ret)
+            && params
+                .iter()
+                .zip(target_params)
+                .all(|(&param, &target_param)| self.is_same_type(param, target_param));
+        let name = match existing {
+            Some(name) => name,
+            // The types only differ in sugar such as typedefs, so no trampoline is needed.
+            None if is_same_signature => func_name,
+            None => {
+                let name =
+                    self.convert_trampoline(&func_name, ret, params, target_ret, target_params)?;
+                self.callback_trampolines
+                    .borrow_mut()
+                    .insert(key, name.clone());
+                name
+            }
+        };
+        Ok(Some(mk().call_expr(
+            mk().ident_expr("Some"),
+            vec![mk().ident_expr(name)],
+        )))
+    }
+
+    /// Add a function with the signature given by `target_ret` and `target_params` that calls
+    /// `func_name`, whose signature is given by `ret` and `params`, and return its name.
+    fn convert_trampoline(
+        &self,
+        func_name: &str,
+        ret: CQualTypeId,
+        params: &[CQualTypeId],
+        target_ret: CQualTypeId,
+        target_params: &[CQualTypeId],
+    ) -> TranslationResult<String> {
+        let name = self
+            .renamer
+            .borrow_mut()
+            .pick_name_root(&format!("{}_trampoline", func_name));
+        let mut args = Vec::new();
+        let mut call_args = Vec::new();
+        for (i, (&param, &target_param)) in params.iter().zip(target_params).enumerate() {
+            let arg_name = format!("arg{}", i);
+            args.push(mk().arg(
+                self.convert_type(target_param.ctype)?,
+                mk().ident_pat(&arg_name),
+            ));
+            let mut arg = mk().ident_expr(&arg_name);
+            if !self.is_same_type(param, target_param) {
+                arg = mk().cast_expr(arg, self.convert_type(param.ctype)?);
+            }
+            call_args.push(arg);
+        }
+        let mut call = mk().call_expr(mk().path_expr(vec![func_name]), call_args);
+        let ret_ty = if self.ast_context.resolve_type(target_ret.ctype).kind == CTypeKind::Void {
+            ReturnType::Default
+        } else {
+            let target_ret_ty = self.convert_type(target_ret.ctype)?;
+            if !self.is_same_type(ret, target_ret) {
+                call = mk().cast_expr(call, target_ret_ty.clone());
+            }
+            ReturnType::Type(Default::default(), target_ret_ty)
+        };
+
+        let decl = mk().fn_decl(&name, args, None, ret_ty);
+        let block = mk().block(vec![mk().expr_stmt(call)]);
+        let trampoline = mk().unsafe_().extern_("C").fn_item(decl, block);
+        self.items
+            .borrow_mut()
+            .entry(self.cur_file())
+            .or_insert_with(ItemStore::new)
+            .add_item(trampoline);
+        Ok(name)
+    }
+}
@@ -0,0 +1,304 @@
+//! This module implements the errno wrappers enabled by `--errno-wrapper`.  Calls to each of the
+//! given libc functions go through a generated `<function>_checked` wrapper, which turns a
+//! failure, signaled by returning `-1` or a null pointer, into an `Err` holding `errno`.  Reads and
+//! writes of `errno` itself, which C expands to `*__errno_location()`, go through generated
+//! `errno()` and `set_errno()` helpers, so the raw pointer to `errno` is only dereferenced there.
+
+use super::*;
+
+/// The functions C libraries use to locate `errno`: `__errno_location` in glibc and musl, and
+/// `__error` in the BSDs and macOS.
+const ERRNO_LOCATIONS: &[&str] = &["__errno_location", "__error"];
+
+/// The names of the `errno()` and `set_errno()` helpers of a module.
+#[derive(Clone)]
+pub struct ErrnoAccessors {
+    get: String,
+    set: String,
+}
+
+impl<'c> Translation<'c> {
+    fn errno_wrappers_enabled(&self) -> bool {
+        // `std::io::Error` isn't available without `std`.
+        !self.tcfg.errno_wrappers.is_empty() && !self.tcfg.emit_no_std
+    }
+
+    /// If `expr` is `*__errno_location()`, the expansion of `errno`, return the `DeclRef` of
+    /// `__errno_location`.
+    fn errno_location(&self, expr: CExprId) -> Option<CExprId> {
+        if !self.errno_wrappers_enabled() {
+            return None;
+        }
+        let call = match self.ast_context[expr].kind {
+            CExprKind::Unary(_, c_ast::UnOp::Deref, call, _) => call,
+            _ => return None,
+        };
+        let func = match self.ast_context[call].kind {
+            CExprKind::Call(_, func, ref args) if args.is_empty() => func,
+            _ => return None,
+        };
+        let decl_ref = match self.ast_context[func].kind {
+            CExprKind::ImplicitCast(_, decl_ref, CastKind::FunctionToPointerDecay, _, _) => {
+                decl_ref
+            }
+            _ => return None,
+        };
+        let decl_id = match self.ast_context[decl_ref].kind {
+            CExprKind::DeclRef(_, decl_id, _) => decl_id,
+            _ => return None,
+        };
+        match self.ast_context[decl_id].kind {
+            CDeclKind::Function { ref name, .. } if ERRNO_LOCATIONS.contains(&name.as_str()) => {
+                Some(decl_ref)
+            }
+            _ => None,
+        }
+    }
+
+    /// Is `expr` an access to `errno` that should go through the `errno()` and `set_errno()`
+    /// helpers?
+    pub fn is_errno_access(&self, expr: CExprId) -> bool {
+        self.errno_location(expr).is_some()
+    }
+
+    /// Return the `errno()` and `set_errno()` helpers of the current module, adding them if this
+    /// is the first access to `errno` in it.
+    fn errno_accessors_for(
+        &self,
+        ctx: ExprContext,
+        errno: CExprId,
+    ) -> TranslationResult<ErrnoAccessors> {
+        let decl_ref = self
+            .errno_location(errno)
+            .ok_or_else(|| format_err!("not an access to errno"))?;
+        let file = self.cur_file();
+        if let Some(accessors) = self.errno_accessors.borrow().get(&file) {
+            return Ok(accessors.clone());
+        }
+
+        let errno_ty = self.ast_context[errno]
+            .kind
+            .get_type()
+            .ok_or_else(|| format_err!("bad errno type"))?;
+        let ty = self.convert_type(errno_ty)?;
+        let location = self.convert_expr(ctx.used(), decl_ref)?.to_expr();
+        let deref = || {
+            mk().unary_expr(
+                UnOp::Deref(Default::default()),
+                mk().call_expr(location.clone(), vec![]),
+            )
+        };
+
+        let mut renamer = self.renamer.borrow_mut();
+        let accessors = ErrnoAccessors {
+            get: renamer.pick_name_root("errno"),
+            set: renamer.pick_name_root("set_errno"),
+        };
+        drop(renamer);
+
+        let get_decl = mk().fn_decl(
+            &accessors.get,
+            vec![],
+            None,
+            ReturnType::Type(Default::default(), ty.clone()),
+        );
+        let get = mk()
+            .unsafe_()
+            .fn_item(get_decl, mk().block(vec![mk().expr_stmt(deref())]));
+        let set_decl = mk().fn_decl(
+            &accessors.set,
+            vec![mk().arg(ty, mk().ident_pat("value"))],
+            None,
+            ReturnType::Default,
+        );
+        let set = mk().unsafe_().fn_item(
+            set_decl,
+            mk().block(vec![
+                mk().semi_stmt(mk().assign_expr(deref(), mk().ident_expr("value")))
+            ]),
+        );
+        let mut items = self.items.borrow_mut();
+        let store = items.entry(file).or_insert_with(ItemStore::new);
+        store.add_item(get);
+        store.add_item(set);
+
+        self.errno_accessors
+            .borrow_mut()
+            .insert(file, accessors.clone());
+        Ok(accessors)
+    }
+
+    /// Translate a read of `errno`, for which [`Self::is_errno_access`] holds, as a call to
+    /// `errno()`.
+    pub fn convert_errno_read(
+        &self,
+        ctx: ExprContext,
+        errno: CExprId,
+    ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        let accessors = self.errno_accessors_for(ctx, errno)?;
+        Ok(WithStmts::new_val(
+            mk().call_expr(mk().ident_expr(accessors.get), vec![]),
+        ))
+    }
+
+    /// Translate an assignment of `rhs` to `errno`, for which [`Self::is_errno_access`] holds,
+    /// as a call to `set_errno()`.
+    pub fn convert_errno_write(
+        &self,
+        ctx: ExprContext,
+        errno: CExprId,
+        rhs: WithStmts<Box<Expr>>,
+    ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        let accessors = self.errno_accessors_for(ctx, errno)?;
+        Ok(rhs.map(|rhs| mk().call_expr(mk().ident_expr(accessors.set), vec![rhs])))
+    }
+
+    /// If `func` refers to one of the functions given to `--errno-wrapper`, translate its call
+    /// with `args` as a call to its `<function>_checked` wrapper, which returns an
+    /// `io::Result`.  The call site unwraps the result back into the C return value, so the
+    /// wrapper can be adopted by hand where the error is handled.
+    pub fn convert_errno_call(
+        &self,
+        ctx: ExprContext,
+        func: CExprId,
+        args: &[CExprId],
+    ) -> TranslationResult<Option<WithStmts<Box<Expr>>>> {
+        if !self.errno_wrappers_enabled() {
+            return Ok(None);
+        }
+        let decl_id = match self.ast_context[func].kind {
+            CExprKind::DeclRef(_, decl_id, _) => decl_id,
+            _ => return Ok(None),
+        };
+        let (name, typ) = match self.ast_context[decl_id].kind {
+            CDeclKind::Function { ref name, typ, .. } => (name, typ),
+            _ => return Ok(None),
+        };
+        if !self.tcfg.errno_wrappers.contains(name) {
+            return Ok(None);
+        }
+        let (ret, params) = match self.ast_context.resolve_type(typ).kind {
+            CTypeKind::Function(ret, ref params, false, _, true) => (ret, params),
+            _ => {
+                warn!("cannot wrap variadic or unprototyped function {name} for errno");
+                return Ok(None);
+            }
+        };
+        let ret_kind = &self.ast_context.resolve_type(ret.ctype).kind;
+        let is_pointer = ret_kind.is_pointer() && !self.ast_context.is_function_pointer(ret.ctype);
+        if !is_pointer && !ret_kind.is_signed_integral_type() {
+            warn!("cannot wrap {name} for errno, as it doesn't return a signed integer or pointer");
+            return Ok(None);
+        }
+
+        let key = (self.cur_file(), decl_id);
+        let existing = self.errno_wrappers.borrow().get(&key).cloned();
+        let wrapper = match existing {
+            Some(wrapper) => wrapper,
+            None => {
+                let func = self.convert_expr(ctx.used(), func)?.to_expr();
+                let wrapper = self.convert_errno_wrapper(name, func, ret, params, is_pointer)?;
+                self.errno_wrappers
+                    .borrow_mut()
+                    .insert(key, wrapper.clone());
+                wrapper
+            }
+        };
+
+        let failure = if is_pointer {
+            self.null_ptr(ret.ctype, false)?
+        } else {
+            neg_one()
+        };
+        let args = self.convert_exprs(ctx.used(), args)?;
+        Ok(Some(args.map(|args| {
+            let call = mk().call_expr(mk().ident_expr(wrapper), args);
+            mk().method_call_expr(call, "unwrap_or", vec![failure])
+        })))
+    }
+
+    /// Add the `<name>_checked` wrapper of `func`, whose signature is given by `ret` and `params`,
+    /// and return its name.
+    fn convert_errno_wrapper(
+        &self,
+        name: &str,
+        func: Box<Expr>,
+        ret: CQualTypeId,
+        params: &[CQualTypeId],
+        is_pointer: bool,
+    ) -> TranslationResult<String> {
+        let wrapper = self
+            .renamer
+            .borrow_mut()
+            .pick_name_root(&format!("{}_checked", name));
+        let mut args = Vec::new();
+        let mut call_args = Vec::new();
+        for (i, param) in params.iter().enumerate() {
+            let arg_name = format!("arg{}", i);
+            args.push(mk().arg(self.convert_type(param.ctype)?, mk().ident_pat(&arg_name)));
+            call_args.push(mk().ident_expr(&arg_name));
+        }
+        let ret_ty = mk().path_ty(mk().abs_path(vec![
+            mk().path_segment("std"),
+            mk().path_segment("io"),
+            mk().path_segment_with_args(
+                "Result",
+                mk().angle_bracketed_args(vec![self.convert_type(ret.ctype)?]),
+            ),
+        ]));
+
+        // let ret = f(args);
+        // if ret == -1 {
+        //     return Err(std::io::Error::last_os_error());
+        // }
+        // Ok(ret)
+        let ret_var = || mk().ident_expr("ret");
+        let call = mk().call_expr(func, call_args);
+        let failed = if is_pointer {
+            mk().method_call_expr(ret_var(), "is_null", vec![])
+        } else {
+            mk().binary_expr(BinOp::Eq(Default::default()), ret_var(), neg_one())
+        };
+        let last_error = mk().call_expr(
+            mk().abs_path_expr(vec!["std", "io", "Error", "last_os_error"]),
+            vec![],
+        );
+        let return_error = mk().return_expr(Some(
+            mk().call_expr(mk().ident_expr("Err"), vec![last_error]),
+        ));
+        let stmts = vec![
+            mk().local_stmt(Box::new(mk().local(
+                mk().ident_pat("ret"),
+                None,
+                Some(call),
+            ))),
+            mk().semi_stmt(mk().ifte_expr(
+                failed,
+                mk().block(vec![mk().semi_stmt(return_error)]),
+                None,
+            )),
+            mk().expr_stmt(mk().call_expr(mk().ident_expr("Ok"), vec![ret_var()])),
+        ];
+
+        let decl = mk().fn_decl(
+            &wrapper,
+            args,
+            None,
+            ReturnType::Type(Default::default(), ret_ty),
+        );
+        let item = mk().unsafe_().fn_item(decl, mk().block(stmts));
+        self.items
+            .borrow_mut()
+            .entry(self.cur_file())
+            .or_insert_with(ItemStore::new)
+            .add_item(item);
+        Ok(wrapper)
+    }
+}
+
+fn neg_one() -> Box<Expr> {
+    mk().unary_expr(
+        UnOp::Neg(Default::default()),
+        mk().lit_expr(mk().int_unsuffixed_lit(1)),
+    )
+}
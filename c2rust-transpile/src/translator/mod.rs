@@ -1,6 +1,6 @@
 use std::cell::RefCell;
 use std::char;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::mem;
 use std::ops::Index;
 use std::path::{self, PathBuf};
@@ -22,6 +22,7 @@ use crate::rust_ast::comment_store::CommentStore;
 use crate::rust_ast::item_store::ItemStore;
 use crate::rust_ast::set_span::SetSpan;
 use crate::rust_ast::{pos_to_span, SpanExt};
+use crate::translator::errno::ErrnoAccessors;
 use crate::translator::named_references::NamedReference;
 use c2rust_ast_builder::{mk, properties::*, Builder};
 use c2rust_ast_printer::pprust::{self};
@@ -39,11 +40,15 @@ use c2rust_ast_exporter::clang_ast::LRValue;
 mod assembly;
 mod atomics;
 mod builtins;
+mod callbacks;
 mod comments;
+mod errno;
 mod literals;
 mod main_function;
 mod named_references;
 mod operators;
+mod promotions;
+mod provenance;
 mod simd;
 mod structs;
 mod variadic;
@@ -100,6 +105,15 @@ pub enum ReplaceMode {
     Extern,
 }
 
+/// How to translate an `alloca` whose size is not a compile-time constant
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AllocaMode {
+    /// A `Vec` local
+    Vec,
+    /// A `SmallVec` local from the `smallvec` crate, which stays on the stack for small sizes
+    SmallVec,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct ExprContext {
     used: bool,
@@ -256,6 +270,10 @@ pub struct Translation<'c> {
     function_context: RefCell<FuncContext>,
     potential_flexible_array_members: RefCell<IndexSet<CDeclId>>,
     macro_expansions: RefCell<IndexMap<CDeclId, Option<MacroExpansion>>>,
+    callback_trampolines: RefCell<IndexMap<(FileId, CDeclId, CTypeId), String>>,
+    errno_accessors: RefCell<IndexMap<FileId, ErrnoAccessors>>,
+    errno_wrappers: RefCell<IndexMap<(FileId, CDeclId), String>>,
+    public_decls: Option<HashSet<CDeclId>>,
 
     // Comment support
     pub comment_context: CommentContext,      // Incoming comments
@@ -514,10 +532,22 @@ pub fn translate(
 
         t.locate_comments();
 
+        // Find the public API before pruning drops the redeclarations in the public headers.
+        // The types that the public API exposes have to be public too.
+        if !tcfg.public_headers.is_empty() {
+            let mut public_decls = t.ast_context.decls_declared_in(&tcfg.public_headers);
+            public_decls.extend(t.ast_context.types_exposed_by(&public_decls));
+            t.public_decls = Some(public_decls);
+        }
+
         // Headers often pull in declarations that are unused;
         // we simplify the translator output by omitting those.
         t.ast_context
-            .prune_unwanted_decls(tcfg.preserve_unused_functions);
+            .prune_unwanted_decls(tcfg.preserve_unused_functions, tcfg.entry_points.as_ref());
+
+        if tcfg.emit_callback_trampolines {
+            t.ast_context.retype_callback_fields();
+        }
 
         enum Name<'a> {
             Var(&'a str),
@@ -601,8 +631,13 @@ pub fn translate(
                 || prenamed_decls.values().any(|id| *id == *decl_id)
         }
 
-        // Populate renamer with top-level names
-        for (&decl_id, decl) in t.ast_context.iter_decls() {
+        // Populate renamer with top-level names, starting with the public API, so that it keeps
+        // its C names
+        let (public_decls, private_decls): (Vec<_>, Vec<_>) = t
+            .ast_context
+            .iter_decls()
+            .partition(|&(decl_id, _)| !t.is_private_decl(*decl_id));
+        for (&decl_id, decl) in public_decls.into_iter().chain(private_decls) {
             use CDeclKind::*;
             let decl_name = match decl.kind {
                 _ if contains(&t.ast_context.prenamed_decls, &decl_id) => Name::None,
@@ -652,14 +687,14 @@ pub fn translate(
                         use ConvertedDecl::*;
                         match converted_decl {
                             Item(item) => {
-                                t.insert_item(item, decl);
+                                t.insert_item(item, decl_id, decl);
                             }
                             ForeignItem(item) => {
-                                t.insert_foreign_item(*item, decl);
+                                t.insert_foreign_item(*item, decl_id, decl);
                             }
                             Items(items) => {
                                 for item in items {
-                                    t.insert_item(item, decl);
+                                    t.insert_item(item, decl_id, decl);
                                 }
                             }
                             NoItem => {}
@@ -739,14 +774,14 @@ pub fn translate(
                         use ConvertedDecl::*;
                         match converted_decl {
                             Item(item) => {
-                                t.insert_item(item, decl);
+                                t.insert_item(item, decl_id, decl);
                             }
                             ForeignItem(item) => {
-                                t.insert_foreign_item(*item, decl);
+                                t.insert_foreign_item(*item, decl_id, decl);
                             }
                             Items(items) => {
                                 for item in items {
-                                    t.insert_item(item, decl);
+                                    t.insert_item(item, decl_id, decl);
                                 }
                             }
                             NoItem => {}
@@ -952,6 +987,39 @@ fn item_vis(i: &Item) -> Option<Visibility> {
     )
 }
 
+/// Get a mutable reference to the visibility of the kinds of items declarations translate to
+fn item_vis_mut(i: &mut Item) -> Option<&mut Visibility> {
+    use Item::*;
+    Some(match i {
+        Const(ic) => &mut ic.vis,
+        Enum(ie) => &mut ie.vis,
+        Fn(ifn) => &mut ifn.vis,
+        Static(is) => &mut is.vis,
+        Struct(is) => &mut is.vis,
+        Type(it) => &mut it.vis,
+        Union(iu) => &mut iu.vis,
+        _ => return None,
+    })
+}
+
+fn foreign_item_vis_mut(fi: &mut ForeignItem) -> Option<&mut Visibility> {
+    use ForeignItem::*;
+    Some(match fi {
+        Fn(ifn) => &mut ifn.vis,
+        Static(is) => &mut is.vis,
+        Type(it) => &mut it.vis,
+        _ => return None,
+    })
+}
+
+/// Turn a `pub` item into a `pub(crate)` one, which other modules of the crate can still use
+fn restrict_to_crate(vis: &mut Visibility) {
+    use c2rust_ast_builder::Make;
+    if let Visibility::Public(_) = vis {
+        *vis = "pub(crate)".make(&mk());
+    }
+}
+
 fn foreign_item_ident_vis(fi: &ForeignItem) -> Option<(&Ident, Visibility)> {
     use ForeignItem::*;
     Some(match fi {
@@ -1119,7 +1187,7 @@ fn foreign_item_attrs(item: &mut ForeignItem) -> Option<&mut Vec<syn::Attribute>
 }
 
 /// Get a mutable reference to the attributes of an Item
-fn item_attrs(item: &mut Item) -> Option<&mut Vec<syn::Attribute>> {
+pub(crate) fn item_attrs(item: &mut Item) -> Option<&mut Vec<syn::Attribute>> {
     use Item::*;
     Some(match item {
         Const(ItemConst { ref mut attrs, .. }) => attrs,
@@ -1232,6 +1300,10 @@ impl<'c> Translation<'c> {
             function_context: RefCell::new(FuncContext::new()),
             potential_flexible_array_members: RefCell::new(IndexSet::new()),
             macro_expansions: RefCell::new(IndexMap::new()),
+            callback_trampolines: RefCell::new(IndexMap::new()),
+            errno_accessors: RefCell::new(IndexMap::new()),
+            errno_wrappers: RefCell::new(IndexMap::new()),
+            public_decls: None,
             comment_context,
             comment_store: RefCell::new(CommentStore::new()),
             spans: HashMap::new(),
@@ -1915,6 +1987,20 @@ impl<'c> Translation<'c> {
                     attrs,
                 );
 
+                let converted_function = converted_function.map(|mut converted| {
+                    // Keep a disabled declaration of a function unreachable from the entry points
+                    if let ConvertedDecl::ForeignItem(ref mut item) = converted {
+                        if let ForeignItem::Fn(ref mut item) = **item {
+                            if self.ast_context.pruned_functions.contains(&decl_id) {
+                                item.attrs.extend(
+                                    mk().call_attr("cfg", vec!["c2rust_pruned"]).into_attrs(),
+                                );
+                            }
+                        }
+                    }
+                    converted
+                });
+
                 converted_function.or_else(|e| match self.tcfg.replace_unsupported_decls {
                     ReplaceMode::Extern if body.is_none() => self.convert_function(
                         ctx,
@@ -3450,6 +3536,11 @@ impl<'c> Translation<'c> {
 
             Literal(ty, ref kind) => self.convert_literal(ctx, ty, kind),
 
+            // `errno` is read through the `errno()` helper
+            ImplicitCast(_, expr, CastKind::LValueToRValue, _, _) if self.is_errno_access(expr) => {
+                self.convert_errno_read(ctx, expr)
+            }
+
             ImplicitCast(ty, expr, kind, opt_field_id, _)
             | ExplicitCast(ty, expr, kind, opt_field_id, _) => {
                 let is_explicit = matches!(expr_kind, CExprKind::ExplicitCast(..));
@@ -3731,6 +3822,24 @@ impl<'c> Translation<'c> {
                     // callee is a declref
                     if matches!(self.ast_context[fexp].kind, CExprKind::DeclRef(..)) =>
                         {
+                            // `alloca` and the MSVC atomic intrinsics are builtins even when
+                            // called through their declarations
+                            if let CExprKind::DeclRef(_, decl_id, _) = self.ast_context[fexp].kind {
+                                if matches!(
+                                    self.ast_context[decl_id].kind,
+                                    CDeclKind::Function { ref name, body: None, .. }
+                                        if name == "alloca" || builtins::is_msvc_atomic_intrinsic(name)
+                                ) {
+                                    return self.convert_builtin(ctx, fexp, args);
+                                }
+                            }
+                            if let Some(call) = self.convert_errno_call(ctx, fexp, args)? {
+                                return self.convert_side_effects_expr(
+                                    ctx,
+                                    call,
+                                    "Function call expression is not supposed to be used",
+                                );
+                            }
                             self.convert_expr(ctx.used(), fexp)?
                         }
 
@@ -4223,6 +4332,11 @@ impl<'c> Translation<'c> {
 
         match kind {
             CastKind::BitCast | CastKind::NoOp => {
+                if let Some(expr) = expr.filter(|_| self.tcfg.emit_callback_trampolines) {
+                    if let Some(trampoline) = self.convert_callback_cast(expr, ty.ctype)? {
+                        return Ok(val.map(|_| trampoline));
+                    }
+                }
                 val.and_then(|x| {
                     if self.ast_context.is_function_pointer(ty.ctype)
                         || self.ast_context.is_function_pointer(source_ty.ctype)
@@ -4251,6 +4365,12 @@ impl<'c> Translation<'c> {
                 })
             }
 
+            CastKind::IntegralToPointer | CastKind::PointerToIntegral
+                if !ctx.is_const && self.is_provenance_cast(source_ty, ty) =>
+            {
+                self.convert_provenance_cast(kind, ty, val)
+            }
+
             CastKind::IntegralToPointer
             | CastKind::PointerToIntegral
             | CastKind::IntegralCast
@@ -4297,6 +4417,12 @@ impl<'c> Translation<'c> {
                             Ok(WithStmts::new_unsafe_val(transmute_expr(
                                 source_ty, target_ty, x,
                             )))
+                        } else if *source_ty_kind == CTypeKind::Char
+                            && !matches!(target_ty_ctype, CTypeKind::SChar | CTypeKind::UChar)
+                        {
+                            Ok(WithStmts::new_val(
+                                self.convert_char_promotion(expr, x, target_ty),
+                            ))
                         } else {
                             Ok(WithStmts::new_val(mk().cast_expr(x, target_ty)))
                         }
@@ -4807,11 +4933,24 @@ impl<'c> Translation<'c> {
         result
     }
 
+    /// Is `decl_id` left out of the public API given by `--public-header`?
+    fn is_private_decl(&self, decl_id: CDeclId) -> bool {
+        self.public_decls
+            .as_ref()
+            .map_or(false, |public_decls| !public_decls.contains(&decl_id))
+    }
+
     /// If we're trying to organize item definitions into submodules, add them to a module
     /// scoped "namespace" if we have a path available, otherwise add it to the global "namespace"
-    fn insert_item(&self, mut item: Box<Item>, decl: &CDecl) {
+    fn insert_item(&self, mut item: Box<Item>, decl_id: CDeclId, decl: &CDecl) {
         let decl_file_id = self.ast_context.file_id(decl);
 
+        if self.is_private_decl(decl_id) {
+            if let Some(vis) = item_vis_mut(&mut item) {
+                restrict_to_crate(vis);
+            }
+        }
+
         if self.tcfg.reorganize_definitions {
             self.use_feature("register_tool");
             let attrs = item_attrs(&mut item).expect("no attrs field on unexpected item variant");
@@ -4829,9 +4968,15 @@ impl<'c> Translation<'c> {
 
     /// If we're trying to organize foreign item definitions into submodules, add them to a module
     /// scoped "namespace" if we have a path available, otherwise add it to the global "namespace"
-    fn insert_foreign_item(&self, mut item: ForeignItem, decl: &CDecl) {
+    fn insert_foreign_item(&self, mut item: ForeignItem, decl_id: CDeclId, decl: &CDecl) {
         let decl_file_id = self.ast_context.file_id(decl);
 
+        if self.is_private_decl(decl_id) {
+            if let Some(vis) = foreign_item_vis_mut(&mut item) {
+                restrict_to_crate(vis);
+            }
+        }
+
         if self.tcfg.reorganize_definitions {
             self.use_feature("register_tool");
             let attrs = foreign_item_attrs(&mut item)
@@ -250,6 +250,15 @@ impl<'c> Translation<'c> {
             .get_qual_type()
             .ok_or_else(|| format_err!("bad assignment rhs type"))?;
         let rhs_translation = self.convert_expr(ctx.used(), rhs)?;
+        // `errno` is written through the `set_errno()` helper, which doesn't return the value
+        if op == c_ast::BinOp::Assign && ctx.is_unused() && self.is_errno_access(lhs) {
+            let set_errno = self.convert_errno_write(ctx, lhs, rhs_translation)?;
+            return self.convert_side_effects_expr(
+                ctx,
+                set_errno,
+                "Assignment expression is not supposed to be used",
+            );
+        }
         self.convert_assignment_operator_with_rhs(
             ctx,
             op,
@@ -675,6 +684,9 @@ impl<'c> Translation<'c> {
         up: bool,
         arg: CExprId,
     ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        if let Some(translation) = self.convert_promoted_increment(ctx, up, true, arg)? {
+            return Ok(translation);
+        }
         let op = if up {
             c_ast::BinOp::AssignAdd
         } else {
@@ -721,6 +733,9 @@ impl<'c> Translation<'c> {
         if ctx.is_unused() {
             return self.convert_pre_increment(ctx, ty, up, arg);
         }
+        if let Some(translation) = self.convert_promoted_increment(ctx, up, false, arg)? {
+            return Ok(translation);
+        }
 
         let ty = self
             .ast_context
@@ -0,0 +1,144 @@
+//! This module handles the places where the translated arithmetic can behave differently from
+//! the C because of the signedness of plain `char` or the integer promotions.  The
+//! `-W promotions` lint lists them, and `--explicit-promotions` translates them with explicit
+//! casts that match the C semantics, reporting each one under the same lint.
+//!
+//! * A plain `char` is translated as `libc::c_char`, whose signedness depends on the Rust target
+//!   rather than the C target we translate for, so widening it may sign- or zero-extend
+//!   differently.
+//! * Incrementing or decrementing a `signed char` or `short` promotes it to `int` in C, so
+//!   overflow wraps when the result is converted back, while it panics in a debug Rust build.
+
+use crate::diagnostics::{diag, Diagnostic};
+
+use super::*;
+
+impl<'c> Translation<'c> {
+    /// Is plain `char` signed on the C target?  It is unsigned on most ARM, PowerPC, RISC-V, and
+    /// s390x targets, except on Apple and Windows platforms.
+    fn target_char_is_signed(&self) -> bool {
+        let target = &self.ast_context.target;
+        let arch = target.split('-').next().unwrap_or_default();
+        let unsigned_char = [
+            "arm", "thumb", "aarch64", "powerpc", "ppc", "riscv", "s390x",
+        ]
+        .iter()
+        .any(|prefix| arch.starts_with(prefix));
+        !unsigned_char || target.contains("apple") || target.contains("windows")
+    }
+
+    fn report_promotion(&self, expr: CExprId, message: &str) {
+        let made_explicit = if self.tcfg.explicit_promotions {
+            " (made explicit)"
+        } else {
+            ""
+        };
+        diag!(
+            Diagnostic::Promotions,
+            "{}: {}{}",
+            self.ast_context
+                .display_loc(&self.ast_context[expr].loc)
+                .map_or("unknown location".to_string(), |l| l.to_string()),
+            message,
+            made_explicit,
+        );
+    }
+
+    /// Translate the conversion of `val`, a plain `char`, to the wider integer or floating type
+    /// `target_ty`, as `val as target_ty`, or through `i8` or `u8` with `--explicit-promotions`.
+    pub fn convert_char_promotion(
+        &self,
+        expr: Option<CExprId>,
+        val: Box<Expr>,
+        target_ty: Box<Type>,
+    ) -> Box<Expr> {
+        if let Some(expr) = expr {
+            self.report_promotion(
+                expr,
+                "conversion of plain `char` depends on the signedness of `c_char`",
+            );
+        }
+        if !self.tcfg.explicit_promotions {
+            return mk().cast_expr(val, target_ty);
+        }
+        let char_ty = if self.target_char_is_signed() {
+            "i8"
+        } else {
+            "u8"
+        };
+        mk().cast_expr(mk().cast_expr(val, mk().path_ty(vec![char_ty])), target_ty)
+    }
+
+    /// Is `ctype` a signed type narrower than `int`, whose increments wrap through promotion?
+    fn is_promoted_signed_type(&self, ctype: CTypeId) -> bool {
+        match self.ast_context.resolve_type(ctype).kind {
+            CTypeKind::SChar | CTypeKind::Short => true,
+            CTypeKind::Char => self.target_char_is_signed(),
+            _ => false,
+        }
+    }
+
+    /// Translate an increment or decrement of `arg`, if it is of a type narrower than `int`
+    /// which overflows differently in Rust, as `(arg as c_int + 1) as T` with
+    /// `--explicit-promotions`.  Returns `None` to translate it as usual.
+    pub fn convert_promoted_increment(
+        &self,
+        ctx: ExprContext,
+        up: bool,
+        pre: bool,
+        arg: CExprId,
+    ) -> TranslationResult<Option<WithStmts<Box<Expr>>>> {
+        let arg_ty = self.ast_context[arg]
+            .kind
+            .get_qual_type()
+            .ok_or_else(|| format_err!("bad arg type"))?;
+        if !self.is_promoted_signed_type(arg_ty.ctype) {
+            return Ok(None);
+        }
+        self.report_promotion(
+            arg,
+            "overflow of a promoted increment or decrement panics in Rust",
+        );
+        if !self.tcfg.explicit_promotions {
+            return Ok(None);
+        }
+
+        let ty = self.convert_type(arg_ty.ctype)?;
+        let int_ty = mk().path_ty(mk().path(vec!["libc", "c_int"]));
+        let op = if up {
+            BinOp::Add(Default::default())
+        } else {
+            BinOp::Sub(Default::default())
+        };
+        let translation = self.name_reference_write_read(ctx, arg)?.and_then(
+            |NamedReference {
+                 lvalue: write,
+                 rvalue: read,
+             }| {
+                let one = mk().lit_expr(mk().int_unsuffixed_lit(1));
+                let promoted = mk().cast_expr(read.clone(), int_ty);
+                let val = mk().cast_expr(mk().binary_expr(op, promoted, one), ty);
+                let assign = if arg_ty.qualifiers.is_volatile {
+                    self.volatile_write(write, arg_ty, val)?
+                } else {
+                    mk().assign_expr(write, val)
+                };
+
+                if pre {
+                    return Ok(WithStmts::new(vec![mk().semi_stmt(assign)], read));
+                }
+                let old_val_name = self.renamer.borrow_mut().fresh();
+                let save_old_val = mk().local_stmt(Box::new(mk().local(
+                    mk().ident_pat(&old_val_name),
+                    None,
+                    Some(read),
+                )));
+                Ok(WithStmts::new(
+                    vec![save_old_val, mk().semi_stmt(assign)],
+                    mk().ident_expr(old_val_name),
+                ))
+            },
+        )?;
+        Ok(Some(translation))
+    }
+}
@@ -0,0 +1,61 @@
+//! This module implements the provenance-preserving pointer/integer casts enabled by
+//! `--preserve-provenance`.  C code that round-trips pointers through `uintptr_t` relies on the
+//! integer carrying the pointer's provenance, which a plain `as` cast doesn't guarantee under the
+//! strict provenance model.  We instead translate pointer-to-integer casts as `expose_addr()`
+//! and integer-to-pointer casts as `from_exposed_addr()`, which also marks these casts for
+//! later analyses.
+
+use super::*;
+
+impl<'c> Translation<'c> {
+    /// Should the `PointerToIntegral` or `IntegralToPointer` cast from `source_ty` to `ty` go
+    /// through [`Self::convert_provenance_cast`]?
+    pub fn is_provenance_cast(&self, source_ty: CQualTypeId, ty: CQualTypeId) -> bool {
+        // Function pointers are `Option<fn>`s, which don't have these methods.
+        self.tcfg.preserve_provenance
+            && !self.ast_context.is_function_pointer(source_ty.ctype)
+            && !self.ast_context.is_function_pointer(ty.ctype)
+    }
+
+    /// Translate a `PointerToIntegral` cast of `val` as `val.expose_addr() as ty`, or an
+    /// `IntegralToPointer` cast as `from_exposed_addr::<T>(val as usize)`.
+    pub fn convert_provenance_cast(
+        &self,
+        kind: CastKind,
+        ty: CQualTypeId,
+        val: WithStmts<Box<Expr>>,
+    ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        self.use_feature("strict_provenance");
+
+        if kind == CastKind::PointerToIntegral {
+            let target_ty = self.convert_type(ty.ctype)?;
+            return Ok(val.map(|val| {
+                let addr = mk().method_call_expr(val, "expose_addr", vec![]);
+                mk().cast_expr(addr, target_ty)
+            }));
+        }
+
+        let pointee = self
+            .ast_context
+            .get_pointee_qual_type(ty.ctype)
+            .ok_or_else(|| format_err!("integer-to-pointer cast to a non-pointer type"))?;
+        let from_exposed_addr = if pointee.qualifiers.is_const {
+            "from_exposed_addr"
+        } else {
+            "from_exposed_addr_mut"
+        };
+        let pointee_ty = self.convert_type(pointee.ctype)?;
+        let func = mk().path_expr(mk().abs_path(vec![
+            mk().path_segment("core"),
+            mk().path_segment("ptr"),
+            mk().path_segment_with_args(
+                from_exposed_addr,
+                mk().angle_bracketed_args(vec![pointee_ty]),
+            ),
+        ]));
+        Ok(val.map(|val| {
+            let addr = mk().cast_expr(val, mk().path_ty(vec!["usize"]));
+            mk().call_expr(func, vec![addr])
+        }))
+    }
+}
@@ -1,9 +1,13 @@
 use clap::{Parser, ValueEnum};
 use log::LevelFilter;
 use regex::Regex;
-use std::{fs, path::PathBuf};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
 
-use c2rust_transpile::{Diagnostic, ReplaceMode, TranspilerConfig};
+use c2rust_transpile::{AllocaMode, Diagnostic, ReplaceMode, TranspilerConfig};
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -48,6 +52,26 @@ struct Args {
     #[clap(long)]
     translate_fn_macros: bool,
 
+    /// Translate `void *` struct fields holding callbacks as `Option<fn>`, and adapt functions passed as callbacks of a different type through typed trampolines
+    #[clap(long)]
+    emit_callback_trampolines: bool,
+
+    /// Call the specified errno-returning function through a generated wrapper returning `io::Result`, and access errno through `errno()` and `set_errno()` helpers
+    #[clap(long = "errno-wrapper", multiple = true, number_of_values = 1)]
+    errno_wrapper: Option<Vec<String>>,
+
+    /// How to allocate the buffer of an `alloca` whose size is not a compile-time constant
+    #[clap(long, value_enum, default_value_t = DynamicAlloca::Vec)]
+    dynamic_alloca: DynamicAlloca,
+
+    /// Translate conversions of plain `char` and increments of types narrower than `int` with explicit casts matching the C semantics, reporting each site as a `promotions` warning
+    #[clap(long)]
+    explicit_promotions: bool,
+
+    /// Translate casts between pointers and integers with the strict provenance APIs `expose_addr` and `from_exposed_addr` instead of `as`
+    #[clap(long)]
+    preserve_provenance: bool,
+
     /// Disable relooping function bodies incrementally
     #[clap(long)]
     no_incremental_relooper: bool,
@@ -84,10 +108,23 @@ struct Args {
     #[clap(long = "ddebug-labels")]
     debug_labels: bool,
 
-    /// Path to compile_commands.json, or a list of source files
+    /// Path to compile_commands.json, a TOML project description, or a list of source files
     #[clap(parse(from_os_str), multiple_values = true)]
     compile_commands: Vec<PathBuf>,
 
+    /// Add a directory to the include path of the source files given instead of a compile_commands.json
+    #[clap(
+        short = 'I',
+        long = "include-dir",
+        multiple = true,
+        number_of_values = 1
+    )]
+    include_dirs: Vec<PathBuf>,
+
+    /// Define a macro, as `NAME` or `NAME=VALUE`, when compiling the source files given instead of a compile_commands.json
+    #[clap(short = 'D', long = "define", multiple = true, number_of_values = 1)]
+    defines: Vec<String>,
+
     /// How to handle violated invariants or invalid code
     #[clap(long, value_enum, default_value_t = InvalidCodes::CompileError)]
     invalid_code: InvalidCodes,
@@ -148,6 +185,18 @@ struct Args {
     #[clap(long)]
     preserve_unused_functions: bool,
 
+    /// Only translate the definitions of functions reachable from the functions listed in the specified file, one per line, or in the specified GNU ld map file; other externally visible functions are declared behind `#[cfg(c2rust_pruned)]`
+    #[clap(long, value_name = "FILE", value_parser = parse_entry_points)]
+    entry_points: Option<HashSet<String>>,
+
+    /// Make the functions and types declared in the specified header public, with their C names, and everything else visible only within the crate
+    #[clap(long = "public-header", multiple = true, number_of_values = 1)]
+    public_header: Option<Vec<PathBuf>>,
+
+    /// Translate each file under the preprocessor configurations in the specified file, one `<feature> = <clang args>` per line, into a single module gating configuration-specific items on the cargo features
+    #[clap(long, value_name = "FILE")]
+    feature_matrix: Option<PathBuf>,
+
     /// Logging level
     #[clap(long, default_value_t = LevelFilter::Warn)]
     log_level: LevelFilter,
@@ -157,6 +206,11 @@ struct Args {
     fail_on_multiple: bool,
 }
 
+fn parse_entry_points(path: &str) -> Result<HashSet<String>, String> {
+    c2rust_transpile::read_entry_points(Path::new(path))
+        .map_err(|e| format!("failed to read entry points from {}: {}", path, e))
+}
+
 #[derive(Debug, PartialEq, Eq, ValueEnum, Clone)]
 #[clap(rename_all = "snake_case")]
 enum InvalidCodes {
@@ -164,6 +218,13 @@ enum InvalidCodes {
     CompileError,
 }
 
+#[derive(Debug, PartialEq, Eq, ValueEnum, Clone)]
+#[clap(rename_all = "snake_case")]
+enum DynamicAlloca {
+    Vec,
+    SmallVec,
+}
+
 fn main() {
     let args = Args::parse();
 
@@ -198,8 +259,39 @@ fn main() {
 
         translate_const_macros: args.translate_const_macros,
         translate_fn_macros: args.translate_fn_macros,
+        emit_callback_trampolines: args.emit_callback_trampolines,
+        errno_wrappers: args.errno_wrapper.unwrap_or_default(),
+        dynamic_alloca: match args.dynamic_alloca {
+            DynamicAlloca::Vec => AllocaMode::Vec,
+            DynamicAlloca::SmallVec => AllocaMode::SmallVec,
+        },
+        explicit_promotions: args.explicit_promotions,
+        preserve_provenance: args.preserve_provenance,
         disable_refactoring: args.disable_refactoring,
         preserve_unused_functions: args.preserve_unused_functions,
+        entry_points: args.entry_points,
+        public_headers: args
+            .public_header
+            .unwrap_or_default()
+            .iter()
+            .map(|path| {
+                fs::canonicalize(path).unwrap_or_else(|e| {
+                    panic!("Failed to canonicalize path {}: {:?}", path.display(), e)
+                })
+            })
+            .collect(),
+        feature_configs: args
+            .feature_matrix
+            .map(|path| {
+                c2rust_transpile::read_feature_matrix(&path).unwrap_or_else(|e| {
+                    panic!(
+                        "Failed to read feature matrix from {}: {}",
+                        path.display(),
+                        e
+                    )
+                })
+            })
+            .unwrap_or_default(),
 
         use_c_loop_info: !args.ignore_c_loop_info,
         use_c_multiple_info: !args.ignore_c_multiple_info,
@@ -217,6 +309,10 @@ fn main() {
         enabled_warnings: args.warn.into_iter().collect(),
         log_level: args.log_level,
     };
+    // explicit-promotions reports the sites it changes
+    if tcfg.explicit_promotions {
+        tcfg.enabled_warnings.insert(Diagnostic::Promotions);
+    }
     // binaries imply emit-build-files
     if !tcfg.binaries.is_empty() {
         tcfg.emit_build_files = true
@@ -228,26 +324,52 @@ fn main() {
 
     let mut created_temp_compile_commands = false;
 
+    // Include directories and defines given on the command line apply to the source files
+    let mut clang_args = args
+        .include_dirs
+        .iter()
+        .map(|dir| format!("-I{}", dir.display()))
+        .collect::<Vec<_>>();
+    clang_args.extend(args.defines.iter().map(|define| format!("-D{}", define)));
+
+    let has_extension =
+        |path: &PathBuf, ext: &str| path.extension() == Some(std::ffi::OsStr::new(ext));
     let compile_commands = if args.compile_commands.len() == 1
-        && args.compile_commands[0].extension() == Some(std::ffi::OsStr::new("json"))
+        && has_extension(&args.compile_commands[0], "json")
     {
         // Only one file provided and it's a JSON file
+        if !clang_args.is_empty() {
+            panic!("Include directories and defines can't be given with a compile_commands.json; add them to its commands instead.");
+        }
         match fs::canonicalize(&args.compile_commands[0]) {
             Ok(canonical_path) => canonical_path,
             Err(e) => panic!("Failed to canonicalize path: {:?}", e),
         }
+    } else if args.compile_commands.len() == 1 && has_extension(&args.compile_commands[0], "toml") {
+        // Only one file provided and it's a project description
+        let path = &args.compile_commands[0];
+        let project = c2rust_transpile::read_project_description(path).unwrap_or_else(|e| {
+            panic!(
+                "Failed to read project description from {}: {}",
+                path.display(),
+                e
+            )
+        });
+        clang_args.extend(project.clang_args);
+        created_temp_compile_commands = true;
+        c2rust_transpile::create_temp_compile_commands(&project.sources, &clang_args)
     } else if args
         .compile_commands
         .iter()
-        .any(|path| path.extension() == Some(std::ffi::OsStr::new("json")))
+        .any(|path| has_extension(path, "json") || has_extension(path, "toml"))
     {
-        // More than one file provided and at least one is a JSON file
-        panic!("Compile commands JSON and multiple sources provided.
-                Exactly one compile_commands.json file should be provided, or a list of source files, but not both.");
+        // More than one file provided and at least one is a JSON or TOML file
+        panic!("Compile commands JSON or project description and multiple sources provided.
+                Exactly one compile_commands.json or project description file should be provided, or a list of source files, but not both.");
     } else {
         // Handle as a list of source files
         created_temp_compile_commands = true;
-        c2rust_transpile::create_temp_compile_commands(&args.compile_commands)
+        c2rust_transpile::create_temp_compile_commands(&args.compile_commands, &clang_args)
     };
 
     let extra_args = args
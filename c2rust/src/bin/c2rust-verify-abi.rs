@@ -0,0 +1,975 @@
+//! Verify that a translated crate exposes the same ABI as the C headers it was translated from.
+//!
+//! We generate a C header from the translated crate with `cbindgen` and compare its function
+//! prototypes, global variables, and struct and union layouts against the original headers.
+//! Both headers are compared token by token after dropping comments, preprocessor directives,
+//! parameter and field names, and spelling differences like `unsigned` vs. `unsigned int`, so
+//! formatting differences don't show up as mismatches.  `#if` blocks are resolved against the
+//! macros given with `--define` and the ones the headers define, so only one branch of a
+//! platform conditional is compared.
+
+use anyhow::{anyhow, bail, Context};
+use clap::Parser;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "verify-abi",
+    author = "- The C2Rust Project Developers <c2rust@immunant.com>",
+    version,
+    about = "Check a translated crate against the C headers it was translated from, using cbindgen",
+    long_about = None
+)]
+struct Args {
+    /// The directory of the translated crate
+    crate_dir: PathBuf,
+
+    /// A C header declaring the API the crate should expose (may be given multiple times)
+    #[clap(
+        long = "header",
+        required = true,
+        multiple = true,
+        number_of_values = 1
+    )]
+    headers: Vec<PathBuf>,
+
+    /// Define a macro for evaluating `#if` conditions in the headers, as `NAME` or `NAME=VALUE`
+    /// (may be given multiple times)
+    #[clap(short = 'D', long = "define", multiple = true, number_of_values = 1)]
+    defines: Vec<String>,
+
+    /// The `cbindgen` executable to generate the crate's header with
+    #[clap(long, default_value = "cbindgen")]
+    cbindgen: PathBuf,
+
+    /// Write the JSON mismatch report to this file instead of stdout
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DeclKind {
+    Function,
+    Global,
+    Record,
+}
+
+impl Display for DeclKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self {
+            DeclKind::Function => "function",
+            DeclKind::Global => "global",
+            DeclKind::Record => "record",
+        };
+        f.write_str(kind)
+    }
+}
+
+/// A declaration relevant to the ABI, with its normalized signature: the type of a global,
+/// the prototype of a function without parameter names, or the field types of a record.
+#[derive(Debug)]
+struct Decl {
+    kind: DeclKind,
+    signature: String,
+}
+
+/// Keywords which can be part of a type, and so are never a parameter or field name.
+const TYPE_KEYWORDS: &[&str] = &[
+    "void", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "_Bool",
+    "bool", "const", "volatile", "struct", "union", "enum",
+];
+
+/// Tokens which don't affect the ABI and are dropped before comparing.
+const IGNORED_TOKENS: &[&str] = &[
+    "extern",
+    "restrict",
+    "__restrict",
+    "__restrict__",
+    "__extension__",
+];
+
+fn is_ident(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .map_or(false, |c| c.is_alphabetic() || c == '_')
+}
+
+fn is_name(token: &str) -> bool {
+    is_ident(token) && !TYPE_KEYWORDS.contains(&token)
+}
+
+/// Split C source into tokens, dropping comments and preprocessor directives.
+fn tokenize(src: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line_start = true,
+            c if c.is_whitespace() => {}
+            '#' if line_start => {
+                // Skip the directive, including its continuation lines.
+                let mut prev = '#';
+                for c in chars.by_ref() {
+                    if c == '\n' && prev != '\\' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line_start = true;
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            '"' => {
+                let mut token = String::from('"');
+                let mut prev = '"';
+                for c in chars.by_ref() {
+                    token.push(c);
+                    if c == '"' && prev != '\\' {
+                        break;
+                    }
+                    prev = c;
+                }
+                tokens.push(token);
+                line_start = false;
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut token = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if !c.is_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push(token);
+                line_start = false;
+            }
+            '.' if chars.peek() == Some(&'.') => {
+                chars.next();
+                chars.next();
+                tokens.push("...".to_owned());
+                line_start = false;
+            }
+            c => {
+                tokens.push(c.to_string());
+                line_start = false;
+            }
+        }
+    }
+    tokens
+}
+
+/// Macros known while evaluating `#if` conditions, mapped to their values.
+type Defines = BTreeMap<String, String>;
+
+/// Parse a `--define` argument, which defines the macro as `1` if no value is given, like `-D`
+/// does for C compilers.
+fn parse_define(arg: &str) -> (String, String) {
+    match arg.split_once('=') {
+        Some((name, value)) => (name.to_owned(), value.to_owned()),
+        None => (arg.to_owned(), "1".to_owned()),
+    }
+}
+
+/// An open `#if`, `#ifdef`, or `#ifndef` block.
+struct Conditional {
+    /// The line the block starts on, for error messages.
+    line: usize,
+    /// Whether the block containing this one is active.
+    parent_active: bool,
+    /// Whether one of the branches seen so far was taken.
+    taken: bool,
+    /// Whether the current branch is active.
+    active: bool,
+}
+
+/// Drop preprocessor directives and the lines of inactive `#if` branches, evaluating conditions
+/// against `defines` and the `#define`s seen so far.  This resolves include guards and
+/// `#ifdef __cplusplus` blocks as well as platform conditionals, so the declarations of two
+/// branches aren't merged.  Conditions other than `defined`, integers, macro names, `!`, `&&`,
+/// and `||` are rejected rather than guessed at.
+fn preprocess(src: &str, defines: &Defines) -> anyhow::Result<String> {
+    let mut defines = defines.clone();
+    let mut conditionals: Vec<Conditional> = Vec::new();
+    let mut out = String::new();
+    let mut lines = src.lines().enumerate().map(|(i, line)| (i + 1, line));
+    while let Some((line_no, line)) = lines.next() {
+        let active = conditionals.last().map_or(true, |c| c.active);
+        let directive = match line.trim_start().strip_prefix('#') {
+            Some(directive) => directive,
+            None => {
+                if active {
+                    out.push_str(line);
+                }
+                out.push('\n');
+                continue;
+            }
+        };
+        let mut directive = directive.to_owned();
+        while directive.ends_with('\\') {
+            directive.pop();
+            match lines.next() {
+                Some((_, line)) => directive.push_str(line),
+                None => break,
+            }
+        }
+        out.push('\n');
+
+        let directive = directive.trim_start();
+        let name_len = directive
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(directive.len());
+        let (name, rest) = directive.split_at(name_len);
+        let rest = rest.trim();
+        let first_word = || rest.split_whitespace().next().unwrap_or("");
+        let eval = |defines: &Defines| {
+            eval_condition(rest, defines).with_context(|| format!("on line {line_no}"))
+        };
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                let taken = active
+                    && match name {
+                        "if" => eval(&defines)?,
+                        "ifdef" => defines.contains_key(first_word()),
+                        _ => !defines.contains_key(first_word()),
+                    };
+                conditionals.push(Conditional {
+                    line: line_no,
+                    parent_active: active,
+                    taken,
+                    active: taken,
+                });
+            }
+            "elif" | "else" | "endif" => {
+                let cond = match conditionals.last_mut() {
+                    Some(cond) => cond,
+                    None => bail!("#{name} without #if on line {line_no}"),
+                };
+                match name {
+                    "elif" => {
+                        cond.active = cond.parent_active && !cond.taken && eval(&defines)?;
+                        cond.taken |= cond.active;
+                    }
+                    "else" => {
+                        cond.active = cond.parent_active && !cond.taken;
+                        cond.taken = true;
+                    }
+                    _ => {
+                        conditionals.pop();
+                    }
+                }
+            }
+            "define" if active => {
+                let name_len = rest
+                    .find(|c: char| !c.is_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                let (name, value) = rest.split_at(name_len);
+                defines.insert(name.to_owned(), value.trim().to_owned());
+            }
+            "undef" if active => {
+                defines.remove(first_word());
+            }
+            _ => {}
+        }
+    }
+    if let Some(cond) = conditionals.last() {
+        bail!("unterminated #if starting on line {}", cond.line);
+    }
+    Ok(out)
+}
+
+/// Evaluate the condition of an `#if` or `#elif`.
+fn eval_condition(expr: &str, defines: &Defines) -> anyhow::Result<bool> {
+    let tokens = tokenize(expr);
+    let mut parser = ConditionParser {
+        tokens: &tokens,
+        pos: 0,
+        defines,
+    };
+    let value = parser.parse_or();
+    match value {
+        Some(value) if parser.pos == tokens.len() => Ok(value != 0),
+        _ => bail!("unsupported #if condition `{expr}`"),
+    }
+}
+
+/// A recursive descent parser for the subset of `#if` conditions we evaluate.  Each method
+/// returns `None` if the condition uses anything outside that subset.
+struct ConditionParser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+    defines: &'a Defines,
+}
+
+impl ConditionParser<'_> {
+    fn peek(&self, n: usize) -> Option<&str> {
+        self.tokens.get(self.pos + n).map(|t| t.as_str())
+    }
+
+    fn eat(&mut self, token: &str) -> Option<()> {
+        if self.peek(0) == Some(token) {
+            self.pos += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn parse_or(&mut self) -> Option<i64> {
+        let mut value = self.parse_and()?;
+        while self.peek(0) == Some("|") && self.peek(1) == Some("|") {
+            self.pos += 2;
+            let rhs = self.parse_and()?;
+            value = (value != 0 || rhs != 0) as i64;
+        }
+        Some(value)
+    }
+
+    fn parse_and(&mut self) -> Option<i64> {
+        let mut value = self.parse_unary()?;
+        while self.peek(0) == Some("&") && self.peek(1) == Some("&") {
+            self.pos += 2;
+            let rhs = self.parse_unary()?;
+            value = (value != 0 && rhs != 0) as i64;
+        }
+        Some(value)
+    }
+
+    fn parse_unary(&mut self) -> Option<i64> {
+        if self.eat("!").is_some() {
+            return Some((self.parse_unary()? == 0) as i64);
+        }
+        if self.eat("(").is_some() {
+            let value = self.parse_or()?;
+            self.eat(")")?;
+            return Some(value);
+        }
+        let token = self.peek(0)?.to_owned();
+        self.pos += 1;
+        if token == "defined" {
+            let parens = self.eat("(").is_some();
+            let name = self.peek(0).filter(|t| is_ident(t))?.to_owned();
+            self.pos += 1;
+            if parens {
+                self.eat(")")?;
+            }
+            return Some(self.defines.contains_key(&name) as i64);
+        }
+        if is_ident(&token) {
+            // Undefined macros evaluate to 0, as in C.
+            return match self.defines.get(&token) {
+                Some(value) => parse_int(value),
+                None => Some(0),
+            };
+        }
+        parse_int(&token)
+    }
+}
+
+/// Parse a C integer literal, ignoring any `u` and `l` suffixes.
+fn parse_int(literal: &str) -> Option<i64> {
+    let literal = literal.trim_end_matches(['u', 'U', 'l', 'L']);
+    match literal
+        .strip_prefix("0x")
+        .or_else(|| literal.strip_prefix("0X"))
+    {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => literal.parse().ok(),
+    }
+}
+
+/// Split tokens into top-level declarations, which end with a `;` or the body of a function.
+fn split_decls(tokens: Vec<String>) -> Vec<Vec<String>> {
+    let mut decls = Vec::new();
+    let mut decl = Vec::new();
+    let mut depth = 0usize;
+    // Whether each open brace is the body of a function, which isn't followed by a `;`.
+    let mut braces = Vec::new();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token.as_str() {
+            // Unwrap `extern "C" { ... }` blocks from headers meant for C++ as well.
+            "extern" if depth == 0 && tokens.peek().map(|t| t.as_str()) == Some("\"C\"") => {
+                tokens.next();
+                if tokens.peek().map(|t| t.as_str()) == Some("{") {
+                    tokens.next();
+                    braces.push(None);
+                }
+                continue;
+            }
+            "}" if depth == 0 && braces.last() == Some(&None) => {
+                braces.pop();
+                continue;
+            }
+            "{" => {
+                braces.push(Some(decl.last().map(|t: &String| t.as_str()) == Some(")")));
+                depth += 1;
+            }
+            "(" | "[" => depth += 1,
+            ")" | "]" => depth = depth.saturating_sub(1),
+            "}" => {
+                depth = depth.saturating_sub(1);
+                if braces.pop() == Some(Some(true)) && depth == 0 {
+                    decl.push(token);
+                    decls.push(std::mem::take(&mut decl));
+                    continue;
+                }
+            }
+            ";" if depth == 0 => {
+                if !decl.is_empty() {
+                    decls.push(std::mem::take(&mut decl));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        decl.push(token);
+    }
+    decls
+}
+
+/// Drop tokens that don't affect the ABI, including `__attribute__((...))`.
+fn strip_ignored(tokens: &[String]) -> Vec<String> {
+    let mut stripped = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i].as_str();
+        if token == "__attribute__" || token == "__asm__" || token == "asm" {
+            i = skip_group(tokens, i + 1);
+            continue;
+        }
+        if !IGNORED_TOKENS.contains(&token) {
+            stripped.push(tokens[i].clone());
+        }
+        i += 1;
+    }
+    stripped
+}
+
+/// If `tokens[start]` opens a parenthesized group, return the index after the group.
+fn skip_group(tokens: &[String], start: usize) -> usize {
+    if tokens.get(start).map(|t| t.as_str()) != Some("(") {
+        return start;
+    }
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token.as_str() {
+            "(" => depth += 1,
+            ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+/// Split `tokens` at top-level occurrences of `sep`.
+fn split_top_level<'a>(tokens: &'a [String], sep: &str) -> Vec<&'a [String]> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.as_str() {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth -= 1,
+            t if t == sep && depth == 0 => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < tokens.len() {
+        parts.push(&tokens[start..]);
+    }
+    parts
+}
+
+/// Spell a type canonically: drop the tags of `struct`, `union`, and `enum` types, which
+/// `cbindgen` refers to by their typedefs, and spell integer types the same way throughout.
+fn normalize_type(tokens: &[String]) -> String {
+    let mut normalized = Vec::new();
+    let mut int_words = Vec::new();
+    let flush = |int_words: &mut Vec<&str>, normalized: &mut Vec<String>| {
+        if !int_words.is_empty() {
+            normalized.push(canonical_int(int_words));
+            int_words.clear();
+        }
+    };
+    for token in tokens {
+        match token.as_str() {
+            "struct" | "union" | "enum" => {}
+            "_Bool" => {
+                flush(&mut int_words, &mut normalized);
+                normalized.push("bool".to_owned());
+            }
+            t @ ("signed" | "unsigned" | "short" | "long" | "int" | "char") => int_words.push(t),
+            t => {
+                flush(&mut int_words, &mut normalized);
+                normalized.push(t.to_owned());
+            }
+        }
+    }
+    flush(&mut int_words, &mut normalized);
+    normalized.join(" ")
+}
+
+/// The canonical spelling of the integer type spelled with `words`, e.g. `unsigned long` for
+/// `long unsigned int`.
+fn canonical_int(words: &[&str]) -> String {
+    let unsigned = words.contains(&"unsigned");
+    let longs = words.iter().filter(|&&w| w == "long").count();
+    let base = if words.contains(&"char") {
+        if words.contains(&"signed") {
+            return "signed char".to_owned();
+        }
+        "char"
+    } else if words.contains(&"short") {
+        "short"
+    } else if longs >= 2 {
+        "long long"
+    } else if longs == 1 {
+        "long"
+    } else {
+        "int"
+    };
+    if unsigned {
+        format!("unsigned {base}")
+    } else {
+        base.to_owned()
+    }
+}
+
+/// Find the name declared by a declarator, such as `x` in `int *x[4]` or `f` in
+/// `int (*f)(void)`, and return its index.
+fn declarator_name(tokens: &[String]) -> Option<usize> {
+    // Function pointers: the name follows `(*`.
+    if let Some(i) = tokens.windows(2).position(|w| w[0] == "(" && w[1] == "*") {
+        return tokens[i + 2..]
+            .iter()
+            .position(|t| is_name(t))
+            .map(|j| i + 2 + j);
+    }
+    // Otherwise, it's the last name before any array dimensions or bitfield width.
+    let end = tokens
+        .iter()
+        .position(|t| t == "[" || t == ":")
+        .unwrap_or(tokens.len());
+    let i = end.checked_sub(1)?;
+    let is_tag = i > 0 && matches!(tokens[i - 1].as_str(), "struct" | "union" | "enum");
+    // A lone name, or one following a tag, is the type of an unnamed parameter.
+    if is_name(&tokens[i]) && i > 0 && !is_tag {
+        Some(i)
+    } else {
+        None
+    }
+}
+
+/// Drop the parameter names of a function pointer type, such as `x` in `int (*)(int x)`.
+fn strip_fn_pointer_param_names(tokens: &mut Vec<String>) {
+    let start = match tokens.windows(2).position(|w| w[0] == "(" && w[1] == "*") {
+        Some(i) => i,
+        None => return,
+    };
+    let open = skip_group(tokens, start);
+    if tokens.get(open).map(|t| t.as_str()) != Some("(") {
+        return;
+    }
+    let close = skip_group(tokens, open) - 1;
+    if tokens[close] != ")" {
+        return;
+    }
+    let params = split_top_level(&tokens[open + 1..close], ",")
+        .into_iter()
+        .map(normalize_param)
+        .collect::<Vec<_>>()
+        .join(" , ");
+    let params = params.split(' ').map(str::to_owned).collect::<Vec<_>>();
+    tokens.splice(open + 1..close, params);
+}
+
+/// Normalize a parameter, dropping its name and decaying arrays to pointers.
+fn normalize_param(tokens: &[String]) -> String {
+    let mut tokens = tokens.to_vec();
+    if let Some(i) = declarator_name(&tokens) {
+        tokens.remove(i);
+    }
+    strip_fn_pointer_param_names(&mut tokens);
+    if let Some(i) = tokens.iter().position(|t| t == "[") {
+        if !tokens.contains(&"(".to_owned()) {
+            tokens.truncate(i);
+            tokens.push("*".to_owned());
+        }
+    }
+    normalize_type(&tokens)
+}
+
+/// Normalize a field or global, dropping its name but keeping array dimensions and widths.
+fn normalize_member(tokens: &[String]) -> (Option<String>, String) {
+    let mut tokens = tokens.to_vec();
+    let name = declarator_name(&tokens).map(|i| tokens.remove(i));
+    strip_fn_pointer_param_names(&mut tokens);
+    (name, normalize_type(&tokens))
+}
+
+/// Parse a top-level declaration into the name and [`Decl`] it declares, if it's relevant to
+/// the ABI.  `static` and `inline` declarations aren't, nor are typedefs and enums, which
+/// `c2rust transpile` translates to type aliases and constants.
+fn parse_decl(tokens: &[String]) -> Option<(String, Decl)> {
+    let tokens = strip_ignored(tokens);
+    let first = tokens.first()?.as_str();
+    if tokens
+        .iter()
+        .any(|t| matches!(t.as_str(), "static" | "inline" | "__inline" | "__inline__"))
+    {
+        return None;
+    }
+
+    if let Some(open) = tokens.iter().position(|t| t == "{") {
+        let (is_typedef, tag_index) = match first {
+            "typedef" => (true, 1),
+            _ => (false, 0),
+        };
+        if !matches!(
+            tokens.get(tag_index).map(|t| t.as_str()),
+            Some("struct" | "union")
+        ) {
+            return None;
+        }
+        let close = tokens.iter().rposition(|t| t == "}")?;
+        let name = match &tokens[tag_index + 1..open] {
+            [tag] => tag.clone(),
+            [] if is_typedef => tokens[close + 1..]
+                .iter()
+                .rev()
+                .find(|t| is_name(t))?
+                .clone(),
+            _ => return None,
+        };
+        // `struct foo { ... } x;` also declares a variable, which we don't check.
+        if !is_typedef && close + 1 < tokens.len() {
+            return None;
+        }
+        let fields = split_top_level(&tokens[open + 1..close], ";")
+            .into_iter()
+            .filter(|field| !field.is_empty())
+            .map(|field| normalize_member(field).1)
+            .collect::<Vec<_>>();
+        let signature = format!("{} {{ {}; }}", tokens[tag_index], fields.join("; "));
+        let kind = DeclKind::Record;
+        return Some((name, Decl { kind, signature }));
+    }
+
+    if first == "typedef" {
+        return None;
+    }
+    let open = tokens.iter().position(|t| t == "(");
+    // `int (*f)(int)` declares a function pointer rather than a function.
+    let is_function_pointer = open.map_or(false, |open| {
+        tokens.get(open + 1).map(|t| t.as_str()) == Some("*")
+    });
+    if let (Some(open), false) = (open, is_function_pointer) {
+        let name = tokens.get(open.checked_sub(1)?).filter(|t| is_name(t))?;
+        let close = skip_group(&tokens, open) - 1;
+        let mut params = split_top_level(&tokens[open + 1..close], ",")
+            .into_iter()
+            .map(normalize_param)
+            .collect::<Vec<_>>();
+        if params == ["void"] {
+            params.clear();
+        }
+        let ret = normalize_type(&tokens[..open - 1]);
+        let signature = format!("{ret} ({})", params.join(", "));
+        let kind = DeclKind::Function;
+        return Some((name.clone(), Decl { kind, signature }));
+    }
+
+    let (name, signature) = normalize_member(&tokens);
+    let kind = DeclKind::Global;
+    Some((name?, Decl { kind, signature }))
+}
+
+fn parse_header(src: &str, defines: &Defines) -> anyhow::Result<BTreeMap<String, Decl>> {
+    Ok(split_decls(tokenize(&preprocess(src, defines)?))
+        .iter()
+        .filter_map(|decl| parse_decl(decl))
+        .collect())
+}
+
+fn run_cbindgen(cbindgen: &Path, crate_dir: &Path) -> anyhow::Result<String> {
+    let output = Command::new(cbindgen)
+        .arg("--lang")
+        .arg("c")
+        .arg(crate_dir)
+        .output()
+        .with_context(|| format!("failed to run {}", cbindgen.display()))?;
+    if !output.status.success() {
+        bail!(
+            "{} failed on {}:\n{}",
+            cbindgen.display(),
+            crate_dir.display(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    String::from_utf8(output.stdout).map_err(|e| anyhow!("cbindgen output is not UTF-8: {e}"))
+}
+
+/// Compare the declarations of the C headers against the ones `cbindgen` generated for the
+/// translated crate.  Records only missing from the generated header aren't mismatches, as
+/// `cbindgen` only emits the types used by the exported functions and globals.
+fn compare(c: &BTreeMap<String, Decl>, rust: &BTreeMap<String, Decl>) -> Vec<serde_json::Value> {
+    let mut mismatches = Vec::new();
+    for (name, c_decl) in c {
+        match rust.get(name) {
+            None if c_decl.kind == DeclKind::Record => {}
+            None => mismatches.push(json!({
+                "kind": "missing",
+                "name": name,
+                "decl": c_decl.kind.to_string(),
+                "c": c_decl.signature,
+                "rust": null,
+            })),
+            Some(rust_decl)
+                if rust_decl.kind != c_decl.kind || rust_decl.signature != c_decl.signature =>
+            {
+                let kind = match c_decl.kind {
+                    DeclKind::Record => "layout",
+                    _ => "signature",
+                };
+                mismatches.push(json!({
+                    "kind": kind,
+                    "name": name,
+                    "decl": c_decl.kind.to_string(),
+                    "c": c_decl.signature,
+                    "rust": rust_decl.signature,
+                }));
+            }
+            Some(_) => {}
+        }
+    }
+    for (name, rust_decl) in rust {
+        if rust_decl.kind != DeclKind::Record && !c.contains_key(name) {
+            mismatches.push(json!({
+                "kind": "extra",
+                "name": name,
+                "decl": rust_decl.kind.to_string(),
+                "c": null,
+                "rust": rust_decl.signature,
+            }));
+        }
+    }
+    mismatches
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let defines = args
+        .defines
+        .iter()
+        .map(|arg| parse_define(arg))
+        .collect::<Defines>();
+
+    let mut c_decls = BTreeMap::new();
+    for header in &args.headers {
+        let src = fs::read_to_string(header)
+            .with_context(|| format!("failed to read {}", header.display()))?;
+        let decls = parse_header(&src, &defines)
+            .with_context(|| format!("failed to parse {}", header.display()))?;
+        c_decls.extend(decls);
+    }
+    let rust_src = run_cbindgen(&args.cbindgen, &args.crate_dir)?;
+    let rust_decls =
+        parse_header(&rust_src, &defines).context("failed to parse the cbindgen header")?;
+
+    let mismatches = compare(&c_decls, &rust_decls);
+    let report = json!({
+        "crate": args.crate_dir,
+        "headers": args.headers,
+        "checked": c_decls.len(),
+        "mismatches": mismatches,
+    });
+    let report = serde_json::to_string_pretty(&report)?;
+    match &args.output {
+        Some(path) => fs::write(path, report + "\n")
+            .with_context(|| format!("failed to write {}", path.display()))?,
+        None => println!("{report}"),
+    }
+
+    if !mismatches.is_empty() {
+        eprintln!("{} ABI mismatches found", mismatches.len());
+        process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &str) -> BTreeMap<String, Decl> {
+        parse_header(src, &Defines::new()).unwrap()
+    }
+
+    fn signature(src: &str, name: &str) -> String {
+        let decls = parse(src);
+        let decl = decls
+            .get(name)
+            .unwrap_or_else(|| panic!("{name} not found in {decls:?}"));
+        decl.signature.clone()
+    }
+
+    #[test]
+    fn function_pointers() {
+        let src = "void set_handler(void (*handler)(int sig, void *data));\n\
+                   extern int (*callback)(const char *name);";
+        assert_eq!(
+            signature(src, "set_handler"),
+            "void (void ( * ) ( int , void * ))"
+        );
+        let decls = parse(src);
+        assert_eq!(decls["callback"].kind, DeclKind::Global);
+        assert_eq!(decls["callback"].signature, "int ( * ) ( const char * )");
+    }
+
+    #[test]
+    fn arrays() {
+        let src = "extern int table[16];\nint sum(const int values[], unsigned int n);";
+        assert_eq!(signature(src, "table"), "int [ 16 ]");
+        assert_eq!(signature(src, "sum"), "int (const int *, unsigned int)");
+    }
+
+    #[test]
+    fn typedef_struct() {
+        let anonymous = "typedef struct { unsigned x; char *name; long int y[2]; } point;";
+        let tagged = "typedef struct point { unsigned int x; char *name; long y[2]; } point_t;";
+        let cbindgen = "typedef struct {\n  unsigned int x;\n  char *name;\n  long y[2];\n} point;";
+        assert_eq!(parse(anonymous)["point"].kind, DeclKind::Record);
+        assert_eq!(
+            signature(anonymous, "point"),
+            "struct { unsigned int; char *; long [ 2 ]; }"
+        );
+        assert_eq!(signature(anonymous, "point"), signature(cbindgen, "point"));
+        assert_eq!(signature(tagged, "point"), signature(anonymous, "point"));
+    }
+
+    #[test]
+    fn pointer_returning_functions() {
+        let src = "char *strdup(const char *s);\nstruct node **find(struct node *root, int key);";
+        assert_eq!(signature(src, "strdup"), "char * (const char *)");
+        assert_eq!(signature(src, "find"), "node * * (node *, int)");
+    }
+
+    #[test]
+    fn spellings_and_names_are_ignored() {
+        let c = "extern unsigned long int hash(const char *__restrict key, int len) \
+                 __attribute__((pure));";
+        let rust = "unsigned long hash(const char *key, int);";
+        assert_eq!(
+            compare(&parse(c), &parse(rust)),
+            Vec::<serde_json::Value>::new()
+        );
+    }
+
+    #[test]
+    fn mismatched_signatures() {
+        let c = "int add(int a, int b);\nvoid only_c(void);\nstruct s { int a; };";
+        let rust = "long add(int a, int b);\nvoid only_rust(void);\nstruct s { long a; };";
+        let mismatches = compare(&parse(c), &parse(rust));
+        let kinds = mismatches
+            .iter()
+            .map(|m| (m["name"].as_str().unwrap(), m["kind"].as_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                ("add", "signature"),
+                ("only_c", "missing"),
+                ("s", "layout"),
+                ("only_rust", "extra"),
+            ]
+        );
+        assert_eq!(mismatches[0]["c"], "int (int, int)");
+        assert_eq!(mismatches[0]["rust"], "long (int, int)");
+    }
+
+    #[test]
+    fn conditionals_take_one_branch() {
+        let src = "#ifndef HEADER_H\n\
+                   #define HEADER_H\n\
+                   #ifdef __cplusplus\n\
+                   extern \"C\" {\n\
+                   #endif\n\
+                   #if defined(_WIN32) && !defined(__MINGW32__)\n\
+                   typedef long long ssize;\n\
+                   long long f(void);\n\
+                   #elif WIDE\n\
+                   long f(long x);\n\
+                   #else\n\
+                   int f(int x);\n\
+                   #endif\n\
+                   #ifdef __cplusplus\n\
+                   }\n\
+                   #endif\n\
+                   #endif /* HEADER_H */\n";
+        let eval = |defines: &[&str]| {
+            let defines = defines.iter().map(|d| parse_define(d)).collect();
+            let decls = parse_header(src, &defines).unwrap();
+            assert_eq!(decls.keys().collect::<Vec<_>>(), ["f"]);
+            decls["f"].signature.clone()
+        };
+        assert_eq!(eval(&[]), "int (int)");
+        assert_eq!(eval(&["WIDE"]), "long (long)");
+        assert_eq!(eval(&["WIDE=0"]), "int (int)");
+        assert_eq!(eval(&["_WIN32", "WIDE"]), "long long ()");
+        assert_eq!(eval(&["_WIN32", "__MINGW32__", "WIDE"]), "long (long)");
+    }
+
+    #[test]
+    fn unsupported_conditionals_are_rejected() {
+        let err = |src: &str| {
+            parse_header(src, &Defines::new())
+                .unwrap_err()
+                .chain()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join(": ")
+        };
+        assert_eq!(
+            err("int a;\n#if FOO >= 2\nint f(void);\n#endif\n"),
+            "on line 2: unsupported #if condition `FOO >= 2`"
+        );
+        assert_eq!(
+            err("#ifdef FOO\nint f(void);\n"),
+            "unterminated #if starting on line 1"
+        );
+        assert_eq!(err("#endif\n"), "#endif without #if on line 1");
+        // Conditions in inactive blocks aren't evaluated.
+        assert!(parse_header("#if 0\n#if FOO >= 2\n#endif\n#endif\n", &Defines::new()).is_ok());
+    }
+}
@@ -0,0 +1,58 @@
+//! Translate a small project described by a TOML file instead of a `compile_commands.json`.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn transpile_project_description() {
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("transpile_project");
+    let project = root.join("project");
+    if root.exists() {
+        fs::remove_dir_all(&root).unwrap();
+    }
+    fs::create_dir_all(project.join("include")).unwrap();
+    fs::create_dir_all(project.join("src")).unwrap();
+
+    fs::write(
+        project.join("c2rust.toml"),
+        r#"
+        sources = ["src/greeting.c"]
+        include_dirs = ["include"]
+        defines = ["ANSWER=42", 'GREETING="hello, world"']
+        "#,
+    )
+    .unwrap();
+    fs::write(
+        project.join("include/version.h"),
+        "typedef int version_t;\n#define VERSION_MINOR 7\n",
+    )
+    .unwrap();
+    // This only compiles with the include directory and both defines.
+    fs::write(
+        project.join("src/greeting.c"),
+        "#include <version.h>\n\
+         const char *greeting(void) { return GREETING; }\n\
+         int answer(void) { return ANSWER; }\n\
+         version_t minor(void) { return VERSION_MINOR; }\n",
+    )
+    .unwrap();
+
+    // Run from outside the project, so relative paths must be resolved against the TOML file.
+    let output = Command::new(env!("CARGO_BIN_EXE_c2rust-transpile"))
+        .arg("project/c2rust.toml")
+        .current_dir(&root)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "c2rust-transpile failed with status {}:\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let rs = fs::read_to_string(project.join("src/greeting.rs")).unwrap();
+    assert!(rs.contains("hello, world"), "{rs}");
+    assert!(rs.contains("42"), "{rs}");
+    assert!(rs.contains("pub type version_t"), "{rs}");
+}
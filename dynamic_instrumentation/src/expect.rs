@@ -0,0 +1,141 @@
+//! Collection of `#[c2rust_pdg::expect(...)]` attributes, which annotate a local with the
+//! permissions it is expected to require at run time.  These are recorded in the [`Metadata`]
+//! and checked against the dynamic graphs by `c2rust-pdg assert`.
+//!
+//! The attribute can be placed on a `let` statement or a function parameter that binds a single
+//! variable, and takes a list of permissions, each one of [`PERMS`]:
+//!
+//! ```ignore
+//! #![feature(register_tool)]
+//! #![register_tool(c2rust_pdg)]
+//!
+//! fn f(#[c2rust_pdg::expect(read)] p: *const i32) {
+//!     #[c2rust_pdg::expect(write, offset)]
+//!     let q = malloc(8) as *mut i32;
+//!     // ...
+//! }
+//! ```
+//!
+//! [`Metadata`]: c2rust_analysis_rt::metadata::Metadata
+
+use c2rust_analysis_rt::metadata::LocalExpectation;
+use c2rust_analysis_rt::mir_loc::Func;
+use rustc_ast::{AttrKind, Attribute};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{HirId, Pat, PatKind};
+use rustc_middle::mir::Body;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::def_id::DefId;
+use rustc_span::{Span, Symbol};
+
+/// The permissions that can be named in an `expect` attribute.
+pub const PERMS: [&str; 4] = ["read", "write", "offset", "free"];
+
+fn is_expect_attr(attr: &Attribute) -> bool {
+    let path = match attr.kind {
+        AttrKind::Normal(ref item, _) => &item.path,
+        AttrKind::DocComment(..) => return false,
+    };
+    match &path.segments[..] {
+        [tool, name] => {
+            tool.ident.name == Symbol::intern("c2rust_pdg")
+                && name.ident.name == Symbol::intern("expect")
+        }
+        _ => false,
+    }
+}
+
+/// Visits the parameters and `let`s of a HIR body, collecting the binding span and permissions of
+/// each one with an `expect` attribute.
+struct CollectExpectAttrs<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    bindings: Vec<(Span, Vec<String>)>,
+}
+
+impl<'tcx> CollectExpectAttrs<'tcx> {
+    fn check(&mut self, hir_id: HirId, pat: &Pat) {
+        let sess = self.tcx.sess;
+        for attr in self.tcx.hir().attrs(hir_id) {
+            if !is_expect_attr(attr) {
+                continue;
+            }
+            if !matches!(pat.kind, PatKind::Binding(_, _, _, None)) {
+                sess.span_err(attr.span, "`c2rust_pdg::expect` requires a single binding");
+                continue;
+            }
+            let mut perms = Vec::new();
+            for item in attr.meta_item_list().unwrap_or_default() {
+                let name = item.name_or_empty();
+                if PERMS.contains(&name.as_str()) {
+                    perms.push(name.to_string());
+                } else {
+                    sess.span_err(
+                        item.span(),
+                        format!("unknown permission, expected one of {}", PERMS.join(", ")),
+                    );
+                }
+            }
+            self.bindings.push((pat.span, perms));
+        }
+    }
+}
+
+impl<'tcx> Visitor<'tcx> for CollectExpectAttrs<'tcx> {
+    fn visit_param(&mut self, param: &'tcx rustc_hir::Param<'tcx>) {
+        self.check(param.hir_id, param.pat);
+        intravisit::walk_param(self, param);
+    }
+
+    fn visit_local(&mut self, local: &'tcx rustc_hir::Local<'tcx>) {
+        self.check(local.hir_id, local.pat);
+        intravisit::walk_local(self, local);
+    }
+}
+
+/// Collect the [`LocalExpectation`]s of the function `body_did`, whose MIR is `body`.
+///
+/// This must run before instrumentation, so that the spans of `body`'s locals still match the
+/// bindings they were declared by.
+pub fn collect_expectations(
+    tcx: TyCtxt,
+    body: &Body,
+    body_did: DefId,
+    func: &Func,
+) -> Vec<LocalExpectation> {
+    let body_id = match body_did
+        .as_local()
+        .and_then(|ldid| tcx.hir().maybe_body_owned_by(ldid))
+    {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let mut collector = CollectExpectAttrs {
+        tcx,
+        bindings: Vec::new(),
+    };
+    collector.visit_body(tcx.hir().body(body_id));
+
+    let mut expectations = Vec::new();
+    for (span, perms) in collector.bindings {
+        let local = body
+            .local_decls
+            .iter_enumerated()
+            .find(|(_, decl)| decl.source_info.span == span)
+            .map(|(local, _)| local);
+        let local = match local {
+            Some(x) => x,
+            None => {
+                tcx.sess
+                    .span_err(span, "`c2rust_pdg::expect`: no MIR local for this binding");
+                continue;
+            }
+        };
+        expectations.push(LocalExpectation {
+            func: func.clone(),
+            local: local.as_u32().into(),
+            span: tcx.sess.source_map().span_to_embeddable_string(span),
+            perms,
+        });
+    }
+    expectations
+}
@@ -1,6 +1,7 @@
 use anyhow::Context;
-use c2rust_analysis_rt::metadata::Metadata;
+use c2rust_analysis_rt::metadata::{LocalExpectation, Metadata};
 use c2rust_analysis_rt::mir_loc::{EventMetadata, Func, FuncId, MirLoc, MirLocId, TransferKind};
+use c2rust_analysis_rt::span::{SourcePos, SpanId, SpanInterner};
 use c2rust_analysis_rt::HOOK_FUNCTIONS;
 use fs2::FileExt;
 use fs_err::OpenOptions;
@@ -26,15 +27,16 @@ use std::path::Path;
 use std::sync::Mutex;
 
 use crate::arg::{ArgKind, InstrumentationArg};
+use crate::expect::collect_expectations;
 use crate::hooks::Hooks;
 use crate::into_operand::IntoOperand;
 use crate::mir_utils::remove_outer_deref;
 use crate::point::InstrumentationApplier;
-use crate::point::ProjectionSet;
 use crate::point::{cast_ptr_to_usize, InstrumentationPriority};
 use crate::point::{
     CollectAddressTakenLocals, CollectInstrumentationPoints, RewriteAddressTakenLocals,
 };
+use crate::point::{ProjectionSet, SpanSet};
 use crate::util::Convert;
 
 #[derive(Default)]
@@ -42,6 +44,8 @@ pub struct Instrumenter {
     mir_locs: Mutex<IndexSet<MirLoc>>,
     functions: Mutex<HashMap<FuncId, String>>,
     projections: Mutex<HashMap<Vec<usize>, u64>>,
+    expectations: Mutex<Vec<LocalExpectation>>,
+    spans: Mutex<SpanInterner>,
 }
 
 impl Instrumenter {
@@ -67,6 +71,12 @@ impl Instrumenter {
         debug!("Instrumenting function {}", function_name);
 
         self.add_fn(body_did, tcx);
+        let func = Func {
+            id: FuncId(tcx.def_path_hash(body_did).convert()),
+            name: function_name.to_string(),
+        };
+        let expectations = collect_expectations(tcx, body, body_did, &func);
+        self.expectations.lock().unwrap().extend(expectations);
         debug!("Body before instrumentation: {:#?}", body);
         instrument_body(self, tcx, body, body_did);
         debug!("Body after instrumentation: {:#?}", body);
@@ -84,11 +94,15 @@ impl Instrumenter {
             .into_iter()
             .map(|(proj_vec, proj_key)| (proj_key, proj_vec))
             .collect();
+        let expectations = std::mem::take(&mut *self.expectations.lock().unwrap());
+        let spans = std::mem::take(&mut *self.spans.lock().unwrap()).finish();
 
         let metadata = Metadata {
             locs,
             functions,
             projections,
+            expectations,
+            spans,
         };
         let bytes = bincode::serialize(&metadata).context("Location serialization failed")?;
         let mut file = OpenOptions::new()
@@ -145,6 +159,12 @@ impl ProjectionSet for Instrumenter {
     }
 }
 
+impl SpanSet for Instrumenter {
+    fn add_span(&self, file: &str, lo: SourcePos, hi: SourcePos) -> SpanId {
+        self.spans.lock().unwrap().insert(file, lo, hi)
+    }
+}
+
 fn is_shared_or_unsafe_ptr(ty: Ty) -> bool {
     ty.is_unsafe_ptr() || (ty.is_region_ptr() && !ty.is_mutable_ptr())
 }
@@ -851,7 +871,7 @@ fn instrument_body<'a, 'tcx>(
     // collect instrumentation points
     let points = {
         let mut collector =
-            CollectInstrumentationPoints::new(tcx, hooks, body, local_to_address, state);
+            CollectInstrumentationPoints::new(tcx, hooks, body, local_to_address, state, state);
         collector.visit_body(body);
         collector.into_instrumentation_points()
     };
@@ -3,6 +3,7 @@ extern crate rustc_ast;
 extern crate rustc_const_eval;
 extern crate rustc_data_structures;
 extern crate rustc_driver;
+extern crate rustc_hir;
 extern crate rustc_index;
 extern crate rustc_interface;
 extern crate rustc_middle;
@@ -13,6 +14,7 @@ extern crate rustc_span;
 
 mod arg;
 mod callbacks;
+mod expect;
 mod hooks;
 mod instrument;
 mod into_operand;
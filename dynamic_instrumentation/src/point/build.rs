@@ -1,4 +1,5 @@
 use c2rust_analysis_rt::mir_loc::{EventMetadata, TransferKind};
+use c2rust_analysis_rt::span::SourcePos;
 use itertools::Itertools;
 use rustc_index::vec::Idx;
 use rustc_middle::{
@@ -6,6 +7,7 @@ use rustc_middle::{
     ty::{self, TyCtxt},
 };
 use rustc_span::def_id::DefId;
+use rustc_span::Loc;
 
 use crate::{
     arg::{ArgKind, InstrumentationArg},
@@ -14,7 +16,7 @@ use crate::{
     util::Convert,
 };
 
-use super::{CollectInstrumentationPoints, InstrumentationPoint, InstrumentationPriority};
+use super::{CollectInstrumentationPoints, InstrumentationPoint, InstrumentationPriority, SpanSet};
 
 #[derive(Default)]
 struct InstrumentationPointBuilder<'tcx> {
@@ -62,6 +64,7 @@ pub struct InstrumentationBuilder<'a, 'tcx: 'a> {
     func: DefId,
     instrumentation_priority: InstrumentationPriority,
     point: InstrumentationPointBuilder<'tcx>,
+    spans: &'a dyn SpanSet,
 }
 
 impl<'a, 'tcx: 'a> CollectInstrumentationPoints<'a, 'tcx> {
@@ -79,8 +82,10 @@ impl<'a, 'tcx: 'a> CollectInstrumentationPoints<'a, 'tcx> {
             func,
             point: Default::default(),
             instrumentation_priority: Default::default(),
+            spans: self.spans,
         }
         .debug_mir()
+        .span()
     }
 
     pub fn into_instrumentation_points(mut self) -> Vec<InstrumentationPoint<'tcx>> {
@@ -212,6 +217,27 @@ impl<'tcx> InstrumentationBuilder<'_, 'tcx> {
         self
     }
 
+    /// Set [`span`](EventMetadata::span)
+    /// to the source location of the [`original_location`](Self::original_location).
+    pub fn span(mut self) -> Self {
+        let span = self.body.source_info(self.original_location).span;
+        if span.is_dummy() {
+            return self;
+        }
+        let source_map = self.tcx.sess.source_map();
+        let (lo, hi) = (
+            source_map.lookup_char_pos(span.lo()),
+            source_map.lookup_char_pos(span.hi()),
+        );
+        let pos = |loc: &Loc| SourcePos {
+            line: loc.line.try_into().unwrap(),
+            col: (loc.col.to_usize() + 1).try_into().unwrap(),
+        };
+        let file = lo.file.name.prefer_remapped().to_string();
+        self.point.metadata.span = Some(self.spans.add_span(&file, pos(&lo), pos(&hi)));
+        self
+    }
+
     /// Queue insertion of a call to [`func`].
     ///
     /// The call will be inserted before the statement
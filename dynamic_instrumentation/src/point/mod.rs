@@ -6,6 +6,7 @@ pub mod source;
 use std::cmp::Ordering;
 
 use c2rust_analysis_rt::mir_loc::{EventMetadata, FuncId};
+use c2rust_analysis_rt::span::{SourcePos, SpanId};
 use indexmap::{IndexMap, IndexSet};
 use rustc_middle::{
     mir::{Body, HasLocalDecls, Local, LocalDecls, Location, Place, Rvalue},
@@ -116,6 +117,10 @@ pub trait ProjectionSet {
     fn add_proj(&self, proj: Vec<usize>) -> u64;
 }
 
+pub trait SpanSet {
+    fn add_span(&self, file: &str, lo: SourcePos, hi: SourcePos) -> SpanId;
+}
+
 pub struct CollectInstrumentationPoints<'a, 'tcx: 'a> {
     tcx: TyCtxt<'tcx>,
     hooks: Hooks<'tcx>,
@@ -124,6 +129,7 @@ pub struct CollectInstrumentationPoints<'a, 'tcx: 'a> {
     assignment: Option<(Place<'tcx>, Rvalue<'tcx>)>,
     pub addr_taken_local_addresses: IndexMap<Local, Local>,
     pub projections: &'a dyn ProjectionSet,
+    pub spans: &'a dyn SpanSet,
 }
 
 impl<'a, 'tcx: 'a> CollectInstrumentationPoints<'a, 'tcx> {
@@ -133,6 +139,7 @@ impl<'a, 'tcx: 'a> CollectInstrumentationPoints<'a, 'tcx> {
         body: &'a Body<'tcx>,
         addr_taken_local_addresses: IndexMap<Local, Local>,
         projections: &'a dyn ProjectionSet,
+        spans: &'a dyn SpanSet,
     ) -> Self {
         Self {
             tcx,
@@ -142,6 +149,7 @@ impl<'a, 'tcx: 'a> CollectInstrumentationPoints<'a, 'tcx> {
             assignment: Default::default(),
             addr_taken_local_addresses,
             projections,
+            spans,
         }
     }
 
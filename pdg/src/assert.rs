@@ -5,8 +5,12 @@
 
 use crate::{
     graph::{Graph, Graphs},
+    summary::{PermissionSummary, Summaries},
     util::Duplicates,
 };
+use c2rust_analysis_rt::metadata::{LocalExpectation, Metadata};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
 
 impl Graphs {
     /// Assert that a graph has no duplicate objects.
@@ -40,3 +44,127 @@ impl Graphs {
         self.assert_heads_have_no_sources();
     }
 }
+
+/// The permissions named by a [`LocalExpectation`].  `read` is implied by any use, so it names
+/// none of the tracked permissions.
+fn expected_perms(expectation: &LocalExpectation) -> PermissionSummary {
+    let has = |name| expectation.perms.iter().any(|perm| perm == name);
+    PermissionSummary {
+        write: has("write"),
+        offset: has("offset"),
+        free: has("free"),
+        uncertain: false,
+    }
+}
+
+/// A [`LocalExpectation`] that doesn't match the permissions its local required at run time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedExpectation<'a> {
+    pub expectation: &'a LocalExpectation,
+    pub actual: PermissionSummary,
+}
+
+impl Display for FailedExpectation<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Self {
+            expectation,
+            actual,
+        } = self;
+        write!(
+            f,
+            "{}: {} _{}: expected {}, but dynamically {}",
+            expectation.span,
+            expectation.func,
+            expectation.local.index,
+            expected_perms(expectation),
+            actual
+        )
+    }
+}
+
+/// Check each `#[c2rust_pdg::expect(...)]` annotation in `metadata` against the permissions in
+/// `summaries`.  The write, offset, and free permissions must match exactly.  A local that never
+/// held a pointer in the trace is treated as requiring no permissions.
+pub fn check_expectations<'a>(
+    summaries: &Summaries,
+    metadata: &'a Metadata,
+) -> Vec<FailedExpectation<'a>> {
+    let locals = summaries
+        .functions
+        .iter()
+        .flat_map(|summary| {
+            summary
+                .locals
+                .iter()
+                .map(|(&local, &perms)| ((summary.function.id, local), perms))
+        })
+        .collect::<HashMap<_, _>>();
+
+    metadata
+        .expectations
+        .iter()
+        .filter_map(|expectation| {
+            let actual = locals
+                .get(&(expectation.func.id, expectation.local))
+                .copied()
+                .unwrap_or_default();
+            let expected = expected_perms(expectation);
+            let matches = (actual.write, actual.offset, actual.free)
+                == (expected.write, expected.offset, expected.free);
+            (!matches).then_some(FailedExpectation {
+                expectation,
+                actual,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::FunctionSummary;
+    use crate::test_util::{func, metadata, perms};
+    use c2rust_analysis_rt::mir_loc::Local;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn expectations() {
+        let func = func("f", 1);
+        let expect = |local: u32, perms: &[&str]| LocalExpectation {
+            func: func.clone(),
+            local: local.into(),
+            span: format!("src/lib.rs:{local}:9: {local}:10"),
+            perms: perms.iter().map(|&perm| perm.to_owned()).collect(),
+        };
+        let summaries = Summaries {
+            functions: vec![FunctionSummary {
+                function: func.clone(),
+                locals: BTreeMap::from([
+                    (Local::from(1_u32), perms("write")),
+                    (Local::from(2_u32), perms("write")),
+                ]),
+            }],
+        };
+        let metadata = Metadata {
+            expectations: vec![
+                expect(1, &["write"]),
+                expect(2, &["read"]),
+                expect(3, &["read"]),
+                expect(4, &["free"]),
+            ],
+            ..metadata(Vec::new())
+        };
+
+        let failures = check_expectations(&summaries, &metadata)
+            .iter()
+            .map(|failure| failure.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            failures,
+            [
+                "src/lib.rs:2:9: 2:10: f _2: expected -, but dynamically write",
+                "src/lib.rs:4:9: 4:10: f _4: expected free, but dynamically -",
+            ]
+        );
+    }
+}
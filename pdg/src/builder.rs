@@ -1,23 +1,75 @@
-use crate::graph::{Graph, GraphId, Graphs, Node, NodeId, NodeKind};
-use c2rust_analysis_rt::events::{Event, EventKind, Pointer};
+use crate::graph::{Graph, GraphId, Graphs, Node, NodeId, NodeKind, Process};
+use crate::util::ShortOption;
+use c2rust_analysis_rt::events::{Event, EventKind, Pointer, ProcessOrigin};
 use c2rust_analysis_rt::metadata::Metadata;
 use c2rust_analysis_rt::mir_loc::{EventMetadata, Func, FuncId, Local, MirLoc, TransferKind};
+use c2rust_analysis_rt::runtime::fork::{child_log_path, child_log_pids};
 use color_eyre::eyre;
 use fs_err::File;
 use indexmap::IndexSet;
 use itertools::Itertools;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{self, BufReader};
 use std::iter;
 use std::path::Path;
 
-pub fn read_event_log(path: &Path) -> io::Result<Vec<Event>> {
+/// A class of events that can be left out while reading event logs, to save the time and memory
+/// of building the parts of the graphs that aren't of interest.  Events that produce pointers from
+/// other pointers are always kept, as the events that use those pointers depend on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventClass {
+    /// [`LoadAddr`](EventKind::LoadAddr)
+    Loads,
+    /// [`StoreAddr`](EventKind::StoreAddr) and [`StoreAddrTaken`](EventKind::StoreAddrTaken)
+    Stores,
+    /// [`Free`](EventKind::Free)
+    Frees,
+    /// [`LoadValue`](EventKind::LoadValue) and [`StoreValue`](EventKind::StoreValue)
+    Values,
+    /// [`ToInt`](EventKind::ToInt) and [`FromInt`](EventKind::FromInt)
+    Ints,
+}
+
+impl EventClass {
+    /// The class of `kind`, or `None` if events of this kind are always kept.
+    pub fn of(kind: &EventKind) -> Option<Self> {
+        use EventKind::*;
+        Some(match kind {
+            LoadAddr(..) => Self::Loads,
+            StoreAddr(..) | StoreAddrTaken(..) => Self::Stores,
+            Free { .. } => Self::Frees,
+            LoadValue(..) | StoreValue(..) => Self::Values,
+            ToInt(..) | FromInt(..) => Self::Ints,
+            _ => return None,
+        })
+    }
+}
+
+/// Read the event log at `path`.  If `only` is non-empty, events of [`EventClass`]es not in
+/// `only` are dropped as they are read.
+pub fn read_event_log(path: &Path, only: &[EventClass]) -> io::Result<Vec<Event>> {
     let file = File::open(path)?;
     let mut reader = BufReader::new(file);
-    let events = iter::from_fn(|| bincode::deserialize_from(&mut reader).ok()).collect::<Vec<_>>();
+    let events = iter::from_fn(|| bincode::deserialize_from(&mut reader).ok())
+        .filter(|event: &Event| {
+            only.is_empty() || EventClass::of(&event.kind).map_or(true, |c| only.contains(&c))
+        })
+        .collect::<Vec<_>>();
     Ok(events)
 }
 
+/// Read the event log at `path` of the root process, followed by the logs of its descendants,
+/// which are at [`child_log_path`]s next to it, in order of pid.  The root process removes the
+/// logs of an earlier run's descendants when it starts, unless it appends to its log.  Events are
+/// filtered by `only` as in [`read_event_log`].
+pub fn read_process_logs(path: &Path, only: &[EventClass]) -> io::Result<Vec<Vec<Event>>> {
+    let mut logs = vec![read_event_log(path, only)?];
+    for pid in child_log_pids(path)? {
+        logs.push(read_event_log(&child_log_path(path, pid), only)?);
+    }
+    Ok(logs)
+}
+
 pub fn read_metadata(path: &Path) -> eyre::Result<Metadata> {
     let bytes = fs_err::read(path)?;
     Ok(Metadata::read(&bytes)?)
@@ -26,13 +78,34 @@ pub fn read_metadata(path: &Path) -> eyre::Result<Metadata> {
 fn parent<'a, 'b>(e: &'a NodeKind, obj: &'b ProvenanceInfo) -> Option<&'b ProvenanceInfo> {
     use NodeKind::*;
     match e {
-        Alloc(..) | AddrOfLocal(..) | AddrOfSized(..) => None,
+        Alloc(..) | AddrOfLocal(..) | AddrOfSized(..) | UnknownOrigin => None,
         _ => Some(obj),
     }
 }
 
+/// Whether an operation of this kind must act on a pointer that was produced by some earlier
+/// operation.  If no such operation was observed, there is a gap in the instrumentation.
+fn needs_provenance(e: &NodeKind) -> bool {
+    use NodeKind::*;
+    !matches!(
+        e,
+        Alloc(..)
+            | AddrOfLocal(..)
+            | AddrOfSized(..)
+            | _AddrOfStatic(..)
+            | IntToPtr
+            | LoadValue
+            | UnknownOrigin
+    )
+}
+
 type AddressTaken = IndexSet<(FuncId, Local)>;
 
+/// The latest [`Node`] in each [`Graph`] whose [`Node::dest`] is a given [`Local`] with no
+/// projections.  This is maintained as nodes are added so that finding the direct source of a new
+/// node doesn't require scanning the whole graph.
+type LatestDests = HashMap<(GraphId, Local), NodeId>;
+
 pub trait EventKindExt {
     fn ptr(&self, metadata: &EventMetadata) -> Option<Pointer>;
     fn to_node_kind(
@@ -58,13 +131,19 @@ impl EventKindExt for EventKind {
             LoadValue(ptr) => ptr,
             StoreValue(ptr) => ptr,
             ToInt(ptr) => ptr,
+            // `realloc(NULL, size)` is `malloc(size)`.
+            Realloc {
+                old_ptr: 0,
+                new_ptr,
+                ..
+            } => new_ptr,
             Realloc { old_ptr, .. } => old_ptr,
             FromInt(lhs) => lhs,
             Alloc { ptr, .. } => ptr,
             AddrOfLocal { ptr, .. } => ptr,
             AddrOfSized { ptr, .. } => ptr,
             Offset(ptr, _, _) => ptr,
-            Done | BeginFuncBody => return None,
+            Done | BeginFuncBody | Stats { .. } | Process { .. } | Fork => return None,
         })
     }
 
@@ -77,7 +156,8 @@ impl EventKindExt for EventKind {
         use EventKind::*;
         Some(match *self {
             Alloc { .. } => NodeKind::Alloc(1),
-            Realloc { .. } => NodeKind::Alloc(1),
+            Realloc { old_ptr: 0, .. } => NodeKind::Alloc(1),
+            Realloc { .. } => NodeKind::Realloc,
             Free { .. } => NodeKind::Free,
             CopyPtr(..) => NodeKind::Copy,
             Project(base_ptr, new_ptr, key) => {
@@ -114,7 +194,7 @@ impl EventKindExt for EventKind {
             FromInt(_) => NodeKind::IntToPtr,
             Ret(_) => return None,
             Offset(_, offset, _) => NodeKind::Offset(offset),
-            Done => return None,
+            Done | Stats { .. } | Process { .. } | Fork => return None,
         })
     }
 }
@@ -161,7 +241,16 @@ fn update_provenance(
             mapping.size = Some(size);
             provenances.insert(ptr, mapping);
         }
-        Realloc { new_ptr, size, .. } => {
+        // A failed `realloc` returns null and leaves the old object in place.
+        Realloc { new_ptr: 0, .. } => {}
+        Realloc {
+            old_ptr,
+            size,
+            new_ptr,
+        } => {
+            // The old address is no longer valid, unless the object was resized in place, and
+            // may be reused by later allocations.
+            provenances.remove(&old_ptr);
             mapping.size = Some(size);
             provenances.insert(new_ptr, mapping);
         }
@@ -195,7 +284,6 @@ fn update_provenance(
                         // Insert it for now, we should decide later what to do
                         //
                         // Assume an unknown pointer points to a word-sized object
-                        log::warn!("Pointer of unknown origin 0x{:x}", ptr);
                         return true;
                     }
 
@@ -231,6 +319,7 @@ pub fn add_node(
     graphs: &mut Graphs,
     provenances: &mut BTreeMap<Pointer, ProvenanceInfo>,
     address_taken: &mut AddressTaken,
+    latest_dests: &mut LatestDests,
     event: &Event,
     metadata: &Metadata,
 ) -> Option<NodeId> {
@@ -282,25 +371,20 @@ pub fn add_node(
     });
 
     let direct_source = provenance.and_then(|pi| {
-        graphs.graphs[pi.gid]
-            .nodes
-            .iter()
-            .rposition(|n| {
-                if let (Some(d), Some(s)) = (&n.dest, &event_metadata.source) {
-                    // TODO: Ignore direct assignments with projections for now,
-                    // e.g., `_1.0 = _2;`. We should later add support for
-                    // assignments to sub-fields, e.g.
-                    // ```
-                    //   _1 = _2;
-                    //   _1.0 = _3;
-                    //   _1 = _4;
-                    // ```
-                    d == s && s.projection.is_empty()
-                } else {
-                    false
-                }
-            })
-            .map(|nid| pi.with_node(NodeId::from(nid)))
+        // TODO: Ignore direct assignments with projections for now,
+        // e.g., `_1.0 = _2;`. We should later add support for
+        // assignments to sub-fields, e.g.
+        // ```
+        //   _1 = _2;
+        //   _1.0 = _3;
+        //   _1 = _4;
+        // ```
+        let s = event_metadata.source.as_ref()?;
+        if !s.projection.is_empty() {
+            return None;
+        }
+        let nid = *latest_dests.get(&(pi.gid, s.local))?;
+        Some(pi.with_node(nid))
     });
     let source = direct_source.or(provenance.cloned());
 
@@ -309,6 +393,29 @@ pub fn add_node(
         name: metadata.functions[&dest_fn].clone(),
     };
 
+    let ptr_is_null = ptr.map_or(false, |ptr| ptr == 0);
+    // A non-null pointer of unknown provenance means the operation that created it wasn't
+    // instrumented.  Rather than silently treating this operation as the root of a new object,
+    // record the gap explicitly so that results derived from it can be marked as uncertain.
+    let source = match (source, ptr) {
+        (None, Some(ptr)) if !ptr_is_null && needs_provenance(&node_kind) => {
+            log::warn!("Pointer of unknown origin 0x{:x}", ptr);
+            let gid = graphs.graphs.push(Graph::new(false));
+            let nid = graphs.graphs[gid].nodes.push(Node {
+                function: function.clone(),
+                block: basic_block_idx.into(),
+                statement_idx,
+                kind: NodeKind::UnknownOrigin,
+                source: None,
+                dest: None,
+                debug_info: String::new(),
+                info: None,
+            });
+            Some(ProvenanceInfo::new(gid, nid))
+        }
+        (source, _) => source,
+    };
+
     let node = Node {
         function,
         block: basic_block_idx.into(),
@@ -323,13 +430,17 @@ pub fn add_node(
         info: None,
     };
 
-    let ptr_is_null = ptr.map_or(false, |ptr| ptr == 0);
     let graph_id = source
         .as_ref()
         .and_then(|p| parent(&node_kind, p))
         .map(|pi| pi.gid)
         .unwrap_or_else(|| graphs.graphs.push(Graph::new(ptr_is_null)));
     let node_id = graphs.graphs[graph_id].nodes.push(node);
+    if let Some(dest) = &event_metadata.destination {
+        if dest.projection.is_empty() {
+            latest_dests.insert((graph_id, dest.local), node_id);
+        }
+    }
 
     // Assert that we're not mixing null and non-null pointers
     assert!(
@@ -351,20 +462,391 @@ pub fn add_node(
     Some(node_id)
 }
 
+/// The state of construction within one process image, a copy of which a forked child inherits.
+#[derive(Default, Clone)]
+struct ProcessState {
+    provenances: BTreeMap<Pointer, ProvenanceInfo>,
+    address_taken: AddressTaken,
+    latest_dests: LatestDests,
+}
+
+/// The events of one process image, starting at an [`EventKind::Process`] event, or at the start
+/// of a log from a runtime that didn't record processes.
+struct ProcessImage<'a> {
+    pid: Option<u32>,
+    origin: ProcessOrigin,
+    events: &'a [Event],
+}
+
+impl<'a> ProcessImage<'a> {
+    fn new(events: &'a [Event]) -> Self {
+        let (pid, origin) = match events.first().map(|event| event.kind) {
+            Some(EventKind::Process { pid, origin }) => (Some(pid), origin),
+            _ => (None, ProcessOrigin::Root),
+        };
+        Self {
+            pid,
+            origin,
+            events,
+        }
+    }
+
+    /// Split a log into the process images it contains, as a process that `exec`s continues
+    /// writing to the same log.
+    fn split(events: &'a [Event]) -> Vec<Self> {
+        let mut images = Vec::new();
+        let mut start = 0;
+        for (i, event) in events.iter().enumerate().skip(1) {
+            if let EventKind::Process { .. } = event.kind {
+                images.push(Self::new(&events[start..i]));
+                start = i;
+            }
+        }
+        if start < events.len() {
+            images.push(Self::new(&events[start..]));
+        }
+        images
+    }
+
+    fn num_forks(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|event| matches!(event.kind, EventKind::Fork))
+            .count()
+    }
+
+    /// Find the image in `images` that this one was forked from, or `exec`ed by a child of.
+    ///
+    /// As pids can be reused, and a process's forks are counted from its last `exec`, this is the
+    /// last image with the parent's pid that forked enough times.
+    fn parent(&self, images: &[Self]) -> Option<usize> {
+        match self.origin {
+            ProcessOrigin::Root => None,
+            ProcessOrigin::Fork {
+                parent_pid,
+                fork_idx,
+            } => images.iter().rposition(|image| {
+                image.pid == Some(parent_pid) && image.num_forks() > fork_idx as usize
+            }),
+            ProcessOrigin::Exec { parent_pid } => images
+                .iter()
+                .rposition(|image| image.pid == Some(parent_pid)),
+        }
+    }
+}
+
+/// Split the event logs of a process tree, as read by [`read_process_logs`], into the events of
+/// each process image.  Each image has its own address space, so the reports that match events by
+/// address, like [`find_leaks`](crate::leaks::find_leaks), scan each one separately.
+pub fn process_images<'a>(logs: &[&'a [Event]]) -> Vec<&'a [Event]> {
+    logs.iter()
+        .flat_map(|events| ProcessImage::split(events))
+        .map(|image| image.events)
+        .collect()
+}
+
+/// Construct the [`Graphs`] of a single process's event log.
 pub fn construct_pdg(events: &[Event], metadata: &Metadata) -> Graphs {
+    construct_pdg_from_logs(&[events], metadata)
+}
+
+/// Construct the [`Graphs`] of a process tree from the event logs of its processes, as read by
+/// [`read_process_logs`].
+///
+/// Each forked child continues from a copy of its parent's state at the
+/// [`Fork`](EventKind::Fork), so its operations on objects inherited from its parent are added to
+/// their existing graphs.  A process started by `exec` starts from scratch.
+pub fn construct_pdg_from_logs(logs: &[&[Event]], metadata: &Metadata) -> Graphs {
+    let images = logs
+        .iter()
+        .flat_map(|events| ProcessImage::split(events))
+        .collect::<Vec<_>>();
+    let parents = images
+        .iter()
+        .map(|image| image.parent(&images))
+        .collect::<Vec<_>>();
+
     let mut graphs = Graphs::new();
-    let mut provenances = BTreeMap::new();
-    let mut address_taken = AddressTaken::new();
-    for event in events {
-        add_node(
-            &mut graphs,
-            &mut provenances,
-            &mut address_taken,
-            event,
-            metadata,
-        );
+    // The index of each image in `graphs.processes`, once it has been added.
+    let mut process_ids = vec![None; images.len()];
+    // The state of each image at each of its forks.
+    let mut forks = vec![Vec::<ProcessState>::new(); images.len()];
+    // Add each image before its children.
+    let mut stack = (0..images.len())
+        .filter(|&i| parents[i].is_none())
+        .rev()
+        .collect::<Vec<_>>();
+    while let Some(i) = stack.pop() {
+        let image = &images[i];
+        let mut state = match (image.origin, parents[i]) {
+            (ProcessOrigin::Fork { fork_idx, .. }, Some(parent)) => {
+                forks[parent][fork_idx as usize].clone()
+            }
+            (ProcessOrigin::Fork { parent_pid, .. }, None) => {
+                log::warn!(
+                    "no log of the fork in pid {parent_pid} that started pid {}",
+                    ShortOption(image.pid.as_ref())
+                );
+                ProcessState::default()
+            }
+            _ => ProcessState::default(),
+        };
+        process_ids[i] = Some(graphs.processes.len());
+        graphs.processes.push(Process {
+            pid: image.pid,
+            origin: image.origin,
+            parent: parents[i].and_then(|parent| process_ids[parent]),
+        });
+
+        for event in image.events {
+            match event.kind {
+                EventKind::Stats {
+                    dropped,
+                    sampled_out,
+                } => {
+                    if dropped + sampled_out > 0 {
+                        log::warn!(
+                            "the event log is incomplete: {dropped} events were dropped and \
+                             {sampled_out} were sampled out, so all results are uncertain"
+                        );
+                    }
+                    graphs.unrecorded_events += dropped + sampled_out;
+                }
+                EventKind::Fork => forks[i].push(state.clone()),
+                _ => {
+                    add_node(
+                        &mut graphs,
+                        &mut state.provenances,
+                        &mut state.address_taken,
+                        &mut state.latest_dests,
+                        event,
+                        metadata,
+                    );
+                }
+            }
+        }
+        let children = (0..images.len()).filter(|&child| parents[child] == Some(i));
+        stack.extend(children.collect::<Vec<_>>().into_iter().rev());
+    }
+    let unreached = process_ids.iter().filter(|id| id.is_none()).count();
+    if unreached > 0 {
+        log::warn!("{unreached} process images have a cycle of parents and were skipped");
     }
+
     // TODO(kkysen) check if I have to remove any `GraphId`s from `graphs.latest_assignment`
     graphs.graphs = graphs.graphs.into_iter().unique().collect();
     graphs
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{event, func, loc, metadata, place};
+    use std::time::{Duration, Instant};
+
+    /// Build the graph of a chain of `n` copies of one allocation, returning it and the time
+    /// construction took.
+    fn construct_copy_chain(n: usize) -> (Graphs, Duration) {
+        let f = func("f", 1);
+        // `_1 = malloc(..)`, `_2 = _1`, `_1 = _2`
+        let metadata = metadata(vec![
+            loc(&f, 0, None, place(1)),
+            loc(&f, 1, place(1), place(2)),
+            loc(&f, 2, place(2), place(1)),
+        ]);
+        let ptr = 0x1000;
+        let events = iter::once(event(0, EventKind::Alloc { size: 8, ptr }))
+            .chain((0..n).map(|i| event(1 + (i % 2) as u32, EventKind::CopyPtr(ptr))))
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let graphs = construct_pdg(&events, &metadata);
+        (graphs, start.elapsed())
+    }
+
+    #[test]
+    fn construct_copy_chain_sources() {
+        const N: usize = 1_000;
+
+        let (graphs, _) = construct_copy_chain(N);
+        assert_eq!(graphs.graphs.len(), 1);
+        let nodes = &graphs.graphs[GraphId::from_u32(0)].nodes;
+        assert_eq!(nodes.len(), N + 1);
+        for (id, node) in nodes.iter_enumerated().skip(1) {
+            assert_eq!(node.source, Some(NodeId::from_usize(id.as_usize() - 1)));
+        }
+    }
+
+    /// Construction used to scan the whole graph for the source of each node, making it quadratic
+    /// in the length of the trace.  Making the chain 8 times longer should then take about 64
+    /// times as long, rather than about 8 times as long.  This depends on timing, so it's ignored
+    /// by default.
+    #[test]
+    #[ignore]
+    fn construct_copy_chain_linear() {
+        const N: usize = 25_000;
+
+        let (_, short) = construct_copy_chain(N);
+        let (_, long) = construct_copy_chain(8 * N);
+        assert!(
+            long < short * 24,
+            "{} copies took {long:?}, but {N} took {short:?}",
+            8 * N
+        );
+    }
+
+    #[test]
+    fn unknown_origin() {
+        // `_2 = _1`, where `_1` comes from uninstrumented code
+        let metadata = metadata(vec![loc(&func("f", 1), 0, place(1), place(2))]);
+        let events = [
+            event(0, EventKind::CopyPtr(0x1000)),
+            event(0, EventKind::StoreAddr(0x1000)),
+        ];
+
+        let mut graphs = construct_pdg(&events, &metadata);
+        crate::info::add_info(&mut graphs);
+        assert_eq!(graphs.graphs.len(), 1);
+        let nodes = &graphs.graphs[GraphId::from_u32(0)].nodes;
+        let kinds = nodes.iter().map(|n| n.kind.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [NodeKind::UnknownOrigin, NodeKind::Copy, NodeKind::StoreAddr]
+        );
+        assert!(nodes.iter().all(|n| n.info.as_ref().unwrap().uncertain));
+    }
+
+    #[test]
+    fn incomplete_log() {
+        let f = func("f", 1);
+        // `_1 = malloc(..)`, `_2 = _1`
+        let metadata = metadata(vec![
+            loc(&f, 0, None, place(1)),
+            loc(&f, 0, place(1), place(2)),
+        ]);
+        let events = [
+            event(
+                0,
+                EventKind::Alloc {
+                    size: 8,
+                    ptr: 0x1000,
+                },
+            ),
+            event(1, EventKind::CopyPtr(0x1000)),
+            event(
+                0,
+                EventKind::Stats {
+                    dropped: 2,
+                    sampled_out: 3,
+                },
+            ),
+            Event::done(),
+        ];
+
+        let mut graphs = construct_pdg(&events, &metadata);
+        crate::info::add_info(&mut graphs);
+        assert_eq!(graphs.unrecorded_events, 5);
+        let nodes = &graphs.graphs[GraphId::from_u32(0)].nodes;
+        assert_eq!(nodes.len(), 2);
+        assert!(nodes.iter().all(|n| n.info.as_ref().unwrap().uncertain));
+    }
+
+    #[test]
+    fn realloc_links_old_and_new_objects() {
+        let f = func("f", 1);
+        // `_1 = malloc(..)`, `_2 = realloc(move _1, ..)`, `_3 = _2`
+        let metadata = metadata(vec![
+            loc(&f, 0, None, place(1)),
+            loc(&f, 0, place(1), place(2)),
+            loc(&f, 0, place(2), place(3)),
+        ]);
+        let events = [
+            event(
+                0,
+                EventKind::Alloc {
+                    size: 8,
+                    ptr: 0x1000,
+                },
+            ),
+            event(
+                1,
+                EventKind::Realloc {
+                    old_ptr: 0x1000,
+                    size: 16,
+                    new_ptr: 0x2000,
+                },
+            ),
+            event(2, EventKind::CopyPtr(0x2008)),
+        ];
+
+        let graphs = construct_pdg(&events, &metadata);
+        assert_eq!(graphs.graphs.len(), 1);
+        let nodes = &graphs.graphs[GraphId::from_u32(0)].nodes;
+        let kinds = nodes.iter().map(|n| n.kind.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [NodeKind::Alloc(1), NodeKind::Realloc, NodeKind::Copy]
+        );
+        let sources = nodes.iter().map(|n| n.source).collect::<Vec<_>>();
+        assert_eq!(
+            sources,
+            [None, Some(NodeId::from_u32(0)), Some(NodeId::from_u32(1))]
+        );
+    }
+
+    #[test]
+    fn forked_child_continues_parent_graphs() {
+        let f = func("f", 1);
+        // `_1 = malloc(..)`, `_2 = _1`, `free(move _1)`
+        let metadata = metadata(vec![
+            loc(&f, 0, None, place(1)),
+            loc(&f, 0, place(1), place(2)),
+            loc(&f, 0, place(1), None),
+        ]);
+        let parent = [
+            event(
+                0,
+                EventKind::Process {
+                    pid: 10,
+                    origin: ProcessOrigin::Root,
+                },
+            ),
+            event(
+                0,
+                EventKind::Alloc {
+                    size: 8,
+                    ptr: 0x1000,
+                },
+            ),
+            event(0, EventKind::Fork),
+            event(2, EventKind::Free { ptr: 0x1000 }),
+        ];
+        let child = [
+            event(
+                0,
+                EventKind::Process {
+                    pid: 11,
+                    origin: ProcessOrigin::Fork {
+                        parent_pid: 10,
+                        fork_idx: 0,
+                    },
+                },
+            ),
+            // The child's copy of the object is still live after the parent frees its own.
+            event(1, EventKind::CopyPtr(0x1000)),
+        ];
+
+        let graphs = construct_pdg_from_logs(&[&parent[..], &child[..]], &metadata);
+        assert_eq!(graphs.graphs.len(), 1);
+        let nodes = &graphs.graphs[GraphId::from_u32(0)].nodes;
+        let kinds = nodes.iter().map(|n| n.kind.clone()).collect::<Vec<_>>();
+        assert_eq!(kinds, [NodeKind::Alloc(1), NodeKind::Free, NodeKind::Copy]);
+        assert_eq!(nodes[NodeId::from_u32(2)].source, Some(NodeId::from_u32(0)));
+        let parents = graphs
+            .processes
+            .iter()
+            .map(|process| (process.pid, process.parent))
+            .collect::<Vec<_>>();
+        assert_eq!(parents, [(Some(10), None), (Some(11), Some(0))]);
+    }
+}
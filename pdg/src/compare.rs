@@ -0,0 +1,150 @@
+//! Comparison of the dynamic permission [`summary`](crate::summary) against the permissions
+//! inferred statically by `c2rust-analyze`.
+//!
+//! `c2rust-analyze --dump-local-perms <file>` writes a JSON report listing the permissions of the
+//! pointer stored in each local of each function.  Since the static analysis is meant to be
+//! sound, a pointer that was dynamically written, offset, or freed should always have the
+//! corresponding static permission.  Each case where it doesn't points at either a bug in the
+//! analysis or an unsound assumption, such as a `FIXED` def or a manual assignment.
+
+use crate::summary::{FunctionSummary, PermissionSummary, Summaries};
+use color_eyre::eyre;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+
+/// The statically inferred permissions of one local, as recorded in the JSON report from
+/// `c2rust-analyze`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticLocalPerms {
+    /// The `DefPathHash` of the function, as in [`FuncId`](c2rust_analysis_rt::mir_loc::FuncId).
+    pub def_path_hash: (u64, u64),
+    /// The name of the function, for display only.
+    pub function: String,
+    /// The MIR local index.
+    pub local: u32,
+    /// The source location of the local's declaration.
+    pub span: String,
+    /// `|`-separated permission names, such as `READ|WRITE|UNIQUE`, or `-` for none.
+    pub perms: String,
+}
+
+impl StaticLocalPerms {
+    fn has_perm(&self, name: &str) -> bool {
+        self.perms.split('|').any(|perm| perm == name)
+    }
+
+    fn summary(&self) -> PermissionSummary {
+        PermissionSummary {
+            write: self.has_perm("WRITE"),
+            offset: self.has_perm("OFFSET_ADD") || self.has_perm("OFFSET_SUB"),
+            free: self.has_perm("FREE"),
+            uncertain: false,
+        }
+    }
+}
+
+pub fn read_static_report(path: &Path) -> eyre::Result<Vec<StaticLocalPerms>> {
+    let bytes = fs_err::read(path)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// A pointer that required some permission at run time that the static analysis didn't infer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disagreement<'a> {
+    pub static_perms: &'a StaticLocalPerms,
+    /// The permissions that were observed dynamically but are missing statically.
+    pub missing: PermissionSummary,
+}
+
+impl Display for Disagreement<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Self {
+            static_perms,
+            missing,
+        } = self;
+        write!(
+            f,
+            "{}: {} _{}: dynamically {}, but statically {}",
+            static_perms.span,
+            static_perms.function,
+            static_perms.local,
+            missing,
+            static_perms.perms
+        )
+    }
+}
+
+/// List every local whose dynamic permissions in `summaries` aren't covered by its static
+/// permissions in `report`.  Locals missing from either side are ignored.
+pub fn compare<'a>(summaries: &Summaries, report: &'a [StaticLocalPerms]) -> Vec<Disagreement<'a>> {
+    let static_perms = report
+        .iter()
+        .map(|s| ((s.def_path_hash, s.local), s))
+        .collect::<HashMap<_, _>>();
+
+    let mut disagreements = Vec::new();
+    for FunctionSummary { function, locals } in &summaries.functions {
+        let def_path_hash: (u64, u64) = function.id.0.into();
+        for (local, dynamic) in locals {
+            let static_perms = match static_perms.get(&(def_path_hash, local.index)) {
+                Some(&x) => x,
+                None => continue,
+            };
+            let missing = dynamic.difference(static_perms.summary());
+            if !missing.is_empty() {
+                disagreements.push(Disagreement {
+                    static_perms,
+                    missing,
+                });
+            }
+        }
+    }
+    disagreements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{func, perms};
+    use c2rust_analysis_rt::mir_loc::Local;
+    use std::collections::BTreeMap;
+
+    fn static_perms(local: u32, perms: &str) -> StaticLocalPerms {
+        StaticLocalPerms {
+            def_path_hash: (1, 2),
+            function: "f".into(),
+            local,
+            span: format!("src/lib.rs:{local}:5: {local}:6"),
+            perms: perms.into(),
+        }
+    }
+
+    #[test]
+    fn compare_reports_missing_perms() {
+        let summaries = Summaries {
+            functions: vec![FunctionSummary {
+                function: func("f", 1),
+                locals: BTreeMap::from([
+                    (Local::from(1_u32), perms("write")),
+                    (Local::from(2_u32), perms("offset")),
+                    (Local::from(3_u32), perms("write")),
+                ]),
+            }],
+        };
+        let report = vec![
+            static_perms(1, "READ|UNIQUE"),
+            static_perms(2, "READ|OFFSET_ADD"),
+        ];
+
+        let disagreements = compare(&summaries, &report)
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            disagreements,
+            ["src/lib.rs:1:5: 1:6: f _1: dynamically write, but statically READ|UNIQUE"]
+        );
+    }
+}
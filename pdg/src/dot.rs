@@ -0,0 +1,169 @@
+//! Export of the PDG in Graphviz DOT format.
+//!
+//! Each [`Graph`] becomes a cluster labeled with its [`StableGraphId`], and each [`Node`] is filled
+//! with a color showing the strongest access that it or any pointer derived from it requires:
+//!
+//! * light blue: read-only
+//! * yellow: written through
+//! * orange: written through while [`unique`](crate::info::NodeInfo::unique), so it needs
+//!   exclusive access
+//! * red: freed
+//!
+//! Edges that transfer ownership of the object, either into a [`Free`](NodeKind::Free) or
+//! [`Realloc`](NodeKind::Realloc) or across a function boundary (an argument or return value
+//! copy), are drawn bold and red.
+//!
+//! [`StableGraphId`]: crate::graph::StableGraphId
+
+use crate::graph::{Graph, GraphId, Graphs, Node, NodeId, NodeKind};
+use crate::summary::node_permissions;
+use crate::util::ShortOption;
+use std::fmt::{self, Display, Formatter};
+
+/// The strongest kind of access required by a [`Node`] and its descendants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Requirement {
+    ReadOnly,
+    Write,
+    Unique,
+    Freed,
+}
+
+impl Requirement {
+    fn color(self) -> &'static str {
+        match self {
+            Requirement::ReadOnly => "lightblue",
+            Requirement::Write => "yellow",
+            Requirement::Unique => "orange",
+            Requirement::Freed => "red",
+        }
+    }
+}
+
+/// Compute the [`Requirement`] of each [`Node`] in `graph`.
+pub fn node_requirements(graph: &Graph) -> Vec<Requirement> {
+    let perms = node_permissions(graph);
+    graph
+        .nodes
+        .iter_enumerated()
+        .map(|(id, node)| {
+            let perms = perms[&id];
+            let unique = node.info.as_ref().map_or(false, |info| info.unique);
+            if perms.free {
+                Requirement::Freed
+            } else if perms.write && unique {
+                Requirement::Unique
+            } else if perms.write {
+                Requirement::Write
+            } else {
+                Requirement::ReadOnly
+            }
+        })
+        .collect()
+}
+
+/// Whether the edge from `source` to `node` moves ownership of the object.
+fn is_ownership_transfer(source: &Node, node: &Node) -> bool {
+    match node.kind {
+        NodeKind::Free | NodeKind::Realloc => true,
+        NodeKind::Copy => source.function.id != node.function.id,
+        _ => false,
+    }
+}
+
+/// Escape `s` for use inside a double-quoted DOT string.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn dot_node_id(graph_id: GraphId, node_id: NodeId) -> String {
+    format!("g{}n{}", graph_id.as_usize(), node_id.as_usize())
+}
+
+/// A [`Display`] wrapper that prints [`Graphs`] in DOT format.
+pub struct Dot<'a>(pub &'a Graphs);
+
+impl Display for Dot<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "digraph pdg {{")?;
+        writeln!(f, "\tnode [shape=box, style=filled];")?;
+        let stable_ids = self.0.stable_ids();
+        for (graph_id, graph) in self.0.graphs.iter_enumerated() {
+            writeln!(f, "\tsubgraph cluster_{} {{", graph_id.as_usize())?;
+            writeln!(
+                f,
+                "\t\tlabel=\"{} is_null={}\";",
+                stable_ids[graph_id], graph.is_null
+            )?;
+            let requirements = node_requirements(graph);
+            for (node_id, node) in graph.nodes.iter_enumerated() {
+                let kind = format!("{node_id}: {}", node.kind);
+                let place = format!("{} => {}", node.function, ShortOption(node.dest.as_ref()));
+                writeln!(
+                    f,
+                    "\t\t{} [label=\"{}\\n{}\", fillcolor={}];",
+                    dot_node_id(graph_id, node_id),
+                    escape(&kind),
+                    escape(&place),
+                    requirements[node_id.as_usize()].color(),
+                )?;
+            }
+            for (node_id, node) in graph.nodes.iter_enumerated() {
+                let source_id = match node.source {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let attrs = if is_ownership_transfer(&graph.nodes[source_id], node) {
+                    " [style=bold, color=red]"
+                } else {
+                    ""
+                };
+                writeln!(
+                    f,
+                    "\t\t{} -> {}{attrs};",
+                    dot_node_id(graph_id, source_id),
+                    dot_node_id(graph_id, node_id),
+                )?;
+            }
+            writeln!(f, "\t}}")?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::info::add_info;
+    use crate::test_util::{func, node};
+
+    #[test]
+    fn requirements_and_transfers() {
+        let f = func("f", 1);
+        let g = func("g", 3);
+        let mut graph = Graph::new(false);
+        graph.nodes.push(node(&f, NodeKind::Alloc(1), None));
+        graph.nodes.push(node(&f, NodeKind::Copy, Some(0)));
+        graph.nodes.push(node(&f, NodeKind::LoadAddr, Some(1)));
+        graph.nodes.push(node(&g, NodeKind::Copy, Some(0)));
+        graph.nodes.push(node(&g, NodeKind::StoreAddr, Some(3)));
+        graph.nodes.push(node(&f, NodeKind::Free, Some(0)));
+        let mut graphs = Graphs::new();
+        graphs.graphs.push(graph);
+        add_info(&mut graphs);
+
+        let requirements = node_requirements(&graphs.graphs[GraphId::from_u32(0)]);
+        use Requirement::*;
+        assert_eq!(
+            requirements,
+            [Freed, ReadOnly, ReadOnly, Unique, Unique, Freed]
+        );
+
+        let dot = Dot(&graphs).to_string();
+        assert!(dot.contains("label=\"0000000000000001-0-0-0 is_null=false\";"));
+        assert!(dot.contains("g0n0 [label=\"n[0]: alloc\\nf => _\", fillcolor=red];"));
+        assert!(dot.contains("g0n0 -> g0n1;\n"));
+        assert!(dot.contains("g0n0 -> g0n3 [style=bold, color=red];"));
+        assert!(dot.contains("g0n0 -> g0n5 [style=bold, color=red];"));
+    }
+}
@@ -0,0 +1,203 @@
+//! Export of the PDG to formats understood by graph databases and general graph tooling.
+//!
+//! * GraphML, a single XML file readable by most graph libraries and by Neo4j's APOC plugin.
+//! * Neo4j CSV, a pair of `nodes.csv` and `relationships.csv` files in the format expected by
+//!   `neo4j-admin database import`.
+//!
+//! Both formats give every [`Node`] the ID `<graph>/<node>`, where `<graph>` is the
+//! [`StableGraphId`] of its [`Graph`](crate::graph::Graph), and the same set of properties, and
+//! link each [`Node`] to its [`Node::source`] with a `SOURCE_OF` edge.
+
+use crate::graph::{Graphs, Node, NodeId, StableGraphId};
+use crate::util::ShortOption;
+use std::io::{self, Write};
+
+/// The values of the [`PROPERTIES`] of an exported [`Node`].
+fn node_properties(
+    graph_id: StableGraphId,
+    node_id: NodeId,
+    node: &Node,
+    is_null: bool,
+) -> [String; 9] {
+    [
+        graph_id.to_string(),
+        node_id.as_usize().to_string(),
+        node.kind.to_string(),
+        node.function.name.clone(),
+        format!("{:?}", node.block),
+        node.statement_idx.to_string(),
+        ShortOption(node.dest.as_ref()).to_string(),
+        is_null.to_string(),
+        node.info
+            .as_ref()
+            .map_or(false, |info| info.unique)
+            .to_string(),
+    ]
+}
+
+/// Names and GraphML types of the values returned by [`node_properties`].
+const PROPERTIES: [(&str, &str); 9] = [
+    ("graph", "string"),
+    ("node", "int"),
+    ("kind", "string"),
+    ("function", "string"),
+    ("block", "string"),
+    ("statement", "int"),
+    ("dest", "string"),
+    ("is_null", "boolean"),
+    ("unique", "boolean"),
+];
+
+fn export_id(graph_id: StableGraphId, node_id: NodeId) -> String {
+    format!("{graph_id}/{}", node_id.as_usize())
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+/// Write `graphs` as a single GraphML document.
+pub fn write_graphml(graphs: &Graphs, w: &mut impl Write) -> io::Result<()> {
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    for (name, ty) in PROPERTIES {
+        writeln!(
+            w,
+            r#"  <key id="{name}" for="node" attr.name="{name}" attr.type="{ty}"/>"#
+        )?;
+    }
+    writeln!(w, r#"  <graph id="pdg" edgedefault="directed">"#)?;
+    for (graph, graph_id) in graphs.graphs.iter().zip(graphs.stable_ids()) {
+        for (node_id, node) in graph.nodes.iter_enumerated() {
+            writeln!(w, r#"    <node id="{}">"#, export_id(graph_id, node_id))?;
+            let values = node_properties(graph_id, node_id, node, graph.is_null);
+            for ((name, _), value) in PROPERTIES.iter().zip(values) {
+                writeln!(
+                    w,
+                    r#"      <data key="{name}">{}</data>"#,
+                    xml_escape(&value)
+                )?;
+            }
+            writeln!(w, "    </node>")?;
+        }
+        for (node_id, node) in graph.nodes.iter_enumerated() {
+            if let Some(source) = node.source {
+                writeln!(
+                    w,
+                    r#"    <edge source="{}" target="{}"/>"#,
+                    export_id(graph_id, source),
+                    export_id(graph_id, node_id)
+                )?;
+            }
+        }
+    }
+    writeln!(w, "  </graph>")?;
+    writeln!(w, "</graphml>")
+}
+
+/// Write `graphs` as Neo4j import CSV files, with nodes in `nodes` and edges in `relationships`.
+pub fn write_neo4j_csv(
+    graphs: &Graphs,
+    nodes: &mut impl Write,
+    relationships: &mut impl Write,
+) -> io::Result<()> {
+    let header = PROPERTIES
+        .iter()
+        .map(|&(name, ty)| match ty {
+            "string" => name.to_owned(),
+            _ => format!("{name}:{ty}"),
+        })
+        .collect::<Vec<_>>();
+    writeln!(nodes, "id:ID,{},:LABEL", header.join(","))?;
+    writeln!(relationships, ":START_ID,:END_ID,:TYPE")?;
+    for (graph, graph_id) in graphs.graphs.iter().zip(graphs.stable_ids()) {
+        for (node_id, node) in graph.nodes.iter_enumerated() {
+            let values = node_properties(graph_id, node_id, node, graph.is_null)
+                .map(|value| csv_escape(&value));
+            writeln!(
+                nodes,
+                "{},{},PdgNode",
+                export_id(graph_id, node_id),
+                values.join(",")
+            )?;
+            if let Some(source) = node.source {
+                writeln!(
+                    relationships,
+                    "{},{},SOURCE_OF",
+                    export_id(graph_id, source),
+                    export_id(graph_id, node_id)
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{Graph, NodeKind};
+    use crate::test_util::{func, node};
+    use rustc_middle::mir::BasicBlock;
+
+    fn graphs() -> Graphs {
+        let function = func("f<'a, T>", 1);
+        let mk_node = |kind, source| Node {
+            block: BasicBlock::from_u32(1),
+            statement_idx: 2,
+            ..node(&function, kind, source)
+        };
+        let mut graph = Graph::new(false);
+        graph.nodes.push(mk_node(NodeKind::Alloc(1), None));
+        graph.nodes.push(mk_node(NodeKind::StoreAddr, Some(0)));
+        let mut graphs = Graphs::new();
+        graphs.graphs.push(graph);
+        graphs
+    }
+
+    #[test]
+    fn graphml() {
+        let mut out = Vec::new();
+        write_graphml(&graphs(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(r#"<node id="0000000000000001-1-2-0/1">"#));
+        assert!(out.contains(r#"<data key="kind">addr.store</data>"#));
+        assert!(out.contains(r#"<data key="function">f&lt;'a, T&gt;</data>"#));
+        assert!(out.contains(
+            r#"<edge source="0000000000000001-1-2-0/0" target="0000000000000001-1-2-0/1"/>"#
+        ));
+    }
+
+    #[test]
+    fn neo4j_csv() {
+        let mut nodes = Vec::new();
+        let mut relationships = Vec::new();
+        write_neo4j_csv(&graphs(), &mut nodes, &mut relationships).unwrap();
+        let nodes = String::from_utf8(nodes).unwrap();
+        let relationships = String::from_utf8(relationships).unwrap();
+        assert_eq!(
+            nodes,
+            "id:ID,graph,node:int,kind,function,block,statement:int,dest,is_null:boolean,unique:boolean,:LABEL\n\
+             0000000000000001-1-2-0/0,0000000000000001-1-2-0,0,alloc,\"f<'a, T>\",bb1,2,_,false,false,PdgNode\n\
+             0000000000000001-1-2-0/1,0000000000000001-1-2-0,1,addr.store,\"f<'a, T>\",bb1,2,_,false,false,PdgNode\n"
+        );
+        assert_eq!(
+            relationships,
+            ":START_ID,:END_ID,:TYPE\n0000000000000001-1-2-0/0,0000000000000001-1-2-0/1,SOURCE_OF\n"
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Report of how far pointer arithmetic reached into heap allocations, grouped by allocation site.
+//!
+//! Each [`Offset`](EventKind::Offset) event records the base pointer, the signed offset in
+//! elements, and the resulting pointer.  Matching the base pointer to the live allocation
+//! containing it gives the byte range of each allocation that was actually reached, which can be
+//! checked against the allocation's size and against the slice lengths inferred statically.  Like
+//! the [`leaks`](crate::leaks) report, this works directly from the event log.
+
+use c2rust_analysis_rt::events::{Event, EventKind, Pointer};
+use c2rust_analysis_rt::metadata::Metadata;
+use c2rust_analysis_rt::mir_loc::{Func, MirLocId};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+
+/// The extents reached by pointer arithmetic on the allocations from one site.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtentSite {
+    pub func: Func,
+    pub basic_block_idx: usize,
+    pub statement_idx: usize,
+    pub span: String,
+    /// The number of allocations from this site that were offset into.
+    pub count: usize,
+    /// The size in bytes of the largest of those allocations.
+    pub bytes: usize,
+    /// The lowest and highest byte offsets from the start of an allocation that offset results
+    /// pointed to.
+    pub reach: (isize, isize),
+    /// The element size implied by the byte distance of nonzero offsets, or `None` if they
+    /// disagree or there are none.
+    pub elem_size: Option<usize>,
+    /// The number of offsets whose result is outside its allocation, allowing one past the end.
+    pub out_of_bounds: usize,
+}
+
+/// All [`ExtentSite`]s, sorted with the most out-of-bounds offsets first.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Extents {
+    pub sites: Vec<ExtentSite>,
+}
+
+impl Display for Extents {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for site in &self.sites {
+            let ExtentSite {
+                func,
+                basic_block_idx,
+                statement_idx,
+                span,
+                count,
+                bytes,
+                reach: (lo, hi),
+                elem_size,
+                out_of_bounds,
+            } = site;
+            write!(
+                f,
+                "{span} (fn {func} bb{basic_block_idx}[{statement_idx}]): {count} allocations of up to {bytes} bytes, reached bytes {lo}..={hi}"
+            )?;
+            if let Some(elem_size) = elem_size {
+                write!(f, " in elements of {elem_size} bytes")?;
+            }
+            if *out_of_bounds > 0 {
+                write!(f, ", {out_of_bounds} offsets out of bounds")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The state of one allocation site while scanning the event log.
+struct SiteState {
+    allocs: usize,
+    bytes: usize,
+    reach: (isize, isize),
+    elem_size: Option<Option<usize>>,
+    out_of_bounds: usize,
+}
+
+/// Find the extents reached by the offsets in each of the process `images` within each heap
+/// allocation.  Each image, as split by [`process_images`](crate::builder::process_images), has
+/// its own address space, so offsets are matched to allocations within each one.
+pub fn find_extents(images: &[&[Event]], metadata: &Metadata) -> Extents {
+    let mut sites = HashMap::<MirLocId, SiteState>::new();
+    for events in images {
+        // Live allocations by start address, with the site and size of each and whether it has
+        // been offset into yet.
+        let mut live = BTreeMap::<Pointer, (MirLocId, usize, bool)>::new();
+        for event in *events {
+            match event.kind {
+                EventKind::Alloc { size, ptr } if ptr != 0 => {
+                    live.insert(ptr, (event.mir_loc, size, false));
+                }
+                EventKind::Realloc {
+                    old_ptr,
+                    size,
+                    new_ptr,
+                } => {
+                    live.remove(&old_ptr);
+                    if new_ptr != 0 {
+                        live.insert(new_ptr, (event.mir_loc, size, false));
+                    }
+                }
+                EventKind::Free { ptr } => {
+                    live.remove(&ptr);
+                }
+                EventKind::Offset(ptr, offset, new_ptr) => {
+                    let (&start, alloc) = match live.range_mut(..=ptr).next_back() {
+                        Some((start, alloc)) if ptr <= start.saturating_add(alloc.1) => {
+                            (start, alloc)
+                        }
+                        // Not a heap allocation, or one made before the trace started.
+                        _ => continue,
+                    };
+                    let (mir_loc, size, ref mut seen) = *alloc;
+                    let site = sites.entry(mir_loc).or_insert_with(|| SiteState {
+                        allocs: 0,
+                        bytes: 0,
+                        reach: (0, 0),
+                        elem_size: None,
+                        out_of_bounds: 0,
+                    });
+                    if !*seen {
+                        *seen = true;
+                        site.allocs += 1;
+                        site.bytes = site.bytes.max(size);
+                    }
+
+                    let pos = new_ptr.wrapping_sub(start) as isize;
+                    site.reach = (site.reach.0.min(pos), site.reach.1.max(pos));
+                    if pos < 0 || pos as usize > size {
+                        site.out_of_bounds += 1;
+                    }
+                    if offset != 0 {
+                        let distance = new_ptr.wrapping_sub(ptr) as isize;
+                        let elem_size = (distance % offset == 0)
+                            .then(|| distance / offset)
+                            .and_then(|elem_size| usize::try_from(elem_size).ok());
+                        site.elem_size = match site.elem_size {
+                            None => Some(elem_size),
+                            Some(prev) if prev == elem_size => Some(prev),
+                            Some(_) => Some(None),
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut sites = sites
+        .into_iter()
+        .map(|(mir_loc, state)| {
+            let loc = metadata.get(mir_loc);
+            ExtentSite {
+                func: loc.func.clone(),
+                basic_block_idx: loc.basic_block_idx,
+                statement_idx: loc.statement_idx,
+                span: metadata.span(loc.metadata.span).to_string(),
+                count: state.allocs,
+                bytes: state.bytes,
+                reach: state.reach,
+                elem_size: state.elem_size.flatten(),
+                out_of_bounds: state.out_of_bounds,
+            }
+        })
+        .collect::<Vec<_>>();
+    sites.sort_by(|a, b| {
+        b.out_of_bounds
+            .cmp(&a.out_of_bounds)
+            .then_with(|| a.func.name.cmp(&b.func.name))
+            .then_with(|| {
+                (a.basic_block_idx, a.statement_idx).cmp(&(b.basic_block_idx, b.statement_idx))
+            })
+    });
+    Extents { sites }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{event, func, loc, metadata};
+
+    #[test]
+    fn extents_by_site() {
+        let metadata = metadata(vec![
+            loc(&func("f", 1), 1, None, None),
+            loc(&func("g", 2), 2, None, None),
+            loc(&func("h", 3), 3, None, None),
+        ]);
+        let events = [
+            event(
+                0,
+                EventKind::Alloc {
+                    size: 16,
+                    ptr: 0x100,
+                },
+            ),
+            event(
+                0,
+                EventKind::Alloc {
+                    size: 8,
+                    ptr: 0x200,
+                },
+            ),
+            event(
+                1,
+                EventKind::Alloc {
+                    size: 4,
+                    ptr: 0x300,
+                },
+            ),
+            // Offsets within and to the end of `f`'s allocations, in `i32`s.
+            event(2, EventKind::Offset(0x100, 2, 0x108)),
+            event(2, EventKind::Offset(0x108, 2, 0x110)),
+            event(2, EventKind::Offset(0x200, 1, 0x204)),
+            // `g`'s allocation is read past its end.
+            event(2, EventKind::Offset(0x300, 3, 0x30c)),
+            // Offsets from pointers outside any heap allocation are ignored.
+            event(2, EventKind::Offset(0x1000, 1, 0x1004)),
+        ];
+
+        let extents = find_extents(&[&events], &metadata);
+        let sites = extents
+            .sites
+            .iter()
+            .map(|site| {
+                (
+                    site.func.name.as_str(),
+                    site.count,
+                    site.bytes,
+                    site.reach,
+                    site.elem_size,
+                    site.out_of_bounds,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sites,
+            [
+                ("g", 1, 4, (0, 12), Some(4), 1),
+                ("f", 2, 16, (0, 16), Some(4), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn extents_per_process() {
+        let metadata = metadata(vec![
+            loc(&func("f", 1), 1, None, None),
+            loc(&func("g", 2), 2, None, None),
+            loc(&func("h", 3), 3, None, None),
+        ]);
+        let parent = [
+            event(
+                0,
+                EventKind::Alloc {
+                    size: 16,
+                    ptr: 0x100,
+                },
+            ),
+            event(
+                0,
+                EventKind::Alloc {
+                    size: 16,
+                    ptr: 0x200,
+                },
+            ),
+            event(2, EventKind::Offset(0x100, 2, 0x108)),
+        ];
+        // The child's allocation at the same address as the parent's is matched to the child's
+        // own, smaller allocation, and the parent's other allocation isn't known in the child.
+        let child = [
+            event(
+                1,
+                EventKind::Alloc {
+                    size: 4,
+                    ptr: 0x100,
+                },
+            ),
+            event(2, EventKind::Offset(0x100, 2, 0x108)),
+            event(2, EventKind::Offset(0x200, 1, 0x204)),
+        ];
+
+        let extents = find_extents(&[&parent, &child], &metadata);
+        let sites = extents
+            .sites
+            .iter()
+            .map(|site| {
+                (
+                    site.func.name.as_str(),
+                    site.count,
+                    site.reach,
+                    site.out_of_bounds,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sites, [("g", 1, (0, 8), 1), ("f", 1, (0, 8), 0)]);
+    }
+}
@@ -0,0 +1,208 @@
+//! Export of PDG [`Graph`]s as test cases for `c2rust-analyze`.
+//!
+//! Each selected [`Graph`] becomes a function that performs the same pointer operations in the
+//! same order, on `*mut i32`s, with a `FileCheck` annotation for each pointer giving the
+//! permissions it required at run time.  The static analysis must infer at least those
+//! permissions, so writing the output into `c2rust-analyze/tests/filecheck` turns a trace shape
+//! seen in a real program into a regression test.
+//!
+//! Root nodes other than [`NodeKind::Alloc`] become parameters of the function.  Operations that
+//! can't be expressed on a lone `*mut i32`, like [`NodeKind::Project`], are modeled as copies.
+
+use crate::graph::{Graph, GraphId, Graphs, NodeId, NodeKind, StableGraphId};
+use crate::summary::{node_permissions, PermissionSummary};
+use std::io::{self, Write};
+
+const HEADER: &str = r#"#![feature(rustc_private)]
+#![allow(unused_variables)]
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn realloc(_: *mut libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+"#;
+
+/// A `FileCheck` pattern matching a permission set that includes everything in `perms`, or `None`
+/// if there is nothing to check.  Uncertain permissions are never checked, as the trace may have
+/// missed uses that the static analysis would see.
+fn perms_pattern(perms: PermissionSummary) -> Option<String> {
+    if perms.is_empty() || perms.uncertain {
+        return None;
+    }
+    // These are in the order `c2rust-analyze` prints them in.
+    let names = [
+        (perms.write, "WRITE"),
+        (perms.offset, "OFFSET_"),
+        (perms.free, "FREE"),
+    ];
+    let mut pattern = "type = ".to_owned();
+    for (_, name) in names.into_iter().filter(|&(set, _)| set) {
+        pattern.push_str("{{[^#]*}}");
+        pattern.push_str(name);
+    }
+    pattern.push_str("{{[^#]*}}#");
+    Some(pattern)
+}
+
+fn var(id: NodeId) -> String {
+    format!("p{}", id.as_usize())
+}
+
+/// How the operation of a node is written in the fixture.
+enum NodeStmt {
+    /// The node's pointer is a parameter of the function.
+    Param,
+    /// A `let` statement defining the node's pointer.
+    Let(String),
+    /// A statement that doesn't produce a pointer.
+    Use(String),
+}
+
+fn node_stmt(graph: &Graph, id: NodeId) -> NodeStmt {
+    let node = &graph.nodes[id];
+    let v = var(id);
+    let src = match node.source {
+        Some(source) => var(source),
+        None => {
+            return match node.kind {
+                NodeKind::Alloc(n) => {
+                    NodeStmt::Let(format!("let {v} = malloc({}) as *mut i32;", 4 * n.max(1)))
+                }
+                _ => NodeStmt::Param,
+            };
+        }
+    };
+    match node.kind {
+        NodeKind::Offset(offset) => NodeStmt::Let(format!("let {v} = {src}.offset({offset});")),
+        // The new size isn't recorded in the PDG.
+        NodeKind::Realloc => NodeStmt::Let(format!(
+            "let {v} = realloc({src} as *mut libc::c_void, 4) as *mut i32;"
+        )),
+        NodeKind::Free => NodeStmt::Use(format!("free({src} as *mut libc::c_void);")),
+        NodeKind::LoadAddr => NodeStmt::Use(format!("let _ = *{src};")),
+        NodeKind::StoreAddr => NodeStmt::Use(format!("*{src} = 0;")),
+        NodeKind::PtrToInt => NodeStmt::Use(format!("let _ = {src} as usize;")),
+        NodeKind::StoreValue => NodeStmt::Use(format!("let _ = {src};")),
+        _ => NodeStmt::Let(format!("let {v} = {src};")),
+    }
+}
+
+fn write_graph(graph: &Graph, graph_id: StableGraphId, w: &mut impl Write) -> io::Result<()> {
+    let perms = node_permissions(graph);
+    let pattern = |id: NodeId| perms_pattern(perms[&id]);
+
+    let stmts = graph
+        .nodes
+        .indices()
+        .map(|id| (id, node_stmt(graph, id)))
+        .collect::<Vec<_>>();
+    let params = stmts
+        .iter()
+        .filter(|(_, stmt)| matches!(stmt, NodeStmt::Param))
+        .map(|&(id, _)| id)
+        .collect::<Vec<_>>();
+
+    let name = format!("pdg_{graph_id}").replace('-', "_");
+    writeln!(w)?;
+    writeln!(w, "// Generated from PDG graph {graph_id}.")?;
+    writeln!(w, "// CHECK-LABEL: final labeling for \"{name}\"")?;
+    let param_checks = params
+        .iter()
+        .filter_map(|&id| Some((id, pattern(id)?)))
+        .collect::<Vec<_>>();
+    for (i, (id, pattern)) in param_checks.iter().enumerate() {
+        let line_offset = param_checks.len() - i;
+        writeln!(
+            w,
+            "// CHECK-DAG: ([[@LINE+{line_offset}]]: {}): {{{{.*}}}}{pattern}",
+            var(*id)
+        )?;
+    }
+    let params = params
+        .iter()
+        .map(|&id| format!("{}: *mut i32", var(id)))
+        .collect::<Vec<_>>();
+    writeln!(w, "pub unsafe fn {name}({}) {{", params.join(", "))?;
+    for (id, stmt) in &stmts {
+        let node = &graph.nodes[*id];
+        writeln!(
+            w,
+            "    // {id}: {}, from fn {} at {:?}[{}]",
+            node.kind, node.function.name, node.block, node.statement_idx
+        )?;
+        match stmt {
+            NodeStmt::Param => {}
+            NodeStmt::Let(stmt) => {
+                if let Some(pattern) = pattern(*id) {
+                    writeln!(
+                        w,
+                        "    // CHECK-DAG: ([[@LINE+1]]: {}): {{{{.*}}}}{pattern}",
+                        var(*id)
+                    )?;
+                }
+                writeln!(w, "    {stmt}")?;
+            }
+            NodeStmt::Use(stmt) => writeln!(w, "    {stmt}")?,
+        }
+    }
+    writeln!(w, "}}")
+}
+
+/// Write the graphs of `graphs` selected by `selected` as a `c2rust-analyze` `FileCheck` test.
+pub fn write_fixture(graphs: &Graphs, selected: &[GraphId], w: &mut impl Write) -> io::Result<()> {
+    let stable_ids = graphs.stable_ids();
+    write!(w, "{HEADER}")?;
+    for &graph_id in selected {
+        write_graph(&graphs.graphs[graph_id], stable_ids[graph_id], w)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Node;
+    use crate::test_util::{func, node};
+    use rustc_middle::mir::BasicBlock;
+
+    #[test]
+    fn alloc_offset_store_free() {
+        let function = func("f", 1);
+        let mk_node = |kind, source| Node {
+            block: BasicBlock::from_u32(1),
+            statement_idx: 2,
+            ..node(&function, kind, source)
+        };
+        let mut graph = Graph::new(false);
+        graph.nodes.push(mk_node(NodeKind::Alloc(2), None));
+        graph.nodes.push(mk_node(NodeKind::Offset(1), Some(0)));
+        graph.nodes.push(mk_node(NodeKind::StoreAddr, Some(1)));
+        graph.nodes.push(mk_node(NodeKind::Free, Some(0)));
+        let mut graphs = Graphs::new();
+        graphs.graphs.push(graph);
+
+        let mut out = Vec::new();
+        write_fixture(&graphs, &[GraphId::from_u32(0)], &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let body = out.split_once("// Generated").unwrap().1;
+        assert_eq!(
+            body,
+            " from PDG graph 0000000000000001-1-2-0.\n\
+             // CHECK-LABEL: final labeling for \"pdg_0000000000000001_1_2_0\"\n\
+             pub unsafe fn pdg_0000000000000001_1_2_0() {\n    \
+                 // n[0]: alloc(n = 2), from fn f at bb1[2]\n    \
+                 // CHECK-DAG: ([[@LINE+1]]: p0): {{.*}}type = {{[^#]*}}WRITE{{[^#]*}}OFFSET_{{[^#]*}}FREE{{[^#]*}}#\n    \
+                 let p0 = malloc(8) as *mut i32;\n    \
+                 // n[1]: offset[1], from fn f at bb1[2]\n    \
+                 // CHECK-DAG: ([[@LINE+1]]: p1): {{.*}}type = {{[^#]*}}WRITE{{[^#]*}}#\n    \
+                 let p1 = p0.offset(1);\n    \
+                 // n[2]: addr.store, from fn f at bb1[2]\n    \
+                 *p1 = 0;\n    \
+                 // n[3]: free, from fn f at bb1[2]\n    \
+                 free(p0 as *mut libc::c_void);\n\
+             }\n"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Folded-stack output of heap allocation activity, for rendering as a flame graph with `inferno`
+//! or `flamegraph.pl`.
+//!
+//! Each line is a caller chain ending in an allocation site, followed by the number of
+//! allocations or bytes allocated there.  The event log doesn't record calls and returns
+//! directly, so the chain is reconstructed from [`BeginFuncBody`](EventKind::BeginFuncBody)
+//! events, which push a function, and from events in a function further down the chain, which
+//! pop the functions above it.  Calls through uninstrumented code may therefore be missing.
+
+use c2rust_analysis_rt::events::{Event, EventKind};
+use c2rust_analysis_rt::metadata::Metadata;
+use c2rust_analysis_rt::mir_loc::Func;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+/// What each allocation contributes to the width of its stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weight {
+    /// The number of allocations.
+    Count,
+    /// The number of bytes allocated.
+    Bytes,
+}
+
+/// The allocations made at each stack.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AllocStacks {
+    /// The number of allocations and bytes allocated, keyed by the frames of the stack,
+    /// outermost first.
+    pub stacks: BTreeMap<Vec<String>, (usize, usize)>,
+}
+
+impl AllocStacks {
+    pub fn folded(&self, weight: Weight) -> Folded<'_> {
+        Folded {
+            stacks: self,
+            weight,
+        }
+    }
+}
+
+/// The folded-stack representation of [`AllocStacks`].
+pub struct Folded<'a> {
+    stacks: &'a AllocStacks,
+    weight: Weight,
+}
+
+impl Display for Folded<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (frames, &(count, bytes)) in &self.stacks.stacks {
+            let value = match self.weight {
+                Weight::Count => count,
+                Weight::Bytes => bytes,
+            };
+            writeln!(f, "{} {value}", frames.join(";"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Frames are separated by `;` in the folded format, so it can't appear within one.
+fn frame(s: &str) -> String {
+    s.replace(';', ",")
+}
+
+/// Collect the allocations and reallocations in each of the process `images` by stack.  Each
+/// image, as split by [`process_images`](crate::builder::process_images), starts with an empty
+/// call stack.
+pub fn alloc_stacks(images: &[&[Event]], metadata: &Metadata) -> AllocStacks {
+    let mut stacks = AllocStacks::default();
+    for events in images {
+        let mut call_stack = Vec::<&Func>::new();
+        for event in *events {
+            if event.kind.is_structural() {
+                continue;
+            }
+            let loc = metadata.get(event.mir_loc);
+            let func = &loc.func;
+            if let EventKind::BeginFuncBody = event.kind {
+                call_stack.push(func);
+                continue;
+            }
+            match call_stack.iter().rposition(|caller| caller.id == func.id) {
+                Some(i) => call_stack.truncate(i + 1),
+                // The function was entered without a `BeginFuncBody`, e.g. in uninstrumented code.
+                None => call_stack.push(func),
+            }
+
+            let size = match event.kind {
+                EventKind::Alloc { size, ptr } if ptr != 0 => size,
+                EventKind::Realloc { size, new_ptr, .. } if new_ptr != 0 => size,
+                _ => continue,
+            };
+            let mut frames = call_stack
+                .iter()
+                .map(|func| frame(&func.name))
+                .collect::<Vec<_>>();
+            frames.push(frame(&format!(
+                "alloc at {}",
+                metadata.span(loc.metadata.span)
+            )));
+            let (count, bytes) = stacks.stacks.entry(frames).or_default();
+            *count += 1;
+            *bytes += size;
+        }
+    }
+    stacks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{event, func, loc, metadata};
+
+    #[test]
+    fn stacks_follow_calls_and_returns() {
+        let metadata = metadata(vec![
+            loc(&func("main", 1), 0, None, None),
+            loc(&func("f", 2), 0, None, None),
+        ]);
+        let events = [
+            event(0, EventKind::BeginFuncBody),
+            event(0, EventKind::Alloc { size: 8, ptr: 0x10 }),
+            event(1, EventKind::BeginFuncBody),
+            event(1, EventKind::Alloc { size: 4, ptr: 0x20 }),
+            event(
+                1,
+                EventKind::Realloc {
+                    old_ptr: 0x20,
+                    size: 16,
+                    new_ptr: 0x30,
+                },
+            ),
+            // Back in `main` after `f` returns.
+            event(0, EventKind::Alloc { size: 8, ptr: 0x40 }),
+            // A failed allocation isn't counted.
+            event(0, EventKind::Alloc { size: 8, ptr: 0 }),
+        ];
+
+        let stacks = alloc_stacks(&[&events], &metadata);
+        let leaf = format!("alloc at {}", metadata.span(None));
+        assert_eq!(
+            stacks.folded(Weight::Count).to_string(),
+            format!("main;{leaf} 2\nmain;f;{leaf} 2\n")
+        );
+        assert_eq!(
+            stacks.folded(Weight::Bytes).to_string(),
+            format!("main;{leaf} 16\nmain;f;{leaf} 20\n")
+        );
+    }
+
+    #[test]
+    fn stacks_per_process() {
+        let metadata = metadata(vec![
+            loc(&func("main", 1), 0, None, None),
+            loc(&func("f", 2), 0, None, None),
+        ]);
+        let parent = [
+            event(0, EventKind::BeginFuncBody),
+            event(1, EventKind::BeginFuncBody),
+            event(1, EventKind::Alloc { size: 4, ptr: 0x10 }),
+        ];
+        // The child doesn't continue the parent's stack.
+        let child = [event(1, EventKind::Alloc { size: 8, ptr: 0x20 })];
+
+        let stacks = alloc_stacks(&[&parent, &child], &metadata);
+        let leaf = format!("alloc at {}", metadata.span(None));
+        assert_eq!(
+            stacks.folded(Weight::Count).to_string(),
+            format!("f;{leaf} 1\nmain;f;{leaf} 1\n")
+        );
+    }
+}
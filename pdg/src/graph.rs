@@ -1,3 +1,4 @@
+use c2rust_analysis_rt::events::ProcessOrigin;
 use c2rust_analysis_rt::mir_loc::{self, DefPathHash, Func};
 use c2rust_analysis_rt::mir_loc::{FuncId, MirPlace};
 use rustc_index::newtype_index;
@@ -78,6 +79,13 @@ pub enum NodeKind {
     /// Can't be the [`Node::source`] of any other operation.
     Free,
 
+    /// Heap reallocation.
+    ///
+    /// The [`Node::source`] is the old pointer, and the result is the new pointer, which continues
+    /// the same [`Graph`] even if the object moved, so the old and new objects are treated as one
+    /// logical allocation.  Like [`Free`](Self::Free), this requires ownership of the old pointer.
+    Realloc,
+
     /// Int to pointer conversion.
     ///
     /// Details TBD.
@@ -115,6 +123,16 @@ pub enum NodeKind {
     ///
     /// Can't be the [`Node::source`] of any other operation.
     StoreValue,
+
+    /// A pointer whose origin wasn't observed.
+    ///
+    /// This is the root of a graph whose first observed operation derives from a pointer that the
+    /// event log has no record of, usually because the operation that created it wasn't
+    /// instrumented (e.g. with sampling or selective instrumentation).  Everything derived from
+    /// this node may be missing operations, so results computed from it are uncertain.
+    ///
+    /// Can't have a [`Node::source`].
+    UnknownOrigin,
 }
 
 impl Display for NodeKind {
@@ -137,12 +155,14 @@ impl Display for NodeKind {
                 Ok(())
             }
             Free => write!(f, "free"),
+            Realloc => write!(f, "realloc"),
             PtrToInt => write!(f, "ptr_to_int"),
             IntToPtr => write!(f, "int_to_ptr"),
             LoadValue => write!(f, "value.load"),
             StoreValue => write!(f, "value.store"),
             LoadAddr => write!(f, "addr.load"),
             StoreAddr => write!(f, "addr.store"),
+            UnknownOrigin => write!(f, "unknown"),
         }
     }
 }
@@ -359,6 +379,77 @@ impl Display for GraphId {
     }
 }
 
+/// An identifier for a [`Graph`] that stays the same across runs of the same program, unlike its
+/// [`GraphId`], which depends on the order of every event in the trace.
+///
+/// It names the graph's allocation site, the location of its root [`Node`], by the
+/// [`DefPathHash`] of the function and the MIR location within it, along with the number of
+/// earlier graphs rooted at that same site.  It is written as
+/// `<func_hash>-<block>-<statement_idx>-<seq>`, with `func_hash` in hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct StableGraphId {
+    /// The first half of the [`DefPathHash`] of the allocation site's function.
+    pub func_hash: u64,
+    pub block: u32,
+    pub statement_idx: usize,
+    /// The number of graphs rooted at the same site that were created before this one.
+    pub seq: usize,
+}
+
+impl StableGraphId {
+    /// Parse a [`StableGraphId`] in the format written by its [`Display`] impl.
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split('-');
+        let id = Self {
+            func_hash: u64::from_str_radix(parts.next()?, 16).ok()?,
+            block: parts.next()?.parse().ok()?,
+            statement_idx: parts.next()?.parse().ok()?,
+            seq: parts.next()?.parse().ok()?,
+        };
+        parts.next().is_none().then_some(id)
+    }
+}
+
+impl Display for StableGraphId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Self {
+            func_hash,
+            block,
+            statement_idx,
+            seq,
+        } = self;
+        write!(f, "{func_hash:016x}-{block}-{statement_idx}-{seq}")
+    }
+}
+
+/// One process image whose events were added to the [`Graphs`]: a process as started by `exec`,
+/// or as forked from its parent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Process {
+    /// [`None`] if the log is from a runtime that didn't record processes.
+    pub pid: Option<u32>,
+    pub origin: ProcessOrigin,
+    /// The index in [`Graphs::processes`] of the process image this one was forked from, or
+    /// `exec`ed by a child of.
+    pub parent: Option<usize>,
+}
+
+impl Display for Process {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "pid {}: ", ShortOption(self.pid.as_ref()))?;
+        match self.origin {
+            ProcessOrigin::Root => write!(f, "root"),
+            ProcessOrigin::Fork {
+                parent_pid,
+                fork_idx,
+            } => write!(f, "forked from pid {parent_pid} at its fork #{fork_idx}"),
+            ProcessOrigin::Exec { parent_pid } => {
+                write!(f, "exec'd in a child of pid {parent_pid}")
+            }
+        }
+    }
+}
+
 /// A collection of graphs describing the handling of one or more objects within the program.
 #[derive(Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Graphs {
@@ -369,6 +460,14 @@ pub struct Graphs {
 
     /// Lookup table for finding all nodes in all graphs that store to a particular MIR local.
     pub latest_assignment: HashMap<(FuncId, mir_loc::Local), (GraphId, NodeId)>,
+
+    /// The number of events the runtime dropped or sampled out instead of recording, from the
+    /// event log's [`Stats`](c2rust_analysis_rt::events::EventKind::Stats) footer.  If this is
+    /// nonzero, any graph may be missing nodes.
+    pub unrecorded_events: u64,
+
+    /// The process images whose events were added, each after its parent.
+    pub processes: Vec<Process>,
 }
 
 impl Graphs {
@@ -376,6 +475,41 @@ impl Graphs {
         Self::default()
     }
 
+    /// Compute the [`StableGraphId`] of each [`Graph`].
+    pub fn stable_ids(&self) -> IndexVec<GraphId, StableGraphId> {
+        let mut seqs = HashMap::<(FuncId, BasicBlock, usize), usize>::new();
+        self.graphs
+            .iter()
+            .map(|graph| {
+                let root = &graph.nodes[_ROOT_NODE];
+                let site = (root.function.id, root.block, root.statement_idx);
+                let seq = seqs.entry(site).or_default();
+                let (func_hash, _): (u64, u64) = root.function.id.0.into();
+                let id = StableGraphId {
+                    func_hash,
+                    block: root.block.as_u32(),
+                    statement_idx: root.statement_idx,
+                    seq: *seq,
+                };
+                *seq += 1;
+                id
+            })
+            .collect()
+    }
+
+    /// Find the [`Graph`] named by `s`, either a [`StableGraphId`] or a [`GraphId`] index.
+    pub fn resolve_graph_id(&self, s: &str) -> Option<GraphId> {
+        if let Some(stable_id) = StableGraphId::parse(s) {
+            return self
+                .stable_ids()
+                .iter_enumerated()
+                .find(|&(_, &id)| id == stable_id)
+                .map(|(graph_id, _)| graph_id);
+        }
+        let index = s.parse::<usize>().ok()?;
+        (index < self.graphs.len()).then(|| GraphId::from_usize(index))
+    }
+
     /// The [`Node::dest`] node of [`AddrOfLocal`]is always `Some(local)`
     /// and is used in determining the sources of subsequent PDG nodes.
     /// However, for the purposes of static analysis, it's undesired
@@ -406,3 +540,90 @@ impl Display for Graphs {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{func, node};
+
+    /// A graph with a single [`NodeKind::Alloc`] node at `block` of `function`.
+    fn alloc_graph(function: &Func, block: u32) -> Graph {
+        let mut root = node(function, NodeKind::Alloc(1), None);
+        root.block = BasicBlock::from_u32(block);
+        let mut graph = Graph::new(false);
+        graph.nodes.push(root);
+        graph
+    }
+
+    #[test]
+    fn stable_id_format() {
+        let id = StableGraphId {
+            func_hash: 0xabc,
+            block: 1,
+            statement_idx: 2,
+            seq: 3,
+        };
+        assert_eq!(id.to_string(), "0000000000000abc-1-2-3");
+        assert_eq!(StableGraphId::parse("0000000000000abc-1-2-3"), Some(id));
+        assert_eq!(StableGraphId::parse("abc-1-2-3"), Some(id));
+
+        assert_eq!(StableGraphId::parse("abc-1-2"), None);
+        assert_eq!(StableGraphId::parse("abc-1-2-3-4"), None);
+        assert_eq!(StableGraphId::parse("xyz-1-2-3"), None);
+        assert_eq!(StableGraphId::parse("abc-1--3"), None);
+        assert_eq!(StableGraphId::parse("3"), None);
+    }
+
+    #[test]
+    fn stable_ids_count_per_site() {
+        let f = func("f", 1);
+        let g = func("g", 2);
+        let mut graphs = Graphs::new();
+        graphs.graphs.push(alloc_graph(&f, 0));
+        graphs.graphs.push(alloc_graph(&g, 0));
+        graphs.graphs.push(alloc_graph(&f, 0));
+        graphs.graphs.push(alloc_graph(&f, 1));
+        let ids = graphs
+            .stable_ids()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            [
+                "0000000000000001-0-0-0",
+                "0000000000000002-0-0-0",
+                "0000000000000001-0-0-1",
+                "0000000000000001-1-0-0",
+            ]
+        );
+
+        // Graphs from other sites don't change a graph's stable ID, unlike its `GraphId`.
+        let mut other_run = Graphs::new();
+        other_run.graphs.push(alloc_graph(&g, 0));
+        other_run.graphs.push(alloc_graph(&g, 0));
+        other_run.graphs.push(alloc_graph(&f, 0));
+        other_run.graphs.push(alloc_graph(&f, 0));
+        assert_eq!(
+            other_run.stable_ids()[GraphId::from_u32(3)],
+            graphs.stable_ids()[GraphId::from_u32(2)]
+        );
+    }
+
+    #[test]
+    fn resolve_graph_ids() {
+        let f = func("f", 1);
+        let mut graphs = Graphs::new();
+        graphs.graphs.push(alloc_graph(&f, 0));
+        graphs.graphs.push(alloc_graph(&f, 0));
+
+        let resolve = |s: &str| graphs.resolve_graph_id(s).map(GraphId::as_u32);
+        assert_eq!(resolve("0000000000000001-0-0-1"), Some(1));
+        assert_eq!(resolve("1-0-0-0"), Some(0));
+        assert_eq!(resolve("1"), Some(1));
+        assert_eq!(resolve("2"), None);
+        assert_eq!(resolve("0000000000000001-0-0-2"), None);
+        assert_eq!(resolve("0000000000000002-0-0-0"), None);
+        assert_eq!(resolve("x"), None);
+    }
+}
@@ -20,6 +20,11 @@ pub struct NodeInfo {
 
     /// Whether the [`Node`] can be used as a `&mut`.
     pub unique: bool,
+
+    /// Whether the [`Node`] derives from a [`NodeKind::UnknownOrigin`], so that operations on
+    /// the object may be missing from the [`Graph`] and the other information here may be
+    /// incomplete.
+    pub uncertain: bool,
 }
 
 impl Display for NodeInfo {
@@ -73,6 +78,7 @@ fn set_flow_info(g: &mut Graph) {
         node.info = Some(NodeInfo {
             flows_to: cur_node_flow_info,
             unique: false,
+            uncertain: false,
         });
     }
 }
@@ -210,14 +216,33 @@ fn set_uniqueness(g: &mut Graph) {
     }
 }
 
+/// Compute and set [`NodeInfo::uncertain`].  If the event log is `incomplete`, every [`Node`] is
+/// uncertain.
+///
+/// Parents always precede their children, so a single forward pass suffices.
+fn set_uncertainty(g: &mut Graph, incomplete: bool) {
+    let mut uncertain = HashSet::new();
+    for (n_id, node) in g.nodes.iter_enumerated_mut() {
+        let is_uncertain = incomplete
+            || node.kind == NodeKind::UnknownOrigin
+            || matches!(node.source, Some(parent) if uncertain.contains(&parent));
+        if is_uncertain {
+            uncertain.insert(n_id);
+        }
+        node.info.as_mut().unwrap().uncertain = is_uncertain;
+    }
+}
+
 /// Initialize [`Node::info`] for each [`Node`].
 ///
 /// This includes all of the information answering questions of the form "is there a [`Node`] that
 /// this is an ancestor of with trait X", as well as whether the node can be considered unique.
 pub fn add_info(pdg: &mut Graphs) {
+    let incomplete = pdg.unrecorded_events > 0;
     for g in &mut pdg.graphs {
         set_flow_info(g);
         set_uniqueness(g);
+        set_uncertainty(g, incomplete);
     }
 }
 
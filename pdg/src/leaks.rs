@@ -0,0 +1,217 @@
+//! Report of heap allocations that were never freed, grouped by allocation site.
+//!
+//! This works directly from the event log rather than the graphs, since it only needs to match
+//! [`Alloc`](EventKind::Alloc) and [`Free`](EventKind::Free) events by address, and the sizes
+//! recorded by the allocation hooks.
+
+use c2rust_analysis_rt::events::{Event, EventKind, Pointer};
+use c2rust_analysis_rt::metadata::Metadata;
+use c2rust_analysis_rt::mir_loc::{Func, MirLocId};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+/// The allocations from one site that were never freed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeakSite {
+    pub func: Func,
+    pub basic_block_idx: usize,
+    pub statement_idx: usize,
+    pub span: String,
+    /// The number of leaked allocations.
+    pub count: usize,
+    /// The total size in bytes of the leaked allocations.
+    pub bytes: usize,
+}
+
+/// All [`LeakSite`]s, sorted with the largest total size first.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Leaks {
+    pub sites: Vec<LeakSite>,
+}
+
+impl Display for Leaks {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let count = self.sites.iter().map(|site| site.count).sum::<usize>();
+        let bytes = self.sites.iter().map(|site| site.bytes).sum::<usize>();
+        writeln!(f, "{count} allocations ({bytes} bytes) never freed")?;
+        for site in &self.sites {
+            let LeakSite {
+                func,
+                basic_block_idx,
+                statement_idx,
+                span,
+                count,
+                bytes,
+            } = site;
+            writeln!(
+                f,
+                "\t{bytes} bytes in {count} allocations at {span} (fn {func} bb{basic_block_idx}[{statement_idx}])"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Find the allocations in each of the process `images` that were still live at the end of the
+/// trace.  Each image, as split by [`process_images`](crate::builder::process_images), has its
+/// own address space, so allocations are matched to frees within each one.
+pub fn find_leaks(images: &[&[Event]], metadata: &Metadata) -> Leaks {
+    let mut leaked = Vec::new();
+    for events in images {
+        let mut live = HashMap::<Pointer, (MirLocId, usize)>::new();
+        for event in *events {
+            match event.kind {
+                EventKind::Alloc { size, ptr } if ptr != 0 => {
+                    live.insert(ptr, (event.mir_loc, size));
+                }
+                EventKind::Realloc {
+                    old_ptr,
+                    size,
+                    new_ptr,
+                } => {
+                    // A failed `realloc` leaves the old allocation in place.
+                    if new_ptr != 0 {
+                        live.remove(&old_ptr);
+                        live.insert(new_ptr, (event.mir_loc, size));
+                    }
+                }
+                EventKind::Free { ptr } => {
+                    live.remove(&ptr);
+                }
+                _ => {}
+            }
+        }
+        leaked.extend(live.into_values());
+    }
+
+    let mut sites = HashMap::<MirLocId, LeakSite>::new();
+    for (mir_loc, size) in leaked {
+        let loc = metadata.get(mir_loc);
+        let site = sites.entry(mir_loc).or_insert_with(|| LeakSite {
+            func: loc.func.clone(),
+            basic_block_idx: loc.basic_block_idx,
+            statement_idx: loc.statement_idx,
+            span: metadata.span(loc.metadata.span).to_string(),
+            count: 0,
+            bytes: 0,
+        });
+        site.count += 1;
+        site.bytes += size;
+    }
+
+    let mut sites = sites.into_values().collect::<Vec<_>>();
+    sites.sort_by(|a, b| {
+        (b.bytes, b.count)
+            .cmp(&(a.bytes, a.count))
+            .then_with(|| a.func.name.cmp(&b.func.name))
+            .then_with(|| {
+                (a.basic_block_idx, a.statement_idx).cmp(&(b.basic_block_idx, b.statement_idx))
+            })
+    });
+    Leaks { sites }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{event, func, loc, metadata};
+
+    #[test]
+    fn leaks_grouped_by_site() {
+        let metadata = metadata(vec![
+            loc(&func("f", 1), 1, None, None),
+            loc(&func("g", 2), 2, None, None),
+            loc(&func("h", 3), 3, None, None),
+        ]);
+        let events = [
+            event(0, EventKind::Alloc { size: 8, ptr: 0x10 }),
+            event(0, EventKind::Alloc { size: 8, ptr: 0x20 }),
+            event(0, EventKind::Alloc { size: 8, ptr: 0x30 }),
+            event(
+                1,
+                EventKind::Alloc {
+                    size: 100,
+                    ptr: 0x40,
+                },
+            ),
+            event(2, EventKind::Alloc { size: 4, ptr: 0x50 }),
+            event(0, EventKind::Free { ptr: 0x10 }),
+            event(2, EventKind::Free { ptr: 0x50 }),
+            // A failed allocation and a free of null leak nothing.
+            event(2, EventKind::Alloc { size: 4, ptr: 0 }),
+            event(2, EventKind::Free { ptr: 0 }),
+        ];
+
+        let leaks = find_leaks(&[&events], &metadata);
+        let sites = leaks
+            .sites
+            .iter()
+            .map(|site| (site.func.name.as_str(), site.count, site.bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(sites, [("g", 1, 100), ("f", 2, 16)]);
+    }
+
+    #[test]
+    fn failed_realloc_keeps_old_allocation() {
+        let metadata = metadata(vec![
+            loc(&func("f", 1), 1, None, None),
+            loc(&func("g", 2), 2, None, None),
+        ]);
+        let events = [
+            event(0, EventKind::Alloc { size: 8, ptr: 0x10 }),
+            event(0, EventKind::Alloc { size: 8, ptr: 0x20 }),
+            // `g` grows the first allocation, but fails to grow the second.
+            event(
+                1,
+                EventKind::Realloc {
+                    old_ptr: 0x10,
+                    size: 32,
+                    new_ptr: 0x30,
+                },
+            ),
+            event(
+                1,
+                EventKind::Realloc {
+                    old_ptr: 0x20,
+                    size: 32,
+                    new_ptr: 0,
+                },
+            ),
+        ];
+
+        let leaks = find_leaks(&[&events], &metadata);
+        let sites = leaks
+            .sites
+            .iter()
+            .map(|site| (site.func.name.as_str(), site.count, site.bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(sites, [("g", 1, 32), ("f", 1, 8)]);
+    }
+
+    #[test]
+    fn leaks_per_process() {
+        let metadata = metadata(vec![
+            loc(&func("f", 1), 1, None, None),
+            loc(&func("g", 2), 2, None, None),
+        ]);
+        let parent = [
+            event(0, EventKind::Alloc { size: 8, ptr: 0x10 }),
+            event(0, EventKind::Alloc { size: 8, ptr: 0x20 }),
+            event(0, EventKind::Free { ptr: 0x10 }),
+        ];
+        // The child reuses the address the parent freed, and frees its copy of the parent's other
+        // allocation, which doesn't free it in the parent.
+        let child = [
+            event(1, EventKind::Alloc { size: 4, ptr: 0x10 }),
+            event(1, EventKind::Free { ptr: 0x20 }),
+        ];
+
+        let leaks = find_leaks(&[&parent, &child], &metadata);
+        let sites = leaks
+            .sites
+            .iter()
+            .map(|site| (site.func.name.as_str(), site.count, site.bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(sites, [("f", 1, 8), ("g", 1, 4)]);
+    }
+}
@@ -19,7 +19,18 @@ extern crate rustc_target;
 
 pub mod assert;
 pub mod builder;
+pub mod compare;
+pub mod dot;
+pub mod export;
+pub mod extent;
+pub mod fixture;
+pub mod flame;
 pub mod graph;
 pub mod info;
+pub mod leaks;
 pub mod query;
+pub mod serve;
+pub mod summary;
+#[cfg(test)]
+mod test_util;
 pub mod util;
@@ -18,13 +18,27 @@ extern crate rustc_span;
 extern crate rustc_target;
 
 use c2rust_analysis_rt::{events::Event, metadata::Metadata};
-use c2rust_pdg::builder::{construct_pdg, read_event_log, read_metadata};
-use c2rust_pdg::graph::Graphs;
+use c2rust_pdg::assert::check_expectations;
+use c2rust_pdg::builder::{
+    construct_pdg_from_logs, process_images, read_metadata, read_process_logs, EventClass,
+};
+use c2rust_pdg::compare::{compare, read_static_report};
+use c2rust_pdg::dot::Dot;
+use c2rust_pdg::export::{write_graphml, write_neo4j_csv};
+use c2rust_pdg::extent::find_extents;
+use c2rust_pdg::fixture::write_fixture;
+use c2rust_pdg::flame::{alloc_stacks, Weight};
+use c2rust_pdg::graph::{Graphs, NodeId};
 use c2rust_pdg::info::add_info;
-use clap::{Parser, ValueEnum};
-use color_eyre::eyre;
+use c2rust_pdg::leaks::find_leaks;
+use c2rust_pdg::query::AncestorChain;
+use c2rust_pdg::serve::serve;
+use c2rust_pdg::summary::{aggregate, summarize};
+use clap::{Parser, Subcommand, ValueEnum};
+use color_eyre::eyre::{self, ensure, eyre};
 use std::{
     fmt::{self, Display, Formatter},
+    io::{BufWriter, Write},
     path::{Path, PathBuf},
     sync::Once,
 };
@@ -37,6 +51,9 @@ pub enum ToPrint {
     LatestAssignments,
     WritePermissions,
     Metadata,
+    Summary,
+    Dot,
+    Processes,
 }
 
 impl Display for ToPrint {
@@ -46,25 +63,38 @@ impl Display for ToPrint {
 }
 
 pub struct Pdg {
-    pub events: Vec<Event>,
+    /// The event log of each process, the root process's first.
+    pub logs: Vec<Vec<Event>>,
     pub metadata: Metadata,
     pub graphs: Graphs,
 }
 
 impl Pdg {
-    pub fn new(metadata_path: &Path, event_log_path: &Path) -> eyre::Result<Self> {
-        let events = read_event_log(event_log_path)?;
+    pub fn new(
+        metadata_path: &Path,
+        event_log_path: &Path,
+        only: &[EventClass],
+    ) -> eyre::Result<Self> {
+        let logs = read_process_logs(event_log_path, only)?;
         let metadata = read_metadata(metadata_path)?;
-        let mut graphs = construct_pdg(&events, &metadata);
+        let mut graphs = construct_pdg_from_logs(
+            &logs.iter().map(Vec::as_slice).collect::<Vec<_>>(),
+            &metadata,
+        );
         add_info(&mut graphs);
         graphs.remove_addr_of_local_sources();
         Ok(Self {
-            events,
+            logs,
             metadata,
             graphs,
         })
     }
 
+    /// The events of each process image, for the reports that scan events directly.
+    pub fn process_images(&self) -> Vec<&[Event]> {
+        process_images(&self.logs.iter().map(Vec::as_slice).collect::<Vec<_>>())
+    }
+
     pub fn repr<'a>(&'a self, to_print: &'a [ToPrint]) -> PdgRepr<'a> {
         PdgRepr {
             pdg: self,
@@ -83,7 +113,7 @@ impl Display for PdgRepr<'_> {
         let Self {
             pdg:
                 Pdg {
-                    events,
+                    logs,
                     metadata,
                     graphs,
                 },
@@ -96,13 +126,22 @@ impl Display for PdgRepr<'_> {
         }
 
         if should_print(ToPrint::Events) {
-            for event in events.iter() {
+            for event in logs.iter().flatten() {
                 let mir_loc = metadata.get(event.mir_loc);
                 let kind = &event.kind;
                 writeln!(f, "{mir_loc:?} -> {kind:?}")?;
             }
         }
 
+        if should_print(ToPrint::Processes) {
+            let mut depths = Vec::with_capacity(graphs.processes.len());
+            for process in &graphs.processes {
+                let depth = process.parent.map_or(0, |parent| depths[parent] + 1);
+                depths.push(depth);
+                writeln!(f, "{:indent$}{process}", "", indent = 2 * depth)?;
+            }
+        }
+
         if should_print(ToPrint::LatestAssignments) {
             for ((func_hash, local), p) in &graphs.latest_assignment {
                 let func = &metadata.functions[func_hash];
@@ -126,6 +165,14 @@ impl Display for PdgRepr<'_> {
             }
         }
 
+        if should_print(ToPrint::Summary) {
+            write!(f, "{}", summarize(graphs))?;
+        }
+
+        if should_print(ToPrint::Dot) {
+            write!(f, "{}", Dot(graphs))?;
+        }
+
         if should_print(ToPrint::Counts) {
             let num_graphs = graphs.graphs.len();
             let num_nodes = graphs
@@ -157,9 +204,107 @@ pub struct Args {
     #[clap(long, value_parser, default_value = "graphs")]
     print: Vec<ToPrint>,
 
+    /// Only keep these classes of events while reading the event log, besides those that derive
+    /// pointers from other pointers, which are always kept.  By default, all events are kept.
+    #[clap(long, value_enum, value_delimiter = ',')]
+    only: Vec<EventFilter>,
+
     /// Where to save a serialized copy of the PDG.
     #[clap(long, value_parser)]
     output: Option<PathBuf>,
+
+    /// Path to a JSON report of statically inferred permissions, as written by
+    /// `c2rust-analyze --dump-local-perms`.  Every pointer that dynamically needed a permission
+    /// that the static analysis didn't infer is printed along with its source location.
+    #[clap(long, value_parser)]
+    compare_static: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Query the PDG instead of printing it.
+    #[clap(subcommand)]
+    Query(Query),
+    /// Export the PDG for use with graph databases and other graph tooling.
+    Export {
+        #[clap(long, value_enum)]
+        format: ExportFormat,
+        /// The file to write, or for `neo4j-csv`, the directory to write `nodes.csv` and
+        /// `relationships.csv` into.
+        output: PathBuf,
+    },
+    /// Serve the PDG over a JSON HTTP API until killed.
+    Serve {
+        /// The address to listen on.
+        #[clap(long, default_value = "127.0.0.1:8000")]
+        addr: String,
+    },
+    /// Report allocations that were never freed, grouped by allocation site.
+    Leaks,
+    /// Report how far pointer arithmetic reached into the allocations from each allocation site,
+    /// and how many offsets went out of bounds.
+    Extents,
+    /// Print allocations in the folded-stack format read by `inferno-flamegraph` and
+    /// `flamegraph.pl`, grouped by allocation site and caller chain.
+    Flame {
+        /// What each allocation adds to the width of its stack.
+        #[clap(long, value_enum, default_value = "bytes")]
+        weight: FlameWeight,
+    },
+    /// Check the `#[c2rust_pdg::expect(...)]` annotations in the instrumented source against
+    /// the permissions each annotated local required at run time, failing if any don't match.
+    Assert,
+    /// Write graphs as a `c2rust-analyze` FileCheck test, which checks that the static analysis
+    /// infers at least the permissions each pointer required at run time.
+    Fixture {
+        /// The test file to write, usually in `c2rust-analyze/tests/filecheck`.
+        output: PathBuf,
+        /// The graphs to write, as stable graph IDs or graph indices.  By default, all graphs are
+        /// written.
+        graphs: Vec<String>,
+    },
+    /// Union the permissions each local required at run time across the `--event-log` trace and
+    /// the traces in `event_logs`, all of the same instrumented program, and print the result as
+    /// with `--print summary`.
+    Aggregate {
+        /// Further event logs to include, such as from the other runs of a test suite.
+        event_logs: Vec<PathBuf>,
+        /// Also write the aggregated summary as JSON to this file.
+        #[clap(long)]
+        json: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Graphml,
+    Neo4jCsv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EventFilter {
+    Loads,
+    Stores,
+    Frees,
+    Values,
+    Ints,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FlameWeight {
+    Count,
+    Bytes,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Query {
+    /// Print the chain of operations from the original allocation to a node, each with its
+    /// source location.  `graph` is a stable graph ID, as shown by `--print dot`, or a graph
+    /// index, and node indices are as printed by `--print graphs`.
+    Ancestors { graph: String, node: usize },
 }
 
 static INIT: Once = Once::new();
@@ -182,10 +327,124 @@ pub fn init() {
 fn main() -> eyre::Result<()> {
     init();
     let args = Args::parse();
-    let pdg = Pdg::new(&args.metadata, &args.event_log)?;
+    let only = args
+        .only
+        .iter()
+        .map(|filter| match filter {
+            EventFilter::Loads => EventClass::Loads,
+            EventFilter::Stores => EventClass::Stores,
+            EventFilter::Frees => EventClass::Frees,
+            EventFilter::Values => EventClass::Values,
+            EventFilter::Ints => EventClass::Ints,
+        })
+        .collect::<Vec<_>>();
+    let pdg = Pdg::new(&args.metadata, &args.event_log, &only)?;
     pdg.graphs.assert_all_tests();
-    let repr = pdg.repr(&args.print);
-    println!("{repr}");
+    match args.command {
+        None => {
+            let repr = pdg.repr(&args.print);
+            println!("{repr}");
+        }
+        Some(Command::Query(Query::Ancestors { graph, node })) => {
+            let graph_id = pdg.graphs.resolve_graph_id(&graph);
+            let graph_id = graph_id.ok_or_else(|| eyre!("no graph {graph}"))?;
+            let graph = &pdg.graphs.graphs[graph_id];
+            ensure!(node < graph.nodes.len(), "no node n[{node}] in this graph");
+            let chain = AncestorChain::new(graph, NodeId::from_usize(node), &pdg.metadata);
+            print!("{chain}");
+        }
+        Some(Command::Export { format, output }) => match format {
+            ExportFormat::Graphml => {
+                let mut f = BufWriter::new(fs_err::File::create(output)?);
+                write_graphml(&pdg.graphs, &mut f)?;
+                f.flush()?;
+            }
+            ExportFormat::Neo4jCsv => {
+                fs_err::create_dir_all(&output)?;
+                let mut nodes = BufWriter::new(fs_err::File::create(output.join("nodes.csv"))?);
+                let mut relationships =
+                    BufWriter::new(fs_err::File::create(output.join("relationships.csv"))?);
+                write_neo4j_csv(&pdg.graphs, &mut nodes, &mut relationships)?;
+                nodes.flush()?;
+                relationships.flush()?;
+            }
+        },
+        Some(Command::Serve { addr }) => {
+            serve(addr.as_str(), &pdg.graphs, &pdg.metadata)?;
+        }
+        Some(Command::Leaks) => {
+            print!("{}", find_leaks(&pdg.process_images(), &pdg.metadata));
+        }
+        Some(Command::Extents) => {
+            print!("{}", find_extents(&pdg.process_images(), &pdg.metadata));
+        }
+        Some(Command::Flame { weight }) => {
+            let weight = match weight {
+                FlameWeight::Count => Weight::Count,
+                FlameWeight::Bytes => Weight::Bytes,
+            };
+            print!(
+                "{}",
+                alloc_stacks(&pdg.process_images(), &pdg.metadata).folded(weight)
+            );
+        }
+        Some(Command::Assert) => {
+            let failures = check_expectations(&summarize(&pdg.graphs), &pdg.metadata);
+            for failure in &failures {
+                println!("{failure}");
+            }
+            let total = pdg.metadata.expectations.len();
+            ensure!(
+                failures.is_empty(),
+                "{} of {total} expectations failed",
+                failures.len()
+            );
+            println!("all {total} expectations hold");
+        }
+        Some(Command::Fixture { output, graphs }) => {
+            let selected = if graphs.is_empty() {
+                pdg.graphs.graphs.indices().collect()
+            } else {
+                graphs
+                    .iter()
+                    .map(|graph| {
+                        let graph_id = pdg.graphs.resolve_graph_id(graph);
+                        graph_id.ok_or_else(|| eyre!("no graph {graph}"))
+                    })
+                    .collect::<eyre::Result<Vec<_>>>()?
+            };
+            let mut f = BufWriter::new(fs_err::File::create(&output)?);
+            write_fixture(&pdg.graphs, &selected, &mut f)?;
+            f.flush()?;
+            if let Some(name) = output.file_stem() {
+                println!(
+                    "add `{}` to `define_tests!` in `c2rust-analyze/tests/filecheck.rs` to run it",
+                    name.to_string_lossy()
+                );
+            }
+        }
+        Some(Command::Aggregate { event_logs, json }) => {
+            let mut all = vec![summarize(&pdg.graphs)];
+            for event_log in &event_logs {
+                let run = Pdg::new(&args.metadata, event_log, &only)?;
+                all.push(summarize(&run.graphs));
+            }
+            let summaries = aggregate(all);
+            print!("{summaries}");
+            if let Some(json) = json {
+                let mut f = BufWriter::new(fs_err::File::create(json)?);
+                serde_json::to_writer_pretty(&mut f, &summaries)?;
+                f.flush()?;
+            }
+        }
+    }
+
+    if let Some(report_path) = args.compare_static {
+        let report = read_static_report(&report_path)?;
+        for disagreement in compare(&summarize(&pdg.graphs), &report) {
+            println!("{disagreement}");
+        }
+    }
 
     if let Some(output_path) = args.output {
         let f = std::fs::File::create(output_path)?;
@@ -345,7 +604,7 @@ mod tests {
         let status = cmd.status()?;
         ensure!(status.success(), eyre!("{cmd:?} failed: {status}"));
 
-        let pdg = Pdg::new(&metadata_path, &event_log_path)?;
+        let pdg = Pdg::new(&metadata_path, &event_log_path, &[])?;
         pdg.graphs.assert_all_tests();
         let repr = pdg.repr(to_print);
         Ok(repr.to_string())
@@ -11,9 +11,15 @@
 //! and be able to test if certain changes have any effect on the PDG output.
 //! We are thinking about using [`insta`](https://insta.rs/) for this.
 
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+use c2rust_analysis_rt::metadata::Metadata;
+use c2rust_analysis_rt::mir_loc::FuncId;
+use c2rust_analysis_rt::span::{DisplaySpan, SpanId};
 use linked_hash_set::LinkedHashSet;
 
-use crate::graph::{Graph, NodeId, NodeKind};
+use crate::graph::{Graph, Node, NodeId, NodeKind};
 
 impl Graph {
     /// Query an object [`Graph`] to determine which of its [`Node`]s (returned as [`NodeId`]s)
@@ -50,4 +56,92 @@ impl Graph {
         }
         needs_write.into_iter()
     }
+
+    /// The chain of [`Node`]s from the root of the [`Graph`] to `node_id`, following
+    /// [`Node::source`] edges.  The first element is the root, usually the allocation of the
+    /// object, and the last is `node_id` itself.
+    ///
+    /// [`Node`]: crate::graph::Node
+    /// [`Node::source`]: crate::graph::Node::source
+    pub fn ancestors(&self, node_id: NodeId) -> Vec<NodeId> {
+        let mut chain = vec![node_id];
+        let mut cur = node_id;
+        while let Some(source) = self.nodes[cur].source {
+            chain.push(source);
+            cur = source;
+        }
+        chain.reverse();
+        chain
+    }
+}
+
+/// The [`Graph::ancestors`] of a node, displayed one per line along with the source location of
+/// each operation.
+pub struct AncestorChain<'a> {
+    graph: &'a Graph,
+    chain: Vec<NodeId>,
+    metadata: &'a Metadata,
+    spans: HashMap<(FuncId, usize, usize), Option<SpanId>>,
+}
+
+impl<'a> AncestorChain<'a> {
+    pub fn new(graph: &'a Graph, node_id: NodeId, metadata: &'a Metadata) -> Self {
+        let spans = metadata
+            .locs
+            .iter()
+            .map(|loc| {
+                let key = (loc.func.id, loc.basic_block_idx, loc.statement_idx);
+                (key, loc.metadata.span)
+            })
+            .collect();
+        Self {
+            graph,
+            chain: graph.ancestors(node_id),
+            metadata,
+            spans,
+        }
+    }
+
+    /// Iterate over the chain, from the root to the queried node, along with the source location
+    /// of each operation.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &'a Node, DisplaySpan<'a>)> + '_ {
+        self.chain.iter().map(|&node_id| {
+            let node = &self.graph.nodes[node_id];
+            let key = (node.function.id, node.block.as_usize(), node.statement_idx);
+            let span = self.spans.get(&key).copied().flatten();
+            (node_id, node, self.metadata.span(span))
+        })
+    }
+}
+
+impl Display for AncestorChain<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (node_id, node, span) in self.iter() {
+            writeln!(f, "{span}: {node_id}: {node}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{func, node};
+
+    #[test]
+    fn ancestors_from_root() {
+        let f = func("f", 1);
+        let mut graph = Graph::new(false);
+        graph.nodes.push(node(&f, NodeKind::Alloc(1), None));
+        graph.nodes.push(node(&f, NodeKind::Copy, Some(0)));
+        graph.nodes.push(node(&f, NodeKind::Offset(1), Some(1)));
+        graph.nodes.push(node(&f, NodeKind::Copy, Some(0)));
+        graph.nodes.push(node(&f, NodeKind::StoreAddr, Some(2)));
+        let chain = graph
+            .ancestors(NodeId::from_u32(4))
+            .into_iter()
+            .map(|id| id.as_u32())
+            .collect::<Vec<_>>();
+        assert_eq!(chain, [0, 1, 2, 4]);
+    }
 }
@@ -0,0 +1,231 @@
+//! A small JSON-over-HTTP API for exploring a constructed PDG, for use by web-based visualizers
+//! and notebooks.
+//!
+//! Only `GET` requests are supported, and each connection serves a single request.  The routes
+//! are:
+//!
+//! * `/graphs`: a summary of every graph, with its index, [`StableGraphId`], root operation, and
+//!   node count.
+//! * `/graphs/<graph>`: the full [`Graph`], in the same form as its serialized copy.
+//! * `/graphs/<graph>/needs-write`: the nodes that need write permission.
+//! * `/graphs/<graph>/ancestors/<node>`: the chain of nodes from the root to `<node>`, each with
+//!   its source location.
+//!
+//! `<graph>` is either a [`StableGraphId`] or a graph index, though only the former stays valid
+//! across runs.
+
+use crate::graph::{Graph, GraphId, Graphs, NodeId, StableGraphId};
+use crate::query::AncestorChain;
+use c2rust_analysis_rt::metadata::Metadata;
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// An HTTP status and JSON body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        let message = message.into();
+        Self {
+            status,
+            body: json!({ "error": message }),
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+fn parse_index(s: &str, len: usize, what: &str) -> Result<usize, Response> {
+    match s.parse::<usize>() {
+        Ok(i) if i < len => Ok(i),
+        Ok(i) => Err(Response::error(404, format!("no {what} {i}"))),
+        Err(_) => Err(Response::error(400, format!("invalid {what} index {s:?}"))),
+    }
+}
+
+fn graph_summary(graph_id: GraphId, stable_id: StableGraphId, graph: &Graph) -> Value {
+    let root = graph.nodes.raw.first();
+    json!({
+        "id": graph_id.as_usize(),
+        "stable_id": stable_id.to_string(),
+        "is_null": graph.is_null,
+        "num_nodes": graph.nodes.len(),
+        "root": root.map(|node| node.kind.to_string()),
+        "function": root.map(|node| node.function.name.clone()),
+    })
+}
+
+/// Answer a `GET` request for `path`.
+pub fn route(graphs: &Graphs, metadata: &Metadata, path: &str) -> Response {
+    let path = path.split('?').next().unwrap_or_default();
+    let parts = path
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>();
+    let result = (|| {
+        let graph = |s: &str| match graphs.resolve_graph_id(s) {
+            Some(graph_id) => Ok(&graphs.graphs[graph_id]),
+            None => Err(Response::error(404, format!("no graph {s:?}"))),
+        };
+        let body = match parts[..] {
+            ["graphs"] => graphs
+                .graphs
+                .iter_enumerated()
+                .zip(graphs.stable_ids())
+                .map(|((graph_id, graph), stable_id)| graph_summary(graph_id, stable_id, graph))
+                .collect::<Value>(),
+            ["graphs", g] => {
+                serde_json::to_value(graph(g)?).map_err(|e| Response::error(500, e.to_string()))?
+            }
+            ["graphs", g, "needs-write"] => graph(g)?
+                .needs_write_permission()
+                .map(|node_id| node_id.as_usize())
+                .collect::<Value>(),
+            ["graphs", g, "ancestors", n] => {
+                let graph = graph(g)?;
+                let node_id = NodeId::from_usize(parse_index(n, graph.nodes.len(), "node")?);
+                AncestorChain::new(graph, node_id, metadata)
+                    .iter()
+                    .map(|(node_id, node, span)| {
+                        json!({
+                            "id": node_id.as_usize(),
+                            "span": span.to_string(),
+                            "node": node,
+                        })
+                    })
+                    .collect::<Value>()
+            }
+            _ => return Err(Response::error(404, format!("no route for {path:?}"))),
+        };
+        Ok(body)
+    })();
+    match result {
+        Ok(body) => Response::ok(body),
+        Err(response) => response,
+    }
+}
+
+fn handle(stream: TcpStream, graphs: &Graphs, metadata: &Metadata) -> io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers; no request needs them.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut words = request_line.split_whitespace();
+    let response = match (words.next(), words.next()) {
+        (Some("GET"), Some(path)) => {
+            info!("GET {path}");
+            route(graphs, metadata, path)
+        }
+        (Some(_), Some(_)) => Response::error(405, "only GET is supported"),
+        _ => Response::error(400, "malformed request"),
+    };
+
+    let body = response.body.to_string();
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        response.status,
+        reason(response.status),
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Serve `graphs` over HTTP on `addr` until the process is killed.
+pub fn serve(addr: impl ToSocketAddrs, graphs: &Graphs, metadata: &Metadata) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("serving PDG on http://{}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| handle(stream, graphs, metadata));
+        if let Err(e) = result {
+            warn!("error handling request: {e}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::NodeKind;
+    use crate::test_util::{func, metadata, node};
+
+    #[test]
+    fn routes() {
+        let f = func("f", 1);
+        let mut graph = Graph::new(false);
+        graph.nodes.push(node(&f, NodeKind::Alloc(1), None));
+        graph.nodes.push(node(&f, NodeKind::Copy, Some(0)));
+        graph.nodes.push(node(&f, NodeKind::StoreAddr, Some(1)));
+        let mut graphs = Graphs::new();
+        // A second graph from the same allocation site.
+        graphs.graphs.push(graph.clone());
+        graphs.graphs.push(graph);
+        let metadata = metadata(Vec::new());
+        let get = |path| route(&graphs, &metadata, path);
+
+        assert_eq!(
+            get("/graphs").body[0],
+            json!({
+                "id": 0,
+                "stable_id": "0000000000000001-0-0-0",
+                "is_null": false,
+                "num_nodes": 3,
+                "root": "alloc",
+                "function": "f",
+            })
+        );
+        assert_eq!(
+            get("/graphs").body[1]["stable_id"],
+            "0000000000000001-0-0-1"
+        );
+        assert_eq!(get("/graphs/0/needs-write").body, json!([2, 1, 0]));
+        let ancestors = get("/graphs/0/ancestors/2").body;
+        let ids = ancestors
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["id"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [0, 1, 2]);
+        assert_eq!(
+            get("/graphs/0000000000000001-0-0-0/needs-write").body,
+            json!([2, 1, 0])
+        );
+        assert_eq!(get("/graphs/2").status, 404);
+        assert_eq!(get("/graphs/0000000000000001-0-0-2").status, 404);
+        assert_eq!(get("/graphs/0/ancestors/x").status, 400);
+        assert_eq!(get("/nothing").status, 404);
+    }
+}
@@ -0,0 +1,237 @@
+//! Per-function summaries of the permissions that pointers required at run time.
+//!
+//! For each function and each of its pointer-typed locals (including parameters), this records
+//! whether any pointer stored in that local was ever used, directly or through pointers derived
+//! from it, to write, to offset, or to free.  This matches the granularity of the static analysis
+//! in `c2rust-analyze`, so the two can be compared directly.
+
+use crate::graph::{Graph, Graphs, Node, NodeId, NodeKind};
+use c2rust_analysis_rt::mir_loc::{Func, FuncId, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+
+/// The permissions required by some pointer over the course of the trace.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PermissionSummary {
+    /// The pointer was written through.
+    pub write: bool,
+    /// The pointer was offset by a nonzero amount.
+    pub offset: bool,
+    /// The pointer was freed.
+    pub free: bool,
+    /// The pointer's origin wasn't observed, so some of its uses may be missing from the trace.
+    pub uncertain: bool,
+}
+
+impl PermissionSummary {
+    fn for_node(node: &Node) -> Self {
+        let kind = &node.kind;
+        Self {
+            write: matches!(kind, NodeKind::StoreAddr),
+            offset: matches!(kind, NodeKind::Offset(x) if *x != 0),
+            free: matches!(kind, NodeKind::Free | NodeKind::Realloc),
+            uncertain: node.info.as_ref().map_or(false, |info| info.uncertain),
+        }
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self {
+            write: self.write || other.write,
+            offset: self.offset || other.offset,
+            free: self.free || other.free,
+            uncertain: self.uncertain || other.uncertain,
+        }
+    }
+
+    /// The permissions in `self` that are not in `other`.  The result is uncertain if `self` is.
+    pub fn difference(self, other: Self) -> Self {
+        Self {
+            write: self.write && !other.write,
+            offset: self.offset && !other.offset,
+            free: self.free && !other.free,
+            uncertain: self.uncertain,
+        }
+    }
+
+    /// Whether any permission is required at all.
+    pub fn is_empty(&self) -> bool {
+        !(self.write || self.offset || self.free)
+    }
+}
+
+impl Display for PermissionSummary {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Self {
+            write,
+            offset,
+            free,
+            uncertain,
+        } = *self;
+        let names = [(write, "write"), (offset, "offset"), (free, "free")]
+            .into_iter()
+            .filter_map(|(set, name)| set.then_some(name))
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            write!(f, "-")?;
+        } else {
+            write!(f, "{}", names.join(" "))?;
+        }
+        if uncertain {
+            write!(f, " (uncertain)")?;
+        }
+        Ok(())
+    }
+}
+
+/// The [`PermissionSummary`] of each local of one function.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionSummary {
+    pub function: Func,
+    pub locals: BTreeMap<Local, PermissionSummary>,
+}
+
+/// [`FunctionSummary`]s for all functions that appear in the PDG, sorted by name.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Summaries {
+    pub functions: Vec<FunctionSummary>,
+}
+
+impl Display for Summaries {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for FunctionSummary { function, locals } in &self.functions {
+            writeln!(f, "fn {function}:")?;
+            for (local, perms) in locals {
+                writeln!(f, "\t{local:?}: {perms}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compute the permissions required by each [`Node`](crate::graph::Node) of `graph`, including
+/// those required by the nodes derived from it.
+pub(crate) fn node_permissions(graph: &Graph) -> HashMap<NodeId, PermissionSummary> {
+    let mut perms = graph
+        .nodes
+        .iter_enumerated()
+        .map(|(id, node)| (id, PermissionSummary::for_node(node)))
+        .collect::<HashMap<_, _>>();
+    // Nodes are topologically sorted, so visiting them in reverse sees each node's children
+    // before the node itself.
+    for (id, node) in graph.nodes.iter_enumerated().rev() {
+        if let Some(source) = node.source {
+            let child = perms[&id];
+            let parent = perms.get_mut(&source).unwrap();
+            *parent = parent.union(child);
+        }
+    }
+    perms
+}
+
+/// Summarize the permissions required by the pointers stored in each local of each function.
+pub fn summarize(graphs: &Graphs) -> Summaries {
+    let mut funcs = HashMap::<FuncId, FunctionSummary>::new();
+    for graph in &graphs.graphs {
+        let perms = node_permissions(graph);
+        for (id, node) in graph.nodes.iter_enumerated() {
+            let dest = match node.dest {
+                Some(ref dest) if dest.projection.is_empty() => dest,
+                _ => continue,
+            };
+            let summary = funcs
+                .entry(node.function.id)
+                .or_insert_with(|| FunctionSummary {
+                    function: node.function.clone(),
+                    locals: BTreeMap::new(),
+                });
+            let local_perms = summary.locals.entry(dest.local).or_default();
+            *local_perms = local_perms.union(perms[&id]);
+        }
+    }
+
+    let mut functions = funcs.into_values().collect::<Vec<_>>();
+    functions.sort_by(|a, b| a.function.name.cmp(&b.function.name));
+    Summaries { functions }
+}
+
+/// Union the [`Summaries`] of several traces of the same program, such as the runs of a test
+/// suite, into one covering every permission any of them observed for each local.
+pub fn aggregate(all: impl IntoIterator<Item = Summaries>) -> Summaries {
+    let mut funcs = HashMap::<FuncId, FunctionSummary>::new();
+    for summaries in all {
+        for FunctionSummary { function, locals } in summaries.functions {
+            let summary = funcs.entry(function.id).or_insert_with(|| FunctionSummary {
+                function,
+                locals: BTreeMap::new(),
+            });
+            for (local, perms) in locals {
+                let local_perms = summary.locals.entry(local).or_default();
+                *local_perms = local_perms.union(perms);
+            }
+        }
+    }
+
+    let mut functions = funcs.into_values().collect::<Vec<_>>();
+    functions.sort_by(|a, b| a.function.name.cmp(&b.function.name));
+    Summaries { functions }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{func, node, perms, place};
+
+    #[test]
+    fn summarize_propagates_to_ancestors() {
+        let f = func("f", 1);
+        let node_to = |dest: Option<u32>, kind, source| Node {
+            dest: dest.and_then(place),
+            ..node(&f, kind, source)
+        };
+        let mut graph = Graph::new(false);
+        graph.nodes.push(node_to(Some(1), NodeKind::Alloc(1), None));
+        graph.nodes.push(node_to(Some(2), NodeKind::Copy, Some(0)));
+        graph
+            .nodes
+            .push(node_to(Some(3), NodeKind::Offset(1), Some(1)));
+        graph
+            .nodes
+            .push(node_to(None, NodeKind::StoreAddr, Some(2)));
+        graph.nodes.push(node_to(None, NodeKind::Free, Some(0)));
+        let mut graphs = Graphs::new();
+        graphs.graphs.push(graph);
+
+        let summaries = summarize(&graphs);
+        assert_eq!(summaries.functions.len(), 1);
+        let locals = &summaries.functions[0].locals;
+        let perms = |local: u32| locals[&Local::from(local)].to_string();
+        assert_eq!(perms(1), "write offset free");
+        assert_eq!(perms(2), "write offset");
+        assert_eq!(perms(3), "write");
+    }
+
+    #[test]
+    fn aggregate_unions_traces() {
+        let summaries = |function: Func, locals: &[(u32, &str)]| Summaries {
+            functions: vec![FunctionSummary {
+                function,
+                locals: locals
+                    .iter()
+                    .map(|&(local, names)| (Local::from(local), perms(names)))
+                    .collect(),
+            }],
+        };
+
+        let aggregated = aggregate([
+            summaries(func("g", 2), &[(1, "write")]),
+            summaries(func("f", 1), &[(1, "write"), (2, "offset")]),
+            summaries(func("f", 1), &[(1, "offset"), (3, "write")]),
+        ]);
+        assert_eq!(
+            aggregated.to_string(),
+            "fn f:\n\t_1: write offset\n\t_2: offset\n\t_3: write\n\
+             fn g:\n\t_1: write\n"
+        );
+    }
+}
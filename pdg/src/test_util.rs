@@ -0,0 +1,94 @@
+//! Fixtures shared by the unit tests of the PDG modules, for building small traces and graphs by
+//! hand.
+
+use crate::graph::{Node, NodeId, NodeKind};
+use crate::summary::PermissionSummary;
+use c2rust_analysis_rt::events::{Event, EventKind};
+use c2rust_analysis_rt::metadata::Metadata;
+use c2rust_analysis_rt::mir_loc::{
+    DefPathHash, EventMetadata, Func, FuncId, MirLoc, MirLocId, MirPlace,
+};
+use rustc_middle::mir::BasicBlock;
+use std::collections::HashMap;
+
+/// A function named `name`.  Functions with different `hash`es are different functions.
+pub fn func(name: &str, hash: u64) -> Func {
+    Func {
+        id: FuncId(DefPathHash::from((hash, 2))),
+        name: name.into(),
+    }
+}
+
+/// The whole of `local`, as the source or destination of an event.
+pub fn place(local: u32) -> Option<MirPlace> {
+    Some(MirPlace {
+        local: local.into(),
+        projection: vec![],
+    })
+}
+
+/// A node at `bb0[0]` of `function` that has no destination.
+pub fn node(function: &Func, kind: NodeKind, source: Option<u32>) -> Node {
+    Node {
+        function: function.clone(),
+        block: BasicBlock::from_u32(0),
+        statement_idx: 0,
+        dest: None,
+        kind,
+        source: source.map(NodeId::from_u32),
+        debug_info: String::new(),
+        info: None,
+    }
+}
+
+/// A location at the start of block `basic_block_idx` of `func` that copies a pointer from
+/// `source` to `destination`.
+pub fn loc(
+    func: &Func,
+    basic_block_idx: usize,
+    source: Option<MirPlace>,
+    destination: Option<MirPlace>,
+) -> MirLoc {
+    MirLoc {
+        func: func.clone(),
+        basic_block_idx,
+        statement_idx: 0,
+        metadata: EventMetadata {
+            source,
+            destination,
+            ..Default::default()
+        },
+    }
+}
+
+pub fn event(mir_loc: MirLocId, kind: EventKind) -> Event {
+    Event { mir_loc, kind }
+}
+
+/// The [`Metadata`] of a trace whose events happen at `locs`.
+pub fn metadata(locs: Vec<MirLoc>) -> Metadata {
+    Metadata {
+        functions: locs
+            .iter()
+            .map(|loc| (loc.func.id, loc.func.name.clone()))
+            .collect(),
+        locs,
+        projections: HashMap::new(),
+        expectations: Vec::new(),
+        spans: Default::default(),
+    }
+}
+
+/// The [`PermissionSummary`] with the permissions in `names`, such as `"write offset"`.
+pub fn perms(names: &str) -> PermissionSummary {
+    let mut perms = PermissionSummary::default();
+    for name in names.split_whitespace() {
+        match name {
+            "write" => perms.write = true,
+            "offset" => perms.offset = true,
+            "free" => perms.free = true,
+            _ => panic!("unknown permission {name:?}"),
+        }
+    }
+    perms
+}
@@ -72,6 +72,24 @@ class CFile:
         self.translate_const_macros = "translate_const_macros" in flags
         self.reorganize_definitions = "reorganize_definitions" in flags
         self.emit_build_files = "emit_build_files" in flags
+        self.emit_callback_trampolines = "emit_callback_trampolines" in flags
+        self.explicit_promotions = "explicit_promotions" in flags
+        self.preserve_provenance = "preserve_provenance" in flags
+        self.errno_wrappers = sorted(flag[14:] for flag in flags if flag.startswith("errno_wrapper_"))
+        self.public_headers = sorted(flag[14:] for flag in flags if flag.startswith("public_header_"))
+        self.feature_matrix = next((flag[15:] for flag in flags if flag.startswith("feature_matrix_")), None)
+
+    def features(self) -> List[str]:
+        """
+        The cargo features named in this file's feature matrix, if it has one.
+        """
+        if not self.feature_matrix:
+            return []
+        path = os.path.join(os.path.dirname(self.path), self.feature_matrix)
+        with open(path, 'r', encoding="utf-8") as file:
+            lines = (line.strip() for line in file)
+            return [line.split('=')[0].strip() for line in lines
+                    if line and not line.startswith('#')]
 
     def translate(self, cc_db: str, ld_lib_path: str, extra_args: List[str] = []) -> RustFile:
         extensionless_file, _ = os.path.splitext(self.path)
@@ -96,6 +114,18 @@ class CFile:
             args.append("--reorganize-definitions")
         if self.emit_build_files:
             args.append("--emit-build-files")
+        if self.emit_callback_trampolines:
+            args.append("--emit-callback-trampolines")
+        if self.explicit_promotions:
+            args.append("--explicit-promotions")
+        if self.preserve_provenance:
+            args.append("--preserve-provenance")
+        for errno_wrapper in self.errno_wrappers:
+            args.extend(["--errno-wrapper", errno_wrapper])
+        for public_header in self.public_headers:
+            args.extend(["--public-header", os.path.join(os.path.dirname(self.path), public_header)])
+        if self.feature_matrix:
+            args.extend(["--feature-matrix", os.path.join(os.path.dirname(self.path), self.feature_matrix)])
 
         if self.log_level == 'DEBUG':
             args.append("--log-level=debug")
@@ -503,73 +533,81 @@ class TestDirectory:
 
         self.generated_files["rust_src"].append(main_file)
 
-        # Try and build test binary
-        with pb.local.cwd(self.full_path):
-            args = ["build"]
+        # Files translated with a feature matrix are gated on cargo features, so build and run
+        # the tests once with each of those features enabled.
+        features = [feature for c_file in self.c_files for feature in c_file.features()]
+        for feature in features or [None]:
+            feature_args = ["--features", feature] if feature else []
+            feature_desc = " (feature {})".format(feature) if feature else ""
 
-            if c.BUILD_TYPE == 'release':
-                args.append('--release')
+            # Try and build test binary
+            with pb.local.cwd(self.full_path):
+                args = ["build"] + feature_args
 
-            if self.target:
-                args += ["--target", self.target]
+                if c.BUILD_TYPE == 'release':
+                    args.append('--release')
 
-            retcode, stdout, stderr = cargo[args].run(retcode=None)
+                if self.target:
+                    args += ["--target", self.target]
 
-        if retcode != 0:
-            _, main_file_path_short = os.path.split(main_file.path)
+                retcode, stdout, stderr = cargo[args].run(retcode=None)
 
-            self.print_status(Colors.FAIL, "FAILED", "compile {}".format(main_file_path_short))
-            sys.stdout.write('\n')
-            sys.stdout.write(stderr)
+            if retcode != 0:
+                _, main_file_path_short = os.path.split(main_file.path)
 
-            outcomes.append(TestOutcome.UnexpectedFailure)
+                self.print_status(Colors.FAIL, "FAILED", "compile {}{}".format(main_file_path_short, feature_desc))
+                sys.stdout.write('\n')
+                sys.stdout.write(stderr)
 
-            return outcomes
+                outcomes.append(TestOutcome.UnexpectedFailure)
 
-        for test_file in self.rs_test_files:
-            if not test_file.pass_expected:
-                continue
+                return outcomes
 
-            _, file_name = os.path.split(test_file.path)
-            extensionless_file_name, _ = os.path.splitext(file_name)
+            for test_file in self.rs_test_files:
+                if not test_file.pass_expected:
+                    continue
 
-            for test_function in test_file.test_functions:
-                args = ["run", "{}::{}".format(extensionless_file_name, test_function.name)]
+                _, file_name = os.path.split(test_file.path)
+                extensionless_file_name, _ = os.path.splitext(file_name)
 
-                if c.BUILD_TYPE == 'release':
-                    args.append('--release')
+                for test_function in test_file.test_functions:
+                    args = ["run", "{}::{}".format(extensionless_file_name, test_function.name)]
+                    args += feature_args
+
+                    if c.BUILD_TYPE == 'release':
+                        args.append('--release')
 
-                with pb.local.cwd(self.full_path):
-                    retcode, stdout, stderr = cargo[args].run(retcode=None)
+                    with pb.local.cwd(self.full_path):
+                        retcode, stdout, stderr = cargo[args].run(retcode=None)
 
-                logging.debug("stdout:%s\n", stdout)
+                    logging.debug("stdout:%s\n", stdout)
 
-                test_str = file_name + ' - ' + test_function.name
+                    test_str = file_name + ' - ' + test_function.name + feature_desc
 
-                if retcode == 0:
-                    if test_function.pass_expected:
-                        self.print_status(Colors.OKGREEN, "OK", "    test " + test_str)
-                        sys.stdout.write('\n')
+                    if retcode == 0:
+                        if test_function.pass_expected:
+                            self.print_status(Colors.OKGREEN, "OK", "    test " + test_str)
+                            sys.stdout.write('\n')
 
-                        outcomes.append(TestOutcome.Success)
-                    else:
-                        self.print_status(Colors.FAIL, "FAILED", "test " + test_str)
-                        sys.stdout.write('\n')
+                            outcomes.append(TestOutcome.Success)
+                        else:
+                            self.print_status(Colors.FAIL, "FAILED", "test " + test_str)
+                            sys.stdout.write('\n')
 
-                        outcomes.append(TestOutcome.UnexpectedSuccess)
+                            outcomes.append(TestOutcome.UnexpectedSuccess)
 
-                elif retcode != 0:
-                    if test_function.pass_expected:
-                        self.print_status(Colors.FAIL, "FAILED", "test " + test_str)
-                        sys.stdout.write('\n')
-                        sys.stdout.write(stderr)
+                    elif retcode != 0:
+                        if test_function.pass_expected:
+                            self.print_status(Colors.FAIL, "FAILED", "test " + test_str)
+                            sys.stdout.write('\n')
+                            sys.stdout.write(stderr)
 
-                        outcomes.append(TestOutcome.UnexpectedFailure)
-                    else:
-                        self.print_status(Colors.OKBLUE, "FAILED", "test " + test_str)
-                        sys.stdout.write('\n')
+                            outcomes.append(TestOutcome.UnexpectedFailure)
+                        else:
+                            self.print_status(Colors.OKBLUE, "FAILED", "test " + test_str)
+                            sys.stdout.write('\n')
 
-                        outcomes.append(TestOutcome.Failure)
+                            outcomes.append(TestOutcome.Failure)
 
         if not outcomes:
             display_text = "   No rust file(s) matching " + self.files.pattern
@@ -1,6 +1,6 @@
 use crate::arrays::rust_entry;
 use crate::incomplete_arrays::{rust_check_some_ints, rust_entry2, rust_test_sized_array};
-use crate::variable_arrays::{rust_alloca_arrays, rust_variable_arrays};
+use crate::variable_arrays::{rust_alloca_arrays, rust_alloca_const, rust_variable_arrays};
 use libc::{c_int, c_uint};
 
 #[link(name = "test")]
@@ -15,6 +15,8 @@ extern "C" {
 
     fn alloca_arrays(_: *mut c_int);
 
+    fn alloca_const(_: *mut c_int);
+
     fn check_some_ints() -> bool;
 }
 
@@ -26,6 +28,7 @@ pub static rust_SOME_INTS: [u32; 4] = [2, 0, 1, 8];
 const BUFFER_SIZE: usize = 49;
 const BUFFER_SIZE2: usize = 2;
 const BUFFER_SIZEV: usize = 88;
+const BUFFER_SIZEA: usize = 5;
 
 pub fn test_sized_array_impls() {
     unsafe {
@@ -112,3 +115,17 @@ pub fn test_alloca_arrays() {
         assert_eq!(buffer[index], rust_buffer[index], "index: {}", index);
     }
 }
+
+pub fn test_alloca_const() {
+    let mut buffer = [0; BUFFER_SIZEA];
+    let mut rust_buffer = [0; BUFFER_SIZEA];
+    let expected_buffer = [0, 1, 4, 9, 1];
+
+    unsafe {
+        alloca_const(buffer.as_mut_ptr());
+        rust_alloca_const(rust_buffer.as_mut_ptr());
+    }
+
+    assert_eq!(buffer, rust_buffer);
+    assert_eq!(buffer, expected_buffer);
+}
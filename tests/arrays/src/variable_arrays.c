@@ -94,3 +94,18 @@ void alloca_arrays(int buf[const]) {
         int *past_end = &grid[4][4][5];
         past_end = &var1[n];
 }
+
+/* An alloca of a constant size, used as a `void *` */
+void alloca_const(int buf[const]) {
+
+        void *mem = alloca(sizeof(int) * 4);
+        int *ints = mem;
+
+        for (int i = 0; i < 4; i++) {
+                ints[i] = i * i;
+        }
+        for (int i = 0; i < 4; i++) {
+                buf[i] = ints[i];
+        }
+        buf[4] = ((unsigned long) mem % sizeof(long)) == 0;
+}
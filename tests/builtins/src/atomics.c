@@ -66,3 +66,68 @@ void new_atomics(const unsigned buffer_size, int buffer[const])
     __atomic_store_n(&x, 0, __ATOMIC_RELAXED);
     buffer[i++] = x;
 }
+
+// The MSVC atomic intrinsics, declared as in `<intrin.h>`.  Outside of MSVC, clang treats these
+// as ordinary functions; `msvc_intrinsics.c` defines them for the C side of the test.
+long _InterlockedIncrement(long volatile *);
+short _InterlockedIncrement16(short volatile *);
+long long _InterlockedIncrement64(long long volatile *);
+long _InterlockedDecrement(long volatile *);
+short _InterlockedDecrement16(short volatile *);
+long long _InterlockedDecrement64(long long volatile *);
+long _InterlockedExchangeAdd(long volatile *, long);
+char _InterlockedExchangeAdd8(char volatile *, char);
+long _InterlockedOr(long volatile *, long);
+short _InterlockedOr16(short volatile *, short);
+long _InterlockedAnd(long volatile *, long);
+long long _InterlockedAnd64(long long volatile *, long long);
+long _InterlockedXor(long volatile *, long);
+char _InterlockedXor8(char volatile *, char);
+long _InterlockedExchange(long volatile *, long);
+short _InterlockedExchange16(short volatile *, short);
+long _InterlockedCompareExchange(long volatile *, long, long);
+char _InterlockedCompareExchange8(char volatile *, char, char);
+short _InterlockedCompareExchange16(short volatile *, short, short);
+long long _InterlockedCompareExchange64(long long volatile *, long long, long long);
+
+void msvc_atomics(const unsigned buffer_size, int buffer[const])
+{
+    int i = 0;
+    long x = 34;
+    short s = 12;
+    char c = 5;
+    long long big = 1LL << 40;
+
+    // Increment and decrement return the new value.
+    buffer[i++] = _InterlockedIncrement(&x);       buffer[i++] = x;
+    buffer[i++] = _InterlockedDecrement(&x);       buffer[i++] = x;
+    buffer[i++] = _InterlockedIncrement16(&s);     buffer[i++] = s;
+    buffer[i++] = _InterlockedDecrement16(&s);     buffer[i++] = s;
+    buffer[i++] = _InterlockedIncrement64(&big) - (1LL << 40);
+    buffer[i++] = _InterlockedDecrement64(&big) - (1LL << 40);
+    buffer[i++] = big == 1LL << 40;
+
+    // The other operations return the old value.
+    buffer[i++] = _InterlockedExchangeAdd(&x, 55); buffer[i++] = x;
+    buffer[i++] = _InterlockedExchangeAdd8(&c, 7); buffer[i++] = c;
+    buffer[i++] = _InterlockedOr(&x, 128);         buffer[i++] = x;
+    buffer[i++] = _InterlockedOr16(&s, 0x100);     buffer[i++] = s;
+    buffer[i++] = _InterlockedAnd(&x, 0xAA);       buffer[i++] = x;
+    buffer[i++] = _InterlockedAnd64(&big, (1LL << 40) | 0xFF) == 1LL << 40;
+    buffer[i++] = _InterlockedXor(&x, 0xA5);       buffer[i++] = x;
+    buffer[i++] = _InterlockedXor8(&c, 0x0F);      buffer[i++] = c;
+    buffer[i++] = _InterlockedExchange(&x, 33);    buffer[i++] = x;
+    buffer[i++] = _InterlockedExchange16(&s, 44);  buffer[i++] = s;
+
+    // The new value comes before the expected one, so only the second call of each pair, whose
+    // expected value matches, swaps in 77.
+    buffer[i++] = _InterlockedCompareExchange(&x, 77, 34);   buffer[i++] = x;
+    buffer[i++] = _InterlockedCompareExchange(&x, 77, 33);   buffer[i++] = x;
+    buffer[i++] = _InterlockedCompareExchange8(&c, 77, 0);   buffer[i++] = c;
+    buffer[i++] = _InterlockedCompareExchange8(&c, 77, c);   buffer[i++] = c;
+    buffer[i++] = _InterlockedCompareExchange16(&s, 77, 0);  buffer[i++] = s;
+    buffer[i++] = _InterlockedCompareExchange16(&s, 77, 44); buffer[i++] = s;
+    buffer[i++] = _InterlockedCompareExchange64(&big, 77, 0);
+    buffer[i++] = _InterlockedCompareExchange64(&big, 77, 1LL << 40) == 1LL << 40;
+    buffer[i++] = big;
+}
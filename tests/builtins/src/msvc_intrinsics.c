@@ -0,0 +1,42 @@
+// Reference implementations of the MSVC atomic intrinsics used by `msvc_atomics` in
+// `atomics.c`, which only MSVC provides.  The translator turns calls to the declarations in
+// `atomics.c` into Rust atomics, so the C library runs these while the translated Rust runs the
+// translator's version, and the test compares the two.
+
+long _InterlockedIncrement(long volatile *p) { return __sync_add_and_fetch(p, 1); }
+short _InterlockedIncrement16(short volatile *p) { return __sync_add_and_fetch(p, 1); }
+long long _InterlockedIncrement64(long long volatile *p) { return __sync_add_and_fetch(p, 1); }
+
+long _InterlockedDecrement(long volatile *p) { return __sync_sub_and_fetch(p, 1); }
+short _InterlockedDecrement16(short volatile *p) { return __sync_sub_and_fetch(p, 1); }
+long long _InterlockedDecrement64(long long volatile *p) { return __sync_sub_and_fetch(p, 1); }
+
+long _InterlockedExchangeAdd(long volatile *p, long v) { return __sync_fetch_and_add(p, v); }
+char _InterlockedExchangeAdd8(char volatile *p, char v) { return __sync_fetch_and_add(p, v); }
+long _InterlockedOr(long volatile *p, long v) { return __sync_fetch_and_or(p, v); }
+short _InterlockedOr16(short volatile *p, short v) { return __sync_fetch_and_or(p, v); }
+long _InterlockedAnd(long volatile *p, long v) { return __sync_fetch_and_and(p, v); }
+long long _InterlockedAnd64(long long volatile *p, long long v) { return __sync_fetch_and_and(p, v); }
+long _InterlockedXor(long volatile *p, long v) { return __sync_fetch_and_xor(p, v); }
+char _InterlockedXor8(char volatile *p, char v) { return __sync_fetch_and_xor(p, v); }
+
+long _InterlockedExchange(long volatile *p, long v) { return __sync_lock_test_and_set(p, v); }
+short _InterlockedExchange16(short volatile *p, short v) { return __sync_lock_test_and_set(p, v); }
+
+// Unlike `__sync_val_compare_and_swap`, the new value comes before the expected one.
+long _InterlockedCompareExchange(long volatile *p, long exchange, long comparand)
+{
+    return __sync_val_compare_and_swap(p, comparand, exchange);
+}
+char _InterlockedCompareExchange8(char volatile *p, char exchange, char comparand)
+{
+    return __sync_val_compare_and_swap(p, comparand, exchange);
+}
+short _InterlockedCompareExchange16(short volatile *p, short exchange, short comparand)
+{
+    return __sync_val_compare_and_swap(p, comparand, exchange);
+}
+long long _InterlockedCompareExchange64(long long volatile *p, long long exchange, long long comparand)
+{
+    return __sync_val_compare_and_swap(p, comparand, exchange);
+}
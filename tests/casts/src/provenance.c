@@ -0,0 +1,32 @@
+//! preserve_provenance
+
+#include <stdint.h>
+
+uintptr_t ptr_to_int(int *p) {
+    return (uintptr_t)p;
+}
+
+int *int_to_ptr(uintptr_t addr) {
+    return (int *)addr;
+}
+
+const int *int_to_const_ptr(uintptr_t addr) {
+    return (const int *)addr;
+}
+
+unsigned long long ptr_to_u64(const int *p) {
+    return (unsigned long long)p;
+}
+
+// The offset of `p` into a 16-byte-aligned block, through a 32-bit integer.
+unsigned offset_in_block(const char *p) {
+    return (unsigned)p & 15;
+}
+
+// Round-trip `p` through an integer and write through the result.
+int increment_through_int(int *p) {
+    uintptr_t addr = (uintptr_t)p;
+    int *q = (int *)addr;
+    *q += 1;
+    return *q;
+}
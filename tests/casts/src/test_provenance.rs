@@ -0,0 +1,57 @@
+//! feature_strict_provenance
+
+use crate::provenance::{
+    rust_increment_through_int, rust_int_to_const_ptr, rust_int_to_ptr, rust_offset_in_block,
+    rust_ptr_to_int, rust_ptr_to_u64,
+};
+use libc::{c_char, c_int, c_uint, c_ulonglong, uintptr_t};
+
+#[link(name = "test")]
+extern "C" {
+    fn ptr_to_int(p: *mut c_int) -> uintptr_t;
+
+    fn offset_in_block(p: *const c_char) -> c_uint;
+
+    fn increment_through_int(p: *mut c_int) -> c_int;
+}
+
+pub fn test_round_trip() {
+    let mut x: c_int = 41;
+    let p: *mut c_int = &mut x;
+
+    unsafe {
+        let addr = rust_ptr_to_int(p);
+        assert_eq!(addr, ptr_to_int(p));
+        assert_eq!(rust_int_to_ptr(addr), p);
+        assert_eq!(rust_int_to_const_ptr(addr), p as *const c_int);
+        assert_eq!(rust_ptr_to_u64(p), addr as c_ulonglong);
+
+        assert_eq!(rust_increment_through_int(p), 42);
+        assert_eq!(increment_through_int(p), 43);
+    }
+    assert_eq!(x, 43);
+}
+
+pub fn test_truncating_cast() {
+    #[repr(align(16))]
+    struct Block([c_char; 16]);
+    let block = Block([0; 16]);
+    let p = block.0[5..].as_ptr();
+
+    unsafe {
+        assert_eq!(rust_offset_in_block(p), 5);
+        assert_eq!(offset_in_block(p), 5);
+    }
+}
+
+pub fn test_provenance_apis() {
+    // Pointer-to-integer casts expose the address, and integer-to-pointer casts pick the
+    // function for the pointee's mutability, whatever the integer type.
+    let src = include_str!("provenance.rs");
+    assert!(src.contains("p.expose_addr() as uintptr_t"));
+    assert!(src.contains("::core::ptr::from_exposed_addr_mut::<libc::c_int>(addr as usize)"));
+    assert!(src.contains("::core::ptr::from_exposed_addr::<libc::c_int>(addr as usize)"));
+    assert!(src.contains("p.expose_addr() as libc::c_ulonglong"));
+    assert!(src.contains("p.expose_addr() as libc::c_uint"));
+    assert!(!src.contains("as *mut libc::c_int"));
+}
@@ -0,0 +1,24 @@
+//! feature_matrix_samples.features
+
+#include "samples.h"
+
+int sample_bits(void) {
+    return sizeof(sample_t) * 8;
+}
+
+long long sum(const struct buffer *buf) {
+    long long total = 0;
+    for (int i = 0; i < 4; i++) {
+        total += buf->samples[i];
+    }
+#ifdef SCALED
+    total *= buf->scale;
+#endif
+    return total;
+}
+
+#ifdef SCALED
+void set_scale(struct buffer *buf, int scale) {
+    buf->scale = scale;
+}
+#endif
@@ -0,0 +1,14 @@
+#if SAMPLE_BITS == 64
+typedef long long sample_t;
+#elif SAMPLE_BITS == 32
+typedef int sample_t;
+#else
+typedef short sample_t;
+#endif
+
+struct buffer {
+    sample_t samples[4];
+#ifdef SCALED
+    int scale;
+#endif
+};
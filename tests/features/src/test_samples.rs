@@ -0,0 +1,55 @@
+use crate::samples::{buffer, rust_sample_bits, rust_sum, sample_t};
+
+use std::mem::size_of;
+
+// `sample_t` and `struct buffer` differ between the configurations, so only the definitions for
+// the enabled feature are compiled.
+pub fn test_sample_type() {
+    let bits = if cfg!(feature = "wide") {
+        64
+    } else if cfg!(feature = "mid") {
+        32
+    } else {
+        16
+    };
+
+    assert_eq!(size_of::<sample_t>() * 8, bits);
+    assert_eq!(unsafe { rust_sample_bits() }, bits as i32);
+}
+
+pub fn test_sum() {
+    let buf = buffer {
+        samples: [1, -2, 30, 400],
+        #[cfg(any(feature = "mid", feature = "wide"))]
+        scale: 3,
+    };
+    let expected = if cfg!(feature = "narrow") { 429 } else { 1287 };
+
+    assert_eq!(unsafe { rust_sum(&buf) }, expected);
+}
+
+// `set_scale` only exists in the configurations with `-DSCALED`.
+pub fn test_set_scale() {
+    #[cfg(any(feature = "mid", feature = "wide"))]
+    {
+        let mut buf = buffer {
+            samples: [0; 4],
+            scale: 1,
+        };
+
+        unsafe { crate::samples::rust_set_scale(&mut buf, 2) };
+
+        assert_eq!(buf.scale, 2);
+    }
+}
+
+pub fn test_gated_items() {
+    let src = include_str!("samples.rs");
+
+    // Items that differ in each configuration, like `sample_t`, are gated on one feature, and
+    // items shared by some of them, like `set_scale`, on all of theirs.
+    assert!(src.contains("#[cfg(feature = \"narrow\")]\npub type sample_t"));
+    assert!(src.contains("#[cfg(feature = \"wide\")]\npub type sample_t"));
+    assert!(src.contains("#[cfg(any(feature = \"mid\", feature = \"wide\"))]"));
+    assert_eq!(src.matches("fn rust_sample_bits").count(), 1);
+}
@@ -0,0 +1,21 @@
+//! explicit_promotions
+
+void promotions(const unsigned sz, int buffer[const]) {
+    int i = 0;
+
+    signed char sc = 127;
+    sc++;
+    buffer[i++] = sc;
+    buffer[i++] = sc--;
+    buffer[i++] = sc;
+
+    short s = -32768;
+    --s;
+    buffer[i++] = s;
+    buffer[i++] = ++s;
+
+    char c = 'A';
+    buffer[i++] = c;
+    c++;
+    buffer[i++] = c;
+}
@@ -0,0 +1,24 @@
+use crate::promotions::rust_promotions;
+
+use libc::{c_int, c_uint};
+
+#[link(name = "test")]
+extern "C" {
+    fn promotions(_: c_uint, _: *mut c_int);
+}
+
+const BUFFER_SIZE: usize = 7;
+
+pub fn test_buffer() {
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut rust_buffer = [0; BUFFER_SIZE];
+    let expected_buffer = [-128, -128, 127, 32767, -32768, 65, 66];
+
+    unsafe {
+        promotions(BUFFER_SIZE as u32, buffer.as_mut_ptr());
+        rust_promotions(BUFFER_SIZE as u32, rust_buffer.as_mut_ptr());
+    }
+
+    assert_eq!(buffer, rust_buffer);
+    assert_eq!(buffer, expected_buffer);
+}
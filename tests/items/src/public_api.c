@@ -0,0 +1,11 @@
+//! public_header_public_api.h
+
+#include "public_api.h"
+
+int range_scaled_len(struct range r, int k) {
+    return (r.hi - r.lo) * k;
+}
+
+int range_len(struct range r) {
+    return range_scaled_len(r, 1);
+}
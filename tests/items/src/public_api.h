@@ -0,0 +1,3 @@
+#include "range.h"
+
+int range_len(struct range r);
@@ -0,0 +1,4 @@
+struct range {
+    int lo;
+    int hi;
+};
@@ -0,0 +1,22 @@
+use crate::public_api::{range, rust_range_len};
+use libc::c_int;
+
+#[link(name = "test")]
+extern "C" {
+    fn range_len(r: range) -> c_int;
+}
+
+pub fn test_public_api() {
+    let r = range { lo: 3, hi: 10 };
+    unsafe {
+        assert_eq!(range_len(r), 7);
+        assert_eq!(rust_range_len(r), 7);
+    }
+
+    // `range_len` is declared in the public header.  `struct range`, which it takes, is declared
+    // in another header, but has to be public too.  `range_scaled_len` isn't declared in either.
+    let src = include_str!("public_api.rs");
+    assert!(src.contains("pub struct range {"));
+    assert!(src.contains("pub unsafe extern \"C\" fn rust_range_len("));
+    assert!(src.contains("pub(crate) unsafe extern \"C\" fn rust_range_scaled_len("));
+}
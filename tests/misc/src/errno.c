@@ -0,0 +1,28 @@
+//! errno_wrapper_close, errno_wrapper_dup
+
+#include <errno.h>
+#include <unistd.h>
+
+// Calls to `close` and `dup` go through `io::Result` wrappers, and `errno`
+// through the `errno()` and `set_errno()` helpers.
+void check_errno(int buffer[])
+{
+    errno = 0;
+    buffer[0] = errno;
+
+    // Closing an invalid descriptor fails with EBADF
+    buffer[1] = close(-1);
+    buffer[2] = errno == EBADF;
+
+    int fd = dup(0);
+    buffer[3] = fd >= 0;
+    if (fd >= 0) {
+        buffer[4] = close(fd);
+    } else {
+        buffer[4] = -2;
+    }
+
+    errno = 42;
+    buffer[5] = errno;
+    errno = 0;
+}
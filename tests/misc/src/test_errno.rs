@@ -0,0 +1,23 @@
+use crate::errno::rust_check_errno;
+use libc::c_int;
+
+#[link(name = "test")]
+extern "C" {
+    fn check_errno(_: *mut c_int);
+}
+
+const BUFFER_SIZE: usize = 6;
+
+pub fn test_errno() {
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut rust_buffer = [0; BUFFER_SIZE];
+    let expected_buffer = [0, -1, 1, 1, 0, 42];
+
+    unsafe {
+        check_errno(buffer.as_mut_ptr());
+        rust_check_errno(rust_buffer.as_mut_ptr());
+    }
+
+    assert_eq!(buffer, rust_buffer);
+    assert_eq!(buffer, expected_buffer);
+}
@@ -0,0 +1,52 @@
+//! emit_callback_trampolines
+
+#include <stdlib.h>
+
+typedef int (*int_fn)(int);
+
+struct handler {
+        void *callback;
+        int calls;
+};
+
+struct point {
+        int x;
+        int y;
+};
+
+static int compare_points(const struct point *a, const struct point *b) {
+        return a->x - b->x;
+}
+
+static int add_one(int x) { return x + 1; }
+static int twice(int x) { return 2 * x; }
+
+static int run(struct handler *h, int x) {
+        if (!h->callback) {
+                return -1;
+        }
+        h->calls++;
+        return ((int_fn)h->callback)(x);
+}
+
+void entry4(const unsigned sz, int buffer[const]) {
+        int i = 0;
+
+        // A comparator taking `struct point *` passed where `void *` is expected
+        struct point points[3] = {{3, 30}, {1, 10}, {2, 20}};
+        qsort(points, 3, sizeof(struct point),
+              (int (*)(const void *, const void *))compare_points);
+        for (int j = 0; j < 3; j++) {
+                buffer[i++] = points[j].y;
+        }
+
+        // A `void *` field that only ever holds an `int_fn`
+        struct handler h = {(void *)add_one, 0};
+        buffer[i++] = run(&h, 41);
+        h.callback = (void *)twice;
+        buffer[i++] = run(&h, 21);
+        h.callback = NULL;
+        buffer[i++] = run(&h, 0);
+        buffer[i++] = h.calls;
+        buffer[i++] = h.callback == NULL;
+}
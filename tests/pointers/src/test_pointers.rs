@@ -1,5 +1,6 @@
 //! feature_c_variadic
 
+use crate::callbacks::rust_entry4;
 use crate::function_pointers::rust_entry3;
 use crate::pointer_arith::rust_entry2;
 use crate::pointer_init::rust_entry;
@@ -15,11 +16,14 @@ extern "C" {
     fn entry2(_: c_uint, _: *mut c_int);
 
     fn entry3(_: c_uint, _: *mut c_int);
+
+    fn entry4(_: c_uint, _: *mut c_int);
 }
 
 const BUFFER_SIZE: usize = 5;
 const BUFFER_SIZE2: usize = 31;
 const BUFFER_SIZE3: usize = 18;
+const BUFFER_SIZE4: usize = 8;
 
 pub fn test_init() {
     let mut buffer = [0; BUFFER_SIZE];
@@ -67,3 +71,17 @@ pub fn test_fn_ptrs() {
     assert_eq!(&buffer[..], &expected_buffer[..], "c version");
     assert_eq!(&rust_buffer[..], &expected_buffer[..], "rust version");
 }
+
+pub fn test_callbacks() {
+    let mut buffer = [0; BUFFER_SIZE4];
+    let mut rust_buffer = [0; BUFFER_SIZE4];
+    let expected_buffer = [10, 20, 30, 42, 42, -1, 2, 1];
+
+    unsafe {
+        entry4(BUFFER_SIZE4 as u32, buffer.as_mut_ptr());
+        rust_entry4(BUFFER_SIZE4 as u32, rust_buffer.as_mut_ptr());
+    }
+
+    assert_eq!(buffer, rust_buffer);
+    assert_eq!(buffer, expected_buffer);
+}